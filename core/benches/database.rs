@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use beancount_import_core::db::{
+    AccessToken, Account, AccountId, AccountType, AddOrVerifyResult, Amount, BankConnection,
+    BeancountAccountInfo, Cipher, DatabaseFile, DatabaseV16, DbCipher, DbPlaidAuth,
+    PlaidAccountInfo, Transaction, TransactionCategory, TransactionId, TransactionInfo,
+    XChaCha20Poly1305Cipher,
+};
+use beancount_import_core::export::export_all_transactions;
+use chrono::{Days, NaiveDate};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rust_decimal::Decimal;
+use tokio::runtime::Runtime;
+
+const NUM_ACCOUNTS: usize = 20;
+const NUM_TRANSACTIONS_PER_ACCOUNT: usize = 5_000;
+const NUM_TRANSACTIONS: usize = NUM_ACCOUNTS * NUM_TRANSACTIONS_PER_ACCOUNT;
+
+fn transaction(account: usize, index: usize) -> Transaction {
+    let posted_date = NaiveDate::from_ymd_opt(2020, 1, 1)
+        .unwrap()
+        .checked_add_days(Days::new((index % 1500) as u64))
+        .unwrap();
+    Transaction::new(TransactionInfo {
+        posted_date,
+        authorized_date: (index % 3 == 1).then(|| posted_date.pred_opt().unwrap()),
+        category: Some(TransactionCategory {
+            primary: "FOOD_AND_DRINK".to_string(),
+            detailed: "FOOD_AND_DRINK_COFFEE".to_string(),
+        }),
+        amount: Amount {
+            amount: Decimal::new(-((index as i64 * 137) % 10_000 + 1), 2),
+            iso_currency_code: Some("USD".to_string()),
+        },
+        merchant_name: Some(format!("Merchant {}", index % 200)),
+        description_or_merchant_name: Some(format!("Purchase {index} at merchant {}", index % 200)),
+        original_description: Some(format!(
+            "POS PURCHASE {account}-{index} MERCHANT {}",
+            index % 200
+        )),
+        transaction_type: Some("place".to_string()),
+        location: None,
+        check_number: None,
+        associated_website: None,
+    })
+}
+
+/// Generate a database with [NUM_ACCOUNTS] accounts of [NUM_TRANSACTIONS_PER_ACCOUNT] transactions each
+fn generate_database() -> DatabaseV16 {
+    let mut database = DatabaseV16::new(DbPlaidAuth::new(
+        "client-id".to_string(),
+        "secret".to_string(),
+    ));
+    let accounts = (0..NUM_ACCOUNTS)
+        .map(|account| {
+            let mut connected = Account::new_connected(
+                PlaidAccountInfo {
+                    name: format!("Account {account}"),
+                    official_name: None,
+                    mask: Some(format!("{account:04}")),
+                    type_: "depository".to_string(),
+                    subtype: Some("checking".to_string()),
+                },
+                BeancountAccountInfo {
+                    ty: AccountType::Assets,
+                    name_parts: vec!["Bank".to_string(), format!("Account{account}")],
+                },
+            );
+            let transactions = connected.account.as_mut().unwrap();
+            for index in 0..NUM_TRANSACTIONS_PER_ACCOUNT {
+                let result = transactions.add_or_verify_transaction(
+                    TransactionId(format!("transaction-{account}-{index}")),
+                    transaction(account, index),
+                );
+                assert!(matches!(result, AddOrVerifyResult::Added));
+            }
+            (AccountId::new(format!("account-{account}")), connected)
+        })
+        .collect::<HashMap<_, _>>();
+    database.bank_connections.push(BankConnection::new(
+        "Bank".to_string(),
+        AccessToken::new("access-token".to_string()),
+        Some("ins_1".to_string()),
+        accounts,
+    ));
+    database
+}
+
+/// Serializing, compressing and encrypting the database and the way back
+fn bench_save_load(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let tempdir = tempfile::tempdir().unwrap();
+    let db_path = tempdir.path().join("db");
+    let key = XChaCha20Poly1305Cipher::new_key();
+    let db = DatabaseFile::new(
+        generate_database(),
+        db_path.clone(),
+        DbCipher::Encrypted(XChaCha20Poly1305Cipher::with_key(&key)),
+    );
+
+    let mut group = c.benchmark_group("database");
+    group.throughput(Throughput::Elements(NUM_TRANSACTIONS as u64));
+    group.sample_size(10);
+    group.bench_function("save", |b| {
+        b.iter(|| runtime.block_on(db.save_copy_to(&db_path)).unwrap())
+    });
+    group.bench_function("load", |b| {
+        b.iter(|| {
+            let cipher = DbCipher::Encrypted(XChaCha20Poly1305Cipher::with_key(&key));
+            runtime
+                .block_on(DatabaseFile::load(black_box(db_path.clone()), cipher))
+                .unwrap()
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn bench_export(c: &mut Criterion) {
+    let database = generate_database();
+    let mut group = c.benchmark_group("database");
+    group.throughput(Throughput::Elements(NUM_TRANSACTIONS as u64));
+    group.sample_size(10);
+    group.bench_function("export", |b| {
+        b.iter(|| {
+            let mut out = Vec::new();
+            export_all_transactions(black_box(&database), None, None, &mut out, |_, _| {}).unwrap();
+            out
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_save_load, bench_export);
+criterion_main!(benches);
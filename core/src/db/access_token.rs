@@ -1,15 +1,18 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Formatter};
+use zeroize::Zeroizing;
 
 #[derive(Serialize, Deserialize, Clone)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub struct AccessToken {
-    access_token: String,
+    access_token: Zeroizing<String>,
 }
 
 impl AccessToken {
     pub fn new(access_token: String) -> AccessToken {
-        AccessToken { access_token }
+        AccessToken {
+            access_token: Zeroizing::new(access_token),
+        }
     }
 
     pub fn get(&self) -> &str {
@@ -0,0 +1,257 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::{transactions::AddOrVerifyResult, Transaction, TransactionId, Transactions};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AccountId(pub String);
+
+impl AccountId {
+    pub fn new(id: String) -> Self {
+        Self(id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct PlaidAccountInfo {
+    pub name: String,
+    pub official_name: Option<String>,
+    /// e.g. the last 4 digits of the account number.
+    pub mask: Option<String>,
+    pub type_: String,
+    pub subtype: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct BeancountAccountInfo {
+    pub ty: AccountType,
+    pub name_parts: Vec<String>,
+}
+
+impl BeancountAccountInfo {
+    pub fn beancount_name(&self) -> String {
+        let ty = match self.ty {
+            AccountType::Assets => "Assets",
+            AccountType::Liabilities => "Liabilities",
+            AccountType::Equity => "Equity",
+            AccountType::Income => "Income",
+            AccountType::Expenses => "Expenses",
+        };
+        format!("{ty}:{}", self.name_parts.join(":"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct Account {
+    pub plaid_account_info: PlaidAccountInfo,
+    /// A connection can have multiple accounts, we may have only added some to our database.
+    /// Other accounts in the connection will still have an entry but be `None` here.
+    pub account: Option<ConnectedAccount>,
+}
+
+impl Account {
+    pub fn new_connected(
+        plaid_account_info: PlaidAccountInfo,
+        beancount_account_info: BeancountAccountInfo,
+    ) -> Self {
+        Self {
+            plaid_account_info,
+            account: Some(ConnectedAccount {
+                beancount_account_info,
+                transactions: Transactions::new_empty(),
+                sync_enabled: true,
+                balance_snapshots: vec![],
+                pruned_transactions: HashSet::new(),
+            }),
+        }
+    }
+
+    pub fn new_unconnected(plaid_account_info: PlaidAccountInfo) -> Self {
+        Self {
+            plaid_account_info,
+            account: None,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.account.is_some()
+    }
+
+    /// Connected accounts with sync enabled
+    pub fn is_synced(&self) -> bool {
+        self.account
+            .as_ref()
+            .is_some_and(|account| account.sync_enabled)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct ConnectedAccount {
+    pub beancount_account_info: BeancountAccountInfo,
+    pub transactions: Transactions,
+    /// Accounts with sync disabled keep their transactions but don't get new ones
+    pub sync_enabled: bool,
+    /// Balances as reported by Plaid, oldest first
+    pub balance_snapshots: Vec<BalanceSnapshot>,
+    /// Transactions removed by `db prune`. Plaid sends them again when a sync downloads all transactions, so we need to
+    /// remember not to add them back.
+    pub pruned_transactions: HashSet<TransactionId>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct BalanceSnapshot {
+    pub date: NaiveDate,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub current: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub available: Option<Decimal>,
+    pub iso_currency_code: Option<String>,
+}
+
+impl ConnectedAccount {
+    pub fn add_or_verify_transaction(
+        &mut self,
+        transaction_id: TransactionId,
+        transaction: Transaction,
+    ) -> AddOrVerifyResult {
+        if self.pruned_transactions.contains(&transaction_id) {
+            return AddOrVerifyResult::Pruned;
+        }
+        self.transactions.add_or_verify(transaction_id, transaction)
+    }
+
+    /// Remove transactions dated before `before`, optionally only those that were already exported.
+    /// Returns the number of removed transactions.
+    pub fn prune_transactions(&mut self, before: NaiveDate, only_exported: bool) -> usize {
+        let pruned = self.transactions.remove_where(|transaction| {
+            transaction.transaction.date() < before
+                && (transaction.already_exported || !only_exported)
+        });
+        let num_pruned = pruned.len();
+        self.pruned_transactions.extend(pruned);
+        num_pruned
+    }
+
+    /// Mark the exported transactions dated on or after `since` as not exported so `export-new` exports them again.
+    /// Returns the number of changed transactions.
+    pub fn mark_as_not_exported_since(&mut self, since: NaiveDate) -> usize {
+        let mut num_changed = 0;
+        for (_, transaction) in self.transactions.iter_all_sorted_by_date_mut() {
+            if transaction.already_exported && transaction.transaction.date() >= since {
+                transaction.mark_as_not_exported();
+                num_changed += 1;
+            }
+        }
+        num_changed
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccountType {
+    Assets,
+    Liabilities,
+    Equity,
+    Income,
+    Expenses,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TransactionBuilder;
+
+    fn transaction(day: u32, already_exported: bool) -> Transaction {
+        TransactionBuilder::new()
+            .posted_on(2024, 1, day)
+            .exported(already_exported)
+            .build()
+    }
+
+    fn account() -> ConnectedAccount {
+        ConnectedAccount {
+            beancount_account_info: BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Checking".to_string()],
+            },
+            transactions: [
+                ("old-exported", 1, true),
+                ("old-new", 2, false),
+                ("recent-exported", 20, true),
+            ]
+            .into_iter()
+            .map(|(id, day, exported)| (TransactionId(id.to_string()), transaction(day, exported)))
+            .collect(),
+            sync_enabled: true,
+            balance_snapshots: vec![],
+            pruned_transactions: HashSet::new(),
+        }
+    }
+
+    fn transaction_ids(account: &ConnectedAccount) -> Vec<&str> {
+        let mut ids: Vec<&str> = account
+            .transactions
+            .iter_all_sorted_by_date()
+            .map(|(id, _)| id.0.as_str())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn prune_only_exported() {
+        let mut account = account();
+        let before = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        assert_eq!(1, account.prune_transactions(before, true));
+        assert_eq!(
+            vec!["old-new", "recent-exported"],
+            transaction_ids(&account)
+        );
+    }
+
+    #[test]
+    fn prune_all() {
+        let mut account = account();
+        let before = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        assert_eq!(2, account.prune_transactions(before, false));
+        assert_eq!(vec!["recent-exported"], transaction_ids(&account));
+    }
+
+    #[test]
+    fn pruned_transactions_arent_added_again() {
+        let mut account = account();
+        let before = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        account.prune_transactions(before, true);
+        assert!(matches!(
+            account.add_or_verify_transaction(
+                TransactionId("old-exported".to_string()),
+                transaction(1, false)
+            ),
+            AddOrVerifyResult::Pruned
+        ));
+        assert_eq!(
+            vec!["old-new", "recent-exported"],
+            transaction_ids(&account)
+        );
+    }
+
+    #[test]
+    fn mark_as_not_exported_since() {
+        let mut account = account();
+        let since = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        assert_eq!(1, account.mark_as_not_exported_since(since));
+        let exported: Vec<&str> = account
+            .transactions
+            .iter_all_sorted_by_date()
+            .filter(|(_, transaction)| transaction.already_exported)
+            .map(|(id, _)| id.0.as_str())
+            .collect();
+        assert_eq!(vec!["old-exported"], exported);
+    }
+}
@@ -0,0 +1,33 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::{AccountId, BeancountAccountInfo};
+
+/// A connected account that was moved to another Beancount account with `account remap`.
+/// Transactions exported before keep the old account, later exports use the new one.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct AccountRename {
+    pub account_id: AccountId,
+    /// The day the new account takes effect. That's the day of the remap, or the date of the oldest transaction
+    /// that wasn't exported yet if it's older, since that one is exported to the new account.
+    pub renamed_on: NaiveDate,
+    pub old_account: BeancountAccountInfo,
+    pub new_account: BeancountAccountInfo,
+    pub directives: RenameDirectives,
+    /// `export-new` already wrote the directives
+    pub already_exported: bool,
+}
+
+/// What `export-new` writes to tell the ledger about a remapped account
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum RenameDirectives {
+    /// Nothing, e.g. because the ledger was already updated by hand
+    #[default]
+    None,
+    /// Close the old account and open the new one
+    CloseOpen,
+    /// A note on the new account that mentions the old one
+    Note,
+}
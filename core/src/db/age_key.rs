@@ -0,0 +1,125 @@
+//! Protects the database key with age identities, e.g. ones kept on a YubiKey through `age-plugin-yubikey`,
+//! so the key doesn't have to be stored in plain text.
+
+use std::{
+    fs::File,
+    io::{BufReader, Read as _, Write as _},
+    path::Path,
+};
+
+use age::{Callbacks, Decryptor, Encryptor, IdentityFile};
+use zeroize::Zeroizing;
+
+use super::{
+    crypto::{EncryptionKey, KEY_SIZE},
+    DbError,
+};
+
+/// Encrypt `key` to the recipients of the identities in `identity_file`. Plugins may use `callbacks` to talk to the user.
+pub fn wrap_key_with_age(
+    key: &EncryptionKey,
+    identity_file: &Path,
+    callbacks: impl Callbacks,
+) -> Result<Vec<u8>, DbError> {
+    let recipients = load_identity_file(identity_file)?
+        .with_callbacks(callbacks)
+        .to_recipients()
+        .map_err(age_error("Failed to get the recipients of the identities"))?;
+    let encryptor = Encryptor::with_recipients(recipients.iter().map(|r| r.as_ref() as _))
+        .map_err(age_error("Failed to wrap the key"))?;
+    let mut wrapped = vec![];
+    let mut writer = encryptor.wrap_output(&mut wrapped)?;
+    writer.write_all(key.as_slice())?;
+    writer.finish()?;
+    Ok(wrapped)
+}
+
+/// Decrypt a key wrapped by [wrap_key_with_age] with the identities in `identity_file`
+pub fn unwrap_key_with_age(
+    wrapped: &[u8],
+    identity_file: &Path,
+    callbacks: impl Callbacks,
+) -> Result<EncryptionKey, DbError> {
+    let identities = load_identity_file(identity_file)?
+        .with_callbacks(callbacks)
+        .into_identities()
+        .map_err(age_error("Failed to load the identities"))?;
+    let mut reader = Decryptor::new(wrapped)
+        .map_err(age_error("The wrapped key isn't an age file"))?
+        .decrypt(identities.iter().map(|i| i.as_ref() as _))
+        .map_err(age_error(format!(
+            "Failed to unwrap the key with the identities in {}",
+            identity_file.display()
+        )))?;
+    let mut unwrapped = Zeroizing::new(vec![]);
+    reader.read_to_end(&mut unwrapped)?;
+    if unwrapped.len() != KEY_SIZE {
+        return Err(DbError::Corrupted(format!(
+            "Wrapped key must be {KEY_SIZE} bytes long"
+        )));
+    }
+    let mut key = EncryptionKey::default();
+    key.copy_from_slice(&unwrapped);
+    Ok(key)
+}
+
+fn load_identity_file(identity_file: &Path) -> Result<IdentityFile<age::NoCallbacks>, DbError> {
+    let file =
+        File::open(identity_file).map_err(DbError::file("open identity file", identity_file))?;
+    IdentityFile::from_buffer(BufReader::new(file))
+        .map_err(DbError::file("parse identity file", identity_file))
+}
+
+fn age_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(
+    context: impl Into<String>,
+) -> impl FnOnce(E) -> DbError {
+    let context = context.into();
+    move |source| DbError::Age {
+        context,
+        source: source.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use age::{secrecy::ExposeSecret as _, x25519, NoCallbacks};
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::db::{Cipher as _, XChaCha20Poly1305Cipher};
+
+    fn identity_file(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        let identity = x25519::Identity::generate();
+        std::fs::write(&path, identity.to_string().expose_secret()).unwrap();
+        path
+    }
+
+    #[test]
+    fn wrap_and_unwrap() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let identity_file = identity_file(tempdir.path(), "identity");
+        let key = XChaCha20Poly1305Cipher::new_key();
+
+        let wrapped = wrap_key_with_age(&key, &identity_file, NoCallbacks).unwrap();
+        assert!(!wrapped
+            .windows(KEY_SIZE)
+            .any(|window| window == key.as_slice()));
+        let unwrapped = unwrap_key_with_age(&wrapped, &identity_file, NoCallbacks).unwrap();
+        assert_eq!(key, unwrapped);
+    }
+
+    #[test]
+    fn refuse_other_identity() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let identity_file_1 = identity_file(tempdir.path(), "identity1");
+        let identity_file_2 = identity_file(tempdir.path(), "identity2");
+        let key = XChaCha20Poly1305Cipher::new_key();
+
+        let wrapped = wrap_key_with_age(&key, &identity_file_1, NoCallbacks).unwrap();
+        let err = unwrap_key_with_age(&wrapped, &identity_file_2, NoCallbacks)
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Failed to unwrap the key"), "{err}");
+    }
+}
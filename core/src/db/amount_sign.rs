@@ -0,0 +1,49 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Which sign the amounts of an account's transactions are stored and exported with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum AmountSign {
+    /// Money coming into the account is positive, like Beancount expects. Charges to a credit card are negative and
+    /// payments positive.
+    #[default]
+    Standard,
+    /// The opposite of `Standard`, e.g. charges to a credit card are positive and payments negative. Also for
+    /// institutions that report the amounts of an account with the wrong sign.
+    Inverted,
+}
+
+impl AmountSign {
+    /// The amount to store for an amount Plaid reported. Plaid reports money leaving the account as positive.
+    pub fn apply_to_plaid(self, plaid_amount: Decimal) -> Decimal {
+        self.apply(-plaid_amount)
+    }
+
+    /// Convert an amount with the [AmountSign::Standard] sign to this sign, or back
+    pub fn apply(self, amount: Decimal) -> Decimal {
+        match self {
+            Self::Standard => amount,
+            Self::Inverted => -amount,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_plaid_amounts() {
+        // A purchase of 12.50
+        let plaid_amount = Decimal::new(1250, 2);
+        assert_eq!(
+            Decimal::new(-1250, 2),
+            AmountSign::Standard.apply_to_plaid(plaid_amount)
+        );
+        assert_eq!(
+            Decimal::new(1250, 2),
+            AmountSign::Inverted.apply_to_plaid(plaid_amount)
+        );
+    }
+}
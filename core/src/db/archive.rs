@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use std::{io::Write as _, path::Path};
+
+use super::{crypto::Cipher, lock::DbLock, DbError, XChaCha20Poly1305Cipher};
+use crate::error::ParseError;
+
+/// Archives start with this header, followed by the encrypted and compressed [Archive]
+const ARCHIVE_HEADER: &[u8] = b"beancount-plaid archive\n";
+
+/// Name of the database file in the archive
+const DATABASE_ENTRY: &str = "database";
+
+/// All files needed to move the tool to another machine
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    entries: Vec<ArchiveEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveEntry {
+    name: String,
+    content: Vec<u8>,
+}
+
+/// Write the database file at `db_path` into a new archive at `archive_path`, encrypted with `cipher`.
+/// The database file is copied as is, so it stays encrypted with its own key.
+pub async fn pack_archive(
+    db_path: &Path,
+    archive_path: &Path,
+    cipher: &XChaCha20Poly1305Cipher,
+) -> Result<(), DbError> {
+    let _lock = DbLock::acquire(db_path)?;
+    let archive = Archive {
+        entries: vec![ArchiveEntry {
+            name: DATABASE_ENTRY.to_string(),
+            content: tokio::fs::read(db_path).await?,
+        }],
+    };
+    let plaintext = postcard::to_stdvec(&archive).map_err(|source| DbError::Serialize {
+        what: "archive",
+        source: source.into(),
+    })?;
+    let compressed = zstd::bulk::compress(&plaintext, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+    let ciphertext = cipher.encrypt(&compressed)?;
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(archive_path)?;
+    file.write_all(ARCHIVE_HEADER)?;
+    file.write_all(&ciphertext)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Restore the database file from an archive created by [pack_archive]. Fails if there already is a database at `db_path`.
+pub async fn unpack_archive(
+    archive_path: &Path,
+    db_path: &Path,
+    cipher: &XChaCha20Poly1305Cipher,
+) -> Result<(), DbError> {
+    let _lock = DbLock::acquire(db_path)?;
+    if tokio::fs::try_exists(db_path).await? {
+        return Err(DbError::Refused(format!(
+            "There already is a database at {}, refusing to overwrite it",
+            db_path.display()
+        )));
+    }
+    let content = tokio::fs::read(archive_path).await?;
+    let ciphertext = content.strip_prefix(ARCHIVE_HEADER).ok_or_else(|| {
+        DbError::Corrupted(format!("{} isn't an archive", archive_path.display()))
+    })?;
+    let compressed = cipher.decrypt(ciphertext)?;
+    let plaintext = zstd::bulk::decompress(&compressed, compressed.len().max(1024 * 1024 * 1024))?;
+    let archive: Archive =
+        postcard::from_bytes(&plaintext).map_err(|source| ParseError::Postcard {
+            what: "archive",
+            source,
+        })?;
+
+    let mut database = None;
+    for entry in archive.entries {
+        match entry.name.as_str() {
+            DATABASE_ENTRY => database = Some(entry.content),
+            name => {
+                return Err(DbError::Corrupted(format!(
+                    "Archive contains unknown file {name}"
+                )))
+            }
+        }
+    }
+    let database = database
+        .ok_or_else(|| DbError::Corrupted("Archive doesn't contain a database".to_string()))?;
+    if database.is_empty() {
+        return Err(DbError::Corrupted("Archived database is empty".to_string()));
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(db_path)?;
+    file.write_all(&database)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> XChaCha20Poly1305Cipher {
+        XChaCha20Poly1305Cipher::with_key(&XChaCha20Poly1305Cipher::new_key())
+    }
+
+    #[tokio::test]
+    async fn pack_and_unpack() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let archive_path = tempdir.path().join("archive");
+        let new_db_path = tempdir.path().join("new_database");
+        std::fs::write(&db_path, b"database content").unwrap();
+        let cipher = cipher();
+
+        pack_archive(&db_path, &archive_path, &cipher)
+            .await
+            .unwrap();
+        unpack_archive(&archive_path, &new_db_path, &cipher)
+            .await
+            .unwrap();
+        assert_eq!(
+            b"database content".to_vec(),
+            std::fs::read(&new_db_path).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn unpack_with_wrong_key() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let archive_path = tempdir.path().join("archive");
+        let new_db_path = tempdir.path().join("new_database");
+        std::fs::write(&db_path, b"database content").unwrap();
+
+        pack_archive(&db_path, &archive_path, &cipher())
+            .await
+            .unwrap();
+        unpack_archive(&archive_path, &new_db_path, &cipher())
+            .await
+            .unwrap_err();
+        assert!(!new_db_path.exists());
+    }
+
+    #[tokio::test]
+    async fn unpack_doesnt_overwrite_database() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let archive_path = tempdir.path().join("archive");
+        std::fs::write(&db_path, b"database content").unwrap();
+        let cipher = cipher();
+
+        pack_archive(&db_path, &archive_path, &cipher)
+            .await
+            .unwrap();
+        let err = unpack_archive(&archive_path, &db_path, &cipher)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("There already is a database"), "{err}");
+    }
+}
@@ -0,0 +1,41 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    account::{ConnectedAccount, PlaidAccountInfo},
+    bank_connection::BankConnection,
+    AccountId,
+};
+
+/// Connections and accounts the user removed. We keep them with their transactions and export flags
+/// instead of dropping the data, but they aren't synced or exported anymore.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct Archived {
+    pub connections: Vec<ArchivedConnection>,
+    pub accounts: Vec<ArchivedAccount>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct ArchivedConnection {
+    pub archived_on: NaiveDate,
+    pub connection: BankConnection,
+}
+
+/// An account that was disconnected while its bank connection stayed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct ArchivedAccount {
+    pub archived_on: NaiveDate,
+    pub connection_name: String,
+    pub account_id: AccountId,
+    pub plaid_account_info: PlaidAccountInfo,
+    pub account: ConnectedAccount,
+}
+
+impl Archived {
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty() && self.accounts.is_empty()
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+
+use super::DbError;
+
+/// Number of previous database versions kept by default
+pub const DEFAULT_NUM_BACKUPS: usize = 3;
+
+/// Path of a file next to the database file, e.g. `beancount_plaid.db.lock` for suffix `.lock`
+pub fn sibling_path(db_path: &Path, suffix: &str) -> Result<PathBuf, DbError> {
+    let filename = db_path
+        .file_name()
+        .ok_or_else(|| DbError::Refused(format!("{} has no filename", db_path.display())))?
+        .to_str()
+        .ok_or_else(|| {
+            DbError::Refused(format!(
+                "Filename of {} isn't valid utf-8",
+                db_path.display()
+            ))
+        })?;
+    Ok(db_path.with_file_name(format!("{}{}", filename, suffix)))
+}
+
+/// Path of the backup of the given generation. Generation 1 is the most recent backup.
+pub fn backup_path(db_path: &Path, generation: usize) -> Result<PathBuf, DbError> {
+    sibling_path(db_path, &format!(".bak.{generation}"))
+}
+
+/// Shift all existing backups one generation back and copy the current database file to generation 1.
+/// Backups beyond `num_backups` generations are deleted.
+pub async fn rotate_backups(db_path: &Path, num_backups: usize) -> Result<(), DbError> {
+    if num_backups == 0 || !tokio::fs::try_exists(db_path).await? {
+        return Ok(());
+    }
+    let oldest = backup_path(db_path, num_backups)?;
+    if tokio::fs::try_exists(&oldest).await? {
+        tokio::fs::remove_file(&oldest).await?;
+    }
+    for generation in (1..num_backups).rev() {
+        let path = backup_path(db_path, generation)?;
+        if tokio::fs::try_exists(&path).await? {
+            tokio::fs::rename(&path, backup_path(db_path, generation + 1)?).await?;
+        }
+    }
+    // Copy instead of rename so that there is always a database file, even if we crash before the new version is written
+    tokio::fs::copy(db_path, backup_path(db_path, 1)?).await?;
+    Ok(())
+}
+
+/// Replace the database file with the most recent backup and shift the older backups one generation forward.
+/// Unlike restoring a backup, this drops the current database file.
+pub async fn pop_backup(db_path: &Path, num_backups: usize) -> Result<(), DbError> {
+    tokio::fs::rename(backup_path(db_path, 1)?, db_path).await?;
+    for generation in 2..=num_backups {
+        let path = backup_path(db_path, generation)?;
+        if tokio::fs::try_exists(&path).await? {
+            tokio::fs::rename(&path, backup_path(db_path, generation - 1)?).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(path: PathBuf) -> Option<String> {
+        tokio::fs::read_to_string(path).await.ok()
+    }
+
+    #[tokio::test]
+    async fn rotates_and_drops_oldest() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        for version in 1..=4 {
+            rotate_backups(&db_path, 2).await.unwrap();
+            tokio::fs::write(&db_path, format!("version {version}"))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(Some("version 4".to_string()), read(db_path.clone()).await);
+        assert_eq!(
+            Some("version 3".to_string()),
+            read(backup_path(&db_path, 1).unwrap()).await
+        );
+        assert_eq!(
+            Some("version 2".to_string()),
+            read(backup_path(&db_path, 2).unwrap()).await
+        );
+        assert_eq!(None, read(backup_path(&db_path, 3).unwrap()).await);
+    }
+
+    #[tokio::test]
+    async fn no_backups() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        tokio::fs::write(&db_path, "version 1").await.unwrap();
+        rotate_backups(&db_path, 0).await.unwrap();
+
+        assert_eq!(None, read(backup_path(&db_path, 1).unwrap()).await);
+    }
+
+    #[tokio::test]
+    async fn pop_restores_most_recent() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        for version in 1..=3 {
+            rotate_backups(&db_path, 3).await.unwrap();
+            tokio::fs::write(&db_path, format!("version {version}"))
+                .await
+                .unwrap();
+        }
+        pop_backup(&db_path, 3).await.unwrap();
+
+        assert_eq!(Some("version 2".to_string()), read(db_path.clone()).await);
+        assert_eq!(
+            Some("version 1".to_string()),
+            read(backup_path(&db_path, 1).unwrap()).await
+        );
+        assert_eq!(None, read(backup_path(&db_path, 2).unwrap()).await);
+    }
+}
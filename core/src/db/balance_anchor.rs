@@ -0,0 +1,32 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Plaid's balance of an account when it was last reconciled, and the sum of the transactions sync added since.
+/// Plaid's balance should always be the anchor balance plus the added transactions, otherwise transactions are missing.
+/// Amounts have the sign of transaction amounts, so the balance of a credit card with debt is negative.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BalanceAnchor {
+    pub date: NaiveDate,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub balance: Decimal,
+    pub iso_currency_code: Option<String>,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub added_since: Decimal,
+}
+
+impl BalanceAnchor {
+    pub fn new(date: NaiveDate, balance: Decimal, iso_currency_code: Option<String>) -> Self {
+        Self {
+            date,
+            balance,
+            iso_currency_code,
+            added_since: Decimal::ZERO,
+        }
+    }
+
+    /// The balance Plaid should report if it sent us all transactions
+    pub fn expected_balance(&self) -> Decimal {
+        self.balance + self.added_since
+    }
+}
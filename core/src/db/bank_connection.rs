@@ -9,6 +9,9 @@ use super::{account::Account, AccessToken, AccountId};
 pub struct BankConnection {
     name: String,
     access_token: AccessToken,
+    institution_id: Option<String>,
+    /// Plaid transactions sync cursor after the last successful sync, `None` if it was never synced
+    sync_cursor: Option<String>,
     accounts: HashMap<AccountId, Account>,
 }
 
@@ -16,11 +19,14 @@ impl BankConnection {
     pub fn new(
         name: String,
         access_token: AccessToken,
+        institution_id: Option<String>,
         accounts: HashMap<AccountId, Account>,
     ) -> Self {
         Self {
             name,
             access_token,
+            institution_id,
+            sync_cursor: None,
             accounts,
         }
     }
@@ -33,6 +39,24 @@ impl BankConnection {
         &self.access_token
     }
 
+    pub fn institution_id(&self) -> Option<&str> {
+        self.institution_id.as_deref()
+    }
+
+    pub fn sync_cursor(&self) -> Option<&str> {
+        self.sync_cursor.as_deref()
+    }
+
+    pub fn set_sync_cursor(&mut self, sync_cursor: String) {
+        self.sync_cursor = Some(sync_cursor);
+    }
+
+    /// Make the next sync download all transactions again instead of only the ones since the last sync, e.g. for
+    /// an account whose transactions were skipped until now
+    pub fn reset_sync_cursor(&mut self) {
+        self.sync_cursor = None;
+    }
+
     pub fn accounts(&self) -> impl Iterator<Item = (&AccountId, &Account)> {
         self.accounts.iter()
     }
@@ -48,4 +72,13 @@ impl BankConnection {
     pub fn account_mut(&mut self, account_id: &AccountId) -> Option<&mut Account> {
         self.accounts.get_mut(account_id)
     }
+
+    /// Add an account, replacing any existing account with the same id
+    pub fn insert_account(&mut self, account_id: AccountId, account: Account) {
+        self.accounts.insert(account_id, account);
+    }
+
+    pub fn into_accounts(self) -> impl Iterator<Item = (AccountId, Account)> {
+        self.accounts.into_iter()
+    }
 }
@@ -1,4 +1,6 @@
-use anyhow::{bail, Result};
+use zeroize::Zeroizing;
+
+use super::DbError;
 
 // TODO Maybe we should factor out cryfs's crypto implementation into a separate crate and use that here.
 
@@ -7,13 +9,19 @@ pub trait Cipher {
 
     fn new_key() -> Self::EncryptionKey;
     fn with_key(key: &Self::EncryptionKey) -> Self;
-    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>>;
-    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>>;
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, DbError>;
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DbError>;
 }
 
+/// Size of the key for [XChaCha20Poly1305Cipher]
+pub const KEY_SIZE: usize = 32;
+
+/// Wiped from memory when dropped
+pub type EncryptionKey = Zeroizing<[u8; KEY_SIZE]>;
+
 mod xchacha20poly1305cipher {
     use chacha20poly1305::{
-        aead::{Aead, AeadCore, KeyInit, OsRng},
+        aead::{rand_core::RngCore as _, Aead, AeadCore, KeyInit, OsRng},
         Key, XChaCha20Poly1305,
     };
 
@@ -21,27 +29,34 @@ mod xchacha20poly1305cipher {
 
     const NONCE_LEN: usize = 24;
 
+    /// The underlying cipher wipes its copy of the key when dropped
     pub struct XChaCha20Poly1305Cipher {
         cipher: XChaCha20Poly1305,
     }
 
     impl Cipher for XChaCha20Poly1305Cipher {
-        type EncryptionKey = Key;
+        type EncryptionKey = EncryptionKey;
 
-        fn new_key() -> Key {
-            XChaCha20Poly1305::generate_key(&mut OsRng)
+        fn new_key() -> EncryptionKey {
+            // Generate directly into the zeroizing buffer so no copy of the key is left behind
+            let mut key = Zeroizing::new([0; KEY_SIZE]);
+            OsRng.fill_bytes(key.as_mut_slice());
+            key
         }
 
-        fn with_key(key: &Key) -> Self {
+        fn with_key(key: &EncryptionKey) -> Self {
             Self {
-                cipher: XChaCha20Poly1305::new(key),
+                cipher: XChaCha20Poly1305::new(Key::from_slice(key.as_slice())),
             }
         }
 
-        fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, DbError> {
             let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
             assert_eq!(NONCE_LEN, nonce.len());
-            let ciphertext = self.cipher.encrypt(&nonce, plaintext)?;
+            let ciphertext = self
+                .cipher
+                .encrypt(&nonce, plaintext)
+                .map_err(|_| DbError::Encryption)?;
 
             let mut result = Vec::with_capacity(NONCE_LEN + ciphertext.len());
             result.extend_from_slice(&nonce);
@@ -50,34 +65,68 @@ mod xchacha20poly1305cipher {
             Ok(result)
         }
 
-        fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DbError> {
+            // Too small for the nonce
             if ciphertext.len() < NONCE_LEN {
-                bail!("Ciphertext too small for nonce");
+                return Err(DbError::Decryption);
             }
             let nonce = &ciphertext[..NONCE_LEN];
             let ciphertext = &ciphertext[NONCE_LEN..];
 
-            let plaintext = self.cipher.decrypt(nonce.into(), ciphertext)?;
-            Ok(plaintext)
+            self.cipher
+                .decrypt(nonce.into(), ciphertext)
+                .map_err(|_| DbError::Decryption)
         }
     }
 }
 pub use xchacha20poly1305cipher::XChaCha20Poly1305Cipher;
 
+/// How the database content is protected on disk.
+/// Unencrypted databases are meant for users who already keep the database on an encrypted disk.
+pub enum DbCipher {
+    Encrypted(XChaCha20Poly1305Cipher),
+    Unencrypted,
+}
+
+impl DbCipher {
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, Self::Encrypted(_))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, DbError> {
+        match self {
+            Self::Encrypted(cipher) => cipher.encrypt(plaintext),
+            Self::Unencrypted => Ok(plaintext.to_vec()),
+        }
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, DbError> {
+        match self {
+            Self::Encrypted(cipher) => cipher.decrypt(ciphertext),
+            Self::Unencrypted => Ok(ciphertext.to_vec()),
+        }
+    }
+
+    /// Fails for [DbCipher::Unencrypted], for when we need to read a database that turned out to be encrypted
+    pub fn require_key(&self) -> Result<&XChaCha20Poly1305Cipher, DbError> {
+        match self {
+            Self::Encrypted(cipher) => Ok(cipher),
+            Self::Unencrypted => Err(DbError::MissingKey),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use chacha20poly1305::Key;
     use rand::{rngs::StdRng, RngCore, SeedableRng};
 
     use super::*;
 
-    const KEY_SIZE: usize = 32;
-
-    fn key(seed: u64) -> Key {
+    fn key(seed: u64) -> EncryptionKey {
         let mut rng = StdRng::seed_from_u64(seed);
-        let mut key_bytes = [0; KEY_SIZE];
-        rng.fill_bytes(&mut key_bytes);
-        Key::clone_from_slice(&key_bytes)
+        let mut key = Zeroizing::new([0; KEY_SIZE]);
+        rng.fill_bytes(key.as_mut_slice());
+        key
     }
 
     #[test]
@@ -107,7 +156,7 @@ mod tests {
         let mut ciphertext = cipher.encrypt(&plaintext).unwrap();
         ciphertext[20] ^= 1;
         let decrypted_plaintext = cipher.decrypt(&ciphertext);
-        assert!(decrypted_plaintext.is_err());
+        assert!(matches!(decrypted_plaintext, Err(DbError::Decryption)));
     }
 
     #[test]
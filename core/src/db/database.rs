@@ -0,0 +1,649 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{
+    account_rename::AccountRename,
+    amount_sign::AmountSign,
+    archived::Archived,
+    balance_anchor::BalanceAnchor,
+    bank_connection::BankConnection,
+    duplicates::Duplicates,
+    ignore::IgnoreList,
+    ledger_target::LedgerTargets,
+    legacy::{BankConnectionV1, BankConnectionV3, TransactionOverridesV1},
+    manual::ManualTransaction,
+    overrides::TransactionOverrides,
+    plaid_auth::DbPlaidAuth,
+    transaction_times::TransactionTimes,
+    AccountId, TransactionId, Transactions,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV1 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnectionV1>,
+}
+
+/// Format changes since DatabaseV1:
+/// * all transaction amounts are multplied by `-1`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV2 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnectionV1>,
+}
+
+impl DatabaseV2 {
+    pub fn migrate(database: DatabaseV1) -> Self {
+        let DatabaseV1 {
+            plaid_auth,
+            bank_connections,
+        } = database;
+
+        let bank_connections = bank_connections
+            .into_iter()
+            .map(|mut connection| {
+                for account in connection.accounts.values_mut() {
+                    if let Some(connected_account) = &mut account.account {
+                        for (_id, transaction) in
+                            connected_account.transactions.iter_all_sorted_by_date_mut()
+                        {
+                            transaction.transaction.amount.amount =
+                                -transaction.transaction.amount.amount;
+                        }
+                    }
+                }
+                connection
+            })
+            .collect();
+
+        Self {
+            plaid_auth,
+            bank_connections,
+        }
+    }
+}
+
+/// Format changes since DatabaseV2:
+/// * bank connections store the Plaid sync cursor and institution id
+/// * connected accounts store whether they should be synced and snapshots of their balances
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV3 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnectionV3>,
+}
+
+impl DatabaseV3 {
+    pub fn migrate(database: DatabaseV2) -> Self {
+        let DatabaseV2 {
+            plaid_auth,
+            bank_connections,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections: bank_connections
+                .into_iter()
+                .map(BankConnectionV1::migrate)
+                .collect(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV3:
+/// * connected accounts remember the ids of pruned transactions so syncing doesn't add them again
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV4 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+}
+
+impl DatabaseV4 {
+    pub fn migrate(database: DatabaseV3) -> Self {
+        let DatabaseV3 {
+            plaid_auth,
+            bank_connections,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections: bank_connections
+                .into_iter()
+                .map(BankConnectionV3::migrate)
+                .collect(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV4:
+/// * user overrides of transactions, e.g. notes or the account to book them on
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV5 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverridesV1>,
+}
+
+impl DatabaseV5 {
+    pub fn migrate(database: DatabaseV4) -> Self {
+        let DatabaseV4 {
+            plaid_auth,
+            bank_connections,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides: HashMap::new(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV5:
+/// * manually entered transactions that don't belong to a bank connection
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV6 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverridesV1>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+}
+
+impl DatabaseV6 {
+    pub fn migrate(database: DatabaseV5) -> Self {
+        let DatabaseV5 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions: HashMap::new(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV6:
+/// * transactions and rules for transactions that should never be exported
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV7 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverridesV1>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+}
+
+impl DatabaseV7 {
+    pub fn migrate(database: DatabaseV6) -> Self {
+        let DatabaseV6 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list: IgnoreList::default(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV7:
+/// * removed connections and disconnected accounts are archived instead of dropped
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV8 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverridesV1>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+    pub archived: Archived,
+}
+
+impl DatabaseV8 {
+    pub fn migrate(database: DatabaseV7) -> Self {
+        let DatabaseV7 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived: Archived::default(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV8:
+/// * transaction overrides can replace the Plaid category
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV9 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverrides>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+    pub archived: Archived,
+}
+
+impl DatabaseV9 {
+    pub fn migrate(database: DatabaseV8) -> Self {
+        let DatabaseV8 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides: transaction_overrides
+                .into_iter()
+                .map(|(transaction_id, overrides)| (transaction_id, overrides.migrate()))
+                .collect(),
+            manual_transactions,
+            ignore_list,
+            archived,
+        }
+    }
+}
+
+/// Format changes since DatabaseV9:
+/// * accounts waiting for a Beancount account, whose transactions are synced but not exported yet
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV10 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverrides>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+    pub archived: Archived,
+    /// Unconnected accounts that are synced anyways, so their transactions are kept until `map-account`
+    /// connects them to a Beancount account. Plaid account ids are unique across bank connections.
+    pub pending_accounts: HashMap<AccountId, Transactions>,
+}
+
+impl DatabaseV10 {
+    pub fn migrate(database: DatabaseV9) -> Self {
+        let DatabaseV9 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts: HashMap::new(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV10:
+/// * ledger targets, so connections and accounts can be exported to separate sets of books
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV11 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverrides>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+    pub archived: Archived,
+    /// Unconnected accounts that are synced anyways, so their transactions are kept until `map-account`
+    /// connects them to a Beancount account. Plaid account ids are unique across bank connections.
+    pub pending_accounts: HashMap<AccountId, Transactions>,
+    pub ledger_targets: LedgerTargets,
+}
+
+impl DatabaseV11 {
+    pub fn migrate(database: DatabaseV10) -> Self {
+        let DatabaseV10 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets: LedgerTargets::default(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV11:
+/// * accounts that were moved to another Beancount account, so the ledger can be told about it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV12 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverrides>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+    pub archived: Archived,
+    pub pending_accounts: HashMap<AccountId, Transactions>,
+    pub ledger_targets: LedgerTargets,
+    pub account_renames: Vec<AccountRename>,
+}
+
+impl DatabaseV12 {
+    pub fn migrate(database: DatabaseV11) -> Self {
+        let DatabaseV11 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+            account_renames: vec![],
+        }
+    }
+}
+
+/// Format changes since DatabaseV12:
+/// * balances of accounts that sync compares Plaid's balance against, to notice missing transactions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV13 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverrides>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+    pub archived: Archived,
+    /// Unconnected accounts that are synced anyways, so their transactions are kept until `map-account`
+    /// connects them to a Beancount account. Plaid account ids are unique across bank connections.
+    pub pending_accounts: HashMap<AccountId, Transactions>,
+    pub ledger_targets: LedgerTargets,
+    /// Oldest first
+    pub account_renames: Vec<AccountRename>,
+    /// Only for connected accounts with sync enabled, see [crate::sync::reconcile_balances]
+    pub balance_anchors: HashMap<AccountId, BalanceAnchor>,
+}
+
+impl DatabaseV13 {
+    pub fn migrate(database: DatabaseV12) -> Self {
+        let DatabaseV12 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+            account_renames,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+            account_renames,
+            balance_anchors: HashMap::new(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV13:
+/// * Plaid's posted and authorized times of transactions, for the institutions that report them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV14 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverrides>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+    pub archived: Archived,
+    /// Unconnected accounts that are synced anyways, so their transactions are kept until `map-account`
+    /// connects them to a Beancount account. Plaid account ids are unique across bank connections.
+    pub pending_accounts: HashMap<AccountId, Transactions>,
+    pub ledger_targets: LedgerTargets,
+    /// Oldest first
+    pub account_renames: Vec<AccountRename>,
+    /// Only for connected accounts with sync enabled, see [crate::sync::reconcile_balances]
+    pub balance_anchors: HashMap<AccountId, BalanceAnchor>,
+    /// Only for transactions Plaid reported times for, see [crate::sync::Timezone]
+    pub transaction_times: HashMap<TransactionId, TransactionTimes>,
+}
+
+impl DatabaseV14 {
+    pub fn migrate(database: DatabaseV13) -> Self {
+        let DatabaseV13 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+            account_renames,
+            balance_anchors,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+            account_renames,
+            balance_anchors,
+            transaction_times: HashMap::new(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV14:
+/// * transactions that look like a reissue of another transaction, held back from export until they're reviewed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV15 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverrides>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+    pub archived: Archived,
+    /// Unconnected accounts that are synced anyways, so their transactions are kept until `map-account`
+    /// connects them to a Beancount account. Plaid account ids are unique across bank connections.
+    pub pending_accounts: HashMap<AccountId, Transactions>,
+    pub ledger_targets: LedgerTargets,
+    /// Oldest first
+    pub account_renames: Vec<AccountRename>,
+    /// Only for connected accounts with sync enabled, see [crate::sync::reconcile_balances]
+    pub balance_anchors: HashMap<AccountId, BalanceAnchor>,
+    /// Only for transactions Plaid reported times for, see [crate::sync::Timezone]
+    pub transaction_times: HashMap<TransactionId, TransactionTimes>,
+    /// See [super::flag_duplicates]
+    pub duplicates: Duplicates,
+}
+
+impl DatabaseV15 {
+    pub fn migrate(database: DatabaseV14) -> Self {
+        let DatabaseV14 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+            account_renames,
+            balance_anchors,
+            transaction_times,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+            account_renames,
+            balance_anchors,
+            transaction_times,
+            duplicates: Duplicates::default(),
+        }
+    }
+}
+
+/// Format changes since DatabaseV15:
+/// * accounts whose amounts are stored and exported with the inverted sign
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DatabaseV16 {
+    pub plaid_auth: DbPlaidAuth,
+    pub bank_connections: Vec<BankConnection>,
+    pub transaction_overrides: HashMap<TransactionId, TransactionOverrides>,
+    pub manual_transactions: HashMap<TransactionId, ManualTransaction>,
+    pub ignore_list: IgnoreList,
+    pub archived: Archived,
+    /// Unconnected accounts that are synced anyways, so their transactions are kept until `map-account`
+    /// connects them to a Beancount account. Plaid account ids are unique across bank connections.
+    pub pending_accounts: HashMap<AccountId, Transactions>,
+    pub ledger_targets: LedgerTargets,
+    /// Oldest first
+    pub account_renames: Vec<AccountRename>,
+    /// Only for connected accounts with sync enabled, see [crate::sync::reconcile_balances]
+    pub balance_anchors: HashMap<AccountId, BalanceAnchor>,
+    /// Only for transactions Plaid reported times for, see [crate::sync::Timezone]
+    pub transaction_times: HashMap<TransactionId, TransactionTimes>,
+    /// See [super::flag_duplicates]
+    pub duplicates: Duplicates,
+    /// Only for accounts that don't use [AmountSign::Standard], see [DatabaseV16::amount_sign]
+    pub amount_signs: HashMap<AccountId, AmountSign>,
+}
+
+impl DatabaseV16 {
+    pub fn new(plaid_auth: DbPlaidAuth) -> Self {
+        Self {
+            plaid_auth,
+            bank_connections: vec![],
+            transaction_overrides: HashMap::new(),
+            manual_transactions: HashMap::new(),
+            ignore_list: IgnoreList::default(),
+            archived: Archived::default(),
+            pending_accounts: HashMap::new(),
+            ledger_targets: LedgerTargets::default(),
+            account_renames: vec![],
+            balance_anchors: HashMap::new(),
+            transaction_times: HashMap::new(),
+            duplicates: Duplicates::default(),
+            amount_signs: HashMap::new(),
+        }
+    }
+
+    pub fn migrate(database: DatabaseV15) -> Self {
+        let DatabaseV15 {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+            account_renames,
+            balance_anchors,
+            transaction_times,
+            duplicates,
+        } = database;
+
+        Self {
+            plaid_auth,
+            bank_connections,
+            transaction_overrides,
+            manual_transactions,
+            ignore_list,
+            archived,
+            pending_accounts,
+            ledger_targets,
+            account_renames,
+            balance_anchors,
+            transaction_times,
+            duplicates,
+            amount_signs: HashMap::new(),
+        }
+    }
+
+    /// The sign the amounts of the account are stored and exported with, the same for pending accounts
+    pub fn amount_sign(&self, account_id: &AccountId) -> AmountSign {
+        self.amount_signs
+            .get(account_id)
+            .copied()
+            .unwrap_or_default()
+    }
+}
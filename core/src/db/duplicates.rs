@@ -0,0 +1,322 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use super::{AccountId, BeancountAccountInfo, DatabaseV16, TransactionId, TransactionInfo};
+
+/// Identifies a transaction by its content instead of Plaid's transaction id, which changes when Plaid reissues a
+/// transaction, e.g. after a bank connection was linked again. The account is the Beancount account, because linking
+/// again gives the accounts new Plaid ids.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    account: String,
+    date: NaiveDate,
+    amount: Decimal,
+    iso_currency_code: Option<String>,
+    description: String,
+}
+
+impl Fingerprint {
+    pub fn new(account: &BeancountAccountInfo, transaction: &TransactionInfo) -> Self {
+        let description = transaction
+            .original_description
+            .as_deref()
+            .or(transaction.description_or_merchant_name.as_deref())
+            .or(transaction.merchant_name.as_deref())
+            .unwrap_or("");
+        Self {
+            account: account.beancount_name(),
+            date: transaction.date(),
+            amount: transaction.amount.amount.normalize(),
+            iso_currency_code: transaction.amount.iso_currency_code.clone(),
+            description: normalize_description(description),
+        }
+    }
+}
+
+/// Lowercase words of letters and digits, so differences in case, punctuation and spacing don't matter
+fn normalize_description(description: &str) -> String {
+    description
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Transactions that look like a reissue of another transaction, see [Fingerprint] and [flag_duplicates].
+/// They're held back from export until they're reviewed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct Duplicates {
+    /// Maps each suspected duplicate to the transaction it seems to duplicate
+    suspected: HashMap<TransactionId, TransactionId>,
+    /// Reviewed and found to be separate transactions, they aren't suspected again
+    not_duplicates: HashSet<TransactionId>,
+}
+
+impl Duplicates {
+    pub fn is_suspected(&self, transaction_id: &TransactionId) -> bool {
+        self.suspected.contains_key(transaction_id)
+    }
+
+    /// The suspected duplicates with the transaction each of them seems to duplicate
+    pub fn suspected(&self) -> impl Iterator<Item = (&TransactionId, &TransactionId)> {
+        self.suspected.iter()
+    }
+
+    /// Returns false if the transaction is already suspected or was reviewed to be a separate transaction
+    pub fn suspect(&mut self, duplicate: TransactionId, original: TransactionId) -> bool {
+        if self.not_duplicates.contains(&duplicate) || self.suspected.contains_key(&duplicate) {
+            return false;
+        }
+        self.suspected.insert(duplicate, original);
+        true
+    }
+
+    /// Export a suspected duplicate as a separate transaction. Returns false if it wasn't suspected.
+    pub fn keep(&mut self, transaction_id: &TransactionId) -> bool {
+        if self.suspected.remove(transaction_id).is_none() {
+            return false;
+        }
+        self.not_duplicates.insert(transaction_id.clone());
+        true
+    }
+
+    /// Stop holding back a suspected duplicate without exporting it, e.g. because it's ignored now.
+    /// Returns false if it wasn't suspected.
+    pub fn remove(&mut self, transaction_id: &TransactionId) -> bool {
+        self.suspected.remove(transaction_id).is_some()
+    }
+
+    /// Add the suspected and reviewed transactions of `other`. A transaction reviewed in either is reviewed.
+    pub fn merge(&mut self, other: Duplicates) {
+        self.not_duplicates.extend(other.not_duplicates);
+        for (duplicate, original) in other.suspected {
+            self.suspected.entry(duplicate).or_insert(original);
+        }
+        let not_duplicates = &self.not_duplicates;
+        self.suspected
+            .retain(|duplicate, _| !not_duplicates.contains(duplicate));
+    }
+}
+
+/// A transaction that looks like a reissue of `original`, see [flag_duplicates]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuspectedDuplicate {
+    pub transaction_id: TransactionId,
+    pub original: TransactionId,
+}
+
+struct Candidate<'a> {
+    account_id: &'a AccountId,
+    transaction_id: &'a TransactionId,
+    already_exported: bool,
+}
+
+/// Suspect transactions that weren't exported yet of duplicating another transaction with the same [Fingerprint],
+/// and record them in [DatabaseV16::duplicates] so they're held back from export. The other transaction must either
+/// be exported already or belong to a different Plaid account, in which case only one of the two is suspected.
+/// Two new transactions of the same Plaid account are more likely two identical purchases than a reissue.
+/// Ignored transactions are left out. Returns the newly suspected transactions, sorted by id.
+pub fn flag_duplicates(database: &mut DatabaseV16) -> Vec<SuspectedDuplicate> {
+    let mut candidates: HashMap<Fingerprint, Vec<Candidate>> = HashMap::new();
+    for connection in &database.bank_connections {
+        for (account_id, account) in connection.accounts() {
+            let Some(account) = &account.account else {
+                continue;
+            };
+            for (transaction_id, transaction) in account.transactions.iter_all_sorted_by_date() {
+                if database
+                    .ignore_list
+                    .is_ignored(transaction_id, &transaction.transaction)
+                {
+                    continue;
+                }
+                candidates
+                    .entry(Fingerprint::new(
+                        &account.beancount_account_info,
+                        &transaction.transaction,
+                    ))
+                    .or_default()
+                    .push(Candidate {
+                        account_id,
+                        transaction_id,
+                        already_exported: transaction.already_exported,
+                    });
+            }
+        }
+    }
+
+    let mut suspected = vec![];
+    for group in candidates.values().filter(|group| group.len() > 1) {
+        for candidate in group.iter().filter(|candidate| !candidate.already_exported) {
+            let original = group
+                .iter()
+                .filter(|other| {
+                    other.already_exported
+                        || (other.account_id != candidate.account_id
+                            && other.transaction_id < candidate.transaction_id)
+                })
+                .min_by_key(|other| (!other.already_exported, other.transaction_id));
+            if let Some(original) = original {
+                suspected.push(SuspectedDuplicate {
+                    transaction_id: candidate.transaction_id.clone(),
+                    original: original.transaction_id.clone(),
+                });
+            }
+        }
+    }
+    suspected.retain(|duplicate| {
+        database
+            .duplicates
+            .suspect(duplicate.transaction_id.clone(), duplicate.original.clone())
+    });
+    suspected.sort_by(|lhs, rhs| lhs.transaction_id.cmp(&rhs.transaction_id));
+    suspected
+}
+
+#[cfg(test)]
+mod tests {
+    use common_macros::hash_map;
+
+    use super::*;
+    use crate::db::{
+        AccessToken, Account, AccountType, BankConnection, DbPlaidAuth, PlaidAccountInfo,
+        Transaction, TransactionBuilder,
+    };
+
+    fn transaction(description: &str, already_exported: bool) -> Transaction {
+        TransactionBuilder::new()
+            .posted_on(2024, 1, 15)
+            .cents(-450)
+            .original_description(description)
+            .exported(already_exported)
+            .build()
+    }
+
+    fn account(transactions: &[(&str, Transaction)]) -> Account {
+        let mut account = Account::new_connected(
+            PlaidAccountInfo {
+                name: "Checking".to_string(),
+                official_name: None,
+                mask: None,
+                type_: "depository".to_string(),
+                subtype: None,
+            },
+            BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Checking".to_string()],
+            },
+        );
+        for (id, transaction) in transactions {
+            let _ = account
+                .account
+                .as_mut()
+                .unwrap()
+                .add_or_verify_transaction(TransactionId(id.to_string()), transaction.clone());
+        }
+        account
+    }
+
+    fn database(accounts: HashMap<AccountId, Account>) -> DatabaseV16 {
+        let mut database =
+            DatabaseV16::new(DbPlaidAuth::new("client".to_string(), "secret".to_string()));
+        database.bank_connections.push(BankConnection::new(
+            "Bank".to_string(),
+            AccessToken::new("token".to_string()),
+            None,
+            accounts,
+        ));
+        database
+    }
+
+    fn suspected(duplicate: &str, original: &str) -> SuspectedDuplicate {
+        SuspectedDuplicate {
+            transaction_id: TransactionId(duplicate.to_string()),
+            original: TransactionId(original.to_string()),
+        }
+    }
+
+    #[test]
+    fn normalize_descriptions() {
+        assert_eq!(
+            "coffee shop 1234",
+            normalize_description("  COFFEE-SHOP   #1234 ")
+        );
+    }
+
+    #[test]
+    fn suspect_reissue_of_exported_transaction() {
+        let mut database = database(hash_map![
+            AccountId("account".to_string()) => account(&[
+                ("old", transaction("Coffee Shop #1234", true)),
+                ("new", transaction("COFFEE SHOP 1234", false)),
+                ("other", transaction("Bakery", false)),
+            ]),
+        ]);
+
+        assert_eq!(
+            vec![suspected("new", "old")],
+            flag_duplicates(&mut database)
+        );
+        assert!(database
+            .duplicates
+            .is_suspected(&TransactionId("new".to_string())));
+        // Already suspected
+        assert_eq!(
+            Vec::<SuspectedDuplicate>::new(),
+            flag_duplicates(&mut database)
+        );
+
+        assert!(database.duplicates.keep(&TransactionId("new".to_string())));
+        assert_eq!(
+            Vec::<SuspectedDuplicate>::new(),
+            flag_duplicates(&mut database)
+        );
+    }
+
+    #[test]
+    fn suspect_one_of_two_new_transactions_of_different_accounts() {
+        let mut database = database(hash_map![
+            AccountId("old-account".to_string()) => account(&[("a", transaction("Coffee", false))]),
+            AccountId("new-account".to_string()) => account(&[("b", transaction("Coffee", false))]),
+        ]);
+
+        assert_eq!(vec![suspected("b", "a")], flag_duplicates(&mut database));
+    }
+
+    #[test]
+    fn dont_suspect_identical_new_transactions_of_one_account() {
+        let mut database = database(hash_map![
+            AccountId("account".to_string()) => account(&[
+                ("a", transaction("Coffee", false)),
+                ("b", transaction("Coffee", false)),
+            ]),
+        ]);
+
+        assert_eq!(
+            Vec::<SuspectedDuplicate>::new(),
+            flag_duplicates(&mut database)
+        );
+    }
+
+    #[test]
+    fn dont_suspect_ignored_transactions() {
+        let mut database = database(hash_map![
+            AccountId("account".to_string()) => account(&[
+                ("old", transaction("Coffee", true)),
+                ("new", transaction("Coffee", false)),
+            ]),
+        ]);
+        database
+            .ignore_list
+            .ignore_transaction(TransactionId("new".to_string()));
+
+        assert_eq!(
+            Vec::<SuspectedDuplicate>::new(),
+            flag_duplicates(&mut database)
+        );
+    }
+}
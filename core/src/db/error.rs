@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use super::DatabaseLocked;
+use crate::error::ParseError;
+
+/// Errors of loading, saving and changing the database
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error(transparent)]
+    Locked(#[from] DatabaseLocked),
+
+    #[error("The database is encrypted but no encryption key was given")]
+    MissingKey,
+
+    /// The key is wrong or the encrypted data was modified
+    #[error("Failed to decrypt, the key is wrong or the data is corrupted")]
+    Decryption,
+
+    #[error("Failed to encrypt")]
+    Encryption,
+
+    /// The cancellation token was cancelled before saving started, the database file wasn't changed
+    #[error("Cancelled, the database wasn't saved")]
+    Cancelled,
+
+    /// The content of a file isn't what we wrote there, e.g. because it was truncated or isn't a database at all
+    #[error("{0}")]
+    Corrupted(String),
+
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+
+    #[error("Failed to serialize {what}")]
+    Serialize {
+        what: &'static str,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A connection, account, transaction or backup that doesn't exist
+    #[error("{0}")]
+    NotFound(String),
+
+    /// The change isn't possible with the current content of the database, e.g. merging databases of different
+    /// Plaid clients
+    #[error("{0}")]
+    Refused(String),
+
+    /// A file next to the database, e.g. a backup, that can't be loaded
+    #[error("Failed to load {}", path.display())]
+    Load {
+        path: PathBuf,
+        #[source]
+        source: Box<DbError>,
+    },
+
+    #[error("Failed to {action} {}", path.display())]
+    File {
+        action: &'static str,
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+
+    /// Wrapping or unwrapping the key with age identities failed
+    #[cfg(feature = "age")]
+    #[error("{context}")]
+    Age {
+        context: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+impl DbError {
+    /// For `map_err` of file operations whose path isn't obvious from the context
+    pub(super) fn file(action: &'static str, path: &Path) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.to_path_buf();
+        move |source| Self::File {
+            action,
+            path,
+            source,
+        }
+    }
+
+    /// For `map_err` of loading `path` instead of the database itself
+    pub(super) fn load(path: &Path) -> impl FnOnce(Self) -> Self {
+        let path = path.to_path_buf();
+        move |source| Self::Load {
+            path,
+            source: Box::new(source),
+        }
+    }
+}
@@ -0,0 +1,1922 @@
+use crc::{Crc, CRC_32_BZIP2};
+use std::{
+    io::Read as _,
+    path::{Path, PathBuf},
+};
+use tokio::io::AsyncWriteExt as _;
+use tokio_util::sync::CancellationToken;
+
+use crate::db::versioned::VersionedDatabase;
+
+use super::{
+    backup::{backup_path, pop_backup, rotate_backups, sibling_path, DEFAULT_NUM_BACKUPS},
+    crypto::{Cipher as _, DbCipher},
+    database::{
+        DatabaseV10, DatabaseV11, DatabaseV12, DatabaseV13, DatabaseV14, DatabaseV15, DatabaseV16,
+        DatabaseV2, DatabaseV3, DatabaseV4, DatabaseV5, DatabaseV6, DatabaseV7, DatabaseV8,
+        DatabaseV9,
+    },
+    integrity::{add_hash, check_hash, Checked},
+    lock::{remove_stale_lock, stale_lock_pid, DbLock},
+    snapshot::{load_snapshots, pop_snapshot, push_snapshot, Snapshot},
+    sqlite::{self, StoredRows},
+    storage::StorageBackend,
+    DbError,
+};
+use crate::error::ParseError;
+
+type Result<T, E = DbError> = std::result::Result<T, E>;
+
+/// Unencrypted database files start with this header. Encrypted ones have no header and start with the random nonce.
+const UNENCRYPTED_HEADER: &[u8] = b"beancount-plaid unencrypted\n";
+
+/// zstd level used for database files unless configured otherwise. The highest level, since database files are
+/// small enough that compressing them takes no noticeable time.
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 22;
+
+/// Recorded in snapshots if the database was saved without calling [DatabaseFile::with_command]
+const UNKNOWN_COMMAND: &str = "unknown command";
+
+/// Files a crashed run left behind next to the database
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Leftovers {
+    /// PID of a process that crashed while holding the database lock
+    pub stale_lock_pid: Option<u32>,
+    /// A new version of the database that was written but not moved into place yet
+    pub temp_file: Option<LeftoverTempFile>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LeftoverTempFile {
+    pub path: PathBuf,
+    /// Whether the file was completely written and can be loaded with the key. Only then it can be recovered.
+    pub intact: bool,
+}
+
+impl Leftovers {
+    pub fn is_empty(&self) -> bool {
+        self.stale_lock_pid.is_none() && self.temp_file.is_none()
+    }
+}
+
+enum Storage {
+    File,
+    /// Remembers what's stored in the SQLite database so saving only writes the changed rows
+    Sqlite(StoredRows),
+}
+
+pub struct DatabaseFile {
+    database: DatabaseV16,
+    db_path: PathBuf,
+    db_cipher: DbCipher,
+    modified: bool,
+    /// Number of previous versions of the database file to keep when saving
+    num_backups: usize,
+    /// zstd level for the file backend. SQLite databases aren't compressed.
+    compression_level: i32,
+    storage: Storage,
+    /// Format version the database was stored with. Saving always writes the current version.
+    format_version: u32,
+    /// The command that modified the database, recorded when saving so `undo` can show what it undoes
+    command: String,
+    /// Once cancelled, saving fails with [DbError::Cancelled] instead of starting to write
+    cancel: CancellationToken,
+    /// Held from loading until the database is saved or dropped. `None` for newly created databases.
+    _lock: Option<DbLock>,
+    /// State at [DatabaseFile::begin], restored by [DatabaseFile::rollback]
+    checkpoint: Option<Checkpoint>,
+}
+
+struct Checkpoint {
+    database: DatabaseV16,
+    modified: bool,
+}
+
+impl DatabaseFile {
+    pub fn new(database: DatabaseV16, db_path: PathBuf, db_cipher: DbCipher) -> Self {
+        Self {
+            database,
+            db_path,
+            db_cipher,
+            modified: false,
+            num_backups: DEFAULT_NUM_BACKUPS,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            storage: Storage::File,
+            format_version: VersionedDatabase::CURRENT_VERSION,
+            command: UNKNOWN_COMMAND.to_string(),
+            cancel: CancellationToken::new(),
+            _lock: None,
+            checkpoint: None,
+        }
+    }
+
+    /// Only has an effect on newly created databases, loaded databases keep the backend they were stored with
+    pub fn with_storage_backend(self, storage_backend: StorageBackend) -> Self {
+        let (storage, format_version) = match storage_backend {
+            StorageBackend::File => (Storage::File, VersionedDatabase::CURRENT_VERSION),
+            StorageBackend::Sqlite => (
+                Storage::Sqlite(StoredRows::default()),
+                sqlite::SCHEMA_VERSION,
+            ),
+        };
+        Self {
+            storage,
+            format_version,
+            ..self
+        }
+    }
+
+    pub fn with_command(self, command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            ..self
+        }
+    }
+
+    /// Cancel `cancel` to keep the database from being saved, e.g. on Ctrl+C. A save that already started writing
+    /// still finishes, so the database file is never left half written.
+    pub fn with_cancellation_token(self, cancel: CancellationToken) -> Self {
+        Self { cancel, ..self }
+    }
+
+    pub fn with_num_backups(self, num_backups: usize) -> Self {
+        Self {
+            num_backups,
+            ..self
+        }
+    }
+
+    /// Only used by the file backend. Loading works regardless of the level the file was compressed with.
+    pub fn with_compression_level(self, compression_level: i32) -> Self {
+        Self {
+            compression_level,
+            ..self
+        }
+    }
+
+    pub fn database(&self) -> &DatabaseV16 {
+        &self.database
+    }
+
+    pub fn database_mut(&mut self) -> &mut DatabaseV16 {
+        self.modified = true;
+        &mut self.database
+    }
+
+    /// Remember the current state, so the changes of a command that fails midway can be taken back with
+    /// [Self::rollback] instead of being saved half done. A later call replaces the remembered state.
+    pub fn begin(&mut self) {
+        self.checkpoint = Some(Checkpoint {
+            database: self.database.clone(),
+            modified: self.modified,
+        });
+    }
+
+    /// Keep the changes since [Self::begin]
+    pub fn commit(&mut self) {
+        self.checkpoint = None;
+    }
+
+    /// Take back the changes since [Self::begin]. Returns false if there was nothing to take back to.
+    pub fn rollback(&mut self) -> bool {
+        let Some(checkpoint) = self.checkpoint.take() else {
+            return false;
+        };
+        self.database = checkpoint.database;
+        self.modified = checkpoint.modified;
+        true
+    }
+
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.db_cipher.is_encrypted()
+    }
+
+    pub fn storage_backend(&self) -> StorageBackend {
+        match self.storage {
+            Storage::File => StorageBackend::File,
+            Storage::Sqlite(_) => StorageBackend::Sqlite,
+        }
+    }
+
+    /// Version of the database format for files, or the schema version for SQLite databases
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    /// Version that saving writes. It's newer than [Self::format_version] if the database was migrated when loading.
+    pub fn current_format_version(&self) -> u32 {
+        match self.storage {
+            Storage::File => VersionedDatabase::CURRENT_VERSION,
+            Storage::Sqlite(_) => sqlite::SCHEMA_VERSION,
+        }
+    }
+
+    /// Change how the database is encrypted. Takes effect when the database is saved.
+    pub fn set_cipher(&mut self, db_cipher: DbCipher) {
+        self.db_cipher = db_cipher;
+        self.modified = true;
+        if let Storage::Sqlite(stored_rows) = &mut self.storage {
+            // All rows need to be rewritten with the new cipher
+            *stored_rows = StoredRows::default();
+        }
+    }
+
+    /// Check whether an existing database file is encrypted, without needing the key
+    pub fn detect_encryption(db_path: &Path) -> Result<bool> {
+        match StorageBackend::detect(db_path)? {
+            StorageBackend::File => {
+                let mut header = Vec::with_capacity(UNENCRYPTED_HEADER.len());
+                std::fs::File::open(db_path)?
+                    .take(UNENCRYPTED_HEADER.len() as u64)
+                    .read_to_end(&mut header)?;
+                Ok(header != UNENCRYPTED_HEADER)
+            }
+            StorageBackend::Sqlite => sqlite::is_encrypted(db_path),
+        }
+    }
+
+    /// Returns Ok(None) if the db file doesn't exist yet.
+    /// Fails if another process has the database loaded.
+    /// `db_cipher` is only used if the database file is encrypted.
+    pub async fn load(db_path: PathBuf, db_cipher: DbCipher) -> Result<Option<Self>> {
+        tracing::info!("Loading database...");
+        if !tokio::fs::try_exists(&db_path).await? {
+            return Ok(None);
+        }
+        let lock = DbLock::acquire(&db_path)?;
+        let db_cipher = if Self::detect_encryption(&db_path)? {
+            db_cipher
+        } else {
+            DbCipher::Unencrypted
+        };
+
+        let (database, storage, format_version) = match StorageBackend::detect(&db_path)? {
+            StorageBackend::File => {
+                let (database, format_version) = read_database(&db_path, &db_cipher).await?;
+                (database, Storage::File, format_version)
+            }
+            StorageBackend::Sqlite => {
+                let (database, stored_rows, schema_version) = sqlite::load(&db_path, &db_cipher)?;
+                (database, Storage::Sqlite(stored_rows), schema_version)
+            }
+        };
+
+        tracing::info!("Loading database...done");
+
+        Ok(Some(Self {
+            database,
+            db_path,
+            db_cipher,
+            modified: false,
+            num_backups: DEFAULT_NUM_BACKUPS,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            storage,
+            format_version,
+            command: UNKNOWN_COMMAND.to_string(),
+            cancel: CancellationToken::new(),
+            _lock: Some(lock),
+            checkpoint: None,
+        }))
+    }
+
+    /// Replace the database file with the backup of the given generation (1 = most recent).
+    /// The current database file becomes the most recent backup, so the restore can be undone.
+    pub async fn restore_backup(
+        db_path: PathBuf,
+        db_cipher: DbCipher,
+        generation: usize,
+        num_backups: usize,
+    ) -> Result<()> {
+        let _lock = DbLock::acquire(&db_path)?;
+        let backup_path = backup_path(&db_path, generation)?;
+        if !tokio::fs::try_exists(&backup_path).await? {
+            return Err(DbError::NotFound(format!(
+                "Backup {} not found",
+                backup_path.display()
+            )));
+        }
+        // Make sure the backup is readable with our key before replacing the database with it
+        validate_database(&backup_path, &db_cipher)
+            .await
+            .map_err(DbError::load(&backup_path))?;
+        let content_ciphertext = tokio::fs::read(&backup_path).await?;
+        // The restored generation gets shifted by the rotation, but we already have its content in memory
+        let num_backups = num_backups.max(1);
+        write_durably(&db_path, &content_ciphertext, num_backups).await?;
+        push_snapshot(
+            &db_path,
+            &format!("db restore-backup {generation}"),
+            num_backups,
+        )
+        .await
+    }
+
+    /// Returns the changes `undo` can revert, most recent first.
+    /// Changes from before snapshots were recorded are `None`.
+    pub async fn undo_history(db_path: &Path, num_backups: usize) -> Result<Vec<Option<Snapshot>>> {
+        let mut snapshots = load_snapshots(db_path).await?.into_iter();
+        let mut history = vec![];
+        for generation in 1..=num_backups {
+            if !tokio::fs::try_exists(backup_path(db_path, generation)?).await? {
+                break;
+            }
+            history.push(snapshots.next());
+        }
+        Ok(history)
+    }
+
+    /// Restore the database from before the last change and drop the current version.
+    /// Returns the snapshot describing the undone change, or `None` if the backup was taken before snapshots were recorded.
+    pub async fn undo(
+        db_path: PathBuf,
+        db_cipher: DbCipher,
+        num_backups: usize,
+    ) -> Result<Option<Snapshot>> {
+        let _lock = DbLock::acquire(&db_path)?;
+        let backup_path = backup_path(&db_path, 1)?;
+        if !tokio::fs::try_exists(&backup_path).await? {
+            return Err(DbError::NotFound("There is nothing to undo".to_string()));
+        }
+        validate_database(&backup_path, &db_cipher)
+            .await
+            .map_err(DbError::load(&backup_path))?;
+        pop_backup(&db_path, num_backups).await?;
+        sync_parent_dir(&db_path).await?;
+        pop_snapshot(&db_path).await
+    }
+
+    /// Look for a stale lock or a temporary file left behind by a run that crashed while it had the database loaded
+    pub async fn find_leftovers(db_path: &Path, db_cipher: &DbCipher) -> Result<Leftovers> {
+        let stale_lock_pid = stale_lock_pid(db_path)?;
+        let temp_path = temp_path(db_path)?;
+        let temp_file = if tokio::fs::try_exists(&temp_path).await? {
+            let intact = validate_database(&temp_path, db_cipher).await.is_ok();
+            Some(LeftoverTempFile {
+                path: temp_path,
+                intact,
+            })
+        } else {
+            None
+        };
+        Ok(Leftovers {
+            stale_lock_pid,
+            temp_file,
+        })
+    }
+
+    /// Remove the lock of a process that crashed. Fails if the process holding the lock is still running.
+    pub fn remove_stale_lock(db_path: &Path) -> Result<()> {
+        remove_stale_lock(db_path)
+    }
+
+    /// Replace the database with the temporary file a crashed save left behind, see [DatabaseFile::find_leftovers].
+    /// Like saving, this keeps the current database file as a backup.
+    pub async fn recover_temp_file(
+        db_path: &Path,
+        db_cipher: &DbCipher,
+        num_backups: usize,
+    ) -> Result<()> {
+        let _lock = DbLock::acquire(db_path)?;
+        let temp_path = temp_path(db_path)?;
+        validate_database(&temp_path, db_cipher)
+            .await
+            .map_err(DbError::load(&temp_path))?;
+        let creates_backup = num_backups > 0 && tokio::fs::try_exists(db_path).await?;
+        rotate_backups(db_path, num_backups).await?;
+        tokio::fs::rename(&temp_path, db_path).await?;
+        sync_parent_dir(db_path).await?;
+        if creates_backup {
+            push_snapshot(db_path, "recover crashed save", num_backups).await?;
+        }
+        Ok(())
+    }
+
+    /// Delete the temporary file a crashed save left behind, see [DatabaseFile::find_leftovers]
+    pub async fn remove_temp_file(db_path: &Path) -> Result<()> {
+        let _lock = DbLock::acquire(db_path)?;
+        tokio::fs::remove_file(temp_path(db_path)?).await?;
+        Ok(())
+    }
+
+    pub async fn save_if_modified(self) -> Result<()> {
+        if self.modified {
+            self.save().await
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Like [Self::save_if_modified], but keeps the database in memory so saving can be retried,
+    /// e.g. after freeing disk space
+    pub async fn try_save_if_modified(&mut self) -> Result<()> {
+        if self.modified {
+            self.write().await?;
+            self.modified = false;
+        }
+        Ok(())
+    }
+
+    /// Write the database to `path` as a single file without backups, e.g. because it can't be saved at its own path.
+    /// Replacing the database file with it keeps the changes.
+    pub async fn save_copy_to(&self, path: &Path) -> Result<()> {
+        write_versioned(
+            &VersionedDatabase::V16(self.database.clone()),
+            path,
+            &self.db_cipher,
+            self.compression_level,
+            0,
+        )
+        .await
+    }
+
+    async fn save(mut self) -> Result<()> {
+        self.write().await
+    }
+
+    async fn write(&mut self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(DbError::Cancelled);
+        }
+        tracing::info!("Saving database...");
+        let creates_backup = self.num_backups > 0 && tokio::fs::try_exists(&self.db_path).await?;
+
+        match &mut self.storage {
+            Storage::File => {
+                write_versioned(
+                    &VersionedDatabase::V16(self.database.clone()),
+                    &self.db_path,
+                    &self.db_cipher,
+                    self.compression_level,
+                    self.num_backups,
+                )
+                .await?;
+            }
+            Storage::Sqlite(stored_rows) => {
+                rotate_backups(&self.db_path, self.num_backups).await?;
+                // The next save only writes the rows that changed since this one
+                *stored_rows =
+                    sqlite::save(&self.db_path, &self.db_cipher, &self.database, stored_rows)?;
+            }
+        }
+        if creates_backup {
+            push_snapshot(&self.db_path, &self.command, self.num_backups).await?;
+        }
+
+        tracing::info!("Saving database...done");
+
+        Ok(())
+    }
+}
+
+/// Check that the database file can be loaded, whatever backend it was written with
+async fn validate_database(db_path: &Path, db_cipher: &DbCipher) -> Result<()> {
+    match StorageBackend::detect(db_path)? {
+        StorageBackend::File => {
+            read_database(db_path, db_cipher).await?;
+        }
+        StorageBackend::Sqlite => {
+            sqlite::load(db_path, db_cipher)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the database migrated to the current version, and the version it was stored with
+async fn read_database(db_path: &Path, db_cipher: &DbCipher) -> Result<(DatabaseV16, u32)> {
+    let content_ciphertext = tokio::fs::read(&db_path).await?;
+    let content_plaintext = match content_ciphertext.strip_prefix(UNENCRYPTED_HEADER) {
+        Some(content_plaintext) => content_plaintext.to_vec(),
+        None => db_cipher.require_key()?.decrypt(&content_ciphertext)?,
+    };
+    let content_decompressed = zstd::bulk::decompress(
+        &content_plaintext,
+        content_plaintext.len().max(1024 * 1024 * 1024),
+    )?;
+    let crc = legacy_crc();
+    let (parsed, remaining): (VersionedDatabase, &[u8]) =
+        match check_hash(&content_decompressed)? {
+            Checked::Blake3(serialized) => postcard::take_from_bytes(serialized),
+            Checked::Crc32(serialized) => postcard::take_from_bytes_crc32(serialized, crc.digest()),
+        }
+        .map_err(|source| ParseError::Postcard {
+            what: "database file",
+            source,
+        })?;
+    let format_version = parsed.version();
+    let database = match parsed {
+        VersionedDatabase::V1(database) => migrate_v2(DatabaseV2::migrate(database)),
+        VersionedDatabase::V2(database) => migrate_v2(database),
+        VersionedDatabase::V3(database) => migrate_v3(database),
+        VersionedDatabase::V4(database) => migrate_v4(database),
+        VersionedDatabase::V5(database) => migrate_v5(database),
+        VersionedDatabase::V6(database) => migrate_v6(database),
+        VersionedDatabase::V7(database) => migrate_v7(database),
+        VersionedDatabase::V8(database) => migrate_v8(database),
+        VersionedDatabase::V9(database) => migrate_v9(database),
+        VersionedDatabase::V10(database) => migrate_v10(database),
+        VersionedDatabase::V11(database) => migrate_v11(database),
+        VersionedDatabase::V12(database) => migrate_v12(database),
+        VersionedDatabase::V13(database) => migrate_v13(database),
+        VersionedDatabase::V14(database) => migrate_v14(database),
+        VersionedDatabase::V15(database) => migrate_v15(database),
+        VersionedDatabase::V16(database) => database,
+    };
+    if !remaining.is_empty() {
+        return Err(DbError::Corrupted("File had extra bytes".to_string()));
+    }
+
+    Ok((database, format_version))
+}
+
+fn migrate_v2(database: DatabaseV2) -> DatabaseV16 {
+    migrate_v3(DatabaseV3::migrate(database))
+}
+
+fn migrate_v3(database: DatabaseV3) -> DatabaseV16 {
+    migrate_v4(DatabaseV4::migrate(database))
+}
+
+fn migrate_v4(database: DatabaseV4) -> DatabaseV16 {
+    migrate_v5(DatabaseV5::migrate(database))
+}
+
+fn migrate_v5(database: DatabaseV5) -> DatabaseV16 {
+    migrate_v6(DatabaseV6::migrate(database))
+}
+
+fn migrate_v6(database: DatabaseV6) -> DatabaseV16 {
+    migrate_v7(DatabaseV7::migrate(database))
+}
+
+fn migrate_v7(database: DatabaseV7) -> DatabaseV16 {
+    migrate_v8(DatabaseV8::migrate(database))
+}
+
+fn migrate_v8(database: DatabaseV8) -> DatabaseV16 {
+    migrate_v9(DatabaseV9::migrate(database))
+}
+
+fn migrate_v9(database: DatabaseV9) -> DatabaseV16 {
+    migrate_v10(DatabaseV10::migrate(database))
+}
+
+fn migrate_v10(database: DatabaseV10) -> DatabaseV16 {
+    migrate_v11(DatabaseV11::migrate(database))
+}
+
+fn migrate_v11(database: DatabaseV11) -> DatabaseV16 {
+    migrate_v12(DatabaseV12::migrate(database))
+}
+
+fn migrate_v12(database: DatabaseV12) -> DatabaseV16 {
+    migrate_v13(DatabaseV13::migrate(database))
+}
+
+fn migrate_v13(database: DatabaseV13) -> DatabaseV16 {
+    migrate_v14(DatabaseV14::migrate(database))
+}
+
+fn migrate_v14(database: DatabaseV14) -> DatabaseV16 {
+    migrate_v15(DatabaseV15::migrate(database))
+}
+
+fn migrate_v15(database: DatabaseV15) -> DatabaseV16 {
+    DatabaseV16::migrate(database)
+}
+
+async fn write_versioned(
+    database: &VersionedDatabase,
+    db_path: &Path,
+    db_cipher: &DbCipher,
+    compression_level: i32,
+    num_backups: usize,
+) -> Result<()> {
+    if !zstd::compression_level_range().contains(&compression_level) {
+        return Err(DbError::Refused(format!(
+            "Compression level {compression_level} isn't supported, it must be in {:?}",
+            zstd::compression_level_range()
+        )));
+    }
+    let serialized = postcard::to_stdvec(database).map_err(|source| DbError::Serialize {
+        what: "database file",
+        source: source.into(),
+    })?;
+    let content_plaintext = add_hash(&serialized);
+    let content_compressed = zstd::bulk::compress(&content_plaintext, compression_level)?;
+    let content_ciphertext = match db_cipher {
+        DbCipher::Encrypted(cipher) => cipher.encrypt(&content_compressed)?,
+        DbCipher::Unencrypted => [UNENCRYPTED_HEADER, &content_compressed].concat(),
+    };
+    write_durably(db_path, &content_ciphertext, num_backups).await
+}
+
+/// Replace the database file with `content` such that we end up with either the old or the new file, even on a crash or power loss
+async fn write_durably(db_path: &Path, content: &[u8], num_backups: usize) -> Result<()> {
+    // First write to temporary file so we don't lose data if writing fails halfway
+    let tmppath = temp_path(db_path)?;
+    let mut tmpfile = tokio::fs::File::create(&tmppath).await?;
+    let partial = PartialFile(Some(&tmppath));
+    tmpfile.write_all(content).await?;
+    tmpfile.sync_all().await?;
+    drop(tmpfile);
+    partial.complete();
+
+    rotate_backups(db_path, num_backups).await?;
+
+    // Ok, writing succeeded, let's now replace the real file with the tmpfile
+    tokio::fs::rename(&tmppath, db_path).await?;
+    sync_parent_dir(db_path).await?;
+
+    Ok(())
+}
+
+/// Removes a file when dropped before [PartialFile::complete], i.e. when writing it failed or the future writing it
+/// was dropped. A temp file left next to the database is then always complete and can be recovered.
+struct PartialFile<'a>(Option<&'a Path>);
+
+impl PartialFile<'_> {
+    fn complete(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for PartialFile<'_> {
+    fn drop(&mut self) {
+        if let Some(path) = self.0 {
+            if let Err(err) = std::fs::remove_file(path) {
+                tracing::warn!(
+                    "Failed to remove partially written {}: {err}",
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+fn temp_path(db_path: &Path) -> Result<PathBuf> {
+    sibling_path(db_path, ".temp:")
+}
+
+/// Persist the rename of the database file
+#[cfg(unix)]
+async fn sync_parent_dir(db_path: &Path) -> Result<()> {
+    let parent = match db_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    tokio::fs::File::open(parent).await?.sync_all().await?;
+    Ok(())
+}
+
+/// Windows can't open directories to sync them, but renames there are durable once they return
+#[cfg(not(unix))]
+async fn sync_parent_dir(_db_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Checksum of files written before they were protected by a BLAKE3 hash
+fn legacy_crc() -> Crc<u32> {
+    Crc::<u32>::new(&CRC_32_BZIP2)
+}
+
+#[cfg(test)]
+impl PartialEq for DatabaseFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.database == other.database && self.db_path == other.db_path
+    }
+}
+#[cfg(test)]
+impl std::fmt::Debug for DatabaseFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatabaseFile")
+            .field("database", &self.database)
+            .field("db_path", &self.db_path)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use common_macros::hash_map;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+    use rust_decimal::Decimal;
+    use zeroize::Zeroizing;
+
+    use crate::db::{
+        account::{Account, AccountType, BalanceSnapshot, BeancountAccountInfo, PlaidAccountInfo},
+        archived::Archived,
+        bank_connection::BankConnection,
+        crypto::{XChaCha20Poly1305Cipher, KEY_SIZE},
+        database::{
+            DatabaseV1, DatabaseV10, DatabaseV11, DatabaseV12, DatabaseV13, DatabaseV14,
+            DatabaseV15, DatabaseV16, DatabaseV4, DatabaseV5, DatabaseV6, DatabaseV7, DatabaseV8,
+            DatabaseV9,
+        },
+        duplicates::Duplicates,
+        ignore::IgnoreList,
+        ledger_target::LedgerTargets,
+        legacy::{AccountV1, BankConnectionV1, ConnectedAccountV1, TransactionOverridesV1},
+        manual::ManualTransaction,
+        plaid_auth::DbPlaidAuth,
+        AccessToken, AccountId, TransactionBuilder, TransactionId, TransactionOverrides,
+        Transactions,
+    };
+
+    use super::*;
+
+    fn cipher(seed: u64) -> DbCipher {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut key = Zeroizing::new([0; KEY_SIZE]);
+        rng.fill_bytes(key.as_mut_slice());
+
+        DbCipher::Encrypted(XChaCha20Poly1305Cipher::with_key(&key))
+    }
+
+    fn some_db_1() -> DatabaseV16 {
+        DatabaseV16 {
+            plaid_auth: DbPlaidAuth::new("client-id".to_string(), "secret".to_string()),
+            bank_connections: vec![BankConnection::new(
+                "connection-name-1".to_string(),
+                AccessToken::new("access-token-1".to_string()),
+                Some("ins_1".to_string()),
+                hash_map![
+                    AccountId("account-1".to_string()) => Account::new_connected(PlaidAccountInfo {
+                        name: "Account 1".to_string(),
+                        official_name: None,
+                        mask: None,
+                        type_: "account-type".to_string(),
+                        subtype: None,
+                    }, BeancountAccountInfo{
+                        ty: AccountType::Assets,
+                        name_parts: vec!["Part1".to_string(), "Part2".to_string()],
+                    }), AccountId("account-2".to_string()) =>
+                    Account::new_connected(PlaidAccountInfo {
+                        name: "Account 2".to_string(),
+                        official_name: None,
+                        mask: None,
+                        type_: "account-type".to_string(),
+                        subtype: None,
+                    }, BeancountAccountInfo{
+                        ty: AccountType::Liabilities,
+                        name_parts: vec!["Part1".to_string(), "Part2".to_string()],
+                    }),
+                ],
+            )],
+            transaction_overrides: hash_map![],
+            manual_transactions: hash_map![],
+            ignore_list: IgnoreList::default(),
+            archived: Archived::default(),
+            pending_accounts: hash_map![],
+            ledger_targets: LedgerTargets::default(),
+            account_renames: vec![],
+            balance_anchors: hash_map![],
+            transaction_times: hash_map![],
+            duplicates: Duplicates::default(),
+            amount_signs: hash_map![],
+        }
+    }
+
+    fn some_db_2() -> DatabaseV16 {
+        DatabaseV16 {
+            plaid_auth: DbPlaidAuth::new("client-id".to_string(), "secret".to_string()),
+            bank_connections: vec![BankConnection::new(
+                "connection-name-1".to_string(),
+                AccessToken::new("access-token-2".to_string()),
+                None,
+                hash_map![AccountId("account-100".to_string()) => Account::new_connected(PlaidAccountInfo {
+                    name: "Account 100".to_string(),
+                    official_name: None,
+                    mask: None,
+                    type_: "account-type".to_string(),
+                    subtype: None,
+                }, BeancountAccountInfo{
+                    ty: AccountType::Assets,
+                    name_parts: vec!["Part1".to_string(), "Part2".to_string()],
+                })],
+            )],
+            transaction_overrides: hash_map![],
+            manual_transactions: hash_map![],
+            ignore_list: IgnoreList::default(),
+            archived: Archived::default(),
+            pending_accounts: hash_map![],
+            ledger_targets: LedgerTargets::default(),
+            account_renames: vec![],
+            balance_anchors: hash_map![],
+            transaction_times: hash_map![],
+            duplicates: Duplicates::default(),
+            amount_signs: hash_map![],
+        }
+    }
+
+    #[tokio::test]
+    async fn load_nonexisting() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1)).await.unwrap();
+        assert_eq!(None, loaded);
+    }
+
+    #[tokio::test]
+    async fn save_new_file_and_load() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let db = DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1));
+
+        db.save().await.unwrap();
+        let loaded = DatabaseFile::load(tempfile, cipher(1)).await.unwrap();
+        assert_eq!(some_db_1(), *loaded.unwrap().database());
+    }
+
+    #[tokio::test]
+    async fn overwrite_existing_file_and_load() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let db1 = DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1));
+        let db2 = DatabaseFile::new(some_db_2(), tempfile.clone(), cipher(1));
+
+        db1.save().await.unwrap();
+        db2.save().await.unwrap();
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(some_db_1(), *loaded.database());
+        assert_eq!(some_db_2(), *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn doesnt_load_with_wrong_key() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let db = DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(2));
+
+        db.save().await.unwrap();
+        let loaded = DatabaseFile::load(tempfile, cipher(1)).await.unwrap_err();
+        assert!(matches!(loaded, DbError::Decryption), "{loaded}");
+    }
+
+    fn some_db_with_sync_state() -> DatabaseV16 {
+        let mut db = some_db_1();
+        let connection = &mut db.bank_connections[0];
+        connection.set_sync_cursor("cursor-1".to_string());
+        let account = connection
+            .account_mut(&AccountId("account-1".to_string()))
+            .unwrap()
+            .account
+            .as_mut()
+            .unwrap();
+        account.sync_enabled = false;
+        account.balance_snapshots.push(BalanceSnapshot {
+            date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            current: Some(Decimal::new(12345, 2)),
+            available: None,
+            iso_currency_code: Some("USD".to_string()),
+        });
+        db
+    }
+
+    fn some_transactions(amount: Decimal) -> Transactions {
+        let mut transactions = Transactions::new_empty();
+        let _ = transactions.add_or_verify(
+            TransactionId("transaction-1".to_string()),
+            TransactionBuilder::new()
+                .amount(amount)
+                .description("Description")
+                .build(),
+        );
+        transactions
+    }
+
+    fn some_legacy_connection(amount: Decimal) -> BankConnectionV1 {
+        BankConnectionV1 {
+            name: "connection-name-1".to_string(),
+            access_token: AccessToken::new("access-token-1".to_string()),
+            accounts: hash_map![
+                AccountId("account-1".to_string()) => AccountV1 {
+                    plaid_account_info: PlaidAccountInfo {
+                        name: "Account 1".to_string(),
+                        official_name: None,
+                        mask: None,
+                        type_: "account-type".to_string(),
+                        subtype: None,
+                    },
+                    account: Some(ConnectedAccountV1 {
+                        beancount_account_info: BeancountAccountInfo {
+                            ty: AccountType::Assets,
+                            name_parts: vec!["Part1".to_string()],
+                        },
+                        transactions: some_transactions(amount),
+                    }),
+                },
+            ],
+        }
+    }
+
+    fn expected_migrated_db() -> DatabaseV16 {
+        let mut account = Account::new_connected(
+            PlaidAccountInfo {
+                name: "Account 1".to_string(),
+                official_name: None,
+                mask: None,
+                type_: "account-type".to_string(),
+                subtype: None,
+            },
+            BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Part1".to_string()],
+            },
+        );
+        account.account.as_mut().unwrap().transactions = some_transactions(Decimal::new(-1000, 2));
+        DatabaseV16 {
+            plaid_auth: DbPlaidAuth::new("client-id".to_string(), "secret".to_string()),
+            bank_connections: vec![BankConnection::new(
+                "connection-name-1".to_string(),
+                AccessToken::new("access-token-1".to_string()),
+                None,
+                hash_map![AccountId("account-1".to_string()) => account],
+            )],
+            transaction_overrides: hash_map![],
+            manual_transactions: hash_map![],
+            ignore_list: IgnoreList::default(),
+            archived: Archived::default(),
+            pending_accounts: hash_map![],
+            ledger_targets: LedgerTargets::default(),
+            account_renames: vec![],
+            balance_anchors: hash_map![],
+            transaction_times: hash_map![],
+            duplicates: Duplicates::default(),
+            amount_signs: hash_map![],
+        }
+    }
+
+    #[tokio::test]
+    async fn save_and_load_sync_state() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let db = DatabaseFile::new(some_db_with_sync_state(), tempfile.clone(), cipher(1));
+
+        db.save().await.unwrap();
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_with_sync_state(), *loaded.database());
+        assert_ne!(some_db_1(), *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v1_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let v1 = VersionedDatabase::V1(DatabaseV1 {
+            plaid_auth: DbPlaidAuth::new("client-id".to_string(), "secret".to_string()),
+            bank_connections: vec![some_legacy_connection(Decimal::new(1000, 2))],
+        });
+        write_versioned(&v1, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile.clone(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(expected_migrated_db(), *loaded.database());
+
+        // Saving writes the new format, which loads without another migration
+        loaded.save().await.unwrap();
+        let reloaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(expected_migrated_db(), *reloaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v2_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let v2 = VersionedDatabase::V2(DatabaseV2 {
+            plaid_auth: DbPlaidAuth::new("client-id".to_string(), "secret".to_string()),
+            bank_connections: vec![some_legacy_connection(Decimal::new(-1000, 2))],
+        });
+        write_versioned(&v2, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(expected_migrated_db(), *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v3_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let v3 = VersionedDatabase::V3(DatabaseV3 {
+            plaid_auth: DbPlaidAuth::new("client-id".to_string(), "secret".to_string()),
+            bank_connections: vec![some_legacy_connection(Decimal::new(-1000, 2)).migrate()],
+        });
+        write_versioned(&v3, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(expected_migrated_db(), *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v4_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v4 = VersionedDatabase::V4(DatabaseV4 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+        });
+        write_versioned(&v4, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(4, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v5_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v5 = VersionedDatabase::V5(DatabaseV5 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: hash_map![],
+        });
+        write_versioned(&v5, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(5, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v6_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v6 = VersionedDatabase::V6(DatabaseV6 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: hash_map![],
+            manual_transactions: expected.manual_transactions.clone(),
+        });
+        write_versioned(&v6, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(6, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v7_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v7 = VersionedDatabase::V7(DatabaseV7 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: hash_map![],
+            manual_transactions: expected.manual_transactions.clone(),
+            ignore_list: expected.ignore_list.clone(),
+        });
+        write_versioned(&v7, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(7, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v8_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let mut expected = expected_migrated_db();
+        let transaction_id = TransactionId("transaction-1".to_string());
+        let v8 = VersionedDatabase::V8(DatabaseV8 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: hash_map![transaction_id.clone() => TransactionOverridesV1 {
+                note: Some("note".to_string()),
+                account: None,
+                payee: Some("payee".to_string()),
+            }],
+            manual_transactions: expected.manual_transactions.clone(),
+            ignore_list: expected.ignore_list.clone(),
+            archived: expected.archived.clone(),
+        });
+        write_versioned(&v8, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        expected.transaction_overrides.insert(
+            transaction_id,
+            TransactionOverrides {
+                note: Some("note".to_string()),
+                account: None,
+                payee: Some("payee".to_string()),
+                category: None,
+            },
+        );
+        assert_eq!(8, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v9_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v9 = VersionedDatabase::V9(DatabaseV9 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: expected.transaction_overrides.clone(),
+            manual_transactions: expected.manual_transactions.clone(),
+            ignore_list: expected.ignore_list.clone(),
+            archived: expected.archived.clone(),
+        });
+        write_versioned(&v9, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(9, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v10_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v10 = VersionedDatabase::V10(DatabaseV10 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: expected.transaction_overrides.clone(),
+            manual_transactions: expected.manual_transactions.clone(),
+            ignore_list: expected.ignore_list.clone(),
+            archived: expected.archived.clone(),
+            pending_accounts: expected.pending_accounts.clone(),
+        });
+        write_versioned(&v10, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(10, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v11_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v11 = VersionedDatabase::V11(DatabaseV11 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: expected.transaction_overrides.clone(),
+            manual_transactions: expected.manual_transactions.clone(),
+            ignore_list: expected.ignore_list.clone(),
+            archived: expected.archived.clone(),
+            pending_accounts: expected.pending_accounts.clone(),
+            ledger_targets: expected.ledger_targets.clone(),
+        });
+        write_versioned(&v11, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(11, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v12_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v12 = VersionedDatabase::V12(DatabaseV12 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: expected.transaction_overrides.clone(),
+            manual_transactions: expected.manual_transactions.clone(),
+            ignore_list: expected.ignore_list.clone(),
+            archived: expected.archived.clone(),
+            pending_accounts: expected.pending_accounts.clone(),
+            ledger_targets: expected.ledger_targets.clone(),
+            account_renames: expected.account_renames.clone(),
+        });
+        write_versioned(&v12, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(12, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v13_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v13 = VersionedDatabase::V13(DatabaseV13 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: expected.transaction_overrides.clone(),
+            manual_transactions: expected.manual_transactions.clone(),
+            ignore_list: expected.ignore_list.clone(),
+            archived: expected.archived.clone(),
+            pending_accounts: expected.pending_accounts.clone(),
+            ledger_targets: expected.ledger_targets.clone(),
+            account_renames: expected.account_renames.clone(),
+            balance_anchors: expected.balance_anchors.clone(),
+        });
+        write_versioned(&v13, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(13, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v14_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v14 = VersionedDatabase::V14(DatabaseV14 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: expected.transaction_overrides.clone(),
+            manual_transactions: expected.manual_transactions.clone(),
+            ignore_list: expected.ignore_list.clone(),
+            archived: expected.archived.clone(),
+            pending_accounts: expected.pending_accounts.clone(),
+            ledger_targets: expected.ledger_targets.clone(),
+            account_renames: expected.account_renames.clone(),
+            balance_anchors: expected.balance_anchors.clone(),
+            transaction_times: expected.transaction_times.clone(),
+        });
+        write_versioned(&v14, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(14, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn load_v15_and_migrate() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let expected = expected_migrated_db();
+        let v15 = VersionedDatabase::V15(DatabaseV15 {
+            plaid_auth: expected.plaid_auth.clone(),
+            bank_connections: expected.bank_connections.clone(),
+            transaction_overrides: expected.transaction_overrides.clone(),
+            manual_transactions: expected.manual_transactions.clone(),
+            ignore_list: expected.ignore_list.clone(),
+            archived: expected.archived.clone(),
+            pending_accounts: expected.pending_accounts.clone(),
+            ledger_targets: expected.ledger_targets.clone(),
+            account_renames: expected.account_renames.clone(),
+            balance_anchors: expected.balance_anchors.clone(),
+            transaction_times: expected.transaction_times.clone(),
+            duplicates: expected.duplicates.clone(),
+        });
+        write_versioned(&v15, &tempfile, &cipher(1), DEFAULT_COMPRESSION_LEVEL, 0)
+            .await
+            .unwrap();
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(15, loaded.format_version());
+        assert_eq!(expected, *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn save_with_compression_level() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let fast = tempdir.path().join("fast");
+        let small = tempdir.path().join("small");
+
+        DatabaseFile::new(some_db_1(), fast.clone(), cipher(1))
+            .with_compression_level(1)
+            .save()
+            .await
+            .unwrap();
+        DatabaseFile::new(some_db_1(), small.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+
+        for path in [fast, small] {
+            let loaded = DatabaseFile::load(path, cipher(1)).await.unwrap().unwrap();
+            assert_eq!(some_db_1(), *loaded.database());
+        }
+    }
+
+    #[tokio::test]
+    async fn refuse_unsupported_compression_level() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let err = DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1))
+            .with_compression_level(100)
+            .save()
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("Compression level 100 isn't supported"),
+            "{err}"
+        );
+        assert!(!tempfile.exists());
+    }
+
+    #[tokio::test]
+    async fn retry_failed_save() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+        let mut loaded = DatabaseFile::load(tempfile.clone(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        *loaded.database_mut() = some_db_2();
+        // Writing the temporary file fails while a directory is in its place
+        let temp_path = temp_path(&tempfile).unwrap();
+        std::fs::create_dir(&temp_path).unwrap();
+        loaded.try_save_if_modified().await.unwrap_err();
+        std::fs::remove_dir(&temp_path).unwrap();
+        loaded.try_save_if_modified().await.unwrap();
+        drop(loaded);
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_2(), *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn cancelled_save() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+        let cancel = CancellationToken::new();
+        let mut loaded = DatabaseFile::load(tempfile.clone(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap()
+            .with_cancellation_token(cancel.clone());
+        *loaded.database_mut() = some_db_2();
+        cancel.cancel();
+        let err = loaded.save_if_modified().await.unwrap_err();
+        assert!(matches!(err, DbError::Cancelled), "{err}");
+        assert!(!temp_path(&tempfile).unwrap().exists());
+
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_1(), *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn save_copy() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+        let copy_path = tempdir.path().join("copy");
+
+        let db = DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1));
+        db.save_copy_to(&copy_path).await.unwrap();
+        assert!(!tempfile.exists());
+
+        let loaded = DatabaseFile::load(copy_path, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_1(), *loaded.database());
+    }
+
+    #[test]
+    fn rollback_to_begin() {
+        let mut db = DatabaseFile::new(some_db_1(), PathBuf::from("database"), cipher(1));
+        assert!(!db.rollback());
+
+        db.begin();
+        *db.database_mut() = some_db_2();
+        assert!(db.rollback());
+        assert_eq!(some_db_1(), *db.database());
+        // Nothing left to save
+        assert!(!db.modified);
+
+        db.begin();
+        *db.database_mut() = some_db_2();
+        db.commit();
+        assert!(!db.rollback());
+        assert_eq!(some_db_2(), *db.database());
+        assert!(db.modified);
+    }
+
+    #[tokio::test]
+    async fn cannot_load_twice() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+        let loaded = DatabaseFile::load(tempfile.clone(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        let err = DatabaseFile::load(tempfile.clone(), cipher(1))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("The database is locked by another process"),
+            "{err}"
+        );
+
+        loaded.save_if_modified().await.unwrap();
+        DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn save_keeps_backups() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+        DatabaseFile::new(some_db_2(), tempfile.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+
+        let (backup, _) = read_database(&backup_path(&tempfile, 1).unwrap(), &cipher(1))
+            .await
+            .unwrap();
+        assert_eq!(some_db_1(), backup);
+        assert!(!tokio::fs::try_exists(backup_path(&tempfile, 2).unwrap())
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn restore_backup() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+        DatabaseFile::new(some_db_2(), tempfile.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+
+        DatabaseFile::restore_backup(tempfile.clone(), cipher(1), 1, DEFAULT_NUM_BACKUPS)
+            .await
+            .unwrap();
+        let loaded = DatabaseFile::load(tempfile.clone(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_1(), *loaded.database());
+        drop(loaded);
+
+        // The database from before the restore became the most recent backup
+        let (backup, _) = read_database(&backup_path(&tempfile, 1).unwrap(), &cipher(1))
+            .await
+            .unwrap();
+        assert_eq!(some_db_2(), backup);
+    }
+
+    #[tokio::test]
+    async fn restore_nonexisting_backup() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+
+        let err = DatabaseFile::restore_backup(tempfile, cipher(1), 1, DEFAULT_NUM_BACKUPS)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Backup "), "{err}");
+    }
+
+    #[tokio::test]
+    async fn save_and_load_sqlite() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        DatabaseFile::new(some_db_with_sync_state(), tempfile.clone(), cipher(1))
+            .with_storage_backend(StorageBackend::Sqlite)
+            .save()
+            .await
+            .unwrap();
+        assert_eq!(
+            StorageBackend::Sqlite,
+            StorageBackend::detect(&tempfile).unwrap()
+        );
+
+        let mut loaded = DatabaseFile::load(tempfile.clone(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_with_sync_state(), *loaded.database());
+        loaded.database_mut().bank_connections[0].set_sync_cursor("cursor-2".to_string());
+        loaded.save_if_modified().await.unwrap();
+
+        // Loaded databases are saved with the backend they were loaded from
+        let reloaded = DatabaseFile::load(tempfile.clone(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            Some("cursor-2"),
+            reloaded.database().bank_connections[0].sync_cursor()
+        );
+        assert_eq!(
+            StorageBackend::Sqlite,
+            StorageBackend::detect(&tempfile).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn save_sqlite_repeatedly_in_one_session() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+        DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1))
+            .with_storage_backend(StorageBackend::Sqlite)
+            .save()
+            .await
+            .unwrap();
+        let mut loaded = DatabaseFile::load(tempfile.clone(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        // The loaded database holds the lock, so read the file directly
+        let reload = || sqlite::load(&tempfile, &cipher(1)).unwrap().0;
+
+        let transaction_id = TransactionId::new_manual();
+        let manual_transaction = |cents| ManualTransaction {
+            beancount_account_info: BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Cash".to_string()],
+            },
+            transaction: TransactionBuilder::new().cents(cents).build(),
+        };
+        loaded
+            .database_mut()
+            .manual_transactions
+            .insert(transaction_id.clone(), manual_transaction(100));
+        loaded.try_save_if_modified().await.unwrap();
+        loaded
+            .database_mut()
+            .manual_transactions
+            .insert(transaction_id.clone(), manual_transaction(200));
+        loaded.try_save_if_modified().await.unwrap();
+        assert_eq!(*loaded.database(), reload());
+
+        loaded
+            .database_mut()
+            .manual_transactions
+            .remove(&transaction_id);
+        loaded.try_save_if_modified().await.unwrap();
+        assert_eq!(*loaded.database(), reload());
+        assert!(reload().manual_transactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn restore_sqlite_backup() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        DatabaseFile::new(some_db_1(), tempfile.clone(), cipher(1))
+            .with_storage_backend(StorageBackend::Sqlite)
+            .save()
+            .await
+            .unwrap();
+        DatabaseFile::new(some_db_2(), tempfile.clone(), cipher(1))
+            .with_storage_backend(StorageBackend::Sqlite)
+            .save()
+            .await
+            .unwrap();
+
+        DatabaseFile::restore_backup(tempfile.clone(), cipher(1), 1, DEFAULT_NUM_BACKUPS)
+            .await
+            .unwrap();
+        let loaded = DatabaseFile::load(tempfile, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_1(), *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn save_and_load_unencrypted() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        DatabaseFile::new(some_db_1(), tempfile.clone(), DbCipher::Unencrypted)
+            .save()
+            .await
+            .unwrap();
+        assert!(!DatabaseFile::detect_encryption(&tempfile).unwrap());
+
+        let loaded = DatabaseFile::load(tempfile.clone(), DbCipher::Unencrypted)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!loaded.is_encrypted());
+        assert_eq!(some_db_1(), *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn encrypt_unencrypted_database() {
+        for storage_backend in [StorageBackend::File, StorageBackend::Sqlite] {
+            let tempdir = tempfile::tempdir().unwrap();
+            let tempfile = tempdir.path().join("database");
+
+            DatabaseFile::new(some_db_1(), tempfile.clone(), DbCipher::Unencrypted)
+                .with_storage_backend(storage_backend)
+                .save()
+                .await
+                .unwrap();
+            let mut loaded = DatabaseFile::load(tempfile.clone(), DbCipher::Unencrypted)
+                .await
+                .unwrap()
+                .unwrap();
+            loaded.set_cipher(cipher(1));
+            loaded.save_if_modified().await.unwrap();
+            assert!(DatabaseFile::detect_encryption(&tempfile).unwrap());
+
+            let err = DatabaseFile::load(tempfile.clone(), DbCipher::Unencrypted)
+                .await
+                .unwrap_err()
+                .to_string();
+            assert_eq!(
+                "The database is encrypted but no encryption key was given",
+                err
+            );
+            let loaded = DatabaseFile::load(tempfile, cipher(1))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(loaded.is_encrypted());
+            assert_eq!(some_db_1(), *loaded.database());
+        }
+    }
+
+    /// Write an unencrypted database file with the given decompressed content
+    fn write_unencrypted(path: &Path, content_decompressed: &[u8]) {
+        let content_compressed =
+            zstd::bulk::compress(content_decompressed, DEFAULT_COMPRESSION_LEVEL).unwrap();
+        std::fs::write(path, [UNENCRYPTED_HEADER, &content_compressed].concat()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn load_file_with_crc32() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let serialized =
+            postcard::to_stdvec_crc32(&VersionedDatabase::V16(some_db_1()), legacy_crc().digest())
+                .unwrap();
+        write_unencrypted(&tempfile, &serialized);
+
+        let loaded = DatabaseFile::load(tempfile, DbCipher::Unencrypted)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_1(), *loaded.database());
+    }
+
+    #[tokio::test]
+    async fn detect_corrupted_file_with_crc32() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let mut serialized =
+            postcard::to_stdvec_crc32(&VersionedDatabase::V16(some_db_1()), legacy_crc().digest())
+                .unwrap();
+        *serialized.last_mut().unwrap() ^= 1;
+        write_unencrypted(&tempfile, &serialized);
+
+        assert!(DatabaseFile::load(tempfile, DbCipher::Unencrypted)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn detect_corrupted_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let tempfile = tempdir.path().join("database");
+
+        let mut content =
+            add_hash(&postcard::to_stdvec(&VersionedDatabase::V16(some_db_1())).unwrap());
+        *content.last_mut().unwrap() ^= 1;
+        write_unencrypted(&tempfile, &content);
+
+        let err = DatabaseFile::load(tempfile, DbCipher::Unencrypted)
+            .await
+            .map(drop)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            "Database file is corrupted, its content doesn't match the stored hash",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn recover_leftover_temp_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        DatabaseFile::new(some_db_1(), db_path.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+        // Simulate a crash after writing the new version but before moving it into place
+        let new_version_path = tempdir.path().join("new_version");
+        DatabaseFile::new(some_db_2(), new_version_path.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+        std::fs::rename(&new_version_path, temp_path(&db_path).unwrap()).unwrap();
+
+        let leftovers = DatabaseFile::find_leftovers(&db_path, &cipher(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            Leftovers {
+                stale_lock_pid: None,
+                temp_file: Some(LeftoverTempFile {
+                    path: temp_path(&db_path).unwrap(),
+                    intact: true,
+                }),
+            },
+            leftovers
+        );
+
+        DatabaseFile::recover_temp_file(&db_path, &cipher(1), DEFAULT_NUM_BACKUPS)
+            .await
+            .unwrap();
+        assert!(DatabaseFile::find_leftovers(&db_path, &cipher(1))
+            .await
+            .unwrap()
+            .is_empty());
+        let loaded = DatabaseFile::load(db_path.clone(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_2(), *loaded.database());
+        drop(loaded);
+        let loaded_backup = DatabaseFile::load(backup_path(&db_path, 1).unwrap(), cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_1(), *loaded_backup.database());
+    }
+
+    #[tokio::test]
+    async fn remove_incomplete_temp_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        DatabaseFile::new(some_db_1(), db_path.clone(), cipher(1))
+            .save()
+            .await
+            .unwrap();
+        std::fs::write(temp_path(&db_path).unwrap(), b"incomplete").unwrap();
+
+        let leftovers = DatabaseFile::find_leftovers(&db_path, &cipher(1))
+            .await
+            .unwrap();
+        assert!(!leftovers.temp_file.unwrap().intact);
+        assert!(
+            DatabaseFile::recover_temp_file(&db_path, &cipher(1), DEFAULT_NUM_BACKUPS)
+                .await
+                .is_err()
+        );
+
+        DatabaseFile::remove_temp_file(&db_path).await.unwrap();
+        assert!(DatabaseFile::find_leftovers(&db_path, &cipher(1))
+            .await
+            .unwrap()
+            .is_empty());
+        let loaded = DatabaseFile::load(db_path, cipher(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(some_db_1(), *loaded.database());
+    }
+}
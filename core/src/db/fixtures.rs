@@ -0,0 +1,107 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use super::{Amount, Transaction, TransactionCategory, TransactionInfo};
+
+/// Builds transactions for tests. Defaults to a USD 1.00 transaction posted on 2024-01-01
+/// without category or description that wasn't exported yet.
+#[derive(Debug, Clone)]
+pub struct TransactionBuilder {
+    info: TransactionInfo,
+    already_exported: bool,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self {
+            info: TransactionInfo {
+                posted_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                authorized_date: None,
+                category: None,
+                amount: Amount {
+                    amount: Decimal::new(100, 2),
+                    iso_currency_code: Some("USD".to_string()),
+                },
+                merchant_name: None,
+                description_or_merchant_name: None,
+                original_description: None,
+                transaction_type: None,
+                location: None,
+                check_number: None,
+                associated_website: None,
+            },
+            already_exported: false,
+        }
+    }
+
+    pub fn posted_on(mut self, year: i32, month: u32, day: u32) -> Self {
+        self.info.posted_date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        self
+    }
+
+    pub fn authorized_on(mut self, year: i32, month: u32, day: u32) -> Self {
+        self.info.authorized_date = Some(NaiveDate::from_ymd_opt(year, month, day).unwrap());
+        self
+    }
+
+    /// Amount in USD
+    pub fn amount(mut self, amount: Decimal) -> Self {
+        self.info.amount.amount = amount;
+        self
+    }
+
+    /// Amount in USD cents
+    pub fn cents(self, cents: i64) -> Self {
+        self.amount(Decimal::new(cents, 2))
+    }
+
+    pub fn category(mut self, category: Option<TransactionCategory>) -> Self {
+        self.info.category = category;
+        self
+    }
+
+    /// Category with the given primary category and `{primary}_OTHER` as detailed category
+    pub fn primary_category(self, primary: Option<&str>) -> Self {
+        self.category(primary.map(|primary| TransactionCategory {
+            primary: primary.to_string(),
+            detailed: format!("{primary}_OTHER"),
+        }))
+    }
+
+    pub fn merchant_name(mut self, merchant_name: &str) -> Self {
+        self.info.merchant_name = Some(merchant_name.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.info.description_or_merchant_name = Some(description.to_string());
+        self
+    }
+
+    pub fn original_description(mut self, description: &str) -> Self {
+        self.info.original_description = Some(description.to_string());
+        self
+    }
+
+    pub fn exported(mut self, already_exported: bool) -> Self {
+        self.already_exported = already_exported;
+        self
+    }
+
+    pub fn info(self) -> TransactionInfo {
+        self.info
+    }
+
+    pub fn build(self) -> Transaction {
+        Transaction {
+            transaction: self.info,
+            already_exported: self.already_exported,
+        }
+    }
+}
+
+impl Default for TransactionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::{TransactionId, TransactionInfo};
+
+/// Transactions that should never be exported, e.g. internal sweeps between accounts.
+/// Ignored transactions are still synced and stored, so they can be un-ignored later.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct IgnoreList {
+    transactions: HashSet<TransactionId>,
+    rules: Vec<IgnoreRule>,
+}
+
+/// Ignores all transactions whose description or merchant name contains `pattern`, ignoring case
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreRule {
+    pub pattern: String,
+}
+
+impl IgnoreRule {
+    pub(super) fn matches(&self, transaction: &TransactionInfo) -> bool {
+        let pattern = self.pattern.to_lowercase();
+        [
+            &transaction.description_or_merchant_name,
+            &transaction.original_description,
+            &transaction.merchant_name,
+        ]
+        .into_iter()
+        .flatten()
+        .any(|text| text.to_lowercase().contains(&pattern))
+    }
+}
+
+impl IgnoreList {
+    pub fn is_ignored(
+        &self,
+        transaction_id: &TransactionId,
+        transaction: &TransactionInfo,
+    ) -> bool {
+        self.transactions.contains(transaction_id)
+            || self.rules.iter().any(|rule| rule.matches(transaction))
+    }
+
+    /// Returns false if the transaction was already ignored
+    pub fn ignore_transaction(&mut self, transaction_id: TransactionId) -> bool {
+        self.transactions.insert(transaction_id)
+    }
+
+    /// Returns false if the transaction wasn't ignored by id. It may still be ignored by a rule.
+    pub fn unignore_transaction(&mut self, transaction_id: &TransactionId) -> bool {
+        self.transactions.remove(transaction_id)
+    }
+
+    /// Returns false if there already is a rule with this pattern
+    pub fn add_rule(&mut self, rule: IgnoreRule) -> bool {
+        if self.rules.contains(&rule) {
+            return false;
+        }
+        self.rules.push(rule);
+        true
+    }
+
+    /// Returns false if there was no rule with this pattern
+    pub fn remove_rule(&mut self, pattern: &str) -> bool {
+        let num_rules = self.rules.len();
+        self.rules.retain(|rule| rule.pattern != pattern);
+        self.rules.len() != num_rules
+    }
+
+    /// Add all ignored transactions and rules of `other`
+    pub fn merge(&mut self, other: IgnoreList) {
+        self.transactions.extend(other.transactions);
+        for rule in other.rules {
+            self.add_rule(rule);
+        }
+    }
+
+    pub fn rules(&self) -> &[IgnoreRule] {
+        &self.rules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TransactionBuilder;
+
+    fn transaction(description: &str) -> TransactionInfo {
+        TransactionBuilder::new().description(description).info()
+    }
+
+    #[test]
+    fn ignore_by_id() {
+        let mut ignore_list = IgnoreList::default();
+        let id = TransactionId("transaction-1".to_string());
+        assert!(!ignore_list.is_ignored(&id, &transaction("Coffee")));
+        assert!(ignore_list.ignore_transaction(id.clone()));
+        assert!(!ignore_list.ignore_transaction(id.clone()));
+        assert!(ignore_list.is_ignored(&id, &transaction("Coffee")));
+        assert!(ignore_list.unignore_transaction(&id));
+        assert!(!ignore_list.is_ignored(&id, &transaction("Coffee")));
+    }
+
+    #[test]
+    fn ignore_by_rule() {
+        let mut ignore_list = IgnoreList::default();
+        let id = TransactionId("transaction-1".to_string());
+        assert!(ignore_list.add_rule(IgnoreRule {
+            pattern: "sweep".to_string(),
+        }));
+        assert!(ignore_list.is_ignored(&id, &transaction("Daily SWEEP to savings")));
+        assert!(!ignore_list.is_ignored(&id, &transaction("Coffee")));
+        assert!(ignore_list.remove_rule("sweep"));
+        assert!(!ignore_list.remove_rule("sweep"));
+        assert!(!ignore_list.is_ignored(&id, &transaction("Daily SWEEP to savings")));
+    }
+}
@@ -0,0 +1,107 @@
+//! Detects corrupted database files. The hash is stored inside the encrypted content, so for encrypted databases
+//! it's authenticated together with the data. Files written before BLAKE3 hashes were introduced use a CRC32
+//! appended by postcard instead and still load.
+
+use super::DbError;
+
+/// Starts the decompressed content of files with a BLAKE3 hash. Files with a CRC32 start with the postcard
+/// encoding of the [super::versioned::VersionedDatabase] variant, which is a small number and never `0xff`.
+const BLAKE3_HEADER: &[u8] = b"\xffbeancount-plaid blake3\n";
+
+/// Domain separation so the hash can't be confused with BLAKE3 hashes used for anything else
+const HASH_CONTEXT: &str = "beancount-plaid database file integrity v1";
+
+const HASH_LEN: usize = blake3::OUT_LEN;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Checked<'a> {
+    /// The hash matched, this is the serialized database
+    Blake3(&'a [u8]),
+    /// Older file, the serialized database still needs its CRC32 checked when deserializing
+    Crc32(&'a [u8]),
+}
+
+/// Prefix `serialized` with the header and its hash
+pub fn add_hash(serialized: &[u8]) -> Vec<u8> {
+    [BLAKE3_HEADER, hash(serialized).as_bytes(), serialized].concat()
+}
+
+/// Check the hash added by [add_hash] and return the serialized database
+pub fn check_hash(content: &[u8]) -> Result<Checked<'_>, DbError> {
+    let Some(content) = content.strip_prefix(BLAKE3_HEADER) else {
+        return Ok(Checked::Crc32(content));
+    };
+    if content.len() < HASH_LEN {
+        return Err(DbError::Corrupted(
+            "Database file is truncated, it doesn't contain the full hash".to_string(),
+        ));
+    }
+    let (expected_hash, serialized) = content.split_at(HASH_LEN);
+    let expected_hash = blake3::Hash::from_bytes(expected_hash.try_into().expect("Checked above"));
+    // blake3::Hash compares in constant time
+    if hash(serialized) != expected_hash {
+        return Err(DbError::Corrupted(
+            "Database file is corrupted, its content doesn't match the stored hash".to_string(),
+        ));
+    }
+    Ok(Checked::Blake3(serialized))
+}
+
+fn hash(serialized: &[u8]) -> blake3::Hash {
+    blake3::Hasher::new_derive_key(HASH_CONTEXT)
+        .update(serialized)
+        .finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERIALIZED: &[u8] = b"serialized database";
+
+    #[test]
+    fn add_and_check() {
+        let content = add_hash(SERIALIZED);
+        assert_eq!(Checked::Blake3(SERIALIZED), check_hash(&content).unwrap());
+    }
+
+    #[test]
+    fn crc32_content_is_passed_through() {
+        let content = [&[3u8][..], SERIALIZED].concat();
+        assert_eq!(Checked::Crc32(&content), check_hash(&content).unwrap());
+    }
+
+    #[test]
+    fn detect_corrupted_data() {
+        let mut content = add_hash(SERIALIZED);
+        *content.last_mut().unwrap() ^= 1;
+        let err = check_hash(&content).unwrap_err().to_string();
+        assert!(err.starts_with("Database file is corrupted"), "{err}");
+    }
+
+    #[test]
+    fn detect_corrupted_hash() {
+        let mut content = add_hash(SERIALIZED);
+        content[BLAKE3_HEADER.len()] ^= 1;
+        let err = check_hash(&content).unwrap_err().to_string();
+        assert!(err.starts_with("Database file is corrupted"), "{err}");
+    }
+
+    #[test]
+    fn detect_truncated_hash() {
+        let content = add_hash(SERIALIZED);
+        let err = check_hash(&content[..BLAKE3_HEADER.len() + HASH_LEN - 1])
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Database file is truncated"), "{err}");
+    }
+
+    #[test]
+    fn detect_missing_data() {
+        let content = add_hash(SERIALIZED);
+        let err = check_hash(&content[..content.len() - 1])
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Database file is corrupted"), "{err}");
+    }
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use super::{ignore::IgnoreRule, AccountId, TransactionInfo};
+
+/// Named sets of books, e.g. personal and business, that connections or single accounts are exported to separately.
+/// Transactions of connections and accounts that aren't assigned to any target belong to the default target.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct LedgerTargets {
+    targets: BTreeMap<String, LedgerTarget>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct LedgerTarget {
+    /// Exported transactions are appended to this file instead of being printed
+    pub output_file: Option<PathBuf>,
+    /// Used for transactions that Plaid didn't report a currency for
+    pub operating_currency: Option<String>,
+    /// Connections whose accounts all belong to this target, by connection name
+    pub connections: HashSet<String>,
+    /// Accounts that belong to this target, regardless of the target of their connection
+    pub accounts: HashSet<AccountId>,
+    /// Transactions matching any of these aren't exported to this target, in addition to the ignore list
+    pub ignore_rules: Vec<IgnoreRule>,
+}
+
+impl LedgerTarget {
+    pub fn is_ignored(&self, transaction: &TransactionInfo) -> bool {
+        self.ignore_rules
+            .iter()
+            .any(|rule| rule.matches(transaction))
+    }
+}
+
+impl LedgerTargets {
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &LedgerTarget)> {
+        self.targets
+            .iter()
+            .map(|(name, target)| (name.as_str(), target))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LedgerTarget> {
+        self.targets.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut LedgerTarget> {
+        self.targets.get_mut(name)
+    }
+
+    /// Returns false if there already is a target with this name
+    pub fn add(&mut self, name: String, target: LedgerTarget) -> bool {
+        if self.targets.contains_key(&name) {
+            return false;
+        }
+        self.targets.insert(name, target);
+        true
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<LedgerTarget> {
+        self.targets.remove(name)
+    }
+
+    /// Name of the target the transactions of this account are exported to, `None` for the default target.
+    /// An account assigned to a target directly takes precedence over the target of its connection.
+    pub fn target_of(&self, connection_name: &str, account_id: &AccountId) -> Option<&str> {
+        self.iter()
+            .find(|(_, target)| target.accounts.contains(account_id))
+            .or_else(|| {
+                self.iter()
+                    .find(|(_, target)| target.connections.contains(connection_name))
+            })
+            .map(|(name, _)| name)
+    }
+
+    /// Move the connection, or only one of its accounts, to the given target or to the default target if `None`
+    pub fn assign(
+        &mut self,
+        target_name: Option<&str>,
+        connection_name: &str,
+        account_id: Option<&AccountId>,
+    ) {
+        for target in self.targets.values_mut() {
+            match account_id {
+                Some(account_id) => {
+                    target.accounts.remove(account_id);
+                }
+                None => {
+                    target.connections.remove(connection_name);
+                }
+            }
+        }
+        let Some(target) = target_name.and_then(|name| self.targets.get_mut(name)) else {
+            return;
+        };
+        match account_id {
+            Some(account_id) => {
+                target.accounts.insert(account_id.clone());
+            }
+            None => {
+                target.connections.insert(connection_name.to_string());
+            }
+        }
+    }
+
+    /// Add the targets of `other` that don't exist here yet. Connections and accounts that are already assigned
+    /// to a target here keep it.
+    pub fn merge(&mut self, other: LedgerTargets) {
+        for (name, mut target) in other.targets {
+            if self.targets.contains_key(&name) {
+                continue;
+            }
+            target.connections.retain(|connection_name| {
+                !self
+                    .targets
+                    .values()
+                    .any(|existing| existing.connections.contains(connection_name))
+            });
+            target.accounts.retain(|account_id| {
+                !self
+                    .targets
+                    .values()
+                    .any(|existing| existing.accounts.contains(account_id))
+            });
+            self.targets.insert(name, target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn account_assignment_takes_precedence() {
+        let mut targets = LedgerTargets::default();
+        assert!(targets.add("business".to_string(), LedgerTarget::default()));
+        assert!(targets.add("personal".to_string(), LedgerTarget::default()));
+        assert!(!targets.add("personal".to_string(), LedgerTarget::default()));
+
+        let checking = AccountId("checking".to_string());
+        let credit_card = AccountId("credit-card".to_string());
+        assert_eq!(None, targets.target_of("bank", &checking));
+
+        targets.assign(Some("business"), "bank", None);
+        targets.assign(Some("personal"), "bank", Some(&credit_card));
+        assert_eq!(Some("business"), targets.target_of("bank", &checking));
+        assert_eq!(Some("personal"), targets.target_of("bank", &credit_card));
+        assert_eq!(None, targets.target_of("other-bank", &checking));
+
+        targets.assign(None, "bank", Some(&credit_card));
+        assert_eq!(Some("business"), targets.target_of("bank", &credit_card));
+
+        targets.remove("business");
+        assert_eq!(None, targets.target_of("bank", &checking));
+    }
+}
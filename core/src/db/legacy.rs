@@ -0,0 +1,140 @@
+//! Types as they were stored by older database versions, e.g. [super::database::DatabaseV1] to [super::database::DatabaseV3].
+//! The database format isn't self-describing, so these must never change. They're only used to load old databases.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    account::{Account, ConnectedAccount},
+    AccessToken, AccountId, BalanceSnapshot, BankConnection, BeancountAccountInfo,
+    PlaidAccountInfo, TransactionOverrides, Transactions,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct BankConnectionV1 {
+    pub name: String,
+    pub access_token: AccessToken,
+    pub accounts: HashMap<AccountId, AccountV1>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct AccountV1 {
+    pub plaid_account_info: PlaidAccountInfo,
+    pub account: Option<ConnectedAccountV1>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct ConnectedAccountV1 {
+    pub beancount_account_info: BeancountAccountInfo,
+    pub transactions: Transactions,
+}
+
+impl BankConnectionV1 {
+    /// Old databases didn't store the institution id or sync state. All connected accounts get synced.
+    pub fn migrate(self) -> BankConnectionV3 {
+        let accounts = self
+            .accounts
+            .into_iter()
+            .map(|(account_id, account)| {
+                let account = AccountV3 {
+                    plaid_account_info: account.plaid_account_info,
+                    account: account.account.map(|account| ConnectedAccountV3 {
+                        beancount_account_info: account.beancount_account_info,
+                        transactions: account.transactions,
+                        sync_enabled: true,
+                        balance_snapshots: vec![],
+                    }),
+                };
+                (account_id, account)
+            })
+            .collect();
+        BankConnectionV3 {
+            name: self.name,
+            access_token: self.access_token,
+            institution_id: None,
+            sync_cursor: None,
+            accounts,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct BankConnectionV3 {
+    pub name: String,
+    pub access_token: AccessToken,
+    pub institution_id: Option<String>,
+    pub sync_cursor: Option<String>,
+    pub accounts: HashMap<AccountId, AccountV3>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct AccountV3 {
+    pub plaid_account_info: PlaidAccountInfo,
+    pub account: Option<ConnectedAccountV3>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct ConnectedAccountV3 {
+    pub beancount_account_info: BeancountAccountInfo,
+    pub transactions: Transactions,
+    pub sync_enabled: bool,
+    pub balance_snapshots: Vec<BalanceSnapshot>,
+}
+
+impl BankConnectionV3 {
+    /// Nothing was pruned yet in old databases
+    pub fn migrate(self) -> BankConnection {
+        let accounts = self
+            .accounts
+            .into_iter()
+            .map(|(account_id, account)| {
+                let account = Account {
+                    plaid_account_info: account.plaid_account_info,
+                    account: account.account.map(|account| ConnectedAccount {
+                        beancount_account_info: account.beancount_account_info,
+                        transactions: account.transactions,
+                        sync_enabled: account.sync_enabled,
+                        balance_snapshots: account.balance_snapshots,
+                        pruned_transactions: HashSet::new(),
+                    }),
+                };
+                (account_id, account)
+            })
+            .collect();
+        let mut connection =
+            BankConnection::new(self.name, self.access_token, self.institution_id, accounts);
+        if let Some(sync_cursor) = self.sync_cursor {
+            connection.set_sync_cursor(sync_cursor);
+        }
+        connection
+    }
+}
+
+/// Transaction overrides as stored by [super::database::DatabaseV5] to [super::database::DatabaseV8]
+/// and by SQLite databases before schema version 7
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct TransactionOverridesV1 {
+    pub note: Option<String>,
+    pub account: Option<BeancountAccountInfo>,
+    pub payee: Option<String>,
+}
+
+impl TransactionOverridesV1 {
+    /// Old databases didn't store category overrides
+    pub fn migrate(self) -> TransactionOverrides {
+        TransactionOverrides {
+            note: self.note,
+            account: self.account,
+            payee: self.payee,
+            category: None,
+        }
+    }
+}
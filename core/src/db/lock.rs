@@ -0,0 +1,201 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::OpenOptions,
+    io::{ErrorKind, Write as _},
+    path::{Path, PathBuf},
+};
+
+use super::{backup::sibling_path, DbError};
+
+/// Lock file next to the database that prevents multiple processes from accessing the database at the same time.
+/// It contains the PID of the process holding the lock and is removed when the [DbLock] is dropped.
+#[derive(Debug)]
+pub struct DbLock {
+    lock_path: PathBuf,
+}
+
+impl DbLock {
+    pub fn acquire(db_path: &Path) -> Result<Self, DbError> {
+        let lock_path = lock_path(db_path)?;
+        let mut lock_file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(lock_file) => lock_file,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                let holder_pid = std::fs::read_to_string(&lock_path)
+                    .ok()
+                    .map(|pid| pid.trim().to_string());
+                return Err(DatabaseLocked {
+                    lock_path,
+                    holder_pid,
+                }
+                .into());
+            }
+            Err(source) => {
+                return Err(DbError::File {
+                    action: "create lock file",
+                    path: lock_path,
+                    source,
+                })
+            }
+        };
+        let lock = Self { lock_path };
+        write!(lock_file, "{}", std::process::id())
+            .map_err(DbError::file("write lock file", &lock.lock_path))?;
+        Ok(lock)
+    }
+}
+
+/// Returned as [DbError::Locked] if another process holds the lock
+#[derive(Debug)]
+pub struct DatabaseLocked {
+    pub lock_path: PathBuf,
+    pub holder_pid: Option<String>,
+}
+
+impl Display for DatabaseLocked {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "The database is locked by another process")?;
+        if let Some(holder_pid) = &self.holder_pid {
+            write!(f, " (PID {holder_pid})")?;
+        }
+        write!(
+            f,
+            ". If no other process is using the database, delete the lock file at {}",
+            self.lock_path.display(),
+        )
+    }
+}
+
+impl std::error::Error for DatabaseLocked {}
+
+impl Drop for DbLock {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.lock_path) {
+            tracing::warn!(
+                "Failed to remove lock file at {}: {err}",
+                self.lock_path.display()
+            );
+        }
+    }
+}
+
+/// PID of the process holding the lock if that process isn't running anymore, e.g. because it crashed
+pub fn stale_lock_pid(db_path: &Path) -> Result<Option<u32>, DbError> {
+    let lock_path = lock_path(db_path)?;
+    let content = match std::fs::read_to_string(&lock_path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(DbError::File {
+                action: "read lock file",
+                path: lock_path,
+                source,
+            })
+        }
+    };
+    // Without a PID we can't tell whether the holder is still running
+    let Ok(pid) = content.trim().parse::<u32>() else {
+        return Ok(None);
+    };
+    Ok((!is_running(pid)).then_some(pid))
+}
+
+/// Remove the lock left behind by a crashed process. Fails if the holder is still running.
+pub fn remove_stale_lock(db_path: &Path) -> Result<(), DbError> {
+    if stale_lock_pid(db_path)?.is_none() {
+        return Err(DbError::Refused(
+            "The database isn't locked by a crashed process".to_string(),
+        ));
+    }
+    let lock_path = lock_path(db_path)?;
+    std::fs::remove_file(&lock_path).map_err(DbError::file("remove lock file", &lock_path))
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks whether the process exists. EPERM means it exists but belongs to another user.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// We can't check other platforms, so never consider their locks stale
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+fn lock_path(db_path: &Path) -> Result<PathBuf, DbError> {
+    sibling_path(db_path, ".lock")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cannot_acquire_twice() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        let _lock = DbLock::acquire(&db_path).unwrap();
+        let err = DbLock::acquire(&db_path).unwrap_err();
+        assert!(matches!(err, DbError::Locked(_)));
+        let err = err.to_string();
+        assert!(
+            err.starts_with(&format!(
+                "The database is locked by another process (PID {})",
+                std::process::id()
+            )),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn can_acquire_after_release() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        let lock = DbLock::acquire(&db_path).unwrap();
+        drop(lock);
+        assert!(!lock_path(&db_path).unwrap().exists());
+        let _lock = DbLock::acquire(&db_path).unwrap();
+    }
+
+    #[test]
+    fn lock_of_running_process_isnt_stale() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        let _lock = DbLock::acquire(&db_path).unwrap();
+        assert_eq!(None, stale_lock_pid(&db_path).unwrap());
+        assert!(remove_stale_lock(&db_path).is_err());
+        assert!(lock_path(&db_path).unwrap().exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn remove_lock_of_crashed_process() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        // Larger than the maximum PID on Linux
+        std::fs::write(lock_path(&db_path).unwrap(), "4294967").unwrap();
+
+        assert_eq!(Some(4294967), stale_lock_pid(&db_path).unwrap());
+        remove_stale_lock(&db_path).unwrap();
+        let _lock = DbLock::acquire(&db_path).unwrap();
+    }
+
+    #[test]
+    fn different_databases_dont_conflict() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let _lock1 = DbLock::acquire(&tempdir.path().join("database1")).unwrap();
+        let _lock2 = DbLock::acquire(&tempdir.path().join("database2")).unwrap();
+    }
+}
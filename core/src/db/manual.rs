@@ -0,0 +1,24 @@
+use rand::RngCore as _;
+use serde::{Deserialize, Serialize};
+
+use super::{account::BeancountAccountInfo, Transaction, TransactionId};
+
+/// Prefix of the ids of manually entered transactions. Plaid ids never contain a dash.
+const MANUAL_TRANSACTION_ID_PREFIX: &str = "manual-";
+
+/// A transaction that didn't come from Plaid, e.g. a cash payment, entered with the `transaction add` command
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct ManualTransaction {
+    pub beancount_account_info: BeancountAccountInfo,
+    pub transaction: Transaction,
+}
+
+impl TransactionId {
+    pub fn new_manual() -> Self {
+        let mut id = [0; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+        let id: String = id.iter().map(|byte| format!("{byte:02x}")).collect();
+        Self(format!("{MANUAL_TRANSACTION_ID_PREFIX}{id}"))
+    }
+}
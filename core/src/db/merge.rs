@@ -0,0 +1,409 @@
+use super::{
+    account::Account, bank_connection::BankConnection, database::DatabaseV16, AccountId,
+    AddOrVerifyResult, DbError, Transaction, TransactionId, Transactions,
+};
+
+#[derive(Debug)]
+pub struct MergeReport {
+    pub connections: Vec<ConnectionMergeReport>,
+}
+
+#[derive(Debug)]
+pub struct ConnectionMergeReport {
+    pub name: String,
+    /// The connection didn't exist yet and was added with all its accounts
+    pub added_connection: bool,
+    pub accounts: Vec<AccountMergeReport>,
+}
+
+#[derive(Debug)]
+pub struct AccountMergeReport {
+    pub name: String,
+    /// The account didn't exist or wasn't connected yet and was taken over from the other database
+    pub added_account: bool,
+    pub num_added: usize,
+    pub num_verified: usize,
+    /// Transactions that exist in both databases with different content. We keep our version of these.
+    pub conflicts: Vec<MergeConflict>,
+}
+
+#[derive(Debug)]
+pub struct MergeConflict {
+    pub transaction_id: TransactionId,
+    pub existing_value: Transaction,
+    pub new_value: Transaction,
+}
+
+/// Import the connections and transactions of `other` into `database`, e.g. from a database on a second machine.
+/// Connections are matched by their access token, accounts by their Plaid account id.
+/// Transactions that exist in both databases are verified to match, mismatches are reported as conflicts.
+/// A transaction that was exported from either database stays marked as exported.
+/// Transaction overrides are taken from `other` unless `database` has its own for that transaction, same for the times
+/// Plaid reported for transactions.
+/// Manually entered transactions of `other` are added unless `database` already has them.
+/// Ignored transactions and ignore rules of both databases are combined, same for suspected and reviewed duplicates.
+/// Archived connections of `other` aren't imported.
+/// Ledger targets of `other` are added unless `database` has one with the same name.
+/// Account renames of `other` aren't imported, the Beancount accounts of `database` stay as they are.
+/// Balance anchors of `other` aren't imported either, the next sync of `database` takes new ones.
+/// Pending accounts of `other` stay pending unless they're connected in `database`, in which case their transactions aren't imported.
+/// Accounts in both databases must store their amounts with the same [super::AmountSign], because the transactions
+/// are compared. Accounts that are only in `other` keep their sign.
+pub fn merge_databases(
+    database: &mut DatabaseV16,
+    other: DatabaseV16,
+) -> Result<MergeReport, DbError> {
+    if database.plaid_auth.client_id() != other.plaid_auth.client_id() {
+        return Err(DbError::Refused(
+            "The databases use different Plaid clients, their access tokens can't be merged"
+                .to_string(),
+        ));
+    }
+    // Check everything before changing anything so we don't end up with a half merged database
+    for other_connection in &other.bank_connections {
+        if find_connection(database, other_connection).is_none()
+            && database
+                .bank_connections
+                .iter()
+                .any(|connection| connection.name() == other_connection.name())
+        {
+            return Err(DbError::Refused(format!(
+                "Connection {} exists in both databases but for different bank logins",
+                other_connection.name()
+            )));
+        }
+    }
+    for (account_id, account) in other
+        .bank_connections
+        .iter()
+        .flat_map(BankConnection::accounts)
+    {
+        let in_both = database
+            .bank_connections
+            .iter()
+            .any(|connection| connection.account(account_id).is_some());
+        if in_both && database.amount_sign(account_id) != other.amount_sign(account_id) {
+            return Err(DbError::Refused(format!(
+                "Account {} stores its amounts with a different sign in the two databases, change one with `account sign` first",
+                account.plaid_account_info.name
+            )));
+        }
+    }
+
+    for (transaction_id, overrides) in other.transaction_overrides {
+        database
+            .transaction_overrides
+            .entry(transaction_id)
+            .or_insert(overrides);
+    }
+    for (transaction_id, times) in other.transaction_times {
+        database
+            .transaction_times
+            .entry(transaction_id)
+            .or_insert(times);
+    }
+    database.ignore_list.merge(other.ignore_list);
+    database.duplicates.merge(other.duplicates);
+    for (account_id, sign) in other.amount_signs {
+        database.amount_signs.entry(account_id).or_insert(sign);
+    }
+    database.ledger_targets.merge(other.ledger_targets);
+    for (transaction_id, transaction) in other.manual_transactions {
+        database
+            .manual_transactions
+            .entry(transaction_id)
+            .or_insert(transaction);
+    }
+    let connections = other
+        .bank_connections
+        .into_iter()
+        .map(
+            |other_connection| match find_connection(database, &other_connection) {
+                Some(index) => {
+                    merge_connection(&mut database.bank_connections[index], other_connection)
+                }
+                None => {
+                    let report = ConnectionMergeReport {
+                        name: other_connection.name().to_string(),
+                        added_connection: true,
+                        accounts: other_connection
+                            .accounts()
+                            .map(|(_, account)| added_account_report(account))
+                            .collect(),
+                    };
+                    database.bank_connections.push(other_connection);
+                    report
+                }
+            },
+        )
+        .collect();
+    for (account_id, other_transactions) in other.pending_accounts {
+        let is_connected = database.bank_connections.iter().any(|connection| {
+            connection
+                .account(&account_id)
+                .is_some_and(|account| account.is_connected())
+        });
+        if is_connected {
+            continue;
+        }
+        let transactions = database
+            .pending_accounts
+            .entry(account_id)
+            .or_insert_with(Transactions::new_empty);
+        for (transaction_id, transaction) in other_transactions.into_iter_sorted_by_date() {
+            let _ = transactions.add_or_verify(transaction_id, transaction);
+        }
+    }
+    Ok(MergeReport { connections })
+}
+
+fn find_connection(database: &DatabaseV16, other_connection: &BankConnection) -> Option<usize> {
+    database.bank_connections.iter().position(|connection| {
+        connection.access_token().get() == other_connection.access_token().get()
+    })
+}
+
+fn merge_connection(
+    connection: &mut BankConnection,
+    other_connection: BankConnection,
+) -> ConnectionMergeReport {
+    let name = connection.name().to_string();
+    let accounts = other_connection
+        .into_accounts()
+        .filter_map(|(account_id, other_account)| {
+            merge_account(connection, account_id, other_account)
+        })
+        .collect();
+    ConnectionMergeReport {
+        name,
+        added_connection: false,
+        accounts,
+    }
+}
+
+/// Returns `None` if there was nothing to merge because the account isn't connected in the other database
+fn merge_account(
+    connection: &mut BankConnection,
+    account_id: AccountId,
+    other_account: Account,
+) -> Option<AccountMergeReport> {
+    let existing = connection
+        .account_mut(&account_id)
+        .and_then(|account| account.account.as_mut());
+    let Some(existing) = existing else {
+        if other_account.is_connected() || connection.account(&account_id).is_none() {
+            let report = added_account_report(&other_account);
+            connection.insert_account(account_id, other_account);
+            return Some(report);
+        }
+        return None;
+    };
+    let name = other_account.plaid_account_info.name;
+    let other_account = other_account.account?;
+
+    let mut report = AccountMergeReport {
+        name,
+        added_account: false,
+        num_added: 0,
+        num_verified: 0,
+        conflicts: vec![],
+    };
+    for (transaction_id, transaction) in other_account.transactions.into_iter_sorted_by_date() {
+        let already_exported = transaction.already_exported;
+        match existing.add_or_verify_transaction(transaction_id.clone(), transaction) {
+            AddOrVerifyResult::Added => report.num_added += 1,
+            AddOrVerifyResult::ExistsAndMatches => {
+                report.num_verified += 1;
+                if already_exported {
+                    if let Some(existing) = existing.transactions.get_mut(&transaction_id) {
+                        existing.mark_as_exported();
+                    }
+                }
+            }
+            AddOrVerifyResult::Pruned => report.num_verified += 1,
+            AddOrVerifyResult::ExistsAndDoesntMatch {
+                existing_value,
+                new_value,
+            } => report.conflicts.push(MergeConflict {
+                transaction_id,
+                existing_value,
+                new_value,
+            }),
+        }
+    }
+    Some(report)
+}
+
+fn added_account_report(account: &Account) -> AccountMergeReport {
+    AccountMergeReport {
+        name: account.plaid_account_info.name.clone(),
+        added_account: true,
+        num_added: account
+            .account
+            .as_ref()
+            .map(|account| account.transactions.len())
+            .unwrap_or(0),
+        num_verified: 0,
+        conflicts: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common_macros::hash_map;
+
+    use crate::db::{
+        archived::Archived, duplicates::Duplicates, ignore::IgnoreList,
+        ledger_target::LedgerTargets, AccessToken, AccountType, AmountSign, BeancountAccountInfo,
+        DbPlaidAuth, PlaidAccountInfo, TransactionBuilder,
+    };
+
+    use super::*;
+
+    fn transaction(day: u32, amount: i64, already_exported: bool) -> Transaction {
+        TransactionBuilder::new()
+            .posted_on(2024, 1, day)
+            .cents(amount)
+            .exported(already_exported)
+            .build()
+    }
+
+    fn database(
+        connection_name: &str,
+        access_token: &str,
+        transactions: &[(&str, Transaction)],
+    ) -> DatabaseV16 {
+        let mut account = Account::new_connected(
+            PlaidAccountInfo {
+                name: "Checking".to_string(),
+                official_name: None,
+                mask: None,
+                type_: "depository".to_string(),
+                subtype: None,
+            },
+            BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Checking".to_string()],
+            },
+        );
+        let connected_account = account.account.as_mut().unwrap();
+        for (id, transaction) in transactions {
+            let _ = connected_account
+                .add_or_verify_transaction(TransactionId(id.to_string()), transaction.clone());
+        }
+        DatabaseV16 {
+            plaid_auth: DbPlaidAuth::new("client-id".to_string(), "secret".to_string()),
+            bank_connections: vec![BankConnection::new(
+                connection_name.to_string(),
+                AccessToken::new(access_token.to_string()),
+                None,
+                hash_map![AccountId("account-1".to_string()) => account],
+            )],
+            transaction_overrides: hash_map![],
+            manual_transactions: hash_map![],
+            ignore_list: IgnoreList::default(),
+            archived: Archived::default(),
+            pending_accounts: hash_map![],
+            ledger_targets: LedgerTargets::default(),
+            account_renames: vec![],
+            balance_anchors: hash_map![],
+            transaction_times: hash_map![],
+            duplicates: Duplicates::default(),
+            amount_signs: hash_map![],
+        }
+    }
+
+    fn transactions(database: &DatabaseV16, connection: usize) -> Vec<(String, Transaction)> {
+        database.bank_connections[connection]
+            .account(&AccountId("account-1".to_string()))
+            .unwrap()
+            .account
+            .as_ref()
+            .unwrap()
+            .transactions
+            .iter_all_sorted_by_date()
+            .map(|(id, transaction)| (id.0.clone(), transaction.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn merge_transactions_into_existing_connection() {
+        let mut db = database(
+            "Bank",
+            "token",
+            &[
+                ("a", transaction(1, 100, false)),
+                ("b", transaction(2, 200, false)),
+            ],
+        );
+        let other = database(
+            "Bank on laptop",
+            "token",
+            &[
+                ("a", transaction(1, 100, true)),
+                ("b", transaction(2, 999, false)),
+                ("c", transaction(3, 300, false)),
+            ],
+        );
+
+        let report = merge_databases(&mut db, other).unwrap();
+
+        assert_eq!(1, db.bank_connections.len());
+        let account = &report.connections[0].accounts[0];
+        assert!(!report.connections[0].added_connection);
+        assert_eq!(1, account.num_added);
+        assert_eq!(1, account.num_verified);
+        assert_eq!(1, account.conflicts.len());
+        assert_eq!("b", account.conflicts[0].transaction_id.0);
+        assert_eq!(
+            vec![
+                ("a".to_string(), transaction(1, 100, true)),
+                ("b".to_string(), transaction(2, 200, false)),
+                ("c".to_string(), transaction(3, 300, false)),
+            ],
+            transactions(&db, 0)
+        );
+    }
+
+    #[test]
+    fn add_new_connection() {
+        let mut db = database("Bank", "token-1", &[("a", transaction(1, 100, false))]);
+        let other = database(
+            "Other bank",
+            "token-2",
+            &[("b", transaction(2, 200, false))],
+        );
+
+        let report = merge_databases(&mut db, other).unwrap();
+
+        assert!(report.connections[0].added_connection);
+        assert_eq!(1, report.connections[0].accounts[0].num_added);
+        assert_eq!(2, db.bank_connections.len());
+        assert_eq!(
+            vec![("b".to_string(), transaction(2, 200, false))],
+            transactions(&db, 1)
+        );
+    }
+
+    #[test]
+    fn refuse_same_name_for_different_login() {
+        let mut db = database("Bank", "token-1", &[]);
+        let other = database("Bank", "token-2", &[("b", transaction(2, 200, false))]);
+
+        let err = merge_databases(&mut db, other).unwrap_err().to_string();
+        assert!(err.contains("different bank logins"), "{err}");
+        assert_eq!(1, db.bank_connections.len());
+    }
+
+    #[test]
+    fn refuse_different_amount_signs() {
+        let mut db = database("Bank", "token", &[("a", transaction(1, 100, false))]);
+        let mut other = database("Bank", "token", &[("a", transaction(1, -100, false))]);
+        other
+            .amount_signs
+            .insert(AccountId("account-1".to_string()), AmountSign::Inverted);
+
+        let err = merge_databases(&mut db, other).unwrap_err().to_string();
+        assert!(err.contains("different sign"), "{err}");
+    }
+}
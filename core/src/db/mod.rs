@@ -0,0 +1,71 @@
+mod access_token;
+mod account;
+mod account_rename;
+#[cfg(feature = "age")]
+mod age_key;
+mod amount_sign;
+mod archive;
+mod archived;
+mod backup;
+mod balance_anchor;
+mod bank_connection;
+mod crypto;
+mod database;
+mod duplicates;
+mod error;
+mod file;
+#[cfg(any(test, feature = "test-utils"))]
+mod fixtures;
+mod ignore;
+mod integrity;
+mod ledger_target;
+mod legacy;
+mod lock;
+mod manual;
+mod merge;
+mod overrides;
+mod plaid_auth;
+mod snapshot;
+mod sqlite;
+mod storage;
+mod transaction_times;
+mod transactions;
+mod versioned;
+
+pub use access_token::AccessToken;
+pub use account::{
+    Account, AccountId, AccountType, BalanceSnapshot, BeancountAccountInfo, ConnectedAccount,
+    PlaidAccountInfo,
+};
+pub use account_rename::{AccountRename, RenameDirectives};
+#[cfg(feature = "age")]
+pub use age_key::{unwrap_key_with_age, wrap_key_with_age};
+pub use amount_sign::AmountSign;
+pub use archive::{pack_archive, unpack_archive};
+pub use archived::{Archived, ArchivedAccount, ArchivedConnection};
+pub use backup::DEFAULT_NUM_BACKUPS;
+pub use balance_anchor::BalanceAnchor;
+pub use bank_connection::BankConnection;
+pub use crypto::{Cipher, DbCipher, EncryptionKey, XChaCha20Poly1305Cipher, KEY_SIZE};
+pub use database::DatabaseV16;
+pub use duplicates::{flag_duplicates, Duplicates, Fingerprint, SuspectedDuplicate};
+pub use error::DbError;
+pub use file::{DatabaseFile, LeftoverTempFile, Leftovers, DEFAULT_COMPRESSION_LEVEL};
+#[cfg(any(test, feature = "test-utils"))]
+pub use fixtures::TransactionBuilder;
+pub use ignore::{IgnoreList, IgnoreRule};
+pub use ledger_target::{LedgerTarget, LedgerTargets};
+pub use lock::DatabaseLocked;
+pub use manual::ManualTransaction;
+pub use merge::{
+    merge_databases, AccountMergeReport, ConnectionMergeReport, MergeConflict, MergeReport,
+};
+pub use overrides::TransactionOverrides;
+pub use plaid_auth::DbPlaidAuth;
+pub use snapshot::Snapshot;
+pub use storage::StorageBackend;
+pub use transaction_times::TransactionTimes;
+pub use transactions::{
+    AddOrVerifyResult, Amount, Transaction, TransactionCategory, TransactionId, TransactionInfo,
+    Transactions,
+};
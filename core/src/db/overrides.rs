@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+use super::{account::BeancountAccountInfo, TransactionCategory};
+
+/// Corrections the user made to a stored transaction. They take precedence over the Plaid data when exporting
+/// and are kept separately from the transaction, so they survive re-syncs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct TransactionOverrides {
+    pub note: Option<String>,
+    /// Account for the balancing posting, e.g. `Expenses:Groceries`
+    pub account: Option<BeancountAccountInfo>,
+    pub payee: Option<String>,
+    /// Replaces the category Plaid assigned to the transaction, e.g. because Plaid got it wrong
+    pub category: Option<TransactionCategory>,
+}
+
+impl TransactionOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.note.is_none()
+            && self.account.is_none()
+            && self.payee.is_none()
+            && self.category.is_none()
+    }
+}
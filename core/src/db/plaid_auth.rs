@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+use zeroize::Zeroizing;
+
+const PLAID_VERSION: &str = "2020-09-14";
+
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct DbPlaidAuth {
+    client_id: String,
+    secret: Zeroizing<String>,
+}
+
+impl DbPlaidAuth {
+    pub fn new(client_id: String, secret: String) -> Self {
+        Self {
+            client_id,
+            secret: Zeroizing::new(secret),
+        }
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// The Plaid client takes the secret as a plain [String], so that copy isn't wiped from memory
+    pub fn to_api_auth(&self) -> plaid::PlaidAuth {
+        plaid::PlaidAuth::ClientId {
+            client_id: self.client_id.clone(),
+            secret: self.secret.to_string(),
+            plaid_version: PLAID_VERSION.to_string(),
+        }
+    }
+}
+
+impl Debug for DbPlaidAuth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DbPlaidAuth")
+            .field("client_id", &self.client_id)
+            .field("secret", &"*****")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_redacts_secret() {
+        let auth = DbPlaidAuth::new("client-id".to_string(), "very-secret".to_string());
+        let debug = format!("{auth:?}");
+        assert!(debug.contains("client-id"), "{debug}");
+        assert!(!debug.contains("very-secret"), "{debug}");
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use super::{backup::sibling_path, DbError};
+use crate::error::ParseError;
+
+/// Describes the change that was made after a backup was taken, so `undo` can tell the user what it undoes.
+/// The snapshots are stored newest first in a file next to the database. The first snapshot describes the change
+/// from backup generation 1 to the current database, the second one from generation 2 to generation 1, and so on.
+/// Backups from before snapshots were recorded don't have one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub command: String,
+    pub created_at: DateTime<Utc>,
+}
+
+fn snapshots_path(db_path: &Path) -> Result<PathBuf, DbError> {
+    sibling_path(db_path, ".snapshots")
+}
+
+/// Returns the snapshots, newest first
+pub async fn load_snapshots(db_path: &Path) -> Result<Vec<Snapshot>, DbError> {
+    let path = snapshots_path(db_path)?;
+    if !tokio::fs::try_exists(&path).await? {
+        return Ok(vec![]);
+    }
+    let content = tokio::fs::read(&path).await?;
+    serde_json::from_slice(&content).map_err(|source| ParseError::Json { path, source }.into())
+}
+
+async fn store_snapshots(db_path: &Path, snapshots: &[Snapshot]) -> Result<(), DbError> {
+    let path = snapshots_path(db_path)?;
+    let tmppath = sibling_path(&path, ".temp")?;
+    let content = serde_json::to_vec_pretty(snapshots).map_err(|err| DbError::Serialize {
+        what: "snapshots",
+        source: err.into(),
+    })?;
+    tokio::fs::write(&tmppath, content).await?;
+    tokio::fs::rename(&tmppath, &path).await?;
+    Ok(())
+}
+
+/// Record that `command` changed the database after the backups were rotated.
+/// Only the snapshots of the `num_backups` kept backups are kept.
+pub async fn push_snapshot(
+    db_path: &Path,
+    command: &str,
+    num_backups: usize,
+) -> Result<(), DbError> {
+    let mut snapshots = load_snapshots(db_path).await?;
+    snapshots.insert(
+        0,
+        Snapshot {
+            command: command.to_string(),
+            created_at: Utc::now(),
+        },
+    );
+    snapshots.truncate(num_backups);
+    store_snapshots(db_path, &snapshots).await
+}
+
+/// Remove the newest snapshot after its backup was restored by `undo`.
+/// Returns `None` if the backup didn't have a snapshot.
+pub async fn pop_snapshot(db_path: &Path) -> Result<Option<Snapshot>, DbError> {
+    let mut snapshots = load_snapshots(db_path).await?;
+    if snapshots.is_empty() {
+        return Ok(None);
+    }
+    let snapshot = snapshots.remove(0);
+    store_snapshots(db_path, &snapshots).await?;
+    Ok(Some(snapshot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(snapshots: &[Snapshot]) -> Vec<&str> {
+        snapshots.iter().map(|s| s.command.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn push_and_pop() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        assert_eq!(None, pop_snapshot(&db_path).await.unwrap());
+        for command in ["sync", "export-new", "remove-connection"] {
+            push_snapshot(&db_path, command, 2).await.unwrap();
+        }
+        assert_eq!(
+            vec!["remove-connection", "export-new"],
+            commands(&load_snapshots(&db_path).await.unwrap())
+        );
+
+        let popped = pop_snapshot(&db_path).await.unwrap().unwrap();
+        assert_eq!("remove-connection", popped.command);
+        assert_eq!(
+            vec!["export-new"],
+            commands(&load_snapshots(&db_path).await.unwrap())
+        );
+    }
+}
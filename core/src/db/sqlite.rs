@@ -0,0 +1,1370 @@
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension as _};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash as _, Hasher as _},
+    path::Path,
+};
+
+use super::{
+    account::{Account, BalanceSnapshot, BeancountAccountInfo, ConnectedAccount, PlaidAccountInfo},
+    archived::Archived,
+    bank_connection::BankConnection,
+    crypto::{Cipher as _, DbCipher},
+    database::DatabaseV16,
+    duplicates::Duplicates,
+    ignore::IgnoreList,
+    ledger_target::LedgerTargets,
+    legacy::TransactionOverridesV1,
+    manual::ManualTransaction,
+    overrides::TransactionOverrides,
+    plaid_auth::DbPlaidAuth,
+    transaction_times::TransactionTimes,
+    AccessToken, AccountId, DbError, Transaction, TransactionId, Transactions,
+};
+use crate::error::ParseError;
+
+type Result<T, E = DbError> = std::result::Result<T, E>;
+
+/// Every SQLite database file starts with this, see https://www.sqlite.org/fileformat.html
+pub const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Stored in `PRAGMA user_version`. Increase it when changing the tables or the format of any row.
+/// Version 1 didn't have the `pruned_transactions` table yet, version 2 didn't have the `transaction_overrides` table,
+/// version 3 didn't have the `manual_transactions` table, version 4 didn't have the ignore list row in `meta`,
+/// version 5 didn't have the archived row in `meta`, version 6 stored transaction overrides without a category,
+/// version 7 didn't have the `pending_accounts` and `pending_transactions` tables, version 8 didn't have the ledger
+/// targets row in `meta`, version 9 didn't have the account renames row in `meta`, version 10 didn't have the balance
+/// anchors row in `meta`, version 11 didn't have the `transaction_times` table, version 12 didn't have the duplicates
+/// row in `meta`, version 13 didn't have the amount signs row in `meta`. Otherwise they're the same as version 14.
+pub const SCHEMA_VERSION: u32 = 14;
+
+/// Plaid's account and transaction ids are random identifiers, so they're stored in plaintext to be usable as keys.
+/// Everything else is in the `data` columns, encrypted with the database key.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS bank_connections (
+        position INTEGER PRIMARY KEY NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transactions (
+        connection INTEGER NOT NULL,
+        account_id TEXT NOT NULL,
+        transaction_id TEXT NOT NULL,
+        data BLOB NOT NULL,
+        PRIMARY KEY (connection, account_id, transaction_id)
+    );
+    CREATE TABLE IF NOT EXISTS pruned_transactions (
+        connection INTEGER NOT NULL,
+        account_id TEXT NOT NULL,
+        transaction_id TEXT NOT NULL,
+        PRIMARY KEY (connection, account_id, transaction_id)
+    );
+    CREATE TABLE IF NOT EXISTS transaction_overrides (
+        transaction_id TEXT PRIMARY KEY NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transaction_times (
+        transaction_id TEXT PRIMARY KEY NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS manual_transactions (
+        transaction_id TEXT PRIMARY KEY NOT NULL,
+        data BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS pending_accounts (
+        account_id TEXT PRIMARY KEY NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS pending_transactions (
+        account_id TEXT NOT NULL,
+        transaction_id TEXT NOT NULL,
+        data BLOB NOT NULL,
+        PRIMARY KEY (account_id, transaction_id)
+    );
+";
+
+const PLAID_AUTH_KEY: &str = "plaid_auth";
+const IGNORE_LIST_KEY: &str = "ignore_list";
+/// Archived connections and accounts are rarely changed, so they're stored together in one row
+const ARCHIVED_KEY: &str = "archived";
+const LEDGER_TARGETS_KEY: &str = "ledger_targets";
+const ACCOUNT_RENAMES_KEY: &str = "account_renames";
+/// Changed by every sync, but small enough to keep in one row
+const BALANCE_ANCHORS_KEY: &str = "balance_anchors";
+const DUPLICATES_KEY: &str = "duplicates";
+const AMOUNT_SIGNS_KEY: &str = "amount_signs";
+/// Stored in plaintext, `[1]` if the other rows are encrypted and `[0]` if not
+const ENCRYPTED_KEY: &str = "encrypted";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum RowKey {
+    PlaidAuth,
+    IgnoreList,
+    Archived,
+    LedgerTargets,
+    AccountRenames,
+    BalanceAnchors,
+    Duplicates,
+    AmountSigns,
+    BankConnection {
+        position: usize,
+    },
+    Transaction {
+        connection: usize,
+        account_id: AccountId,
+        transaction_id: TransactionId,
+    },
+    /// Doesn't have any data besides its key
+    PrunedTransaction {
+        connection: usize,
+        account_id: AccountId,
+        transaction_id: TransactionId,
+    },
+    TransactionOverrides {
+        transaction_id: TransactionId,
+    },
+    TransactionTimes {
+        transaction_id: TransactionId,
+    },
+    ManualTransaction {
+        transaction_id: TransactionId,
+    },
+    /// Doesn't have any data besides its key
+    PendingAccount {
+        account_id: AccountId,
+    },
+    PendingTransaction {
+        account_id: AccountId,
+        transaction_id: TransactionId,
+    },
+}
+
+/// Hashes of the plaintext of all rows currently in the SQLite database.
+/// Used to only encrypt and write rows that changed since the database was loaded.
+/// Empty for databases that weren't loaded from SQLite, in which case saving replaces all rows.
+#[derive(Debug, Default)]
+pub struct StoredRows {
+    hashes: HashMap<RowKey, u64>,
+}
+
+/// Everything about a bank connection except for its transactions, which are stored in their own rows
+#[derive(Serialize, Deserialize)]
+struct BankConnectionRow {
+    name: String,
+    access_token: AccessToken,
+    institution_id: Option<String>,
+    sync_cursor: Option<String>,
+    /// Sorted by account id so unchanged connections serialize to the same bytes
+    accounts: Vec<(AccountId, AccountRow)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccountRow {
+    plaid_account_info: PlaidAccountInfo,
+    account: Option<ConnectedAccountRow>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ConnectedAccountRow {
+    beancount_account_info: BeancountAccountInfo,
+    sync_enabled: bool,
+    balance_snapshots: Vec<BalanceSnapshot>,
+}
+
+impl BankConnectionRow {
+    fn new(connection: &BankConnection) -> Self {
+        let mut accounts: Vec<(AccountId, AccountRow)> = connection
+            .accounts()
+            .map(|(account_id, account)| {
+                let row = AccountRow {
+                    plaid_account_info: account.plaid_account_info.clone(),
+                    account: account.account.as_ref().map(|account| ConnectedAccountRow {
+                        beancount_account_info: account.beancount_account_info.clone(),
+                        sync_enabled: account.sync_enabled,
+                        balance_snapshots: account.balance_snapshots.clone(),
+                    }),
+                };
+                (account_id.clone(), row)
+            })
+            .collect();
+        accounts.sort_by(|(lhs, _), (rhs, _)| lhs.0.cmp(&rhs.0));
+        Self {
+            name: connection.name().to_string(),
+            access_token: connection.access_token().clone(),
+            institution_id: connection.institution_id().map(str::to_string),
+            sync_cursor: connection.sync_cursor().map(str::to_string),
+            accounts,
+        }
+    }
+
+    fn into_bank_connection(
+        self,
+        mut transactions: HashMap<AccountId, Transactions>,
+        mut pruned_transactions: HashMap<AccountId, HashSet<TransactionId>>,
+    ) -> Result<BankConnection> {
+        let accounts = self
+            .accounts
+            .into_iter()
+            .map(|(account_id, row)| {
+                let account = row.account.map(|account| ConnectedAccount {
+                    beancount_account_info: account.beancount_account_info,
+                    transactions: transactions
+                        .remove(&account_id)
+                        .unwrap_or_else(Transactions::new_empty),
+                    sync_enabled: account.sync_enabled,
+                    balance_snapshots: account.balance_snapshots,
+                    pruned_transactions: pruned_transactions
+                        .remove(&account_id)
+                        .unwrap_or_default(),
+                });
+                let account = Account {
+                    plaid_account_info: row.plaid_account_info,
+                    account,
+                };
+                (account_id, account)
+            })
+            .collect();
+        if let Some(account_id) = transactions.keys().chain(pruned_transactions.keys()).next() {
+            return Err(DbError::Corrupted(format!(
+                "Found transactions for account {} which isn't a connected account of bank connection {}",
+                account_id.0,
+                self.name,
+            )));
+        }
+        let mut connection =
+            BankConnection::new(self.name, self.access_token, self.institution_id, accounts);
+        if let Some(sync_cursor) = self.sync_cursor {
+            connection.set_sync_cursor(sync_cursor);
+        }
+        Ok(connection)
+    }
+}
+
+/// Returns the connection and the schema version of the database
+fn open_read_only(db_path: &Path) -> Result<(Connection, u32)> {
+    let connection = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let schema_version: u32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if !(1..=SCHEMA_VERSION).contains(&schema_version) {
+        return Err(DbError::Corrupted(format!(
+            "Unsupported SQLite database version {schema_version}, expected at most {SCHEMA_VERSION}"
+        )));
+    }
+    Ok((connection, schema_version))
+}
+
+fn read_is_encrypted(connection: &Connection) -> Result<bool> {
+    let encrypted: Option<Vec<u8>> = connection
+        .query_row(
+            "SELECT data FROM meta WHERE key = ?1",
+            [ENCRYPTED_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match encrypted.as_deref() {
+        Some([0]) => Ok(false),
+        Some([1]) => Ok(true),
+        Some(_) => Err(DbError::Corrupted(
+            "Invalid encryption marker in the database".to_string(),
+        )),
+        None => Err(DbError::Corrupted(
+            "Database doesn't say whether it's encrypted".to_string(),
+        )),
+    }
+}
+
+pub fn is_encrypted(db_path: &Path) -> Result<bool> {
+    read_is_encrypted(&open_read_only(db_path)?.0)
+}
+
+/// Returns the database, what's stored in it, and its schema version.
+/// `db_cipher` is only used if the database is encrypted
+pub fn load(db_path: &Path, db_cipher: &DbCipher) -> Result<(DatabaseV16, StoredRows, u32)> {
+    let (connection, schema_version) = open_read_only(db_path)?;
+    let cipher = if read_is_encrypted(&connection)? {
+        Some(db_cipher.require_key()?)
+    } else {
+        None
+    };
+    let mut hashes = HashMap::new();
+    let mut decrypt = |key: RowKey, ciphertext: Vec<u8>| -> Result<Vec<u8>> {
+        let plaintext = match cipher {
+            Some(cipher) => cipher.decrypt(&ciphertext)?,
+            None => ciphertext,
+        };
+        hashes.insert(key, hash(&plaintext));
+        Ok(plaintext)
+    };
+
+    let plaid_auth: Vec<u8> = connection
+        .query_row(
+            "SELECT data FROM meta WHERE key = ?1",
+            [PLAID_AUTH_KEY],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| {
+            DbError::Corrupted("Database doesn't contain the Plaid credentials".to_string())
+        })?;
+    let plaid_auth: DbPlaidAuth = deserialize(&decrypt(RowKey::PlaidAuth, plaid_auth)?)?;
+
+    let ignore_list: Option<Vec<u8>> = connection
+        .query_row(
+            "SELECT data FROM meta WHERE key = ?1",
+            [IGNORE_LIST_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let ignore_list = match ignore_list {
+        Some(ignore_list) => deserialize(&decrypt(RowKey::IgnoreList, ignore_list)?)?,
+        None => IgnoreList::default(),
+    };
+
+    let archived: Option<Vec<u8>> = connection
+        .query_row(
+            "SELECT data FROM meta WHERE key = ?1",
+            [ARCHIVED_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let archived = match archived {
+        Some(archived) => deserialize(&decrypt(RowKey::Archived, archived)?)?,
+        None => Archived::default(),
+    };
+
+    let ledger_targets: Option<Vec<u8>> = connection
+        .query_row(
+            "SELECT data FROM meta WHERE key = ?1",
+            [LEDGER_TARGETS_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let ledger_targets = match ledger_targets {
+        Some(ledger_targets) => deserialize(&decrypt(RowKey::LedgerTargets, ledger_targets)?)?,
+        None => LedgerTargets::default(),
+    };
+
+    let account_renames: Option<Vec<u8>> = connection
+        .query_row(
+            "SELECT data FROM meta WHERE key = ?1",
+            [ACCOUNT_RENAMES_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let account_renames = match account_renames {
+        Some(account_renames) => deserialize(&decrypt(RowKey::AccountRenames, account_renames)?)?,
+        None => vec![],
+    };
+
+    let balance_anchors: Option<Vec<u8>> = connection
+        .query_row(
+            "SELECT data FROM meta WHERE key = ?1",
+            [BALANCE_ANCHORS_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let balance_anchors = match balance_anchors {
+        Some(balance_anchors) => deserialize(&decrypt(RowKey::BalanceAnchors, balance_anchors)?)?,
+        None => HashMap::new(),
+    };
+
+    let duplicates: Option<Vec<u8>> = connection
+        .query_row(
+            "SELECT data FROM meta WHERE key = ?1",
+            [DUPLICATES_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let duplicates = match duplicates {
+        Some(duplicates) => deserialize(&decrypt(RowKey::Duplicates, duplicates)?)?,
+        None => Duplicates::default(),
+    };
+
+    let amount_signs: Option<Vec<u8>> = connection
+        .query_row(
+            "SELECT data FROM meta WHERE key = ?1",
+            [AMOUNT_SIGNS_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let amount_signs = match amount_signs {
+        Some(amount_signs) => deserialize(&decrypt(RowKey::AmountSigns, amount_signs)?)?,
+        None => HashMap::new(),
+    };
+
+    let mut transactions: HashMap<usize, HashMap<AccountId, Vec<(TransactionId, Transaction)>>> =
+        HashMap::new();
+    let mut statement = connection
+        .prepare("SELECT connection, account_id, transaction_id, data FROM transactions")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let connection: usize = row.get(0)?;
+        let account_id = AccountId(row.get(1)?);
+        let transaction_id = TransactionId(row.get(2)?);
+        let key = RowKey::Transaction {
+            connection,
+            account_id: account_id.clone(),
+            transaction_id: transaction_id.clone(),
+        };
+        let transaction: Transaction = deserialize(&decrypt(key, row.get(3)?)?)?;
+        transactions
+            .entry(connection)
+            .or_default()
+            .entry(account_id)
+            .or_default()
+            .push((transaction_id, transaction));
+    }
+    drop(rows);
+
+    let mut pruned_transactions: HashMap<usize, HashMap<AccountId, HashSet<TransactionId>>> =
+        HashMap::new();
+    let mut pruned_keys = vec![];
+    if schema_version >= 2 {
+        let mut statement = connection
+            .prepare("SELECT connection, account_id, transaction_id FROM pruned_transactions")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let connection: usize = row.get(0)?;
+            let account_id = AccountId(row.get(1)?);
+            let transaction_id = TransactionId(row.get(2)?);
+            let key = RowKey::PrunedTransaction {
+                connection,
+                account_id: account_id.clone(),
+                transaction_id: transaction_id.clone(),
+            };
+            pruned_keys.push(key);
+            pruned_transactions
+                .entry(connection)
+                .or_default()
+                .entry(account_id)
+                .or_default()
+                .insert(transaction_id);
+        }
+    }
+
+    let mut transaction_overrides = HashMap::new();
+    if schema_version >= 3 {
+        let mut statement =
+            connection.prepare("SELECT transaction_id, data FROM transaction_overrides")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let transaction_id = TransactionId(row.get(0)?);
+            let key = RowKey::TransactionOverrides {
+                transaction_id: transaction_id.clone(),
+            };
+            let plaintext = decrypt(key, row.get(1)?)?;
+            let overrides: TransactionOverrides = if schema_version >= 7 {
+                deserialize(&plaintext)?
+            } else {
+                deserialize::<TransactionOverridesV1>(&plaintext)?.migrate()
+            };
+            transaction_overrides.insert(transaction_id, overrides);
+        }
+    }
+
+    let mut transaction_times = HashMap::new();
+    if schema_version >= 12 {
+        let mut statement =
+            connection.prepare("SELECT transaction_id, data FROM transaction_times")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let transaction_id = TransactionId(row.get(0)?);
+            let key = RowKey::TransactionTimes {
+                transaction_id: transaction_id.clone(),
+            };
+            let times: TransactionTimes = deserialize(&decrypt(key, row.get(1)?)?)?;
+            transaction_times.insert(transaction_id, times);
+        }
+    }
+
+    let mut manual_transactions = HashMap::new();
+    if schema_version >= 4 {
+        let mut statement =
+            connection.prepare("SELECT transaction_id, data FROM manual_transactions")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let transaction_id = TransactionId(row.get(0)?);
+            let key = RowKey::ManualTransaction {
+                transaction_id: transaction_id.clone(),
+            };
+            let transaction: ManualTransaction = deserialize(&decrypt(key, row.get(1)?)?)?;
+            manual_transactions.insert(transaction_id, transaction);
+        }
+    }
+
+    let mut pending_accounts: HashMap<AccountId, Transactions> = HashMap::new();
+    let mut pending_keys = vec![];
+    if schema_version >= 8 {
+        let mut statement = connection.prepare("SELECT account_id FROM pending_accounts")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let account_id = AccountId(row.get(0)?);
+            pending_keys.push(RowKey::PendingAccount {
+                account_id: account_id.clone(),
+            });
+            pending_accounts.insert(account_id, Transactions::new_empty());
+        }
+        drop(rows);
+
+        let mut statement = connection
+            .prepare("SELECT account_id, transaction_id, data FROM pending_transactions")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let account_id = AccountId(row.get(0)?);
+            let transaction_id = TransactionId(row.get(1)?);
+            let key = RowKey::PendingTransaction {
+                account_id: account_id.clone(),
+                transaction_id: transaction_id.clone(),
+            };
+            let transaction: Transaction = deserialize(&decrypt(key, row.get(2)?)?)?;
+            let transactions = pending_accounts.get_mut(&account_id).ok_or_else(|| {
+                DbError::Corrupted(format!(
+                    "Found pending transactions for account {} which isn't pending",
+                    account_id.0
+                ))
+            })?;
+            let _ = transactions.add_or_verify(transaction_id, transaction);
+        }
+    }
+
+    let mut bank_connections = vec![];
+    let mut statement =
+        connection.prepare("SELECT position, data FROM bank_connections ORDER BY position")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let position: usize = row.get(0)?;
+        if position != bank_connections.len() {
+            return Err(DbError::Corrupted(format!(
+                "Bank connection {} is missing in the database",
+                bank_connections.len()
+            )));
+        }
+        let bank_connection: BankConnectionRow =
+            deserialize(&decrypt(RowKey::BankConnection { position }, row.get(1)?)?)?;
+        let account_transactions = transactions
+            .remove(&position)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(account_id, transactions)| (account_id, transactions.into_iter().collect()))
+            .collect();
+        let account_pruned_transactions = pruned_transactions.remove(&position).unwrap_or_default();
+        bank_connections.push(
+            bank_connection
+                .into_bank_connection(account_transactions, account_pruned_transactions)?,
+        );
+    }
+    if !transactions.is_empty() || !pruned_transactions.is_empty() {
+        return Err(DbError::Corrupted(
+            "Found transactions for a bank connection that doesn't exist".to_string(),
+        ));
+    }
+
+    hashes.extend(
+        pruned_keys
+            .into_iter()
+            .chain(pending_keys)
+            .map(|key| (key, hash(&[]))),
+    );
+
+    let database = DatabaseV16 {
+        plaid_auth,
+        bank_connections,
+        transaction_overrides,
+        manual_transactions,
+        ignore_list,
+        archived,
+        pending_accounts,
+        ledger_targets,
+        account_renames,
+        balance_anchors,
+        transaction_times,
+        duplicates,
+        amount_signs,
+    };
+    Ok((database, StoredRows { hashes }, schema_version))
+}
+
+/// Write all rows that changed since `stored_rows` and delete the ones that don't exist anymore.
+/// All changes are written in one SQLite transaction, so a crash leaves either the old or the new database.
+pub fn save(
+    db_path: &Path,
+    db_cipher: &DbCipher,
+    database: &DatabaseV16,
+    stored_rows: &StoredRows,
+) -> Result<StoredRows> {
+    let mut connection = Connection::open(db_path)?;
+    connection.execute_batch(SCHEMA)?;
+    connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    let transaction = connection.transaction()?;
+    if stored_rows.hashes.is_empty() {
+        // We don't know what's in the file, start from scratch
+        transaction.execute_batch(
+            "DELETE FROM meta; DELETE FROM bank_connections; DELETE FROM transactions; DELETE FROM pruned_transactions; DELETE FROM transaction_overrides; DELETE FROM transaction_times; DELETE FROM manual_transactions; DELETE FROM pending_accounts; DELETE FROM pending_transactions;",
+        )?;
+    }
+
+    transaction.execute(
+        "INSERT OR REPLACE INTO meta (key, data) VALUES (?1, ?2)",
+        params![ENCRYPTED_KEY, [u8::from(db_cipher.is_encrypted())]],
+    )?;
+
+    let mut hashes = HashMap::new();
+    for (key, plaintext) in rows(database)? {
+        let hash = hash(&plaintext);
+        if stored_rows.hashes.get(&key) != Some(&hash) {
+            let ciphertext = match key {
+                RowKey::PrunedTransaction { .. } | RowKey::PendingAccount { .. } => vec![],
+                _ => db_cipher.encrypt(&plaintext)?,
+            };
+            upsert_row(&transaction, &key, &ciphertext)?;
+        }
+        hashes.insert(key, hash);
+    }
+    for key in stored_rows.hashes.keys() {
+        if !hashes.contains_key(key) {
+            delete_row(&transaction, key)?;
+        }
+    }
+    transaction.commit()?;
+
+    Ok(StoredRows { hashes })
+}
+
+/// Serialize the database into the plaintext of its rows
+fn rows(database: &DatabaseV16) -> Result<Vec<(RowKey, Vec<u8>)>> {
+    let mut rows = vec![
+        (RowKey::PlaidAuth, serialize(&database.plaid_auth)?),
+        (RowKey::IgnoreList, serialize(&database.ignore_list)?),
+        (RowKey::Archived, serialize(&database.archived)?),
+        (RowKey::LedgerTargets, serialize(&database.ledger_targets)?),
+        (
+            RowKey::AccountRenames,
+            serialize(&database.account_renames)?,
+        ),
+        (
+            RowKey::BalanceAnchors,
+            serialize(&database.balance_anchors)?,
+        ),
+        (RowKey::Duplicates, serialize(&database.duplicates)?),
+        (RowKey::AmountSigns, serialize(&database.amount_signs)?),
+    ];
+    for (position, bank_connection) in database.bank_connections.iter().enumerate() {
+        rows.push((
+            RowKey::BankConnection { position },
+            serialize(&BankConnectionRow::new(bank_connection))?,
+        ));
+        for (account_id, account) in bank_connection.accounts() {
+            let Some(account) = &account.account else {
+                continue;
+            };
+            for (transaction_id, transaction) in account.transactions.iter_all_sorted_by_date() {
+                let key = RowKey::Transaction {
+                    connection: position,
+                    account_id: account_id.clone(),
+                    transaction_id: transaction_id.clone(),
+                };
+                rows.push((key, serialize(transaction)?));
+            }
+            for transaction_id in &account.pruned_transactions {
+                let key = RowKey::PrunedTransaction {
+                    connection: position,
+                    account_id: account_id.clone(),
+                    transaction_id: transaction_id.clone(),
+                };
+                rows.push((key, vec![]));
+            }
+        }
+    }
+    for (transaction_id, overrides) in &database.transaction_overrides {
+        let key = RowKey::TransactionOverrides {
+            transaction_id: transaction_id.clone(),
+        };
+        rows.push((key, serialize(overrides)?));
+    }
+    for (transaction_id, times) in &database.transaction_times {
+        let key = RowKey::TransactionTimes {
+            transaction_id: transaction_id.clone(),
+        };
+        rows.push((key, serialize(times)?));
+    }
+    for (transaction_id, transaction) in &database.manual_transactions {
+        let key = RowKey::ManualTransaction {
+            transaction_id: transaction_id.clone(),
+        };
+        rows.push((key, serialize(transaction)?));
+    }
+    for (account_id, transactions) in &database.pending_accounts {
+        rows.push((
+            RowKey::PendingAccount {
+                account_id: account_id.clone(),
+            },
+            vec![],
+        ));
+        for (transaction_id, transaction) in transactions.iter_all_sorted_by_date() {
+            let key = RowKey::PendingTransaction {
+                account_id: account_id.clone(),
+                transaction_id: transaction_id.clone(),
+            };
+            rows.push((key, serialize(transaction)?));
+        }
+    }
+    Ok(rows)
+}
+
+fn upsert_row(transaction: &rusqlite::Transaction, key: &RowKey, data: &[u8]) -> Result<()> {
+    match key {
+        RowKey::PlaidAuth => transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, data) VALUES (?1, ?2)",
+            params![PLAID_AUTH_KEY, data],
+        )?,
+        RowKey::IgnoreList => transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, data) VALUES (?1, ?2)",
+            params![IGNORE_LIST_KEY, data],
+        )?,
+        RowKey::Archived => transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, data) VALUES (?1, ?2)",
+            params![ARCHIVED_KEY, data],
+        )?,
+        RowKey::LedgerTargets => transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, data) VALUES (?1, ?2)",
+            params![LEDGER_TARGETS_KEY, data],
+        )?,
+        RowKey::AccountRenames => transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, data) VALUES (?1, ?2)",
+            params![ACCOUNT_RENAMES_KEY, data],
+        )?,
+        RowKey::BalanceAnchors => transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, data) VALUES (?1, ?2)",
+            params![BALANCE_ANCHORS_KEY, data],
+        )?,
+        RowKey::Duplicates => transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, data) VALUES (?1, ?2)",
+            params![DUPLICATES_KEY, data],
+        )?,
+        RowKey::AmountSigns => transaction.execute(
+            "INSERT OR REPLACE INTO meta (key, data) VALUES (?1, ?2)",
+            params![AMOUNT_SIGNS_KEY, data],
+        )?,
+        RowKey::BankConnection { position } => transaction.execute(
+            "INSERT OR REPLACE INTO bank_connections (position, data) VALUES (?1, ?2)",
+            params![position, data],
+        )?,
+        RowKey::Transaction {
+            connection,
+            account_id,
+            transaction_id,
+        } => transaction.execute(
+            "INSERT OR REPLACE INTO transactions (connection, account_id, transaction_id, data) VALUES (?1, ?2, ?3, ?4)",
+            params![connection, account_id.0, transaction_id.0, data],
+        )?,
+        RowKey::PrunedTransaction {
+            connection,
+            account_id,
+            transaction_id,
+        } => transaction.execute(
+            "INSERT OR REPLACE INTO pruned_transactions (connection, account_id, transaction_id) VALUES (?1, ?2, ?3)",
+            params![connection, account_id.0, transaction_id.0],
+        )?,
+        RowKey::TransactionOverrides { transaction_id } => transaction.execute(
+            "INSERT OR REPLACE INTO transaction_overrides (transaction_id, data) VALUES (?1, ?2)",
+            params![transaction_id.0, data],
+        )?,
+        RowKey::TransactionTimes { transaction_id } => transaction.execute(
+            "INSERT OR REPLACE INTO transaction_times (transaction_id, data) VALUES (?1, ?2)",
+            params![transaction_id.0, data],
+        )?,
+        RowKey::ManualTransaction { transaction_id } => transaction.execute(
+            "INSERT OR REPLACE INTO manual_transactions (transaction_id, data) VALUES (?1, ?2)",
+            params![transaction_id.0, data],
+        )?,
+        RowKey::PendingAccount { account_id } => transaction.execute(
+            "INSERT OR REPLACE INTO pending_accounts (account_id) VALUES (?1)",
+            [&account_id.0],
+        )?,
+        RowKey::PendingTransaction {
+            account_id,
+            transaction_id,
+        } => transaction.execute(
+            "INSERT OR REPLACE INTO pending_transactions (account_id, transaction_id, data) VALUES (?1, ?2, ?3)",
+            params![account_id.0, transaction_id.0, data],
+        )?,
+    };
+    Ok(())
+}
+
+fn delete_row(transaction: &rusqlite::Transaction, key: &RowKey) -> Result<()> {
+    match key {
+        RowKey::PlaidAuth => {
+            transaction.execute("DELETE FROM meta WHERE key = ?1", [PLAID_AUTH_KEY])?
+        }
+        RowKey::IgnoreList => {
+            transaction.execute("DELETE FROM meta WHERE key = ?1", [IGNORE_LIST_KEY])?
+        }
+        RowKey::Archived => {
+            transaction.execute("DELETE FROM meta WHERE key = ?1", [ARCHIVED_KEY])?
+        }
+        RowKey::LedgerTargets => {
+            transaction.execute("DELETE FROM meta WHERE key = ?1", [LEDGER_TARGETS_KEY])?
+        }
+        RowKey::AccountRenames => {
+            transaction.execute("DELETE FROM meta WHERE key = ?1", [ACCOUNT_RENAMES_KEY])?
+        }
+        RowKey::BalanceAnchors => {
+            transaction.execute("DELETE FROM meta WHERE key = ?1", [BALANCE_ANCHORS_KEY])?
+        }
+        RowKey::Duplicates => {
+            transaction.execute("DELETE FROM meta WHERE key = ?1", [DUPLICATES_KEY])?
+        }
+        RowKey::AmountSigns => {
+            transaction.execute("DELETE FROM meta WHERE key = ?1", [AMOUNT_SIGNS_KEY])?
+        }
+        RowKey::BankConnection { position } => transaction.execute(
+            "DELETE FROM bank_connections WHERE position = ?1",
+            [position],
+        )?,
+        RowKey::Transaction {
+            connection,
+            account_id,
+            transaction_id,
+        } => transaction.execute(
+            "DELETE FROM transactions WHERE connection = ?1 AND account_id = ?2 AND transaction_id = ?3",
+            params![connection, account_id.0, transaction_id.0],
+        )?,
+        RowKey::PrunedTransaction {
+            connection,
+            account_id,
+            transaction_id,
+        } => transaction.execute(
+            "DELETE FROM pruned_transactions WHERE connection = ?1 AND account_id = ?2 AND transaction_id = ?3",
+            params![connection, account_id.0, transaction_id.0],
+        )?,
+        RowKey::TransactionOverrides { transaction_id } => transaction.execute(
+            "DELETE FROM transaction_overrides WHERE transaction_id = ?1",
+            [&transaction_id.0],
+        )?,
+        RowKey::TransactionTimes { transaction_id } => transaction.execute(
+            "DELETE FROM transaction_times WHERE transaction_id = ?1",
+            [&transaction_id.0],
+        )?,
+        RowKey::ManualTransaction { transaction_id } => transaction.execute(
+            "DELETE FROM manual_transactions WHERE transaction_id = ?1",
+            [&transaction_id.0],
+        )?,
+        RowKey::PendingAccount { account_id } => transaction.execute(
+            "DELETE FROM pending_accounts WHERE account_id = ?1",
+            [&account_id.0],
+        )?,
+        RowKey::PendingTransaction {
+            account_id,
+            transaction_id,
+        } => transaction.execute(
+            "DELETE FROM pending_transactions WHERE account_id = ?1 AND transaction_id = ?2",
+            params![account_id.0, transaction_id.0],
+        )?,
+    };
+    Ok(())
+}
+
+fn serialize(value: &impl Serialize) -> Result<Vec<u8>> {
+    postcard::to_stdvec(value).map_err(|source| DbError::Serialize {
+        what: "database row",
+        source: source.into(),
+    })
+}
+
+fn deserialize<T: DeserializeOwned>(plaintext: &[u8]) -> Result<T> {
+    postcard::from_bytes(plaintext).map_err(|source| {
+        ParseError::Postcard {
+            what: "database row",
+            source,
+        }
+        .into()
+    })
+}
+
+fn hash(plaintext: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    plaintext.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate};
+    use common_macros::hash_map;
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::db::{
+        account::AccountType, account_rename::RenameDirectives, archived::ArchivedConnection,
+        ledger_target::LedgerTarget, AccountRename, AmountSign, BalanceAnchor,
+        BeancountAccountInfo, Cipher, TransactionBuilder, TransactionCategory,
+        XChaCha20Poly1305Cipher,
+    };
+
+    fn cipher() -> DbCipher {
+        DbCipher::Encrypted(XChaCha20Poly1305Cipher::with_key(
+            &XChaCha20Poly1305Cipher::new_key(),
+        ))
+    }
+
+    fn transaction(day: u32) -> Transaction {
+        TransactionBuilder::new()
+            .posted_on(2024, 1, day)
+            .cents(-1234)
+            .description(&format!("Transaction {day}"))
+            .build()
+    }
+
+    fn connection(name: &str, num_transactions: u32) -> BankConnection {
+        let mut account = Account::new_connected(
+            PlaidAccountInfo {
+                name: "Checking".to_string(),
+                official_name: None,
+                mask: Some("1234".to_string()),
+                type_: "depository".to_string(),
+                subtype: None,
+            },
+            BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Checking".to_string()],
+            },
+        );
+        account.account.as_mut().unwrap().transactions = (1..=num_transactions)
+            .map(|day| (TransactionId(format!("{name}-{day}")), transaction(day)))
+            .collect();
+        let unconnected = Account::new_unconnected(PlaidAccountInfo {
+            name: "Savings".to_string(),
+            official_name: None,
+            mask: None,
+            type_: "depository".to_string(),
+            subtype: None,
+        });
+        BankConnection::new(
+            name.to_string(),
+            AccessToken::new(format!("{name}-token")),
+            None,
+            hash_map![
+                AccountId(format!("{name}-checking")) => account,
+                AccountId(format!("{name}-savings")) => unconnected,
+            ],
+        )
+    }
+
+    fn some_db() -> DatabaseV16 {
+        DatabaseV16 {
+            plaid_auth: DbPlaidAuth::new("client-id".to_string(), "secret".to_string()),
+            bank_connections: vec![connection("bank-1", 3), connection("bank-2", 2)],
+            transaction_overrides: hash_map![],
+            manual_transactions: hash_map![],
+            ignore_list: IgnoreList::default(),
+            archived: Archived::default(),
+            pending_accounts: hash_map![],
+            ledger_targets: LedgerTargets::default(),
+            account_renames: vec![],
+            balance_anchors: hash_map![],
+            transaction_times: hash_map![],
+            duplicates: Duplicates::default(),
+            amount_signs: hash_map![],
+        }
+    }
+
+    fn stored_transactions(db_path: &Path) -> HashMap<String, Vec<u8>> {
+        let connection = Connection::open(db_path).unwrap();
+        let mut statement = connection
+            .prepare("SELECT transaction_id, data FROM transactions")
+            .unwrap();
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn save_and_load() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(some_db(), loaded);
+    }
+
+    #[test]
+    fn doesnt_load_with_wrong_key() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        save(&db_path, &cipher(), &some_db(), &StoredRows::default()).unwrap();
+        assert!(matches!(
+            load(&db_path, &cipher()),
+            Err(DbError::Decryption)
+        ));
+    }
+
+    #[test]
+    fn save_and_load_unencrypted() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        save(
+            &db_path,
+            &DbCipher::Unencrypted,
+            &some_db(),
+            &StoredRows::default(),
+        )
+        .unwrap();
+        assert!(!is_encrypted(&db_path).unwrap());
+        // Unencrypted databases load without a key, even if one is given
+        let (loaded, _, _) = load(&db_path, &cipher()).unwrap();
+        assert_eq!(some_db(), loaded);
+
+        save(&db_path, &cipher(), &some_db(), &StoredRows::default()).unwrap();
+        assert!(is_encrypted(&db_path).unwrap());
+        assert_eq!(
+            "The database is encrypted but no encryption key was given",
+            load(&db_path, &DbCipher::Unencrypted)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn only_writes_changed_rows() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let before = stored_transactions(&db_path);
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        let (_, transaction) = db.bank_connections[0]
+            .account_mut(&AccountId("bank-1-checking".to_string()))
+            .unwrap()
+            .account
+            .as_mut()
+            .unwrap()
+            .transactions
+            .iter_all_sorted_by_date_mut()
+            .next()
+            .unwrap();
+        transaction.mark_as_exported();
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+        let after = stored_transactions(&db_path);
+
+        // Encryption uses a random nonce, so only rewritten rows have a different ciphertext
+        let changed: Vec<&String> = before
+            .keys()
+            .filter(|transaction_id| before[*transaction_id] != after[*transaction_id])
+            .collect();
+        assert_eq!(vec!["bank-1-1"], changed);
+        assert_eq!(db, load(&db_path, &cipher).unwrap().0);
+    }
+
+    #[test]
+    fn save_and_load_pruned_transactions() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        let num_pruned = db.bank_connections[0]
+            .account_mut(&AccountId("bank-1-checking".to_string()))
+            .unwrap()
+            .account
+            .as_mut()
+            .unwrap()
+            .prune_transactions(NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(), false);
+        assert_eq!(2, num_pruned);
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+        assert_eq!(3, stored_transactions(&db_path).len());
+    }
+
+    #[test]
+    fn removed_connections_are_deleted() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        db.bank_connections.remove(0);
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+        assert_eq!(2, stored_transactions(&db_path).len());
+    }
+
+    #[test]
+    fn save_and_load_transaction_overrides() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        db.transaction_overrides.insert(
+            TransactionId("bank-1-2".to_string()),
+            TransactionOverrides {
+                note: Some("Birthday present".to_string()),
+                account: None,
+                payee: Some("Toy store".to_string()),
+                category: Some(TransactionCategory {
+                    primary: "GENERAL_MERCHANDISE".to_string(),
+                    detailed: "GENERAL_MERCHANDISE_TOYS".to_string(),
+                }),
+            },
+        );
+        let stored_rows = save(&db_path, &cipher, &db, &stored_rows).unwrap();
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+
+        db.transaction_overrides.clear();
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+    }
+
+    #[test]
+    fn load_transaction_overrides_from_schema_6() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+
+        save(
+            &db_path,
+            &DbCipher::Unencrypted,
+            &some_db(),
+            &StoredRows::default(),
+        )
+        .unwrap();
+        let connection = Connection::open(&db_path).unwrap();
+        let overrides = TransactionOverridesV1 {
+            note: Some("Birthday present".to_string()),
+            account: None,
+            payee: None,
+        };
+        connection
+            .execute(
+                "INSERT INTO transaction_overrides (transaction_id, data) VALUES (?1, ?2)",
+                params!["bank-1-2", serialize(&overrides).unwrap()],
+            )
+            .unwrap();
+        connection.pragma_update(None, "user_version", 6).unwrap();
+        drop(connection);
+
+        let (db, _, schema_version) = load(&db_path, &DbCipher::Unencrypted).unwrap();
+        assert_eq!(6, schema_version);
+        assert_eq!(
+            hash_map![TransactionId("bank-1-2".to_string()) => overrides.migrate()],
+            db.transaction_overrides
+        );
+    }
+
+    #[test]
+    fn save_and_load_pending_accounts() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        let mut db = some_db();
+        db.pending_accounts.insert(
+            AccountId("bank-1-savings".to_string()),
+            [(TransactionId("pending-1".to_string()), transaction(4))]
+                .into_iter()
+                .collect(),
+        );
+        db.pending_accounts.insert(
+            AccountId("bank-2-savings".to_string()),
+            Transactions::new_empty(),
+        );
+        let stored_rows = save(&db_path, &cipher, &db, &StoredRows::default()).unwrap();
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+
+        db.pending_accounts
+            .remove(&AccountId("bank-1-savings".to_string()));
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+    }
+
+    #[test]
+    fn save_and_load_manual_transactions() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        let mut db = some_db();
+        db.manual_transactions.insert(
+            TransactionId::new_manual(),
+            ManualTransaction {
+                beancount_account_info: BeancountAccountInfo {
+                    ty: AccountType::Assets,
+                    name_parts: vec!["Cash".to_string()],
+                },
+                transaction: transaction(5),
+            },
+        );
+        save(&db_path, &cipher, &db, &StoredRows::default()).unwrap();
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+    }
+
+    #[test]
+    fn save_and_load_archived_connection() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        let connection = db.bank_connections.remove(0);
+        db.archived.connections.push(ArchivedConnection {
+            archived_on: NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
+            connection,
+        });
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+        assert_eq!(2, stored_transactions(&db_path).len());
+    }
+
+    #[test]
+    fn save_and_load_ledger_targets() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        db.ledger_targets.add(
+            "business".to_string(),
+            LedgerTarget {
+                output_file: Some("business.beancount".into()),
+                operating_currency: Some("EUR".to_string()),
+                ..Default::default()
+            },
+        );
+        db.ledger_targets.assign(Some("business"), "bank-2", None);
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+    }
+
+    #[test]
+    fn save_and_load_account_renames() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        db.account_renames.push(AccountRename {
+            account_id: AccountId("account-1".to_string()),
+            renamed_on: NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
+            old_account: BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Checking".to_string()],
+            },
+            new_account: BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Bank".to_string(), "Checking".to_string()],
+            },
+            directives: RenameDirectives::CloseOpen,
+            already_exported: false,
+        });
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+    }
+
+    #[test]
+    fn save_and_load_balance_anchors() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        let mut anchor = BalanceAnchor::new(
+            NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
+            Decimal::new(123456, 2),
+            Some("USD".to_string()),
+        );
+        anchor.added_since = Decimal::new(-1234, 2);
+        db.balance_anchors
+            .insert(AccountId("account-1".to_string()), anchor);
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+    }
+
+    #[test]
+    fn save_and_load_duplicates() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        db.duplicates.suspect(
+            TransactionId("transaction-2".to_string()),
+            TransactionId("transaction-1".to_string()),
+        );
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+    }
+
+    #[test]
+    fn save_and_load_amount_signs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        db.amount_signs.insert(
+            AccountId("bank-1-checking".to_string()),
+            AmountSign::Inverted,
+        );
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+    }
+
+    #[test]
+    fn save_and_load_transaction_times() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let db_path = tempdir.path().join("database");
+        let cipher = cipher();
+
+        save(&db_path, &cipher, &some_db(), &StoredRows::default()).unwrap();
+        let (mut db, stored_rows, _) = load(&db_path, &cipher).unwrap();
+        db.transaction_times.insert(
+            TransactionId("transaction-1".to_string()),
+            TransactionTimes {
+                posted: Some(DateTime::from_timestamp(1_706_745_600, 0).unwrap()),
+                authorized: None,
+            },
+        );
+        save(&db_path, &cipher, &db, &stored_rows).unwrap();
+
+        let (loaded, _, _) = load(&db_path, &cipher).unwrap();
+        assert_eq!(db, loaded);
+    }
+}
@@ -0,0 +1,31 @@
+use serde::Serialize;
+use std::{fs::File, io::Read as _, path::Path};
+
+use super::{sqlite::SQLITE_HEADER, DbError};
+
+/// How the database is laid out on disk
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// A single encrypted and compressed file. Every load and save processes the whole database.
+    #[default]
+    File,
+    /// An SQLite database with one encrypted row per transaction. Saving only writes the rows that changed.
+    Sqlite,
+}
+
+impl StorageBackend {
+    /// Figure out which backend an existing database file was written with
+    pub fn detect(db_path: &Path) -> Result<Self, DbError> {
+        let mut header = [0; SQLITE_HEADER.len()];
+        let mut file = File::open(db_path)?;
+        match file.read_exact(&mut header) {
+            Ok(()) if header == SQLITE_HEADER => Ok(Self::Sqlite),
+            Ok(()) => Ok(Self::File),
+            // Too short to be an SQLite database
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(Self::File),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
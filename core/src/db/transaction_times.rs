@@ -0,0 +1,10 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// When Plaid says a transaction was posted and authorized. Only some institutions report times and not just dates.
+/// Sync derives the dates of the transaction from them, see [crate::sync::Timezone].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionTimes {
+    pub posted: Option<DateTime<Utc>>,
+    pub authorized: Option<DateTime<Utc>>,
+}
@@ -15,6 +15,8 @@ pub enum AddOrVerifyResult {
         existing_value: Transaction,
         new_value: Transaction,
     },
+    /// The transaction was removed by pruning and isn't added again
+    Pruned,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -53,6 +55,23 @@ impl Transactions {
         }
     }
 
+    pub fn get(&self, id: &TransactionId) -> Option<&Transaction> {
+        self.transactions.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: &TransactionId) -> Option<&mut Transaction> {
+        self.transactions.get_mut(id)
+    }
+
+    pub fn into_iter_sorted_by_date(self) -> impl Iterator<Item = (TransactionId, Transaction)> {
+        let mut transactions: Vec<(TransactionId, Transaction)> =
+            self.transactions.into_iter().collect();
+        transactions.sort_by(|(lhs_id, lhs), (rhs_id, rhs)| {
+            (lhs.transaction.date(), lhs_id).cmp(&(rhs.transaction.date(), rhs_id))
+        });
+        transactions.into_iter()
+    }
+
     pub fn iter_all_sorted_by_date(&self) -> impl Iterator<Item = (&TransactionId, &Transaction)> {
         sorted_by_date(self.transactions.iter())
     }
@@ -73,16 +92,45 @@ impl Transactions {
         )
     }
 
+    /// Remove all transactions matching `predicate` and return their ids
+    pub fn remove_where(
+        &mut self,
+        mut predicate: impl FnMut(&Transaction) -> bool,
+    ) -> Vec<TransactionId> {
+        let mut removed = vec![];
+        self.transactions.retain(|id, transaction| {
+            let remove = predicate(transaction);
+            if remove {
+                removed.push(id.clone());
+            }
+            !remove
+        });
+        removed
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.transactions.is_empty()
     }
 }
 
+impl FromIterator<(TransactionId, Transaction)> for Transactions {
+    fn from_iter<I: IntoIterator<Item = (TransactionId, Transaction)>>(iter: I) -> Self {
+        Self {
+            transactions: iter.into_iter().collect(),
+        }
+    }
+}
+
+// Transactions of the same day are sorted by id, the order of the hash map would change between runs
 fn sorted_by_date<'a, 'b>(
     transactions: impl Iterator<Item = (&'a TransactionId, &'b Transaction)>,
 ) -> impl Iterator<Item = (&'a TransactionId, &'b Transaction)> {
     let mut transactions: Vec<(&TransactionId, &Transaction)> = transactions.collect();
-    transactions.sort_by_key(|(_, t)| t.transaction.date());
+    transactions.sort_by_key(|(id, t)| (t.transaction.date(), *id));
     transactions.into_iter()
 }
 
@@ -90,11 +138,11 @@ fn sorted_by_date_mut<'a, 'b>(
     transactions: impl Iterator<Item = (&'a TransactionId, &'b mut Transaction)>,
 ) -> impl Iterator<Item = (&'a TransactionId, &'b mut Transaction)> {
     let mut transactions: Vec<(&TransactionId, &mut Transaction)> = transactions.collect();
-    transactions.sort_by_key(|(_, t)| t.transaction.date());
+    transactions.sort_by_key(|(id, t)| (t.transaction.date(), *id));
     transactions.into_iter()
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TransactionId(pub String);
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -147,6 +195,10 @@ impl Transaction {
     pub fn mark_as_exported(&mut self) {
         self.already_exported = true;
     }
+
+    pub fn mark_as_not_exported(&mut self) {
+        self.already_exported = false;
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -175,3 +227,48 @@ impl TransactionInfo {
         self.authorized_date.unwrap_or(self.posted_date)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TransactionBuilder;
+
+    fn transaction(day: u32) -> Transaction {
+        TransactionBuilder::new()
+            .posted_on(2024, 1, day)
+            .cents(-500)
+            .build()
+    }
+
+    #[test]
+    fn sort_same_day_by_id() {
+        let mut transactions: Transactions = [("d", 2), ("c", 1), ("a", 1), ("b", 1)]
+            .into_iter()
+            .map(|(id, day)| (TransactionId(id.to_string()), transaction(day)))
+            .collect();
+        let expected = vec!["a", "b", "c", "d"];
+        let ids =
+            |ids: Vec<&TransactionId>| ids.into_iter().map(|id| id.0.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            expected,
+            ids(transactions
+                .iter_all_sorted_by_date()
+                .map(|(id, _)| id)
+                .collect())
+        );
+        assert_eq!(
+            expected,
+            ids(transactions
+                .iter_all_sorted_by_date_mut()
+                .map(|(id, _)| id)
+                .collect())
+        );
+        assert_eq!(
+            expected,
+            transactions
+                .into_iter_sorted_by_date()
+                .map(|(id, _)| id.0)
+                .collect::<Vec<_>>()
+        );
+    }
+}
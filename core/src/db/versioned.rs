@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+use super::database::{
+    DatabaseV1, DatabaseV10, DatabaseV11, DatabaseV12, DatabaseV13, DatabaseV14, DatabaseV15,
+    DatabaseV16, DatabaseV2, DatabaseV3, DatabaseV4, DatabaseV5, DatabaseV6, DatabaseV7,
+    DatabaseV8, DatabaseV9,
+};
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(PartialEq, Eq, Debug))]
+pub enum VersionedDatabase {
+    V1(DatabaseV1),
+    V2(DatabaseV2),
+    V3(DatabaseV3),
+    V4(DatabaseV4),
+    V5(DatabaseV5),
+    V6(DatabaseV6),
+    V7(DatabaseV7),
+    V8(DatabaseV8),
+    V9(DatabaseV9),
+    V10(DatabaseV10),
+    V11(DatabaseV11),
+    V12(DatabaseV12),
+    V13(DatabaseV13),
+    V14(DatabaseV14),
+    V15(DatabaseV15),
+    V16(DatabaseV16),
+}
+
+impl VersionedDatabase {
+    /// Version that new database files are written with
+    pub const CURRENT_VERSION: u32 = 16;
+
+    pub fn version(&self) -> u32 {
+        match self {
+            Self::V1(_) => 1,
+            Self::V2(_) => 2,
+            Self::V3(_) => 3,
+            Self::V4(_) => 4,
+            Self::V5(_) => 5,
+            Self::V6(_) => 6,
+            Self::V7(_) => 7,
+            Self::V8(_) => 8,
+            Self::V9(_) => 9,
+            Self::V10(_) => 10,
+            Self::V11(_) => 11,
+            Self::V12(_) => 12,
+            Self::V13(_) => 13,
+            Self::V14(_) => 14,
+            Self::V15(_) => 15,
+            Self::V16(_) => 16,
+        }
+    }
+}
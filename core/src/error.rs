@@ -0,0 +1,42 @@
+//! The errors of this crate. Each module returns its own error type, so embedders can match on the kind of failure,
+//! e.g. [PlaidApiError::NeedsRelink] to ask the user to link a bank connection again. Command line tools can
+//! still convert them into `anyhow::Error` with `?`.
+
+use std::path::PathBuf;
+
+pub use crate::db::DbError;
+pub use crate::export::ExportError;
+pub use crate::plaid_api::PlaidApiError;
+
+/// Data that doesn't have the format we expect, from the user or from a file
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("Invalid account {0}, it must start with one of: Assets:, Liabilities:, Equity:, Income:, Expenses:")]
+    AccountName(String),
+
+    /// An account name with a valid root but a component Beancount doesn't accept
+    #[error("Invalid account {name}, {problem}")]
+    AccountNameComponent { name: String, problem: String },
+
+    /// A database row, database file or archive that can't be deserialized
+    #[error("Failed to deserialize {what}")]
+    Postcard {
+        what: &'static str,
+        #[source]
+        source: postcard::Error,
+    },
+
+    #[error("Failed to parse {}", path.display())]
+    Csv {
+        path: PathBuf,
+        #[source]
+        source: csv::Error,
+    },
+
+    #[error("Failed to parse {}", path.display())]
+    Json {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+}
@@ -0,0 +1,1087 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet},
+    io::Write,
+};
+
+use beancount_core::{Directive, Ledger};
+use beancount_import_ir::{
+    self as ir,
+    beancount::{quoted_string, BeancountAccount},
+    MetaValue,
+};
+use chrono::NaiveDate;
+
+pub use beancount_import_ir::script::Script;
+use common_macros::hash_map;
+use serde::Serialize;
+
+use crate::db::{
+    flag_duplicates, AccountRename, AccountType, AmountSign, BeancountAccountInfo, DatabaseV16,
+    Duplicates, LedgerTarget, LedgerTargets, RenameDirectives, Transaction, TransactionId,
+    TransactionInfo, TransactionOverrides,
+};
+use crate::error::ParseError;
+use crate::mapping::parse_beancount_account_name;
+
+mod transfers;
+
+type Result<T, E = ExportError> = std::result::Result<T, E>;
+
+/// Errors of rendering transactions from the database
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error("No ledger target found with name {0}")]
+    UnknownTarget(String),
+
+    /// An override or the script booked a transaction to an account that isn't a Beancount account name
+    #[error(transparent)]
+    InvalidAccount(#[from] ParseError),
+
+    #[error("The script failed")]
+    Script(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Failed to render the transactions")]
+    Render(#[from] beancount_render::BasicRendererError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("Failed to write the candidates")]
+    Json(#[from] serde_json::Error),
+}
+
+/// What an export wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportResult {
+    pub num_transactions: usize,
+    /// Directives for remapped accounts, see [write_account_renames]
+    pub num_directives: usize,
+    /// Suspected duplicates that weren't exported until they're reviewed, see [flag_duplicates]
+    pub num_held_back: usize,
+}
+
+/// Render all transactions of the given ledger target, or of the default target if `None`, into `out`.
+/// Ignored transactions and suspected duplicates are skipped. `script` can change or skip the transactions, see [Script].
+/// `on_progress` is called with the number of rendered transactions and the total.
+pub fn export_all_transactions(
+    database: &DatabaseV16,
+    target_name: Option<&str>,
+    script: Option<&Script>,
+    out: &mut impl Write,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<ExportResult> {
+    let target = ledger_target(&database.ledger_targets, target_name)?;
+    let mut all_transactions = all_transactions(database, target_name, target);
+    let num_held_back = hold_back_duplicates(&mut all_transactions, &database.duplicates);
+    let total = all_transactions.len();
+    let num_transactions = write_exported_transactions(
+        all_transactions.into_iter(),
+        &database.transaction_overrides,
+        &transaction_amount_signs(database),
+        target.and_then(|target| target.operating_currency.as_deref()),
+        script,
+        out,
+        |num_written| on_progress(num_written, total),
+    )?;
+    Ok(ExportResult {
+        num_transactions,
+        num_directives: 0,
+        num_held_back,
+    })
+}
+
+/// The transactions of the ledger target that aren't ignored, sorted like [sort_for_export]
+fn all_transactions<'a>(
+    database: &'a DatabaseV16,
+    target_name: Option<&str>,
+    target: Option<&LedgerTarget>,
+) -> Vec<(&'a BeancountAccountInfo, &'a TransactionId, &'a Transaction)> {
+    let ledger_targets = &database.ledger_targets;
+    let ignore_list = &database.ignore_list;
+    let all_transactions = database.bank_connections.iter().flat_map(|c| {
+        c.accounts()
+            .filter(|(account_id, _)| ledger_targets.target_of(c.name(), account_id) == target_name)
+            .flat_map(|account| {
+                account.1.account.iter().flat_map(|account| {
+                    account
+                        .transactions
+                        .iter_all_sorted_by_date()
+                        .filter(|(transaction_id, transaction)| {
+                            !ignore_list.is_ignored(transaction_id, &transaction.transaction)
+                                && !target.is_some_and(|target| {
+                                    target.is_ignored(&transaction.transaction)
+                                })
+                        })
+                        .map(move |(transaction_id, transaction)| {
+                            (&account.beancount_account_info, transaction_id, transaction)
+                        })
+                })
+            })
+    });
+    // Manual transactions have no connection, they're always exported without a target
+    let manual_transactions = database
+        .manual_transactions
+        .iter()
+        .filter(|(transaction_id, t)| {
+            target_name.is_none()
+                && !ignore_list.is_ignored(transaction_id, &t.transaction.transaction)
+        });
+    let mut all_transactions: Vec<_> =
+        all_transactions
+            .chain(manual_transactions.map(|(transaction_id, t)| {
+                (&t.beancount_account_info, transaction_id, &t.transaction)
+            }))
+            .collect();
+    sort_for_export(&mut all_transactions);
+    all_transactions
+}
+
+/// The sign of the account of each transaction whose account doesn't use [AmountSign::Standard]
+fn transaction_amount_signs(database: &DatabaseV16) -> HashMap<&TransactionId, AmountSign> {
+    database
+        .bank_connections
+        .iter()
+        .flat_map(|c| c.accounts())
+        .filter_map(|(account_id, account)| {
+            let amount_sign = database.amount_sign(account_id);
+            let account = account.account.as_ref()?;
+            (amount_sign != AmountSign::Standard).then_some((amount_sign, account))
+        })
+        .flat_map(|(amount_sign, account)| {
+            account
+                .transactions
+                .iter_all_sorted_by_date()
+                .map(move |(transaction_id, _)| (transaction_id, amount_sign))
+        })
+        .collect()
+}
+
+/// Remove the suspected duplicates from `transactions`, see [flag_duplicates]. Returns how many were removed.
+fn hold_back_duplicates<T>(
+    transactions: &mut Vec<(&BeancountAccountInfo, &TransactionId, T)>,
+    duplicates: &Duplicates,
+) -> usize {
+    let num_transactions = transactions.len();
+    transactions.retain(|(_, transaction_id, _)| !duplicates.is_suspected(transaction_id));
+    num_transactions - transactions.len()
+}
+
+/// Sort by date, then by account and transaction id. The accounts and transactions are stored in hash maps, so without
+/// this the order of the transactions of a day would change between exports.
+fn sort_for_export(transactions: &mut [(&BeancountAccountInfo, &TransactionId, &Transaction)]) {
+    transactions.sort_by_cached_key(|(account, transaction_id, t)| {
+        (
+            t.transaction.date(),
+            account.beancount_name(),
+            (*transaction_id).clone(),
+        )
+    });
+}
+
+/// Render the transactions of the given ledger target that weren't exported yet into `out` and mark them as exported,
+/// preceded by the directives of remapped accounts. `script` can change or skip the transactions, see [Script].
+/// Skipped transactions are marked as exported too. Transactions that look like a reissue of another transaction are
+/// held back until they're reviewed, see [flag_duplicates]. `on_progress` is called with the number of rendered
+/// transactions and the total. Nothing is marked as exported unless all of it was written and `out` was flushed, so
+/// a failed export can be retried. The database must only be saved once the output is stored, for the same reason.
+pub fn export_new_transactions(
+    database: &mut DatabaseV16,
+    target_name: Option<&str>,
+    script: Option<&Script>,
+    out: &mut impl Write,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<ExportResult> {
+    flag_duplicates(database);
+    let target = ledger_target(&database.ledger_targets, target_name)?;
+    let ledger_targets = &database.ledger_targets;
+    let bank_connections = &database.bank_connections;
+    let renames: Vec<usize> = database
+        .account_renames
+        .iter()
+        .enumerate()
+        .filter(|(_, rename)| {
+            // Renames of accounts that were removed since go to the default target
+            let account_target = bank_connections.iter().find_map(|c| {
+                c.account(&rename.account_id)
+                    .map(|_| ledger_targets.target_of(c.name(), &rename.account_id))
+            });
+            !rename.already_exported && account_target.flatten() == target_name
+        })
+        .map(|(index, _)| index)
+        .collect();
+    let num_directives = write_account_renames(
+        renames
+            .iter()
+            .map(|&index| &database.account_renames[index]),
+        out,
+    )?;
+    // Ignored transactions aren't marked as exported so they're exported if they get un-ignored
+    let mut new_transactions = all_transactions(database, target_name, target);
+    new_transactions.retain(|(_, _, transaction)| !transaction.already_exported);
+    // Suspected duplicates aren't marked as exported so they're exported if they turn out to be separate transactions
+    let num_held_back = hold_back_duplicates(&mut new_transactions, &database.duplicates);
+    let total = new_transactions.len();
+    let num_transactions = write_exported_transactions(
+        new_transactions.iter().copied(),
+        &database.transaction_overrides,
+        &transaction_amount_signs(database),
+        target.and_then(|target| target.operating_currency.as_deref()),
+        script,
+        out,
+        |num_written| on_progress(num_written, total),
+    )?;
+    out.flush()?;
+
+    let exported: HashSet<TransactionId> = new_transactions
+        .into_iter()
+        .map(|(_, transaction_id, _)| transaction_id.clone())
+        .collect();
+    mark_as_exported(database, &exported);
+    for index in renames {
+        database.account_renames[index].already_exported = true;
+    }
+    Ok(ExportResult {
+        num_transactions,
+        num_directives,
+        num_held_back,
+    })
+}
+
+fn mark_as_exported(database: &mut DatabaseV16, exported: &HashSet<TransactionId>) {
+    let accounts = database
+        .bank_connections
+        .iter_mut()
+        .flat_map(|c| c.accounts_mut())
+        .filter_map(|(_, account)| account.account.as_mut());
+    for account in accounts {
+        for transaction_id in exported {
+            if let Some(transaction) = account.transactions.get_mut(transaction_id) {
+                transaction.mark_as_exported();
+            }
+        }
+    }
+    for transaction_id in exported {
+        if let Some(manual_transaction) = database.manual_transactions.get_mut(transaction_id) {
+            manual_transaction.transaction.mark_as_exported();
+        }
+    }
+}
+
+fn ledger_target<'a>(
+    ledger_targets: &'a LedgerTargets,
+    target_name: Option<&str>,
+) -> Result<Option<&'a LedgerTarget>> {
+    target_name
+        .map(|name| {
+            ledger_targets
+                .get(name)
+                .ok_or_else(|| ExportError::UnknownTarget(name.to_string()))
+        })
+        .transpose()
+}
+
+/// Number of transactions rendered at once. Rendering in chunks keeps memory flat for large exports.
+const CHUNK_SIZE: usize = 1000;
+
+/// Render the transactions as a Beancount ledger into `out`, with the overrides taking precedence over the Plaid data.
+/// The two sides of a transfer between exported accounts become one transaction, see [transfers::find_transfers].
+/// `amount_signs` has the sign of the account of each transaction that isn't [AmountSign::Standard].
+/// `default_currency` is used for transactions without a currency. `on_progress` is called with the number of
+/// transactions processed so far after each chunk. Transactions are passed through `script` if given, and left out
+/// if it skips them. Returns the number of exported transactions.
+pub fn write_exported_transactions<'a>(
+    transactions: impl Iterator<Item = (&'a BeancountAccountInfo, &'a TransactionId, &'a Transaction)>,
+    overrides: &'a HashMap<TransactionId, TransactionOverrides>,
+    amount_signs: &HashMap<&TransactionId, AmountSign>,
+    default_currency: Option<&'a str>,
+    script: Option<&Script>,
+    out: &mut impl Write,
+    mut on_progress: impl FnMut(usize),
+) -> Result<usize> {
+    // Transfers can only be paired once all transactions are known. These are only references, the rendered
+    // directives are still produced in chunks.
+    let transactions: Vec<_> = transactions.collect();
+    let transfers = transfers::find_transfers(&transactions, overrides, amount_signs);
+    let receiving_sides: HashSet<usize> = transfers.values().copied().collect();
+    let mut num_processed = 0;
+    let mut num_exported = 0;
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    for transactions_chunk in transactions.chunks(CHUNK_SIZE) {
+        for &(account, id, t) in transactions_chunk {
+            let index = num_processed;
+            num_processed += 1;
+            if receiving_sides.contains(&index) {
+                // Rendered together with the sending side
+                continue;
+            }
+            let directive = match transfers.get(&index) {
+                Some(&receiving_side) => {
+                    let (receiving_account, receiving_id, receiving_t) =
+                        transactions[receiving_side];
+                    transfer_to_beancount(
+                        (account, id, &t.transaction),
+                        (receiving_account, receiving_id, &receiving_t.transaction),
+                        overrides,
+                        default_currency,
+                        script,
+                    )?
+                }
+                None => transaction_to_beancount(
+                    account,
+                    id,
+                    &t.transaction,
+                    overrides.get(id),
+                    default_currency,
+                    script,
+                )?,
+            };
+            chunk.extend(directive);
+        }
+        num_exported += chunk.len();
+        let ledger = Ledger {
+            directives: std::mem::take(&mut chunk),
+        };
+        beancount_render::render(out, &ledger)?;
+        chunk = ledger.directives;
+        chunk.clear();
+        on_progress(num_processed);
+    }
+    Ok(num_exported)
+}
+
+/// Write the directives that tell the ledger about remapped accounts to `out`. They have to come before
+/// the transactions of the new account. Returns the number of written directives.
+pub fn write_account_renames<'a>(
+    renames: impl Iterator<Item = &'a AccountRename>,
+    out: &mut impl Write,
+) -> Result<usize> {
+    let mut num_directives = 0;
+    for rename in renames {
+        let date = rename.renamed_on;
+        let old_account = rename.old_account.beancount_name();
+        let new_account = rename.new_account.beancount_name();
+        match rename.directives {
+            RenameDirectives::None => continue,
+            RenameDirectives::CloseOpen => {
+                writeln!(out, "{date} close {old_account}")?;
+                writeln!(out, "{date} open {new_account}")?;
+                num_directives += 2;
+            }
+            RenameDirectives::Note => {
+                let note = quoted_string(&format!("Renamed from {old_account}"));
+                writeln!(out, "{date} note {new_account} {note}")?;
+                num_directives += 1;
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(num_directives)
+}
+
+/// Metadata key of the postings that [export_beancount_import_candidates] writes. beancount-import considers postings
+/// with it as cleared, i.e. matched to a Plaid transaction.
+pub const CLEARED_METADATA_KEY: &str = "plaid_transaction_id";
+/// Metadata key that beancount-import learns the counter account from
+const SOURCE_DESC_METADATA_KEY: &str = "source_desc";
+/// The account beancount-import asks the user to replace
+const FIXME_ACCOUNT: &str = "Expenses:FIXME";
+
+/// What [export_beancount_import_candidates] writes, read by the source in `plaid/beancount_import_source.py`
+#[derive(Debug, Serialize)]
+pub struct BeancountImportCandidates {
+    /// Postings with this metadata key are cleared
+    pub cleared_metadata_key: &'static str,
+    /// The Beancount accounts of the exported transactions. beancount-import reports postings to them that aren't
+    /// cleared.
+    pub accounts: BTreeSet<String>,
+    pub candidates: Vec<BeancountImportCandidate>,
+}
+
+/// A transaction that is pending in beancount-import until the journal has a posting with its key
+#[derive(Debug, Serialize)]
+pub struct BeancountImportCandidate {
+    pub date: NaiveDate,
+    /// The value of [CLEARED_METADATA_KEY]
+    pub key: String,
+    /// The transaction in Beancount syntax. Its unbalanced rest is booked to `Expenses:FIXME` for the user to assign.
+    pub entry: String,
+}
+
+/// Write all transactions of the given ledger target, or of the default target if `None`, as JSON candidates for the
+/// [beancount-import](https://github.com/jbms/beancount-import) web UI into `out`. Unlike [export_new_transactions],
+/// nothing is marked as exported, beancount-import itself finds out which candidates are already in the journal.
+pub fn export_beancount_import_candidates(
+    database: &DatabaseV16,
+    target_name: Option<&str>,
+    script: Option<&Script>,
+    out: &mut impl Write,
+) -> Result<ExportResult> {
+    let target = ledger_target(&database.ledger_targets, target_name)?;
+    let default_currency = target.and_then(|target| target.operating_currency.as_deref());
+    let mut accounts = BTreeSet::new();
+    let mut candidates = vec![];
+    let mut transactions = all_transactions(database, target_name, target);
+    let num_held_back = hold_back_duplicates(&mut transactions, &database.duplicates);
+    for (account, transaction_id, t) in transactions {
+        let Some(transaction_ir) = candidate_transaction(
+            account,
+            transaction_id,
+            &t.transaction,
+            database.transaction_overrides.get(transaction_id),
+            script,
+        )?
+        else {
+            continue;
+        };
+        let date = transaction_ir.date;
+        let currency = t
+            .transaction
+            .amount
+            .iso_currency_code
+            .as_deref()
+            .or(default_currency);
+        let mut entry = vec![];
+        beancount_render::render(
+            &mut entry,
+            &Ledger {
+                directives: vec![ir_to_beancount(transaction_ir, currency)?],
+            },
+        )?;
+        accounts.insert(account.beancount_name());
+        candidates.push(BeancountImportCandidate {
+            date,
+            key: transaction_id.0.clone(),
+            entry: String::from_utf8(entry).expect("Rendered Beancount is always UTF-8"),
+        });
+    }
+    let num_transactions = candidates.len();
+    serde_json::to_writer_pretty(
+        &mut *out,
+        &BeancountImportCandidates {
+            cleared_metadata_key: CLEARED_METADATA_KEY,
+            accounts,
+            candidates,
+        },
+    )?;
+    writeln!(out)?;
+    Ok(ExportResult {
+        num_transactions,
+        num_directives: 0,
+        num_held_back,
+    })
+}
+
+/// The transaction as beancount-import expects pending entries: with the description as `source_desc` to learn the
+/// counter account from, and the unbalanced rest booked to [FIXME_ACCOUNT]
+fn candidate_transaction(
+    account: &BeancountAccountInfo,
+    transaction_id: &TransactionId,
+    transaction: &TransactionInfo,
+    overrides: Option<&TransactionOverrides>,
+    script: Option<&Script>,
+) -> Result<Option<ir::Transaction>> {
+    let Some(mut transaction_ir) =
+        transaction_to_ir(account, transaction_id, transaction, overrides, script)?
+    else {
+        return Ok(None);
+    };
+    transaction_ir.postings[0].metadata.insert(
+        SOURCE_DESC_METADATA_KEY.to_string(),
+        transaction_ir.description.as_str().into(),
+    );
+    let rest: ir::Amount = transaction_ir
+        .postings
+        .iter()
+        .map(|posting| posting.amount)
+        .sum();
+    if !rest.is_zero() {
+        transaction_ir.postings.push(ir::Posting {
+            account_name: FIXME_ACCOUNT.to_string(),
+            amount: -rest,
+            metadata: hash_map![],
+        });
+    }
+    Ok(Some(transaction_ir))
+}
+
+fn transaction_to_beancount<'a>(
+    account: &'a BeancountAccountInfo,
+    transaction_id: &TransactionId,
+    transaction: &'a TransactionInfo,
+    overrides: Option<&'a TransactionOverrides>,
+    default_currency: Option<&'a str>,
+    script: Option<&Script>,
+) -> Result<Option<Directive<'a>>> {
+    let Some(transaction_ir) =
+        transaction_to_ir(account, transaction_id, transaction, overrides, script)?
+    else {
+        return Ok(None);
+    };
+    let currency = transaction
+        .amount
+        .iso_currency_code
+        .as_deref()
+        .or(default_currency);
+    ir_to_beancount(transaction_ir, currency).map(Some)
+}
+
+/// The sending and receiving side of a transfer as one transaction with a posting for each account. It has the date,
+/// description and payee of the sending side and is passed through `script` as a whole.
+fn transfer_to_beancount<'a>(
+    sending: (&BeancountAccountInfo, &TransactionId, &'a TransactionInfo),
+    receiving: (&BeancountAccountInfo, &TransactionId, &TransactionInfo),
+    overrides: &HashMap<TransactionId, TransactionOverrides>,
+    default_currency: Option<&'a str>,
+    script: Option<&Script>,
+) -> Result<Option<Directive<'a>>> {
+    let (sending_account, sending_id, sending_transaction) = sending;
+    let (receiving_account, receiving_id, receiving_transaction) = receiving;
+    let mut transaction_ir = unscripted_transaction_to_ir(
+        sending_account,
+        sending_id,
+        sending_transaction,
+        overrides.get(sending_id),
+    );
+    let receiving_ir = unscripted_transaction_to_ir(
+        receiving_account,
+        receiving_id,
+        receiving_transaction,
+        overrides.get(receiving_id),
+    );
+    transaction_ir.postings.extend(receiving_ir.postings);
+    let Some(transaction_ir) = apply_script(transaction_ir, script)? else {
+        return Ok(None);
+    };
+    let currency = sending_transaction
+        .amount
+        .iso_currency_code
+        .as_deref()
+        .or(default_currency);
+    ir_to_beancount(transaction_ir, currency).map(Some)
+}
+
+/// The transaction with the overrides applied, passed through `script`. `None` if the script skipped it.
+fn transaction_to_ir(
+    account: &BeancountAccountInfo,
+    transaction_id: &TransactionId,
+    transaction: &TransactionInfo,
+    overrides: Option<&TransactionOverrides>,
+    script: Option<&Script>,
+) -> Result<Option<ir::Transaction>> {
+    let transaction_ir =
+        unscripted_transaction_to_ir(account, transaction_id, transaction, overrides);
+    apply_script(transaction_ir, script)
+}
+
+fn unscripted_transaction_to_ir(
+    account: &BeancountAccountInfo,
+    transaction_id: &TransactionId,
+    transaction: &TransactionInfo,
+    overrides: Option<&TransactionOverrides>,
+) -> ir::Transaction {
+    let mut meta = hash_map![
+        CLEARED_METADATA_KEY.to_string() => MetaValue::from(transaction_id.0.as_str()),
+    ];
+    let category = overrides
+        .and_then(|overrides| overrides.category.as_ref())
+        .or(transaction.category.as_ref());
+    if let Some(category) = category {
+        meta.insert(
+            "plaid_category".to_string(),
+            format!("{}.{}", category.primary, category.detailed).into(),
+        );
+    }
+    let date = if let Some(authorized_date) = transaction.authorized_date {
+        // Transaction has both a posted and an authorized date. Let's report the authorized date
+        // as the transaction date, but add metadata with the posted date.
+        if transaction.posted_date != authorized_date {
+            meta.insert(
+                "posted_date".to_string(),
+                MetaValue::Date(transaction.posted_date),
+            );
+        }
+        authorized_date
+    } else {
+        transaction.posted_date
+    };
+    if let Some(location) = &transaction.location {
+        if location != "{}" {
+            meta.insert("plaid_location".to_string(), location.as_str().into());
+        }
+    }
+    if let Some(website) = &transaction.associated_website {
+        meta.insert(
+            "plaid_associated_website".to_string(),
+            website.as_str().into(),
+        );
+    }
+    if let Some(check_number) = &transaction.check_number {
+        meta.insert(
+            "plaid_check_number".to_string(),
+            check_number.as_str().into(),
+        );
+    }
+    let mut transaction_meta = hash_map![];
+    if let Some(note) = overrides.and_then(|overrides| overrides.note.as_deref()) {
+        transaction_meta.insert("note".to_string(), note.into());
+    }
+    let payee = overrides
+        .and_then(|overrides| overrides.payee.as_deref())
+        .or(transaction.merchant_name.as_deref());
+    let other_account = overrides.and_then(|overrides| overrides.account.as_ref());
+    let amount = ir::Amount::single_currency(transaction.amount.amount);
+    let mut postings = vec![ir::Posting {
+        account_name: account.beancount_name(),
+        amount,
+        metadata: meta,
+    }];
+    if let Some(other_account) = other_account {
+        postings.push(ir::Posting {
+            account_name: other_account.beancount_name(),
+            amount: -amount,
+            metadata: hash_map![],
+        });
+    }
+    ir::Transaction {
+        date,
+        description: transaction
+            .description_or_merchant_name
+            .clone()
+            .unwrap_or_default(),
+        payee: payee.map(str::to_string),
+        metadata: transaction_meta,
+        tags: vec![],
+        postings,
+    }
+}
+
+fn apply_script(
+    transaction_ir: ir::Transaction,
+    script: Option<&Script>,
+) -> Result<Option<ir::Transaction>> {
+    match script {
+        Some(script) => script
+            .apply(transaction_ir)
+            .map_err(|err| ExportError::Script(err.into())),
+        None => Ok(Some(transaction_ir)),
+    }
+}
+
+fn ir_to_beancount<'a>(
+    transaction_ir: ir::Transaction,
+    currency: Option<&'a str>,
+) -> Result<Directive<'a>> {
+    // The accounts of the postings are Beancount account names, including the ones set by the script.
+    // They're parsed up front so the conversion below can't fail.
+    let accounts = transaction_ir
+        .postings
+        .iter()
+        .map(|posting| {
+            let account = parse_beancount_account_name(&posting.account_name)?;
+            Ok((posting.account_name.clone(), account))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    // Plaid amounts are all in the currency of the transaction, there is no ledger currency to convert to
+    let directive = ir::beancount::transaction_to_beancount(transaction_ir, None, |account_name| {
+        Ok(BeancountAccount {
+            account: account_to_beancount(accounts[account_name].clone()),
+            currency,
+        })
+    })
+    .expect("All accounts were parsed above");
+    Ok(directive)
+}
+
+fn account_to_beancount(account: BeancountAccountInfo) -> beancount_core::Account<'static> {
+    let ty = match account.ty {
+        AccountType::Assets => beancount_core::AccountType::Assets,
+        AccountType::Liabilities => beancount_core::AccountType::Liabilities,
+        AccountType::Equity => beancount_core::AccountType::Equity,
+        AccountType::Income => beancount_core::AccountType::Income,
+        AccountType::Expenses => beancount_core::AccountType::Expenses,
+    };
+    let parts = account.name_parts.into_iter().map(Cow::Owned).collect();
+    beancount_core::Account { ty, parts }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::db::{
+        AccessToken, Account, AccountId, BankConnection, DbPlaidAuth, ManualTransaction,
+        PlaidAccountInfo, TransactionBuilder,
+    };
+    use crate::mapping::parse_beancount_account_name;
+
+    fn rename(directives: RenameDirectives) -> AccountRename {
+        AccountRename {
+            account_id: AccountId("account-1".to_string()),
+            renamed_on: NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
+            old_account: BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Checking".to_string()],
+            },
+            new_account: BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Bank".to_string(), "Checking".to_string()],
+            },
+            directives,
+            already_exported: false,
+        }
+    }
+
+    #[test]
+    fn write_rename_directives() {
+        let renames = [
+            rename(RenameDirectives::CloseOpen),
+            rename(RenameDirectives::None),
+            rename(RenameDirectives::Note),
+        ];
+        let mut out = vec![];
+        assert_eq!(3, write_account_renames(renames.iter(), &mut out).unwrap());
+        assert_eq!(
+            "2024-02-01 close Assets:Checking\n\
+            2024-02-01 open Assets:Bank:Checking\n\
+            \n\
+            2024-02-01 note Assets:Bank:Checking \"Renamed from Assets:Checking\"\n\
+            \n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn balance_overridden_account() {
+        let account = BeancountAccountInfo {
+            ty: AccountType::Liabilities,
+            name_parts: vec!["CreditCard".to_string()],
+        };
+        let transaction = TransactionBuilder::new()
+            .posted_on(2024, 1, 16)
+            .authorized_on(2024, 1, 15)
+            .cents(-500)
+            .merchant_name("Store")
+            .description("STORE 123")
+            .info();
+        let overrides = TransactionOverrides {
+            note: None,
+            account: Some(BeancountAccountInfo {
+                ty: AccountType::Expenses,
+                name_parts: vec!["Groceries".to_string()],
+            }),
+            payee: Some("Grocery Store".to_string()),
+            category: None,
+        };
+        let Directive::Transaction(directive) = transaction_to_beancount(
+            &account,
+            &TransactionId("transaction-1".to_string()),
+            &transaction,
+            Some(&overrides),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap() else {
+            panic!("Expected a transaction");
+        };
+        assert_eq!(Some(Cow::Borrowed("Grocery Store")), directive.payee);
+        assert_eq!("STORE 123", directive.narration);
+        assert_eq!(
+            vec![
+                (Some(Decimal::new(-500, 2)), Some(Cow::Borrowed("USD"))),
+                (Some(Decimal::new(500, 2)), Some(Cow::Borrowed("USD"))),
+            ],
+            directive
+                .postings
+                .iter()
+                .map(|posting| (posting.units.num, posting.units.currency.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(&beancount_core::metadata::MetaValue::Date(
+                NaiveDate::from_ymd_opt(2024, 1, 16).unwrap().into()
+            )),
+            directive.postings[0].meta.get("posted_date")
+        );
+    }
+
+    #[test]
+    fn book_rest_of_candidate_to_fixme() {
+        let account = BeancountAccountInfo {
+            ty: AccountType::Liabilities,
+            name_parts: vec!["CreditCard".to_string()],
+        };
+        let transaction = TransactionBuilder::new()
+            .posted_on(2024, 1, 15)
+            .cents(-500)
+            .description("STORE 123")
+            .info();
+        let candidate = candidate_transaction(
+            &account,
+            &TransactionId("transaction-1".to_string()),
+            &transaction,
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(candidate.is_balanced());
+        assert_eq!(
+            vec![
+                ("Liabilities:CreditCard", Decimal::new(-500, 2)),
+                ("Expenses:FIXME", Decimal::new(500, 2)),
+            ],
+            candidate
+                .postings
+                .iter()
+                .map(|posting| (
+                    posting.account_name.as_str(),
+                    posting.amount.in_account_currency
+                ))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(&MetaValue::from("STORE 123")),
+            candidate.postings[0].metadata.get("source_desc")
+        );
+        assert_eq!(
+            Some(&MetaValue::from("transaction-1")),
+            candidate.postings[0].metadata.get(CLEARED_METADATA_KEY)
+        );
+    }
+
+    #[test]
+    fn export_transfer_as_one_transaction() {
+        let checking = BeancountAccountInfo {
+            ty: AccountType::Assets,
+            name_parts: vec!["Checking".to_string()],
+        };
+        let credit_card = BeancountAccountInfo {
+            ty: AccountType::Liabilities,
+            name_parts: vec!["CreditCard".to_string()],
+        };
+        let transaction = |day, amount, description: &str| {
+            TransactionBuilder::new()
+                .posted_on(2024, 1, day)
+                .cents(amount)
+                .description(description)
+                .build()
+        };
+        let payment = transaction(15, -10000, "CREDIT CARD PAYMENT");
+        let received = transaction(16, 10000, "THANK YOU");
+        let ids = [
+            TransactionId("payment".to_string()),
+            TransactionId("received".to_string()),
+        ];
+        let mut progress = vec![];
+        let num_exported = write_exported_transactions(
+            [
+                (&credit_card, &ids[1], &received),
+                (&checking, &ids[0], &payment),
+            ]
+            .into_iter(),
+            &HashMap::new(),
+            &HashMap::new(),
+            None,
+            None,
+            &mut std::io::sink(),
+            |num_written| progress.push(num_written),
+        )
+        .unwrap();
+        assert_eq!(1, num_exported);
+        assert_eq!(vec![2], progress);
+
+        let Directive::Transaction(directive) = transfer_to_beancount(
+            (&checking, &ids[0], &payment.transaction),
+            (&credit_card, &ids[1], &received.transaction),
+            &HashMap::new(),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap() else {
+            panic!("Expected a transaction");
+        };
+        assert_eq!("CREDIT CARD PAYMENT", directive.narration);
+        assert_eq!(
+            vec![
+                ("Checking", Some(Decimal::new(-10000, 2))),
+                ("CreditCard", Some(Decimal::new(10000, 2))),
+            ],
+            directive
+                .postings
+                .iter()
+                .map(|posting| (posting.account.parts[0].as_ref(), posting.units.num))
+                .collect::<Vec<_>>()
+        );
+        assert!(directive.postings[1]
+            .meta
+            .contains_key(CLEARED_METADATA_KEY));
+    }
+
+    #[test]
+    fn sort_same_day_by_account_and_id() {
+        let transaction = |day| {
+            TransactionBuilder::new()
+                .posted_on(2024, 1, day)
+                .cents(-500)
+                .build()
+        };
+        let account = |name: &str, transactions: &[(&str, u32)]| {
+            let mut account = Account::new_connected(
+                PlaidAccountInfo {
+                    name: name.to_string(),
+                    official_name: None,
+                    mask: None,
+                    type_: "depository".to_string(),
+                    subtype: None,
+                },
+                parse_beancount_account_name(&format!("Assets:{name}")).unwrap(),
+            );
+            for (id, day) in transactions {
+                let _ = account
+                    .account
+                    .as_mut()
+                    .unwrap()
+                    .add_or_verify_transaction(TransactionId(id.to_string()), transaction(*day));
+            }
+            account
+        };
+        let mut database =
+            DatabaseV16::new(DbPlaidAuth::new("client".to_string(), "secret".to_string()));
+        database.bank_connections.push(BankConnection::new(
+            "Bank".to_string(),
+            AccessToken::new("token".to_string()),
+            None,
+            hash_map![
+                AccountId("savings".to_string()) => account("Savings", &[("s2", 2), ("s1", 1)]),
+                AccountId("checking".to_string()) => account("Checking", &[("c3", 1), ("c1", 1), ("c2", 1)]),
+            ],
+        ));
+        database.manual_transactions.insert(
+            TransactionId("manual".to_string()),
+            ManualTransaction {
+                beancount_account_info: parse_beancount_account_name("Assets:Cash").unwrap(),
+                transaction: transaction(1),
+            },
+        );
+
+        let ids = |transactions: Vec<(&BeancountAccountInfo, &TransactionId, &Transaction)>| {
+            transactions
+                .into_iter()
+                .map(|(_, id, _)| id.0.clone())
+                .collect::<Vec<_>>()
+        };
+        let expected = vec!["manual", "c1", "c2", "c3", "s1", "s2"];
+        assert_eq!(expected, ids(all_transactions(&database, None, None)));
+        let mut new_transactions: Vec<_> = all_transactions(&database, None, None)
+            .into_iter()
+            .rev()
+            .collect();
+        sort_for_export(&mut new_transactions);
+        assert_eq!(expected, ids(new_transactions));
+    }
+
+    #[test]
+    fn dont_mark_as_exported_if_output_fails() {
+        struct FailingWriter;
+        impl Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk full"))
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut account = Account::new_connected(
+            PlaidAccountInfo {
+                name: "Checking".to_string(),
+                official_name: None,
+                mask: None,
+                type_: "depository".to_string(),
+                subtype: None,
+            },
+            parse_beancount_account_name("Assets:Bank:Checking").unwrap(),
+        );
+        let _ = account.account.as_mut().unwrap().add_or_verify_transaction(
+            TransactionId("a".to_string()),
+            TransactionBuilder::new()
+                .posted_on(2024, 2, 15)
+                .cents(-500)
+                .build(),
+        );
+        let mut database =
+            DatabaseV16::new(DbPlaidAuth::new("client".to_string(), "secret".to_string()));
+        database.bank_connections.push(BankConnection::new(
+            "Bank".to_string(),
+            AccessToken::new("token".to_string()),
+            None,
+            hash_map![AccountId("account-1".to_string()) => account],
+        ));
+        database
+            .account_renames
+            .push(rename(RenameDirectives::CloseOpen));
+        let is_exported = |database: &DatabaseV16| {
+            let transaction = database.bank_connections[0]
+                .account(&AccountId("account-1".to_string()))
+                .unwrap()
+                .account
+                .as_ref()
+                .unwrap()
+                .transactions
+                .get(&TransactionId("a".to_string()))
+                .unwrap();
+            (
+                transaction.already_exported,
+                database.account_renames[0].already_exported,
+            )
+        };
+
+        let result =
+            export_new_transactions(&mut database, None, None, &mut FailingWriter, |_, _| {});
+        assert!(matches!(result, Err(ExportError::Io(_))));
+        assert_eq!((false, false), is_exported(&database));
+
+        let mut output = vec![];
+        let result =
+            export_new_transactions(&mut database, None, None, &mut output, |_, _| {}).unwrap();
+        assert_eq!((1, 2), (result.num_transactions, result.num_directives));
+        assert_eq!((true, true), is_exported(&database));
+    }
+
+    #[test]
+    fn report_progress_per_chunk() {
+        let account = BeancountAccountInfo {
+            ty: AccountType::Assets,
+            name_parts: vec!["Checking".to_string()],
+        };
+        let transactions: Vec<_> = (0..CHUNK_SIZE + 500)
+            .map(|i| {
+                (
+                    TransactionId(i.to_string()),
+                    TransactionBuilder::new()
+                        .posted_on(2024, 1, 15)
+                        .cents(-500)
+                        .build(),
+                )
+            })
+            .collect();
+        let mut progress = vec![];
+        let num_exported = write_exported_transactions(
+            transactions.iter().map(|(id, t)| (&account, id, t)),
+            &HashMap::new(),
+            &HashMap::new(),
+            None,
+            None,
+            &mut std::io::sink(),
+            |num_written| progress.push(num_written),
+        )
+        .unwrap();
+        assert_eq!(CHUNK_SIZE + 500, num_exported);
+        assert_eq!(vec![CHUNK_SIZE, CHUNK_SIZE + 500], progress);
+    }
+}
@@ -0,0 +1,215 @@
+//! Pairing the two sides of transfers between connected accounts, e.g. a credit card payment from a checking account,
+//! so they're exported as one transaction instead of two that each book the money to an unknown account
+
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+
+use crate::db::{
+    AmountSign, BeancountAccountInfo, Transaction, TransactionId, TransactionInfo,
+    TransactionOverrides,
+};
+
+/// How many days the two sides of a transfer can be apart, e.g. because the receiving bank posts it later
+const MAX_DAYS_APART: i64 = 5;
+
+/// Plaid categories of money moving between the user's accounts
+const TRANSFER_CATEGORIES: &[&str] = &["TRANSFER_IN", "TRANSFER_OUT", "LOAN_PAYMENTS"];
+
+/// Words in the descriptions of transfers, for transactions Plaid didn't categorize
+const TRANSFER_DESCRIPTION_WORDS: &[&str] = &["transfer", "payment"];
+
+/// Find the transfers among `transactions`: a sending and a receiving transaction in different accounts with
+/// opposite amounts in the same currency, at most [MAX_DAYS_APART] days apart, where at least one side looks like a
+/// transfer by its category or description. Each sending side is paired with the closest receiving side by date.
+/// Transactions that an override books to another account are left alone. Amounts are compared with the
+/// [AmountSign::Standard] sign, `amount_signs` has the sign of the account of each transaction that isn't standard.
+/// Returns the index of the receiving side for the index of the sending side.
+pub(super) fn find_transfers(
+    transactions: &[(&BeancountAccountInfo, &TransactionId, &Transaction)],
+    overrides: &HashMap<TransactionId, TransactionOverrides>,
+    amount_signs: &HashMap<&TransactionId, AmountSign>,
+) -> HashMap<usize, usize> {
+    let standard_amount = |transaction_id: &TransactionId, t: &Transaction| {
+        amount_signs
+            .get(transaction_id)
+            .copied()
+            .unwrap_or_default()
+            .apply(t.transaction.amount.amount)
+    };
+    let is_candidate = |transaction_id: &TransactionId| {
+        overrides
+            .get(transaction_id)
+            .is_none_or(|overrides| overrides.account.is_none())
+    };
+    let mut receiving_sides: HashMap<(Option<&str>, Decimal), Vec<usize>> = HashMap::new();
+    for (index, (_, transaction_id, t)) in transactions.iter().enumerate() {
+        let amount = standard_amount(transaction_id, t);
+        if amount > Decimal::ZERO && is_candidate(transaction_id) {
+            receiving_sides
+                .entry((t.transaction.amount.iso_currency_code.as_deref(), amount))
+                .or_default()
+                .push(index);
+        }
+    }
+    let mut transfers = HashMap::new();
+    for (index, (account, transaction_id, t)) in transactions.iter().enumerate() {
+        let amount = standard_amount(transaction_id, t);
+        if amount >= Decimal::ZERO || !is_candidate(transaction_id) {
+            continue;
+        }
+        let Some(candidates) =
+            receiving_sides.get_mut(&(t.transaction.amount.iso_currency_code.as_deref(), -amount))
+        else {
+            continue;
+        };
+        let sending_looks_like_transfer =
+            looks_like_transfer(&t.transaction, overrides.get(*transaction_id));
+        let closest = candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(position, &other)| {
+                let (other_account, other_id, other_t) = transactions[other];
+                let days_apart = (t.transaction.date() - other_t.transaction.date())
+                    .num_days()
+                    .abs();
+                let is_transfer = !same_account(account, other_account)
+                    && days_apart <= MAX_DAYS_APART
+                    && (sending_looks_like_transfer
+                        || looks_like_transfer(&other_t.transaction, overrides.get(other_id)));
+                is_transfer.then_some((position, days_apart))
+            })
+            .min_by_key(|(_, days_apart)| *days_apart);
+        if let Some((position, _)) = closest {
+            transfers.insert(index, candidates.remove(position));
+        }
+    }
+    transfers
+}
+
+fn same_account(account: &BeancountAccountInfo, other: &BeancountAccountInfo) -> bool {
+    account.ty == other.ty && account.name_parts == other.name_parts
+}
+
+fn looks_like_transfer(
+    transaction: &TransactionInfo,
+    overrides: Option<&TransactionOverrides>,
+) -> bool {
+    let category = overrides
+        .and_then(|overrides| overrides.category.as_ref())
+        .or(transaction.category.as_ref());
+    if category.is_some_and(|category| TRANSFER_CATEGORIES.contains(&category.primary.as_str())) {
+        return true;
+    }
+    transaction
+        .description_or_merchant_name
+        .as_deref()
+        .is_some_and(|description| {
+            let description = description.to_lowercase();
+            TRANSFER_DESCRIPTION_WORDS
+                .iter()
+                .any(|word| description.contains(word))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{AccountType, TransactionBuilder};
+
+    fn account(name: &str) -> BeancountAccountInfo {
+        BeancountAccountInfo {
+            ty: AccountType::Assets,
+            name_parts: vec![name.to_string()],
+        }
+    }
+
+    fn transaction(day: u32, amount: i64, category: Option<&str>) -> Transaction {
+        TransactionBuilder::new()
+            .posted_on(2024, 1, day)
+            .cents(amount)
+            .primary_category(category)
+            .description("ACH")
+            .build()
+    }
+
+    fn ids(n: usize) -> Vec<TransactionId> {
+        (0..n).map(|i| TransactionId(format!("t{i}"))).collect()
+    }
+
+    #[test]
+    fn pair_closest_receiving_side() {
+        let checking = account("Checking");
+        let credit_card = account("CreditCard");
+        let savings = account("Savings");
+        let ids = ids(4);
+        let transactions = [
+            transaction(15, -10000, Some("LOAN_PAYMENTS")),
+            transaction(19, 10000, None),
+            transaction(17, 10000, None),
+            // Same amount in the same account isn't a transfer
+            transaction(15, 10000, None),
+        ];
+        let accounts = [&checking, &savings, &credit_card, &checking];
+        let transactions: Vec<_> = (0..4)
+            .map(|i| (accounts[i], &ids[i], &transactions[i]))
+            .collect();
+        assert_eq!(
+            HashMap::from([(0, 2)]),
+            find_transfers(&transactions, &HashMap::new(), &HashMap::new())
+        );
+    }
+
+    #[test]
+    fn pair_with_inverted_account() {
+        let checking = account("Checking");
+        let credit_card = account("CreditCard");
+        let other_card = account("OtherCard");
+        let ids = ids(3);
+        // With AmountSign::Inverted, the payment received by the credit card is stored negative and a charge positive
+        let payment = transaction(15, -10000, Some("LOAN_PAYMENTS"));
+        let received = transaction(16, -10000, None);
+        let charge = transaction(15, 10000, Some("TRANSFER_IN"));
+        let transactions = [
+            (&checking, &ids[0], &payment),
+            (&credit_card, &ids[1], &received),
+            (&other_card, &ids[2], &charge),
+        ];
+        let amount_signs = HashMap::from([
+            (&ids[1], AmountSign::Inverted),
+            (&ids[2], AmountSign::Inverted),
+        ]);
+        assert_eq!(
+            HashMap::from([(0, 1)]),
+            find_transfers(&transactions, &HashMap::new(), &amount_signs)
+        );
+    }
+
+    #[test]
+    fn dont_pair_without_transfer_hint() {
+        let checking = account("Checking");
+        let credit_card = account("CreditCard");
+        let ids = ids(2);
+        let sending = transaction(15, -10000, Some("GENERAL_MERCHANDISE"));
+        let receiving = transaction(16, 10000, None);
+        let transactions = [
+            (&checking, &ids[0], &sending),
+            (&credit_card, &ids[1], &receiving),
+        ];
+        assert!(find_transfers(&transactions, &HashMap::new(), &HashMap::new()).is_empty());
+    }
+
+    #[test]
+    fn dont_pair_too_far_apart() {
+        let checking = account("Checking");
+        let credit_card = account("CreditCard");
+        let ids = ids(2);
+        let sending = transaction(1, -10000, Some("TRANSFER_OUT"));
+        let receiving = transaction(1 + MAX_DAYS_APART as u32 + 1, 10000, None);
+        let transactions = [
+            (&checking, &ids[0], &sending),
+            (&credit_card, &ids[1], &receiving),
+        ];
+        assert!(find_transfers(&transactions, &HashMap::new(), &HashMap::new()).is_empty());
+    }
+}
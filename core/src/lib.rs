@@ -0,0 +1,22 @@
+//! Storage, Plaid access and Beancount export for beancount-import.
+//!
+//! This crate doesn't print anything or interact with the user, so it can be embedded in other tools.
+//! Functions return structured results and leave presenting them to the caller.
+//!
+//! * [db] contains the encrypted database of bank connections and their transactions, and how it's stored on disk.
+//! * [plaid_api] talks to the Plaid API to link bank accounts and download their transactions.
+//! * [sync] downloads the transactions of bank connections into the database.
+//! * [mapping] assigns the accounts of bank connections to Beancount accounts.
+//! * [export] renders transactions from the database as a Beancount ledger.
+//! * [error] has the error types of the other modules, so embedders can match on them.
+//!
+//! With the `pyo3` feature, opening the database and exporting are also available as a Python module.
+
+pub mod db;
+pub mod error;
+pub mod export;
+pub mod mapping;
+pub mod plaid_api;
+#[cfg(feature = "pyo3")]
+mod python;
+pub mod sync;
@@ -0,0 +1,487 @@
+use chrono::NaiveDate;
+
+use crate::db::{
+    Account, AccountId, AccountRename, AccountType, AmountSign, BankConnection,
+    BeancountAccountInfo, DatabaseV16, DbError, RenameDirectives, Transactions,
+};
+use crate::error::ParseError;
+
+const ACCOUNT_ROOTS: [(&str, AccountType); 5] = [
+    ("Assets", AccountType::Assets),
+    ("Liabilities", AccountType::Liabilities),
+    ("Equity", AccountType::Equity),
+    ("Income", AccountType::Income),
+    ("Expenses", AccountType::Expenses),
+];
+
+/// Parse an account name like `Assets:Bank:Checking`. Like in Beancount, there must be at least one component after
+/// the root, and each component must start with a capital letter or digit and only contain letters, digits and dashes.
+/// See [suggest_beancount_account_name] to correct a rejected name.
+pub fn parse_beancount_account_name(name: &str) -> Result<BeancountAccountInfo, ParseError> {
+    let mut parts = name.split(':');
+    let root = parts
+        .next()
+        .expect("There should always be at least one part to the split");
+    let (_, ty) = ACCOUNT_ROOTS
+        .iter()
+        .find(|(root_name, _)| *root_name == root)
+        .ok_or_else(|| ParseError::AccountName(name.to_string()))?;
+    let name_parts: Vec<String> = parts.map(|v| v.to_string()).collect();
+    let invalid = |problem: String| ParseError::AccountNameComponent {
+        name: name.to_string(),
+        problem,
+    };
+    if name_parts.is_empty() {
+        return Err(invalid(format!(
+            "it needs a component after {root}, e.g. {root}:Bank"
+        )));
+    }
+    for component in &name_parts {
+        let Some(first) = component.chars().next() else {
+            return Err(invalid("it has an empty component".to_string()));
+        };
+        if !first.is_uppercase() && !first.is_numeric() {
+            return Err(invalid(format!(
+                "component {component} must start with a capital letter or a digit"
+            )));
+        }
+        if let Some(c) = component
+            .chars()
+            .find(|c| !c.is_alphanumeric() && *c != '-')
+        {
+            return Err(invalid(format!(
+                "component {component} contains {c:?}, only letters, digits and dashes are allowed"
+            )));
+        }
+    }
+    Ok(BeancountAccountInfo {
+        ty: *ty,
+        name_parts,
+    })
+}
+
+/// Correct an account name that [parse_beancount_account_name] rejects, e.g. `assets:bank:credit card` to
+/// `Assets:Bank:CreditCard`. The root can be in any case, empty components are dropped, words separated by spaces or
+/// underscores are joined and capitalized, and other characters that aren't allowed become dashes.
+/// Returns `None` if the name can't be corrected, e.g. because its root is unknown.
+pub fn suggest_beancount_account_name(name: &str) -> Option<String> {
+    let mut parts = name.split(':');
+    let root = parts.next()?.trim();
+    let (root, _) = ACCOUNT_ROOTS
+        .iter()
+        .find(|(root_name, _)| root_name.eq_ignore_ascii_case(root))?;
+    let components: Vec<String> = parts
+        .map(correct_component)
+        .filter(|component| !component.is_empty())
+        .collect();
+    let suggestion = std::iter::once(root.to_string())
+        .chain(components)
+        .collect::<Vec<_>>()
+        .join(":");
+    parse_beancount_account_name(&suggestion)
+        .is_ok()
+        .then_some(suggestion)
+}
+
+fn correct_component(component: &str) -> String {
+    component
+        .split(|c: char| c.is_whitespace() || c == '_')
+        .map(|word| {
+            let word: String = word
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { '-' })
+                .collect();
+            let mut chars = word.trim_matches('-').chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Find the connection with this name
+pub fn find_connection_mut<'a>(
+    bank_connections: &'a mut [BankConnection],
+    connection_name: &str,
+) -> Result<&'a mut BankConnection, DbError> {
+    bank_connections
+        .iter_mut()
+        .find(|c| c.name() == connection_name)
+        .ok_or_else(|| {
+            DbError::NotFound(format!("No connection found with name {connection_name}"))
+        })
+}
+
+/// Find the only account of the connection with this name that is connected, or unconnected if `connected` is false
+pub fn find_account_by_name(
+    connection: &BankConnection,
+    account_name: &str,
+    connected: bool,
+) -> Result<AccountId, DbError> {
+    let matching_accounts: Vec<&AccountId> = connection
+        .accounts()
+        .filter(|(_, account)| {
+            account.is_connected() == connected && account.plaid_account_info.name == account_name
+        })
+        .map(|(account_id, _)| account_id)
+        .collect();
+    let kind = if connected {
+        "connected"
+    } else {
+        "unconnected"
+    };
+    match matching_accounts.as_slice() {
+        [account_id] => Ok((*account_id).clone()),
+        [] => Err(DbError::NotFound(format!(
+            "No {kind} account found with name {account_name}"
+        ))),
+        _ => Err(DbError::Refused(format!(
+            "There are multiple {kind} accounts with name {account_name}"
+        ))),
+    }
+}
+
+/// Result of [connect_account]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectAccountResult {
+    pub account_id: AccountId,
+    /// Transactions synced while the account was pending, which are exported from now on
+    pub num_released: usize,
+}
+
+/// Connect an account that wasn't added to a Beancount account. If it was pending, its synced transactions are
+/// released for export.
+pub fn connect_account(
+    database: &mut DatabaseV16,
+    connection_name: &str,
+    account_name: &str,
+    beancount_account_info: BeancountAccountInfo,
+) -> Result<ConnectAccountResult, DbError> {
+    let connection = find_connection_mut(&mut database.bank_connections, connection_name)?;
+    let account_id = find_account_by_name(connection, account_name, false)?;
+    let account = connection
+        .account_mut(&account_id)
+        .expect("We just found this account");
+    let mut new_account =
+        Account::new_connected(account.plaid_account_info.clone(), beancount_account_info);
+    let pending_transactions = database.pending_accounts.remove(&account_id);
+    let num_released = pending_transactions.as_ref().map_or(0, Transactions::len);
+    let was_pending = pending_transactions.is_some();
+    if let Some(transactions) = pending_transactions {
+        new_account
+            .account
+            .as_mut()
+            .expect("We just created a connected account")
+            .transactions = transactions;
+    }
+    *account = new_account;
+    if !was_pending {
+        // Its transactions were skipped by the previous syncs
+        connection.reset_sync_cursor();
+    }
+    Ok(ConnectAccountResult {
+        account_id,
+        num_released,
+    })
+}
+
+/// Export the transactions of a connected account to `new_account` from now on and record the rename,
+/// so the next export can write `directives` for it. Returns the recorded rename.
+pub fn remap_account(
+    database: &mut DatabaseV16,
+    connection_name: &str,
+    account_name: &str,
+    new_account: BeancountAccountInfo,
+    directives: RenameDirectives,
+    today: NaiveDate,
+) -> Result<AccountRename, DbError> {
+    let connection = find_connection_mut(&mut database.bank_connections, connection_name)?;
+    let account_id = find_account_by_name(connection, account_name, true)?;
+    let connected_account = connection
+        .account_mut(&account_id)
+        .expect("We just found this account")
+        .account
+        .as_mut()
+        .expect("We just checked that the account is connected");
+    if connected_account.beancount_account_info.beancount_name() == new_account.beancount_name() {
+        return Err(DbError::Refused(format!(
+            "Account {account_name} is already exported to {}",
+            new_account.beancount_name()
+        )));
+    }
+    // Transactions that weren't exported yet go to the new account, so it has to be open by then
+    let renamed_on = connected_account
+        .transactions
+        .iter_all_sorted_by_date()
+        .filter(|(_, transaction)| !transaction.already_exported)
+        .map(|(_, transaction)| transaction.transaction.date())
+        .min()
+        .map_or(today, |date| date.min(today));
+    let old_account = std::mem::replace(
+        &mut connected_account.beancount_account_info,
+        new_account.clone(),
+    );
+    let rename = AccountRename {
+        account_id,
+        renamed_on,
+        old_account,
+        new_account,
+        directives,
+        already_exported: false,
+    };
+    database.account_renames.push(rename.clone());
+    Ok(rename)
+}
+
+/// Store and export the amounts of an account with `sign` from now on, see [AmountSign]. The stored transactions are
+/// converted so syncing them again still matches, and the balance anchor is dropped so the next sync takes a new one.
+/// Transactions that were already exported stay as they are in the ledger. Returns how many transactions were
+/// converted, `None` if the account already had this sign.
+pub fn set_amount_sign(
+    database: &mut DatabaseV16,
+    connection_name: &str,
+    account_name: &str,
+    sign: AmountSign,
+) -> Result<Option<usize>, DbError> {
+    let connection = find_connection_mut(&mut database.bank_connections, connection_name)?;
+    let account_id = find_account_by_name(connection, account_name, true)
+        .or_else(|err| find_account_by_name(connection, account_name, false).map_err(|_| err))?;
+    if database
+        .amount_signs
+        .get(&account_id)
+        .copied()
+        .unwrap_or_default()
+        == sign
+    {
+        return Ok(None);
+    }
+    let account = connection
+        .account_mut(&account_id)
+        .expect("We just found this account");
+    let transactions = match account.account.as_mut() {
+        Some(connected_account) => Some(&mut connected_account.transactions),
+        None => database.pending_accounts.get_mut(&account_id),
+    };
+    let mut num_converted = 0;
+    if let Some(transactions) = transactions {
+        for (_, transaction) in transactions.iter_all_sorted_by_date_mut() {
+            let amount = &mut transaction.transaction.amount.amount;
+            *amount = -*amount;
+            num_converted += 1;
+        }
+    }
+    database.balance_anchors.remove(&account_id);
+    match sign {
+        AmountSign::Standard => database.amount_signs.remove(&account_id),
+        AmountSign::Inverted => database.amount_signs.insert(account_id, sign),
+    };
+    Ok(Some(num_converted))
+}
+
+/// Stop or continue syncing a connected account. Accounts with sync disabled keep their transactions, and they're
+/// still exported. Enabling sync makes the next sync download all transactions of the connection again, to get the
+/// ones that were skipped in the meantime. Returns `false` if sync was already enabled or disabled.
+pub fn set_sync_enabled(
+    database: &mut DatabaseV16,
+    connection_name: &str,
+    account_name: &str,
+    sync_enabled: bool,
+) -> Result<bool, DbError> {
+    let connection = find_connection_mut(&mut database.bank_connections, connection_name)?;
+    let account_id = find_account_by_name(connection, account_name, true)?;
+    let connected_account = connection
+        .account_mut(&account_id)
+        .expect("We just found this account")
+        .account
+        .as_mut()
+        .expect("We just checked that the account is connected");
+    if connected_account.sync_enabled == sync_enabled {
+        return Ok(false);
+    }
+    connected_account.sync_enabled = sync_enabled;
+    if sync_enabled {
+        connection.reset_sync_cursor();
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use common_macros::hash_map;
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::db::{
+        AccessToken, DbPlaidAuth, PlaidAccountInfo, TransactionBuilder, TransactionId,
+    };
+
+    #[test]
+    fn parse_account_name() {
+        assert_eq!(
+            BeancountAccountInfo {
+                ty: AccountType::Liabilities,
+                name_parts: vec!["Bank".to_string(), "CreditCard".to_string()],
+            },
+            parse_beancount_account_name("Liabilities:Bank:CreditCard").unwrap()
+        );
+        assert!(matches!(
+            parse_beancount_account_name("Bank:Checking"),
+            Err(ParseError::AccountName(name)) if name == "Bank:Checking"
+        ));
+    }
+
+    #[test]
+    fn reject_invalid_components() {
+        for (name, problem) in [
+            (
+                "Assets",
+                "it needs a component after Assets, e.g. Assets:Bank",
+            ),
+            ("Assets::Checking", "it has an empty component"),
+            (
+                "Assets:bank",
+                "component bank must start with a capital letter or a digit",
+            ),
+            (
+                "Assets:Credit Card",
+                "component Credit Card contains ' ', only letters, digits and dashes are allowed",
+            ),
+        ] {
+            let err = parse_beancount_account_name(name).unwrap_err();
+            assert_eq!(
+                format!("Invalid account {name}, {problem}"),
+                err.to_string()
+            );
+        }
+        assert!(parse_beancount_account_name("Expenses:2024:Café-Bar").is_ok());
+    }
+
+    #[test]
+    fn suggest_account_name() {
+        assert_eq!(
+            Some("Assets:Bank:CreditCard".to_string()),
+            suggest_beancount_account_name("assets:bank:credit card")
+        );
+        assert_eq!(
+            Some("Expenses:Food:Take-out".to_string()),
+            suggest_beancount_account_name("Expenses::food:take/out_")
+        );
+        assert_eq!(None, suggest_beancount_account_name("Bank:Checking"));
+        assert_eq!(None, suggest_beancount_account_name("Assets:_"));
+    }
+
+    #[test]
+    fn convert_transactions_to_new_amount_sign() {
+        let mut account = Account::new_connected(
+            PlaidAccountInfo {
+                name: "Card".to_string(),
+                official_name: None,
+                mask: None,
+                type_: "credit".to_string(),
+                subtype: None,
+            },
+            BeancountAccountInfo {
+                ty: AccountType::Liabilities,
+                name_parts: vec!["Card".to_string()],
+            },
+        );
+        let _ = account.account.as_mut().unwrap().add_or_verify_transaction(
+            TransactionId("purchase".to_string()),
+            TransactionBuilder::new()
+                .posted_on(2024, 1, 15)
+                .cents(-1250)
+                .build(),
+        );
+        let account_id = AccountId("card".to_string());
+        let mut database =
+            DatabaseV16::new(DbPlaidAuth::new("client".to_string(), "secret".to_string()));
+        database.bank_connections.push(BankConnection::new(
+            "Bank".to_string(),
+            AccessToken::new("token".to_string()),
+            None,
+            hash_map![account_id.clone() => account],
+        ));
+        let amount = |database: &DatabaseV16| {
+            let account = database.bank_connections[0].account(&account_id).unwrap();
+            let transactions = &account.account.as_ref().unwrap().transactions;
+            let (_, transaction) = transactions.iter_all_sorted_by_date().next().unwrap();
+            transaction.transaction.amount.amount
+        };
+
+        assert_eq!(
+            Some(1),
+            set_amount_sign(&mut database, "Bank", "Card", AmountSign::Inverted).unwrap()
+        );
+        assert_eq!(Decimal::new(1250, 2), amount(&database));
+        assert_eq!(AmountSign::Inverted, database.amount_sign(&account_id));
+        assert_eq!(
+            None,
+            set_amount_sign(&mut database, "Bank", "Card", AmountSign::Inverted).unwrap()
+        );
+
+        set_amount_sign(&mut database, "Bank", "Card", AmountSign::Standard).unwrap();
+        assert_eq!(Decimal::new(-1250, 2), amount(&database));
+        assert!(database.amount_signs.is_empty());
+    }
+
+    fn database_with_account(account: Account) -> DatabaseV16 {
+        let mut database =
+            DatabaseV16::new(DbPlaidAuth::new("client".to_string(), "secret".to_string()));
+        let mut connection = BankConnection::new(
+            "Bank".to_string(),
+            AccessToken::new("token".to_string()),
+            None,
+            hash_map![AccountId("checking".to_string()) => account],
+        );
+        connection.set_sync_cursor("cursor".to_string());
+        database.bank_connections.push(connection);
+        database
+    }
+
+    fn checking_info() -> PlaidAccountInfo {
+        PlaidAccountInfo {
+            name: "Checking".to_string(),
+            official_name: None,
+            mask: None,
+            type_: "depository".to_string(),
+            subtype: None,
+        }
+    }
+
+    #[test]
+    fn enabling_sync_downloads_all_transactions_again() {
+        let mut database = database_with_account(Account::new_connected(
+            checking_info(),
+            parse_beancount_account_name("Assets:Checking").unwrap(),
+        ));
+        let is_synced = |database: &DatabaseV16| {
+            database.bank_connections[0]
+                .account(&AccountId("checking".to_string()))
+                .unwrap()
+                .is_synced()
+        };
+
+        assert!(set_sync_enabled(&mut database, "Bank", "Checking", false).unwrap());
+        assert!(!is_synced(&database));
+        assert_eq!(Some("cursor"), database.bank_connections[0].sync_cursor());
+        assert!(!set_sync_enabled(&mut database, "Bank", "Checking", false).unwrap());
+
+        assert!(set_sync_enabled(&mut database, "Bank", "Checking", true).unwrap());
+        assert!(is_synced(&database));
+        assert_eq!(None, database.bank_connections[0].sync_cursor());
+    }
+
+    #[test]
+    fn connecting_skipped_account_downloads_all_transactions_again() {
+        let mut database = database_with_account(Account::new_unconnected(checking_info()));
+        connect_account(
+            &mut database,
+            "Bank",
+            "Checking",
+            parse_beancount_account_name("Assets:Checking").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(None, database.bank_connections[0].sync_cursor());
+    }
+}
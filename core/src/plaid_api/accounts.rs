@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use plaid::model::AccountBase;
+use rust_decimal::Decimal;
+
+use crate::db::{AccessToken, AccountId, PlaidAccountInfo};
+
+use super::{amount::decimal_from_plaid, client::Plaid, error::translate_error, PlaidApiError};
+
+pub struct Accounts<I> {
+    pub institution_id: Option<String>,
+    pub accounts: I,
+}
+
+pub async fn get_accounts(
+    client: &Plaid,
+    access_token: &AccessToken,
+) -> Result<
+    Accounts<
+        impl Iterator<Item = Result<(AccountId, PlaidAccountInfo), PlaidApiError>> + ExactSizeIterator,
+    >,
+    PlaidApiError,
+> {
+    tracing::info!("Requesting accounts...");
+
+    let response = client
+        .client()
+        .accounts_get(access_token.get())
+        .await
+        .map_err(translate_error)?;
+    let accounts = response.accounts.into_iter().map(account_info);
+
+    tracing::info!("Requesting accounts...done");
+    Ok(Accounts {
+        institution_id: response.item.institution_id,
+        accounts,
+    })
+}
+
+fn account_info(account: AccountBase) -> Result<(AccountId, PlaidAccountInfo), PlaidApiError> {
+    Ok((
+        AccountId(account.account_id),
+        PlaidAccountInfo {
+            name: account.name,
+            official_name: account.official_name,
+            mask: account.mask,
+            type_: account.type_,
+            subtype: account
+                .subtype
+                .map(|subtype| match subtype.0 {
+                    serde_json::Value::String(s) => Ok(s),
+                    _ => Err(PlaidApiError::UnexpectedResponse(format!(
+                        "Expected string for account subtype but got {:?}",
+                        subtype
+                    ))),
+                })
+                .transpose()?,
+        },
+    ))
+}
+
+/// Balance of an account as reported by Plaid, without pending transactions.
+/// For credit and loan accounts, it's the amount owed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Balance {
+    pub current: Decimal,
+    pub iso_currency_code: Option<String>,
+}
+
+/// What Plaid currently reports about an account, see [get_account_status]
+#[derive(Debug, Clone)]
+pub struct AccountStatus {
+    pub info: PlaidAccountInfo,
+    /// `None` if Plaid doesn't know the balance
+    pub balance: Option<Balance>,
+}
+
+/// The current details and balances of the accounts of a bank connection
+pub async fn get_account_status(
+    client: &Plaid,
+    access_token: &AccessToken,
+) -> Result<HashMap<AccountId, AccountStatus>, PlaidApiError> {
+    tracing::info!("Requesting account status...");
+
+    let response = client
+        .client()
+        .accounts_get(access_token.get())
+        .await
+        .map_err(translate_error)?;
+    let status = response
+        .accounts
+        .into_iter()
+        .map(|account| {
+            let balance = account
+                .balances
+                .current
+                .map(|current| {
+                    decimal_from_plaid(current)
+                        .map(|current| Balance {
+                            current,
+                            iso_currency_code: account.balances.iso_currency_code.clone(),
+                        })
+                        .ok_or_else(|| {
+                            PlaidApiError::UnexpectedResponse(format!(
+                                "Failed to parse balance {current}"
+                            ))
+                        })
+                })
+                .transpose()?;
+            let (account_id, info) = account_info(account)?;
+            Ok::<_, PlaidApiError>((account_id, AccountStatus { info, balance }))
+        })
+        .collect::<Result<_, _>>()?;
+
+    tracing::info!("Requesting account status...done");
+    Ok(status)
+}
@@ -0,0 +1,64 @@
+use rust_decimal::Decimal;
+
+/// Convert an amount the `plaid` crate decoded as `f64` back to the decimal number Plaid sent.
+///
+/// The JSON text isn't available anymore at this point, but the shortest representation that round trips to the same
+/// `f64` is the number Plaid sent for any amount with up to 15 significant digits. Converting through it instead of
+/// the binary value avoids artifacts like `0.1` becoming `0.1000000000000000055511151231`.
+/// Returns `None` if the amount isn't finite or doesn't fit into a [Decimal] without rounding.
+pub(super) fn decimal_from_plaid(amount: f64) -> Option<Decimal> {
+    if !amount.is_finite() {
+        return None;
+    }
+    // Display for f64 prints the shortest round tripping representation and never uses an exponent
+    Decimal::from_str_exact(&amount.to_string()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    #[test]
+    fn keeps_reported_digits() {
+        for reported in [
+            "0.1",
+            "0.07",
+            "0.3",
+            "2.675",
+            "1.005",
+            "-19.99",
+            "1234567.89",
+            "12345678901.23",
+            "123456789012.3456",
+            "0.00000001",
+            "9007199254740.99",
+        ] {
+            let parsed: f64 = reported.parse().unwrap();
+            assert_eq!(
+                Some(Decimal::from_str(reported).unwrap()),
+                decimal_from_plaid(parsed),
+                "{reported}"
+            );
+            assert_eq!(
+                reported,
+                decimal_from_plaid(parsed).unwrap().to_string(),
+                "{reported}"
+            );
+        }
+    }
+
+    #[test]
+    fn negative_zero_is_zero() {
+        assert_eq!("0", decimal_from_plaid(-0.0).unwrap().to_string());
+    }
+
+    #[test]
+    fn rejects_unrepresentable_amounts() {
+        assert_eq!(None, decimal_from_plaid(f64::NAN));
+        assert_eq!(None, decimal_from_plaid(f64::INFINITY));
+        assert_eq!(None, decimal_from_plaid(1e300));
+        assert_eq!(None, decimal_from_plaid(5e-324));
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::db::TransactionCategory;
+use crate::error::ParseError;
+
+const CATEGORIES_CSV: &str = include_str!("plaid_categories.csv");
+
+/// The embedded table, merged with the overrides if [load_category_overrides] was called
+static CATEGORIES: OnceLock<CategoryTable> = OnceLock::new();
+
+/// Plaid's categories with their descriptions. Plaid adds categories over time, so the table that is embedded in the
+/// binary can be extended with a CSV file in the same format, see [load_category_overrides].
+#[derive(Debug)]
+struct CategoryTable {
+    categories: Vec<(TransactionCategory, String)>,
+    positions: HashMap<TransactionCategory, usize>,
+}
+
+impl CategoryTable {
+    fn embedded() -> Self {
+        let categories =
+            parse_categories(CATEGORIES_CSV.as_bytes()).expect("The embedded categories are valid");
+        let mut table = Self {
+            categories: vec![],
+            positions: HashMap::new(),
+        };
+        table.merge(categories);
+        table
+    }
+
+    /// Categories that are already in the table get the new description, the others are added at the end.
+    /// Returns how many were added.
+    fn merge(&mut self, categories: Vec<(TransactionCategory, String)>) -> usize {
+        let mut num_added = 0;
+        for (category, description) in categories {
+            match self.positions.get(&category) {
+                Some(&position) => self.categories[position].1 = description,
+                None => {
+                    self.positions
+                        .insert(category.clone(), self.categories.len());
+                    self.categories.push((category, description));
+                    num_added += 1;
+                }
+            }
+        }
+        num_added
+    }
+
+    fn description(&self, category: &TransactionCategory) -> Option<&str> {
+        self.positions
+            .get(category)
+            .map(|&position| self.categories[position].1.as_str())
+    }
+}
+
+fn table() -> &'static CategoryTable {
+    CATEGORIES.get_or_init(CategoryTable::embedded)
+}
+
+/// Merge the categories of the CSV file at `path` over the embedded ones, e.g. categories Plaid added after this
+/// version was released. The file has the columns `PRIMARY,DETAILED,DESCRIPTION` like the one Plaid publishes.
+/// A category that is in both gets the description from the file. Returns how many categories were added.
+///
+/// # Panics
+/// If the categories were already looked up, load the overrides before doing anything else.
+pub fn load_category_overrides(path: &Path) -> Result<usize, ParseError> {
+    let file = std::fs::File::open(path).map_err(|err| ParseError::Csv {
+        path: path.to_path_buf(),
+        source: err.into(),
+    })?;
+    let overrides = parse_categories(file).map_err(|source| ParseError::Csv {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut table = CategoryTable::embedded();
+    let num_added = table.merge(overrides);
+    CATEGORIES
+        .set(table)
+        .expect("The category overrides must be loaded before the categories are looked up");
+    Ok(num_added)
+}
+
+/// All categories Plaid assigns to transactions with their description, in the order Plaid lists them.
+/// Categories added by [load_category_overrides] come last.
+pub fn categories() -> &'static [(TransactionCategory, String)] {
+    &table().categories
+}
+
+/// Description of the category, `None` if it isn't in Plaid's list, e.g. because it was entered with
+/// `transaction recategorize` or Plaid added it after the list was last updated. See [unknown_categories].
+pub fn category_description(category: &TransactionCategory) -> Option<&'static str> {
+    table().description(category)
+}
+
+/// Descriptions of the categories, in the same order. For looking up the categories of many transactions at once,
+/// e.g. when exporting.
+pub fn category_descriptions<'a>(
+    categories: impl IntoIterator<Item = &'a TransactionCategory>,
+) -> Vec<Option<&'static str>> {
+    categories.into_iter().map(category_description).collect()
+}
+
+/// The categories that aren't in Plaid's list, sorted and without repetitions. Plaid reporting one of these means the
+/// list is outdated and the category should be added with [load_category_overrides].
+pub fn unknown_categories<'a>(
+    categories: impl IntoIterator<Item = &'a TransactionCategory>,
+) -> Vec<TransactionCategory> {
+    categories
+        .into_iter()
+        .filter(|category| category_description(category).is_none())
+        .map(|category| (&category.primary, &category.detailed))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|(primary, detailed)| TransactionCategory {
+            primary: primary.clone(),
+            detailed: detailed.clone(),
+        })
+        .collect()
+}
+
+fn parse_categories(
+    csv: impl std::io::Read,
+) -> Result<Vec<(TransactionCategory, String)>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .delimiter(b',')
+        .from_reader(csv);
+    reader
+        .deserialize()
+        .map(|row| {
+            let row: CategoryRow = row?;
+            Ok((
+                TransactionCategory {
+                    primary: row.primary.trim().to_string(),
+                    detailed: row.detailed.trim().to_string(),
+                },
+                row.description.trim().to_string(),
+            ))
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct CategoryRow {
+    primary: String,
+    detailed: String,
+    description: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(primary: &str, detailed: &str) -> TransactionCategory {
+        TransactionCategory {
+            primary: primary.to_string(),
+            detailed: detailed.to_string(),
+        }
+    }
+
+    fn description(primary: &str, detailed: &str) -> &'static str {
+        let category = category(primary, detailed);
+        categories()
+            .iter()
+            .find(|(c, _)| *c == category)
+            .map(|(_, description)| description.as_str())
+            .unwrap()
+    }
+
+    #[test]
+    fn parse_categories() {
+        assert_eq!(104, categories().len());
+        assert_eq!(
+            "Pet supplies and pet food",
+            description("GENERAL_MERCHANDISE", "GENERAL_MERCHANDISE_PET_SUPPLIES")
+        );
+        // And a row that has a comma in the description
+        assert_eq!(
+            "Rental cars, charter buses, and trucks",
+            description("TRAVEL", "TRAVEL_RENTAL_CARS")
+        );
+    }
+
+    #[test]
+    fn lookup_descriptions() {
+        assert_eq!(
+            Some("Pet supplies and pet food"),
+            category_description(&category(
+                "GENERAL_MERCHANDISE",
+                "GENERAL_MERCHANDISE_PET_SUPPLIES"
+            ))
+        );
+        assert_eq!(
+            vec![Some("Rental cars, charter buses, and trucks"), None],
+            category_descriptions(&[
+                category("TRAVEL", "TRAVEL_RENTAL_CARS"),
+                category("FOOD_AND_DRINK", "FOOD_AND_DRINK_UNKNOWN"),
+            ])
+        );
+        // Every category has exactly one entry
+        assert_eq!(categories().len(), table().positions.len());
+    }
+
+    #[test]
+    fn merge_overrides() {
+        let mut table = CategoryTable::embedded();
+        let num_embedded = table.categories.len();
+        let overrides = super::parse_categories(
+            "PRIMARY,DETAILED,DESCRIPTION\n\
+            TRAVEL,TRAVEL_RENTAL_CARS,Rental cars\n\
+            TRAVEL,TRAVEL_SPACE_FLIGHTS,\"Flights to orbit, and back\"\n"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(1, table.merge(overrides));
+        assert_eq!(num_embedded + 1, table.categories.len());
+        assert_eq!(
+            Some("Rental cars"),
+            table.description(&category("TRAVEL", "TRAVEL_RENTAL_CARS"))
+        );
+        assert_eq!(
+            Some("Flights to orbit, and back"),
+            table.description(&category("TRAVEL", "TRAVEL_SPACE_FLIGHTS"))
+        );
+    }
+
+    #[test]
+    fn reject_overrides_without_description() {
+        assert!(
+            super::parse_categories("PRIMARY,DETAILED\nTRAVEL,TRAVEL_SPACE\n".as_bytes()).is_err()
+        );
+    }
+
+    #[test]
+    fn find_unknown_categories() {
+        let known = category("TRAVEL", "TRAVEL_RENTAL_CARS");
+        let unknown = category("TRAVEL", "TRAVEL_SPACE_FLIGHTS");
+        assert_eq!(
+            vec![unknown.clone()],
+            unknown_categories(&[unknown.clone(), known, unknown])
+        );
+    }
+}
@@ -0,0 +1,120 @@
+/// The error of a Plaid request, e.g. from the HTTP client
+pub type RequestError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Errors of talking to Plaid. The variants before [PlaidApiError::Request] are Plaid errors the user can do something
+/// about, their message explains it and suggests the command to run.
+#[derive(Debug, thiserror::Error)]
+pub enum PlaidApiError {
+    /// The Plaid client id or secret is wrong, e.g. because the secret was rotated
+    #[error(
+        "Plaid rejected the client ID or secret. Check them at https://dashboard.plaid.com/developers/keys \
+        and create the database again with `init` if they changed."
+    )]
+    InvalidApiKeys(#[source] RequestError),
+
+    /// The login of a bank connection expired or changed, e.g. after a password change
+    #[error(
+        "The bank connection has to be linked again because its login expired or changed. \
+        Remove it with `connection remove` and add it again with `connection add`."
+    )]
+    NeedsRelink(#[source] RequestError),
+
+    /// Plaid didn't finish the first download of transactions for a new bank connection yet
+    #[error(
+        "Plaid is still downloading the transactions of this bank connection, which can take a few minutes \
+        after it was added. Run `sync` again later."
+    )]
+    ProductNotReady(#[source] RequestError),
+
+    /// Too many requests in a short time
+    #[error("Plaid received too many requests. Wait a few minutes before trying again.")]
+    RateLimit(#[source] RequestError),
+
+    /// Any other failed request
+    #[error("Plaid request failed")]
+    Request(#[source] RequestError),
+
+    /// Plaid answered with data we can't store
+    #[error("{0}")]
+    UnexpectedResponse(String),
+
+    /// The local server for Plaid's link UI didn't start or crashed
+    #[error("Failed to run the server for the link flow")]
+    LinkServer(#[source] Box<rocket::Error>),
+
+    #[error("Failed to find the LAN address of this machine")]
+    LanAddress(#[source] std::io::Error),
+
+    /// The cancellation token was cancelled while waiting for Plaid
+    #[error("Cancelled")]
+    Cancelled,
+
+    /// The `open_url` callback of the link flow failed
+    #[error("Failed to open the link flow")]
+    OpenUrl(#[source] RequestError),
+}
+
+/// Wraps the cause of a failed request into the matching [PlaidApiError] variant
+type ErrorConstructor = fn(RequestError) -> PlaidApiError;
+
+/// Plaid error codes, and for rate limits the error type, as they appear in the response body
+const ERROR_CODES: &[(&str, ErrorConstructor)] = &[
+    ("INVALID_API_KEYS", PlaidApiError::InvalidApiKeys),
+    ("ITEM_LOGIN_REQUIRED", PlaidApiError::NeedsRelink),
+    ("PRODUCT_NOT_READY", PlaidApiError::ProductNotReady),
+    ("RATE_LIMIT_EXCEEDED", PlaidApiError::RateLimit),
+    ("RATE_LIMIT", PlaidApiError::RateLimit),
+];
+
+/// Pick the [PlaidApiError] of a failed Plaid request from its known cause
+pub(super) fn translate_error(err: impl Into<RequestError>) -> PlaidApiError {
+    let err = err.into();
+    // The Plaid client doesn't expose the error code, but its errors contain the response body
+    let message = format!("{err:?}");
+    match ERROR_CODES.iter().find(|(code, _)| message.contains(code)) {
+        Some((_, error)) => error(err),
+        None => PlaidApiError::Request(err),
+    }
+}
+
+impl From<rocket::Error> for PlaidApiError {
+    fn from(err: rocket::Error) -> Self {
+        Self::LinkServer(Box::new(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &str) -> RequestError {
+        body.into()
+    }
+
+    #[test]
+    fn detect_item_login_required() {
+        let err = translate_error(response(
+            r#"{"error_type": "ITEM_ERROR", "error_code": "ITEM_LOGIN_REQUIRED"}"#,
+        ));
+        assert!(matches!(err, PlaidApiError::NeedsRelink(_)), "{err:?}");
+    }
+
+    #[test]
+    fn detect_rate_limit() {
+        let err = translate_error(response(
+            r#"{"error_type": "RATE_LIMIT_EXCEEDED", "error_code": "TRANSACTIONS_LIMIT"}"#,
+        ));
+        assert!(matches!(err, PlaidApiError::RateLimit(_)), "{err:?}");
+        assert!(err
+            .to_string()
+            .starts_with("Plaid received too many requests"));
+    }
+
+    #[test]
+    fn keep_other_errors() {
+        let err = translate_error(response(
+            r#"{"error_type": "INSTITUTION_ERROR", "error_code": "INSTITUTION_DOWN"}"#,
+        ));
+        assert!(matches!(err, PlaidApiError::Request(_)), "{err:?}");
+    }
+}
@@ -1,10 +1,12 @@
-use anyhow::Result;
 use plaid::{
     model::{LinkTokenCreateRequestUser, LinkTokenTransactions},
     request::LinkTokenCreateRequired,
 };
 
-use crate::{db::AccessToken, plaid_api::Plaid};
+use crate::{
+    db::AccessToken,
+    plaid_api::{error::translate_error, Plaid, PlaidApiError, RequestError},
+};
 
 use super::{
     link_http_server,
@@ -17,23 +19,30 @@ const LANGUAGE: &str = "en";
 const USER_ID: &str = "user-id";
 const PRODUCTS: &[&str] = &["transactions"];
 
-/// Link a new account and return the access token. This will launch an in-browser account linking flow with Plaid's UI
-pub async fn link_new_account(client: &Plaid) -> Result<AccessToken> {
-    log::info!("Requesting link token...");
+/// Link a new account and return the access token. This will launch an in-browser account linking flow with Plaid's UI.
+/// `open_url` is called with the URL the user has to open to go through the flow. With `listen_on_lan`, the URL can be
+/// opened on other devices in the network as well.
+pub async fn link_new_account<E: Into<RequestError>>(
+    client: &Plaid,
+    listen_on_lan: bool,
+    open_url: impl FnOnce(&str) -> Result<(), E>,
+) -> Result<AccessToken, PlaidApiError> {
+    tracing::info!("Requesting link token...");
     let link_token: LinkToken = link_token_create(client).await?;
-    log::info!("Requesting link token...done");
+    tracing::info!("Requesting link token...done");
 
-    log::info!("Initiating link flow...");
-    let public_token = link_http_server::link_in_browser(link_token).await?;
-    log::info!("Initiating link flow...done");
+    tracing::info!("Initiating link flow...");
+    let public_token =
+        link_http_server::link_in_browser(link_token, listen_on_lan, open_url).await?;
+    tracing::info!("Initiating link flow...done");
 
-    log::info!("Requesting access token...");
+    tracing::info!("Requesting access token...");
     let access_token = exchange_public_token(client, public_token).await?;
-    log::info!("Requesting access token...done");
+    tracing::info!("Requesting access token...done");
     Ok(access_token)
 }
 
-pub async fn link_token_create(client: &Plaid) -> Result<LinkToken> {
+pub async fn link_token_create(client: &Plaid) -> Result<LinkToken, PlaidApiError> {
     let response = client
         .client()
         .link_token_create(LinkTokenCreateRequired {
@@ -49,14 +58,19 @@ pub async fn link_token_create(client: &Plaid) -> Result<LinkToken> {
         .transactions(LinkTokenTransactions {
             days_requested: Some(730), // Ask for access to 730 days of transaction history. This is the maximum allowed by the Plaid API.
         })
-        .await?;
+        .await
+        .map_err(translate_error)?;
     Ok(LinkToken(response.link_token))
 }
 
-async fn exchange_public_token(client: &Plaid, public_token: PublicToken) -> Result<AccessToken> {
+async fn exchange_public_token(
+    client: &Plaid,
+    public_token: PublicToken,
+) -> Result<AccessToken, PlaidApiError> {
     let response = client
         .client()
         .item_public_token_exchange(&public_token.0)
-        .await?;
+        .await
+        .map_err(translate_error)?;
     Ok(AccessToken::new(response.access_token))
 }
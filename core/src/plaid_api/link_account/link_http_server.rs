@@ -1,13 +1,13 @@
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
 
-use anyhow::Result;
-use console::style;
 use rocket::{get, http::ContentType, response::content::RawHtml, routes, Config, Shutdown, State};
 use std::sync::Mutex;
 
 use super::tokens::{LinkToken, PublicToken};
+use crate::plaid_api::{PlaidApiError, RequestError};
 
 const LISTEN_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+const LISTEN_ADDR_LAN: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
 const LISTEN_PORT: u16 = 8080;
 
 const FAVICON_ICO: &[u8] = include_bytes!("static/logo.ico");
@@ -17,10 +17,23 @@ struct ServerState {
     public_token: Mutex<Option<PublicToken>>,
 }
 
-pub async fn link_in_browser(link_token: LinkToken) -> Result<PublicToken> {
+/// Serve Plaid's link UI on a local port and wait until the user finished it there.
+/// With `listen_on_lan`, the server is reachable from other devices in the network, e.g. a phone,
+/// instead of only from this machine.
+/// `open_url` is called with the URL of the link UI once the server is ready, e.g. to open it in a browser.
+pub async fn link_in_browser<E: Into<RequestError>>(
+    link_token: LinkToken,
+    listen_on_lan: bool,
+    open_url: impl FnOnce(&str) -> Result<(), E>,
+) -> Result<PublicToken, PlaidApiError> {
+    let (listen_addr, url_addr) = if listen_on_lan {
+        (LISTEN_ADDR_LAN, lan_address()?)
+    } else {
+        (LISTEN_ADDR, LISTEN_ADDR)
+    };
     let server = rocket::custom(Config {
         log_level: rocket::config::LogLevel::Critical,
-        address: LISTEN_ADDR,
+        address: listen_addr,
         port: LISTEN_PORT,
         ..Default::default()
     })
@@ -32,12 +45,9 @@ pub async fn link_in_browser(link_token: LinkToken) -> Result<PublicToken> {
     .ignite()
     .await?;
 
-    let url = format!("http://{LISTEN_ADDR}:{LISTEN_PORT}");
+    let url = format!("http://{url_addr}:{LISTEN_PORT}");
 
-    println!("Starting in-browser link flow.");
-    println!("If it doesn't open automatically, please open the following URL in your browser:");
-    println!("{}", style(&url).cyan().italic());
-    open::that(url)?;
+    open_url(&url).map_err(|err| PlaidApiError::OpenUrl(err.into()))?;
 
     // start server and wait for it to shutdown
     let server = server.launch().await?;
@@ -52,6 +62,16 @@ pub async fn link_in_browser(link_token: LinkToken) -> Result<PublicToken> {
     Ok(public_token)
 }
 
+/// The address other devices in the network reach this machine at. Connecting a UDP socket doesn't send anything,
+/// it only makes the OS pick the interface that routes to the internet.
+fn lan_address() -> Result<IpAddr, PlaidApiError> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(PlaidApiError::LanAddress)?;
+    socket
+        .connect((Ipv4Addr::new(8, 8, 8, 8), 80))
+        .map_err(PlaidApiError::LanAddress)?;
+    Ok(socket.local_addr().map_err(PlaidApiError::LanAddress)?.ip())
+}
+
 #[get("/")]
 fn show_auth_page(state: &State<ServerState>) -> RawHtml<String> {
     let link_token = &state.link_token.0;
@@ -0,0 +1,19 @@
+mod accounts;
+mod amount;
+mod categories;
+mod client;
+mod error;
+mod link_account;
+mod test_connection;
+mod transactions;
+
+pub use accounts::{get_account_status, get_accounts, AccountStatus, Accounts, Balance};
+pub use categories::{
+    categories, category_description, category_descriptions, load_category_overrides,
+    unknown_categories,
+};
+pub use client::Plaid;
+pub use error::{PlaidApiError, RequestError};
+pub use link_account::link_new_account;
+pub use test_connection::test_connection;
+pub use transactions::{refresh_transactions, stream_transactions, TransactionWithAccount};
@@ -0,0 +1,7 @@
+use super::{link_account::link_token_create, Plaid, PlaidApiError};
+
+pub async fn test_connection(client: &Plaid) -> Result<(), PlaidApiError> {
+    // The easiest way to test the connection is to create a link token
+    link_token_create(client).await?;
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use plaid::model::TransactionsSyncRequestOptions;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument as _;
+
+use super::{amount::decimal_from_plaid, client::Plaid, error::translate_error, PlaidApiError};
+use crate::db::{
+    AccessToken, AccountId, Amount, Transaction, TransactionCategory, TransactionId,
+    TransactionTimes,
+};
+
+/// Download the transactions added or modified since `cursor` page by page into `pages`, so the caller can add each
+/// page while the next one is downloaded. Without `cursor`, all transactions are downloaded. Returns the cursor after
+/// the last page, which can be stored to continue syncing from there.
+/// Fails with [PlaidApiError::Cancelled] as soon as `cancel` is cancelled, without waiting for the page that is
+/// being requested, or if `pages` was closed.
+pub async fn stream_transactions(
+    client: &Plaid,
+    access_token: &AccessToken,
+    mut cursor: Option<String>,
+    cancel: &CancellationToken,
+    pages: mpsc::Sender<Vec<TransactionWithAccount>>,
+) -> Result<String, PlaidApiError> {
+    tracing::info!("Requesting transactions...");
+
+    let mut pagenum = 0;
+    loop {
+        pagenum += 1;
+        let page = cancel
+            .run_until_cancelled(
+                sync_transactions_page(client, access_token, cursor)
+                    .instrument(tracing::info_span!("page", number = pagenum)),
+            )
+            .await
+            .ok_or(PlaidApiError::Cancelled)??;
+        // The receiver only stops early if adding the transactions failed
+        if pages.send(page.transactions).await.is_err() {
+            return Err(PlaidApiError::Cancelled);
+        }
+        if !page.has_more {
+            tracing::info!(num_pages = pagenum, "Requesting transactions...done");
+            return Ok(page.next_cursor);
+        }
+        cursor = Some(page.next_cursor);
+    }
+}
+
+/// Ask Plaid to check the bank for new transactions now instead of at its next scheduled update. Plaid downloads them
+/// in the background, the next `sync` a few minutes later gets them.
+pub async fn refresh_transactions(
+    client: &Plaid,
+    access_token: &AccessToken,
+) -> Result<(), PlaidApiError> {
+    tracing::info!("Requesting transaction refresh...");
+    client
+        .client()
+        .transactions_refresh(access_token.get())
+        .await
+        .map_err(translate_error)?;
+    tracing::info!("Requesting transaction refresh...done");
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct TransactionWithAccount {
+    pub account_id: AccountId,
+    pub transaction_id: TransactionId,
+    /// The dates of `transaction` are Plaid's, sync derives them from the times in the configured timezone.
+    /// The amount has Plaid's sign, money leaving the account is positive. Sync stores it with the account's
+    /// [crate::db::AmountSign].
+    pub transaction: Transaction,
+    pub times: TransactionTimes,
+}
+
+struct TransactionsPage {
+    transactions: Vec<TransactionWithAccount>,
+    has_more: bool,
+    next_cursor: String,
+}
+
+async fn sync_transactions_page(
+    client: &Plaid,
+    access_token: &AccessToken,
+    cursor: Option<String>,
+) -> Result<TransactionsPage, PlaidApiError> {
+    let mut request = client
+        .client()
+        .transactions_sync(access_token.get())
+        .options(TransactionsSyncRequestOptions {
+            include_original_description: Some(true),
+            // days_requested: Some(730), // This is specified in the link token create flow, not here.
+            ..Default::default()
+        })
+        .count(500); // 500 is the max page size allowed by the Plaid API
+    if let Some(cursor) = cursor {
+        request = request.cursor(&cursor);
+    }
+    tracing::debug!("Requesting page...");
+    let response = request.await.map_err(translate_error)?;
+    tracing::debug!(
+        num_added = response.added.len(),
+        num_modified = response.modified.len(),
+        num_removed = response.removed.len(),
+        has_more = response.has_more,
+        "Requesting page...done"
+    );
+
+    // Mostly pending transactions that were posted under a new id, which aren't stored. Stored transactions are kept.
+    for removed in &response.removed {
+        tracing::warn!("Ignoring removed transaction: {:?}", removed);
+    }
+    // Modified transactions are verified against the stored ones, so the changes are reported as mismatches
+    let transactions = response
+        .added
+        .into_iter()
+        .chain(response.modified)
+        .flat_map(|transaction| {
+            if transaction.transaction_base.pending {
+                tracing::warn!("Ignoring pending transaction: {:?}", transaction);
+                None
+            } else {
+                let amount = match decimal_from_plaid(transaction.transaction_base.amount) {
+                    Some(amount) => amount,
+                    None => {
+                        return Some(Err(PlaidApiError::UnexpectedResponse(format!(
+                            "Failed to parse amount {}",
+                            transaction.transaction_base.amount
+                        ))))
+                    }
+                };
+                let posted_date = transaction.date;
+                Some(Ok(TransactionWithAccount {
+                    account_id: AccountId::new(transaction.transaction_base.account_id),
+                    transaction_id: TransactionId(transaction.transaction_base.transaction_id),
+                    transaction: crate::db::Transaction::new(crate::db::TransactionInfo {
+                        merchant_name: transaction.transaction_base.merchant_name,
+                        description_or_merchant_name: transaction.transaction_base.name,
+                        original_description: transaction.transaction_base.original_description,
+                        posted_date,
+                        authorized_date: transaction.authorized_date,
+                        category: transaction.personal_finance_category.map(|category| {
+                            TransactionCategory {
+                                primary: category.primary,
+                                detailed: category.detailed,
+                            }
+                        }),
+                        amount: Amount {
+                            amount,
+                            iso_currency_code: transaction.transaction_base.iso_currency_code,
+                        },
+                        check_number: transaction.transaction_base.check_number,
+                        transaction_type: transaction.transaction_base.transaction_type,
+                        associated_website: transaction.transaction_base.website,
+                        location: transaction
+                            .transaction_base
+                            .location
+                            .map(|location| format!("{}", location)),
+                    }),
+                    times: TransactionTimes {
+                        posted: transaction.datetime,
+                        authorized: transaction.authorized_datetime,
+                    },
+                }))
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(TransactionsPage {
+        transactions,
+        has_more: response.has_more,
+        next_cursor: response.next_cursor,
+    })
+}
@@ -0,0 +1,177 @@
+//! Python bindings, built with `maturin build --features pyo3` from this directory.
+//!
+//! ```python
+//! import base64, os
+//! from beancount_import_core import Database
+//!
+//! db = Database.open("plaid.db", key=base64.b64decode(os.environ["BEANCOUNT_PLAID_KEY"]))
+//! print(db.export_new())
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use tokio::runtime::Runtime;
+
+use crate::db::{Cipher as _, DatabaseFile, DbCipher, EncryptionKey, XChaCha20Poly1305Cipher};
+use crate::export::{self, Script};
+
+fn to_py_err(err: impl Into<anyhow::Error>) -> PyErr {
+    PyRuntimeError::new_err(format!("{:#}", err.into()))
+}
+
+/// A database opened by [Database::open]. It stays locked until the object is garbage collected.
+#[pyclass(unsendable, module = "beancount_import_core")]
+struct Database {
+    db: DatabaseFile,
+    runtime: Runtime,
+}
+
+#[pymethods]
+impl Database {
+    /// Open the database at `path`. `key` is the raw 32 byte key, or `None` for an unencrypted database.
+    #[staticmethod]
+    #[pyo3(signature = (path, key=None))]
+    fn open(path: PathBuf, key: Option<&[u8]>) -> PyResult<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let db_cipher = match key {
+            Some(key) => {
+                let key: EncryptionKey = EncryptionKey::new(
+                    key.try_into()
+                        .map_err(|_| PyRuntimeError::new_err("The key must be 32 bytes"))?,
+                );
+                DbCipher::Encrypted(XChaCha20Poly1305Cipher::with_key(&key))
+            }
+            None => DbCipher::Unencrypted,
+        };
+        let db = runtime
+            .block_on(DatabaseFile::load(path, db_cipher))
+            .map_err(to_py_err)?
+            .ok_or_else(|| PyRuntimeError::new_err("Database file not found"))?
+            // Shown by `undo --list`
+            .with_command("python");
+        Ok(Self { db, runtime })
+    }
+
+    /// Render all transactions of the ledger target, or of the default target if `None`, as Beancount.
+    /// `script` is the path of a Rhai script that can change or skip transactions.
+    #[pyo3(signature = (target=None, script=None))]
+    fn export_all(&self, target: Option<&str>, script: Option<PathBuf>) -> PyResult<String> {
+        let script = load_script(script.as_deref())?;
+        let mut out = vec![];
+        export::export_all_transactions(
+            self.db.database(),
+            target,
+            script.as_ref(),
+            &mut out,
+            |_, _| {},
+        )
+        .map_err(to_py_err)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    /// Render the transactions of the ledger target that weren't exported yet as Beancount, mark them as
+    /// exported and save the database.
+    #[pyo3(signature = (target=None, script=None))]
+    fn export_new(&mut self, target: Option<&str>, script: Option<PathBuf>) -> PyResult<String> {
+        let script = load_script(script.as_deref())?;
+        let out = self
+            .export_new_and_save(target, script.as_ref())
+            .map_err(to_py_err)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    /// Names of the bank connections
+    fn connections(&self) -> Vec<String> {
+        self.db
+            .database()
+            .bank_connections
+            .iter()
+            .map(|connection| connection.name().to_string())
+            .collect()
+    }
+}
+
+impl Database {
+    /// If exporting or saving fails, the transactions aren't marked as exported, so a retry exports them again
+    fn export_new_and_save(
+        &mut self,
+        target: Option<&str>,
+        script: Option<&Script>,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.db.begin();
+        let mut out = vec![];
+        let result = export::export_new_transactions(
+            self.db.database_mut(),
+            target,
+            script,
+            &mut out,
+            |_, _| {},
+        )
+        .map_err(anyhow::Error::from)
+        .and_then(|_| Ok(self.runtime.block_on(self.db.try_save_if_modified())?));
+        match result {
+            Ok(()) => {
+                self.db.commit();
+                Ok(out)
+            }
+            Err(err) => {
+                self.db.rollback();
+                Err(err)
+            }
+        }
+    }
+}
+
+fn load_script(path: Option<&Path>) -> PyResult<Option<Script>> {
+    path.map(Script::load).transpose().map_err(to_py_err)
+}
+
+#[pymodule]
+fn beancount_import_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Database>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{
+        DatabaseV16, DbPlaidAuth, ManualTransaction, TransactionBuilder, TransactionId,
+    };
+    use crate::mapping::parse_beancount_account_name;
+
+    #[test]
+    fn export_again_after_failed_save() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database =
+            DatabaseV16::new(DbPlaidAuth::new("client".to_string(), "secret".to_string()));
+        database.manual_transactions.insert(
+            TransactionId::new_manual(),
+            ManualTransaction {
+                beancount_account_info: parse_beancount_account_name("Assets:Cash").unwrap(),
+                transaction: TransactionBuilder::new().description("Coffee").build(),
+            },
+        );
+        // The database can't be saved until its directory exists
+        let db_dir = tempdir.path().join("db");
+        let mut database = Database {
+            db: DatabaseFile::new(database, db_dir.join("plaid.db"), DbCipher::Unencrypted),
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap(),
+        };
+
+        assert!(database.export_new_and_save(None, None).is_err());
+
+        std::fs::create_dir(&db_dir).unwrap();
+        let out = database.export_new_and_save(None, None).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("Coffee"));
+        let out = database.export_new_and_save(None, None).unwrap();
+        assert!(out.is_empty());
+    }
+}
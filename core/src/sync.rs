@@ -0,0 +1,817 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, Offset as _, Utc};
+use rust_decimal::Decimal;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::db::{
+    AccountId, AddOrVerifyResult, AmountSign, BalanceAnchor, BalanceSnapshot, BankConnection,
+    DatabaseV16, DbError, PlaidAccountInfo, Transaction, TransactionCategory, TransactionId,
+    TransactionTimes, Transactions,
+};
+use crate::plaid_api::{self, PlaidApiError, TransactionWithAccount};
+
+/// What syncing one bank connection changed
+#[derive(Debug)]
+pub struct SyncReport {
+    pub account_results: HashMap<AccountId, AccountSyncReport>,
+    /// Synced transactions that differ from the stored ones. They aren't applied, see [replace_transaction].
+    pub mismatches: Vec<Mismatch>,
+    /// Plaid's balances after the sync, empty if they couldn't be downloaded. See [reconcile_balances].
+    pub balances: HashMap<AccountId, plaid_api::Balance>,
+    /// Accounts whose type, subtype or mask Plaid reports differently now, see [AccountChange]
+    pub account_changes: Vec<AccountChange>,
+    /// Categories of the added transactions that aren't in Plaid's category list, see
+    /// [plaid_api::unknown_categories]
+    pub unknown_categories: HashSet<TransactionCategory>,
+    /// Times Plaid reported for the stored transactions, to be kept in [DatabaseV16::transaction_times]
+    pub transaction_times: HashMap<TransactionId, TransactionTimes>,
+}
+
+/// Timezone that the dates of transactions are derived in from the times Plaid reports, see [TransactionTimes].
+/// Plaid's own dates can be off by a day for transactions close to midnight. Transactions without times keep them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timezone {
+    /// The timezone of the system, which can be changed with the `TZ` environment variable
+    #[default]
+    Local,
+    /// A fixed offset from UTC, e.g. `+02:00`. Doesn't follow daylight saving time.
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    pub fn date(&self, time: DateTime<Utc>) -> NaiveDate {
+        match self {
+            Self::Local => time.with_timezone(&Local).date_naive(),
+            Self::Fixed(offset) => time.with_timezone(offset).date_naive(),
+        }
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+
+    /// Parses `local`, `utc` or an offset from UTC like `+02:00`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if s.eq_ignore_ascii_case("utc") {
+            return Ok(Self::Fixed(Utc.fix()));
+        }
+        s.parse().map(Self::Fixed).map_err(|_| {
+            format!(
+                "Invalid timezone {s}, expected `local`, `utc` or an offset from UTC like `+02:00`"
+            )
+        })
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local => write!(f, "local"),
+            Self::Fixed(offset) => write!(f, "{offset}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountSyncReport {
+    pub num_added: u64,
+    pub num_verified: u64,
+    /// Sum of the amounts of the added transactions, with `amount_sign`
+    pub added_sum: Decimal,
+    /// The sign the account's transactions were stored with, see [AmountSign]
+    pub amount_sign: AmountSign,
+    /// The account isn't connected yet, but its transactions are kept until `account connect`
+    pub pending: bool,
+}
+
+/// A synced transaction that differs from the stored transaction with the same id
+#[derive(Debug)]
+pub struct Mismatch {
+    pub account_id: AccountId,
+    pub transaction_id: TransactionId,
+    pub existing_value: Transaction,
+    pub new_value: Transaction,
+}
+
+/// Plaid reports a different type, subtype or mask for an account than stored, e.g. because the card was reissued or
+/// the bank migrated the account. The stored info is already updated to `new`, but the Beancount account the account
+/// is exported to may not fit anymore.
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct AccountChange {
+    pub account_id: AccountId,
+    pub old: PlaidAccountInfo,
+    pub new: PlaidAccountInfo,
+}
+
+/// Plaid's balance of an account differs from the balance of its anchor plus the transactions synced since
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceMismatch {
+    pub account_id: AccountId,
+    /// When the anchor balance was taken
+    pub since: NaiveDate,
+    pub expected: Decimal,
+    /// Plaid's balance, with the sign of transaction amounts
+    pub actual: Decimal,
+    pub iso_currency_code: Option<String>,
+    pub date: NaiveDate,
+}
+
+impl SyncReport {
+    fn increment_num_added(&mut self, account_id: &AccountId) {
+        self.account_results.get_mut(account_id).unwrap().num_added += 1;
+    }
+
+    fn add_to_sum(&mut self, account_id: &AccountId, amount: Decimal) {
+        self.account_results.get_mut(account_id).unwrap().added_sum += amount;
+    }
+
+    fn increment_num_verified(&mut self, account_id: &AccountId) {
+        self.account_results
+            .get_mut(account_id)
+            .unwrap()
+            .num_verified += 1;
+    }
+}
+
+/// Find the account `account` of the connection to sync it alone, by its name or Plaid account id
+pub fn find_sync_account(
+    database: &DatabaseV16,
+    connection_name: &str,
+    account: &str,
+) -> Result<AccountId, DbError> {
+    let connection = database
+        .bank_connections
+        .iter()
+        .find(|c| c.name() == connection_name)
+        .ok_or_else(|| {
+            DbError::NotFound(format!("No connection found with name {connection_name}"))
+        })?;
+    let matching_accounts: Vec<&AccountId> = connection
+        .accounts()
+        .filter(|(account_id, a)| account_id.0 == account || a.plaid_account_info.name == account)
+        .map(|(account_id, _)| account_id)
+        .collect();
+    match matching_accounts.as_slice() {
+        [account_id] => Ok((*account_id).clone()),
+        [] => Err(DbError::NotFound(format!(
+            "No account found with name or id {account} in connection {connection_name}"
+        ))),
+        _ => Err(DbError::Refused(format!(
+            "There are multiple accounts with name {account}, please use its id"
+        ))),
+    }
+}
+
+/// Pages downloaded ahead of the one being added. Bounded so memory stays flat for connections with hundreds of
+/// pages, even if adding is slower than downloading.
+const PAGE_BUFFER: usize = 2;
+
+/// Download the transactions of the connection that were added or modified since the last sync and add them to its
+/// accounts, or to `pending_accounts` for accounts that aren't connected yet. The transactions of accounts with sync
+/// disabled are skipped, and with `only_account`, the ones of the other accounts too.
+/// Each page is added while the next one is downloaded. `on_progress` is called with the number of processed
+/// transactions after each page.
+/// If `cancel` is cancelled before all transactions are downloaded, fails with [PlaidApiError::Cancelled]. The pages
+/// added until then stay added, but the sync cursor only moves once all pages are added, so syncing again downloads
+/// them again and verifies them.
+/// The dates of transactions that Plaid reports times for are derived in `timezone`. The amounts are stored with the
+/// sign from `amount_signs`, see [DatabaseV16::amount_signs].
+#[allow(clippy::too_many_arguments)]
+pub async fn sync_connection(
+    plaid_api: &plaid_api::Plaid,
+    bank_connection: &mut BankConnection,
+    pending_accounts: &mut HashMap<AccountId, Transactions>,
+    amount_signs: &HashMap<AccountId, AmountSign>,
+    only_account: Option<&AccountId>,
+    timezone: Timezone,
+    cancel: &CancellationToken,
+    mut on_progress: impl FnMut(u64),
+) -> Result<SyncReport, PlaidApiError> {
+    let mut sync_report = SyncReport {
+        account_results: bank_connection
+            .accounts()
+            .filter(|(id, _)| only_account.is_none_or(|only_account| *id == only_account))
+            .map(|(id, _)| {
+                (
+                    id.clone(),
+                    AccountSyncReport {
+                        num_added: 0,
+                        num_verified: 0,
+                        added_sum: Decimal::ZERO,
+                        amount_sign: amount_signs.get(id).copied().unwrap_or_default(),
+                        pending: pending_accounts.contains_key(id),
+                    },
+                )
+            })
+            .collect(),
+        mismatches: vec![],
+        balances: HashMap::new(),
+        account_changes: vec![],
+        unknown_categories: HashSet::new(),
+        transaction_times: HashMap::new(),
+    };
+
+    // Cloned so the connection can be changed while downloading
+    let access_token = bank_connection.access_token().clone();
+    let cursor = bank_connection.sync_cursor().map(str::to_string);
+    let (pages_sender, mut pages) = mpsc::channel(PAGE_BUFFER);
+    let download =
+        plaid_api::stream_transactions(plaid_api, &access_token, cursor, cancel, pages_sender);
+    let add = async {
+        let mut num_processed = 0;
+        while let Some(page) = pages.recv().await {
+            for transaction in page {
+                if only_account.is_none_or(|only_account| transaction.account_id == *only_account) {
+                    add_transaction(
+                        bank_connection,
+                        pending_accounts,
+                        &mut sync_report,
+                        timezone,
+                        transaction,
+                    )?;
+                }
+                num_processed += 1;
+            }
+            on_progress(num_processed);
+        }
+        Ok(())
+    };
+    let (cursor, ()) = futures::future::try_join(download, add).await?;
+
+    // The cursor covers all accounts of the connection, so it can only move on if all of them were synced
+    if only_account.is_none() {
+        bank_connection.set_sync_cursor(cursor);
+    }
+    // Only needed to reconcile the balances and notice changed accounts, so syncing still succeeds without them
+    let account_status = match cancel
+        .run_until_cancelled(plaid_api::get_account_status(plaid_api, &access_token))
+        .await
+    {
+        Some(Ok(account_status)) => account_status,
+        Some(Err(err)) => {
+            tracing::warn!(error = %err, "Failed to get account status");
+            HashMap::new()
+        }
+        None => return Err(PlaidApiError::Cancelled),
+    };
+    sync_report.account_changes = update_account_infos(
+        bank_connection,
+        &sync_report.account_results,
+        &account_status,
+    );
+    sync_report.balances = account_status
+        .into_iter()
+        .filter_map(|(account_id, status)| Some((account_id, status.balance?)))
+        .collect();
+    for (account_id, result) in &sync_report.account_results {
+        let _span = tracing::info_span!("account", id = account_id.0).entered();
+        tracing::info!(
+            num_added = result.num_added,
+            num_verified = result.num_verified,
+            pending = result.pending,
+            "Synced account"
+        );
+    }
+
+    Ok(sync_report)
+}
+
+/// Store the type, subtype and mask Plaid reports now for the synced accounts, and return the accounts where they
+/// changed. The name isn't compared, users rename accounts at their bank all the time and the name is only used to
+/// find accounts in commands.
+fn update_account_infos(
+    bank_connection: &mut BankConnection,
+    synced_accounts: &HashMap<AccountId, AccountSyncReport>,
+    account_status: &HashMap<AccountId, plaid_api::AccountStatus>,
+) -> Vec<AccountChange> {
+    let mut changes = vec![];
+    for account_id in synced_accounts.keys() {
+        let (Some(account), Some(status)) = (
+            bank_connection.account_mut(account_id),
+            account_status.get(account_id),
+        ) else {
+            continue;
+        };
+        let stored = &mut account.plaid_account_info;
+        let new = &status.info;
+        if stored.type_ == new.type_ && stored.subtype == new.subtype && stored.mask == new.mask {
+            continue;
+        }
+        let old = stored.clone();
+        stored.type_ = new.type_.clone();
+        stored.subtype = new.subtype.clone();
+        stored.mask = new.mask.clone();
+        tracing::warn!(
+            account = account_id.0,
+            "Plaid reports changed account details"
+        );
+        changes.push(AccountChange {
+            account_id: account_id.clone(),
+            old,
+            new: stored.clone(),
+        });
+    }
+    changes.sort_by(|lhs, rhs| lhs.account_id.0.cmp(&rhs.account_id.0));
+    changes
+}
+
+fn add_transaction(
+    bank_connection: &mut BankConnection,
+    pending_accounts: &mut HashMap<AccountId, Transactions>,
+    sync_report: &mut SyncReport,
+    timezone: Timezone,
+    mut transaction: TransactionWithAccount,
+) -> Result<(), PlaidApiError> {
+    let account = bank_connection
+        .account_mut(&transaction.account_id)
+        .ok_or_else(|| {
+            PlaidApiError::UnexpectedResponse(format!(
+                "Found transaction for account {:?} that we don't have in our database",
+                transaction.account_id,
+            ))
+        })?;
+    let info = &mut transaction.transaction.transaction;
+    info.amount.amount = sync_report.account_results[&transaction.account_id]
+        .amount_sign
+        .apply_to_plaid(info.amount.amount);
+    if let Some(posted) = transaction.times.posted {
+        info.posted_date = timezone.date(posted);
+    }
+    if let Some(authorized) = transaction.times.authorized {
+        info.authorized_date = Some(timezone.date(authorized));
+    }
+    let amount = info.amount.amount;
+    let unknown_category = info
+        .category
+        .as_ref()
+        .filter(|category| plaid_api::category_description(category).is_none())
+        .cloned();
+    let add_or_verify_result = if let Some(account) = account
+        .account
+        .as_mut()
+        .filter(|account| account.sync_enabled)
+    {
+        account
+            .add_or_verify_transaction(transaction.transaction_id.clone(), transaction.transaction)
+    } else if let Some(transactions) = pending_accounts.get_mut(&transaction.account_id) {
+        transactions.add_or_verify(transaction.transaction_id.clone(), transaction.transaction)
+    } else {
+        // Counted as ignored, the transactions of disabled accounts aren't stored
+        sync_report.increment_num_added(&transaction.account_id);
+        return Ok(());
+    };
+    let has_times = transaction.times.posted.is_some() || transaction.times.authorized.is_some();
+    match add_or_verify_result {
+        AddOrVerifyResult::Added => {
+            sync_report.increment_num_added(&transaction.account_id);
+            sync_report.add_to_sum(&transaction.account_id, amount);
+            sync_report.unknown_categories.extend(unknown_category);
+            if has_times {
+                sync_report
+                    .transaction_times
+                    .insert(transaction.transaction_id, transaction.times);
+            }
+        }
+        AddOrVerifyResult::ExistsAndMatches => {
+            sync_report.increment_num_verified(&transaction.account_id);
+            if has_times {
+                sync_report
+                    .transaction_times
+                    .insert(transaction.transaction_id, transaction.times);
+            }
+        }
+        AddOrVerifyResult::Pruned => {
+            sync_report.increment_num_verified(&transaction.account_id);
+        }
+        AddOrVerifyResult::ExistsAndDoesntMatch {
+            existing_value,
+            new_value,
+        } => {
+            sync_report.mismatches.push(Mismatch {
+                account_id: transaction.account_id,
+                transaction_id: transaction.transaction_id,
+                existing_value,
+                new_value,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Replace a stored transaction with the synced version from a [Mismatch]
+pub fn replace_transaction(database: &mut DatabaseV16, mismatch: Mismatch) -> Result<(), DbError> {
+    let transactions = match database
+        .bank_connections
+        .iter_mut()
+        .find_map(|connection| connection.account_mut(&mismatch.account_id))
+        .and_then(|account| account.account.as_mut())
+    {
+        Some(account) => Some(&mut account.transactions),
+        None => database.pending_accounts.get_mut(&mismatch.account_id),
+    };
+    let stored = transactions
+        .and_then(|transactions| transactions.get_mut(&mismatch.transaction_id))
+        .ok_or_else(|| {
+            DbError::NotFound(format!(
+                "Transaction {} not found",
+                mismatch.transaction_id.0
+            ))
+        })?;
+    stored.transaction = mismatch.new_value.transaction;
+    Ok(())
+}
+
+/// Smallest difference between Plaid's balance and the expected balance that is reported, to ignore rounding
+const BALANCE_TOLERANCE: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
+
+/// Compare Plaid's balances from the sync of `bank_connection` against the balance anchors of its accounts plus the
+/// transactions the sync added. Differences mean that Plaid didn't send some transactions, or sent some twice.
+/// Accounts without an anchor get one with Plaid's current balance. Anchors of mismatched accounts aren't changed,
+/// so a difference that is only caused by Plaid updating balances and transactions at different times goes away
+/// with a later sync. See [reset_balance_anchor] to accept Plaid's balance instead.
+/// Only checking, savings and credit card accounts are compared, the balances of investments and loans change
+/// without transactions.
+pub fn reconcile_balances(
+    balance_anchors: &mut HashMap<AccountId, BalanceAnchor>,
+    bank_connection: &BankConnection,
+    sync_report: &SyncReport,
+    today: NaiveDate,
+) -> Vec<BalanceMismatch> {
+    let mut mismatches = vec![];
+    for (account_id, result) in &sync_report.account_results {
+        let Some(account) = bank_connection.account(account_id) else {
+            continue;
+        };
+        // Plaid's balance of credit accounts is the amount owed, while transactions spending money are negative,
+        // unless the account uses the inverted sign
+        let sign = match account.plaid_account_info.type_.as_str() {
+            "depository" => Decimal::ONE,
+            "credit" => Decimal::NEGATIVE_ONE,
+            _ => continue,
+        };
+        if !account.is_synced() {
+            // The transactions synced while it's disabled aren't stored, so the anchor would be wrong afterwards
+            balance_anchors.remove(account_id);
+            continue;
+        }
+        let Some(balance) = sync_report.balances.get(account_id) else {
+            continue;
+        };
+        let actual = result.amount_sign.apply(sign * balance.current);
+        let anchor = balance_anchors
+            .get_mut(account_id)
+            .filter(|anchor| anchor.iso_currency_code == balance.iso_currency_code);
+        let Some(anchor) = anchor else {
+            balance_anchors.insert(
+                account_id.clone(),
+                BalanceAnchor::new(today, actual, balance.iso_currency_code.clone()),
+            );
+            continue;
+        };
+        anchor.added_since += result.added_sum;
+        let expected = anchor.expected_balance();
+        if (expected - actual).abs() >= BALANCE_TOLERANCE {
+            mismatches.push(BalanceMismatch {
+                account_id: account_id.clone(),
+                since: anchor.date,
+                expected,
+                actual,
+                iso_currency_code: balance.iso_currency_code.clone(),
+                date: today,
+            });
+        }
+    }
+    mismatches
+}
+
+/// Take Plaid's balance from a [BalanceMismatch] as the new anchor, e.g. after the missing transactions were added
+/// to the ledger by hand
+pub fn reset_balance_anchor(
+    balance_anchors: &mut HashMap<AccountId, BalanceAnchor>,
+    mismatch: &BalanceMismatch,
+) {
+    balance_anchors.insert(
+        mismatch.account_id.clone(),
+        BalanceAnchor::new(
+            mismatch.date,
+            mismatch.actual,
+            mismatch.iso_currency_code.clone(),
+        ),
+    );
+}
+
+/// Store Plaid's balances from the sync of `bank_connection` as snapshots of its connected accounts, e.g. for the
+/// balance column of `account list`. A snapshot from an earlier sync on the same day is replaced.
+pub fn record_balance_snapshots(
+    bank_connection: &mut BankConnection,
+    sync_report: &SyncReport,
+    today: NaiveDate,
+) {
+    for (account_id, balance) in &sync_report.balances {
+        let Some(account) = bank_connection
+            .account_mut(account_id)
+            .and_then(|account| account.account.as_mut())
+        else {
+            continue;
+        };
+        let snapshots = &mut account.balance_snapshots;
+        if snapshots
+            .last()
+            .is_some_and(|snapshot| snapshot.date == today)
+        {
+            snapshots.pop();
+        }
+        snapshots.push(BalanceSnapshot {
+            date: today,
+            current: Some(balance.current),
+            available: None,
+            iso_currency_code: balance.iso_currency_code.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common_macros::hash_map;
+
+    use super::*;
+    use crate::db::{
+        AccessToken, Account, AccountType, BeancountAccountInfo, PlaidAccountInfo,
+        TransactionBuilder,
+    };
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn connection(type_: &str) -> BankConnection {
+        BankConnection::new(
+            "bank".to_string(),
+            AccessToken::new("access-token".to_string()),
+            None,
+            hash_map![
+                AccountId::new("account-1".to_string()) => Account::new_connected(
+                    PlaidAccountInfo {
+                        name: "Account 1".to_string(),
+                        official_name: None,
+                        mask: None,
+                        type_: type_.to_string(),
+                        subtype: None,
+                    },
+                    BeancountAccountInfo {
+                        ty: AccountType::Assets,
+                        name_parts: vec!["Account1".to_string()],
+                    },
+                ),
+            ],
+        )
+    }
+
+    /// Report of a sync that added transactions summing up to `added_sum`, after which Plaid reported `balance`
+    fn report(added_sum: i64, balance: i64) -> SyncReport {
+        let account_id = AccountId::new("account-1".to_string());
+        SyncReport {
+            account_results: hash_map![
+                account_id.clone() => AccountSyncReport {
+                    num_added: 1,
+                    num_verified: 0,
+                    added_sum: Decimal::new(added_sum, 2),
+                    amount_sign: AmountSign::Standard,
+                    pending: false,
+                },
+            ],
+            mismatches: vec![],
+            balances: hash_map![
+                account_id => plaid_api::Balance {
+                    current: Decimal::new(balance, 2),
+                    iso_currency_code: Some("USD".to_string()),
+                },
+            ],
+            account_changes: vec![],
+            unknown_categories: HashSet::new(),
+            transaction_times: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn matching_balances() {
+        let connection = connection("depository");
+        let mut anchors = HashMap::new();
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &report(0, 10000), date(1)).is_empty()
+        );
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &report(-2550, 7450), date(2)).is_empty()
+        );
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &report(1000, 8450), date(3)).is_empty()
+        );
+        let anchor = &anchors[&AccountId::new("account-1".to_string())];
+        assert_eq!(date(1), anchor.date);
+        assert_eq!(Decimal::new(8450, 2), anchor.expected_balance());
+    }
+
+    #[test]
+    fn missing_transaction() {
+        let connection = connection("depository");
+        let mut anchors = HashMap::new();
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &report(0, 10000), date(1)).is_empty()
+        );
+        let mismatches =
+            reconcile_balances(&mut anchors, &connection, &report(-2550, 5000), date(2));
+        assert_eq!(
+            vec![BalanceMismatch {
+                account_id: AccountId::new("account-1".to_string()),
+                since: date(1),
+                expected: Decimal::new(7450, 2),
+                actual: Decimal::new(5000, 2),
+                iso_currency_code: Some("USD".to_string()),
+                date: date(2),
+            }],
+            mismatches
+        );
+
+        // Reported again until the anchor is reset
+        assert_eq!(
+            1,
+            reconcile_balances(&mut anchors, &connection, &report(0, 5000), date(3)).len()
+        );
+        reset_balance_anchor(&mut anchors, &mismatches[0]);
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &report(0, 5000), date(4)).is_empty()
+        );
+    }
+
+    #[test]
+    fn credit_balance_is_amount_owed() {
+        let connection = connection("credit");
+        let mut anchors = HashMap::new();
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &report(0, 10000), date(1)).is_empty()
+        );
+        // Spending increases the debt
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &report(-2550, 12550), date(2))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn inverted_credit_balance() {
+        let connection = connection("credit");
+        let mut anchors = HashMap::new();
+        let inverted = |added_sum, balance| {
+            let mut report = report(added_sum, balance);
+            for result in report.account_results.values_mut() {
+                result.amount_sign = AmountSign::Inverted;
+            }
+            report
+        };
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &inverted(0, 10000), date(1)).is_empty()
+        );
+        // Spending is positive like the debt
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &inverted(2550, 12550), date(2))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn skip_investments() {
+        let connection = connection("investment");
+        let mut anchors = HashMap::new();
+        assert!(
+            reconcile_balances(&mut anchors, &connection, &report(0, 10000), date(1)).is_empty()
+        );
+        assert!(anchors.is_empty());
+    }
+
+    #[test]
+    fn one_balance_snapshot_per_day() {
+        let mut connection = connection("depository");
+        record_balance_snapshots(&mut connection, &report(0, 10000), date(1));
+        record_balance_snapshots(&mut connection, &report(0, 7450), date(1));
+        record_balance_snapshots(&mut connection, &report(0, 8450), date(2));
+        let account = connection
+            .account(&AccountId::new("account-1".to_string()))
+            .unwrap();
+        let snapshots = &account.account.as_ref().unwrap().balance_snapshots;
+        assert_eq!(
+            vec![
+                (date(1), Some(Decimal::new(7450, 2))),
+                (date(2), Some(Decimal::new(8450, 2))),
+            ],
+            snapshots
+                .iter()
+                .map(|snapshot| (snapshot.date, snapshot.current))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_timezone() {
+        assert_eq!(Ok(Timezone::Local), "local".parse());
+        assert_eq!(Ok(Timezone::Fixed(Utc.fix())), "UTC".parse());
+        assert_eq!(
+            Ok(Timezone::Fixed(FixedOffset::west_opt(5 * 3600).unwrap())),
+            "-05:00".parse()
+        );
+        assert!("Europe/Berlin".parse::<Timezone>().is_err());
+    }
+
+    #[test]
+    fn derive_dates_from_times() {
+        let mut connection = connection("depository");
+        let account_id = AccountId::new("account-1".to_string());
+        // Plaid's date is the UTC date, but it was still the evening before in New York
+        let posted = DateTime::from_timestamp(1_705_374_000, 0).unwrap();
+        let transaction = TransactionWithAccount {
+            account_id: account_id.clone(),
+            transaction_id: TransactionId("transaction-1".to_string()),
+            transaction: TransactionBuilder::new()
+                .posted_on(2024, 1, 16)
+                .cents(-1000)
+                .build(),
+            times: TransactionTimes {
+                posted: Some(posted),
+                authorized: None,
+            },
+        };
+        let mut report = report(0, 0);
+        let timezone = Timezone::Fixed(FixedOffset::west_opt(5 * 3600).unwrap());
+        add_transaction(
+            &mut connection,
+            &mut HashMap::new(),
+            &mut report,
+            timezone,
+            transaction,
+        )
+        .unwrap();
+
+        let stored = connection
+            .account(&account_id)
+            .unwrap()
+            .account
+            .as_ref()
+            .unwrap();
+        let (_, stored) = stored
+            .transactions
+            .iter_all_sorted_by_date()
+            .next()
+            .unwrap();
+        assert_eq!(date(15), stored.transaction.posted_date);
+        assert_eq!(
+            Some(posted),
+            report.transaction_times[&TransactionId("transaction-1".to_string())].posted
+        );
+    }
+
+    #[test]
+    fn update_changed_account_info() {
+        let mut connection = connection("depository");
+        let account_id = AccountId::new("account-1".to_string());
+        let report = report(0, 0);
+        let status = |mask: Option<&str>| {
+            hash_map![
+                account_id.clone() => plaid_api::AccountStatus {
+                    info: PlaidAccountInfo {
+                        name: "Renamed at the bank".to_string(),
+                        official_name: None,
+                        mask: mask.map(str::to_string),
+                        type_: "depository".to_string(),
+                        subtype: None,
+                    },
+                    balance: None,
+                },
+            ]
+        };
+
+        // Only the name differs
+        assert_eq!(
+            Vec::<AccountChange>::new(),
+            update_account_infos(&mut connection, &report.account_results, &status(None))
+        );
+
+        let changes = update_account_infos(
+            &mut connection,
+            &report.account_results,
+            &status(Some("5678")),
+        );
+        assert_eq!(1, changes.len());
+        assert_eq!(None, changes[0].old.mask);
+        assert_eq!(Some("5678".to_string()), changes[0].new.mask);
+        let stored = &connection.account(&account_id).unwrap().plaid_account_info;
+        assert_eq!(Some("5678".to_string()), stored.mask);
+        assert_eq!("Account 1", stored.name);
+    }
+}
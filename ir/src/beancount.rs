@@ -0,0 +1,317 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+use beancount_core::{Directive, Flag, IncompleteAmount, PriceSpec};
+use common_macros::hash_set;
+use rust_decimal::Decimal;
+
+use crate::{MetaValue, Posting, Transaction};
+
+/// The Beancount account that postings to an account of the IR are exported to
+pub struct BeancountAccount<'a> {
+    pub account: beancount_core::Account<'a>,
+    /// `None` if the import source doesn't know the currency, the amounts are then exported without one
+    pub currency: Option<&'a str>,
+}
+
+/// Convert a transaction of the IR to a Beancount transaction. `lookup_account` maps the account names of the postings.
+/// Postings in a currency other than `ledger_currency` get their amount in the ledger currency as total price.
+/// Transactions that aren't balanced are flagged. Payee, narration and text metadata are escaped, see [escape_string].
+pub fn transaction_to_beancount<'a>(
+    transaction: Transaction,
+    ledger_currency: Option<&'a str>,
+    mut lookup_account: impl FnMut(&str) -> Result<BeancountAccount<'a>>,
+) -> Result<Directive<'a>> {
+    let flag = if transaction.is_balanced() {
+        Flag::Okay
+    } else {
+        Flag::Warning
+    };
+    Ok(Directive::Transaction(beancount_core::Transaction {
+        date: transaction.date.into(),
+        flag,
+        // The renderer puts them in quotes, but doesn't escape them
+        payee: transaction
+            .payee
+            .map(|payee| Cow::Owned(escape_string(&payee))),
+        tags: transaction.tags.into_iter().map(Cow::Owned).collect(),
+        links: hash_set![],
+        narration: Cow::Owned(escape_string(&transaction.description)),
+        postings: transaction
+            .postings
+            .into_iter()
+            .map(|posting| {
+                let account = lookup_account(&posting.account_name)?;
+                Ok(posting_to_beancount(posting, account, ledger_currency))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        meta: metadata_to_beancount(transaction.metadata),
+        source: None,
+    }))
+}
+
+/// ISO 4217 currencies whose minor unit isn't a hundredth, with their number of decimal places
+const MINOR_UNITS: [(&str, u32); 24] = [
+    ("BHD", 3),
+    ("BIF", 0),
+    ("CLP", 0),
+    ("DJF", 0),
+    ("GNF", 0),
+    ("IQD", 3),
+    ("ISK", 0),
+    ("JOD", 3),
+    ("JPY", 0),
+    ("KMF", 0),
+    ("KRW", 0),
+    ("KWD", 3),
+    ("LYD", 3),
+    ("OMR", 3),
+    ("PYG", 0),
+    ("RWF", 0),
+    ("TND", 3),
+    ("UGX", 0),
+    ("UYI", 0),
+    ("VND", 0),
+    ("VUV", 0),
+    ("XAF", 0),
+    ("XOF", 0),
+    ("XPF", 0),
+];
+
+/// Number of decimal places of the currency's minor unit, e.g. 0 for JPY and 3 for BHD. Other currencies and
+/// commodities have 2.
+pub fn decimal_places(currency: &str) -> u32 {
+    MINOR_UNITS
+        .iter()
+        .find(|(code, _)| *code == currency)
+        .map_or(2, |(_, places)| *places)
+}
+
+/// Write the amount with the decimal places of its currency, e.g. `1500` JPY instead of `1500.00` and `12.50` USD
+/// instead of `12.5`. Digits beyond the minor unit are kept, so the amount never changes. Amounts without a currency
+/// are written as they are.
+pub fn format_amount(amount: Decimal, currency: Option<&str>) -> Decimal {
+    let Some(currency) = currency else {
+        return amount;
+    };
+    let mut amount = amount.normalize();
+    let places = decimal_places(currency);
+    if amount.scale() < places {
+        amount.rescale(places);
+    }
+    amount
+}
+
+fn posting_to_beancount<'a>(
+    posting: Posting,
+    account: BeancountAccount<'a>,
+    ledger_currency: Option<&'a str>,
+) -> beancount_core::Posting<'a> {
+    let price = match (account.currency, ledger_currency) {
+        (Some(account_currency), Some(ledger_currency)) if account_currency != ledger_currency => {
+            Some(PriceSpec::Total(IncompleteAmount {
+                num: Some(format_amount(
+                    posting.amount.in_ledger_currency.abs(),
+                    Some(ledger_currency),
+                )),
+                currency: Some(Cow::Borrowed(ledger_currency)),
+            }))
+        }
+        _ => None,
+    };
+    beancount_core::Posting {
+        account: account.account,
+        units: IncompleteAmount {
+            num: Some(format_amount(
+                posting.amount.in_account_currency,
+                account.currency,
+            )),
+            currency: account.currency.map(Cow::Borrowed),
+        },
+        cost: None,
+        price,
+        flag: None,
+        meta: metadata_to_beancount(posting.metadata),
+    }
+}
+
+fn metadata_to_beancount<'a>(
+    metadata: impl IntoIterator<Item = (String, MetaValue)>,
+) -> beancount_core::metadata::Meta<'a> {
+    metadata
+        .into_iter()
+        .map(|(key, value)| (Cow::Owned(key), meta_value_to_beancount(value)))
+        .collect()
+}
+
+/// Escape backslashes and double quotes, so the value can be written between double quotes as a Beancount string
+pub fn escape_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The value as a Beancount string, in double quotes and escaped. For strings that are written without the renderer,
+/// e.g. in `note` directives.
+pub fn quoted_string(value: &str) -> String {
+    format!("\"{}\"", escape_string(value))
+}
+
+fn meta_value_to_beancount(value: MetaValue) -> beancount_core::metadata::MetaValue<'static> {
+    match value {
+        // The renderer writes text metadata as it is, so it has to be quoted here
+        MetaValue::Text(value) => {
+            beancount_core::metadata::MetaValue::Text(Cow::Owned(quoted_string(&value)))
+        }
+        MetaValue::Date(date) => beancount_core::metadata::MetaValue::Date(date.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use common_macros::hash_map;
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::Amount;
+
+    fn account(name: &str) -> beancount_core::Account<'static> {
+        beancount_core::Account {
+            ty: beancount_core::AccountType::Assets,
+            parts: vec![Cow::Owned(name.to_string())],
+        }
+    }
+
+    fn transaction(postings: Vec<Posting>) -> Transaction {
+        Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            description: "Groceries".to_string(),
+            payee: Some("Store".to_string()),
+            metadata: hash_map![],
+            tags: vec![],
+            postings,
+        }
+    }
+
+    fn posting(account_name: &str, amount: Amount) -> Posting {
+        Posting {
+            account_name: account_name.to_string(),
+            amount,
+            metadata: hash_map![],
+        }
+    }
+
+    #[test]
+    fn flag_unbalanced_transactions() {
+        let balanced = transaction(vec![
+            posting("Checking", Amount::single_currency(Decimal::new(-500, 2))),
+            posting("Savings", Amount::single_currency(Decimal::new(500, 2))),
+        ]);
+        let unbalanced = transaction(vec![posting(
+            "Checking",
+            Amount::single_currency(Decimal::new(-500, 2)),
+        )]);
+        let lookup = |name: &str| {
+            Ok(BeancountAccount {
+                account: account(name),
+                currency: Some("USD"),
+            })
+        };
+        let Directive::Transaction(balanced) =
+            transaction_to_beancount(balanced, Some("USD"), lookup).unwrap()
+        else {
+            panic!("Expected a transaction");
+        };
+        assert_eq!(Flag::Okay, balanced.flag);
+        assert_eq!(Some(Cow::Borrowed("Store")), balanced.payee);
+        let Directive::Transaction(unbalanced) =
+            transaction_to_beancount(unbalanced, Some("USD"), lookup).unwrap()
+        else {
+            panic!("Expected a transaction");
+        };
+        assert_eq!(Flag::Warning, unbalanced.flag);
+    }
+
+    #[test]
+    fn price_postings_in_other_currencies() {
+        let transaction = transaction(vec![posting(
+            "Euro Checking",
+            Amount {
+                in_account_currency: Decimal::new(-1000, 2),
+                in_ledger_currency: Decimal::new(-1100, 2),
+            },
+        )]);
+        let Directive::Transaction(transaction) =
+            transaction_to_beancount(transaction, Some("USD"), |name| {
+                Ok(BeancountAccount {
+                    account: account(name),
+                    currency: Some("EUR"),
+                })
+            })
+            .unwrap()
+        else {
+            panic!("Expected a transaction");
+        };
+        let posting = &transaction.postings[0];
+        assert_eq!(Some(Decimal::new(-1000, 2)), posting.units.num);
+        assert_eq!(Some(Cow::Borrowed("EUR")), posting.units.currency);
+        assert_eq!(
+            Some(PriceSpec::Total(IncompleteAmount {
+                num: Some(Decimal::new(1100, 2)),
+                currency: Some(Cow::Borrowed("USD")),
+            })),
+            posting.price
+        );
+    }
+
+    #[test]
+    fn format_amounts_per_currency() {
+        for (amount, currency, expected) in [
+            (Decimal::new(150000, 2), Some("JPY"), "1500"),
+            (Decimal::new(15005, 1), Some("JPY"), "1500.5"),
+            (Decimal::new(1234, 1), Some("BHD"), "123.400"),
+            (Decimal::new(-125, 1), Some("USD"), "-12.50"),
+            (Decimal::new(123456, 4), Some("USD"), "12.3456"),
+            (Decimal::new(-125, 1), None, "-12.5"),
+            (Decimal::new(0, 4), Some("EUR"), "0.00"),
+        ] {
+            assert_eq!(expected, format_amount(amount, currency).to_string());
+        }
+    }
+
+    #[test]
+    fn escape_text_metadata() {
+        assert_eq!(
+            beancount_core::metadata::MetaValue::Text(Cow::Borrowed(r#""say \"hi\" \\ bye""#)),
+            meta_value_to_beancount(MetaValue::from(r#"say "hi" \ bye"#))
+        );
+    }
+
+    #[test]
+    fn escape_payee_and_narration() {
+        let mut transaction = transaction(vec![]);
+        transaction.payee = Some(r#"Joe's "Diner""#.to_string());
+        transaction.description = r#"Refund \ "fees""#.to_string();
+        transaction.metadata = hash_map![
+            "plaid_transaction_id".to_string() => MetaValue::from(r#"id"with\quotes"#),
+        ];
+        let Directive::Transaction(transaction) =
+            transaction_to_beancount(transaction, Some("USD"), |name| {
+                Ok(BeancountAccount {
+                    account: account(name),
+                    currency: Some("USD"),
+                })
+            })
+            .unwrap()
+        else {
+            panic!("Expected a transaction");
+        };
+        assert_eq!(Some(Cow::Borrowed(r#"Joe's \"Diner\""#)), transaction.payee);
+        assert_eq!(r#"Refund \\ \"fees\""#, transaction.narration);
+        assert_eq!(
+            Some(&beancount_core::metadata::MetaValue::Text(Cow::Borrowed(
+                r#""id\"with\\quotes""#
+            ))),
+            transaction.meta.get("plaid_transaction_id")
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Intermediate representation of a ledger that importers convert their input to. It is independent of the
+//! import source, so the operations on it and the conversion to Beancount in [beancount] are shared.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    iter::Sum,
+    ops::{Add, AddAssign, Neg, Sub},
+};
+
+use chrono::NaiveDate;
+use rust_decimal::{prelude::Zero as _, Decimal};
+use serde::{Serialize, Serializer};
+
+pub mod beancount;
+#[cfg(feature = "script")]
+pub mod script;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ledger {
+    /// Human readable name of the program or file format the ledger was imported from, e.g. "Wave"
+    pub source: String,
+    pub ledger_name: String,
+    pub ledger_currency: String,
+    pub dates: Dates,
+    #[serde(serialize_with = "serialize_sorted")]
+    pub accounts: HashMap<String, AccountInfo>,
+    pub transactions: Vec<Transaction>,
+}
+
+impl Ledger {
+    pub fn account_names(&self) -> HashSet<&str> {
+        self.transactions
+            .iter()
+            .flat_map(|transaction| {
+                transaction
+                    .postings
+                    .iter()
+                    .map(|posting| posting.account_name.as_str())
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct Amount {
+    pub in_account_currency: Decimal,
+    pub in_ledger_currency: Decimal,
+}
+
+impl Amount {
+    pub fn zero() -> Amount {
+        Amount {
+            in_account_currency: Decimal::zero(),
+            in_ledger_currency: Decimal::zero(),
+        }
+    }
+
+    /// Amount in an account that is held in the ledger currency
+    pub fn single_currency(amount: Decimal) -> Amount {
+        Amount {
+            in_account_currency: amount,
+            in_ledger_currency: amount,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.in_account_currency.is_zero() && self.in_ledger_currency.is_zero()
+    }
+}
+
+impl Add<Amount> for Amount {
+    type Output = Amount;
+
+    fn add(self, other: Amount) -> Amount {
+        Amount {
+            in_account_currency: self.in_account_currency + other.in_account_currency,
+            in_ledger_currency: self.in_ledger_currency + other.in_ledger_currency,
+        }
+    }
+}
+
+impl AddAssign<Amount> for Amount {
+    fn add_assign(&mut self, other: Amount) {
+        self.in_account_currency += other.in_account_currency;
+        self.in_ledger_currency += other.in_ledger_currency;
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::zero(), |acc, amount| acc + amount)
+    }
+}
+
+impl Sub<Amount> for Amount {
+    type Output = Amount;
+
+    fn sub(self, other: Amount) -> Amount {
+        Amount {
+            in_account_currency: self.in_account_currency - other.in_account_currency,
+            in_ledger_currency: self.in_ledger_currency - other.in_ledger_currency,
+        }
+    }
+}
+
+impl Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Amount {
+        Amount {
+            in_account_currency: -self.in_account_currency,
+            in_ledger_currency: -self.in_ledger_currency,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountInfo {
+    /// `None` if the import source doesn't report balances for this account
+    pub start_balance: Option<Amount>,
+    pub end_balance: Option<Amount>,
+    pub account_currency: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Dates {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transaction {
+    pub date: NaiveDate,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payee: Option<String>,
+    /// Additional information about the whole transaction, exported as beancount transaction metadata
+    #[serde(
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "serialize_sorted"
+    )]
+    pub metadata: HashMap<String, MetaValue>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub postings: Vec<Posting>,
+}
+
+impl Transaction {
+    pub fn is_balanced(&self) -> bool {
+        self.postings
+            .iter()
+            .map(|posting| posting.amount)
+            .sum::<Amount>()
+            .is_zero()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Posting {
+    pub account_name: String,
+    pub amount: Amount,
+    /// Additional information from the import source, exported as beancount posting metadata
+    #[serde(serialize_with = "serialize_sorted")]
+    pub metadata: HashMap<String, MetaValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum MetaValue {
+    Text(String),
+    Date(NaiveDate),
+}
+
+impl From<String> for MetaValue {
+    fn from(value: String) -> MetaValue {
+        MetaValue::Text(value)
+    }
+}
+
+impl From<&str> for MetaValue {
+    fn from(value: &str) -> MetaValue {
+        MetaValue::Text(value.to_string())
+    }
+}
+
+/// Serialize maps with sorted keys so that dumps of the same ledger are identical and can be diffed
+fn serialize_sorted<V: Serialize, S: Serializer>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>())
+}
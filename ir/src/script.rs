@@ -0,0 +1,246 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+
+use crate::{Amount, MetaValue, Posting, Transaction};
+
+/// A [Rhai](https://rhai.rs) script that is run for each transaction before it's exported, for categorization
+/// logic that is too complex for rules. The transaction is available as the map `tx` with these fields:
+///
+/// * `date`: the date as `YYYY-MM-DD`
+/// * `narration`, `payee` (`()` if unknown) and `tags`: can be changed by the script
+/// * `metadata`: everything else the import source knows about the transaction, e.g. `plaid_category`
+/// * `postings`: maps with `account`, `amount` and `metadata`
+/// * `counter_account`: set it to book the unbalanced rest of the transaction to this account
+/// * `skip`: set it to `true` to leave the transaction out of the export
+///
+/// Changes to the other fields are ignored.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read script {}", path.display()))?;
+        Self::compile(&source).with_context(|| format!("Failed to compile {}", path.display()))
+    }
+
+    pub fn compile(source: &str) -> Result<Self> {
+        let engine = Engine::new();
+        let ast = engine.compile(source).map_err(|err| anyhow!("{err}"))?;
+        Ok(Self { engine, ast })
+    }
+
+    /// Run the script for the transaction. Returns `None` if the script skipped it.
+    pub fn apply(&self, mut transaction: Transaction) -> Result<Option<Transaction>> {
+        let mut scope = Scope::new();
+        scope.push("tx", transaction_to_map(&transaction));
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|err| {
+                anyhow!(
+                    "Script failed for transaction on {} \"{}\": {err}",
+                    transaction.date,
+                    transaction.description
+                )
+            })?;
+        let tx: Map = scope
+            .get_value("tx")
+            .ok_or_else(|| anyhow!("Script replaced tx with something that isn't a map"))?;
+
+        if read_field(&tx, "skip", "a bool", |value| value.as_bool())?.unwrap_or(false) {
+            return Ok(None);
+        }
+        if let Some(narration) = read_field(&tx, "narration", "a string", Dynamic::into_string)? {
+            transaction.description = narration;
+        }
+        transaction.payee = read_field(&tx, "payee", "a string", Dynamic::into_string)?;
+        if let Some(tags) = read_field(&tx, "tags", "an array", Dynamic::into_array)? {
+            transaction.tags = tags
+                .into_iter()
+                .map(|tag| {
+                    tag.into_string().map_err(|actual| {
+                        anyhow!("tx.tags must only contain strings, but has {actual}")
+                    })
+                })
+                .collect::<Result<_>>()?;
+        }
+        if let Some(counter_account) =
+            read_field(&tx, "counter_account", "a string", Dynamic::into_string)?
+        {
+            let rest: Amount = transaction
+                .postings
+                .iter()
+                .map(|posting| posting.amount)
+                .sum();
+            if !rest.is_zero() {
+                transaction.postings.push(Posting {
+                    account_name: counter_account,
+                    amount: -rest,
+                    metadata: Default::default(),
+                });
+            }
+        }
+        Ok(Some(transaction))
+    }
+}
+
+fn transaction_to_map(transaction: &Transaction) -> Map {
+    let mut tx = Map::new();
+    tx.insert("date".into(), transaction.date.to_string().into());
+    tx.insert("narration".into(), transaction.description.clone().into());
+    tx.insert(
+        "payee".into(),
+        transaction
+            .payee
+            .clone()
+            .map_or(Dynamic::UNIT, Dynamic::from),
+    );
+    tx.insert(
+        "tags".into(),
+        transaction
+            .tags
+            .iter()
+            .cloned()
+            .map(Dynamic::from)
+            .collect::<Array>()
+            .into(),
+    );
+    tx.insert(
+        "metadata".into(),
+        metadata_to_map(&transaction.metadata).into(),
+    );
+    tx.insert(
+        "postings".into(),
+        transaction
+            .postings
+            .iter()
+            .map(|posting| {
+                let mut map = Map::new();
+                map.insert("account".into(), posting.account_name.clone().into());
+                map.insert(
+                    "amount".into(),
+                    Dynamic::from_decimal(posting.amount.in_account_currency),
+                );
+                map.insert("metadata".into(), metadata_to_map(&posting.metadata).into());
+                Dynamic::from_map(map)
+            })
+            .collect::<Array>()
+            .into(),
+    );
+    tx.insert("counter_account".into(), Dynamic::UNIT);
+    tx.insert("skip".into(), false.into());
+    tx
+}
+
+fn metadata_to_map<'a>(metadata: impl IntoIterator<Item = (&'a String, &'a MetaValue)>) -> Map {
+    metadata
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                MetaValue::Text(text) => text.clone(),
+                MetaValue::Date(date) => date.to_string(),
+            };
+            (key.into(), value.into())
+        })
+        .collect()
+}
+
+/// Read a field of `tx`, `None` if the script set it to `()` or removed it
+fn read_field<T>(
+    tx: &Map,
+    field: &str,
+    expected: &str,
+    cast: impl FnOnce(Dynamic) -> Result<T, &'static str>,
+) -> Result<Option<T>> {
+    match tx.get(field) {
+        None => Ok(None),
+        Some(value) if value.is_unit() => Ok(None),
+        Some(value) => match cast(value.clone()) {
+            Ok(value) => Ok(Some(value)),
+            Err(actual) => bail!("tx.{field} must be {expected}, but is {actual}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use common_macros::hash_map;
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    fn transaction() -> Transaction {
+        Transaction {
+            date: NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            description: "AMAZON MKTPL 123".to_string(),
+            payee: None,
+            metadata: hash_map![],
+            tags: vec![],
+            postings: vec![Posting {
+                account_name: "Liabilities:CreditCard".to_string(),
+                amount: Amount::single_currency(Decimal::new(-2500, 2)),
+                metadata: hash_map![
+                    "plaid_category".to_string() => MetaValue::from("GENERAL_MERCHANDISE.ONLINE_MARKETPLACES"),
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn change_transaction() {
+        let script = Script::compile(
+            r#"
+            if tx.narration.starts_with("AMAZON") && tx.postings[0].amount < 0 {
+                tx.payee = "Amazon";
+                tx.narration = "Online order";
+                tx.tags.push("online");
+                tx.counter_account = "Expenses:Shopping";
+            }
+            "#,
+        )
+        .unwrap();
+        let transaction = script.apply(transaction()).unwrap().unwrap();
+        assert_eq!(Some("Amazon"), transaction.payee.as_deref());
+        assert_eq!("Online order", transaction.description);
+        assert_eq!(vec!["online".to_string()], transaction.tags);
+        assert!(transaction.is_balanced());
+        assert_eq!("Expenses:Shopping", transaction.postings[1].account_name);
+        assert_eq!(
+            Amount::single_currency(Decimal::new(2500, 2)),
+            transaction.postings[1].amount
+        );
+    }
+
+    #[test]
+    fn skip_transaction() {
+        let script = Script::compile(
+            r#"
+            let category = tx.postings[0].metadata.plaid_category;
+            tx.skip = category.starts_with("GENERAL_MERCHANDISE");
+            "#,
+        )
+        .unwrap();
+        assert!(script.apply(transaction()).unwrap().is_none());
+    }
+
+    #[test]
+    fn keep_unchanged_transaction() {
+        let script = Script::compile("").unwrap();
+        let transaction = script.apply(transaction()).unwrap().unwrap();
+        assert_eq!(None, transaction.payee);
+        assert_eq!("AMAZON MKTPL 123", transaction.description);
+        assert_eq!(1, transaction.postings.len());
+    }
+
+    #[test]
+    fn reject_field_of_wrong_type() {
+        let script = Script::compile("tx.payee = 42;").unwrap();
+        let err = script.apply(transaction()).unwrap_err();
+        assert_eq!("tx.payee must be a string, but is i64", err.to_string());
+    }
+}
@@ -0,0 +1,64 @@
+"""Source for the beancount-import web UI (https://github.com/jbms/beancount-import) that reads the candidates written by
+`beancount-import-plaid export-all --format beancount-import > plaid_candidates.json`.
+
+Add it to the sources of your beancount-import config:
+
+    data_sources=[
+        dict(module="beancount_import_source", filename="plaid_candidates.json"),
+    ]
+
+Candidates whose key is already in the journal, i.e. a posting has the `plaid_transaction_id` metadata with that value,
+are cleared. All others are shown as pending, with the unknown counter account left as `Expenses:FIXME`.
+"""
+
+import json
+
+from beancount.parser import parser
+from beancount_import.source import ImportResult, SourceResults
+from beancount_import.source.description_based_source import DescriptionBasedSource
+from beancount_import.journal_editor import JournalEditor
+
+
+class PlaidSource(DescriptionBasedSource):
+    def __init__(self, filename: str, **kwargs) -> None:
+        super().__init__(**kwargs)
+        self.filename = filename
+        with open(filename) as f:
+            self.candidates = json.load(f)
+        self.cleared_metadata_key = self.candidates["cleared_metadata_key"]
+
+    @property
+    def name(self) -> str:
+        return "plaid"
+
+    def prepare(self, journal: JournalEditor, results: SourceResults) -> None:
+        # Postings with keys that aren't candidates are fine, e.g. their transactions were pruned from the database
+        cleared_keys = {
+            posting.meta[self.cleared_metadata_key]
+            for entry in journal.all_entries
+            for posting in getattr(entry, "postings", [])
+            if self.is_posting_cleared(posting)
+        }
+
+        for account in self.candidates["accounts"]:
+            results.add_account(account)
+        for line, candidate in enumerate(self.candidates["candidates"]):
+            if candidate["key"] in cleared_keys:
+                continue
+            entries, errors, _ = parser.parse_string(candidate["entry"])
+            if errors:
+                raise ValueError(f"Failed to parse candidate {candidate['key']}: {errors}")
+            results.add_pending_entry(
+                ImportResult(
+                    date=entries[0].date,
+                    entries=entries,
+                    info=dict(type="application/json", filename=self.filename, line=line + 1),
+                )
+            )
+
+    def is_posting_cleared(self, posting) -> bool:
+        return posting.meta is not None and self.cleared_metadata_key in posting.meta
+
+
+def load(spec, log_status):
+    return PlaidSource(log_status=log_status, **spec)
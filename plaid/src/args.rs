@@ -1,50 +1,1039 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use chrono::NaiveDate;
+use clap::builder::BoolishValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory as _, FromArgMatches as _, Parser, Subcommand};
+use rust_decimal::Decimal;
+
+use crate::db::{
+    AmountSign, RenameDirectives, StorageBackend, DEFAULT_COMPRESSION_LEVEL, DEFAULT_NUM_BACKUPS,
+};
+use crate::report::{ReportGroupBy, ReportPeriod};
+use crate::sync::Timezone;
+use crate::terminal::ColorMode;
+use crate::validate::Validator;
 
 /// Download transactions from Plaid and export them to Beancount.
+///
+/// Exit codes: 0 on success, 1 on errors, 2 if a bank connection has to be linked again,
+/// 3 if `export-new` found nothing to export and 4 if another process has the database locked.
+///
+/// Global options can also be set with BEANCOUNT_PLAID_* environment variables, e.g. BEANCOUNT_PLAID_DB_PATH.
+/// Options given on the command line take precedence. `config show` prints the effective values.
 #[derive(Parser, Debug)]
 pub struct Args {
     #[clap(subcommand)]
     pub command: Command,
 
-    /// Path to the database file
-    #[clap(long)]
-    pub db_path: PathBuf,
+    /// Only print results, prompts and warnings. Headers, progress spinners and totals are suppressed.
+    #[clap(long, short, global = true, env = "BEANCOUNT_PLAID_QUIET", value_parser = BoolishValueParser::new())]
+    pub quiet: bool,
+
+    /// When to style the output with colors. `auto` uses colors on terminals unless NO_COLOR is set.
+    #[clap(long, value_enum, global = true, default_value_t = ColorMode::Auto, env = "BEANCOUNT_PLAID_COLOR")]
+    pub color: ColorMode,
+
+    /// Same as `--color never`
+    #[clap(long, global = true)]
+    pub no_color: bool,
+
+    /// Append log messages to this file instead of printing them to stderr.
+    /// Set the level with the RUST_LOG environment variable, e.g. `RUST_LOG=debug`. It defaults to `info`.
+    #[clap(long, global = true, env = "BEANCOUNT_PLAID_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Write log messages as JSON, one object per line
+    #[clap(long, global = true, env = "BEANCOUNT_PLAID_LOG_JSON", value_parser = BoolishValueParser::new())]
+    pub log_json: bool,
+
+    /// Answer confirmation prompts with yes and choose the default everywhere else, for unattended runs.
+    /// Operations that would lose transactions that weren't exported yet still need `--force`.
+    #[clap(long, short, global = true, env = "BEANCOUNT_PLAID_YES", value_parser = BoolishValueParser::new())]
+    pub yes: bool,
+
+    /// With `--yes`, also confirm operations that lose transactions that weren't exported yet
+    #[clap(long, global = true, requires = "yes", env = "BEANCOUNT_PLAID_FORCE", value_parser = BoolishValueParser::new())]
+    pub force: bool,
+
+    /// Path to the database file. Defaults to `beancount-plaid/db` in the platform's data directory,
+    /// e.g. `~/.local/share/beancount-plaid/db` on Linux.
+    #[clap(long, env = "BEANCOUNT_PLAID_DB_PATH")]
+    pub db_path: Option<PathBuf>,
+
+    /// Number of previous versions of the database file to keep as `.bak.N` files next to it
+    #[clap(long, default_value_t = DEFAULT_NUM_BACKUPS, env = "BEANCOUNT_PLAID_NUM_BACKUPS")]
+    pub num_backups: usize,
+
+    /// zstd level (1 to 22) to compress the database file with. Lower levels save faster but produce larger files.
+    /// Databases stored with `--storage sqlite` aren't compressed.
+    #[clap(long, default_value_t = DEFAULT_COMPRESSION_LEVEL, env = "BEANCOUNT_PLAID_COMPRESSION_LEVEL")]
+    pub compression_level: i32,
+
+    /// Read the encryption key from this file instead of the BEANCOUNT_PLAID_KEY environment variable or a prompt.
+    /// The file must only be accessible by its owner (chmod 600). `init` creates it if it doesn't exist yet.
+    #[clap(long, env = "BEANCOUNT_PLAID_KEY_FILE")]
+    pub key_file: Option<PathBuf>,
+
+    /// Protect the key file with the age identities in this file, e.g. one created by `age-plugin-yubikey`.
+    /// The key file then holds the encryption key encrypted to these identities instead of the plain key.
+    #[clap(long, requires = "key_file", env = "BEANCOUNT_PLAID_AGE_IDENTITY")]
+    pub age_identity: Option<PathBuf>,
+
+    /// Beancount ledger to offer the open accounts of when asking for a Beancount account, e.g. in `connection add`.
+    /// Files it includes are read as well.
+    #[clap(long, global = true, env = "BEANCOUNT_PLAID_LEDGER")]
+    pub ledger: Option<PathBuf>,
+
+    /// Check the output file with this tool after `export-new` appended to it, before the transactions are marked as
+    /// exported. With `--ledger`, the ledger is checked instead and should include the output file.
+    /// A rejected export is kept as the output file with a `.rejected` suffix and the output file is left unchanged.
+    #[clap(long, value_enum, global = true, env = "BEANCOUNT_PLAID_VALIDATE_WITH")]
+    pub validate_with: Option<Validator>,
+
+    /// Timezone that `sync` dates transactions in, for banks that report the time of transactions and not just their
+    /// date. `local` is the timezone of the system, otherwise an offset from UTC like `+02:00` or `utc`.
+    #[clap(long, global = true, default_value_t = Timezone::Local, env = "BEANCOUNT_PLAID_TIMEZONE")]
+    pub timezone: Timezone,
+
+    /// CSV file with Plaid categories to use in addition to the ones built in, with the columns
+    /// `PRIMARY,DETAILED,DESCRIPTION`. For categories Plaid added after this version was released, `sync` and
+    /// `categories` list the ones it doesn't know. A category that is built in gets the description from the file.
+    #[clap(long, global = true, env = "BEANCOUNT_PLAID_CATEGORIES")]
+    pub categories: Option<PathBuf>,
+
+    /// What a command that fails midway keeps, e.g. `sync` when one connection fails after others were synced.
+    /// `rollback` saves none of its changes, `keep-completed` saves the steps that completed, like the connections
+    /// that were synced. The other commands that change the database, and the actions of `tui` other than syncing,
+    /// are always rolled back.
+    #[clap(long, value_enum, global = true, default_value_t = OnError::Rollback, env = "BEANCOUNT_PLAID_ON_ERROR")]
+    pub on_error: OnError,
+
+    /// How a new database is stored when running `init`. Existing databases keep the backend they were created with.
+    #[clap(long, value_enum, default_value_t = StorageBackend::File, env = "BEANCOUNT_PLAID_STORAGE")]
+    pub storage: StorageBackend,
+
+    /// Effective values of the options above, filled in by [parse]
+    #[clap(skip)]
+    pub resolved_options: Vec<ResolvedOption>,
+}
+
+/// See `--on-error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnError {
+    /// Keep none of the changes of the failed command
+    Rollback,
+    /// Keep the changes of the steps that completed before or besides the failure
+    KeepCompleted,
+}
+
+/// Effective value of a global option and where it came from, for `config show`
+#[derive(Debug, Clone)]
+pub struct ResolvedOption {
+    /// e.g. `--db-path`
+    pub name: String,
+    /// e.g. `BEANCOUNT_PLAID_DB_PATH`
+    pub env: Option<String>,
+    /// `None` if the option isn't set and has no default
+    pub value: Option<String>,
+    pub source: Option<ValueSource>,
 }
 
 #[derive(Debug, Subcommand)]
 pub enum Command {
     /// Create a new database file in the local directory
-    Init,
+    Init {
+        /// Store the database in plaintext, e.g. because it already lives on an encrypted disk.
+        /// Anyone with access to the file can read the Plaid credentials and transactions.
+        #[clap(long)]
+        no_encryption: bool,
+    },
 
-    /// Add a bank connection to the database
-    AddConnection,
+    /// Add, list and remove bank connections
+    Connection {
+        #[clap(subcommand)]
+        command: ConnectionCommand,
+    },
 
-    /// List all bank connections in the database
-    ListConnections,
+    /// List the accounts of the bank connections and choose which ones are exported
+    Account {
+        #[clap(subcommand)]
+        command: AccountCommand,
+    },
 
-    /// Remove a bank connection from the database
-    RemoveConnection {
-        #[clap(short, long)]
-        connection_name: String,
+    /// Look at and correct the stored transactions
+    Transaction {
+        #[clap(subcommand)]
+        command: TransactionCommand,
     },
 
     /// Download transactions from plaid and put them in the local database
-    Sync,
+    Sync {
+        /// Only sync this connection, e.g. one that failed or was just linked again
+        #[clap(long)]
+        connection: Option<String>,
 
-    /// Print the list of transactions in the database
-    ListTransactions,
+        /// Only store the transactions of this account of the connection, given by its name as shown by
+        /// `connection list` or its Plaid account id
+        #[clap(long, requires = "connection")]
+        account: Option<String>,
+    },
+
+    /// Browse, categorize, sync and export transactions in an interactive dashboard.
+    /// With `--ledger`, new transactions show the counter account `suggest` would pick.
+    Tui,
+
+    /// Suggest counter accounts for new transactions, learned from the accounts that transactions with the same words
+    /// in their payee or narration were booked to in the ledger given with `--ledger`
+    Suggest {
+        /// Only suggest accounts with at least this confidence, between 0 and 1
+        #[clap(long, default_value_t = 0.5)]
+        min_confidence: f64,
+
+        /// Assign the suggested accounts to the transactions like `transaction recategorize`, so they're exported
+        /// with them
+        #[clap(long)]
+        apply: bool,
+    },
 
     /// Export all transactions from the database to a Beancount file
-    ExportAll,
+    ExportAll {
+        /// Only export the transactions of this ledger target, see `target add`.
+        /// Without it, only transactions of connections and accounts that aren't assigned to a target are exported.
+        #[clap(long)]
+        target: Option<String>,
+
+        #[clap(long, value_enum, default_value_t = ExportFormat::Beancount)]
+        format: ExportFormat,
+
+        /// Run this Rhai script for each transaction before it's exported. The script gets the transaction as the
+        /// map `tx` and can change `tx.payee`, `tx.narration`, `tx.tags` and `tx.counter_account`, or set `tx.skip`
+        /// to leave it out. The Plaid fields are in `tx.postings[0].metadata`.
+        #[clap(long)]
+        script: Option<PathBuf>,
+    },
 
     /// Export new transactions from the database to a Beancount file,
     /// and mark those transactions as exported so future calls to this
     /// command will not include them.
-    ExportNew,
+    ExportNew {
+        /// Only export the transactions of this ledger target, see `target add`. They're appended to the target's
+        /// output file if it has one. Without it, only transactions of connections and accounts that aren't
+        /// assigned to a target are exported.
+        #[clap(long)]
+        target: Option<String>,
+
+        /// Append the exported transactions to this file instead of printing them.
+        /// Takes precedence over the output file of the target.
+        #[clap(long, env = "BEANCOUNT_PLAID_OUTPUT_FILE")]
+        output_file: Option<PathBuf>,
+
+        /// Run this Rhai script for each transaction before it's exported. The script gets the transaction as the
+        /// map `tx` and can change `tx.payee`, `tx.narration`, `tx.tags` and `tx.counter_account`, or set `tx.skip`
+        /// to leave it out. The Plaid fields are in `tx.postings[0].metadata`.
+        #[clap(long)]
+        script: Option<PathBuf>,
+    },
+
+    /// Manage ledger targets, so e.g. personal and business accounts can be exported to separate Beancount files
+    Target {
+        #[clap(subcommand)]
+        command: TargetCommand,
+    },
+
+    /// Sum up the stored transactions per period, e.g. to sanity check them before exporting.
+    /// Ignored transactions are skipped and categories changed with `transaction recategorize` are taken into account.
+    Report {
+        #[clap(long, value_enum, default_value_t = ReportGroupBy::Category)]
+        group_by: ReportGroupBy,
+
+        #[clap(long, value_enum, default_value_t = ReportPeriod::Month)]
+        period: ReportPeriod,
+
+        /// Print the report as CSV instead of a table
+        #[clap(long)]
+        csv: bool,
+    },
+
+    /// List all Plaid categories with the number of stored transactions in each and the Beancount accounts they were
+    /// assigned to with `transaction recategorize`. Categories with transactions that don't have an account are highlighted.
+    Categories,
+
+    /// Mark exported transactions as not exported so the next `export-new` exports them again,
+    /// e.g. because the export file was lost or rejected by bean-check
+    UndoExport {
+        /// Only include transactions dated on or after this date, e.g. 2024-11-01
+        #[clap(long)]
+        since: NaiveDate,
+
+        /// Only include transactions of this account, given by its name as shown by `account list` or its Beancount account
+        #[clap(long)]
+        account: Option<String>,
+    },
+
+    /// Revert the last change to the database, e.g. a `sync` or `export-new` that went wrong.
+    /// The most recent backup replaces the database and the current version is dropped.
+    Undo {
+        /// Only list the changes that can be undone
+        #[clap(long)]
+        list: bool,
+    },
+
+    /// Show the effective values of the global options
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Manage the database file
+    Db {
+        #[clap(subcommand)]
+        command: DbCommand,
+    },
+
+    // The flat commands from before the commands were grouped. They're hidden but still work for existing scripts,
+    // `parse` replaces them with their grouped counterparts.
+    #[clap(hide = true)]
+    AddConnection(AddConnectionArgs),
+    #[clap(hide = true)]
+    ListConnections(ListConnectionsArgs),
+    #[clap(hide = true)]
+    RemoveConnection(RemoveConnectionArgs),
+    #[clap(hide = true)]
+    ListAccounts,
+    #[clap(hide = true)]
+    MapAccount(MapAccountArgs),
+    #[clap(hide = true)]
+    DisconnectAccount(DisconnectAccountArgs),
+    #[clap(hide = true)]
+    ListTransactions(ListTransactionsArgs),
+    #[clap(hide = true)]
+    Search(SearchArgs),
+    #[clap(hide = true)]
+    Ignore(IgnoreArgs),
+    #[clap(hide = true)]
+    Unignore(UnignoreArgs),
+    #[clap(hide = true)]
+    AddTransaction(AddTransactionArgs),
+    #[clap(hide = true)]
+    Annotate(AnnotateArgs),
+    #[clap(hide = true)]
+    Recategorize(RecategorizeArgs),
+    #[clap(hide = true)]
+    RestoreBackup(RestoreBackupArgs),
+}
+
+/// What `export-all` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// A Beancount file
+    Beancount,
+    /// Candidate entries as JSON for the beancount-import web UI, see `plaid/beancount_import_source.py`
+    BeancountImport,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConnectionCommand {
+    /// Add a bank connection to the database
+    Add(AddConnectionArgs),
+
+    /// List all bank connections in the database
+    List(ListConnectionsArgs),
+
+    /// Remove a bank connection from the database. Its transactions are archived.
+    Remove(RemoveConnectionArgs),
+
+    /// Ask Plaid to check the bank for new transactions now instead of at its next scheduled update
+    Refresh(RefreshConnectionArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Show the global options that are set on the command line or with their environment variable
+    Show {
+        /// Also show the options that have their default value or aren't set
+        #[clap(long)]
+        resolved: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AccountCommand {
+    /// List the accounts of all bank connections as a table, with their mapping, last transaction, balance and
+    /// number of transactions not exported yet
+    List,
+
+    /// Assign a Beancount account to an account that wasn't added yet, e.g. one that was left for later in `connection add`.
+    /// Transactions synced while it was pending mapping are exported from then on.
+    Connect(MapAccountArgs),
+
+    /// Stop syncing and exporting an account of a bank connection. Its transactions are archived.
+    Disable(DisconnectAccountArgs),
+
+    /// Stop syncing a connected account, e.g. while it's dormant. It keeps its transactions, and they're still exported.
+    Pause(DisconnectAccountArgs),
+
+    /// Sync an account again that was stopped with `account pause`.
+    /// The next sync downloads all transactions of its connection again, to get the ones that were skipped.
+    Resume(DisconnectAccountArgs),
+
+    /// Export the transactions of a connected account to a different Beancount account from now on.
+    /// Transactions that were already exported keep the old account.
+    Remap(RemapAccountArgs),
+
+    /// Store and export the amounts of an account with the standard or the inverted sign from now on.
+    /// Transactions that were already exported keep their sign in the ledger.
+    Sign(AmountSignArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TransactionCommand {
+    /// Print the list of transactions in the database
+    List(ListTransactionsArgs),
+
+    /// Find transactions whose merchant name, description or website contains the query, ignoring case
+    Search(SearchArgs),
+
+    /// Store corrections for a transaction. They take precedence when exporting it and survive re-syncs.
+    Annotate(AnnotateArgs),
+
+    /// Assign a transaction to an account or Plaid category, e.g. to triage the output of `transaction list --uncategorized`.
+    /// An account like Expenses:Groceries is exported as the balancing posting,
+    /// a category like FOOD_AND_DRINK.FOOD_AND_DRINK_GROCERIES replaces the one from Plaid.
+    Recategorize(RecategorizeArgs),
+
+    /// Never export a transaction, or all transactions matching a rule, e.g. internal sweeps between accounts
+    Ignore(IgnoreArgs),
+
+    /// Export a transaction again that was ignored with `transaction ignore`, or remove an ignore rule
+    Unignore(UnignoreArgs),
+
+    /// Add a transaction that doesn't come from Plaid, e.g. a cash payment.
+    /// It's exported together with the synced transactions.
+    Add(AddTransactionArgs),
+
+    /// List new transactions that look like a reissue of another transaction, e.g. after a connection was linked again.
+    /// They aren't exported until they're kept with `transaction keep` or dropped with `transaction ignore`.
+    Duplicates,
+
+    /// Export a suspected duplicate as a separate transaction
+    Keep(KeepArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AddConnectionArgs {
+    /// Serve the Plaid Link page on the LAN address instead of only on localhost, so it can be opened on a phone
+    /// when running on a machine without a browser. Scan the printed QR code to open it.
+    #[clap(long)]
+    pub lan: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ListConnectionsArgs {
+    /// List the removed connections and disconnected accounts instead
+    #[clap(long)]
+    pub archived: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RemoveConnectionArgs {
+    #[clap(short, long)]
+    pub connection_name: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RefreshConnectionArgs {
+    #[clap(short, long)]
+    pub connection_name: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct MapAccountArgs {
+    #[clap(short, long)]
+    pub connection_name: String,
+
+    /// Name of the account, as shown by `connection list`
+    #[clap(short, long)]
+    pub account_name: String,
+
+    /// Beancount account to export the transactions to, e.g. Assets:Bank:Checking
+    pub beancount_account: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RemapAccountArgs {
+    #[clap(short, long)]
+    pub connection_name: String,
+
+    /// Name of the account, as shown by `connection list`
+    #[clap(short, long)]
+    pub account_name: String,
+
+    /// Beancount account to export the transactions to from now on, e.g. Assets:Bank:Savings
+    pub beancount_account: String,
+
+    /// Directives the next `export-new` writes so the ledger knows about the new account
+    #[clap(long, value_enum, default_value_t = RenameDirectives::None)]
+    pub directives: RenameDirectives,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AmountSignArgs {
+    #[clap(short, long)]
+    pub connection_name: String,
+
+    /// Name of the account, as shown by `connection list`
+    #[clap(short, long)]
+    pub account_name: String,
+
+    #[clap(value_enum)]
+    pub sign: AmountSign,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DisconnectAccountArgs {
+    #[clap(short, long)]
+    pub connection_name: String,
+
+    /// Name of the account, as shown by `connection list`
+    #[clap(short, long)]
+    pub account_name: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ListTransactionsArgs {
+    /// Only list the ignored transactions
+    #[clap(long, conflicts_with = "uncategorized")]
+    pub ignored: bool,
+
+    /// Only list the transactions that weren't exported yet and don't have an account assigned with `transaction recategorize`
+    #[clap(long)]
+    pub uncategorized: bool,
+
+    #[clap(flatten)]
+    pub query: TransactionQuery,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SearchArgs {
+    pub query: String,
+
+    /// Interpret the query as a regular expression
+    #[clap(long)]
+    pub regex: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct IgnoreArgs {
+    /// Id of the transaction, as in the `plaid_transaction_id` metadata of exported transactions
+    #[clap(required_unless_present = "matching")]
+    pub transaction_id: Option<String>,
+
+    /// Ignore all transactions whose description or merchant name contains this text, ignoring case
+    #[clap(long, conflicts_with = "transaction_id")]
+    pub matching: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct UnignoreArgs {
+    /// Id of the transaction, as shown by `transaction list --ignored`
+    #[clap(required_unless_present = "matching")]
+    pub transaction_id: Option<String>,
+
+    /// Remove the rule that was added with `transaction ignore --matching`
+    #[clap(long, conflicts_with = "transaction_id")]
+    pub matching: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct KeepArgs {
+    /// Id of the transaction, as shown by `transaction duplicates`
+    pub transaction_id: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AddTransactionArgs {
+    /// Date of the transaction, e.g. 2024-01-31
+    #[clap(long)]
+    pub date: NaiveDate,
+
+    /// Amount of the transaction, negative for money leaving the account, e.g. -12.50
+    #[clap(long, allow_hyphen_values = true)]
+    pub amount: Decimal,
+
+    #[clap(long, default_value = "USD")]
+    pub currency: String,
+
+    /// Beancount account of the transaction, e.g. Assets:Cash
+    #[clap(long)]
+    pub account: String,
+
+    #[clap(long)]
+    pub narration: String,
+
+    #[clap(long)]
+    pub payee: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AnnotateArgs {
+    /// Id of the transaction, as in the `plaid_transaction_id` metadata of exported transactions
+    pub transaction_id: String,
+
+    /// Note to export as metadata of the transaction
+    #[clap(long)]
+    pub note: Option<String>,
+
+    /// Account for the balancing posting, e.g. Expenses:Groceries
+    #[clap(long)]
+    pub account: Option<String>,
+
+    /// Payee to export instead of the merchant name from Plaid
+    #[clap(long)]
+    pub payee: Option<String>,
+
+    /// Remove all corrections of the transaction
+    #[clap(long, conflicts_with_all = ["note", "account", "payee"])]
+    pub clear: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RecategorizeArgs {
+    /// Id of the transaction, as shown by `transaction list --uncategorized`
+    pub transaction_id: String,
+
+    /// Beancount account or Plaid category in the form PRIMARY.DETAILED
+    pub category_or_account: String,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RestoreBackupArgs {
+    /// Which backup to restore, 1 is the most recent one
+    #[clap(short, long, default_value_t = 1)]
+    pub generation: usize,
+}
+
+impl Command {
+    /// Replace the hidden flat commands with their grouped counterparts
+    fn into_grouped(self) -> Self {
+        match self {
+            Self::AddConnection(args) => Self::Connection {
+                command: ConnectionCommand::Add(args),
+            },
+            Self::ListConnections(args) => Self::Connection {
+                command: ConnectionCommand::List(args),
+            },
+            Self::RemoveConnection(args) => Self::Connection {
+                command: ConnectionCommand::Remove(args),
+            },
+            Self::ListAccounts => Self::Account {
+                command: AccountCommand::List,
+            },
+            Self::MapAccount(args) => Self::Account {
+                command: AccountCommand::Connect(args),
+            },
+            Self::DisconnectAccount(args) => Self::Account {
+                command: AccountCommand::Disable(args),
+            },
+            Self::ListTransactions(args) => Self::Transaction {
+                command: TransactionCommand::List(args),
+            },
+            Self::Search(args) => Self::Transaction {
+                command: TransactionCommand::Search(args),
+            },
+            Self::Ignore(args) => Self::Transaction {
+                command: TransactionCommand::Ignore(args),
+            },
+            Self::Unignore(args) => Self::Transaction {
+                command: TransactionCommand::Unignore(args),
+            },
+            Self::AddTransaction(args) => Self::Transaction {
+                command: TransactionCommand::Add(args),
+            },
+            Self::Annotate(args) => Self::Transaction {
+                command: TransactionCommand::Annotate(args),
+            },
+            Self::Recategorize(args) => Self::Transaction {
+                command: TransactionCommand::Recategorize(args),
+            },
+            Self::RestoreBackup(args) => Self::Db {
+                command: DbCommand::RestoreBackup(args),
+            },
+            command => command,
+        }
+    }
+
+    /// Name of the command as recorded in the undo history. This doesn't include arguments because
+    /// the history is stored unencrypted next to the database and arguments like notes may be sensitive.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Init { .. } => "init",
+            Self::Connection { command } => match command {
+                ConnectionCommand::Add(_) => "connection add",
+                ConnectionCommand::List(_) => "connection list",
+                ConnectionCommand::Remove(_) => "connection remove",
+                ConnectionCommand::Refresh(_) => "connection refresh",
+            },
+            Self::Account { command } => match command {
+                AccountCommand::List => "account list",
+                AccountCommand::Connect(_) => "account connect",
+                AccountCommand::Disable(_) => "account disable",
+                AccountCommand::Pause(_) => "account pause",
+                AccountCommand::Resume(_) => "account resume",
+                AccountCommand::Remap(_) => "account remap",
+                AccountCommand::Sign(_) => "account sign",
+            },
+            Self::Transaction { command } => match command {
+                TransactionCommand::List(_) => "transaction list",
+                TransactionCommand::Search(_) => "transaction search",
+                TransactionCommand::Annotate(_) => "transaction annotate",
+                TransactionCommand::Recategorize(_) => "transaction recategorize",
+                TransactionCommand::Ignore(_) => "transaction ignore",
+                TransactionCommand::Unignore(_) => "transaction unignore",
+                TransactionCommand::Add(_) => "transaction add",
+                TransactionCommand::Duplicates => "transaction duplicates",
+                TransactionCommand::Keep(_) => "transaction keep",
+            },
+            Self::Sync { .. } => "sync",
+            Self::Tui => "tui",
+            Self::Suggest { .. } => "suggest",
+            Self::ExportAll { .. } => "export-all",
+            Self::ExportNew { .. } => "export-new",
+            Self::Target { command } => match command {
+                TargetCommand::Add { .. } => "target add",
+                TargetCommand::List => "target list",
+                TargetCommand::Remove { .. } => "target remove",
+                TargetCommand::Assign { .. } => "target assign",
+                TargetCommand::Unassign { .. } => "target unassign",
+                TargetCommand::Ignore { .. } => "target ignore",
+            },
+            Self::Report { .. } => "report",
+            Self::Categories => "categories",
+            Self::Config { command } => match command {
+                ConfigCommand::Show { .. } => "config show",
+            },
+            Self::UndoExport { .. } => "undo-export",
+            Self::Undo { .. } => "undo",
+            Self::Db { command } => match command {
+                DbCommand::Encrypt => "db encrypt",
+                DbCommand::Prune { .. } => "db prune",
+                DbCommand::Merge { .. } => "db merge",
+                DbCommand::Inspect { .. } => "db inspect",
+                DbCommand::Pack { .. } => "db pack",
+                DbCommand::Unpack { .. } => "db unpack",
+                DbCommand::RestoreBackup(_) => "db restore-backup",
+            },
+            Self::AddConnection(_)
+            | Self::ListConnections(_)
+            | Self::RemoveConnection(_)
+            | Self::ListAccounts
+            | Self::MapAccount(_)
+            | Self::DisconnectAccount(_)
+            | Self::ListTransactions(_)
+            | Self::Search(_)
+            | Self::Ignore(_)
+            | Self::Unignore(_)
+            | Self::AddTransaction(_)
+            | Self::Annotate(_)
+            | Self::Recategorize(_)
+            | Self::RestoreBackup(_) => unreachable!("Replaced by `parse`"),
+        }
+    }
+}
+
+/// Filters for `transaction list`. Transactions must match all given filters.
+#[derive(Debug, Default, clap::Args)]
+pub struct TransactionQuery {
+    /// Only list transactions of this account, given by its name as shown by `account list` or its Beancount account
+    #[clap(long)]
+    pub account: Option<String>,
+
+    /// Only list transactions of this bank connection
+    #[clap(long)]
+    pub connection: Option<String>,
+
+    /// Only list transactions on or after this date, e.g. 2024-01-01
+    #[clap(long)]
+    pub since: Option<NaiveDate>,
+
+    /// Only list transactions on or before this date, e.g. 2024-12-31
+    #[clap(long)]
+    pub until: Option<NaiveDate>,
+
+    /// Only list transactions in this Plaid category, either the primary category like FOOD_AND_DRINK or the
+    /// detailed one like FOOD_AND_DRINK_COFFEE. Categories set with `transaction recategorize` take precedence.
+    #[clap(long)]
+    pub category: Option<String>,
+
+    /// Only list transactions with at least this amount. Money leaving the account is negative.
+    #[clap(long, allow_hyphen_values = true)]
+    pub min_amount: Option<Decimal>,
+
+    /// Only list transactions with at most this amount. Money leaving the account is negative.
+    #[clap(long, allow_hyphen_values = true)]
+    pub max_amount: Option<Decimal>,
+
+    /// Only list transactions that weren't exported yet
+    #[clap(long)]
+    pub new_only: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TargetCommand {
+    /// Add a ledger target. Assign connections or accounts to it with `target assign`.
+    Add {
+        name: String,
+
+        /// Append the transactions exported by `export-new --target` to this file instead of printing them
+        #[clap(long)]
+        output_file: Option<PathBuf>,
+
+        /// Currency for transactions that Plaid doesn't report a currency for, e.g. USD
+        #[clap(long)]
+        operating_currency: Option<String>,
+    },
+
+    /// List the ledger targets with their settings and assignments
+    List,
+
+    /// Remove a ledger target. Its connections and accounts go back to being exported without `--target`.
+    Remove { name: String },
+
+    /// Export the transactions of a connection, or of one of its accounts, to this ledger target
+    Assign {
+        name: String,
+
+        #[clap(long)]
+        connection: String,
+
+        /// Only assign this account of the connection, given by its name as shown by `account list`
+        #[clap(long)]
+        account: Option<String>,
+    },
+
+    /// Export the transactions of a connection, or of one of its accounts, without `--target` again
+    Unassign {
+        #[clap(long)]
+        connection: String,
+
+        /// Only unassign this account of the connection, given by its name as shown by `account list`
+        #[clap(long)]
+        account: Option<String>,
+    },
+
+    /// Don't export transactions to this ledger target if their description or merchant name contains the given text,
+    /// ignoring case. Unlike `transaction ignore`, this only applies to exports with `--target`.
+    Ignore {
+        name: String,
+
+        #[clap(long)]
+        matching: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// Encrypt a database that was created with `init --no-encryption`
+    Encrypt,
+
+    /// Remove old transactions from the database to keep it small.
+    /// Pruned transactions won't be added again by `sync`.
+    Prune {
+        /// Remove transactions dated before this date, e.g. 2022-01-01
+        #[clap(long)]
+        before: NaiveDate,
+
+        /// Only remove transactions that were already exported
+        #[clap(long)]
+        only_exported: bool,
+    },
+
+    /// Import the connections and transactions of another database, e.g. from a second machine.
+    /// The other database isn't modified.
+    Merge {
+        /// Path of the database to import from. If it's encrypted, it must use the same key.
+        other_db_path: PathBuf,
+    },
+
+    /// Print statistics about the database without accessing the Plaid API
+    Inspect {
+        /// Print the statistics as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Pack the database into a single encrypted archive file, e.g. to move it to another machine
+    Pack {
+        /// Path of the archive file to create
+        #[clap(long)]
+        output: PathBuf,
+    },
+
+    /// Create the database from an archive created by `db pack`
+    Unpack {
+        /// Path of the archive file
+        #[clap(long)]
+        input: PathBuf,
+    },
+
+    /// Replace the database with one of its backups. The current database becomes the most recent backup.
+    RestoreBackup(RestoreBackupArgs),
 }
 
 pub fn parse() -> Args {
-    Args::parse()
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    args.resolved_options = resolve_options(&matches);
+    args.command = args.command.into_grouped();
+    args
+}
+
+/// Where each global option got its value from. Clap resolves the precedence: command line, then environment
+/// variable, then default.
+fn resolve_options(matches: &ArgMatches) -> Vec<ResolvedOption> {
+    Args::command()
+        .get_arguments()
+        .filter(|arg| !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version))
+        .filter_map(|arg| {
+            let id = arg.get_id().as_str();
+            Some(ResolvedOption {
+                name: format!("--{}", arg.get_long()?),
+                env: arg.get_env().map(|env| env.to_string_lossy().into_owned()),
+                value: matches.get_raw(id).map(|values| {
+                    values
+                        .map(|value| value.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                }),
+                source: matches.value_source(id),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory as _;
+
+    use super::*;
+
+    fn parse_from(args: &[&str]) -> Command {
+        Args::try_parse_from(args).unwrap().command.into_grouped()
+    }
+
+    #[test]
+    fn valid_definition() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn flat_commands_are_grouped() {
+        let command = parse_from(&["beancount-plaid", "list-transactions", "--ignored"]);
+        assert!(matches!(
+            command,
+            Command::Transaction {
+                command: TransactionCommand::List(ListTransactionsArgs { ignored: true, .. })
+            }
+        ));
+        assert_eq!("transaction list", command.name());
+
+        let command = parse_from(&["beancount-plaid", "restore-backup", "-g", "2"]);
+        assert!(matches!(
+            command,
+            Command::Db {
+                command: DbCommand::RestoreBackup(RestoreBackupArgs { generation: 2 })
+            }
+        ));
+    }
+
+    #[test]
+    fn grouped_commands() {
+        let command = parse_from(&[
+            "beancount-plaid",
+            "account",
+            "connect",
+            "-c",
+            "Bank",
+            "-a",
+            "Checking",
+            "Assets:Bank:Checking",
+        ]);
+        assert_eq!("account connect", command.name());
+    }
+
+    #[test]
+    fn sync_account_requires_connection() {
+        assert!(
+            Args::try_parse_from(["beancount-plaid", "sync", "--account", "Checking"]).is_err()
+        );
+        let command = parse_from(&[
+            "beancount-plaid",
+            "sync",
+            "--connection",
+            "Bank",
+            "--account",
+            "Checking",
+        ]);
+        assert!(matches!(
+            command,
+            Command::Sync {
+                connection: Some(_),
+                account: Some(_),
+            }
+        ));
+    }
+
+    #[test]
+    fn force_requires_yes() {
+        assert!(Args::try_parse_from([
+            "beancount-plaid",
+            "db",
+            "prune",
+            "--before",
+            "2024-01-01",
+            "--force"
+        ])
+        .is_err());
+        let args = Args::try_parse_from([
+            "beancount-plaid",
+            "db",
+            "prune",
+            "--before",
+            "2024-01-01",
+            "-y",
+            "--force",
+        ])
+        .unwrap();
+        assert!(args.yes && args.force);
+    }
+
+    #[test]
+    fn command_line_takes_precedence_over_environment() {
+        let resolved = |args: &[&str]| {
+            let matches = Args::command().try_get_matches_from(args).unwrap();
+            resolve_options(&matches)
+                .into_iter()
+                .find(|option| option.name == "--compression-level")
+                .unwrap()
+        };
+        // Only this test sets the variable, so it doesn't affect the others
+        std::env::set_var("BEANCOUNT_PLAID_COMPRESSION_LEVEL", "3");
+
+        let option = resolved(&["beancount-plaid", "sync"]);
+        assert_eq!(Some("3".to_string()), option.value);
+        assert_eq!(Some(ValueSource::EnvVariable), option.source);
+        assert_eq!(
+            Some("BEANCOUNT_PLAID_COMPRESSION_LEVEL".to_string()),
+            option.env
+        );
+
+        let option = resolved(&["beancount-plaid", "--compression-level", "5", "sync"]);
+        assert_eq!(Some("5".to_string()), option.value);
+        assert_eq!(Some(ValueSource::CommandLine), option.source);
+
+        std::env::remove_var("BEANCOUNT_PLAID_COMPRESSION_LEVEL");
+        let option = resolved(&["beancount-plaid", "sync"]);
+        assert_eq!(Some(DEFAULT_COMPRESSION_LEVEL.to_string()), option.value);
+        assert_eq!(Some(ValueSource::DefaultValue), option.source);
+    }
 }
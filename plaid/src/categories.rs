@@ -0,0 +1,158 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::db::{
+    IgnoreList, Transaction, TransactionCategory, TransactionId, TransactionOverrides,
+};
+use crate::plaid_api;
+
+/// How the stored transactions of one Plaid category are assigned to Beancount accounts
+#[derive(Debug, PartialEq, Eq)]
+pub struct CategoryCoverage {
+    pub category: TransactionCategory,
+    /// `None` for categories that aren't in Plaid's list, e.g. ones that were entered with `transaction recategorize`
+    pub description: Option<String>,
+    pub num_transactions: usize,
+    /// Transactions that don't have a Beancount account assigned with `transaction recategorize`
+    pub num_unmapped: usize,
+    /// Beancount accounts that transactions of this category were assigned to
+    pub accounts: BTreeSet<String>,
+}
+
+/// One row per Plaid category in Plaid's order, followed by the categories that are only used by transactions.
+/// Categories changed with `transaction recategorize` are taken into account.
+/// Ignored transactions and transactions without a category aren't counted.
+pub fn category_coverage<'a>(
+    transactions: impl Iterator<Item = (&'a TransactionId, &'a Transaction)>,
+    overrides: &HashMap<TransactionId, TransactionOverrides>,
+    ignore_list: &IgnoreList,
+) -> Vec<CategoryCoverage> {
+    let mut rows: Vec<CategoryCoverage> = plaid_api::categories()
+        .iter()
+        .map(|(category, description)| CategoryCoverage {
+            category: category.clone(),
+            description: Some(description.clone()),
+            num_transactions: 0,
+            num_unmapped: 0,
+            accounts: BTreeSet::new(),
+        })
+        .collect();
+    let mut positions: HashMap<TransactionCategory, usize> = rows
+        .iter()
+        .enumerate()
+        .map(|(position, row)| (row.category.clone(), position))
+        .collect();
+    for (transaction_id, transaction) in transactions {
+        let info = &transaction.transaction;
+        if ignore_list.is_ignored(transaction_id, info) {
+            continue;
+        }
+        let overrides = overrides.get(transaction_id);
+        let Some(category) = overrides
+            .and_then(|overrides| overrides.category.as_ref())
+            .or(info.category.as_ref())
+        else {
+            continue;
+        };
+        let position = *positions.entry(category.clone()).or_insert_with(|| {
+            rows.push(CategoryCoverage {
+                category: category.clone(),
+                description: None,
+                num_transactions: 0,
+                num_unmapped: 0,
+                accounts: BTreeSet::new(),
+            });
+            rows.len() - 1
+        });
+        let row = &mut rows[position];
+        row.num_transactions += 1;
+        match overrides.and_then(|overrides| overrides.account.as_ref()) {
+            Some(account) => {
+                row.accounts.insert(account.beancount_name());
+            }
+            None => row.num_unmapped += 1,
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{AccountType, BeancountAccountInfo, TransactionBuilder};
+
+    use super::*;
+
+    fn category(primary: &str, detailed: &str) -> TransactionCategory {
+        TransactionCategory {
+            primary: primary.to_string(),
+            detailed: detailed.to_string(),
+        }
+    }
+
+    fn transaction(category: Option<TransactionCategory>) -> Transaction {
+        TransactionBuilder::new()
+            .posted_on(2024, 1, 15)
+            .cents(-500)
+            .category(category)
+            .build()
+    }
+
+    #[test]
+    fn count_mapped_and_unmapped_transactions() {
+        let groceries = category("FOOD_AND_DRINK", "FOOD_AND_DRINK_GROCERIES");
+        let custom = category("CUSTOM", "CUSTOM_OTHER");
+        let transactions = [
+            ("a", transaction(Some(groceries.clone()))),
+            ("b", transaction(Some(groceries.clone()))),
+            ("c", transaction(Some(groceries.clone()))),
+            ("d", transaction(None)),
+            ("e", transaction(None)),
+        ]
+        .map(|(id, transaction)| (TransactionId(id.to_string()), transaction));
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            TransactionId("a".to_string()),
+            TransactionOverrides {
+                account: Some(BeancountAccountInfo {
+                    ty: AccountType::Expenses,
+                    name_parts: vec!["Groceries".to_string()],
+                }),
+                ..Default::default()
+            },
+        );
+        overrides.insert(
+            TransactionId("e".to_string()),
+            TransactionOverrides {
+                category: Some(custom.clone()),
+                ..Default::default()
+            },
+        );
+        let mut ignore_list = IgnoreList::default();
+        ignore_list.ignore_transaction(TransactionId("c".to_string()));
+
+        let rows = category_coverage(
+            transactions
+                .iter()
+                .map(|(id, transaction)| (id, transaction)),
+            &overrides,
+            &ignore_list,
+        );
+        assert_eq!(plaid_api::categories().len() + 1, rows.len());
+        let groceries_row = rows.iter().find(|row| row.category == groceries).unwrap();
+        assert_eq!(2, groceries_row.num_transactions);
+        assert_eq!(1, groceries_row.num_unmapped);
+        assert_eq!(
+            BTreeSet::from(["Expenses:Groceries".to_string()]),
+            groceries_row.accounts
+        );
+        assert_eq!(
+            &CategoryCoverage {
+                category: custom,
+                description: None,
+                num_transactions: 1,
+                num_unmapped: 1,
+                accounts: BTreeSet::new(),
+            },
+            rows.last().unwrap()
+        );
+    }
+}
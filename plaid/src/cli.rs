@@ -1,93 +1,690 @@
 use anyhow::{anyhow, bail, Context, Result};
-use base64::Engine;
-use chacha20poly1305::{KeySizeUser as _, XChaCha20Poly1305};
+use chrono::{Local, NaiveDate};
+use clap::parser::ValueSource;
 use console::{pad_str, style, Alignment, StyledObject};
 use futures::stream::FuturesUnordered;
 use futures::StreamExt as _;
-use indicatif::{MultiProgress, ProgressBar};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use regex::RegexBuilder;
 use rust_decimal::Decimal;
-use std::collections::HashMap;
-use std::env::VarError;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument as _;
 
-use crate::args::{Args, Command};
+use crate::args::{
+    AccountCommand, AddConnectionArgs, AddTransactionArgs, AmountSignArgs, AnnotateArgs, Args,
+    Command, ConfigCommand, ConnectionCommand, DbCommand, DisconnectAccountArgs, ExportFormat,
+    IgnoreArgs, KeepArgs, ListConnectionsArgs, ListTransactionsArgs, MapAccountArgs, OnError,
+    RecategorizeArgs, RefreshConnectionArgs, RemapAccountArgs, RemoveConnectionArgs,
+    ResolvedOption, RestoreBackupArgs, SearchArgs, TargetCommand, TransactionCommand,
+    TransactionQuery, UnignoreArgs,
+};
+use crate::categories::category_coverage;
 use crate::db::{
-    Account, AccountId, AccountType, AddOrVerifyResult, Amount, BeancountAccountInfo, DatabaseFile,
-    DatabaseV2, PlaidAccountInfo, Transaction,
+    Account, AccountId, Amount, AmountSign, BeancountAccountInfo, DatabaseFile, DatabaseV16,
+    IgnoreList, IgnoreRule, LedgerTarget, ManualTransaction, PlaidAccountInfo, RenameDirectives,
+    StorageBackend, Transaction, TransactionCategory, TransactionId, TransactionInfo,
+    TransactionOverrides, Transactions,
+};
+use crate::exit_code;
+use crate::export::{
+    export_all_transactions, export_beancount_import_candidates, export_new_transactions,
+    ExportResult, Script,
+};
+use crate::inspect::{inspect, Counts};
+use crate::key::KeySource;
+use crate::ledger::{read_open_accounts, read_transactions};
+use crate::logging;
+use crate::mapping::{
+    connect_account, find_account_by_name, find_connection_mut, parse_beancount_account_name,
+    remap_account, set_amount_sign, set_sync_enabled, suggest_beancount_account_name,
+};
+use crate::paths::resolve_db_path;
+use crate::report::{report, ReportGroupBy, ReportPeriod};
+use crate::suggest::Classifier;
+use crate::sync::{
+    find_sync_account, reconcile_balances, record_balance_snapshots, replace_transaction,
+    reset_balance_anchor, sync_connection, AccountChange, BalanceMismatch, Mismatch, SyncReport,
+    Timezone,
 };
-use crate::export::print_exported_transactions;
-use crate::terminal::{self, prompt_select, BulletPointPrinter, LineWriter};
+use crate::terminal::{self, BulletPointPrinter, ColorMode, LineWriter};
+use crate::validate::{append_export, append_validated, AppendedExport};
 
-use super::db::{BankConnection, Cipher, DbPlaidAuth, XChaCha20Poly1305Cipher};
+use super::db::{
+    flag_duplicates, merge_databases, pack_archive, unpack_archive, ArchivedAccount,
+    ArchivedConnection, BankConnection, ConnectedAccount, DbCipher, DbPlaidAuth,
+};
 use super::plaid_api;
 
-const ENCRYPTION_KEY_ENCODER: base64::engine::general_purpose::GeneralPurpose =
-    base64::engine::general_purpose::URL_SAFE_NO_PAD;
+mod tui;
+
+const UNKNOWN_CATEGORIES_HINT: &str =
+    "These categories aren't in Plaid's category list, which may be outdated. \
+    Add them with a description to a CSV file and pass it with `--categories`.";
 
-pub async fn main(args: Args) -> Result<()> {
+pub async fn main(args: Args) -> Result<ExitCode> {
+    terminal::set_color_mode(if args.no_color {
+        ColorMode::Never
+    } else {
+        args.color
+    });
+    logging::init(args.log_file.as_deref(), args.log_json)?;
+    terminal::set_quiet(args.quiet);
+    terminal::set_assume_yes(args.yes, args.force);
+    if let Some(categories) = &args.categories {
+        let num_added = plaid_api::load_category_overrides(categories)?;
+        tracing::info!(num_added, path = %categories.display(), "Loaded category overrides");
+    }
+    let key_source = KeySource::new(args.key_file, args.age_identity);
+    let db_path = resolve_db_path(args.db_path)?;
+    if let Command::Db {
+        command: DbCommand::RestoreBackup(RestoreBackupArgs { generation }),
+    } = args.command
+    {
+        // Don't load the database, it may be the reason the user wants to restore a backup
+        let db_cipher = if tokio::fs::try_exists(&db_path).await? {
+            load_db_cipher(&db_path, &key_source)?
+        } else {
+            DbCipher::Encrypted(key_source.load()?)
+        };
+        handle_leftovers(&db_path, &db_cipher, args.num_backups).await?;
+        DatabaseFile::restore_backup(db_path, db_cipher, generation, args.num_backups).await?;
+        println!("Restored backup {generation}");
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Command::Undo { list } = args.command {
+        // Like restoring a backup, this must work even if the current database is broken
+        if list {
+            print_undo_history(&db_path, args.num_backups).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        let db_cipher = if tokio::fs::try_exists(&db_path).await? {
+            load_db_cipher(&db_path, &key_source)?
+        } else {
+            DbCipher::Encrypted(key_source.load()?)
+        };
+        handle_leftovers(&db_path, &db_cipher, args.num_backups).await?;
+        match DatabaseFile::undo(db_path, db_cipher, args.num_backups).await? {
+            Some(snapshot) => println!(
+                "Undid {} from {}",
+                snapshot.command,
+                snapshot
+                    .created_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S"),
+            ),
+            None => println!(
+                "Undid an unknown change, the backup was created before changes were recorded"
+            ),
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Command::Config {
+        command: ConfigCommand::Show { resolved },
+    } = args.command
+    {
+        print_config(&args.resolved_options, &db_path, resolved);
+        return Ok(ExitCode::SUCCESS);
+    }
+    let command_name = args.command.name();
+    match &args.command {
+        Command::Db {
+            command: DbCommand::Pack { output },
+        } => {
+            if tokio::fs::try_exists(output).await? {
+                if !terminal::confirm(&format!(
+                    "{} already exists. Overwrite it?",
+                    output.display()
+                ))? {
+                    return Ok(ExitCode::SUCCESS);
+                }
+                tokio::fs::remove_file(output).await?;
+            }
+            // The archive is encrypted even if the database isn't, so it's safe to move around
+            let cipher = key_source.load_or_gen_new()?;
+            pack_archive(&db_path, output, &cipher).await?;
+            println!("Packed database into {}", output.display());
+            return Ok(ExitCode::SUCCESS);
+        }
+        Command::Db {
+            command: DbCommand::Unpack { input },
+        } => {
+            let cipher = key_source.load()?;
+            unpack_archive(input, &db_path, &cipher).await?;
+            println!("Unpacked database to {}", db_path.display());
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => {}
+    }
     let mut cli = match args.command {
-        Command::Init => Cli::new_init_db(args.db_path).await?,
-        _ => Cli::new_load_db(args.db_path).await?,
+        Command::Init { no_encryption } => {
+            Cli::new_init_db(
+                db_path,
+                args.num_backups,
+                args.compression_level,
+                args.storage,
+                no_encryption,
+                &key_source,
+            )
+            .await?
+        }
+        _ => {
+            Cli::new_load_db(
+                db_path,
+                args.num_backups,
+                args.compression_level,
+                &key_source,
+            )
+            .await?
+        }
     };
+    let mut exit_code = ExitCode::SUCCESS;
+    let mut export_output = vec![];
+    let mut output_file = None;
+    // Exports appended to output files, taken back if the database can't be saved
+    let mut appended = vec![];
     match args.command {
-        Command::Init => cli.main_init().await?,
-        Command::AddConnection => cli.main_add_connection().await?,
-        Command::ListConnections => cli.main_list_connections().await?,
-        Command::RemoveConnection { connection_name } => {
-            cli.main_remove_connection(&connection_name).await?
-        }
-        Command::Sync => cli.main_sync().await?,
-        Command::ListTransactions => cli.main_list_transactions().await?,
-        Command::ExportAll => cli.main_export_all_transactions().await?,
-        Command::ExportNew => cli.main_export_new_transactions().await?,
-    }
-    cli.save_db().await?;
+        Command::Init { .. } => cli.main_init().await?,
+        Command::Connection { command } => match command {
+            ConnectionCommand::Add(AddConnectionArgs { lan }) => {
+                cli.db.begin();
+                let result = cli.main_add_connection(args.ledger.as_deref(), lan).await;
+                cli.commit_or_rollback(result)?
+            }
+            ConnectionCommand::List(ListConnectionsArgs { archived }) => {
+                cli.main_list_connections(archived).await?
+            }
+            ConnectionCommand::Remove(RemoveConnectionArgs { connection_name }) => {
+                cli.db.begin();
+                let result = cli.main_remove_connection(&connection_name).await;
+                cli.commit_or_rollback(result)?
+            }
+            ConnectionCommand::Refresh(RefreshConnectionArgs { connection_name }) => {
+                cli.main_refresh_connection(&connection_name).await?
+            }
+        },
+        Command::Account { command } => match command {
+            AccountCommand::List => cli.main_list_accounts(),
+            AccountCommand::Connect(MapAccountArgs {
+                connection_name,
+                account_name,
+                beancount_account,
+            }) => cli.atomically(|cli| {
+                cli.main_map_account(&connection_name, &account_name, &beancount_account)
+            })?,
+            AccountCommand::Disable(DisconnectAccountArgs {
+                connection_name,
+                account_name,
+            }) => {
+                cli.atomically(|cli| cli.main_disconnect_account(&connection_name, &account_name))?
+            }
+            AccountCommand::Pause(DisconnectAccountArgs {
+                connection_name,
+                account_name,
+            }) => cli.atomically(|cli| {
+                cli.main_set_sync_enabled(&connection_name, &account_name, false)
+            })?,
+            AccountCommand::Resume(DisconnectAccountArgs {
+                connection_name,
+                account_name,
+            }) => cli.atomically(|cli| {
+                cli.main_set_sync_enabled(&connection_name, &account_name, true)
+            })?,
+            AccountCommand::Remap(RemapAccountArgs {
+                connection_name,
+                account_name,
+                beancount_account,
+                directives,
+            }) => cli.atomically(|cli| {
+                cli.main_remap_account(
+                    &connection_name,
+                    &account_name,
+                    &beancount_account,
+                    directives,
+                )
+            })?,
+            AccountCommand::Sign(AmountSignArgs {
+                connection_name,
+                account_name,
+                sign,
+            }) => cli.atomically(|cli| {
+                cli.main_set_amount_sign(&connection_name, &account_name, sign)
+            })?,
+        },
+        Command::Transaction { command } => match command {
+            TransactionCommand::List(ListTransactionsArgs {
+                ignored,
+                uncategorized,
+                query,
+            }) => {
+                let filter = if ignored {
+                    TransactionFilter::Ignored
+                } else if uncategorized {
+                    TransactionFilter::Uncategorized
+                } else {
+                    TransactionFilter::All
+                };
+                cli.main_list_transactions(filter, &query).await?
+            }
+            TransactionCommand::Search(SearchArgs { query, regex }) => {
+                cli.main_search(&query, regex)?
+            }
+            TransactionCommand::Annotate(AnnotateArgs {
+                transaction_id,
+                note,
+                account,
+                payee,
+                clear,
+            }) => cli.atomically(|cli| {
+                cli.main_annotate(TransactionId(transaction_id), note, account, payee, clear)
+            })?,
+            TransactionCommand::Recategorize(RecategorizeArgs {
+                transaction_id,
+                category_or_account,
+            }) => cli.atomically(|cli| {
+                cli.main_recategorize(TransactionId(transaction_id), &category_or_account)
+            })?,
+            TransactionCommand::Ignore(IgnoreArgs {
+                transaction_id,
+                matching,
+            }) => {
+                cli.atomically(|cli| cli.main_ignore(transaction_id.map(TransactionId), matching))?
+            }
+            TransactionCommand::Unignore(UnignoreArgs {
+                transaction_id,
+                matching,
+            }) => cli
+                .atomically(|cli| cli.main_unignore(transaction_id.map(TransactionId), matching))?,
+            TransactionCommand::Add(AddTransactionArgs {
+                date,
+                amount,
+                currency,
+                account,
+                narration,
+                payee,
+            }) => cli.atomically(|cli| {
+                cli.main_add_transaction(date, amount, currency, &account, narration, payee)
+            })?,
+            TransactionCommand::Duplicates => cli.main_list_duplicates()?,
+            TransactionCommand::Keep(KeepArgs { transaction_id }) => {
+                cli.atomically(|cli| cli.main_keep(TransactionId(transaction_id)))?
+            }
+        },
+        Command::Sync {
+            connection,
+            account,
+        } => {
+            terminal::cancel_on_ctrl_c(cli.cancel.clone());
+            if let Err(err) = cli
+                .main_sync(
+                    connection.as_deref(),
+                    account.as_deref(),
+                    args.timezone,
+                    args.on_error,
+                )
+                .await
+            {
+                // The connections that failed were rolled back, the state is consistent
+                if args.on_error == OnError::KeepCompleted {
+                    cli.save_db(command_name).await?;
+                }
+                return Err(err);
+            }
+        }
+        Command::Tui => {
+            let result = cli
+                .main_tui(
+                    args.ledger.as_deref(),
+                    args.validate_with,
+                    args.timezone,
+                    args.on_error,
+                    &mut appended,
+                )
+                .await;
+            if let Err(err) = result {
+                revert_exports(appended)?;
+                return Err(err);
+            }
+        }
+        Command::Suggest {
+            min_confidence,
+            apply,
+        } => {
+            cli.atomically(|cli| cli.main_suggest(args.ledger.as_deref(), min_confidence, apply))?
+        }
+        Command::ExportAll {
+            target,
+            format,
+            script,
+        } => {
+            let script = script.as_deref().map(Script::load).transpose()?;
+            cli.main_export_all_transactions(target.as_deref(), format, script.as_ref())
+                .await?
+        }
+        Command::ExportNew {
+            target,
+            output_file: output_file_arg,
+            script,
+        } => {
+            output_file = output_file_arg.or_else(|| {
+                target
+                    .as_ref()
+                    .and_then(|name| cli.db.database().ledger_targets.get(name))
+                    .and_then(|target| target.output_file.clone())
+            });
+            if args.validate_with.is_some() && output_file.is_none() {
+                bail!("--validate-with needs an output file, pass --output-file or set one for the target");
+            }
+            let script = script.as_deref().map(Script::load).transpose()?;
+            let (num_exported, output) = cli
+                .main_export_new_transactions(target.as_deref(), script.as_ref())
+                .await?;
+            if num_exported == 0 {
+                exit_code = ExitCode::from(exit_code::NOTHING_TO_EXPORT);
+            }
+            export_output = output;
+        }
+        Command::Target { command } => match command {
+            TargetCommand::Add {
+                name,
+                output_file,
+                operating_currency,
+            } => {
+                cli.atomically(|cli| cli.main_add_target(name, output_file, operating_currency))?
+            }
+            TargetCommand::List => cli.main_list_targets(),
+            TargetCommand::Remove { name } => {
+                cli.atomically(|cli| cli.main_remove_target(&name))?
+            }
+            TargetCommand::Assign {
+                name,
+                connection,
+                account,
+            } => cli.atomically(|cli| {
+                cli.main_assign_target(Some(&name), &connection, account.as_deref())
+            })?,
+            TargetCommand::Unassign {
+                connection,
+                account,
+            } => {
+                cli.atomically(|cli| cli.main_assign_target(None, &connection, account.as_deref()))?
+            }
+            TargetCommand::Ignore { name, matching } => {
+                cli.atomically(|cli| cli.main_target_ignore(&name, matching))?
+            }
+        },
+        Command::Report {
+            group_by,
+            period,
+            csv,
+        } => cli.main_report(group_by, period, csv)?,
+        Command::Categories => cli.main_categories(),
+        Command::UndoExport { since, account } => {
+            cli.atomically(|cli| cli.main_undo_export(since, account))?
+        }
+        Command::Undo { .. } | Command::Config { .. } => unreachable!("Handled above"),
+        Command::Db { command } => match command {
+            DbCommand::Encrypt => cli.main_db_encrypt(&key_source)?,
+            DbCommand::Prune {
+                before,
+                only_exported,
+            } => cli.atomically(|cli| cli.main_db_prune(before, only_exported))?,
+            DbCommand::Merge { other_db_path } => {
+                cli.db.begin();
+                let result = cli.main_db_merge(other_db_path, &key_source).await;
+                cli.commit_or_rollback(result)?
+            }
+            DbCommand::Inspect { json } => cli.main_db_inspect(json)?,
+            DbCommand::Pack { .. } | DbCommand::Unpack { .. } | DbCommand::RestoreBackup(_) => {
+                unreachable!("Handled above")
+            }
+        },
+        Command::AddConnection(_)
+        | Command::ListConnections(_)
+        | Command::RemoveConnection(_)
+        | Command::ListAccounts
+        | Command::MapAccount(_)
+        | Command::DisconnectAccount(_)
+        | Command::ListTransactions(_)
+        | Command::Search(_)
+        | Command::Ignore(_)
+        | Command::Unignore(_)
+        | Command::AddTransaction(_)
+        | Command::Annotate(_)
+        | Command::Recategorize(_)
+        | Command::RestoreBackup(_) => unreachable!("Replaced by `args::parse`"),
+    }
+    // The export is written before saving, so the transactions aren't marked as exported if writing fails or the
+    // validator rejects it
+    let printed = match (&output_file, args.validate_with) {
+        _ if export_output.is_empty() => false,
+        (Some(output_file), Some(validator)) => {
+            appended.push(append_validated(
+                validator,
+                output_file,
+                &export_output,
+                args.ledger.as_deref(),
+            )?);
+            false
+        }
+        (Some(output_file), None) => {
+            appended.push(append_export(output_file, &export_output)?);
+            false
+        }
+        (None, _) => {
+            let mut stdout = stdout().lock();
+            stdout.write_all(&export_output)?;
+            stdout.flush()?;
+            true
+        }
+    };
+    if let Err(err) = save_or_revert_exports(cli, command_name, appended).await {
+        if printed {
+            return Err(err.context(
+                "The exported transactions were printed but aren't marked as exported, \
+                so they'll be exported again next time",
+            ));
+        }
+        return Err(err);
+    }
+    if let Some(output_file) = output_file.filter(|_| !export_output.is_empty()) {
+        terminal::print_status(format!("Appended to {}", output_file.display()));
+    }
+    Ok(exit_code)
+}
+
+/// Save the database. If that fails, the exports in `appended` are taken back, since their transactions aren't
+/// marked as exported and would be exported again.
+async fn save_or_revert_exports(
+    cli: Cli,
+    command_name: &str,
+    appended: Vec<AppendedExport>,
+) -> Result<()> {
+    let Err(err) = cli.save_db(command_name).await else {
+        return Ok(());
+    };
+    revert_exports(appended)?;
+    Err(err)
+}
+
+/// Take back exports, the latest first since each one restores the file as it was before it was appended
+fn revert_exports(appended: Vec<AppendedExport>) -> Result<()> {
+    appended
+        .into_iter()
+        .rev()
+        .try_for_each(AppendedExport::revert)
+}
+
+async fn print_undo_history(db_path: &Path, num_backups: usize) -> Result<()> {
+    let history = DatabaseFile::undo_history(db_path, num_backups).await?;
+    if history.is_empty() {
+        println!("There is nothing to undo");
+    }
+    for (index, snapshot) in history.iter().enumerate() {
+        match snapshot {
+            Some(snapshot) => println!(
+                "{}: {} from {}",
+                index + 1,
+                snapshot.command,
+                snapshot
+                    .created_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S"),
+            ),
+            None => println!("{}: unknown change", index + 1),
+        }
+    }
     Ok(())
 }
 
 pub struct Cli {
     db: DatabaseFile,
     plaid_api: plaid_api::Plaid,
+    /// Cancelled by Ctrl+C during `sync`, which also keeps the database from being saved
+    cancel: CancellationToken,
 }
 
 impl Cli {
-    pub async fn new_init_db(db_path: PathBuf) -> Result<Self> {
+    pub async fn new_init_db(
+        db_path: PathBuf,
+        num_backups: usize,
+        compression_level: i32,
+        storage_backend: StorageBackend,
+        no_encryption: bool,
+        key_source: &KeySource,
+    ) -> Result<Self> {
         if tokio::fs::try_exists(&db_path).await.unwrap() {
             bail!("Database already exists");
         }
         let client_id = terminal::prompt("Plaid Client ID").unwrap();
         let secret = terminal::prompt("Plaid Secret").unwrap();
-        let db_cipher = load_or_gen_new_cipher()?;
+        let db_cipher = if no_encryption {
+            print_unencrypted_warning();
+            DbCipher::Unencrypted
+        } else {
+            DbCipher::Encrypted(key_source.load_or_gen_new()?)
+        };
         let db = DatabaseFile::new(
-            DatabaseV2::new(DbPlaidAuth::new(client_id, secret)),
+            DatabaseV16::new(DbPlaidAuth::new(client_id, secret)),
             db_path,
             db_cipher,
-        );
+        )
+        .with_num_backups(num_backups)
+        .with_compression_level(compression_level)
+        .with_storage_backend(storage_backend);
 
         Ok(Self::_new(db))
     }
 
-    pub async fn new_load_db(db_path: PathBuf) -> Result<Self> {
-        let db_cipher = load_cipher_from_environment()?;
+    pub async fn new_load_db(
+        db_path: PathBuf,
+        num_backups: usize,
+        compression_level: i32,
+        key_source: &KeySource,
+    ) -> Result<Self> {
+        if !tokio::fs::try_exists(&db_path).await? {
+            bail!("Database file not found");
+        }
+        let db_cipher = load_db_cipher(&db_path, key_source)?;
+        handle_leftovers(&db_path, &db_cipher, num_backups).await?;
         let db = DatabaseFile::load(db_path, db_cipher)
             .await
-            .with_context(||format!("Failed to load database. Is the {BEANCOUNT_PLAID_KEY_ENV_VAR} environment variable set correctly?"))?
-            .ok_or_else(|| anyhow!("Database file not found"))?;
+            .with_context(|| {
+                format!(
+                    "Failed to load database. Is {} set correctly?",
+                    key_source.describe()
+                )
+            })?
+            .ok_or_else(|| anyhow!("Database file not found"))?
+            .with_num_backups(num_backups)
+            .with_compression_level(compression_level);
+        if db.format_version() != db.current_format_version() {
+            terminal::print_status(format!(
+                "Loaded v{} database, migrating to v{}.",
+                db.format_version(),
+                db.current_format_version()
+            ));
+        } else {
+            terminal::print_status(format!("Loaded v{} database", db.format_version()));
+        }
         Ok(Self::_new(db))
     }
 
     fn _new(db: DatabaseFile) -> Self {
         let plaid_api = plaid_api::Plaid::new(db.database().plaid_auth.to_api_auth());
-        Self { db, plaid_api }
+        let cancel = CancellationToken::new();
+        let db = db.with_cancellation_token(cancel.clone());
+        Self {
+            db,
+            plaid_api,
+            cancel,
+        }
     }
 
-    pub async fn save_db(self) -> Result<()> {
-        self.db
-            .save_if_modified()
-            .await
-            .context("Failed to save database")?;
-        Ok(())
+    /// If saving fails, e.g. because the disk is full, asks whether to try again or save the changes elsewhere
+    /// instead of losing them
+    pub async fn save_db(self, command_name: &str) -> Result<()> {
+        let mut db = self.db.with_command(command_name);
+        loop {
+            let err = match db.try_save_if_modified().await {
+                Ok(()) => return Ok(()),
+                Err(err) => anyhow::Error::from(err).context("Failed to save database"),
+            };
+            // With --yes, "Try again" would be chosen forever
+            if !console::user_attended() || terminal::assume_yes() {
+                return Err(err);
+            }
+            eprintln!("{}", style(format!("{err:#}")).red().bold());
+            let choice = terminal::prompt_select(
+                "The changes aren't saved yet. What do you want to do?",
+                &["Try again", "Save them to another file", "Discard them"],
+                0,
+            )?;
+            match choice {
+                0 => {}
+                1 => {
+                    let path = PathBuf::from(terminal::prompt("Path of the file")?);
+                    match db.save_copy_to(&path).await {
+                        Ok(()) => {
+                            eprintln!(
+                                "Saved the changes to {}. The database at {} wasn't changed, replace it with that file to keep them.",
+                                path.display(),
+                                db.db_path().display(),
+                            );
+                            return Ok(());
+                        }
+                        Err(err) => eprintln!(
+                            "{}",
+                            style(format!("Failed to save to {}: {err:#}", path.display()))
+                                .red()
+                                .bold()
+                        ),
+                    }
+                }
+                _ => return Err(err),
+            }
+        }
+    }
+
+    /// Run a command that changes the database in several steps. If it fails, none of its changes are kept.
+    fn atomically<T>(&mut self, command: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.db.begin();
+        let result = command(self);
+        self.commit_or_rollback(result)
+    }
+
+    /// Keep the changes since [DatabaseFile::begin] if `result` is ok and take them back otherwise
+    fn commit_or_rollback<T>(&mut self, result: Result<T>) -> Result<T> {
+        if result.is_ok() {
+            self.db.commit();
+        } else {
+            self.db.rollback();
+        }
+        result
     }
 
     pub async fn main_init(&self) -> Result<()> {
@@ -98,353 +695,2165 @@ impl Cli {
         Ok(())
     }
 
-    pub async fn main_add_connection(&mut self) -> Result<()> {
+    pub fn main_db_encrypt(&mut self, key_source: &KeySource) -> Result<()> {
+        if self.db.is_encrypted() {
+            bail!("The database is already encrypted");
+        }
+        let db_cipher = key_source.load_or_gen_new()?;
+        self.db.set_cipher(DbCipher::Encrypted(db_cipher));
+        println!("Encrypting the database");
+        Ok(())
+    }
+
+    pub fn main_db_prune(&mut self, before: NaiveDate, only_exported: bool) -> Result<()> {
+        let database = self.db.database();
+        let num_unexported: usize = database
+            .bank_connections
+            .iter()
+            .flat_map(|connection| connection.accounts())
+            .filter_map(|(_, account)| account.account.as_ref())
+            .flat_map(|account| account.transactions.iter_all_sorted_by_date())
+            .filter(|(transaction_id, transaction)| {
+                transaction.transaction.date() < before
+                    && !transaction.already_exported
+                    && !database
+                        .ignore_list
+                        .is_ignored(transaction_id, &transaction.transaction)
+            })
+            .count();
+        let confirmed = if num_unexported > 0 && !only_exported {
+            terminal::confirm_data_loss(&format!(
+                "{num_unexported} transactions dated before {before} weren't exported yet and will be lost. Prune them anyways?"
+            ))?
+        } else {
+            terminal::confirm(&format!(
+                "Prune the transactions dated before {before}? `sync` won't add them again."
+            ))?
+        };
+        if !confirmed {
+            return Ok(());
+        }
+        println!("{}", style_header("Pruning transactions:"));
+        let printer = BulletPointPrinter::new_stdout();
+        let mut total_num_pruned = 0;
+        let mut total_num_kept = 0;
+        for connection in &mut self.db.database_mut().bank_connections {
+            printer.print_item(style_connection(connection));
+            let printer = printer.indent();
+            for (_, account) in connection.accounts_mut() {
+                let Some(connected_account) = &mut account.account else {
+                    continue;
+                };
+                let num_pruned = connected_account.prune_transactions(before, only_exported);
+                let num_kept = connected_account.transactions.len();
+                printer.print_item(style_account(account));
+                let printer = printer.indent();
+                printer.print_item(style(format!("Pruned: {num_pruned}")).italic());
+                printer.print_item(style(format!("Kept: {num_kept}")).italic());
+                total_num_pruned += num_pruned;
+                total_num_kept += num_kept;
+            }
+        }
+        println!();
+        println!("{}", style_header("Totals:"));
+        println!("{}", style(format!("Pruned: {total_num_pruned}")).italic());
+        println!("{}", style(format!("Kept: {total_num_kept}")).italic());
+        Ok(())
+    }
+
+    pub async fn main_db_merge(
+        &mut self,
+        other_db_path: PathBuf,
+        key_source: &KeySource,
+    ) -> Result<()> {
+        let db_cipher = load_db_cipher(&other_db_path, key_source)?;
+        let other = DatabaseFile::load(other_db_path.clone(), db_cipher)
+            .await
+            .with_context(|| format!("Failed to load {}", other_db_path.display()))?
+            .ok_or_else(|| anyhow!("Database {} not found", other_db_path.display()))?;
+        let report = merge_databases(self.db.database_mut(), other.database().clone())?;
+
+        println!("{}", style_header("Merged connections:"));
+        let printer = BulletPointPrinter::new_stdout();
+        let mut num_conflicts = 0;
+        for connection in &report.connections {
+            let mut connection_info = style(connection.name.clone()).cyan().bold().to_string();
+            if connection.added_connection {
+                connection_info.push_str(&style(" [new]").green().to_string());
+            }
+            printer.print_item(style(connection_info));
+            let printer = printer.indent();
+            for account in &connection.accounts {
+                let mut account_info = account.name.clone();
+                if account.added_account {
+                    account_info.push_str(&style(" [new]").green().to_string());
+                }
+                printer.print_item(style(account_info));
+                let printer = printer.indent();
+                printer.print_item(style(format!("Added: {}", account.num_added)).italic());
+                printer.print_item(style(format!("Verified: {}", account.num_verified)).italic());
+                for conflict in &account.conflicts {
+                    printer.print_item(
+                        style(format!(
+                            "Conflict for transaction {:?}, keeping ours\nOurs: {:?}\nTheirs: {:?}",
+                            conflict.transaction_id, conflict.existing_value, conflict.new_value,
+                        ))
+                        .red(),
+                    );
+                }
+                num_conflicts += account.conflicts.len();
+            }
+        }
+        if num_conflicts > 0 {
+            println!();
+            println!(
+                "{}",
+                style(format!("Found {num_conflicts} conflicting transactions"))
+                    .red()
+                    .bold()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn main_db_inspect(&self, json: bool) -> Result<()> {
+        let report = inspect(&self.db)?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            return Ok(());
+        }
+        println!("{}", style_header("Database:"));
+        let printer = BulletPointPrinter::new_stdout();
+        printer.print_item(style(format!("Storage: {:?}", report.storage)).italic());
+        printer.print_item(style(format!("Format version: {}", report.format_version)).italic());
+        printer.print_item(style(format!("Encrypted: {}", report.encrypted)).italic());
+        printer.print_item(style(format!("File size: {} bytes", report.file_size_bytes)).italic());
+        println!();
+        println!("{}", style_header("Connections:"));
+        for connection in &report.connections {
+            printer.print_item(style(&connection.name).cyan().bold());
+            let printer = printer.indent();
+            for account in &connection.accounts {
+                let beancount_account = account
+                    .beancount_account
+                    .as_deref()
+                    .unwrap_or("not connected");
+                printer.print_item(style(format!("{} [{beancount_account}]", account.name)));
+                if account.beancount_account.is_none() {
+                    continue;
+                }
+                let printer = printer.indent();
+                let date_range = match (
+                    account.first_transaction_date,
+                    account.last_transaction_date,
+                ) {
+                    (Some(first), Some(last)) => format!("{first} to {last}"),
+                    _ => "none".to_string(),
+                };
+                printer
+                    .print_item(style(format!("Sync enabled: {}", account.sync_enabled)).italic());
+                printer.print_item(style(format!("Transactions: {date_range}")).italic());
+                print_counts(&printer, &account.counts);
+                printer
+                    .print_item(style(format!("Pruned: {}", account.pruned_transactions)).italic());
+            }
+        }
+        println!();
+        println!("{}", style_header("Totals:"));
+        printer.print_item(style(format!("Connections: {}", report.connections.len())).italic());
+        printer.print_item(style(format!("Accounts: {}", report.totals.accounts)).italic());
+        print_counts(&printer, &report.totals);
+        Ok(())
+    }
+
+    pub async fn main_add_connection(&mut self, ledger: Option<&Path>, lan: bool) -> Result<()> {
+        let ledger_accounts = match ledger {
+            Some(ledger) => read_open_accounts(ledger)?,
+            None => vec![],
+        };
         let name = terminal::prompt("Enter a name for the new connection").unwrap();
         println!();
-        let access_token = plaid_api::link_new_account(&self.plaid_api).await.unwrap();
+        let access_token = plaid_api::link_new_account(&self.plaid_api, lan, |url| {
+            println!("Starting in-browser link flow.");
+            if lan {
+                println!("Please open the following URL on a device in the same network, e.g. by scanning the QR code with a phone:");
+            } else {
+                println!(
+                    "If it doesn't open automatically, please open the following URL in your browser:"
+                );
+            }
+            println!("{}", style(url).cyan().italic());
+            terminal::print_qr_code(url)?;
+            if !lan {
+                open::that(url)?;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await
+        .context("Failed to link the bank connection")?;
         let accounts = plaid_api::get_accounts(&self.plaid_api, &access_token)
             .await
-            .unwrap();
+            .context("Failed to get the accounts of the bank connection")?;
         println!();
-        println!("Found {} accounts", accounts.len());
+        println!("Found {} accounts", accounts.accounts.len());
+        let institution_id = accounts.institution_id;
+        let mut pending_accounts = vec![];
         let accounts = accounts
+            .accounts
             .enumerate()
             .map(|(index, account)| {
                 let (id, account) = account?;
-                Ok(prompt_add_account(index, id, account)?)
+                let (id, account, pending) =
+                    prompt_add_account(index, id, account, &ledger_accounts)?;
+                if pending {
+                    pending_accounts.push(id.clone());
+                }
+                Ok((id, account))
             })
             .collect::<Result<_>>()?;
-        let connection = BankConnection::new(name, access_token, accounts);
+        let connection = BankConnection::new(name, access_token, institution_id, accounts);
         println!();
         println!("{}", style_header("Adding connection:"));
         print_connection(&BulletPointPrinter::new_stdout(), &connection);
-        self.db.database_mut().bank_connections.push(connection);
+        let database = self.db.database_mut();
+        database.bank_connections.push(connection);
+        for account_id in pending_accounts {
+            database
+                .pending_accounts
+                .insert(account_id, Transactions::new_empty());
+        }
+        Ok(())
+    }
+
+    /// The connection is archived, so its transactions stay in the database
+    pub async fn main_refresh_connection(&self, connection_name: &str) -> Result<()> {
+        let connection = self
+            .db
+            .database()
+            .bank_connections
+            .iter()
+            .find(|c| c.name() == connection_name)
+            .ok_or_else(|| anyhow!("No connection found with name {connection_name}"))?;
+        plaid_api::refresh_transactions(&self.plaid_api, connection.access_token())
+            .await
+            .with_context(|| format!("Failed to refresh connection {connection_name}"))?;
+        terminal::print_status(format!(
+            "Plaid is checking {connection_name} for new transactions. Run `sync` in a few minutes to get them."
+        ));
         Ok(())
     }
 
     pub async fn main_remove_connection(&mut self, connection_name: &str) -> Result<()> {
-        let bank_connections = &mut self.db.database_mut().bank_connections;
-        let index = bank_connections
+        let database = self.db.database_mut();
+        let index = database
+            .bank_connections
             .iter()
             .position(|c| c.name() == connection_name)
             .ok_or_else(|| anyhow!("No connection found with name {connection_name}"))?;
-        let connection = bank_connections.remove(index);
+        let num_unexported: usize = database.bank_connections[index]
+            .accounts()
+            .filter_map(|(account_id, account)| match &account.account {
+                Some(account) => Some(&account.transactions),
+                None => database.pending_accounts.get(account_id),
+            })
+            .map(|transactions| num_unexported(transactions, &database.ignore_list))
+            .sum();
+        let confirmed = if num_unexported > 0 {
+            terminal::confirm_data_loss(&format!(
+                "Connection {connection_name} has {num_unexported} transactions that weren't exported yet. They won't be exported after removing it. Remove it anyways?"
+            ))?
+        } else {
+            terminal::confirm(&format!("Remove connection {connection_name}?"))?
+        };
+        if !confirmed {
+            return Ok(());
+        }
+        let connection = database.bank_connections.remove(index);
+        // Pending accounts were never exported, there is nothing to archive
+        for (account_id, _) in connection.accounts() {
+            database.pending_accounts.remove(account_id);
+        }
         println!();
         println!("{}", style_header("Removed connection:"));
         print_connection(&BulletPointPrinter::new_stdout(), &connection);
+        println!("It's still available with `connection list --archived`.");
+        database.archived.connections.push(ArchivedConnection {
+            archived_on: Local::now().date_naive(),
+            connection,
+        });
+        Ok(())
+    }
+
+    /// The account stays in its connection but isn't synced anymore. Its transactions are archived.
+    pub fn main_disconnect_account(
+        &mut self,
+        connection_name: &str,
+        account_name: &str,
+    ) -> Result<()> {
+        let database = self.db.database_mut();
+        let connection = find_connection_mut(&mut database.bank_connections, connection_name)?;
+        let account_id = find_account_by_name(connection, account_name, true)?;
+        let account = connection
+            .account_mut(&account_id)
+            .expect("We just found this account");
+        let num_unexported = num_unexported(
+            &account
+                .account
+                .as_ref()
+                .expect("We only looked for connected accounts")
+                .transactions,
+            &database.ignore_list,
+        );
+        let confirmed = if num_unexported > 0 {
+            terminal::confirm_data_loss(&format!(
+                "Account {account_name} has {num_unexported} transactions that weren't exported yet. They won't be exported after disabling it. Disable it anyways?"
+            ))?
+        } else {
+            terminal::confirm(&format!("Disable account {account_name}?"))?
+        };
+        if !confirmed {
+            return Ok(());
+        }
+        let connected_account = account
+            .account
+            .take()
+            .expect("We only looked for connected accounts");
+        println!("{}", style_header("Disconnected account:"));
+        BulletPointPrinter::new_stdout().print_item(style(format!(
+            "{} [{}]",
+            account.plaid_account_info.name,
+            connected_account.beancount_account_info.beancount_name()
+        )));
+        println!("It's still available with `connection list --archived`.");
+        let archived_account = ArchivedAccount {
+            archived_on: Local::now().date_naive(),
+            connection_name: connection_name.to_string(),
+            account_id,
+            plaid_account_info: account.plaid_account_info.clone(),
+            account: connected_account,
+        };
+        database.archived.accounts.push(archived_account);
+        Ok(())
+    }
+
+    /// Connect an account that wasn't added to a Beancount account. If it was pending, its synced transactions are released for export.
+    pub fn main_map_account(
+        &mut self,
+        connection_name: &str,
+        account_name: &str,
+        beancount_account: &str,
+    ) -> Result<()> {
+        let beancount_account_info = parse_beancount_account_name(beancount_account)?;
+        let database = self.db.database_mut();
+        let result = connect_account(
+            database,
+            connection_name,
+            account_name,
+            beancount_account_info,
+        )?;
+        let account = find_connection_mut(&mut database.bank_connections, connection_name)?
+            .account(&result.account_id)
+            .expect("We just connected this account");
+        println!("{}", style_header("Mapped account:"));
+        BulletPointPrinter::new_stdout().print_item(style_account(account));
+        println!(
+            "Released {} synced transactions for export.",
+            result.num_released
+        );
+        Ok(())
+    }
+
+    pub fn main_remap_account(
+        &mut self,
+        connection_name: &str,
+        account_name: &str,
+        beancount_account: &str,
+        directives: RenameDirectives,
+    ) -> Result<()> {
+        let new_account = parse_beancount_account_name(beancount_account)?;
+        let rename = remap_account(
+            self.db.database_mut(),
+            connection_name,
+            account_name,
+            new_account,
+            directives,
+            Local::now().date_naive(),
+        )?;
+        println!(
+            "Remapped {account_name} from {} to {} on {}.",
+            rename.old_account.beancount_name(),
+            rename.new_account.beancount_name(),
+            rename.renamed_on,
+        );
+        if directives != RenameDirectives::None {
+            println!("The next `export-new` writes the directives for the new account.");
+        }
+        Ok(())
+    }
+
+    pub fn main_set_amount_sign(
+        &mut self,
+        connection_name: &str,
+        account_name: &str,
+        sign: AmountSign,
+    ) -> Result<()> {
+        let sign_name = match sign {
+            AmountSign::Standard => "standard",
+            AmountSign::Inverted => "inverted",
+        };
+        match set_amount_sign(self.db.database_mut(), connection_name, account_name, sign)? {
+            None => println!("{account_name} already uses the {sign_name} sign."),
+            Some(num_converted) => println!(
+                "{account_name} uses the {sign_name} sign from now on. Converted {num_converted} stored transactions, \
+                the ones that were already exported keep their sign in the ledger."
+            ),
+        }
+        Ok(())
+    }
+
+    pub fn main_set_sync_enabled(
+        &mut self,
+        connection_name: &str,
+        account_name: &str,
+        sync_enabled: bool,
+    ) -> Result<()> {
+        let changed = set_sync_enabled(
+            self.db.database_mut(),
+            connection_name,
+            account_name,
+            sync_enabled,
+        )?;
+        match (changed, sync_enabled) {
+            (false, true) => println!("{account_name} is already synced."),
+            (false, false) => println!("{account_name} is already paused."),
+            (true, true) => println!(
+                "{account_name} is synced again. The next sync downloads all transactions of {connection_name} \
+                again, to get the ones from while it was paused."
+            ),
+            (true, false) => println!(
+                "{account_name} isn't synced anymore. Its transactions stay in the database and are still exported."
+            ),
+        }
+        Ok(())
+    }
+
+    pub async fn main_list_connections(&self, archived: bool) -> Result<()> {
+        if archived {
+            self.print_archived();
+            return Ok(());
+        }
+        println!("{}", style_header("Connections:"));
+        if self.db.database().bank_connections.is_empty() {
+            println!("(none)");
+        } else {
+            let printer = BulletPointPrinter::new_stdout();
+            for connection in &self.db.database().bank_connections {
+                print_connection(&printer, connection);
+            }
+        }
+        self.print_pending_accounts();
+        Ok(())
+    }
+
+    pub fn main_list_accounts(&self) {
+        let rows = self.account_rows();
+        if rows.len() == 1 {
+            println!("No accounts");
+            return;
+        }
+        print_table(&rows);
+    }
+
+    /// The table printed by `account list`, starting with the header
+    fn account_rows(&self) -> Vec<[String; 8]> {
+        let database = self.db.database();
+        let mut rows = vec![[
+            "Connection",
+            "Account",
+            "Mask",
+            "Type",
+            "Beancount account",
+            "Last transaction",
+            "Balance",
+            "Unexported",
+        ]
+        .map(str::to_string)];
+        for connection in &database.bank_connections {
+            for (account_id, account) in connection.accounts() {
+                let info = &account.plaid_account_info;
+                let pending_transactions = database.pending_accounts.get(account_id);
+                let (beancount_account, transactions) =
+                    match (&account.account, pending_transactions) {
+                        (Some(connected_account), _) => {
+                            let mut name =
+                                connected_account.beancount_account_info.beancount_name();
+                            if !connected_account.sync_enabled {
+                                name.push_str(" (paused)");
+                            }
+                            (name, Some(&connected_account.transactions))
+                        }
+                        (None, Some(transactions)) => {
+                            ("(pending mapping)".to_string(), Some(transactions))
+                        }
+                        (None, None) => ("(not connected)".to_string(), None),
+                    };
+                let last_transaction = transactions
+                    .and_then(|transactions| transactions.iter_all_sorted_by_date().last())
+                    .map(|(_, t)| t.transaction.date().to_string());
+                let unexported = transactions.map(|transactions| {
+                    num_unexported(transactions, &database.ignore_list).to_string()
+                });
+                let balance = account
+                    .account
+                    .as_ref()
+                    .and_then(|account| account.balance_snapshots.last())
+                    .and_then(|snapshot| {
+                        let amount = snapshot.current.or(snapshot.available)?;
+                        Some(format!(
+                            "{amount} {}",
+                            snapshot.iso_currency_code.as_deref().unwrap_or("???")
+                        ))
+                    });
+                rows.push([
+                    connection.name().to_string(),
+                    info.name.clone(),
+                    info.mask
+                        .as_ref()
+                        .map(|mask| format!("***{mask}"))
+                        .unwrap_or_default(),
+                    match &info.subtype {
+                        Some(subtype) => format!("{}/{subtype}", info.type_),
+                        None => info.type_.clone(),
+                    },
+                    beancount_account,
+                    last_transaction.unwrap_or_else(|| "-".to_string()),
+                    balance.unwrap_or_else(|| "-".to_string()),
+                    unexported.unwrap_or_else(|| "-".to_string()),
+                ]);
+            }
+        }
+        rows
+    }
+
+    fn print_pending_accounts(&self) {
+        let database = self.db.database();
+        if database.pending_accounts.is_empty() {
+            return;
+        }
+        println!();
+        println!("{}", style_header("Pending mapping:"));
+        let printer = BulletPointPrinter::new_stdout();
+        for connection in &database.bank_connections {
+            for (account_id, account) in connection.accounts() {
+                if let Some(transactions) = database.pending_accounts.get(account_id) {
+                    printer.print_item(style(format!(
+                        "{}: {} ({} transactions)",
+                        connection.name(),
+                        account.plaid_account_info.name,
+                        transactions.len(),
+                    )));
+                }
+            }
+        }
+        println!("Assign a Beancount account with `account connect` to export their transactions.");
+    }
+
+    fn print_archived(&self) {
+        let archived = &self.db.database().archived;
+        println!("{}", style_header("Archived:"));
+        if archived.is_empty() {
+            println!("(none)");
+            return;
+        }
+        let printer = BulletPointPrinter::new_stdout();
+        for archived_connection in &archived.connections {
+            let connection = &archived_connection.connection;
+            printer.print_item(style(format!(
+                "{} {}",
+                style_connection(connection),
+                style(format!("(archived on {})", archived_connection.archived_on)).dim()
+            )));
+            let printer = printer.indent();
+            for (_, account) in connection.accounts() {
+                print_archived_account(&printer, account);
+            }
+        }
+        for archived_account in &archived.accounts {
+            printer.print_item(style(format!(
+                "{} / {} {}",
+                style(&archived_account.connection_name).cyan().bold(),
+                archived_account.plaid_account_info.name,
+                style(format!("(archived on {})", archived_account.archived_on)).dim()
+            )));
+            print_archived_transactions(&printer.indent(), &archived_account.account);
+        }
+    }
+
+    /// Sync all connections, or only `connection_name` and, if given, only its account `account`.
+    /// If a connection fails, `on_error` decides whether the other connections are kept, see [OnError].
+    pub async fn main_sync(
+        &mut self,
+        connection_name: Option<&str>,
+        account: Option<&str>,
+        timezone: Timezone,
+        on_error: OnError,
+    ) -> Result<()> {
+        self.db.begin();
+        match self
+            .sync_connections(connection_name, account, timezone, on_error)
+            .await
+        {
+            Ok(failed) => {
+                self.db.commit();
+                let mut failed = failed.into_iter();
+                match failed.next() {
+                    None => Ok(()),
+                    Some(first) => {
+                        for err in failed {
+                            eprintln!("{}", style(format!("{err:#}")).red().bold());
+                        }
+                        Err(first.context(
+                            "Not all connections could be synced, the ones that were synced are kept",
+                        ))
+                    }
+                }
+            }
+            Err(err) => {
+                self.db.rollback();
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns the errors of the connections that failed with [OnError::KeepCompleted], they're left as they were.
+    /// With [OnError::Rollback], the first failure is returned as error.
+    async fn sync_connections(
+        &mut self,
+        connection_name: Option<&str>,
+        account: Option<&str>,
+        timezone: Timezone,
+        on_error: OnError,
+    ) -> Result<Vec<anyhow::Error>> {
+        if let Some(connection_name) = connection_name {
+            if !self
+                .db
+                .database()
+                .bank_connections
+                .iter()
+                .any(|c| c.name() == connection_name)
+            {
+                bail!("No connection found with name {connection_name}");
+            }
+        }
+        let only_account = match (connection_name, account) {
+            (Some(connection_name), Some(account)) => Some(find_sync_account(
+                self.db.database(),
+                connection_name,
+                account,
+            )?),
+            _ => None,
+        };
+        terminal::print_status(style_header("Syncing connections:"));
+        let progress = terminal::progress();
+        // Printing to a hidden MultiProgress is a no-op, so this also silences the per-account results
+        if terminal::is_quiet() {
+            progress.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        let printer = BulletPointPrinter::new_multiprogress(progress);
+        let database = self.db.database_mut();
+        // Each connection gets its own pending accounts so the connections can be synced concurrently
+        let mut pending_accounts: Vec<HashMap<AccountId, Transactions>> = database
+            .bank_connections
+            .iter()
+            .map(|connection| {
+                connection
+                    .accounts()
+                    .filter_map(|(account_id, _)| {
+                        database.pending_accounts.remove_entry(account_id)
+                    })
+                    .collect()
+            })
+            .collect();
+        let amount_signs = &database.amount_signs;
+        let mut sync_results: FuturesUnordered<_> = database
+            .bank_connections
+            .iter_mut()
+            .zip(pending_accounts.iter_mut())
+            .filter(|(connection, _)| connection_name.is_none_or(|name| connection.name() == name))
+            .map(|(connection, pending_accounts)| async {
+                let backup = (on_error == OnError::KeepCompleted)
+                    .then(|| (connection.clone(), pending_accounts.clone()));
+                let pb = terminal::add_progress_bar(
+                    ProgressBar::new_spinner().with_message(connection.name().to_string()),
+                );
+                pb.enable_steady_tick(Duration::from_millis(50));
+                let name = connection.name().to_string();
+                let span = tracing::info_span!("connection", name);
+                let sync_result = sync_connection(
+                    &self.plaid_api,
+                    connection,
+                    pending_accounts,
+                    amount_signs,
+                    only_account.as_ref(),
+                    timezone,
+                    &self.cancel,
+                    |num_processed| {
+                        pb.set_message(format!("{name} ({num_processed} transactions)"))
+                    },
+                )
+                .instrument(span)
+                .await
+                .with_context(|| format!("Failed to sync connection {name}"));
+                pb.finish_and_clear();
+                let sync_result = match sync_result {
+                    Ok(sync_result) => sync_result,
+                    Err(err) => {
+                        if let Some((connection_backup, pending_accounts_backup)) = backup {
+                            *connection = connection_backup;
+                            *pending_accounts = pending_accounts_backup;
+                        }
+                        return Err(err);
+                    }
+                };
+
+                Ok::<(&mut BankConnection, SyncReport), anyhow::Error>((connection, sync_result))
+            })
+            .collect();
+        let mut total_num_added = 0;
+        let mut total_num_verified = 0;
+        let mut total_num_ignored = 0;
+        let mut total_num_pending = 0;
+        let mut mismatches = vec![];
+        let mut balance_mismatches = vec![];
+        let mut account_changes = vec![];
+        let mut unknown_categories = HashSet::new();
+        let mut failed = vec![];
+        let today = Local::now().date_naive();
+        while let Some(sync_result) = sync_results.next().await {
+            let (connection, mut sync_result) = match sync_result {
+                Ok(sync_result) => sync_result,
+                Err(err) if on_error == OnError::KeepCompleted => {
+                    failed.push(err);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            mismatches.append(&mut sync_result.mismatches);
+            database
+                .transaction_times
+                .extend(sync_result.transaction_times.drain());
+            balance_mismatches.extend(reconcile_balances(
+                &mut database.balance_anchors,
+                connection,
+                &sync_result,
+                today,
+            ));
+            record_balance_snapshots(connection, &sync_result, today);
+            account_changes.extend(
+                sync_result
+                    .account_changes
+                    .drain(..)
+                    .map(|change| (connection.name().to_string(), change)),
+            );
+            unknown_categories.extend(sync_result.unknown_categories.drain());
+            printer.print_item(style_connection(connection));
+            let printer = printer.indent();
+            for (account_id, sync_result) in sync_result.account_results {
+                let account = connection.account(&account_id).unwrap();
+
+                printer.print_item(style_account(&account));
+                let printer = printer.indent();
+                if sync_result.pending {
+                    printer.print_item(
+                        style(format!("Added pending mapping: {}", sync_result.num_added)).italic(),
+                    );
+                    total_num_pending += sync_result.num_added;
+                } else if account.is_synced() {
+                    printer.print_item(style(format!("Added: {}", sync_result.num_added)).italic());
+                    printer.print_item(
+                        style(format!("Verified: {}", sync_result.num_verified)).italic(),
+                    );
+                    total_num_added += sync_result.num_added;
+                    total_num_verified += sync_result.num_verified;
+                } else {
+                    printer.print_item(
+                        style(format!("Ignored: {}", sync_result.num_added))
+                            .italic()
+                            .strikethrough(),
+                    );
+                    total_num_ignored += sync_result.num_added;
+                }
+            }
+        }
+        drop(sync_results);
+        database
+            .pending_accounts
+            .extend(pending_accounts.into_iter().flatten());
+        progress.clear()?;
+        if !terminal::is_quiet() {
+            println!();
+            println!();
+            println!("{}", style_header("Totals:"));
+            println!("{}", style(format!("Added: {}", total_num_added)).italic());
+            println!(
+                "{}",
+                style(format!("Verified: {}", total_num_verified)).italic()
+            );
+            if total_num_pending > 0 {
+                println!(
+                    "{}",
+                    style(format!(
+                        "Added pending mapping: {} (export them after `account connect`)",
+                        total_num_pending
+                    ))
+                    .italic()
+                );
+            }
+            if total_num_ignored > 0 {
+                println!(
+                    "{}",
+                    style(format!("Ignored: {}", total_num_ignored))
+                        .italic()
+                        .strikethrough()
+                );
+            }
+        }
+        if !account_changes.is_empty() {
+            println!();
+            println!("{}", style_header("Changed accounts:"));
+            for (connection_name, change) in &account_changes {
+                self.print_account_change(connection_name, change);
+            }
+        }
+        let unknown_categories = plaid_api::unknown_categories(&unknown_categories);
+        if !unknown_categories.is_empty() {
+            println!();
+            println!("{}", style_header("Unknown categories:"));
+            for category in &unknown_categories {
+                println!("{}.{}", category.primary, category.detailed);
+            }
+            println!("{}", style(UNKNOWN_CATEGORIES_HINT).italic());
+        }
+        let duplicates = flag_duplicates(self.db.database_mut());
+        if !duplicates.is_empty() {
+            println!();
+            println!("{}", style_header("Suspected duplicates:"));
+            for duplicate in &duplicates {
+                println!(
+                    "{} looks like a reissue of {}",
+                    duplicate.transaction_id.0, duplicate.original.0
+                );
+            }
+            println!(
+                "{}",
+                style("They aren't exported until they're reviewed with `transaction duplicates`")
+                    .italic()
+            );
+        }
+        if !mismatches.is_empty() {
+            println!();
+            println!("{}", style_header("Changed transactions:"));
+            for mismatch in mismatches {
+                self.resolve_mismatch(mismatch)?;
+            }
+        }
+        if !balance_mismatches.is_empty() {
+            println!();
+            println!("{}", style_header("Balance differences:"));
+            for mismatch in balance_mismatches {
+                self.resolve_balance_mismatch(mismatch)?;
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Plaid reports a new type, subtype or mask for an account, e.g. because the card was reissued. The stored info is
+    /// already updated, but the Beancount account may not fit anymore, so this is hard to miss.
+    fn print_account_change(&self, connection_name: &str, change: &AccountChange) {
+        let Some(account) = self
+            .db
+            .database()
+            .bank_connections
+            .iter()
+            .find_map(|connection| connection.account(&change.account_id))
+        else {
+            return;
+        };
+        let show = |value: Option<&str>| value.unwrap_or("none").to_string();
+        let mut changed_fields = vec![];
+        if change.old.type_ != change.new.type_ {
+            changed_fields.push(format!("type {} → {}", change.old.type_, change.new.type_));
+        }
+        if change.old.subtype != change.new.subtype {
+            changed_fields.push(format!(
+                "subtype {} → {}",
+                show(change.old.subtype.as_deref()),
+                show(change.new.subtype.as_deref())
+            ));
+        }
+        if change.old.mask != change.new.mask {
+            changed_fields.push(format!(
+                "mask {} → {}",
+                show(change.old.mask.as_deref()),
+                show(change.new.mask.as_deref())
+            ));
+        }
+        println!();
+        println!(
+            "{} of connection {connection_name}:",
+            style_account(account)
+        );
+        println!(
+            "{}",
+            style(format!(
+                "Plaid reports a changed account: {}",
+                changed_fields.join(", ")
+            ))
+            .yellow()
+            .bold()
+        );
+        if account.is_connected() {
+            println!(
+                "{}",
+                style(format!(
+                    "The card may have been reissued or the account migrated. Check that its transactions still belong \
+                    to this Beancount account, or export them to another one with `account remap -c {connection_name} -a \"{}\"`",
+                    account.plaid_account_info.name
+                ))
+                .italic()
+            );
+        }
+    }
+
+    /// Plaid's balance of an account doesn't match the transactions we got, so Plaid probably didn't send some of them.
+    /// Suggest how to fix the ledger and let the user accept Plaid's balance once it's fixed.
+    fn resolve_balance_mismatch(&mut self, mismatch: BalanceMismatch) -> Result<()> {
+        let database = self.db.database_mut();
+        let Some((connection, account)) = database.bank_connections.iter().find_map(|connection| {
+            connection
+                .account(&mismatch.account_id)
+                .map(|account| (connection, account))
+        }) else {
+            return Ok(());
+        };
+        let Some(connected_account) = &account.account else {
+            return Ok(());
+        };
+        let currency = mismatch.iso_currency_code.as_deref().unwrap_or("");
+        println!();
+        println!(
+            "{} of connection {}:",
+            style_account(account),
+            connection.name()
+        );
+        println!(
+            "Plaid's balance is {} {currency}, but the transactions since {} add up to {} {currency}, a difference of {} {currency}.",
+            mismatch.actual,
+            mismatch.since,
+            mismatch.expected,
+            mismatch.actual - mismatch.expected,
+        );
+        println!(
+            "{}",
+            style(format!(
+                "Plaid may not have sent some transactions. Run `connection refresh -c {}` and `sync` again in a few minutes, \
+                or add the missing transactions by hand with a balance assertion:",
+                connection.name()
+            ))
+            .italic()
+        );
+        // Balance assertions apply at the start of the day, so the balance at the end of today is asserted tomorrow
+        println!(
+            "  {} balance {} {} {currency}",
+            mismatch.date.succ_opt().unwrap_or(mismatch.date),
+            connected_account.beancount_account_info.beancount_name(),
+            mismatch.actual,
+        );
+        if !console::user_attended() {
+            return Ok(());
+        }
+        if terminal::confirm(
+            "Accept Plaid's balance, e.g. because the missing transactions were added by hand?",
+        )? {
+            reset_balance_anchor(&mut database.balance_anchors, &mismatch);
+        }
+        Ok(())
+    }
+
+    /// Plaid sometimes changes transactions it already sent us, e.g. when a pending transaction posts.
+    /// Let the user decide which version to keep.
+    fn resolve_mismatch(&mut self, mismatch: Mismatch) -> Result<()> {
+        let Mismatch {
+            transaction_id,
+            existing_value,
+            new_value,
+            ..
+        } = &mismatch;
+        println!();
+        println!(
+            "Transaction {} changed since it was synced:",
+            transaction_id.0
+        );
+        print_transaction_diff(&existing_value.transaction, &new_value.transaction);
+        if !console::user_attended() {
+            bail!(
+                "Transaction {} already exists but doesn't match. Run `sync` in a terminal to decide which version to keep.",
+                transaction_id.0
+            );
+        }
+        if existing_value.already_exported {
+            println!(
+                "{}",
+                style("It was already exported. Replacing it won't export it again.").italic()
+            );
+        }
+        let replace = terminal::prompt_select(
+            "Which version do you want to keep?",
+            &[
+                "Keep the stored version",
+                "Replace it with the synced version",
+            ],
+            0,
+        )? == 1;
+        if replace {
+            replace_transaction(self.db.database_mut(), mismatch)?;
+        }
+        Ok(())
+    }
+
+    /// With `only_ignored`, only lists the transactions that are ignored and shows their ids so they can be un-ignored
+    pub async fn main_list_transactions(
+        &mut self,
+        filter: TransactionFilter,
+        query: &TransactionQuery,
+    ) -> Result<()> {
+        let database = self.db.database();
+        let ignore_list = &database.ignore_list;
+        let overrides = &database.transaction_overrides;
+        let is_categorized = |id: &TransactionId| {
+            overrides
+                .get(id)
+                .is_some_and(|overrides| overrides.account.is_some())
+        };
+        let print_transactions =
+            |printer: &BulletPointPrinter<_>, transactions: Vec<(&TransactionId, &Transaction)>| {
+                let transactions: Vec<_> = transactions
+                    .into_iter()
+                    .filter(|(id, t)| query.matches_transaction(t, overrides.get(*id)))
+                    .map(|(id, t)| (id, t, ignore_list.is_ignored(id, &t.transaction)))
+                    .filter(|(id, t, ignored)| match filter {
+                        TransactionFilter::All => true,
+                        TransactionFilter::Ignored => *ignored,
+                        TransactionFilter::Uncategorized => {
+                            !*ignored && !t.already_exported && !is_categorized(id)
+                        }
+                    })
+                    .collect();
+                if transactions.is_empty() {
+                    printer.print_item(style("(none)").italic());
+                }
+                for (transaction_id, transaction, ignored) in transactions {
+                    print_transaction(printer, transaction, ignored);
+                    if filter != TransactionFilter::All {
+                        printer
+                            .indent()
+                            .print_item(style(format!("Id: {}", transaction_id.0)).dim());
+                    }
+                }
+            };
+
+        match filter {
+            TransactionFilter::All => println!("{}", style_header("Transactions:")),
+            TransactionFilter::Ignored => println!("{}", style_header("Ignored transactions:")),
+            TransactionFilter::Uncategorized => {
+                println!("{}", style_header("Uncategorized transactions:"))
+            }
+        }
+        let printer = BulletPointPrinter::new_stdout();
+        for connection in &database.bank_connections {
+            if !query.matches_connection(connection.name()) {
+                continue;
+            }
+            printer.print_item(style_connection(connection));
+            let printer = printer.indent();
+            for account in connection.accounts() {
+                if !query.matches_account(account.1) {
+                    continue;
+                }
+                if let Some(connected_account) = &account.1.account {
+                    printer.print_item(style_account(account.1));
+                    let transactions = &connected_account.transactions;
+                    if transactions.is_empty() {
+                        printer.indent().print_item(style("(none)").italic());
+                    } else {
+                        print_transactions(
+                            &printer.indent(),
+                            transactions.iter_all_sorted_by_date().collect(),
+                        );
+                    }
+                } else {
+                    printer.print_item(style_account(&account.1).strikethrough());
+                }
+            }
+        }
+        let mut manual_transactions: Vec<_> = database
+            .manual_transactions
+            .iter()
+            .filter(|(id, t)| {
+                query.connection.is_none()
+                    && query.matches_beancount_account(&t.beancount_account_info)
+                    && query.matches_transaction(&t.transaction, overrides.get(*id))
+            })
+            .collect();
+        if !manual_transactions.is_empty() {
+            printer.print_item(style("Manually entered").cyan().bold());
+            let printer = printer.indent();
+            manual_transactions.sort_by_key(|(id, t)| (t.transaction.transaction.date(), *id));
+            for (transaction_id, transaction) in manual_transactions {
+                let account = transaction.beancount_account_info.beancount_name();
+                printer.print_item(style(format!("[{account}]")).green());
+                print_transactions(
+                    &printer.indent(),
+                    vec![(transaction_id, &transaction.transaction)],
+                );
+            }
+        }
+        if filter == TransactionFilter::All && !ignore_list.rules().is_empty() {
+            println!();
+            println!("{}", style_header("Ignore rules:"));
+            for rule in ignore_list.rules() {
+                printer.print_item(style(format!("Matching \"{}\"", rule.pattern)).italic());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn main_search(&self, query: &str, regex: bool) -> Result<()> {
+        let pattern = if regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+            .with_context(|| format!("Invalid regular expression {query}"))?;
+        let matches = |transaction: &TransactionInfo| {
+            [
+                &transaction.merchant_name,
+                &transaction.original_description,
+                &transaction.description_or_merchant_name,
+                &transaction.associated_website,
+            ]
+            .into_iter()
+            .flatten()
+            .any(|field| pattern.is_match(field))
+        };
+
+        let database = self.db.database();
+        let print_matches =
+            |printer: &BulletPointPrinter<_>, transactions: Vec<(&TransactionId, &Transaction)>| {
+                for (transaction_id, transaction) in transactions {
+                    let ignored = database
+                        .ignore_list
+                        .is_ignored(transaction_id, &transaction.transaction);
+                    print_transaction(printer, transaction, ignored);
+                    printer
+                        .indent()
+                        .print_item(style(format!("Id: {}", transaction_id.0)).dim());
+                }
+            };
+
+        println!("{}", style_header("Matching transactions:"));
+        let printer = BulletPointPrinter::new_stdout();
+        let mut num_matches = 0;
+        for connection in &database.bank_connections {
+            let accounts: Vec<_> = connection
+                .accounts()
+                .filter_map(|(_, account)| {
+                    let connected_account = account.account.as_ref()?;
+                    let transactions: Vec<_> = connected_account
+                        .transactions
+                        .iter_all_sorted_by_date()
+                        .filter(|(_, t)| matches(&t.transaction))
+                        .collect();
+                    (!transactions.is_empty()).then_some((account, transactions))
+                })
+                .collect();
+            if accounts.is_empty() {
+                continue;
+            }
+            printer.print_item(style_connection(connection));
+            let printer = printer.indent();
+            for (account, transactions) in accounts {
+                printer.print_item(style_account(account));
+                num_matches += transactions.len();
+                print_matches(&printer.indent(), transactions);
+            }
+        }
+        let mut manual_transactions: Vec<_> = database
+            .manual_transactions
+            .iter()
+            .filter(|(_, t)| matches(&t.transaction.transaction))
+            .collect();
+        if !manual_transactions.is_empty() {
+            printer.print_item(style("Manually entered").cyan().bold());
+            let printer = printer.indent();
+            manual_transactions.sort_by_key(|(id, t)| (t.transaction.transaction.date(), *id));
+            for (transaction_id, transaction) in manual_transactions {
+                let account = transaction.beancount_account_info.beancount_name();
+                printer.print_item(style(format!("[{account}]")).green());
+                num_matches += 1;
+                print_matches(
+                    &printer.indent(),
+                    vec![(transaction_id, &transaction.transaction)],
+                );
+            }
+        }
+        if num_matches == 0 {
+            println!("(none)");
+        }
+        Ok(())
+    }
+
+    pub fn main_ignore(
+        &mut self,
+        transaction_id: Option<TransactionId>,
+        matching: Option<String>,
+    ) -> Result<()> {
+        match (transaction_id, matching) {
+            (Some(transaction_id), None) => {
+                if !self.transaction_exists(&transaction_id) {
+                    bail!("Transaction {} not found", transaction_id.0);
+                }
+                let database = self.db.database_mut();
+                if !database
+                    .ignore_list
+                    .ignore_transaction(transaction_id.clone())
+                {
+                    bail!("Transaction {} is already ignored", transaction_id.0);
+                }
+                database.duplicates.remove(&transaction_id);
+                println!("Ignoring transaction {}", transaction_id.0);
+            }
+            (None, Some(pattern)) => {
+                let ignore_list = &mut self.db.database_mut().ignore_list;
+                if !ignore_list.add_rule(IgnoreRule {
+                    pattern: pattern.clone(),
+                }) {
+                    bail!("There already is a rule ignoring transactions matching \"{pattern}\"");
+                }
+                println!("Ignoring transactions matching \"{pattern}\"");
+            }
+            _ => bail!("Please pass either a transaction id or --matching"),
+        }
+        Ok(())
+    }
+
+    pub fn main_unignore(
+        &mut self,
+        transaction_id: Option<TransactionId>,
+        matching: Option<String>,
+    ) -> Result<()> {
+        let ignore_list = &mut self.db.database_mut().ignore_list;
+        match (transaction_id, matching) {
+            (Some(transaction_id), None) => {
+                if !ignore_list.unignore_transaction(&transaction_id) {
+                    bail!("Transaction {} isn't ignored by id", transaction_id.0);
+                }
+                println!("Not ignoring transaction {} anymore", transaction_id.0);
+            }
+            (None, Some(pattern)) => {
+                if !ignore_list.remove_rule(&pattern) {
+                    bail!("There is no rule ignoring transactions matching \"{pattern}\"");
+                }
+                println!("Not ignoring transactions matching \"{pattern}\" anymore");
+            }
+            _ => bail!("Please pass either a transaction id or --matching"),
+        }
+        Ok(())
+    }
+
+    pub fn main_add_target(
+        &mut self,
+        name: String,
+        output_file: Option<PathBuf>,
+        operating_currency: Option<String>,
+    ) -> Result<()> {
+        let target = LedgerTarget {
+            output_file,
+            operating_currency,
+            ..Default::default()
+        };
+        if !self
+            .db
+            .database_mut()
+            .ledger_targets
+            .add(name.clone(), target)
+        {
+            bail!("There already is a ledger target with name {name}");
+        }
+        println!("Added ledger target {name}. Assign connections or accounts to it with `target assign`.");
+        Ok(())
+    }
+
+    pub fn main_list_targets(&self) {
+        let database = self.db.database();
+        println!("{}", style_header("Ledger targets:"));
+        if database.ledger_targets.iter().next().is_none() {
+            println!("(none)");
+            return;
+        }
+        let printer = BulletPointPrinter::new_stdout();
+        for (name, target) in database.ledger_targets.iter() {
+            printer.print_item(style(name).bold());
+            let printer = printer.indent();
+            if let Some(output_file) = &target.output_file {
+                printer.print_item(format!("Output file: {}", output_file.display()));
+            }
+            if let Some(operating_currency) = &target.operating_currency {
+                printer.print_item(format!("Operating currency: {operating_currency}"));
+            }
+            for connection_name in &target.connections {
+                printer.print_item(format!("Connection: {connection_name}"));
+            }
+            for connection in &database.bank_connections {
+                for (account_id, account) in connection.accounts() {
+                    if target.accounts.contains(account_id) {
+                        printer.print_item(format!(
+                            "Account: {} / {}",
+                            connection.name(),
+                            account.plaid_account_info.name
+                        ));
+                    }
+                }
+            }
+            for rule in &target.ignore_rules {
+                printer.print_item(format!(
+                    "Ignoring transactions matching \"{}\"",
+                    rule.pattern
+                ));
+            }
+        }
+    }
+
+    pub fn main_remove_target(&mut self, name: &str) -> Result<()> {
+        if self.db.database_mut().ledger_targets.remove(name).is_none() {
+            bail!("No ledger target found with name {name}");
+        }
+        println!("Removed ledger target {name}. Its connections and accounts are exported without `--target` again.");
+        Ok(())
+    }
+
+    /// Assign the connection or one of its accounts to the given target, or to the default target if `None`
+    pub fn main_assign_target(
+        &mut self,
+        target_name: Option<&str>,
+        connection_name: &str,
+        account_name: Option<&str>,
+    ) -> Result<()> {
+        let database = self.db.database_mut();
+        if let Some(target_name) = target_name {
+            if database.ledger_targets.get(target_name).is_none() {
+                bail!("No ledger target found with name {target_name}");
+            }
+        }
+        let connection = database
+            .bank_connections
+            .iter()
+            .find(|c| c.name() == connection_name)
+            .ok_or_else(|| anyhow!("No connection found with name {connection_name}"))?;
+        let account_id = match account_name {
+            Some(account_name) => {
+                let matching_accounts: Vec<&AccountId> = connection
+                    .accounts()
+                    .filter(|(_, account)| account.plaid_account_info.name == account_name)
+                    .map(|(account_id, _)| account_id)
+                    .collect();
+                match matching_accounts.as_slice() {
+                    [account_id] => Some((*account_id).clone()),
+                    [] => bail!("No account found with name {account_name}"),
+                    _ => bail!("There are multiple accounts with name {account_name}"),
+                }
+            }
+            None => None,
+        };
+        database
+            .ledger_targets
+            .assign(target_name, connection_name, account_id.as_ref());
+        let what = match account_name {
+            Some(account_name) => format!("account {account_name} of {connection_name}"),
+            None => format!("connection {connection_name}"),
+        };
+        match target_name {
+            Some(target_name) => {
+                println!("Transactions of {what} are exported with `--target {target_name}` now")
+            }
+            None => println!("Transactions of {what} are exported without `--target` now"),
+        }
+        Ok(())
+    }
+
+    pub fn main_target_ignore(&mut self, name: &str, pattern: String) -> Result<()> {
+        let target = self
+            .db
+            .database_mut()
+            .ledger_targets
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("No ledger target found with name {name}"))?;
+        let rule = IgnoreRule { pattern };
+        if target.ignore_rules.contains(&rule) {
+            bail!(
+                "Ledger target {name} already ignores transactions matching \"{}\"",
+                rule.pattern
+            );
+        }
+        println!(
+            "Not exporting transactions matching \"{}\" to ledger target {name}",
+            rule.pattern
+        );
+        target.ignore_rules.push(rule);
+        Ok(())
+    }
+
+    fn transaction_exists(&self, transaction_id: &TransactionId) -> bool {
+        self.find_transaction(transaction_id).is_some()
+            || self
+                .db
+                .database()
+                .manual_transactions
+                .contains_key(transaction_id)
+    }
+
+    /// Find a synced transaction with the Beancount account it's exported to
+    fn find_transaction(
+        &self,
+        transaction_id: &TransactionId,
+    ) -> Option<(&BeancountAccountInfo, &Transaction)> {
+        self.db
+            .database()
+            .bank_connections
+            .iter()
+            .flat_map(|connection| connection.accounts())
+            .filter_map(|(_, account)| account.account.as_ref())
+            .find_map(|account| {
+                account
+                    .transactions
+                    .get(transaction_id)
+                    .map(|transaction| (&account.beancount_account_info, transaction))
+            })
+    }
+
+    pub fn main_list_duplicates(&self) -> Result<()> {
+        let mut duplicates: Vec<_> = self.db.database().duplicates.suspected().collect();
+        if duplicates.is_empty() {
+            println!("No suspected duplicates");
+            return Ok(());
+        }
+        duplicates.sort();
+        println!("{}", style_header("Suspected duplicates:"));
+        let printer = BulletPointPrinter::new_stdout();
+        for (transaction_id, original) in duplicates {
+            for (label, id) in [("Duplicate", transaction_id), ("Original", original)] {
+                let Some((account, transaction)) = self.find_transaction(id) else {
+                    printer.print_item(style(format!("{label} {} not found", id.0)).dim());
+                    continue;
+                };
+                printer.print_item(format!(
+                    "{label} {} in {}",
+                    id.0,
+                    style(account.beancount_name()).cyan().bold()
+                ));
+                print_transaction(&printer.indent(), transaction, false);
+            }
+        }
+        println!(
+            "{}",
+            style("Export them with `transaction keep <id>` or drop them with `transaction ignore <id>`")
+                .italic()
+        );
+        Ok(())
+    }
+
+    pub fn main_keep(&mut self, transaction_id: TransactionId) -> Result<()> {
+        if !self.db.database_mut().duplicates.keep(&transaction_id) {
+            bail!(
+                "Transaction {} isn't a suspected duplicate",
+                transaction_id.0
+            );
+        }
+        println!(
+            "Keeping transaction {}, the next export includes it",
+            transaction_id.0
+        );
+        Ok(())
+    }
+
+    pub fn main_annotate(
+        &mut self,
+        transaction_id: TransactionId,
+        note: Option<String>,
+        account: Option<String>,
+        payee: Option<String>,
+        clear: bool,
+    ) -> Result<()> {
+        if !self.transaction_exists(&transaction_id) {
+            bail!("Transaction {} not found", transaction_id.0);
+        }
+        let account = account
+            .map(|account| parse_beancount_account_name(&account))
+            .transpose()?;
+
+        let transaction_overrides = &mut self.db.database_mut().transaction_overrides;
+        if clear {
+            transaction_overrides.remove(&transaction_id);
+            println!("Removed corrections of transaction {}", transaction_id.0);
+            return Ok(());
+        }
+        let overrides = transaction_overrides
+            .entry(transaction_id.clone())
+            .or_default();
+        if note.is_some() {
+            overrides.note = note;
+        }
+        if account.is_some() {
+            overrides.account = account;
+        }
+        if payee.is_some() {
+            overrides.payee = payee;
+        }
+        if overrides.is_empty() {
+            transaction_overrides.remove(&transaction_id);
+            bail!("Nothing to annotate. Please pass --note, --account or --payee.");
+        }
+        println!("Annotated transaction {}", transaction_id.0);
         Ok(())
     }
 
-    pub async fn main_list_connections(&self) -> Result<()> {
-        println!("{}", style_header("Connections:"));
-        if self.db.database().bank_connections.is_empty() {
-            println!("(none)");
-        } else {
-            let printer = BulletPointPrinter::new_stdout();
-            for connection in &self.db.database().bank_connections {
-                print_connection(&printer, connection);
-            }
-        }
+    pub fn main_recategorize(
+        &mut self,
+        transaction_id: TransactionId,
+        category_or_account: &str,
+    ) -> Result<()> {
+        self.recategorize(&transaction_id, category_or_account)?;
+        println!(
+            "Recategorized transaction {} as {category_or_account}",
+            transaction_id.0
+        );
         Ok(())
     }
 
-    pub async fn main_sync(&mut self) -> Result<()> {
-        println!("{}", style_header("Syncing connections:"));
-        let progress = MultiProgress::new();
-        let printer = BulletPointPrinter::new_multiprogress(&progress);
-        let mut sync_results: FuturesUnordered<_> = self
+    fn recategorize(
+        &mut self,
+        transaction_id: &TransactionId,
+        category_or_account: &str,
+    ) -> Result<()> {
+        if !self.transaction_exists(transaction_id) {
+            bail!("Transaction {} not found", transaction_id.0);
+        }
+        let overrides = self
             .db
             .database_mut()
-            .bank_connections
-            .iter_mut()
-            .map(|connection| async {
-                let pb = progress
-                    .add(ProgressBar::new_spinner().with_message(connection.name().to_string()));
-                pb.enable_steady_tick(Duration::from_millis(50));
-                let sync_result = Self::sync_connection(&self.plaid_api, connection).await?;
-                pb.finish_and_clear();
+            .transaction_overrides
+            .entry(transaction_id.clone())
+            .or_default();
+        if category_or_account.contains(':') {
+            let account = parse_beancount_account_name(category_or_account)?;
+            overrides.account = Some(account);
+        } else {
+            let Some((primary, detailed)) = category_or_account.split_once('.') else {
+                bail!("{category_or_account} is neither a Beancount account like Expenses:Groceries nor a Plaid category like FOOD_AND_DRINK.FOOD_AND_DRINK_GROCERIES");
+            };
+            overrides.category = Some(TransactionCategory {
+                primary: primary.to_string(),
+                detailed: detailed.to_string(),
+            });
+        }
+        Ok(())
+    }
 
-                Ok::<(&mut BankConnection, SyncConnectionResult), anyhow::Error>((
-                    connection,
-                    sync_result,
-                ))
-            })
-            .collect();
-        let mut total_num_added = 0;
-        let mut total_num_verified = 0;
-        let mut total_num_ignored = 0;
-        while let Some(sync_result) = sync_results.next().await {
-            let (connection, sync_result) = sync_result?;
-            printer.print_item(style_connection(connection));
-            let printer = printer.indent();
-            for (account_id, sync_result) in sync_result.account_results {
-                let account = connection.account(&account_id).unwrap();
+    pub fn main_add_transaction(
+        &mut self,
+        date: NaiveDate,
+        amount: Decimal,
+        currency: String,
+        account: &str,
+        narration: String,
+        payee: Option<String>,
+    ) -> Result<()> {
+        let beancount_account_info = parse_beancount_account_name(account)?;
+        let transaction = Transaction::new(TransactionInfo {
+            posted_date: date,
+            authorized_date: None,
+            category: None,
+            amount: Amount {
+                amount,
+                iso_currency_code: Some(currency),
+            },
+            merchant_name: payee,
+            description_or_merchant_name: Some(narration),
+            original_description: None,
+            transaction_type: None,
+            location: None,
+            check_number: None,
+            associated_website: None,
+        });
+        println!("{}", style_header("Adding transaction:"));
+        let printer = BulletPointPrinter::new_stdout();
+        printer.print_item(style(beancount_account_info.beancount_name()).cyan().bold());
+        print_transaction(&printer.indent(), &transaction, false);
 
-                printer.print_item(style_account(&account));
-                let printer = printer.indent();
-                if account.is_connected() {
-                    printer.print_item(style(format!("Added: {}", sync_result.num_added)).italic());
-                    printer.print_item(
-                        style(format!("Verified: {}", sync_result.num_verified)).italic(),
-                    );
-                    total_num_added += sync_result.num_added;
-                    total_num_verified += sync_result.num_verified;
-                } else {
-                    printer.print_item(
-                        style(format!("Ignored: {}", sync_result.num_added))
-                            .italic()
-                            .strikethrough(),
-                    );
-                    total_num_ignored += sync_result.num_added;
-                }
-            }
-        }
-        progress.clear()?;
-        println!();
-        println!();
-        println!("{}", style_header("Totals:"));
-        println!("{}", style(format!("Added: {}", total_num_added)).italic());
-        println!(
-            "{}",
-            style(format!("Verified: {}", total_num_verified)).italic()
+        let transaction_id = TransactionId::new_manual();
+        println!("Transaction id: {}", transaction_id.0);
+        self.db.database_mut().manual_transactions.insert(
+            transaction_id,
+            ManualTransaction {
+                beancount_account_info,
+                transaction,
+            },
         );
-        if total_num_ignored > 0 {
-            println!(
-                "{}",
-                style(format!("Ignored: {}", total_num_ignored))
-                    .italic()
-                    .strikethrough()
-            );
+        Ok(())
+    }
+
+    pub async fn main_export_all_transactions(
+        &mut self,
+        target_name: Option<&str>,
+        format: ExportFormat,
+        script: Option<&Script>,
+    ) -> Result<()> {
+        if format == ExportFormat::BeancountImport {
+            export_beancount_import_candidates(
+                self.db.database(),
+                target_name,
+                script,
+                &mut stdout().lock(),
+            )?;
+            return Ok(());
         }
+        let progress_bar = export_progress_bar();
+        let result = export_all_transactions(
+            self.db.database(),
+            target_name,
+            script,
+            &mut stdout().lock(),
+            |num_written, total| {
+                progress_bar.set_length(total as u64);
+                progress_bar.set_position(num_written as u64);
+            },
+        )?;
+        progress_bar.finish_and_clear();
+        if result.num_transactions == 0 {
+            terminal::print_status("No transactions to export");
+        }
+        warn_held_back(result.num_held_back);
         Ok(())
     }
 
-    async fn sync_connection(
-        plaid_api: &plaid_api::Plaid,
-        bank_connection: &mut BankConnection,
-    ) -> Result<SyncConnectionResult> {
-        let transactions =
-            plaid_api::get_transactions(plaid_api, &bank_connection.access_token()).await?;
+    /// Returns the number of exported transactions and the Beancount output. The database must only be saved once the
+    /// output is stored, otherwise a failed write would lose the transactions.
+    pub async fn main_export_new_transactions(
+        &mut self,
+        target_name: Option<&str>,
+        script: Option<&Script>,
+    ) -> Result<(usize, Vec<u8>)> {
+        let mut output = vec![];
+        let result = self.export_new_transactions(target_name, script, &mut output, true)?;
+        if result.num_transactions == 0 {
+            terminal::print_status("No transactions to export");
+        }
+        warn_held_back(result.num_held_back);
+        Ok((result.num_transactions, output))
+    }
 
-        let mut sync_result = SyncConnectionResult {
-            account_results: bank_connection
-                .accounts()
-                .map(|(id, _)| {
-                    (
-                        id.clone(),
-                        SyncAccountResult {
-                            num_added: 0,
-                            num_verified: 0,
-                        },
-                    )
-                })
-                .collect(),
+    pub fn main_report(
+        &self,
+        group_by: ReportGroupBy,
+        period: ReportPeriod,
+        csv: bool,
+    ) -> Result<()> {
+        let database = self.db.database();
+        let rows = report(
+            stored_transactions(database),
+            &database.transaction_overrides,
+            &database.ignore_list,
+            group_by,
+            period,
+        );
+        if csv {
+            let mut writer = csv::Writer::from_writer(stdout());
+            writer.write_record(["period", "group", "currency", "transactions", "total"])?;
+            for row in rows {
+                writer.write_record([
+                    row.period,
+                    row.group,
+                    row.currency,
+                    row.num_transactions.to_string(),
+                    row.total.to_string(),
+                ])?;
+            }
+            writer.flush()?;
+            return Ok(());
+        }
+        if rows.is_empty() {
+            println!("No transactions");
+            return Ok(());
+        }
+        let group_header = match group_by {
+            ReportGroupBy::Category => "Category",
+            ReportGroupBy::Account => "Account",
         };
-        for transaction in transactions {
-            let account = bank_connection
-                .account_mut(&transaction.account_id)
-                .ok_or_else(|| {
-                    anyhow!(
-                        "Found transaction for account {:?} that we don't have in our database",
-                        transaction.account_id,
-                    )
-                })?;
-            if let Some(account) = &mut account.account {
-                let transaction_id = transaction.transaction_id.clone();
-                let add_or_verify_result = account
-                    .add_or_verify_transaction(transaction.transaction_id, transaction.transaction);
-                match add_or_verify_result {
-                    AddOrVerifyResult::Added => {
-                        sync_result.increment_num_added(&transaction.account_id);
-                    }
-                    AddOrVerifyResult::ExistsAndMatches => {
-                        sync_result.increment_num_verified(&transaction.account_id);
-                    }
-                    AddOrVerifyResult::ExistsAndDoesntMatch {
-                        existing_value,
-                        new_value,
-                    } => {
-                        bail!("Transaction {transaction_id:?} already exists but doesn't match\nExisting: {existing_value:?}\nNew: {new_value:?}",);
-                    }
-                }
+        let mut table = vec![[
+            "Period".to_string(),
+            group_header.to_string(),
+            "Transactions".to_string(),
+            "Total".to_string(),
+        ]];
+        table.extend(rows.into_iter().map(|row| {
+            [
+                row.period,
+                row.group,
+                row.num_transactions.to_string(),
+                format!("{} {}", row.total, row.currency),
+            ]
+        }));
+        print_table(&table);
+        Ok(())
+    }
+
+    pub fn main_categories(&self) {
+        let database = self.db.database();
+        let rows = category_coverage(
+            stored_transactions(database)
+                .map(|(_, transaction_id, transaction)| (transaction_id, transaction)),
+            &database.transaction_overrides,
+            &database.ignore_list,
+        );
+        let mut table = vec![[
+            "Category",
+            "Description",
+            "Transactions",
+            "Unmapped",
+            "Beancount accounts",
+        ]
+        .map(str::to_string)];
+        let mut has_unknown = false;
+        for row in rows {
+            let category = format!("{}.{}", row.category.primary, row.category.detailed);
+            let category = if row.num_unmapped > 0 {
+                style(category).yellow().bold().to_string()
             } else {
-                sync_result.increment_num_added(&transaction.account_id);
-            }
+                category
+            };
+            has_unknown |= row.description.is_none();
+            let description = row
+                .description
+                .unwrap_or_else(|| style("unknown").magenta().italic().to_string());
+            table.push([
+                category,
+                description,
+                row.num_transactions.to_string(),
+                row.num_unmapped.to_string(),
+                row.accounts.into_iter().collect::<Vec<_>>().join(", "),
+            ]);
         }
+        print_table(&table);
+        if has_unknown {
+            println!();
+            println!("{}", style(UNKNOWN_CATEGORIES_HINT).italic());
+        }
+    }
 
-        Ok(sync_result)
+    pub fn main_suggest(
+        &mut self,
+        ledger: Option<&Path>,
+        min_confidence: f64,
+        apply: bool,
+    ) -> Result<()> {
+        let classifier = train_classifier(ledger)?;
+        let database = self.db.database();
+        let mut table =
+            vec![["Date", "Amount", "Description", "Account", "Confidence"].map(str::to_string)];
+        let mut suggestions = vec![];
+        for (_, transaction_id, transaction) in stored_transactions(database) {
+            let info = &transaction.transaction;
+            let has_account = database
+                .transaction_overrides
+                .get(transaction_id)
+                .is_some_and(|overrides| overrides.account.is_some());
+            if transaction.already_exported
+                || has_account
+                || database.ignore_list.is_ignored(transaction_id, info)
+            {
+                continue;
+            }
+            let Some(suggestion) = classifier.suggest_for_transaction(info) else {
+                continue;
+            };
+            if suggestion.confidence < min_confidence {
+                continue;
+            }
+            table.push([
+                info.date().to_string(),
+                format!(
+                    "{} {}",
+                    info.amount.amount,
+                    info.amount.iso_currency_code.as_deref().unwrap_or("???")
+                ),
+                info.description_or_merchant_name
+                    .clone()
+                    .unwrap_or_default(),
+                suggestion.account.clone(),
+                format!("{:.0}%", suggestion.confidence * 100.0),
+            ]);
+            suggestions.push((transaction_id.clone(), suggestion.account));
+        }
+        if suggestions.is_empty() {
+            terminal::print_status("No suggestions for new transactions");
+            return Ok(());
+        }
+        print_table(&table);
+        if apply {
+            for (transaction_id, account) in &suggestions {
+                self.recategorize(transaction_id, account)?;
+            }
+            println!("Assigned {} suggested accounts", suggestions.len());
+        }
+        Ok(())
     }
 
-    pub async fn main_list_transactions(&mut self) -> Result<()> {
-        println!("{}", style_header("Transactions:"));
-        let printer = BulletPointPrinter::new_stdout();
-        for connection in &self.db.database().bank_connections {
-            printer.print_item(style_connection(connection));
-            let printer = printer.indent();
-            for account in connection.accounts() {
-                if let Some(connected_account) = &account.1.account {
-                    printer.print_item(style_account(account.1));
-                    let printer = printer.indent();
-                    let transactions = &connected_account.transactions;
-                    if transactions.is_empty() {
-                        printer.print_item(style("(none)").italic());
-                    } else {
-                        for transaction in connected_account.transactions.iter_all_sorted_by_date()
-                        {
-                            print_transaction(&printer, &transaction.1);
-                        }
-                    }
-                } else {
-                    printer.print_item(style_account(&account.1).strikethrough());
+    pub fn main_undo_export(&mut self, since: NaiveDate, account: Option<String>) -> Result<()> {
+        let query = TransactionQuery {
+            account,
+            since: Some(since),
+            ..Default::default()
+        };
+        let database = self.db.database_mut();
+        let mut found_account = false;
+        let mut num_changed = 0;
+        for connection in &mut database.bank_connections {
+            for (_, account) in connection.accounts_mut() {
+                if !query.matches_account(account) {
+                    continue;
+                }
+                if let Some(connected_account) = &mut account.account {
+                    found_account = true;
+                    num_changed += connected_account.mark_as_not_exported_since(since);
                 }
             }
         }
+        for manual_transaction in database.manual_transactions.values_mut() {
+            if !query.matches_beancount_account(&manual_transaction.beancount_account_info) {
+                continue;
+            }
+            found_account = true;
+            let transaction = &mut manual_transaction.transaction;
+            if transaction.already_exported && query.matches_transaction(transaction, None) {
+                transaction.mark_as_not_exported();
+                num_changed += 1;
+            }
+        }
+        if let Some(account) = &query.account {
+            if !found_account {
+                bail!("Account {account} not found");
+            }
+        }
+        println!(
+            "Marked {num_changed} transactions as not exported. The next `export-new` exports them again."
+        );
         Ok(())
     }
 
-    pub async fn main_export_all_transactions(&mut self) -> Result<()> {
-        let all_transactions = self.db.database().bank_connections.iter().flat_map(|c| {
-            c.accounts().flat_map(|account| {
-                account.1.account.iter().flat_map(|account| {
-                    account.transactions.iter_all_sorted_by_date().map(
-                        move |(transaction_id, transaction)| {
-                            (&account.beancount_account_info, transaction_id, transaction)
-                        },
-                    )
+    /// Write the transactions of the given ledger target that weren't exported yet to `out` and mark them as exported,
+    /// after passing them through `script` if given. Returns the number of exported transactions. `show_progress`
+    /// shows a progress bar on stderr, which has to be off while the TUI owns the terminal.
+    fn export_new_transactions(
+        &mut self,
+        target_name: Option<&str>,
+        script: Option<&Script>,
+        out: &mut impl Write,
+        show_progress: bool,
+    ) -> Result<ExportResult> {
+        let progress_bar = if show_progress {
+            export_progress_bar()
+        } else {
+            ProgressBar::hidden()
+        };
+        let result = export_new_transactions(
+            self.db.database_mut(),
+            target_name,
+            script,
+            out,
+            |num_written, total| {
+                progress_bar.set_length(total as u64);
+                progress_bar.set_position(num_written as u64);
+            },
+        )?;
+        progress_bar.finish_and_clear();
+        Ok(result)
+    }
+}
+
+/// Printed to stderr because the exported transactions may go to stdout
+fn warn_held_back(num_held_back: usize) {
+    if num_held_back > 0 {
+        eprintln!(
+            "{}",
+            style(format!(
+                "Held back {num_held_back} suspected duplicates, review them with `transaction duplicates`"
+            ))
+            .yellow()
+        );
+    }
+}
+
+/// All transactions of connected accounts and all manual transactions, with the Beancount account they belong to
+/// Classifier trained on the transactions of the ledger given with `--ledger`
+fn train_classifier(ledger: Option<&Path>) -> Result<Classifier> {
+    let Some(ledger) = ledger else {
+        bail!("Suggestions are learned from an existing ledger, please pass it with --ledger");
+    };
+    Ok(Classifier::train(&read_transactions(ledger)?))
+}
+
+fn stored_transactions(
+    database: &DatabaseV16,
+) -> impl Iterator<Item = (&BeancountAccountInfo, &TransactionId, &Transaction)> {
+    database
+        .bank_connections
+        .iter()
+        .flat_map(|connection| connection.accounts())
+        .filter_map(|(_, account)| account.account.as_ref())
+        .flat_map(|account| {
+            account.transactions.iter_all_sorted_by_date().map(
+                move |(transaction_id, transaction)| {
+                    (&account.beancount_account_info, transaction_id, transaction)
+                },
+            )
+        })
+        .chain(
+            database
+                .manual_transactions
+                .iter()
+                .map(|(transaction_id, t)| {
+                    (&t.beancount_account_info, transaction_id, &t.transaction)
+                }),
+        )
+}
+
+/// Number of transactions `export-new` would export, i.e. ones that weren't exported yet and aren't ignored
+fn num_unexported(transactions: &Transactions, ignore_list: &IgnoreList) -> usize {
+    transactions
+        .iter_all_sorted_by_date()
+        .filter(|(id, t)| !t.already_exported && !ignore_list.is_ignored(id, &t.transaction))
+        .count()
+}
+
+/// Look up the ledger target `export-all` or `export-new` was called with. `None` is the default target.
+/// Which transactions `transaction list` shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionFilter {
+    All,
+    Ignored,
+    Uncategorized,
+}
+
+impl TransactionQuery {
+    fn matches_connection(&self, connection_name: &str) -> bool {
+        self.connection
+            .as_ref()
+            .is_none_or(|connection| connection == connection_name)
+    }
+
+    fn matches_account(&self, account: &Account) -> bool {
+        self.account.as_ref().is_none_or(|name| {
+            *name == account.plaid_account_info.name
+                || account.account.as_ref().is_some_and(|connected_account| {
+                    *name == connected_account.beancount_account_info.beancount_name()
                 })
-            })
-        });
-        print_exported_transactions(all_transactions)?;
-        Ok(())
+        })
     }
 
-    pub async fn main_export_new_transactions(&mut self) -> Result<()> {
-        let new_transactions = self
-            .db
-            .database_mut()
-            .bank_connections
-            .iter_mut()
-            .flat_map(|c| {
-                c.accounts_mut().flat_map(|account| {
-                    account.1.account.iter_mut().flat_map(|account| {
-                        account.transactions.iter_new_sorted_by_date_mut().map(
-                            |(transaction_id, transaction)| {
-                                transaction.mark_as_exported();
-                                (
-                                    &account.beancount_account_info,
-                                    transaction_id,
-                                    &*transaction,
-                                )
-                            },
-                        )
-                    })
+    fn matches_beancount_account(&self, beancount_account_info: &BeancountAccountInfo) -> bool {
+        self.account
+            .as_ref()
+            .is_none_or(|name| *name == beancount_account_info.beancount_name())
+    }
+
+    fn matches_transaction(
+        &self,
+        transaction: &Transaction,
+        overrides: Option<&TransactionOverrides>,
+    ) -> bool {
+        let info = &transaction.transaction;
+        let category = overrides
+            .and_then(|overrides| overrides.category.as_ref())
+            .or(info.category.as_ref());
+        self.since.is_none_or(|since| info.date() >= since)
+            && self.until.is_none_or(|until| info.date() <= until)
+            && self.category.as_ref().is_none_or(|query| {
+                category.is_some_and(|category| {
+                    query.eq_ignore_ascii_case(&category.primary)
+                        || query.eq_ignore_ascii_case(&category.detailed)
                 })
-            });
-        print_exported_transactions(new_transactions)?;
-        Ok(())
+            })
+            && self
+                .min_amount
+                .is_none_or(|min_amount| info.amount.amount >= min_amount)
+            && self
+                .max_amount
+                .is_none_or(|max_amount| info.amount.amount <= max_amount)
+            && (!self.new_only || !transaction.already_exported)
     }
 }
 
-const BEANCOUNT_PLAID_KEY_ENV_VAR: &str = "BEANCOUNT_PLAID_KEY";
+/// Unencrypted databases don't need a key, so we only require one if the database is encrypted
+fn load_db_cipher(db_path: &Path, key_source: &KeySource) -> Result<DbCipher> {
+    if DatabaseFile::detect_encryption(db_path)? {
+        Ok(DbCipher::Encrypted(key_source.load()?))
+    } else {
+        print_unencrypted_warning();
+        Ok(DbCipher::Unencrypted)
+    }
+}
 
-fn load_or_gen_new_cipher() -> Result<XChaCha20Poly1305Cipher> {
-    match load_cipher_from_environment() {
-        Ok(cipher) => {
-            match prompt_select(
-                "Found an encryption key in the BEANCOUNT_PLAID_KEY environment variable. Use it?",
-                &["Use the environment variable", "Generate a new key"],
+/// Offer to clean up after a previous run that crashed while it had the database loaded
+async fn handle_leftovers(db_path: &Path, db_cipher: &DbCipher, num_backups: usize) -> Result<()> {
+    let leftovers = DatabaseFile::find_leftovers(db_path, db_cipher).await?;
+    // Without a terminal, leave them alone unless the user passed --yes.
+    // A stale lock still fails with an error explaining how to remove it.
+    if leftovers.is_empty() || !(console::user_attended() || terminal::assume_yes()) {
+        return Ok(());
+    }
+    if let Some(pid) = leftovers.stale_lock_pid {
+        if terminal::prompt_yes_no(&format!(
+            "The database is locked by process {pid}, which isn't running anymore. It probably crashed. Remove the lock?"
+        ))? {
+            DatabaseFile::remove_stale_lock(db_path)?;
+            println!("Removed the lock");
+        }
+    }
+    if let Some(temp_file) = leftovers.temp_file {
+        if temp_file.intact {
+            match terminal::prompt_select(
+                &format!(
+                    "A previous run crashed while saving the database. The new version at {} is complete. What do you want to do with it?",
+                    temp_file.path.display()
+                ),
+                &["Recover it, replacing the database", "Delete it", "Keep it for now"],
                 0,
             )? {
-                0 => Ok(cipher),
-                1 => Ok(gen_new_cipher()),
+                0 => {
+                    DatabaseFile::recover_temp_file(db_path, db_cipher, num_backups).await?;
+                    println!("Recovered the database. Run `undo` to go back to the previous version.");
+                }
+                1 => {
+                    DatabaseFile::remove_temp_file(db_path).await?;
+                    println!("Deleted {}", temp_file.path.display());
+                }
+                2 => {}
                 _ => unreachable!(),
             }
+        } else if terminal::prompt_yes_no(&format!(
+            "A previous run crashed while saving the database and left an incomplete file at {}. Delete it?",
+            temp_file.path.display()
+        ))? {
+            DatabaseFile::remove_temp_file(db_path).await?;
+            println!("Deleted {}", temp_file.path.display());
         }
-        Err(_) => Ok(gen_new_cipher()),
     }
+    Ok(())
 }
 
-fn gen_new_cipher() -> XChaCha20Poly1305Cipher {
-    let new_key = XChaCha20Poly1305Cipher::new_key();
-    let cipher = XChaCha20Poly1305Cipher::with_key(&new_key);
-    println!();
-    println!("Generated new encryption key.");
-    println!(
-        "{}",
-        style("Please set this environment variable for future runs:").bold()
-    );
-    println!(
-        "{}",
-        style(format!(
-            "{}={}",
-            BEANCOUNT_PLAID_KEY_ENV_VAR,
-            ENCRYPTION_KEY_ENCODER.encode(new_key),
-        ))
-        .blue()
-        .bold()
-    );
-    println!();
-    cipher
-}
-
-fn load_cipher_from_environment() -> Result<XChaCha20Poly1305Cipher> {
-    let key = match std::env::var(BEANCOUNT_PLAID_KEY_ENV_VAR) {
-        Ok(key) => key,
-        Err(VarError::NotPresent) => bail!("{BEANCOUNT_PLAID_KEY_ENV_VAR} environment variable not set. Please set it to the encryption key."),
-        Err(VarError::NotUnicode(_)) => bail!("{BEANCOUNT_PLAID_KEY_ENV_VAR} environment variable is not valid UTF-8. Please set it to the encryption key."),
-    };
-
-    let key = ENCRYPTION_KEY_ENCODER
-        .decode(key)
-        .with_context(|| format!("Failed to decode {BEANCOUNT_PLAID_KEY_ENV_VAR}"))?;
-    if key.len() != XChaCha20Poly1305::key_size() {
-        bail!(
-            "{BEANCOUNT_PLAID_KEY_ENV_VAR} must be {} bytes long",
-            XChaCha20Poly1305::key_size(),
+/// Print the global options with their value and where it came from. The database path is shown as resolved,
+/// since it has no fixed default.
+fn print_config(options: &[ResolvedOption], db_path: &Path, resolved: bool) {
+    let mut table = vec![["Option", "Environment variable", "Value", "Source"].map(str::to_string)];
+    for option in options {
+        let explicitly_set = matches!(
+            option.source,
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
         );
+        if !resolved && !explicitly_set {
+            continue;
+        }
+        let (value, source) = match (option.name.as_str(), &option.value, option.source) {
+            ("--db-path", None, _) => (db_path.display().to_string(), "default"),
+            (_, None, _) => ("(not set)".to_string(), ""),
+            (_, Some(value), Some(ValueSource::CommandLine)) => (value.clone(), "command line"),
+            (_, Some(value), Some(ValueSource::EnvVariable)) => (value.clone(), "environment"),
+            (_, Some(value), _) => (value.clone(), "default"),
+        };
+        table.push([
+            option.name.clone(),
+            option.env.clone().unwrap_or_default(),
+            value,
+            source.to_string(),
+        ]);
     }
-    let key = <XChaCha20Poly1305Cipher as Cipher>::EncryptionKey::from_slice(&key);
-    Ok(XChaCha20Poly1305Cipher::with_key(key))
-}
-
-struct SyncConnectionResult {
-    account_results: HashMap<AccountId, SyncAccountResult>,
-}
-
-impl SyncConnectionResult {
-    pub fn increment_num_added(&mut self, account_id: &AccountId) {
-        self.account_results.get_mut(account_id).unwrap().num_added += 1;
+    if table.len() == 1 {
+        println!("No options are set. Use `config show --resolved` to show the defaults.");
+        return;
     }
+    print_table(&table);
+}
 
-    pub fn increment_num_verified(&mut self, account_id: &AccountId) {
-        self.account_results
-            .get_mut(account_id)
-            .unwrap()
-            .num_verified += 1;
-    }
+/// Progress bar on stderr for rendering transactions, so it doesn't mix with the exported ledger.
+/// Its length is set once the number of transactions is known.
+fn export_progress_bar() -> ProgressBar {
+    terminal::add_progress_bar(
+        ProgressBar::new(0).with_style(
+            ProgressStyle::with_template("Exporting transactions {wide_bar} {pos}/{len}")
+                .expect("Progress bar template is valid"),
+        ),
+    )
 }
 
-struct SyncAccountResult {
-    num_added: u64,
-    num_verified: u64,
+fn print_unencrypted_warning() {
+    eprintln!(
+        "{}",
+        style("WARNING: The database is not encrypted. Anyone who can read the database file can see your Plaid credentials and transactions. Run the `db encrypt` command to encrypt it.")
+            .red()
+            .bold()
+    );
 }
 
+/// Returns whether the account is pending, i.e. its transactions should be synced until `account connect` connects it
 fn prompt_add_account(
     index: usize,
     account_id: AccountId,
     plaid_account_info: PlaidAccountInfo,
-) -> Result<(AccountId, Account)> {
+    ledger_accounts: &[String],
+) -> Result<(AccountId, Account, bool)> {
     print_found_account(index, &plaid_account_info);
     println!();
-    let prompt = "Add account";
-    if terminal::prompt_yes_no(&prompt)? {
-        let beancount_account_info = prompt_beancount_account_info()?;
-        Ok((
+    match terminal::prompt_select(
+        "Add account",
+        &[
+            "Add it",
+            "Decide later, but already sync its transactions",
+            "Don't add it",
+        ],
+        0,
+    )? {
+        0 => {
+            let beancount_account_info = prompt_beancount_account_info(ledger_accounts)?;
+            Ok((
+                account_id,
+                Account::new_connected(plaid_account_info, beancount_account_info),
+                false,
+            ))
+        }
+        1 => Ok((
             account_id,
-            Account::new_connected(plaid_account_info, beancount_account_info),
-        ))
-    } else {
-        Ok((account_id, Account::new_unconnected(plaid_account_info)))
+            Account::new_unconnected(plaid_account_info),
+            true,
+        )),
+        2 => Ok((
+            account_id,
+            Account::new_unconnected(plaid_account_info),
+            false,
+        )),
+        _ => unreachable!(),
     }
 }
 
@@ -470,41 +2879,39 @@ fn print_found_account(index: usize, plaid_account_info: &PlaidAccountInfo) {
     }
 }
 
-fn prompt_beancount_account_info() -> Result<BeancountAccountInfo> {
+/// Offers the `ledger_accounts` for selection if there are any, and asks for the name otherwise
+fn prompt_beancount_account_info(ledger_accounts: &[String]) -> Result<BeancountAccountInfo> {
     const PROMPT: &str = "Beancount account name";
+    if !ledger_accounts.is_empty() {
+        let options: Vec<String> = std::iter::once("(Enter another account)".to_string())
+            .chain(ledger_accounts.iter().cloned())
+            .collect();
+        let selected = terminal::prompt_fuzzy_select(PROMPT, &options, 0)?;
+        if selected > 0 {
+            match parse_beancount_account_name(&options[selected]) {
+                Ok(info) => return Ok(info),
+                Err(err) => println!("{}", style(err).red().bold()),
+            }
+        }
+    }
     let mut name = terminal::prompt(PROMPT)?;
     loop {
         match parse_beancount_account_name(&name) {
             Ok(info) => return Ok(info),
             Err(err) => {
                 println!("{}", style(err).red().bold());
+                if let Some(suggestion) = suggest_beancount_account_name(&name) {
+                    if terminal::prompt_yes_no(&format!("Use {suggestion} instead?"))? {
+                        name = suggestion;
+                        continue;
+                    }
+                }
                 name = terminal::prompt(PROMPT)?;
             }
         }
     }
 }
 
-fn parse_beancount_account_name(name: &str) -> Result<BeancountAccountInfo, &'static str> {
-    let mut parts = name.split(':');
-    let ty = parts
-        .next()
-        .expect("There should always be at least one part to the split");
-    let ty = match ty {
-        "Assets" => AccountType::Assets,
-        "Liabilities" => AccountType::Liabilities,
-        "Equity" => AccountType::Equity,
-        "Income" => AccountType::Income,
-        "Expenses" => AccountType::Expenses,
-        _ => return Err(
-            "Account must start with one of: Assets:, Liabilities:, Equity:, Income:, Expenses:",
-        ),
-    };
-    Ok(BeancountAccountInfo {
-        ty,
-        name_parts: parts.map(|v| v.to_string()).collect(),
-    })
-}
-
 fn print_accounts<'a, 'b>(
     printer: &BulletPointPrinter<impl LineWriter + Clone>,
     accounts: impl Iterator<Item = (&'a AccountId, &'b Account)>,
@@ -514,6 +2921,70 @@ fn print_accounts<'a, 'b>(
     }
 }
 
+/// Prints the first row as header. Numbers in the last two columns are right aligned.
+fn print_table<const N: usize>(rows: &[[String; N]]) {
+    let widths: [usize; N] = std::array::from_fn(|column| {
+        rows.iter()
+            .map(|row| console::measure_text_width(&row[column]))
+            .max()
+            .unwrap_or(0)
+    });
+    for (index, row) in rows.iter().enumerate() {
+        let line = row
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(column, (cell, width))| {
+                let alignment = if column + 2 >= N {
+                    Alignment::Right
+                } else {
+                    Alignment::Left
+                };
+                pad_str(cell, width, alignment, None).into_owned()
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        if index == 0 {
+            println!("{}", style(line.trim_end()).bold());
+        } else {
+            println!("{}", line.trim_end());
+        }
+    }
+}
+
+fn print_counts(printer: &BulletPointPrinter<impl LineWriter + Clone>, counts: &Counts) {
+    printer.print_item(style(format!("Exported: {}", counts.exported)).italic());
+    printer.print_item(style(format!("Not exported: {}", counts.unexported)).italic());
+}
+
+fn print_archived_account(
+    printer: &BulletPointPrinter<impl LineWriter + Clone>,
+    account: &Account,
+) {
+    printer.print_item(style_account(account));
+    if let Some(connected_account) = &account.account {
+        print_archived_transactions(&printer.indent(), connected_account);
+    }
+}
+
+fn print_archived_transactions(
+    printer: &BulletPointPrinter<impl LineWriter + Clone>,
+    account: &ConnectedAccount,
+) {
+    let num_exported = account
+        .transactions
+        .iter_all_sorted_by_date()
+        .filter(|(_, t)| t.already_exported)
+        .count();
+    printer.print_item(
+        style(format!(
+            "{} transactions, {num_exported} exported",
+            account.transactions.len()
+        ))
+        .italic(),
+    );
+}
+
 fn print_connection(
     printer: &BulletPointPrinter<impl LineWriter + Clone>,
     connection: &BankConnection,
@@ -525,6 +2996,7 @@ fn print_connection(
 fn print_transaction(
     printer: &BulletPointPrinter<impl LineWriter + Clone>,
     transaction: &Transaction,
+    ignored: bool,
 ) {
     let transaction_description = transaction
         .transaction
@@ -577,7 +3049,9 @@ fn print_transaction(
         style_transaction_description(&transaction_description),
         style_merchant_name(&merchant_name),
         style_category(&category),
-        if transaction.already_exported {
+        if ignored {
+            style("[ignored]").dim()
+        } else if transaction.already_exported {
             style("[exported]").dim()
         } else {
             style("[new]").dim()
@@ -597,6 +3071,93 @@ fn print_transaction(
     }
 }
 
+/// Prints the fields of both versions side by side, highlighting the ones that changed
+fn print_transaction_diff(existing: &TransactionInfo, new: &TransactionInfo) {
+    fn fields(transaction: &TransactionInfo) -> [(&'static str, String); 11] {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        [
+            (
+                "Amount",
+                format!(
+                    "{} {}",
+                    transaction.amount.amount,
+                    transaction
+                        .amount
+                        .iso_currency_code
+                        .as_deref()
+                        .unwrap_or("???")
+                ),
+            ),
+            ("Posted", transaction.posted_date.to_string()),
+            (
+                "Authorized",
+                transaction
+                    .authorized_date
+                    .map(|date| date.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("Merchant", optional(&transaction.merchant_name)),
+            (
+                "Description",
+                optional(&transaction.description_or_merchant_name),
+            ),
+            (
+                "Original description",
+                optional(&transaction.original_description),
+            ),
+            (
+                "Category",
+                transaction
+                    .category
+                    .as_ref()
+                    .map(|category| format!("{}.{}", category.primary, category.detailed))
+                    .unwrap_or_default(),
+            ),
+            ("Type", optional(&transaction.transaction_type)),
+            ("Location", optional(&transaction.location)),
+            ("Check number", optional(&transaction.check_number)),
+            ("Website", optional(&transaction.associated_website)),
+        ]
+    }
+
+    let existing = fields(existing);
+    let new = fields(new);
+    let name_width = existing
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    let value_width = existing
+        .iter()
+        .map(|(_, value)| console::measure_text_width(value))
+        .chain(["Stored".len()])
+        .max()
+        .unwrap_or(0);
+    println!(
+        "  {}  {}  {}",
+        pad_str("", name_width, Alignment::Left, None),
+        style(pad_str("Stored", value_width, Alignment::Left, None)).bold(),
+        style("Synced").bold(),
+    );
+    for ((name, existing_value), (_, new_value)) in existing.iter().zip(new.iter()) {
+        let name = pad_str(name, name_width, Alignment::Left, None);
+        let padded_existing_value = pad_str(existing_value, value_width, Alignment::Left, None);
+        if existing_value == new_value {
+            println!(
+                "{}",
+                style(format!("  {name}  {padded_existing_value}  {new_value}")).dim()
+            );
+        } else {
+            println!(
+                "  {}  {}  {}",
+                style(name).bold(),
+                style(padded_existing_value).red(),
+                style(new_value).green(),
+            );
+        }
+    }
+}
+
 fn style_header(header: &str) -> StyledObject<&str> {
     style(header).bold().underlined()
 }
@@ -670,3 +3231,95 @@ fn style_category(category: &str) -> StyledObject<&str> {
 fn style_mask(mask: &str) -> StyledObject<String> {
     style(format!("***{mask}")).italic()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{AccessToken, DbPlaidAuth, ManualTransaction, TransactionBuilder};
+
+    #[tokio::test]
+    async fn revert_tui_export_if_saving_fails() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database =
+            DatabaseV16::new(DbPlaidAuth::new("client".to_string(), "secret".to_string()));
+        database.manual_transactions.insert(
+            TransactionId::new_manual(),
+            ManualTransaction {
+                beancount_account_info: parse_beancount_account_name("Assets:Cash").unwrap(),
+                transaction: TransactionBuilder::new().description("Coffee").build(),
+            },
+        );
+        // The database can't be saved because its directory doesn't exist
+        let db_path = tempdir.path().join("missing").join("db");
+        let mut cli = Cli::_new(DatabaseFile::new(database, db_path, DbCipher::Unencrypted));
+        let output_file = tempdir.path().join("new.beancount");
+
+        let (num_exported, appended) = cli
+            .export_new_transactions_to_file(output_file.to_str().unwrap(), None, None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(1, num_exported);
+        assert!(std::fs::read_to_string(&output_file)
+            .unwrap()
+            .contains("Coffee"));
+
+        let result = save_or_revert_exports(cli, "tui", vec![appended]).await;
+        assert!(result.is_err());
+        assert!(!output_file.exists());
+    }
+
+    #[test]
+    fn list_accounts_shows_synced_balance() {
+        let account_id = AccountId::new("account-1".to_string());
+        let mut connection = BankConnection::new(
+            "bank".to_string(),
+            AccessToken::new("access-token".to_string()),
+            None,
+            HashMap::from([(
+                account_id.clone(),
+                Account::new_connected(
+                    PlaidAccountInfo {
+                        name: "Checking".to_string(),
+                        official_name: None,
+                        mask: None,
+                        type_: "depository".to_string(),
+                        subtype: None,
+                    },
+                    parse_beancount_account_name("Assets:Checking").unwrap(),
+                ),
+            )]),
+        );
+        let sync_report = SyncReport {
+            account_results: HashMap::new(),
+            mismatches: vec![],
+            balances: HashMap::from([(
+                account_id,
+                plaid_api::Balance {
+                    current: Decimal::new(12345, 2),
+                    iso_currency_code: Some("USD".to_string()),
+                },
+            )]),
+            account_changes: vec![],
+            unknown_categories: HashSet::new(),
+            transaction_times: HashMap::new(),
+        };
+        record_balance_snapshots(
+            &mut connection,
+            &sync_report,
+            NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        );
+        let mut database =
+            DatabaseV16::new(DbPlaidAuth::new("client".to_string(), "secret".to_string()));
+        database.bank_connections.push(connection);
+        let cli = Cli::_new(DatabaseFile::new(
+            database,
+            PathBuf::from("db"),
+            DbCipher::Unencrypted,
+        ));
+
+        let rows = cli.account_rows();
+        assert_eq!(2, rows.len());
+        assert_eq!("Balance", rows[0][6]);
+        assert_eq!("123.45 USD", rows[1][6]);
+    }
+}
@@ -0,0 +1,590 @@
+//! Interactive dashboard for browsing, categorizing and exporting transactions
+
+use anyhow::Result;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize as _},
+    text::Line,
+    widgets::{Block, List, ListState, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+use rust_decimal::Decimal;
+use std::path::Path;
+
+use super::{train_classifier, Cli};
+use crate::args::OnError;
+use crate::db::{AccountId, DatabaseV16, Transaction, TransactionId, TransactionOverrides};
+use crate::suggest::{Classifier, Suggestion};
+use crate::sync::Timezone;
+use crate::validate::{append_export, append_validated, AppendedExport, Validator};
+
+/// Default file `e` appends exported transactions to
+const DEFAULT_EXPORT_PATH: &str = "new_transactions.beancount";
+
+const HELP: &str = "q quit  tab switch pane  / filter  c categorize  s sync  e export new";
+
+/// Which transactions the transaction pane shows
+#[derive(Clone, PartialEq, Eq)]
+enum AccountSelection {
+    All,
+    Account(AccountId),
+    Manual,
+}
+
+struct AccountItem {
+    selection: AccountSelection,
+    label: String,
+}
+
+struct TransactionRow {
+    id: TransactionId,
+    date: String,
+    amount: Decimal,
+    currency: String,
+    description: String,
+    category: String,
+    /// Counter account for new transactions that don't have one yet, if the dashboard was started with `--ledger`
+    suggestion: Option<Suggestion>,
+    status: &'static str,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Accounts,
+    Transactions,
+}
+
+enum InputKind {
+    Filter,
+    Categorize(TransactionId),
+    ExportPath,
+}
+
+struct Input {
+    kind: InputKind,
+    text: String,
+}
+
+struct App {
+    accounts: Vec<AccountItem>,
+    account_state: ListState,
+    transactions: Vec<TransactionRow>,
+    transaction_state: TableState,
+    filter: String,
+    focus: Focus,
+    input: Option<Input>,
+    status: String,
+    classifier: Option<Classifier>,
+    /// Exports of `e`, taken back if the database can't be saved when the dashboard is closed
+    appended: Vec<AppendedExport>,
+}
+
+/// What the event loop has to do after handling a key
+enum Action {
+    None,
+    Quit,
+    Sync,
+    Categorize(TransactionId, String),
+    Export(String),
+}
+
+impl Cli {
+    /// `validate_with` checks the exports of `e` like `--validate-with` does for `export-new`, and `s` syncs with
+    /// `timezone` and `on_error` like `sync` does. The exports are added to `appended`, so they can be taken back if
+    /// the database can't be saved afterwards.
+    pub async fn main_tui(
+        &mut self,
+        ledger: Option<&Path>,
+        validate_with: Option<Validator>,
+        timezone: Timezone,
+        on_error: OnError,
+        appended: &mut Vec<AppendedExport>,
+    ) -> Result<()> {
+        let classifier = ledger
+            .map(|ledger| train_classifier(Some(ledger)))
+            .transpose()?;
+        let mut app = App {
+            accounts: vec![],
+            account_state: ListState::default().with_selected(Some(0)),
+            transactions: vec![],
+            transaction_state: TableState::default(),
+            filter: String::new(),
+            focus: Focus::Transactions,
+            input: None,
+            status: String::new(),
+            classifier,
+            appended: vec![],
+        };
+        app.reload(self.db.database());
+
+        let mut terminal = ratatui::init();
+        let result = self
+            .run_tui(
+                &mut terminal,
+                &mut app,
+                ledger,
+                validate_with,
+                timezone,
+                on_error,
+            )
+            .await;
+        ratatui::restore();
+        appended.append(&mut app.appended);
+        result
+    }
+
+    async fn run_tui(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        app: &mut App,
+        ledger: Option<&Path>,
+        validate_with: Option<Validator>,
+        timezone: Timezone,
+        on_error: OnError,
+    ) -> Result<()> {
+        loop {
+            terminal.draw(|frame| app.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match app.handle_key(key) {
+                Action::None => {}
+                Action::Quit => return Ok(()),
+                Action::Sync => {
+                    // Syncing prints progress and may ask questions, so give it the normal terminal
+                    ratatui::restore();
+                    let result = self.main_sync(None, None, timezone, on_error).await;
+                    if let Err(err) = &result {
+                        println!("Sync failed: {err:#}");
+                    }
+                    println!("Press Enter to return to the dashboard");
+                    std::io::stdin().read_line(&mut String::new())?;
+                    *terminal = ratatui::init();
+                    app.status = match result {
+                        Ok(()) => "Synced".to_string(),
+                        Err(err) => format!("Sync failed: {err:#}"),
+                    };
+                }
+                Action::Categorize(transaction_id, category_or_account) => {
+                    let result = self
+                        .atomically(|cli| cli.recategorize(&transaction_id, &category_or_account));
+                    app.status = match result {
+                        Ok(()) => format!("Recategorized as {category_or_account}"),
+                        Err(err) => format!("{err:#}"),
+                    };
+                }
+                Action::Export(path) => {
+                    let result = self.export_new_transactions_to_file(&path, validate_with, ledger);
+                    app.status = match result {
+                        Ok(None) => "No transactions to export".to_string(),
+                        Ok(Some((num_exported, export))) => {
+                            app.appended.push(export);
+                            format!("Exported {num_exported} transactions to {path}")
+                        }
+                        Err(err) => format!("Export failed: {err:#}"),
+                    };
+                }
+            }
+            app.reload(self.db.database());
+        }
+    }
+
+    /// Append the transactions that weren't exported yet to the file at `path`. They're only marked as exported if
+    /// writing succeeds and, if given, `validator` accepts the export, see [append_validated].
+    /// Returns `None` if there was nothing to export.
+    pub(super) fn export_new_transactions_to_file(
+        &mut self,
+        path: &str,
+        validator: Option<Validator>,
+        ledger: Option<&Path>,
+    ) -> Result<Option<(usize, AppendedExport)>> {
+        self.atomically(|cli| {
+            let mut output = vec![];
+            let num_exported = cli
+                .export_new_transactions(None, None, &mut output, false)?
+                .num_transactions;
+            if output.is_empty() {
+                return Ok(None);
+            }
+            let appended = match validator {
+                Some(validator) => append_validated(validator, Path::new(path), &output, ledger)?,
+                None => append_export(Path::new(path), &output)?,
+            };
+            Ok(Some((num_exported, appended)))
+        })
+    }
+}
+
+impl App {
+    /// Rebuild the panes after the database or the filter changed, keeping the selection where possible
+    fn reload(&mut self, database: &DatabaseV16) {
+        self.accounts = account_items(database);
+        let selected_account = self.account_state.selected().unwrap_or(0);
+        if selected_account >= self.accounts.len() {
+            self.account_state.select(Some(0));
+        }
+        let selection = &self.accounts[self.account_state.selected().unwrap_or(0)].selection;
+        self.transactions =
+            transaction_rows(database, selection, &self.filter, self.classifier.as_ref());
+        match self.transaction_state.selected() {
+            _ if self.transactions.is_empty() => self.transaction_state.select(None),
+            Some(selected) if selected < self.transactions.len() => {}
+            _ => self.transaction_state.select(Some(0)),
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Esc => {
+                    if matches!(input.kind, InputKind::Filter) {
+                        self.filter.clear();
+                    }
+                    self.input = None;
+                }
+                KeyCode::Enter => {
+                    let input = self.input.take().expect("Checked above");
+                    return match input.kind {
+                        InputKind::Filter => Action::None,
+                        InputKind::Categorize(_) if input.text.trim().is_empty() => Action::None,
+                        InputKind::Categorize(transaction_id) => {
+                            Action::Categorize(transaction_id, input.text.trim().to_string())
+                        }
+                        InputKind::ExportPath => Action::Export(input.text.trim().to_string()),
+                    };
+                }
+                KeyCode::Backspace => {
+                    input.text.pop();
+                }
+                KeyCode::Char(c) => input.text.push(c),
+                _ => {}
+            }
+            if let Some(Input {
+                kind: InputKind::Filter,
+                text,
+            }) = &self.input
+            {
+                self.filter = text.clone();
+            }
+            // The event loop reloads the transactions, which applies the changed filter
+            return Action::None;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Tab | KeyCode::Left | KeyCode::Right => {
+                self.focus = match self.focus {
+                    Focus::Accounts => Focus::Transactions,
+                    Focus::Transactions => Focus::Accounts,
+                };
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(20),
+            KeyCode::PageUp => self.move_selection(-20),
+            KeyCode::Char('/') => {
+                self.input = Some(Input {
+                    kind: InputKind::Filter,
+                    text: self.filter.clone(),
+                });
+            }
+            KeyCode::Char('c') => {
+                if let Some(row) = self
+                    .transaction_state
+                    .selected()
+                    .and_then(|selected| self.transactions.get(selected))
+                {
+                    // Pre-filled with the suggestion so it can be accepted with Enter
+                    self.input = Some(Input {
+                        kind: InputKind::Categorize(row.id.clone()),
+                        text: row
+                            .suggestion
+                            .as_ref()
+                            .map(|suggestion| suggestion.account.clone())
+                            .unwrap_or_default(),
+                    });
+                }
+            }
+            KeyCode::Char('s') => return Action::Sync,
+            KeyCode::Char('e') => {
+                self.input = Some(Input {
+                    kind: InputKind::ExportPath,
+                    text: DEFAULT_EXPORT_PATH.to_string(),
+                });
+            }
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let (len, selected) = match self.focus {
+            Focus::Accounts => (self.accounts.len(), self.account_state.selected()),
+            Focus::Transactions => (self.transactions.len(), self.transaction_state.selected()),
+        };
+        if len == 0 {
+            return;
+        }
+        let new_selected = selected
+            .unwrap_or(0)
+            .saturating_add_signed(delta)
+            .min(len - 1);
+        match self.focus {
+            Focus::Accounts => {
+                self.account_state.select(Some(new_selected));
+                // Show the transactions of the newly selected account from the top
+                self.transaction_state.select(Some(0));
+            }
+            Focus::Transactions => self.transaction_state.select(Some(new_selected)),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status, help] = Layout::vertical([
+            Constraint::Min(0),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [accounts_area, transactions_area] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+
+        let border_style = |focus| {
+            if self.focus == focus {
+                Style::new().cyan()
+            } else {
+                Style::new()
+            }
+        };
+
+        let accounts = List::new(self.accounts.iter().map(|item| item.label.as_str()))
+            .block(
+                Block::bordered()
+                    .title("Accounts")
+                    .border_style(border_style(Focus::Accounts)),
+            )
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(accounts, accounts_area, &mut self.account_state);
+
+        let rows = self.transactions.iter().map(|row| {
+            let amount_style = if row.amount < Decimal::ZERO {
+                Style::new().red()
+            } else {
+                Style::new().green()
+            };
+            Row::new([
+                Line::from(row.date.as_str()),
+                Line::from(format!("{} {}", row.amount, row.currency))
+                    .right_aligned()
+                    .style(amount_style),
+                Line::from(row.description.as_str()).blue(),
+                match &row.suggestion {
+                    Some(suggestion) => Line::from(format!(
+                        "? {} ({:.0}%)",
+                        suggestion.account,
+                        suggestion.confidence * 100.0
+                    ))
+                    .yellow(),
+                    None => Line::from(row.category.as_str()).magenta(),
+                },
+                Line::from(row.status).dim(),
+            ])
+        });
+        let title = if self.filter.is_empty() {
+            format!("Transactions ({})", self.transactions.len())
+        } else {
+            format!(
+                "Transactions ({}) matching \"{}\"",
+                self.transactions.len(),
+                self.filter
+            )
+        };
+        let transactions = Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Length(16),
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+                Constraint::Length(8),
+            ],
+        )
+        .header(Row::new(["Date", "Amount", "Description", "Category", "Status"]).bold())
+        .block(
+            Block::bordered()
+                .title(title)
+                .border_style(border_style(Focus::Transactions)),
+        )
+        .row_highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(transactions, transactions_area, &mut self.transaction_state);
+
+        let status_line = match &self.input {
+            Some(input) => {
+                let prompt = match input.kind {
+                    InputKind::Filter => "Filter: ",
+                    InputKind::Categorize(_) => {
+                        "Category (e.g. FOOD_AND_DRINK.FOOD_AND_DRINK_GROCERIES) or account: "
+                    }
+                    InputKind::ExportPath => "Append new transactions to: ",
+                };
+                Line::from(format!("{prompt}{}", input.text)).yellow()
+            }
+            None => Line::from(self.status.as_str()),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+        frame.render_widget(Paragraph::new(Line::from(HELP).dim()), help);
+    }
+}
+
+fn account_items(database: &DatabaseV16) -> Vec<AccountItem> {
+    let mut items = vec![AccountItem {
+        selection: AccountSelection::All,
+        label: "All accounts".to_string(),
+    }];
+    for connection in &database.bank_connections {
+        for (account_id, account) in connection.accounts() {
+            if let Some(connected_account) = &account.account {
+                items.push(AccountItem {
+                    selection: AccountSelection::Account(account_id.clone()),
+                    label: format!(
+                        "{} / {} [{}]",
+                        connection.name(),
+                        account.plaid_account_info.name,
+                        connected_account.beancount_account_info.beancount_name()
+                    ),
+                });
+            }
+        }
+    }
+    if !database.manual_transactions.is_empty() {
+        items.push(AccountItem {
+            selection: AccountSelection::Manual,
+            label: "Manually entered".to_string(),
+        });
+    }
+    items
+}
+
+/// Transactions of the selected account containing `filter` in their description, category or account, newest first
+fn transaction_rows(
+    database: &DatabaseV16,
+    selection: &AccountSelection,
+    filter: &str,
+    classifier: Option<&Classifier>,
+) -> Vec<TransactionRow> {
+    let mut transactions: Vec<(&TransactionId, &Transaction, String)> = vec![];
+    for connection in &database.bank_connections {
+        for (account_id, account) in connection.accounts() {
+            let Some(connected_account) = &account.account else {
+                continue;
+            };
+            if !matches!(selection, AccountSelection::All)
+                && *selection != AccountSelection::Account(account_id.clone())
+            {
+                continue;
+            }
+            let beancount_name = connected_account.beancount_account_info.beancount_name();
+            transactions.extend(
+                connected_account
+                    .transactions
+                    .iter_all_sorted_by_date()
+                    .map(|(id, t)| (id, t, beancount_name.clone())),
+            );
+        }
+    }
+    if matches!(selection, AccountSelection::All | AccountSelection::Manual) {
+        transactions.extend(database.manual_transactions.iter().map(|(id, t)| {
+            (
+                id,
+                &t.transaction,
+                t.beancount_account_info.beancount_name(),
+            )
+        }));
+    }
+
+    let filter = filter.to_lowercase();
+    let mut rows: Vec<TransactionRow> = transactions
+        .into_iter()
+        .map(|(id, transaction, beancount_name)| {
+            let row = transaction_row(
+                database,
+                id,
+                transaction,
+                database.transaction_overrides.get(id),
+                classifier,
+            );
+            (row, beancount_name)
+        })
+        .filter(|(row, beancount_name)| {
+            filter.is_empty()
+                || [&row.description, &row.category, beancount_name]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(&filter))
+        })
+        .map(|(row, _)| row)
+        .collect();
+    rows.sort_by(|a, b| b.date.cmp(&a.date));
+    rows
+}
+
+fn transaction_row(
+    database: &DatabaseV16,
+    id: &TransactionId,
+    transaction: &Transaction,
+    overrides: Option<&TransactionOverrides>,
+    classifier: Option<&Classifier>,
+) -> TransactionRow {
+    let info = &transaction.transaction;
+    let description = [&info.merchant_name, &info.description_or_merchant_name]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    // The description is often just the merchant name
+    let description = {
+        let mut description = description;
+        description.dedup();
+        description.join(" - ")
+    };
+    let category = match overrides {
+        Some(TransactionOverrides {
+            account: Some(account),
+            ..
+        }) => format!("-> {}", account.beancount_name()),
+        _ => overrides
+            .and_then(|overrides| overrides.category.as_ref())
+            .or(info.category.as_ref())
+            .map(|category| category.detailed.clone())
+            .unwrap_or_default(),
+    };
+    let status = if database.ignore_list.is_ignored(id, info) {
+        "ignored"
+    } else if transaction.already_exported {
+        "exported"
+    } else {
+        "new"
+    };
+    let has_account = overrides.is_some_and(|overrides| overrides.account.is_some());
+    let suggestion = classifier
+        .filter(|_| status == "new" && !has_account)
+        .and_then(|classifier| classifier.suggest_for_transaction(info));
+    TransactionRow {
+        id: id.clone(),
+        date: info.date().format("%Y-%m-%d").to_string(),
+        amount: info.amount.amount,
+        currency: info
+            .amount
+            .iso_currency_code
+            .clone()
+            .unwrap_or_else(|| "???".to_string()),
+        description,
+        category,
+        suggestion,
+        status,
+    }
+}
@@ -1,21 +0,0 @@
-mod access_token;
-mod account;
-mod bank_connection;
-mod crypto;
-mod database;
-mod file;
-mod plaid_auth;
-mod transactions;
-mod versioned;
-
-pub use access_token::AccessToken;
-pub use account::{Account, AccountId, AccountType, BeancountAccountInfo, PlaidAccountInfo};
-pub use bank_connection::BankConnection;
-pub use crypto::{Cipher, XChaCha20Poly1305Cipher};
-pub use database::DatabaseV2;
-pub use file::DatabaseFile;
-pub use plaid_auth::DbPlaidAuth;
-pub use transactions::{
-    AddOrVerifyResult, Amount, Transaction, TransactionCategory, TransactionId, TransactionInfo,
-    Transactions,
-};
@@ -0,0 +1,68 @@
+//! Exit codes that let scripts and cron jobs branch on the result of a command
+
+use std::process::ExitCode;
+
+use crate::db::DbError;
+use crate::plaid_api::PlaidApiError;
+
+/// Any error that doesn't have a more specific exit code
+pub const ERROR: u8 = 1;
+/// A bank connection has to be linked again, e.g. because its login expired
+pub const NEEDS_RELINK: u8 = 2;
+/// `export-new` didn't find any new transactions
+pub const NOTHING_TO_EXPORT: u8 = 3;
+/// Another process holds the database lock
+pub const DB_LOCKED: u8 = 4;
+
+/// Exit code for a command that failed with `err`
+pub fn for_error(err: &anyhow::Error) -> ExitCode {
+    let code = err
+        .chain()
+        .find_map(|cause| {
+            if let Some(PlaidApiError::NeedsRelink(_)) = cause.downcast_ref() {
+                Some(NEEDS_RELINK)
+            } else if let Some(DbError::Locked(_)) = cause.downcast_ref() {
+                Some(DB_LOCKED)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(ERROR);
+    ExitCode::from(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use anyhow::{anyhow, Context as _};
+
+    use super::*;
+    use crate::db::DatabaseLocked;
+
+    #[test]
+    fn locked_database() {
+        let err = Err::<(), _>(DbError::from(DatabaseLocked {
+            lock_path: PathBuf::from("db.lock"),
+            holder_pid: None,
+        }))
+        .context("Failed to load database")
+        .unwrap_err();
+        assert_eq!(ExitCode::from(DB_LOCKED), for_error(&err));
+    }
+
+    #[test]
+    fn needs_relink() {
+        let err = anyhow::Error::from(PlaidApiError::NeedsRelink("ITEM_LOGIN_REQUIRED".into()))
+            .context("Failed to sync connection");
+        assert_eq!(ExitCode::from(NEEDS_RELINK), for_error(&err));
+    }
+
+    #[test]
+    fn other_error() {
+        assert_eq!(
+            ExitCode::from(ERROR),
+            for_error(&anyhow!("Something failed"))
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::db::{Account, BankConnection, DatabaseFile, StorageBackend};
+
+/// Statistics about a database, computed without accessing the Plaid API
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct DatabaseReport {
+    pub storage: StorageBackend,
+    pub format_version: u32,
+    pub encrypted: bool,
+    pub file_size_bytes: u64,
+    pub totals: Counts,
+    pub connections: Vec<ConnectionReport>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct ConnectionReport {
+    pub name: String,
+    pub totals: Counts,
+    pub accounts: Vec<AccountReport>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct AccountReport {
+    pub name: String,
+    /// `None` if the account isn't connected to a beancount account
+    pub beancount_account: Option<String>,
+    pub sync_enabled: bool,
+    pub counts: Counts,
+    pub pruned_transactions: usize,
+    pub first_transaction_date: Option<NaiveDate>,
+    pub last_transaction_date: Option<NaiveDate>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
+pub struct Counts {
+    pub accounts: usize,
+    pub transactions: usize,
+    pub exported: usize,
+    pub unexported: usize,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.accounts += other.accounts;
+        self.transactions += other.transactions;
+        self.exported += other.exported;
+        self.unexported += other.unexported;
+    }
+}
+
+pub fn inspect(db: &DatabaseFile) -> Result<DatabaseReport> {
+    let connections: Vec<ConnectionReport> = db
+        .database()
+        .bank_connections
+        .iter()
+        .map(inspect_connection)
+        .collect();
+    let mut totals = Counts::default();
+    for connection in &connections {
+        totals.add(connection.totals);
+    }
+    Ok(DatabaseReport {
+        storage: db.storage_backend(),
+        format_version: db.format_version(),
+        encrypted: db.is_encrypted(),
+        file_size_bytes: std::fs::metadata(db.db_path())?.len(),
+        totals,
+        connections,
+    })
+}
+
+fn inspect_connection(connection: &BankConnection) -> ConnectionReport {
+    let mut accounts: Vec<AccountReport> = connection
+        .accounts()
+        .map(|(_, account)| inspect_account(account))
+        .collect();
+    accounts.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut totals = Counts::default();
+    for account in &accounts {
+        totals.add(account.counts);
+    }
+    ConnectionReport {
+        name: connection.name().to_string(),
+        totals,
+        accounts,
+    }
+}
+
+fn inspect_account(account: &Account) -> AccountReport {
+    let mut counts = Counts {
+        accounts: 1,
+        ..Counts::default()
+    };
+    let mut report = AccountReport {
+        name: account.plaid_account_info.name.clone(),
+        beancount_account: None,
+        sync_enabled: false,
+        counts,
+        pruned_transactions: 0,
+        first_transaction_date: None,
+        last_transaction_date: None,
+    };
+    let Some(connected_account) = &account.account else {
+        return report;
+    };
+    for (_, transaction) in connected_account.transactions.iter_all_sorted_by_date() {
+        counts.transactions += 1;
+        if transaction.already_exported {
+            counts.exported += 1;
+        } else {
+            counts.unexported += 1;
+        }
+        let date = transaction.transaction.date();
+        report.first_transaction_date.get_or_insert(date);
+        report.last_transaction_date = Some(date);
+    }
+    report.beancount_account = Some(connected_account.beancount_account_info.beancount_name());
+    report.sync_enabled = connected_account.sync_enabled;
+    report.counts = counts;
+    report.pruned_transactions = connected_account.pruned_transactions.len();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{
+        AccountType, BeancountAccountInfo, PlaidAccountInfo, Transaction, TransactionBuilder,
+        TransactionId,
+    };
+
+    use super::*;
+
+    fn plaid_account_info() -> PlaidAccountInfo {
+        PlaidAccountInfo {
+            name: "Checking".to_string(),
+            official_name: None,
+            mask: None,
+            type_: "depository".to_string(),
+            subtype: None,
+        }
+    }
+
+    fn transaction(day: u32, already_exported: bool) -> Transaction {
+        TransactionBuilder::new()
+            .posted_on(2024, 1, day)
+            .exported(already_exported)
+            .build()
+    }
+
+    #[test]
+    fn connected_account() {
+        let mut account = Account::new_connected(
+            plaid_account_info(),
+            BeancountAccountInfo {
+                ty: AccountType::Assets,
+                name_parts: vec!["Bank".to_string(), "Checking".to_string()],
+            },
+        );
+        let connected_account = account.account.as_mut().unwrap();
+        for (id, day, already_exported) in [("a", 20, false), ("b", 3, true), ("c", 11, true)] {
+            let _ = connected_account.transactions.add_or_verify(
+                TransactionId(id.to_string()),
+                transaction(day, already_exported),
+            );
+        }
+
+        assert_eq!(
+            AccountReport {
+                name: "Checking".to_string(),
+                beancount_account: Some("Assets:Bank:Checking".to_string()),
+                sync_enabled: true,
+                counts: Counts {
+                    accounts: 1,
+                    transactions: 3,
+                    exported: 2,
+                    unexported: 1,
+                },
+                pruned_transactions: 0,
+                first_transaction_date: NaiveDate::from_ymd_opt(2024, 1, 3),
+                last_transaction_date: NaiveDate::from_ymd_opt(2024, 1, 20),
+            },
+            inspect_account(&account),
+        );
+    }
+
+    #[test]
+    fn unconnected_account() {
+        let account = Account::new_unconnected(plaid_account_info());
+        assert_eq!(
+            AccountReport {
+                name: "Checking".to_string(),
+                beancount_account: None,
+                sync_enabled: false,
+                counts: Counts {
+                    accounts: 1,
+                    ..Counts::default()
+                },
+                pruned_transactions: 0,
+                first_transaction_date: None,
+                last_transaction_date: None,
+            },
+            inspect_account(&account),
+        );
+    }
+}
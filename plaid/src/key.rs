@@ -0,0 +1,387 @@
+use age::secrecy::SecretString;
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use console::style;
+use std::env::VarError;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+use crate::db::{
+    unwrap_key_with_age, wrap_key_with_age, Cipher, EncryptionKey, XChaCha20Poly1305Cipher,
+    KEY_SIZE,
+};
+use crate::terminal::{prompt, prompt_hidden, prompt_select};
+
+const ENCRYPTION_KEY_ENCODER: base64::engine::general_purpose::GeneralPurpose =
+    base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+const BEANCOUNT_PLAID_KEY_ENV_VAR: &str = "BEANCOUNT_PLAID_KEY";
+
+/// Where the database encryption key comes from
+#[derive(Debug, Clone)]
+pub enum KeySource {
+    /// The `BEANCOUNT_PLAID_KEY` environment variable, or a hidden prompt if it isn't set
+    Environment,
+    /// A file only readable by its owner, containing the base64 encoded key
+    File(PathBuf),
+    /// A file containing the key encrypted to age identities, which may be kept on a hardware token
+    AgeFile {
+        key_file: PathBuf,
+        identity_file: PathBuf,
+    },
+}
+
+impl KeySource {
+    pub fn new(key_file: Option<PathBuf>, age_identity: Option<PathBuf>) -> Self {
+        match (key_file, age_identity) {
+            (Some(key_file), Some(identity_file)) => Self::AgeFile {
+                key_file,
+                identity_file,
+            },
+            (Some(key_file), None) => Self::File(key_file),
+            (None, _) => Self::Environment,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Environment => match std::env::var_os(BEANCOUNT_PLAID_KEY_ENV_VAR) {
+                Some(_) => format!("the {BEANCOUNT_PLAID_KEY_ENV_VAR} environment variable"),
+                None => "the entered encryption key".to_string(),
+            },
+            Self::File(path) => format!("the key file at {}", path.display()),
+            Self::AgeFile {
+                key_file,
+                identity_file,
+            } => format!(
+                "the key file at {} with the age identities in {}",
+                key_file.display(),
+                identity_file.display()
+            ),
+        }
+    }
+
+    pub fn load(&self) -> Result<XChaCha20Poly1305Cipher> {
+        match self {
+            Self::Environment => match load_cipher_from_environment()? {
+                Some(cipher) => Ok(cipher),
+                None => load_cipher_from_prompt(),
+            },
+            Self::File(path) => load_cipher_from_file(path),
+            Self::AgeFile {
+                key_file,
+                identity_file,
+            } => load_cipher_from_age_file(key_file, identity_file),
+        }
+    }
+
+    /// Used when creating a new database. Offers to reuse an existing key, otherwise generates a new one.
+    pub fn load_or_gen_new(&self) -> Result<XChaCha20Poly1305Cipher> {
+        match self {
+            Self::Environment => match load_cipher_from_environment() {
+                Ok(Some(cipher)) => {
+                    match prompt_select(
+                        "Found an encryption key in the BEANCOUNT_PLAID_KEY environment variable. Use it?",
+                        &["Use the environment variable", "Generate a new key"],
+                        0,
+                    )? {
+                        0 => Ok(cipher),
+                        1 => Ok(gen_new_cipher_for_environment()),
+                        _ => unreachable!(),
+                    }
+                }
+                Ok(None) | Err(_) => Ok(gen_new_cipher_for_environment()),
+            },
+            Self::File(path) => {
+                if path.exists() {
+                    load_cipher_from_file(path)
+                } else {
+                    gen_new_cipher_into_file(path)
+                }
+            }
+            Self::AgeFile {
+                key_file,
+                identity_file,
+            } => {
+                if key_file.exists() {
+                    load_cipher_from_age_file(key_file, identity_file)
+                } else {
+                    gen_new_cipher_into_age_file(key_file, identity_file)
+                }
+            }
+        }
+    }
+}
+
+fn gen_new_cipher_for_environment() -> XChaCha20Poly1305Cipher {
+    let new_key = XChaCha20Poly1305Cipher::new_key();
+    let cipher = XChaCha20Poly1305Cipher::with_key(&new_key);
+    let encoded_key = Zeroizing::new(ENCRYPTION_KEY_ENCODER.encode(new_key.as_slice()));
+    println!();
+    println!("Generated new encryption key.");
+    println!(
+        "{}",
+        style("Please set this environment variable for future runs:").bold()
+    );
+    println!(
+        "{}",
+        style(format!(
+            "{}={}",
+            BEANCOUNT_PLAID_KEY_ENV_VAR,
+            encoded_key.as_str(),
+        ))
+        .blue()
+        .bold()
+    );
+    println!();
+    cipher
+}
+
+fn gen_new_cipher_into_file(path: &Path) -> Result<XChaCha20Poly1305Cipher> {
+    let new_key = XChaCha20Poly1305Cipher::new_key();
+    let encoded_key = Zeroizing::new(format!(
+        "{}\n",
+        ENCRYPTION_KEY_ENCODER.encode(new_key.as_slice())
+    ));
+    write_key_file(path, encoded_key.as_bytes())
+        .with_context(|| format!("Failed to write key file {}", path.display()))?;
+    println!();
+    println!(
+        "Generated new encryption key and stored it in {}",
+        path.display()
+    );
+    println!(
+        "{}",
+        style("Keep a copy of it somewhere safe, the database can't be decrypted without it.")
+            .bold()
+    );
+    println!();
+    Ok(XChaCha20Poly1305Cipher::with_key(&new_key))
+}
+
+fn gen_new_cipher_into_age_file(
+    key_file: &Path,
+    identity_file: &Path,
+) -> Result<XChaCha20Poly1305Cipher> {
+    let new_key = XChaCha20Poly1305Cipher::new_key();
+    let wrapped_key = wrap_key_with_age(&new_key, identity_file, TerminalCallbacks)?;
+    write_key_file(key_file, &wrapped_key)
+        .with_context(|| format!("Failed to write key file {}", key_file.display()))?;
+    println!();
+    println!(
+        "Generated new encryption key and stored it in {}, encrypted to the age identities in {}",
+        key_file.display(),
+        identity_file.display(),
+    );
+    println!(
+        "{}",
+        style("Keep a copy of both somewhere safe, the database can't be decrypted without them.")
+            .bold()
+    );
+    println!();
+    Ok(XChaCha20Poly1305Cipher::with_key(&new_key))
+}
+
+#[cfg(unix)]
+fn write_key_file(path: &Path, content: &[u8]) -> Result<()> {
+    use std::io::Write as _;
+    use std::os::unix::fs::OpenOptionsExt as _;
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn write_key_file(path: &Path, content: &[u8]) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    std::io::Write::write_all(&mut file, content)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Returns `None` if the environment variable isn't set
+fn load_cipher_from_environment() -> Result<Option<XChaCha20Poly1305Cipher>> {
+    let key = match std::env::var(BEANCOUNT_PLAID_KEY_ENV_VAR) {
+        Ok(key) => Zeroizing::new(key),
+        Err(VarError::NotPresent) => return Ok(None),
+        Err(VarError::NotUnicode(_)) => bail!("{BEANCOUNT_PLAID_KEY_ENV_VAR} environment variable is not valid UTF-8. Please set it to the encryption key."),
+    };
+    decode_key(&key, BEANCOUNT_PLAID_KEY_ENV_VAR).map(Some)
+}
+
+/// Asking for the key means it doesn't have to be kept in a shell profile
+fn load_cipher_from_prompt() -> Result<XChaCha20Poly1305Cipher> {
+    if !console::user_attended() {
+        bail!("{BEANCOUNT_PLAID_KEY_ENV_VAR} environment variable not set and there's no terminal to ask for the key. Please set it to the encryption key.");
+    }
+    let key = prompt_hidden("Encryption key")?;
+    decode_key(key.trim(), "The entered encryption key")
+}
+
+fn load_cipher_from_file(path: &Path) -> Result<XChaCha20Poly1305Cipher> {
+    check_key_file_permissions(path)?;
+    let key = Zeroizing::new(
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key file {}", path.display()))?,
+    );
+    decode_key(key.trim(), &format!("Key file {}", path.display()))
+}
+
+fn load_cipher_from_age_file(
+    key_file: &Path,
+    identity_file: &Path,
+) -> Result<XChaCha20Poly1305Cipher> {
+    let wrapped_key = std::fs::read(key_file)
+        .with_context(|| format!("Failed to read key file {}", key_file.display()))?;
+    let key = unwrap_key_with_age(&wrapped_key, identity_file, TerminalCallbacks)?;
+    Ok(XChaCha20Poly1305Cipher::with_key(&key))
+}
+
+/// Lets age plugins ask for a PIN or for touching the hardware token
+#[derive(Clone)]
+struct TerminalCallbacks;
+
+impl age::Callbacks for TerminalCallbacks {
+    fn display_message(&self, message: &str) {
+        eprintln!("{message}");
+    }
+
+    fn confirm(&self, message: &str, yes_string: &str, no_string: Option<&str>) -> Option<bool> {
+        let options: Vec<&str> = [Some(yes_string), no_string]
+            .into_iter()
+            .flatten()
+            .collect();
+        prompt_select(message, &options, 0)
+            .ok()
+            .map(|index| index == 0)
+    }
+
+    fn request_public_string(&self, description: &str) -> Option<String> {
+        prompt(description).ok()
+    }
+
+    fn request_passphrase(&self, description: &str) -> Option<SecretString> {
+        prompt_hidden(description)
+            .ok()
+            .map(|passphrase| SecretString::from(passphrase.as_str()))
+    }
+}
+
+/// Refuse key files other users could read or modify
+#[cfg(unix)]
+fn check_key_file_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let mode = std::fs::metadata(path)
+        .with_context(|| format!("Failed to read key file {}", path.display()))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        bail!(
+            "Key file {} has permissions {:o}, but it must only be accessible by its owner. Please run `chmod 600 {}`.",
+            path.display(),
+            mode & 0o777,
+            path.display(),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_key_file_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+fn decode_key(key: &str, source: &str) -> Result<XChaCha20Poly1305Cipher> {
+    let decoded = Zeroizing::new(
+        ENCRYPTION_KEY_ENCODER
+            .decode(key)
+            .with_context(|| format!("Failed to decode {source}"))?,
+    );
+    if decoded.len() != KEY_SIZE {
+        bail!("{source} must be {KEY_SIZE} bytes long");
+    }
+    let mut key = EncryptionKey::default();
+    key.copy_from_slice(&decoded);
+    Ok(XChaCha20Poly1305Cipher::with_key(&key))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    use super::*;
+
+    const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8";
+
+    fn key_file(dir: &Path, content: &str, mode: u32) -> PathBuf {
+        let path = dir.join("key");
+        std::fs::write(&path, content).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+
+    fn roundtrip(cipher: &XChaCha20Poly1305Cipher) -> Vec<u8> {
+        let expected = decode_key(KEY, "test key").unwrap();
+        expected
+            .decrypt(&cipher.encrypt(b"plaintext").unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn load_from_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = key_file(tempdir.path(), &format!("  {KEY}\n"), 0o600);
+        let cipher = KeySource::File(path).load().unwrap();
+        assert_eq!(b"plaintext".to_vec(), roundtrip(&cipher));
+    }
+
+    #[test]
+    fn refuse_readable_by_others() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = key_file(tempdir.path(), KEY, 0o644);
+        let err = KeySource::File(path)
+            .load()
+            .map(drop)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("has permissions 644"), "{err}");
+    }
+
+    #[test]
+    fn refuse_invalid_key() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = key_file(tempdir.path(), "AAEC", 0o600);
+        let err = KeySource::File(path)
+            .load()
+            .map(drop)
+            .unwrap_err()
+            .to_string();
+        assert!(err.ends_with("must be 32 bytes long"), "{err}");
+    }
+
+    #[test]
+    fn generate_into_new_file() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("key");
+        let source = KeySource::File(path.clone());
+        let generated = source.load_or_gen_new().unwrap();
+        assert_eq!(
+            0o600,
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        );
+
+        let loaded = source.load().unwrap();
+        let ciphertext = generated.encrypt(b"plaintext").unwrap();
+        assert_eq!(b"plaintext".to_vec(), loaded.decrypt(&ciphertext).unwrap());
+    }
+}
@@ -0,0 +1,270 @@
+use anyhow::{Context as _, Result};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Names of the accounts that are opened and not closed again in the Beancount ledger at `path`,
+/// including the files it includes, sorted by name
+pub fn read_open_accounts(path: &Path) -> Result<Vec<String>> {
+    let mut opened = BTreeSet::new();
+    let mut closed = BTreeSet::new();
+    for_each_file(path, &mut BTreeSet::new(), &mut |content| {
+        for directive in content.lines().filter_map(parse_directive) {
+            match directive {
+                Directive::Open(account) => {
+                    opened.insert(account.to_string());
+                }
+                Directive::Close(account) => {
+                    closed.insert(account.to_string());
+                }
+                Directive::Include(_) => {}
+            }
+        }
+    })?;
+    Ok(opened.difference(&closed).cloned().collect())
+}
+
+/// A transaction of the Beancount ledger, with just the parts needed to learn from it
+#[derive(Debug, PartialEq, Eq)]
+pub struct LedgerTransaction {
+    pub payee: Option<String>,
+    pub narration: String,
+    /// Accounts of the postings, in order
+    pub accounts: Vec<String>,
+}
+
+/// The transactions of the Beancount ledger at `path`, including the files it includes
+pub fn read_transactions(path: &Path) -> Result<Vec<LedgerTransaction>> {
+    let mut transactions = vec![];
+    for_each_file(path, &mut BTreeSet::new(), &mut |content| {
+        transactions.extend(parse_transactions(content))
+    })?;
+    Ok(transactions)
+}
+
+/// Call `on_file` with the content of the ledger at `path` and of each file it includes
+fn for_each_file(
+    path: &Path,
+    visited: &mut BTreeSet<PathBuf>,
+    on_file: &mut impl FnMut(&str),
+) -> Result<()> {
+    // Ledgers including each other shouldn't make us loop forever
+    let canonical_path = path
+        .canonicalize()
+        .with_context(|| format!("Failed to read ledger {}", path.display()))?;
+    if !visited.insert(canonical_path) {
+        return Ok(());
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read ledger {}", path.display()))?;
+    on_file(&content);
+    for directive in content.lines().filter_map(parse_directive) {
+        if let Directive::Include(included) = directive {
+            let included = path
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join(included);
+            for_each_file(&included, visited, on_file)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Directive<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    Include(&'a str),
+}
+
+fn parse_directive(line: &str) -> Option<Directive<'_>> {
+    if let Some(included) = line.strip_prefix("include ") {
+        return Some(Directive::Include(included.trim().trim_matches('"')));
+    }
+    let mut tokens = line.split_whitespace();
+    if !is_date(tokens.next()?) {
+        return None;
+    }
+    let keyword = tokens.next()?;
+    let account = tokens.next()?;
+    match keyword {
+        "open" => Some(Directive::Open(account)),
+        "close" => Some(Directive::Close(account)),
+        _ => None,
+    }
+}
+
+fn is_date(token: &str) -> bool {
+    token.len() == 10 && token.starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn parse_transactions(content: &str) -> Vec<LedgerTransaction> {
+    let mut transactions: Vec<LedgerTransaction> = vec![];
+    // Whether the indented lines that follow belong to a transaction
+    let mut in_transaction = false;
+    for line in content.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let (true, Some(transaction), Some(account)) = (
+                in_transaction,
+                transactions.last_mut(),
+                parse_posting_account(line),
+            ) {
+                transaction.accounts.push(account.to_string());
+            }
+        } else {
+            let transaction = parse_transaction_header(line);
+            in_transaction = transaction.is_some();
+            transactions.extend(transaction);
+        }
+    }
+    transactions
+}
+
+/// Parses e.g. `2024-01-02 * "Payee" "Narration" #tag`
+fn parse_transaction_header(line: &str) -> Option<LedgerTransaction> {
+    let (date, rest) = line.split_once(char::is_whitespace)?;
+    if !is_date(date) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let rest = rest
+        .strip_prefix("txn")
+        .or_else(|| rest.strip_prefix(['*', '!']))?;
+    let mut strings = quoted_strings(rest);
+    let (payee, narration) = match strings.len() {
+        0 => (None, String::new()),
+        1 => (None, strings.remove(0)),
+        _ => {
+            let payee = strings.remove(0);
+            (Some(payee), strings.remove(0))
+        }
+    };
+    Some(LedgerTransaction {
+        payee,
+        narration,
+        accounts: vec![],
+    })
+}
+
+/// The strings in double quotes before the comment, if any
+fn quoted_strings(text: &str) -> Vec<String> {
+    let mut strings = vec![];
+    let mut current: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (&mut current, c) {
+            (None, ';') => break,
+            (None, '"') => current = Some(String::new()),
+            (None, _) => {}
+            (Some(string), '\\') => string.extend(chars.next()),
+            (Some(_), '"') => strings.extend(current.take()),
+            (Some(string), c) => string.push(c),
+        }
+    }
+    strings
+}
+
+/// The account of a posting line, `None` for other indented lines like metadata, whose keys start lowercase
+fn parse_posting_account(line: &str) -> Option<&str> {
+    let mut tokens = line.split_whitespace();
+    let mut account = tokens.next()?;
+    if account == "*" || account == "!" {
+        account = tokens.next()?;
+    }
+    (account.starts_with(|c: char| c.is_ascii_uppercase()) && account.contains(':'))
+        .then_some(account)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_directives() {
+        assert_eq!(
+            Some(Directive::Open("Assets:Bank:Checking")),
+            parse_directive("2024-01-01 open Assets:Bank:Checking USD"),
+        );
+        assert_eq!(
+            Some(Directive::Close("Assets:Bank:Checking")),
+            parse_directive("2024-06-30 close Assets:Bank:Checking"),
+        );
+        assert_eq!(
+            Some(Directive::Include("accounts/cards.beancount")),
+            parse_directive("include \"accounts/cards.beancount\""),
+        );
+        assert_eq!(
+            None,
+            parse_directive("2024-01-02 * \"Coffee\" ; open Assets:Nope")
+        );
+        assert_eq!(None, parse_directive("  Assets:Bank:Checking  -3.50 USD"));
+        assert_eq!(None, parse_directive("; 2024-01-01 open Assets:Commented"));
+    }
+
+    #[test]
+    fn parse_transaction_blocks() {
+        assert_eq!(
+            vec![
+                LedgerTransaction {
+                    payee: Some("Trader Joe's".to_string()),
+                    narration: "Weekly \"shop\"".to_string(),
+                    accounts: vec![
+                        "Liabilities:CreditCard".to_string(),
+                        "Expenses:Groceries".to_string(),
+                    ],
+                },
+                LedgerTransaction {
+                    payee: None,
+                    narration: "Paycheck".to_string(),
+                    accounts: vec![
+                        "Assets:Bank:Checking".to_string(),
+                        "Income:Salary".to_string(),
+                    ],
+                },
+            ],
+            parse_transactions(
+                "2024-01-01 open Expenses:Groceries\n\
+                 2024-01-02 * \"Trader Joe's\" \"Weekly \\\"shop\\\"\" #food ; \"comment\"\n\
+                 \x20 plaid_transaction_id: \"abc\"\n\
+                 \x20 Liabilities:CreditCard  -42.10 USD\n\
+                 \x20   plaid_category: \"FOOD_AND_DRINK.FOOD_AND_DRINK_GROCERIES\"\n\
+                 \x20 Expenses:Groceries\n\
+                 \n\
+                 2024-01-03 balance Assets:Bank:Checking 100 USD\n\
+                 \x20 Assets:Ignored  1 USD\n\
+                 2024-01-15 txn \"Paycheck\"\n\
+                 \x20 ! Assets:Bank:Checking  1000 USD\n\
+                 \x20 Income:Salary\n"
+            ),
+        );
+    }
+
+    #[test]
+    fn read_accounts_with_includes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tempdir.path().join("accounts")).unwrap();
+        std::fs::write(
+            tempdir.path().join("main.beancount"),
+            "include \"accounts/cards.beancount\"\n\
+             2020-01-01 open Assets:Bank:Checking USD\n\
+             2020-01-01 open Assets:Bank:Savings USD\n\
+             2023-01-01 close Assets:Bank:Savings\n\
+             2020-01-01 open Expenses:Groceries\n",
+        )
+        .unwrap();
+        std::fs::write(
+            tempdir.path().join("accounts/cards.beancount"),
+            "include \"../main.beancount\"\n\
+             2021-05-01 open Liabilities:CreditCard USD\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                "Assets:Bank:Checking",
+                "Expenses:Groceries",
+                "Liabilities:CreditCard"
+            ],
+            read_open_accounts(&tempdir.path().join("main.beancount")).unwrap(),
+        );
+    }
+}
@@ -1,6 +1,15 @@
+use beancount_import_core::{db, export, mapping, plaid_api, sync};
+
 pub mod args;
+mod categories;
 pub mod cli;
-mod db;
-mod export;
-mod plaid_api;
+pub mod exit_code;
+mod inspect;
+mod key;
+mod ledger;
+mod logging;
+mod paths;
+pub mod report;
+mod suggest;
 mod terminal;
+pub mod validate;
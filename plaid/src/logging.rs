@@ -0,0 +1,74 @@
+use anyhow::{Context as _, Result};
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{
+    fmt::{writer::BoxMakeWriter, MakeWriter},
+    layer::SubscriberExt as _,
+    util::SubscriberInitExt as _,
+    EnvFilter, Layer as _,
+};
+
+use crate::terminal;
+
+/// Set up logging. The level is configured with the `RUST_LOG` environment variable.
+/// It defaults to `info` when logging to `log_file` and to `error` when logging to stderr.
+pub fn init(log_file: Option<&Path>, json: bool) -> Result<()> {
+    let (writer, default_level) = match log_file {
+        Some(log_file) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_file)
+                .with_context(|| format!("Failed to open log file {}", log_file.display()))?;
+            (BoxMakeWriter::new(Mutex::new(file)), LevelFilter::INFO)
+        }
+        None => (BoxMakeWriter::new(ProgressAwareStderr), LevelFilter::ERROR),
+    };
+    let filter = EnvFilter::builder()
+        .with_default_directive(default_level.into())
+        .from_env_lossy();
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(log_file.is_none() && console::colors_enabled_stderr());
+    let layer = if json {
+        layer.json().boxed()
+    } else {
+        layer.boxed()
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(layer)
+        .try_init()?;
+    Ok(())
+}
+
+/// Writes to stderr while the progress bars are hidden, so log lines don't get mixed into them
+#[derive(Clone, Copy)]
+struct ProgressAwareStderr;
+
+impl Write for ProgressAwareStderr {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        terminal::progress().suspend(|| io::stderr().write(buf))
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        terminal::progress().suspend(|| io::stderr().write_all(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for ProgressAwareStderr {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        *self
+    }
+}
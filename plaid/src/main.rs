@@ -1,8 +1,15 @@
-use anyhow::Result;
+use std::process::ExitCode;
+
+use beancount_import_plaid::exit_code;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
+async fn main() -> ExitCode {
     let args = beancount_import_plaid::args::parse();
-    beancount_import_plaid::cli::main(args).await
+    match beancount_import_plaid::cli::main(args).await {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            exit_code::for_error(&err)
+        }
+    }
 }
@@ -0,0 +1,114 @@
+use anyhow::{anyhow, bail, Context, Result};
+use directories::ProjectDirs;
+use std::path::{Path, PathBuf};
+
+/// Where databases were stored before they moved to the platform's data directory
+const LEGACY_DB_FILENAME: &str = "beancount_plaid.db";
+
+/// The database path given on the command line, or the default one in the platform's data directory,
+/// e.g. `~/.local/share/beancount-plaid/db` on Linux. A database in the legacy location
+/// `./beancount_plaid.db` is moved to the default location.
+pub fn resolve_db_path(db_path: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(db_path) = db_path {
+        return Ok(db_path);
+    }
+    let db_path = default_db_path()?;
+    if migrate_legacy_db(Path::new("."), &db_path)? {
+        println!(
+            "Moved the database from ./{LEGACY_DB_FILENAME} to {}",
+            db_path.display()
+        );
+    }
+    Ok(db_path)
+}
+
+/// Creates the data directory if it doesn't exist yet
+fn default_db_path() -> Result<PathBuf> {
+    let dirs = ProjectDirs::from("", "", "beancount-plaid")
+        .ok_or_else(|| anyhow!("Couldn't find the home directory. Please pass --db-path."))?;
+    std::fs::create_dir_all(dirs.data_dir())
+        .with_context(|| format!("Failed to create {}", dirs.data_dir().display()))?;
+    Ok(dirs.data_dir().join("db"))
+}
+
+/// Move the legacy database in `legacy_dir` and the files next to it (backups, undo history) to `db_path`,
+/// unless there already is a database at `db_path`. Returns whether something was moved.
+fn migrate_legacy_db(legacy_dir: &Path, db_path: &Path) -> Result<bool> {
+    let legacy_db_path = legacy_dir.join(LEGACY_DB_FILENAME);
+    if !legacy_db_path.exists() || db_path.exists() {
+        return Ok(false);
+    }
+    let mut files = vec![];
+    for entry in std::fs::read_dir(legacy_dir)? {
+        let filename = entry?.file_name();
+        if let Some(suffix) = filename
+            .to_str()
+            .and_then(|filename| filename.strip_prefix(LEGACY_DB_FILENAME))
+        {
+            if suffix == ".lock" {
+                bail!(
+                    "The database at {} is in use by another process, can't move it to {}",
+                    legacy_db_path.display(),
+                    db_path.display()
+                );
+            }
+            files.push(suffix.to_string());
+        }
+    }
+
+    // Move the database last so an interrupted migration is retried on the next run
+    files.sort_by_key(|suffix| suffix.is_empty());
+    for suffix in files {
+        let from = legacy_dir.join(format!("{LEGACY_DB_FILENAME}{suffix}"));
+        let to = sibling_path(db_path, &suffix);
+        std::fs::rename(&from, &to)
+            .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))?;
+    }
+    Ok(true)
+}
+
+fn sibling_path(db_path: &Path, suffix: &str) -> PathBuf {
+    let mut filename = db_path.file_name().unwrap_or_default().to_owned();
+    filename.push(suffix);
+    db_path.with_file_name(filename)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_legacy_db_with_backups() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let legacy_dir = tempdir.path().join("cwd");
+        std::fs::create_dir(&legacy_dir).unwrap();
+        for filename in ["beancount_plaid.db", "beancount_plaid.db.bak.1", "other"] {
+            std::fs::write(legacy_dir.join(filename), filename).unwrap();
+        }
+        let db_path = tempdir.path().join("db");
+
+        assert!(migrate_legacy_db(&legacy_dir, &db_path).unwrap());
+        assert_eq!(
+            "beancount_plaid.db",
+            std::fs::read_to_string(&db_path).unwrap()
+        );
+        assert_eq!(
+            "beancount_plaid.db.bak.1",
+            std::fs::read_to_string(db_path.with_file_name("db.bak.1")).unwrap()
+        );
+        assert!(!legacy_dir.join("beancount_plaid.db").exists());
+        assert!(legacy_dir.join("other").exists());
+    }
+
+    #[test]
+    fn keep_existing_db() {
+        let tempdir = tempfile::tempdir().unwrap();
+        std::fs::write(tempdir.path().join("beancount_plaid.db"), "legacy").unwrap();
+        let db_path = tempdir.path().join("db");
+        std::fs::write(&db_path, "existing").unwrap();
+
+        assert!(!migrate_legacy_db(tempdir.path(), &db_path).unwrap());
+        assert_eq!("existing", std::fs::read_to_string(&db_path).unwrap());
+        assert!(tempdir.path().join("beancount_plaid.db").exists());
+    }
+}
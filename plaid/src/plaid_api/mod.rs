@@ -1,13 +0,0 @@
-mod accounts;
-mod categories;
-mod client;
-mod link_account;
-mod test_connection;
-mod transactions;
-
-pub use accounts::get_accounts;
-// pub use categories::lookup_category;
-pub use client::Plaid;
-pub use link_account::link_new_account;
-pub use test_connection::test_connection;
-pub use transactions::get_transactions;
@@ -0,0 +1,205 @@
+use chrono::{Datelike as _, NaiveDate};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::db::{
+    BeancountAccountInfo, IgnoreList, Transaction, TransactionId, TransactionInfo,
+    TransactionOverrides,
+};
+
+/// What the totals of a spending report are computed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportGroupBy {
+    /// The Beancount account a transaction was assigned to with `transaction recategorize`,
+    /// otherwise its primary Plaid category like FOOD_AND_DRINK
+    Category,
+    /// The Beancount account of the bank account the transaction belongs to
+    Account,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportPeriod {
+    Month,
+    Quarter,
+    Year,
+}
+
+impl ReportPeriod {
+    fn of(self, date: NaiveDate) -> String {
+        match self {
+            Self::Month => format!("{}-{:02}", date.year(), date.month()),
+            Self::Quarter => format!("{}-Q{}", date.year(), date.month0() / 3 + 1),
+            Self::Year => date.year().to_string(),
+        }
+    }
+}
+
+/// Total of all transactions in one period and group. Amounts in different currencies are summed up separately.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReportRow {
+    pub period: String,
+    pub group: String,
+    pub currency: String,
+    pub num_transactions: usize,
+    pub total: Decimal,
+}
+
+/// Sum up `transactions`, sorted by period and group. Ignored transactions are skipped.
+pub fn report<'a>(
+    transactions: impl Iterator<Item = (&'a BeancountAccountInfo, &'a TransactionId, &'a Transaction)>,
+    overrides: &HashMap<TransactionId, TransactionOverrides>,
+    ignore_list: &IgnoreList,
+    group_by: ReportGroupBy,
+    period: ReportPeriod,
+) -> Vec<ReportRow> {
+    let mut totals: BTreeMap<(String, String, String), (usize, Decimal)> = BTreeMap::new();
+    for (account, transaction_id, transaction) in transactions {
+        let info = &transaction.transaction;
+        if ignore_list.is_ignored(transaction_id, info) {
+            continue;
+        }
+        let group = match group_by {
+            ReportGroupBy::Category => category_group(info, overrides.get(transaction_id)),
+            ReportGroupBy::Account => account.beancount_name(),
+        };
+        let currency = info
+            .amount
+            .iso_currency_code
+            .clone()
+            .unwrap_or_else(|| "[UKN]".to_string());
+        let (num_transactions, total) = totals
+            .entry((period.of(info.date()), group, currency))
+            .or_default();
+        *num_transactions += 1;
+        *total += info.amount.amount;
+    }
+    totals
+        .into_iter()
+        .map(
+            |((period, group, currency), (num_transactions, total))| ReportRow {
+                period,
+                group,
+                currency,
+                num_transactions,
+                total,
+            },
+        )
+        .collect()
+}
+
+fn category_group(info: &TransactionInfo, overrides: Option<&TransactionOverrides>) -> String {
+    if let Some(account) = overrides.and_then(|overrides| overrides.account.as_ref()) {
+        return account.beancount_name();
+    }
+    overrides
+        .and_then(|overrides| overrides.category.as_ref())
+        .or(info.category.as_ref())
+        .map(|category| category.primary.clone())
+        .unwrap_or_else(|| "(uncategorized)".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{AccountType, TransactionBuilder};
+
+    use super::*;
+
+    fn checking() -> BeancountAccountInfo {
+        BeancountAccountInfo {
+            ty: AccountType::Assets,
+            name_parts: vec!["Checking".to_string()],
+        }
+    }
+
+    fn transaction(month: u32, amount: i64, category: Option<&str>) -> Transaction {
+        TransactionBuilder::new()
+            .posted_on(2024, month, 15)
+            .cents(amount)
+            .primary_category(category)
+            .build()
+    }
+
+    fn row(period: &str, group: &str, num_transactions: usize, total: i64) -> ReportRow {
+        ReportRow {
+            period: period.to_string(),
+            group: group.to_string(),
+            currency: "USD".to_string(),
+            num_transactions,
+            total: Decimal::new(total, 2),
+        }
+    }
+
+    #[test]
+    fn group_by_category_and_month() {
+        let account = checking();
+        let transactions = [
+            ("a", transaction(1, -500, Some("FOOD_AND_DRINK"))),
+            ("b", transaction(1, -250, Some("FOOD_AND_DRINK"))),
+            ("c", transaction(2, -100, Some("FOOD_AND_DRINK"))),
+            ("d", transaction(2, -300, None)),
+            ("e", transaction(2, -700, Some("TRAVEL"))),
+            ("f", transaction(2, -900, Some("TRAVEL"))),
+        ]
+        .map(|(id, transaction)| (TransactionId(id.to_string()), transaction));
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            TransactionId("e".to_string()),
+            TransactionOverrides {
+                account: Some(BeancountAccountInfo {
+                    ty: AccountType::Expenses,
+                    name_parts: vec!["Vacation".to_string()],
+                }),
+                ..Default::default()
+            },
+        );
+        let mut ignore_list = IgnoreList::default();
+        ignore_list.ignore_transaction(TransactionId("f".to_string()));
+
+        let rows = report(
+            transactions
+                .iter()
+                .map(|(id, transaction)| (&account, id, transaction)),
+            &overrides,
+            &ignore_list,
+            ReportGroupBy::Category,
+            ReportPeriod::Month,
+        );
+        assert_eq!(
+            vec![
+                row("2024-01", "FOOD_AND_DRINK", 2, -750),
+                row("2024-02", "(uncategorized)", 1, -300),
+                row("2024-02", "Expenses:Vacation", 1, -700),
+                row("2024-02", "FOOD_AND_DRINK", 1, -100),
+            ],
+            rows,
+        );
+    }
+
+    #[test]
+    fn group_by_account_and_quarter() {
+        let account = checking();
+        let transactions = [
+            ("a", transaction(1, -500, None)),
+            ("b", transaction(3, 250, None)),
+            ("c", transaction(4, -100, None)),
+        ]
+        .map(|(id, transaction)| (TransactionId(id.to_string()), transaction));
+
+        let rows = report(
+            transactions
+                .iter()
+                .map(|(id, transaction)| (&account, id, transaction)),
+            &HashMap::new(),
+            &IgnoreList::default(),
+            ReportGroupBy::Account,
+            ReportPeriod::Quarter,
+        );
+        assert_eq!(
+            vec![
+                row("2024-Q1", "Assets:Checking", 2, -250),
+                row("2024-Q2", "Assets:Checking", 1, -100),
+            ],
+            rows,
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::db::TransactionInfo;
+use crate::ledger::LedgerTransaction;
+
+/// Suggests counter accounts for new transactions from the accounts that transactions with the same words in their
+/// payee or narration were booked to in the ledger
+#[derive(Debug, Default)]
+pub struct Classifier {
+    /// How often each counter account was used in transactions containing the word
+    accounts_by_token: HashMap<String, HashMap<String, usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub account: String,
+    /// Between 0 and 1. Words seen only a few times or with different accounts give a low confidence.
+    pub confidence: f64,
+}
+
+impl Classifier {
+    /// Learns from the postings to `Expenses:` and `Income:` accounts, the other accounts are the bank accounts
+    pub fn train<'a>(transactions: impl IntoIterator<Item = &'a LedgerTransaction>) -> Self {
+        let mut classifier = Self::default();
+        for transaction in transactions {
+            let counter_accounts: Vec<&String> = transaction
+                .accounts
+                .iter()
+                .filter(|account| {
+                    account.starts_with("Expenses:") || account.starts_with("Income:")
+                })
+                .collect();
+            if counter_accounts.is_empty() {
+                continue;
+            }
+            let text = format!(
+                "{} {}",
+                transaction.payee.as_deref().unwrap_or_default(),
+                transaction.narration
+            );
+            // Payee and narration often repeat the same words
+            for token in tokens(&text).collect::<HashSet<_>>() {
+                let accounts = classifier.accounts_by_token.entry(token).or_default();
+                for account in &counter_accounts {
+                    *accounts.entry(account.to_string()).or_default() += 1;
+                }
+            }
+        }
+        classifier
+    }
+
+    /// The most likely counter account for a transaction with this description, `None` if none of its words are known
+    pub fn suggest(&self, description: &str) -> Option<Suggestion> {
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        let mut num_known_tokens = 0;
+        for token in tokens(description).collect::<HashSet<_>>() {
+            let Some(accounts) = self.accounts_by_token.get(&token) else {
+                continue;
+            };
+            num_known_tokens += 1;
+            // The +1 keeps words that were only seen once from being fully trusted
+            let total = accounts.values().sum::<usize>() as f64 + 1.0;
+            for (account, count) in accounts {
+                *scores.entry(account.as_str()).or_default() += *count as f64 / total;
+            }
+        }
+        let (account, score) = scores
+            .into_iter()
+            // Ties are broken by name so suggestions don't change between runs
+            .max_by(|(a, a_score), (b, b_score)| a_score.total_cmp(b_score).then(b.cmp(a)))?;
+        Some(Suggestion {
+            account: account.to_string(),
+            confidence: score / num_known_tokens as f64,
+        })
+    }
+
+    /// Like [Classifier::suggest], with the merchant name and description of a Plaid transaction, which are exported
+    /// as payee and narration
+    pub fn suggest_for_transaction(&self, transaction: &TransactionInfo) -> Option<Suggestion> {
+        let description = [
+            &transaction.merchant_name,
+            &transaction.description_or_merchant_name,
+        ]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+        self.suggest(&description)
+    }
+}
+
+/// Lowercase words of the text. Words with digits are left out, they're mostly store numbers and dates.
+fn tokens(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| token.len() > 1 && !token.contains(|c: char| c.is_ascii_digit()))
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(payee: &str, narration: &str, counter_account: &str) -> LedgerTransaction {
+        LedgerTransaction {
+            payee: Some(payee.to_string()),
+            narration: narration.to_string(),
+            accounts: vec![
+                "Liabilities:CreditCard".to_string(),
+                counter_account.to_string(),
+            ],
+        }
+    }
+
+    #[test]
+    fn suggest_most_used_account() {
+        let transactions = [
+            transaction("Safeway", "SAFEWAY #1234", "Expenses:Groceries"),
+            transaction("Safeway", "SAFEWAY #5678", "Expenses:Groceries"),
+            transaction("Safeway", "SAFEWAY FUEL", "Expenses:Car:Fuel"),
+            transaction("Shell", "SHELL OIL 5555", "Expenses:Car:Fuel"),
+        ];
+        let classifier = Classifier::train(&transactions);
+
+        let suggestion = classifier.suggest("SAFEWAY #9999").unwrap();
+        assert_eq!("Expenses:Groceries", suggestion.account);
+        assert!((suggestion.confidence - 0.5).abs() < 1e-9);
+
+        let suggestion = classifier.suggest("SAFEWAY FUEL 42").unwrap();
+        assert_eq!("Expenses:Car:Fuel", suggestion.account);
+
+        assert_eq!(None, classifier.suggest("UNKNOWN STORE 1"));
+    }
+
+    #[test]
+    fn only_learn_counter_accounts() {
+        let transactions = [LedgerTransaction {
+            payee: None,
+            narration: "Transfer to savings".to_string(),
+            accounts: vec![
+                "Assets:Bank:Checking".to_string(),
+                "Assets:Bank:Savings".to_string(),
+            ],
+        }];
+        assert_eq!(None, Classifier::train(&transactions).suggest("Transfer"));
+    }
+
+    #[test]
+    fn split_into_lowercase_words() {
+        assert_eq!(
+            vec!["amazon", "mktpl", "seattle", "wa"],
+            tokens("AMAZON MKTPL*2K4 Seattle, WA 12/01").collect::<Vec<_>>()
+        );
+    }
+}
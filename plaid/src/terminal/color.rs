@@ -0,0 +1,29 @@
+/// Whether output is styled with colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorMode {
+    /// Colors on terminals, unless the NO_COLOR environment variable is set
+    Auto,
+    Always,
+    Never,
+}
+
+/// Configure the styling of stdout and stderr, including prompts and log messages.
+/// See https://no-color.org for NO_COLOR.
+pub fn set_color_mode(mode: ColorMode) {
+    let enabled = match mode {
+        ColorMode::Auto => {
+            if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                Some(false)
+            } else {
+                // console detects terminals itself
+                None
+            }
+        }
+        ColorMode::Always => Some(true),
+        ColorMode::Never => Some(false),
+    };
+    if let Some(enabled) = enabled {
+        console::set_colors_enabled(enabled);
+        console::set_colors_enabled_stderr(enabled);
+    }
+}
@@ -0,0 +1,35 @@
+use anyhow::{bail, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::prompt::prompt_yes_no;
+
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+static FORCE: AtomicBool = AtomicBool::new(false);
+
+/// Answer yes/no prompts with yes and selections with their default, e.g. for cron jobs.
+/// Operations that lose unexported transactions are still refused unless `force` is set as well.
+pub fn set_assume_yes(assume_yes: bool, force: bool) {
+    ASSUME_YES.store(assume_yes, Ordering::Relaxed);
+    FORCE.store(force, Ordering::Relaxed);
+}
+
+pub fn assume_yes() -> bool {
+    ASSUME_YES.load(Ordering::Relaxed)
+}
+
+/// Ask before doing something that can't be undone easily. Without a terminal, this needs `--yes`.
+pub fn confirm(prompt: &str) -> Result<bool> {
+    if !assume_yes() && !console::user_attended() {
+        bail!("{prompt} Pass --yes to confirm without a terminal");
+    }
+    prompt_yes_no(prompt)
+}
+
+/// Like [confirm], but for operations that lose transactions that weren't exported yet.
+/// `--yes` alone doesn't confirm these, they also need `--force`.
+pub fn confirm_data_loss(prompt: &str) -> Result<bool> {
+    if assume_yes() && !FORCE.load(Ordering::Relaxed) {
+        bail!("{prompt} Refusing to do this with --yes alone, pass --force as well");
+    }
+    confirm(prompt)
+}
@@ -0,0 +1,19 @@
+use tokio_util::sync::CancellationToken;
+
+/// Exit code of a process that was interrupted by Ctrl+C
+const INTERRUPTED: i32 = 130;
+
+/// Cancel `cancel` on the first Ctrl+C, so a running sync stops without saving a half synced database.
+/// Ctrl+C no longer kills the process after this, a second Ctrl+C exits right away.
+pub fn cancel_on_ctrl_c(cancel: CancellationToken) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("Cancelling, press Ctrl+C again to exit immediately");
+        cancel.cancel();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(INTERRUPTED);
+        }
+    });
+}
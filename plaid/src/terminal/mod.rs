@@ -1,5 +1,17 @@
 mod bullet_points;
+mod color;
+mod confirm;
+mod ctrl_c;
+mod progress;
 mod prompt;
+mod qr_code;
+mod quiet;
 
 pub use bullet_points::{BulletPointPrinter, LineWriter};
-pub use prompt::{prompt, prompt_select, prompt_yes_no};
+pub use color::{set_color_mode, ColorMode};
+pub use confirm::{assume_yes, confirm, confirm_data_loss, set_assume_yes};
+pub use ctrl_c::cancel_on_ctrl_c;
+pub use progress::{add_progress_bar, progress};
+pub use prompt::{prompt, prompt_fuzzy_select, prompt_hidden, prompt_select, prompt_yes_no};
+pub use qr_code::print_qr_code;
+pub use quiet::{is_quiet, print_status, set_quiet};
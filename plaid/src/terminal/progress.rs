@@ -0,0 +1,21 @@
+use std::sync::LazyLock;
+
+use indicatif::{MultiProgress, ProgressBar};
+
+use super::is_quiet;
+
+static PROGRESS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+/// Progress bars shown on stderr. Log messages go through it as well so they don't garble the progress bars.
+pub fn progress() -> &'static MultiProgress {
+    &PROGRESS
+}
+
+/// Show `progress_bar` below the other progress bars. It's hidden in quiet mode and when stdout isn't a terminal,
+/// e.g. in cron jobs or when the output is piped, so captured output doesn't fill up with spinner frames.
+pub fn add_progress_bar(progress_bar: ProgressBar) -> ProgressBar {
+    if is_quiet() || !console::Term::stdout().is_term() {
+        return ProgressBar::hidden();
+    }
+    progress().add(progress_bar)
+}
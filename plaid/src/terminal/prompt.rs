@@ -1,5 +1,8 @@
 use anyhow::Result;
-use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, Password, Select};
+use zeroize::Zeroizing;
+
+use super::confirm::assume_yes;
 
 pub fn prompt(prompt: &str) -> Result<String> {
     Ok(Input::with_theme(&ColorfulTheme::default())
@@ -7,13 +10,45 @@ pub fn prompt(prompt: &str) -> Result<String> {
         .interact()?)
 }
 
+/// Doesn't echo the input, for secrets
+pub fn prompt_hidden(prompt: &str) -> Result<Zeroizing<String>> {
+    Ok(Zeroizing::new(
+        Password::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .interact()?,
+    ))
+}
+
+/// Always yes with `--yes`
 pub fn prompt_yes_no(prompt: &str) -> Result<bool> {
+    if assume_yes() {
+        eprintln!("{prompt} yes");
+        return Ok(true);
+    }
     Ok(Confirm::with_theme(&ColorfulTheme::default())
         .with_prompt(prompt)
         .interact()?)
 }
 
+/// Like [prompt_select], but the options can be filtered by typing
+pub fn prompt_fuzzy_select(prompt: &str, options: &[String], default: usize) -> Result<usize> {
+    if assume_yes() {
+        eprintln!("{prompt}: {}", options[default]);
+        return Ok(default);
+    }
+    Ok(FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(options)
+        .default(default)
+        .interact()?)
+}
+
+/// Always the default with `--yes`
 pub fn prompt_select(prompt: &str, options: &[&str], default: usize) -> Result<usize> {
+    if assume_yes() {
+        eprintln!("{prompt}: {}", options[default]);
+        return Ok(default);
+    }
     Ok(Select::with_theme(&ColorfulTheme::default())
         .with_prompt(prompt)
         .items(&options)
@@ -0,0 +1,15 @@
+use anyhow::Result;
+use qrcode::{render::unicode::Dense1x2, QrCode};
+
+/// Print `text` as a QR code made of block characters, e.g. so a URL can be opened on a phone
+pub fn print_qr_code(text: &str) -> Result<()> {
+    let code = QrCode::new(text)?;
+    // Inverted, so the code has light modules on a dark background in terminals with a dark theme
+    let rendered = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    println!("{rendered}");
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress decorative output like headers, progress spinners and totals, e.g. for cron jobs.
+/// Results like exported transactions, prompts and warnings are still printed.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Print a status message to stdout unless in quiet mode
+pub fn print_status(message: impl std::fmt::Display) {
+    if !is_quiet() {
+        println!("{message}");
+    }
+}
@@ -0,0 +1,173 @@
+//! Checking exports with a Beancount validator before the transactions are marked as exported, see `--validate-with`
+
+use anyhow::{bail, Context as _, Result};
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write as _};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Tool that checks the ledger after an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Validator {
+    /// Run `bean-check` of the Beancount installation on the PATH
+    BeanCheck,
+}
+
+/// Export that was appended to an output file and can be taken back, e.g. because the database couldn't be saved
+#[must_use]
+pub struct AppendedExport {
+    path: PathBuf,
+    /// `None` if the output file didn't exist before
+    original_len: Option<u64>,
+}
+
+impl AppendedExport {
+    /// Restore the output file as it was before the export was appended
+    pub fn revert(self) -> Result<()> {
+        match self.original_len {
+            Some(len) => OpenOptions::new()
+                .write(true)
+                .open(&self.path)
+                .and_then(|file| file.set_len(len)),
+            None => std::fs::remove_file(&self.path),
+        }
+        .with_context(|| format!("Failed to restore {}", self.path.display()))
+    }
+}
+
+/// Append `output` to `output_file` and check it with `validator`. If given, `ledger` is checked instead of the output
+/// file, it should include the output file so the accounts of the transactions are opened.
+/// If the check fails, the output file with the export is kept as `<output_file>.rejected`, the output file is
+/// restored and the error has the messages of the validator with the directives they are about.
+pub fn append_validated(
+    validator: Validator,
+    output_file: &Path,
+    output: &[u8],
+    ledger: Option<&Path>,
+) -> Result<AppendedExport> {
+    let checked = ledger.unwrap_or(output_file);
+    append_checked(output_file, output, || match validator {
+        Validator::BeanCheck => run_bean_check(checked),
+    })
+}
+
+/// `check` returns the messages of the validator if it rejected the ledger
+fn append_checked(
+    output_file: &Path,
+    output: &[u8],
+    check: impl FnOnce() -> Result<Option<String>>,
+) -> Result<AppendedExport> {
+    let appended = append_export(output_file, output)?;
+    let errors = match check() {
+        Ok(None) => return Ok(appended),
+        Ok(Some(errors)) => errors,
+        Err(err) => {
+            appended.revert()?;
+            return Err(err);
+        }
+    };
+    let rejected_file = rejected_path(output_file);
+    std::fs::copy(output_file, &rejected_file)
+        .with_context(|| format!("Failed to write {}", rejected_file.display()))?;
+    appended.revert()?;
+    bail!(
+        "The export was rejected, the transactions weren't marked as exported. \
+        The output file with the export was kept as {}.\n{errors}",
+        rejected_file.display()
+    );
+}
+
+/// Append `output` to the file at `path` and sync it to disk. The file is restored if that fails.
+pub fn append_export(path: &Path, output: &[u8]) -> Result<AppendedExport> {
+    let original_len = match std::fs::metadata(path) {
+        Ok(metadata) => Some(metadata.len()),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => return Err(err).with_context(|| format!("Failed to open {}", path.display())),
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let appended = AppendedExport {
+        path: path.to_path_buf(),
+        original_len,
+    };
+    if let Err(err) = file.write_all(output).and_then(|()| file.sync_all()) {
+        appended.revert()?;
+        return Err(err).with_context(|| format!("Failed to write {}", path.display()));
+    }
+    Ok(appended)
+}
+
+fn rejected_path(output_file: &Path) -> PathBuf {
+    let mut path = OsString::from(output_file);
+    path.push(".rejected");
+    PathBuf::from(path)
+}
+
+fn run_bean_check(ledger: &Path) -> Result<Option<String>> {
+    let output = Command::new("bean-check")
+        .arg(ledger)
+        .output()
+        .context("Failed to run bean-check, is Beancount installed?")?;
+    if output.status.success() {
+        return Ok(None);
+    }
+    // Each error has the location and the text of the offending directive
+    let messages = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(Some(messages.trim_end().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_accepted_export() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let output_file = tempdir.path().join("new.beancount");
+        std::fs::write(&output_file, "old\n").unwrap();
+        let appended = append_checked(&output_file, b"new\n", || Ok(None)).unwrap();
+        assert_eq!("old\nnew\n", std::fs::read_to_string(&output_file).unwrap());
+
+        // Taken back if the database can't be saved
+        appended.revert().unwrap();
+        assert_eq!("old\n", std::fs::read_to_string(&output_file).unwrap());
+    }
+
+    #[test]
+    fn move_rejected_export() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let output_file = tempdir.path().join("new.beancount");
+        std::fs::write(&output_file, "old\n").unwrap();
+        let err = append_checked(&output_file, b"new\n", || {
+            Ok(Some("new.beancount:2: Invalid token\n\n   new".to_string()))
+        })
+        .err()
+        .unwrap();
+        assert!(
+            err.to_string().ends_with("Invalid token\n\n   new"),
+            "{err}"
+        );
+        assert_eq!("old\n", std::fs::read_to_string(&output_file).unwrap());
+        assert_eq!(
+            "old\nnew\n",
+            std::fs::read_to_string(tempdir.path().join("new.beancount.rejected")).unwrap()
+        );
+    }
+
+    #[test]
+    fn remove_new_output_file_if_validator_fails() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let output_file = tempdir.path().join("new.beancount");
+        let result = append_checked(&output_file, b"new\n", || bail!("bean-check not found"));
+        assert!(result.is_err());
+        assert!(!output_file.exists());
+    }
+}
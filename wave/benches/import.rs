@@ -0,0 +1,97 @@
+use beancount_import_wave::import::load as load_wave;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rust_decimal::Decimal;
+use std::fmt::Write;
+
+const NUM_ACCOUNTS: usize = 20;
+const NUM_POSTINGS_PER_ACCOUNT: usize = 500;
+
+fn format_amount(amount: Decimal) -> String {
+    if amount.is_sign_negative() {
+        format!("-${}", -amount)
+    } else {
+        format!("${}", amount)
+    }
+}
+
+/// Generate a Wave CSV export with [NUM_ACCOUNTS] accounts of [NUM_POSTINGS_PER_ACCOUNT] postings each
+fn generate_wave_csv() -> String {
+    let mut csv = String::from(
+        "Account Transactions
+Personal
+Date Range: 2024-01-01 to 2024-12-31
+Report Type: Accrual (Paid & Unpaid)
+ACCOUNT NUMBER,DATE,DESCRIPTION,DEBIT (In Business Currency),CREDIT (In Business Currency),BALANCE (In Business Currency)
+",
+    );
+    for account in 0..NUM_ACCOUNTS {
+        if account > 0 {
+            csv.push_str("\"\"\n");
+        }
+        let starting_balance = Decimal::new(1_000_000, 2);
+        let mut balance = starting_balance;
+        let mut total_debit = Decimal::ZERO;
+        let mut total_credit = Decimal::ZERO;
+        writeln!(csv, ",Account {account},,,,").unwrap();
+        writeln!(
+            csv,
+            "Starting Balance,,,,,{}",
+            format_amount(starting_balance)
+        )
+        .unwrap();
+        for posting in 0..NUM_POSTINGS_PER_ACCOUNT {
+            let date = format!("2024-{:02}-{:02}", posting % 12 + 1, posting % 28 + 1);
+            let amount = Decimal::new((posting as i64 * 137) % 10_000 + 1, 2);
+            if posting % 2 == 0 {
+                balance += amount;
+                total_debit += amount;
+                writeln!(
+                    csv,
+                    ",{date},\"Deposit, number {posting}\",{},,{}",
+                    format_amount(amount),
+                    format_amount(balance)
+                )
+                .unwrap();
+            } else {
+                balance -= amount;
+                total_credit += amount;
+                writeln!(
+                    csv,
+                    ",{date},Withdrawal {posting},,{},{}",
+                    format_amount(amount),
+                    format_amount(balance)
+                )
+                .unwrap();
+            }
+        }
+        writeln!(
+            csv,
+            "Totals and Ending Balance,,,{},{},{}",
+            format_amount(total_debit),
+            format_amount(total_credit),
+            format_amount(balance)
+        )
+        .unwrap();
+        writeln!(
+            csv,
+            "Balance Change,,,{},,",
+            format_amount(balance - starting_balance)
+        )
+        .unwrap();
+    }
+    csv
+}
+
+fn bench_import(c: &mut Criterion) {
+    let csv = generate_wave_csv();
+    let mut group = c.benchmark_group("wave");
+    group.throughput(Throughput::Bytes(csv.len() as u64));
+    group.sample_size(10);
+    group.bench_function("import", |b| {
+        b.iter(|| load_wave(black_box(csv.as_bytes()), Decimal::ZERO, false).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_import);
+criterion_main!(benches);
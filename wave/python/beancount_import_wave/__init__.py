@@ -0,0 +1,5 @@
+"""Convert exports from Wave and other sources to Beancount, see `beancount_import_wave.beangulp` for beangulp."""
+
+from ._native import convert_wave, load_wave
+
+__all__ = ["convert_wave", "load_wave"]
@@ -0,0 +1,72 @@
+"""The import sources of this package as beangulp importers.
+
+    import beangulp
+    from beancount_import_wave.beangulp import Importer
+
+    importers = [
+        Importer("wave", "Assets:Business:Checking", "wave_accounts.yaml", rounding_tolerance="0.01"),
+        Importer("paypal", "Assets:PayPal", "paypal_accounts.yaml", currency="EUR", date_format="%d.%m.%Y"),
+    ]
+
+    if __name__ == "__main__":
+        beangulp.Ingest(importers)()
+
+The sources are `wave`, `csv-import` (with `schema`), `camt053`, `paypal` and `venmo`. The other options are the
+options of the commands of the same name. The config maps the account names of the source to Beancount accounts,
+like the config the command line tool asks for.
+"""
+
+from os import PathLike
+from typing import Optional
+
+import beangulp
+from beancount.core import data
+from beancount.parser import parser
+
+from . import _native
+
+
+class Importer(beangulp.Importer):
+    def __init__(
+        self,
+        source: str,
+        account: str,
+        config: PathLike,
+        script: Optional[PathLike] = None,
+        **options,
+    ) -> None:
+        """`account` is the account beangulp files the imported documents under. `script` is a Rhai script that can
+        change or skip transactions, like `--script` of the command line tool."""
+        self.source = source
+        self._account = account
+        self.config = config
+        self.script = script
+        self.options = options
+
+    @property
+    def name(self) -> str:
+        return f"beancount_import_wave.{self.source}"
+
+    def identify(self, filepath: str) -> bool:
+        return _native.identify(self.source, filepath, **self.options)
+
+    def account(self, filepath: str) -> data.Account:
+        return self._account
+
+    def file_account(self, file) -> data.Account:
+        """`account` under its name in the importer protocol of beancount.ingest"""
+        return self._account
+
+    def extract(self, filepath: str, existing: data.Entries) -> data.Entries:
+        entries = []
+        for text, source in _native.extract(
+            self.source, filepath, self.config, script=self.script, **self.options
+        ):
+            parsed, errors, _ = parser.parse_string(text)
+            if errors:
+                raise ValueError(f"Failed to parse the transaction for {source}: {errors}")
+            for entry in parsed:
+                # Shown by beangulp next to the extracted entry
+                entry.meta["__source__"] = source
+            entries.extend(parsed)
+        return entries
@@ -0,0 +1,446 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use chrono::NaiveDate;
+use common_macros::hash_map;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::csv_import::parse_amount;
+use crate::ir::{AccountInfo, Amount, Dates, Ledger, Posting, Transaction};
+
+const CURRENCY: &str = "USD";
+const DATE_FORMAT: &str = "%m/%d/%y";
+const TAX_ACCOUNT: &str = "Amazon Tax";
+const SHIPPING_ACCOUNT: &str = "Amazon Shipping";
+const PROMOTIONS_ACCOUNT: &str = "Amazon Promotions";
+
+/// A row of the "Orders and shipments" report. Amazon charges the card once per shipment, so each row
+/// corresponds to one credit card transaction.
+#[derive(Debug)]
+struct Shipment {
+    order_id: String,
+    shipment_date: NaiveDate,
+    payment_instrument: String,
+    shipping: Decimal,
+    promotions: Decimal,
+    tax: Decimal,
+    total: Decimal,
+}
+
+/// A row of the "Items" report
+#[derive(Debug)]
+struct Item {
+    order_id: String,
+    shipment_date: NaiveDate,
+    title: String,
+    category: String,
+    asin: String,
+    quantity: u32,
+    subtotal: Decimal,
+}
+
+/// A row of the "Refunds" report
+#[derive(Debug)]
+struct Refund {
+    order_id: String,
+    refund_date: NaiveDate,
+    title: String,
+    category: String,
+    amount: Decimal,
+    tax: Decimal,
+}
+
+/// Load Amazon order history reports. Every shipment becomes a balanced transaction with one posting per item
+/// plus shipping, promotions and tax, paid from the payment instrument. The payment posting carries the order id as
+/// `amazon_order_id` metadata. This doesn't match the transactions against the card charges imported from the bank,
+/// so the matching "AMZN Mktp" charges have to be removed from those ledgers by hand to avoid double counting.
+pub fn load(
+    orders_stream: impl Read,
+    items_stream: impl Read,
+    refunds_stream: Option<impl Read>,
+) -> Result<Ledger> {
+    let shipments = parse_csv(orders_stream, parse_shipment).context("Failed to parse orders")?;
+    let items = parse_csv(items_stream, parse_item).context("Failed to parse items")?;
+    let refunds = refunds_stream
+        .map(|stream| parse_csv(stream, parse_refund).context("Failed to parse refunds"))
+        .transpose()?
+        .unwrap_or_default();
+    to_ir(shipments, items, refunds)
+}
+
+/// Parse a CSV report, skipping rows for which `parse_record` returns `None`
+fn parse_csv<T>(
+    input_stream: impl Read,
+    parse_record: impl Fn(&Columns, &csv::StringRecord) -> Result<Option<T>>,
+) -> Result<Vec<T>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(input_stream);
+    let columns = Columns::new(reader.headers()?);
+    let mut result = vec![];
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = record
+            .position()
+            .map(|position| position.line())
+            .unwrap_or(index as u64);
+        if let Some(row) = parse_record(&columns, &record)
+            .with_context(|| format!("Failed to parse CSV row {line}"))?
+        {
+            result.push(row);
+        }
+    }
+    Ok(result)
+}
+
+/// Amazon reports have many columns and their order changed over time, so look them up by name
+struct Columns {
+    names: Vec<String>,
+}
+
+impl Columns {
+    fn new(headers: &csv::StringRecord) -> Self {
+        Self {
+            names: headers
+                .iter()
+                .map(|name| name.trim_start_matches('\u{feff}').trim().to_string())
+                .collect(),
+        }
+    }
+
+    fn get<'r>(&self, record: &'r csv::StringRecord, name: &str) -> Result<&'r str> {
+        let index = self
+            .names
+            .iter()
+            .position(|column| column == name)
+            .ok_or_else(|| anyhow!("CSV file doesn't have a '{name}' column"))?;
+        Ok(record.get(index).map(str::trim).unwrap_or_default())
+    }
+
+    fn date(&self, record: &csv::StringRecord, name: &str) -> Result<Option<NaiveDate>> {
+        let value = self.get(record, name)?;
+        if value.is_empty() {
+            return Ok(None);
+        }
+        NaiveDate::parse_from_str(value, DATE_FORMAT)
+            .map(Some)
+            .with_context(|| format!("Failed to parse {name} '{value}'"))
+    }
+
+    fn amount(&self, record: &csv::StringRecord, name: &str) -> Result<Decimal> {
+        Ok(parse_amount(self.get(record, name)?, '.')
+            .with_context(|| format!("Failed to parse {name}"))?
+            .unwrap_or_default())
+    }
+}
+
+fn parse_shipment(columns: &Columns, record: &csv::StringRecord) -> Result<Option<Shipment>> {
+    // Orders that haven't shipped yet haven't been charged either
+    let Some(shipment_date) = columns.date(record, "Shipment Date")? else {
+        return Ok(None);
+    };
+    if columns.get(record, "Order Status")? == "Cancelled" {
+        return Ok(None);
+    }
+    Ok(Some(Shipment {
+        order_id: columns.get(record, "Order ID")?.to_string(),
+        shipment_date,
+        payment_instrument: columns.get(record, "Payment Instrument Type")?.to_string(),
+        shipping: columns.amount(record, "Shipping Charge")?,
+        promotions: columns.amount(record, "Total Promotions")?,
+        tax: columns.amount(record, "Tax Charged")?,
+        total: columns.amount(record, "Total Charged")?,
+    }))
+}
+
+fn parse_item(columns: &Columns, record: &csv::StringRecord) -> Result<Option<Item>> {
+    let Some(shipment_date) = columns.date(record, "Shipment Date")? else {
+        return Ok(None);
+    };
+    let quantity = columns.get(record, "Quantity")?;
+    Ok(Some(Item {
+        order_id: columns.get(record, "Order ID")?.to_string(),
+        shipment_date,
+        title: columns.get(record, "Title")?.to_string(),
+        category: columns.get(record, "Category")?.to_string(),
+        asin: columns.get(record, "ASIN/ISBN")?.to_string(),
+        quantity: quantity
+            .parse()
+            .with_context(|| format!("Failed to parse quantity '{quantity}'"))?,
+        subtotal: columns.amount(record, "Item Subtotal")?,
+    }))
+}
+
+fn parse_refund(columns: &Columns, record: &csv::StringRecord) -> Result<Option<Refund>> {
+    let Some(refund_date) = columns.date(record, "Refund Date")? else {
+        return Ok(None);
+    };
+    Ok(Some(Refund {
+        order_id: columns.get(record, "Order ID")?.to_string(),
+        refund_date,
+        title: columns.get(record, "Title")?.to_string(),
+        category: columns.get(record, "Category")?.to_string(),
+        amount: columns.amount(record, "Refund Amount")?,
+        tax: columns.amount(record, "Refund Tax Amount")?,
+    }))
+}
+
+/// Items are booked to one account per Amazon category so they can be mapped to expense accounts
+fn category_account(category: &str) -> String {
+    if category.is_empty() {
+        "Amazon Uncategorized".to_string()
+    } else {
+        format!("Amazon {category}")
+    }
+}
+
+fn posting(account_name: impl Into<String>, amount: Decimal) -> Posting {
+    Posting {
+        account_name: account_name.into(),
+        amount: Amount::single_currency(amount),
+        metadata: hash_map![],
+    }
+}
+
+fn to_ir(shipments: Vec<Shipment>, items: Vec<Item>, refunds: Vec<Refund>) -> Result<Ledger> {
+    ensure!(
+        !shipments.is_empty(),
+        "Orders report doesn't contain any shipped orders"
+    );
+
+    let mut items_by_shipment: HashMap<(String, NaiveDate), Vec<Item>> = HashMap::new();
+    for item in items {
+        items_by_shipment
+            .entry((item.order_id.clone(), item.shipment_date))
+            .or_default()
+            .push(item);
+    }
+    let payment_instruments: HashMap<&str, &str> = shipments
+        .iter()
+        .map(|shipment| {
+            (
+                shipment.order_id.as_str(),
+                shipment.payment_instrument.as_str(),
+            )
+        })
+        .collect();
+
+    let mut transactions = vec![];
+    for shipment in &shipments {
+        let items = items_by_shipment
+            .remove(&(shipment.order_id.clone(), shipment.shipment_date))
+            .unwrap_or_default();
+        let mut postings: Vec<Posting> = items
+            .iter()
+            .map(|item| {
+                let mut posting = posting(category_account(&item.category), item.subtotal);
+                posting.metadata = hash_map![
+                    "item".to_string() => item.title.clone().into(),
+                    "asin".to_string() => item.asin.clone().into(),
+                    "quantity".to_string() => item.quantity.to_string().into()
+                ];
+                posting
+            })
+            .collect();
+        for (account, amount) in [
+            (SHIPPING_ACCOUNT, shipment.shipping),
+            (PROMOTIONS_ACCOUNT, -shipment.promotions),
+            (TAX_ACCOUNT, shipment.tax),
+        ] {
+            if !amount.is_zero() {
+                postings.push(posting(account, amount));
+            }
+        }
+        let mut payment = posting(shipment.payment_instrument.clone(), -shipment.total);
+        payment.metadata = hash_map![
+            "amazon_order_id".to_string() => shipment.order_id.clone().into()
+        ];
+        postings.push(payment);
+
+        let description = match items.as_slice() {
+            [item] => format!("Amazon: {}", item.title),
+            _ => format!("Amazon order {}", shipment.order_id),
+        };
+        // If the item report doesn't cover the whole shipment, the transaction ends up unbalanced and gets flagged in the export
+        transactions.push(Transaction {
+            date: shipment.shipment_date,
+            description,
+            payee: None,
+            metadata: hash_map![],
+            tags: vec![],
+            postings,
+        });
+    }
+
+    for refund in refunds {
+        let payment_instrument = payment_instruments
+            .get(refund.order_id.as_str())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Refund for order {} on {} doesn't match any order in the orders report",
+                    refund.order_id,
+                    refund.refund_date
+                )
+            })?;
+        let mut postings = vec![posting(category_account(&refund.category), -refund.amount)];
+        if !refund.tax.is_zero() {
+            postings.push(posting(TAX_ACCOUNT, -refund.tax));
+        }
+        let mut payment = posting(payment_instrument.to_string(), refund.amount + refund.tax);
+        payment.metadata = hash_map![
+            "amazon_order_id".to_string() => refund.order_id.clone().into()
+        ];
+        postings.push(payment);
+        transactions.push(Transaction {
+            date: refund.refund_date,
+            description: format!("Amazon refund: {}", refund.title),
+            payee: None,
+            metadata: hash_map![],
+            tags: vec![],
+            postings,
+        });
+    }
+
+    let mut accounts = HashMap::new();
+    for transaction in &transactions {
+        for posting in &transaction.postings {
+            accounts
+                .entry(posting.account_name.clone())
+                .or_insert_with(|| AccountInfo {
+                    start_balance: None,
+                    end_balance: None,
+                    account_currency: CURRENCY.to_string(),
+                });
+        }
+    }
+
+    Ok(Ledger {
+        source: "Amazon".to_string(),
+        ledger_name: "Amazon Orders".to_string(),
+        ledger_currency: CURRENCY.to_string(),
+        dates: Dates {
+            start_date: transactions.iter().map(|t| t.date).min().unwrap(),
+            end_date: transactions.iter().map(|t| t.date).max().unwrap(),
+        },
+        accounts,
+        transactions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::MetaValue;
+
+    const ORDERS: &str = "\
+Order Date,Order ID,Payment Instrument Type,Website,Shipment Date,Order Status,Subtotal,Shipping Charge,Tax Before Promotions,Total Promotions,Tax Charged,Total Charged
+01/02/24,111-1,Visa - 1234,Amazon.com,01/03/24,Shipped,$30.00,$5.99,$2.40,$5.99,$2.40,$32.40
+01/02/24,111-1,Visa - 1234,Amazon.com,01/05/24,Shipped,$10.00,$0.00,$0.80,$0.00,$0.80,$10.80
+01/10/24,222-2,Visa - 1234,Amazon.com,,Shipment planned,$99.00,$0.00,$0.00,$0.00,$0.00,$99.00
+";
+
+    const ITEMS: &str = "\
+Order Date,Order ID,Title,Category,ASIN/ISBN,Quantity,Shipment Date,Item Subtotal,Item Subtotal Tax,Item Total
+01/02/24,111-1,USB Cable,Electronics,B000001,2,01/03/24,$20.00,$1.60,$21.60
+01/02/24,111-1,Paperback,Books,B000002,1,01/03/24,$10.00,$0.80,$10.80
+01/02/24,111-1,Batteries,Electronics,B000003,1,01/05/24,$10.00,$0.80,$10.80
+01/10/24,222-2,Monitor,Electronics,B000004,1,,$99.00,$0.00,$99.00
+";
+
+    const REFUNDS: &str = "\
+Order ID,Order Date,Title,Category,ASIN/ISBN,Quantity,Refund Date,Refund Amount,Refund Tax Amount
+111-1,01/02/24,Paperback,Books,B000002,1,01/20/24,$10.00,$0.80
+";
+
+    fn amounts(transaction: &Transaction) -> Vec<(&str, Decimal)> {
+        transaction
+            .postings
+            .iter()
+            .map(|posting| {
+                (
+                    posting.account_name.as_str(),
+                    posting.amount.in_account_currency,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn itemized_shipments() {
+        let ledger = load(ORDERS.as_bytes(), ITEMS.as_bytes(), None::<&[u8]>).unwrap();
+        assert_eq!(2, ledger.transactions.len());
+
+        let first = &ledger.transactions[0];
+        assert_eq!(NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(), first.date);
+        assert_eq!("Amazon order 111-1", first.description);
+        assert_eq!(
+            vec![
+                ("Amazon Electronics", Decimal::new(2000, 2)),
+                ("Amazon Books", Decimal::new(1000, 2)),
+                (SHIPPING_ACCOUNT, Decimal::new(599, 2)),
+                (PROMOTIONS_ACCOUNT, Decimal::new(-599, 2)),
+                (TAX_ACCOUNT, Decimal::new(240, 2)),
+                ("Visa - 1234", Decimal::new(-3240, 2)),
+            ],
+            amounts(first)
+        );
+        assert!(first.is_balanced());
+        assert_eq!(
+            MetaValue::from("111-1"),
+            first.postings[5].metadata["amazon_order_id"]
+        );
+        assert_eq!(
+            MetaValue::from("USB Cable"),
+            first.postings[0].metadata["item"]
+        );
+        assert_eq!(MetaValue::from("2"), first.postings[0].metadata["quantity"]);
+
+        let second = &ledger.transactions[1];
+        assert_eq!("Amazon: Batteries", second.description);
+        assert!(second.is_balanced());
+    }
+
+    #[test]
+    fn refunds() {
+        let ledger = load(
+            ORDERS.as_bytes(),
+            ITEMS.as_bytes(),
+            Some(REFUNDS.as_bytes()),
+        )
+        .unwrap();
+        assert_eq!(3, ledger.transactions.len());
+        let refund = &ledger.transactions[2];
+        assert_eq!("Amazon refund: Paperback", refund.description);
+        assert_eq!(
+            vec![
+                ("Amazon Books", Decimal::new(-1000, 2)),
+                (TAX_ACCOUNT, Decimal::new(-80, 2)),
+                ("Visa - 1234", Decimal::new(1080, 2)),
+            ],
+            amounts(refund)
+        );
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 1, 20).unwrap(),
+            ledger.dates.end_date
+        );
+    }
+
+    #[test]
+    fn missing_items_leave_shipment_unbalanced() {
+        let items = ITEMS.lines().take(3).collect::<Vec<_>>().join("\n");
+        let ledger = load(ORDERS.as_bytes(), items.as_bytes(), None::<&[u8]>).unwrap();
+        assert!(ledger.transactions[0].is_balanced());
+        assert!(!ledger.transactions[1].is_balanced());
+    }
+
+    #[test]
+    fn refund_for_unknown_order() {
+        let refunds = REFUNDS.replace("111-1", "999-9");
+        assert!(load(
+            ORDERS.as_bytes(),
+            ITEMS.as_bytes(),
+            Some(refunds.as_bytes())
+        )
+        .is_err());
+    }
+}
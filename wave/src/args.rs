@@ -1,11 +1,156 @@
-use clap::Parser;
+use std::path::PathBuf;
 
-/// Import transactions from a Wave CSV and export to beancount
+use clap::{Parser, Subcommand};
+use rust_decimal::Decimal;
+
+use crate::export::FiscalYearEnd;
+use crate::operations::MergeMode;
+
+/// Import transactions from Wave, bank CSV, CAMT.053, PayPal, Amazon or Venmo exports and export to beancount
 #[derive(Parser, Debug)]
 pub struct Args {
-    /// Path to the Wave CSV file
-    #[clap(short, long)]
-    pub from_csv: String,
+    #[clap(subcommand)]
+    pub command: Command,
+
+    /// Write the intermediate representation after each processing stage to this JSON file, for debugging
+    #[clap(long, global = true)]
+    pub dump_ir: Option<PathBuf>,
+
+    /// Last day of the fiscal year as MM-DD, e.g. 12-31. If given, Income and Expenses accounts are closed
+    /// into Equity:Retained-Earnings at the end of each fiscal year.
+    #[clap(long, global = true)]
+    pub fiscal_year_end: Option<FiscalYearEnd>,
+
+    /// Run this Rhai script for each transaction after importing it. The script gets the transaction as the map `tx`
+    /// and can change `tx.payee`, `tx.narration`, `tx.tags` and `tx.counter_account`, or set `tx.skip` to leave it out.
+    #[clap(long, global = true)]
+    pub script: Option<PathBuf>,
+
+    /// Remember the date range and transactions of each import in this JSON file. An export whose date range overlaps
+    /// a previous import of the same ledger is refused, unless `--allow-overlap` is given.
+    #[clap(long, global = true)]
+    pub state: Option<PathBuf>,
+
+    /// Import an export that overlaps a previous import anyway, leaving out the transactions that were already imported
+    #[clap(long, global = true, requires = "state")]
+    pub allow_overlap: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Import a Wave "Account Transactions" CSV export
+    Wave {
+        /// Path to the Wave CSV file
+        #[clap(short, long)]
+        from_csv: PathBuf,
+
+        /// Path to Wave's "Account Balances" CSV report for the same date range.
+        /// If given, the ending balances of all accounts are checked against it.
+        #[clap(long)]
+        account_balances: Option<PathBuf>,
+
+        /// Accept balances that are off by up to this amount, e.g. 0.01 for rounding artifacts in converted columns.
+        /// The differences are booked to a rounding account.
+        #[clap(long, default_value = "0")]
+        rounding_tolerance: Decimal,
+
+        /// How to combine the single-account postings from Wave into transactions
+        #[clap(long, value_enum, default_value_t = MergeMode::SameAmount)]
+        merge: MergeMode,
+
+        /// Skip rows that fail to parse and report them instead of failing the whole import.
+        /// A skipped posting usually still fails the import because the balances of its account don't add up anymore.
+        #[clap(long)]
+        lenient: bool,
+    },
+
+    /// Import an arbitrary bank CSV export whose layout is described by a TOML schema.
+    /// Without a `counter_account` in the schema, transactions only have one posting and are exported as unbalanced
+    /// with the `!` flag, so bean-check fails until they're categorized.
+    CsvImport {
+        /// Path to the TOML schema describing the CSV columns
+        #[clap(short, long)]
+        schema: PathBuf,
+
+        /// Path to the CSV file
+        #[clap(short, long)]
+        from_csv: PathBuf,
+    },
+
+    /// Import an ISO 20022 CAMT.053 bank statement
+    Camt053 {
+        /// Path to the CAMT.053 XML file
+        #[clap(short, long)]
+        from_xml: PathBuf,
+    },
+
+    /// Import a PayPal activity CSV export
+    Paypal {
+        /// Path to the PayPal CSV file
+        #[clap(short, long)]
+        from_csv: PathBuf,
+
+        /// Primary currency of the PayPal account. Payments in other currencies are booked with their converted amount.
+        #[clap(long, default_value = "USD")]
+        currency: String,
+
+        /// chrono format string for the date column, depends on the region of the PayPal account
+        #[clap(long, default_value = "%m/%d/%Y")]
+        date_format: String,
+    },
+
+    /// Import Amazon order history reports with one posting per item.
+    /// The card charges of these orders aren't matched and have to be removed from the bank's ledger by hand.
+    Amazon {
+        /// Path to the "Orders and shipments" report CSV
+        #[clap(long)]
+        orders: PathBuf,
+
+        /// Path to the "Items" report CSV
+        #[clap(long)]
+        items: PathBuf,
+
+        /// Path to the "Refunds" report CSV
+        #[clap(long)]
+        refunds: Option<PathBuf>,
+    },
+
+    /// Import a single export file of any of the other sources except Amazon, detecting the source from its content
+    Import {
+        /// Path to the export file
+        file: PathBuf,
+
+        /// Source of the file, one of the commands. Only needed if the source can't be detected.
+        #[clap(long)]
+        source: Option<String>,
+
+        /// Path to the TOML schema for `csv-import`. Without it, bank CSV files aren't detected.
+        #[clap(long)]
+        schema: Option<PathBuf>,
+
+        /// See the `wave` command
+        #[clap(long, default_value = "0")]
+        rounding_tolerance: Decimal,
+
+        /// See the `wave` command
+        #[clap(long, value_enum, default_value_t = MergeMode::SameAmount)]
+        merge: MergeMode,
+
+        /// See the `paypal` command
+        #[clap(long, default_value = "USD")]
+        currency: String,
+
+        /// See the `paypal` command
+        #[clap(long, default_value = "%m/%d/%Y")]
+        date_format: String,
+    },
+
+    /// Import a Venmo account statement CSV
+    Venmo {
+        /// Path to the Venmo statement CSV file
+        #[clap(short, long)]
+        from_csv: PathBuf,
+    },
 }
 
 pub fn parse() -> Args {
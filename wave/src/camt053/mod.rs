@@ -0,0 +1,525 @@
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use chrono::NaiveDate;
+use common_macros::hash_map;
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use std::collections::{hash_map, HashMap};
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::ir::{AccountInfo, Amount, Dates, Ledger, Posting, Transaction};
+
+/// End-to-end ids that banks use when the sender didn't provide one
+const END_TO_END_ID_NOT_PROVIDED: &str = "NOTPROVIDED";
+
+#[derive(Debug)]
+struct Statement {
+    account: String,
+    owner_name: Option<String>,
+    currency: String,
+    from_date: Option<NaiveDate>,
+    to_date: Option<NaiveDate>,
+    opening_balance: Option<Decimal>,
+    closing_balance: Option<Decimal>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    value_date: NaiveDate,
+    amount: Decimal,
+    remittance_info: Option<String>,
+    end_to_end_ids: Vec<String>,
+    account_servicer_reference: Option<String>,
+}
+
+/// Whether the content looks like a CAMT.053 statement. Only looks for the XML namespace, ignoring the version suffix.
+pub fn detect(content: &[u8]) -> bool {
+    const NAMESPACE: &[u8] = b"urn:iso:std:iso:20022:tech:xsd:camt.053";
+    content
+        .windows(NAMESPACE.len())
+        .any(|window| window == NAMESPACE)
+}
+
+pub fn load(mut input_stream: impl Read) -> Result<Ledger> {
+    let mut content = String::new();
+    input_stream.read_to_string(&mut content)?;
+    let document = Document::parse(&content).context("Failed to parse XML")?;
+    let statements = parse_statements(&document)?;
+    to_ir(statements)
+}
+
+fn parse_statements(document: &Document) -> Result<Vec<Statement>> {
+    let root = document.root_element();
+    ensure!(
+        root.tag_name().name() == "Document",
+        "Expected <Document> root element but found <{}>",
+        root.tag_name().name()
+    );
+    let statement_root = child(root, "BkToCstmrStmt")
+        .ok_or_else(|| anyhow!("Not a CAMT.053 file, <BkToCstmrStmt> element not found"))?;
+    children(statement_root, "Stmt")
+        .map(|statement| {
+            let id = child_text(statement, "Id").unwrap_or("[unknown]");
+            parse_statement(statement).with_context(|| format!("Failed to parse statement {id}"))
+        })
+        .collect()
+}
+
+fn parse_statement(statement: Node) -> Result<Statement> {
+    let account_node =
+        child(statement, "Acct").ok_or_else(|| anyhow!("Statement has no <Acct> element"))?;
+    let account_id =
+        child(account_node, "Id").ok_or_else(|| anyhow!("Account has no <Id> element"))?;
+    let account = child_text(account_id, "IBAN")
+        .or_else(|| path_text(account_id, &["Othr", "Id"]))
+        .ok_or_else(|| anyhow!("Account has neither an IBAN nor another id"))?
+        .to_string();
+    let owner_name = path_text(account_node, &["Ownr", "Nm"]).map(str::to_string);
+
+    let mut opening_balance = None;
+    let mut closing_balance = None;
+    let mut balance_currency = None;
+    for balance in children(statement, "Bal") {
+        let balance_type = path_text(balance, &["Tp", "CdOrPrtry", "Cd"])
+            .ok_or_else(|| anyhow!("Balance has no type"))?;
+        let (amount, currency) = signed_amount(balance)?;
+        match balance_type {
+            // Opening booked or previously closed booked balance
+            "OPBD" | "PRCD" => opening_balance = Some(amount),
+            // Closing booked balance
+            "CLBD" => closing_balance = Some(amount),
+            // Other balances like available balances don't map to beancount balance assertions
+            _ => continue,
+        }
+        balance_currency = Some(currency);
+    }
+    let currency = child_text(account_node, "Ccy")
+        .map(str::to_string)
+        .or(balance_currency)
+        .ok_or_else(|| anyhow!("Couldn't determine the account currency"))?;
+
+    let period = child(statement, "FrToDt");
+    let from_date = period
+        .and_then(|period| child_text(period, "FrDtTm"))
+        .map(parse_date)
+        .transpose()?;
+    let to_date = period
+        .and_then(|period| child_text(period, "ToDtTm"))
+        .map(parse_date)
+        .transpose()?;
+
+    let entries = children(statement, "Ntry")
+        .filter(|entry| !is_pending(*entry))
+        .map(|entry| {
+            let entry_currency = amount_node(entry)?.attribute("Ccy");
+            if let Some(entry_currency) = entry_currency {
+                ensure!(
+                    entry_currency == currency,
+                    "Entry currency {entry_currency} doesn't match account currency {currency}"
+                );
+            }
+            parse_entry(entry)
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Statement {
+        account,
+        owner_name,
+        currency,
+        from_date,
+        to_date,
+        opening_balance,
+        closing_balance,
+        entries,
+    })
+}
+
+fn is_pending(entry: Node) -> bool {
+    // CAMT.053.001.02 has the status as text, newer versions wrap it in a <Cd> element
+    child(entry, "Sts")
+        .and_then(|status| child_text(status, "Cd").or(status.text()))
+        .map(|status| status.trim() == "PDNG")
+        .unwrap_or(false)
+}
+
+fn parse_entry(entry: Node) -> Result<Entry> {
+    let (amount, _currency) = signed_amount(entry)?;
+    let value_date = child(entry, "ValDt")
+        .or_else(|| child(entry, "BookgDt"))
+        .and_then(|date| child_text(date, "Dt").or_else(|| child_text(date, "DtTm")))
+        .ok_or_else(|| anyhow!("Entry has neither a value date nor a booking date"))?;
+    let value_date = parse_date(value_date)?;
+
+    let transaction_details: Vec<Node> = entry
+        .children()
+        .filter(|node| node.has_tag_name("NtryDtls"))
+        .flat_map(|details| children(details, "TxDtls"))
+        .collect();
+
+    let remittance_info: Vec<String> = transaction_details
+        .iter()
+        .flat_map(|details| {
+            child(*details, "RmtInf")
+                .into_iter()
+                .flat_map(|info| children(info, "Ustrd"))
+        })
+        .filter_map(|unstructured| unstructured.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+        .collect();
+    let remittance_info = if remittance_info.is_empty() {
+        child_text(entry, "AddtlNtryInf")
+            .map(str::trim)
+            .or_else(|| {
+                transaction_details
+                    .iter()
+                    .find_map(|details| counterparty_name(*details, amount))
+            })
+            .map(str::to_string)
+    } else {
+        Some(remittance_info.join(" "))
+    };
+
+    let end_to_end_ids = transaction_details
+        .iter()
+        .filter_map(|details| path_text(*details, &["Refs", "EndToEndId"]))
+        .map(str::trim)
+        .filter(|id| *id != END_TO_END_ID_NOT_PROVIDED)
+        .map(str::to_string)
+        .collect();
+
+    Ok(Entry {
+        value_date,
+        amount,
+        remittance_info,
+        end_to_end_ids,
+        account_servicer_reference: child_text(entry, "AcctSvcrRef").map(str::to_string),
+    })
+}
+
+/// For incoming payments, the counterparty is the debtor. For outgoing payments, it's the creditor.
+fn counterparty_name<'a>(details: Node<'a, '_>, amount: Decimal) -> Option<&'a str> {
+    let party = if amount.is_sign_negative() {
+        ["RltdPties", "Cdtr", "Nm"]
+    } else {
+        ["RltdPties", "Dbtr", "Nm"]
+    };
+    path_text(details, &party)
+}
+
+fn amount_node<'a, 'input>(node: Node<'a, 'input>) -> Result<Node<'a, 'input>> {
+    child(node, "Amt").ok_or_else(|| anyhow!("<{}> has no <Amt> element", node.tag_name().name()))
+}
+
+/// Parse the `<Amt>` and `<CdtDbtInd>` children of a balance or entry into a signed amount and its currency.
+fn signed_amount(node: Node) -> Result<(Decimal, String)> {
+    let amount = amount_node(node)?;
+    let currency = amount
+        .attribute("Ccy")
+        .ok_or_else(|| anyhow!("Amount has no currency"))?
+        .to_string();
+    let value = amount
+        .text()
+        .ok_or_else(|| anyhow!("Amount is empty"))?
+        .trim();
+    let value = Decimal::from_str(value).with_context(|| format!("Invalid amount '{value}'"))?;
+    let value = match child_text(node, "CdtDbtInd") {
+        Some("CRDT") => value,
+        Some("DBIT") => -value,
+        Some(indicator) => bail!("Invalid credit/debit indicator '{indicator}'"),
+        None => bail!("Amount has no credit/debit indicator"),
+    };
+    Ok((value, currency))
+}
+
+/// Parse an ISO date, ignoring any time part (e.g. `2024-01-31` or `2024-01-31T23:59:59+01:00`)
+fn parse_date(value: &str) -> Result<NaiveDate> {
+    let value = value.trim();
+    let date = value.get(..10).unwrap_or(value);
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").with_context(|| format!("Invalid date '{value}'"))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|child| child.text())
+}
+
+fn path_text<'a>(node: Node<'a, '_>, path: &[&str]) -> Option<&'a str> {
+    let mut current = node;
+    for name in path {
+        current = child(current, name)?;
+    }
+    current.text()
+}
+
+fn to_ir(statements: Vec<Statement>) -> Result<Ledger> {
+    ensure!(
+        !statements.is_empty(),
+        "File doesn't contain any statements"
+    );
+
+    let ledger_currency = statements[0].currency.clone();
+    ensure!(
+        statements
+            .iter()
+            .all(|statement| statement.currency == ledger_currency),
+        "Statements for accounts in different currencies aren't supported"
+    );
+    let ledger_name = statements
+        .iter()
+        .find_map(|statement| statement.owner_name.clone())
+        .unwrap_or_else(|| "CAMT.053".to_string());
+
+    let start_date = statements
+        .iter()
+        .filter_map(|statement| statement.from_date)
+        .chain(statements.iter().flat_map(entry_dates))
+        .min()
+        .ok_or_else(|| anyhow!("Statements don't have any dates"))?;
+    let end_date = statements
+        .iter()
+        .filter_map(|statement| statement.to_date)
+        .chain(statements.iter().flat_map(entry_dates))
+        .max()
+        .ok_or_else(|| anyhow!("Statements don't have any dates"))?;
+
+    let mut statements = statements;
+    statements.sort_by_key(|statement| {
+        statement
+            .from_date
+            .or_else(|| statement.entries.iter().map(|entry| entry.value_date).min())
+    });
+
+    let mut accounts: HashMap<String, AccountInfo> = HashMap::new();
+    let mut transactions = vec![];
+    for statement in statements {
+        // If a file contains multiple consecutive statements for the same account, the first
+        // opening balance and the last closing balance describe the whole period.
+        match accounts.entry(statement.account.clone()) {
+            hash_map::Entry::Occupied(mut account) => {
+                if statement.closing_balance.is_some() {
+                    account.get_mut().end_balance =
+                        statement.closing_balance.map(Amount::single_currency);
+                }
+            }
+            hash_map::Entry::Vacant(account) => {
+                account.insert(AccountInfo {
+                    start_balance: statement.opening_balance.map(Amount::single_currency),
+                    end_balance: statement.closing_balance.map(Amount::single_currency),
+                    account_currency: statement.currency.clone(),
+                });
+            }
+        }
+        transactions.extend(statement.entries.into_iter().map(|entry| {
+            let mut metadata = hash_map![];
+            if !entry.end_to_end_ids.is_empty() {
+                metadata.insert(
+                    "end_to_end_id".to_string(),
+                    entry.end_to_end_ids.join(", ").into(),
+                );
+            }
+            if let Some(reference) = entry.account_servicer_reference {
+                metadata.insert("bank_reference".to_string(), reference.into());
+            }
+            Transaction {
+                date: entry.value_date,
+                description: entry.remittance_info.unwrap_or_default(),
+                payee: None,
+                metadata: hash_map![],
+                tags: vec![],
+                postings: vec![Posting {
+                    account_name: statement.account.clone(),
+                    amount: Amount::single_currency(entry.amount),
+                    metadata,
+                }],
+            }
+        }));
+    }
+
+    Ok(Ledger {
+        source: "CAMT.053".to_string(),
+        ledger_name,
+        ledger_currency,
+        dates: Dates {
+            start_date,
+            end_date,
+        },
+        accounts,
+        transactions,
+    })
+}
+
+fn entry_dates(statement: &Statement) -> impl Iterator<Item = NaiveDate> + '_ {
+    statement.entries.iter().map(|entry| entry.value_date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::MetaValue;
+
+    const STATEMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <GrpHdr>
+      <MsgId>MSG-1</MsgId>
+      <CreDtTm>2024-02-01T06:00:00</CreDtTm>
+    </GrpHdr>
+    <Stmt>
+      <Id>STMT-1</Id>
+      <FrToDt>
+        <FrDtTm>2024-01-01T00:00:00</FrDtTm>
+        <ToDtTm>2024-01-31T23:59:59</ToDtTm>
+      </FrToDt>
+      <Acct>
+        <Id><IBAN>DE89370400440532013000</IBAN></Id>
+        <Ccy>EUR</Ccy>
+        <Ownr><Nm>Jane Doe</Nm></Ownr>
+      </Acct>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>PRCD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">1000.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><Dt>2023-12-31</Dt></Dt>
+      </Bal>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">2450.50</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><Dt>2024-01-31</Dt></Dt>
+      </Bal>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>CLAV</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">2000.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Dt><Dt>2024-01-31</Dt></Dt>
+      </Bal>
+      <Ntry>
+        <Amt Ccy="EUR">2000.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-01-15</Dt></BookgDt>
+        <ValDt><Dt>2024-01-14</Dt></ValDt>
+        <AcctSvcrRef>REF-1</AcctSvcrRef>
+        <NtryDtls>
+          <TxDtls>
+            <Refs><EndToEndId>E2E-SALARY-01</EndToEndId></Refs>
+            <RltdPties><Dbtr><Nm>ACME Corp</Nm></Dbtr></RltdPties>
+            <RmtInf><Ustrd>Salary January</Ustrd></RmtInf>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">549.50</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-01-20</Dt></BookgDt>
+        <NtryDtls>
+          <TxDtls>
+            <Refs><EndToEndId>NOTPROVIDED</EndToEndId></Refs>
+            <RltdPties><Cdtr><Nm>Landlord</Nm></Cdtr></RltdPties>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">10.00</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>PDNG</Sts>
+        <BookgDt><Dt>2024-01-31</Dt></BookgDt>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>
+"#;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn load_statement() {
+        let ledger = load(STATEMENT.as_bytes()).unwrap();
+        assert_eq!("Jane Doe", ledger.ledger_name);
+        assert_eq!("EUR", ledger.ledger_currency);
+        assert_eq!(date(2024, 1, 1), ledger.dates.start_date);
+        assert_eq!(date(2024, 1, 31), ledger.dates.end_date);
+
+        let account = &ledger.accounts["DE89370400440532013000"];
+        assert_eq!("EUR", account.account_currency);
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(100000, 2))),
+            account.start_balance
+        );
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(245050, 2))),
+            account.end_balance
+        );
+
+        assert_eq!(2, ledger.transactions.len());
+
+        let salary = &ledger.transactions[0];
+        assert_eq!(date(2024, 1, 14), salary.date);
+        assert_eq!("Salary January", salary.description);
+        assert_eq!(
+            Amount::single_currency(Decimal::new(200000, 2)),
+            salary.postings[0].amount
+        );
+        assert_eq!(
+            hash_map![
+                "end_to_end_id".to_string() => MetaValue::from("E2E-SALARY-01"),
+                "bank_reference".to_string() => MetaValue::from("REF-1")
+            ],
+            salary.postings[0].metadata
+        );
+
+        let rent = &ledger.transactions[1];
+        assert_eq!(date(2024, 1, 20), rent.date);
+        assert_eq!("Landlord", rent.description);
+        assert_eq!(
+            Amount::single_currency(Decimal::new(-54950, 2)),
+            rent.postings[0].amount
+        );
+        assert!(rent.postings[0].metadata.is_empty());
+    }
+
+    #[test]
+    fn status_in_code_element() {
+        let input = STATEMENT.replace("<Sts>PDNG</Sts>", "<Sts><Cd>PDNG</Cd></Sts>");
+        let ledger = load(input.as_bytes()).unwrap();
+        assert_eq!(2, ledger.transactions.len());
+    }
+
+    #[test]
+    fn not_camt053() {
+        let input = r#"<?xml version="1.0"?><Document><Foo/></Document>"#;
+        assert!(load(input.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn invalid_credit_debit_indicator() {
+        let input = STATEMENT.replace("<CdtDbtInd>DBIT</CdtDbtInd>", "<CdtDbtInd>XXX</CdtDbtInd>");
+        assert!(load(input.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(date(2024, 1, 31), parse_date("2024-01-31").unwrap());
+        assert_eq!(
+            date(2024, 1, 31),
+            parse_date("2024-01-31T23:59:59+01:00").unwrap()
+        );
+        assert!(parse_date("31.01.2024").is_err());
+    }
+}
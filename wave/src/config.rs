@@ -1,7 +1,7 @@
 use anyhow::{anyhow, Context, Result};
 use beancount_core::AccountType;
 use serde::{Deserialize, Serialize};
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, path::Path};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
@@ -9,11 +9,27 @@ pub struct Config {
 }
 
 impl Config {
+    /// Load a config in the YAML format that [prompt_edit_config] asks the user to fill in
+    pub fn load(path: &Path) -> Result<Config> {
+        let file = std::fs::File::open(path)
+            .with_context(|| anyhow!("Failed to open config {}", path.display()))?;
+        let config: Config = serde_yaml::from_reader(file)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Like [Config::load], but for a config that is already in memory
+    pub fn from_yaml(yaml: &str) -> Result<Config> {
+        let config: Config = serde_yaml::from_str(yaml)?;
+        config.validate()?;
+        Ok(config)
+    }
+
     pub fn validate(&self) -> Result<()> {
         for (name, account) in &self.beancount_account_names {
             account
                 .beancount_name()
-                .with_context(|| anyhow!("Error in account {}: {}", name, account.0))?;
+                .with_context(|| anyhow!("Error in account {}: {}", name, account.name()))?;
         }
         Ok(())
     }
@@ -24,15 +40,45 @@ impl Config {
             .with_context(|| anyhow!("Account not found: {}", name))?
             .beancount_name()
     }
+
+    pub fn lookup_account_type(&self, name: &str) -> Option<DebitOrCredit> {
+        match self.beancount_account_names.get(name)? {
+            AccountConfig::Name(_) => None,
+            AccountConfig::WithAccountType { account_type, .. } => *account_type,
+        }
+    }
 }
 
+/// Either just the beancount account name, or the name together with settings for accounts that need them
 #[derive(Debug, Serialize, Deserialize)]
-pub struct AccountConfig(String);
+#[serde(untagged)]
+pub enum AccountConfig {
+    Name(String),
+    WithAccountType {
+        name: String,
+        /// Used if the account type can't be inferred from the imported balances
+        account_type: Option<DebitOrCredit>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DebitOrCredit {
+    Debit,
+    Credit,
+}
 
 impl AccountConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            AccountConfig::Name(name) => name,
+            AccountConfig::WithAccountType { name, .. } => name,
+        }
+    }
+
     pub fn beancount_name(&self) -> Result<beancount_core::Account> {
         // TODO Deduplicate with parse_beancount_account_name function in //plaid/src/db/account.rs
-        let mut parts = self.0.split(':');
+        let mut parts = self.name().split(':');
         let ty = parts
             .next()
             .expect("There should always be at least one part to the split");
@@ -55,18 +101,30 @@ impl AccountConfig {
     }
 }
 
-pub fn prompt_edit_config(imported_account_names: impl Iterator<Item = String>) -> Result<Config> {
+/// `accounts_with_unknown_type` get an additional `account_type` field the user has to fill in
+#[cfg(not(target_arch = "wasm32"))]
+pub fn prompt_edit_config(
+    imported_account_names: impl Iterator<Item = String>,
+    accounts_with_unknown_type: &[String],
+) -> Result<Config> {
     let initial_config = Config {
         beancount_account_names: imported_account_names
-            .map(|name| (name.clone(), AccountConfig("".to_string())))
+            .map(|name| {
+                let account_config = if accounts_with_unknown_type.contains(&name) {
+                    AccountConfig::WithAccountType {
+                        name: "".to_string(),
+                        account_type: None,
+                    }
+                } else {
+                    AccountConfig::Name("".to_string())
+                };
+                (name, account_config)
+            })
             .collect(),
     };
     let serialized = serde_yaml::to_string(&initial_config)?;
     let Some(edited) = dialoguer::Editor::new().edit(&serialized)? else {
         return Err(anyhow!("You did not save the edits, please try again"));
     };
-    let new_config: Config = serde_yaml::from_str(&edited)?;
-    new_config.validate()?;
-
-    Ok(new_config)
+    Config::from_yaml(&edited)
 }
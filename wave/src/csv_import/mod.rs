@@ -0,0 +1,640 @@
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use chrono::NaiveDate;
+use common_macros::hash_map;
+use rust_decimal::Decimal;
+use std::io::{BufRead, BufReader, Read};
+
+mod schema;
+
+pub use schema::Schema;
+use schema::{AmountColumns, SignConvention};
+
+use crate::ir::{AccountInfo, Amount, Dates, Ledger, Posting, Transaction};
+
+#[derive(Debug, PartialEq, Eq)]
+struct Row {
+    date: NaiveDate,
+    description: String,
+    amount: Decimal,
+    balance: Option<Decimal>,
+}
+
+pub fn load(schema: &Schema, input_stream: impl Read) -> Result<Ledger> {
+    let rows = parse_rows(schema, input_stream)?;
+    to_ir(schema, rows)
+}
+
+fn parse_rows(schema: &Schema, input_stream: impl Read) -> Result<Vec<Row>> {
+    let mut input_stream = BufReader::new(input_stream);
+    for _ in 0..schema.skip_lines {
+        input_stream.read_line(&mut String::new())?;
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(schema.has_header_row)
+        .delimiter(delimiter_byte(schema.delimiter)?)
+        .flexible(true)
+        .from_reader(input_stream);
+    reader
+        .records()
+        .enumerate()
+        .filter(|(_, record)| match record {
+            Ok(record) => !record.iter().all(|cell| cell.trim().is_empty()),
+            Err(_) => true,
+        })
+        .map(|(index, record)| {
+            let record = record?;
+            parse_row(schema, &record).with_context(|| {
+                format!(
+                    "Failed to parse CSV row {}",
+                    record
+                        .position()
+                        .map(|position| position.line() + schema.skip_lines as u64)
+                        .unwrap_or(index as u64)
+                )
+            })
+        })
+        .collect()
+}
+
+fn delimiter_byte(delimiter: char) -> Result<u8> {
+    u8::try_from(delimiter)
+        .ok()
+        .filter(u8::is_ascii)
+        .ok_or_else(|| anyhow!("CSV delimiter must be an ASCII character but is '{delimiter}'"))
+}
+
+fn parse_row(schema: &Schema, record: &csv::StringRecord) -> Result<Row> {
+    let cell = |index: usize| {
+        record
+            .get(index)
+            .map(str::trim)
+            .ok_or_else(|| anyhow!("Row has no column {index}"))
+    };
+    let date = NaiveDate::parse_from_str(cell(schema.columns.date)?, &schema.date_format)
+        .with_context(|| {
+            format!(
+                "Failed to parse date '{}' with format '{}'",
+                record.get(schema.columns.date).unwrap_or_default(),
+                schema.date_format
+            )
+        })?;
+    let description = cell(schema.columns.description)?.to_string();
+    let amount = match schema.columns.amount {
+        AmountColumns::Amount { amount } => parse_amount(cell(amount)?, schema.decimal_separator)?
+            .ok_or_else(|| anyhow!("Amount column is empty"))?,
+        AmountColumns::DebitCredit { debit, credit } => {
+            let debit = parse_amount(cell(debit)?, schema.decimal_separator)?;
+            let credit = parse_amount(cell(credit)?, schema.decimal_separator)?;
+            match (debit, credit) {
+                (Some(debit), None) => debit,
+                (None, Some(credit)) => -credit,
+                (Some(debit), Some(credit)) if credit.is_zero() => debit,
+                (Some(debit), Some(credit)) if debit.is_zero() => -credit,
+                _ => bail!("Exactly one of the debit and credit columns must have a value"),
+            }
+        }
+    };
+    let balance = schema
+        .columns
+        .balance
+        .map(|balance| {
+            parse_amount(cell(balance)?, schema.decimal_separator)?
+                .ok_or_else(|| anyhow!("Balance column is empty"))
+        })
+        .transpose()?;
+    Ok(Row {
+        date,
+        description,
+        amount: apply_sign_convention(schema.sign_convention, amount),
+        balance: balance.map(|balance| apply_sign_convention(schema.sign_convention, balance)),
+    })
+}
+
+fn apply_sign_convention(sign_convention: SignConvention, amount: Decimal) -> Decimal {
+    match sign_convention {
+        SignConvention::PositiveIsDeposit => amount,
+        SignConvention::PositiveIsWithdrawal => -amount,
+    }
+}
+
+/// Characters that group the digits of amounts by thousands, unless they're the decimal separator
+const THOUSANDS_SEPARATORS: [char; 5] = [',', '.', '\'', ' ', '\u{a0}'];
+
+/// Parse an amount like `1,234.56`, `-$1,234.56`, `- $4.50`, `(1,234.56)`, `1.234,56 EUR` or `1,234.56-`. Returns
+/// `None` for empty cells. A currency symbol or three-letter currency code can come before or after the number, and
+/// there can be at most one sign.
+/// Only `decimal_separator` separates the decimals, the digits before it can be grouped by thousands with one of
+/// the other [THOUSANDS_SEPARATORS]. Amounts like `1.234` with `.` as decimal separator are rejected, they're more
+/// likely grouped by thousands than having three decimals.
+pub(crate) fn parse_amount(cell: &str, decimal_separator: char) -> Result<Option<Decimal>> {
+    let content = cell.trim();
+    if content.is_empty() {
+        return Ok(None);
+    }
+    let invalid = || anyhow!("Failed to parse amount '{cell}'");
+    let (parenthesized, content) = match content
+        .strip_prefix('(')
+        .and_then(|content| content.strip_suffix(')'))
+    {
+        Some(content) => (true, content),
+        None => (false, content),
+    };
+    let is_number = |c: char| c.is_ascii_digit() || c == decimal_separator;
+    let number_start = content.find(is_number).ok_or_else(invalid)?;
+    // The number ends with an ASCII character, so the byte after it is the end
+    let number_end = content.rfind(is_number).ok_or_else(invalid)? + 1;
+    let mut num_signs = usize::from(parenthesized);
+    let mut negative = parenthesized;
+    let mut num_currencies = 0;
+    for affix in [&content[..number_start], &content[number_end..]] {
+        let mut chars = affix.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '-' | '+' => {
+                    num_signs += 1;
+                    negative |= c == '-';
+                }
+                '$' | '€' | '£' | '¥' => num_currencies += 1,
+                c if c.is_ascii_uppercase() => {
+                    let mut code_length = 1;
+                    while chars.next_if(char::is_ascii_uppercase).is_some() {
+                        code_length += 1;
+                    }
+                    ensure!(code_length == 3, invalid());
+                    num_currencies += 1;
+                }
+                c if c.is_whitespace() => {}
+                _ => return Err(invalid()),
+            }
+        }
+    }
+    ensure!(num_signs <= 1 && num_currencies <= 1, invalid());
+    let number = &content[number_start..number_end];
+    let (integer, fraction) = match number.split_once(decimal_separator) {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (number, None),
+    };
+    ensure!(
+        fraction.is_none_or(|fraction| fraction.chars().all(|c| c.is_ascii_digit())),
+        invalid()
+    );
+    let mut separators = integer.chars().filter(|c| !c.is_ascii_digit());
+    match separators.next() {
+        Some(separator) => {
+            ensure!(
+                THOUSANDS_SEPARATORS.contains(&separator) && separators.all(|c| c == separator),
+                invalid()
+            );
+            let mut groups = integer.split(separator);
+            let first_group = groups.next().unwrap_or_default();
+            ensure!(
+                (1..=3).contains(&first_group.len()) && groups.all(|group| group.len() == 3),
+                invalid()
+            );
+        }
+        None => {
+            // Amounts rarely have three decimals, so this is more likely a number grouped by thousands in a file
+            // with a different decimal separator than the schema's
+            let looks_grouped = fraction.is_some_and(|fraction| fraction.len() == 3)
+                && (1..=3).contains(&integer.len())
+                && !integer.starts_with('0');
+            ensure!(
+                !looks_grouped,
+                "Amount '{cell}' is ambiguous, '{decimal_separator}' could be a thousands separator. Check the \
+                decimal separator of the schema."
+            );
+        }
+    }
+    let mut number: String = integer.chars().filter(char::is_ascii_digit).collect();
+    if let Some(fraction) = fraction {
+        number.push('.');
+        number.push_str(fraction);
+    }
+    let amount = Decimal::from_str_exact(&number).with_context(invalid)?;
+    Ok(Some(if negative { -amount } else { amount }))
+}
+
+fn to_ir(schema: &Schema, mut rows: Vec<Row>) -> Result<Ledger> {
+    ensure!(
+        !rows.is_empty(),
+        "CSV file doesn't contain any transactions"
+    );
+
+    // Bank exports are often sorted newest first. The running balance only makes sense in chronological order.
+    if rows.first().unwrap().date > rows.last().unwrap().date {
+        rows.reverse();
+    }
+
+    let (start_balance, end_balance) = balances(&rows)?;
+    let dates = Dates {
+        start_date: rows.iter().map(|row| row.date).min().unwrap(),
+        end_date: rows.iter().map(|row| row.date).max().unwrap(),
+    };
+
+    let transactions = rows
+        .into_iter()
+        .map(|row| Transaction {
+            date: row.date,
+            description: row.description,
+            payee: None,
+            metadata: hash_map![],
+            tags: vec![],
+            postings: postings(schema, row.amount),
+        })
+        .collect();
+    let mut accounts = hash_map![
+        schema.account.clone() => AccountInfo {
+            start_balance: start_balance.map(Amount::single_currency),
+            end_balance: end_balance.map(Amount::single_currency),
+            account_currency: schema.currency.clone(),
+        }
+    ];
+    if let Some(counter_account) = &schema.counter_account {
+        accounts.insert(
+            counter_account.clone(),
+            AccountInfo {
+                start_balance: None,
+                end_balance: None,
+                account_currency: schema.currency.clone(),
+            },
+        );
+    }
+
+    Ok(Ledger {
+        source: "CSV".to_string(),
+        ledger_name: schema.name.clone(),
+        ledger_currency: schema.currency.clone(),
+        dates,
+        accounts,
+        transactions,
+    })
+}
+
+/// The posting to the account of the CSV file, and the counter posting if the schema has a
+/// [counter account](Schema::counter_account)
+fn postings(schema: &Schema, amount: Decimal) -> Vec<Posting> {
+    let posting = |account_name: &String, amount: Decimal| Posting {
+        account_name: account_name.clone(),
+        amount: Amount::single_currency(amount),
+        metadata: hash_map![],
+    };
+    let mut postings = vec![posting(&schema.account, amount)];
+    if let Some(counter_account) = &schema.counter_account {
+        postings.push(posting(counter_account, -amount));
+    }
+    postings
+}
+
+/// Returns the balances before the first and after the last row, and checks that the running balance matches the amounts in between.
+fn balances(rows: &[Row]) -> Result<(Option<Decimal>, Option<Decimal>)> {
+    let Some(first_balance) = rows[0].balance else {
+        ensure!(
+            rows.iter().all(|row| row.balance.is_none()),
+            "Balance column must either be empty for all rows or for none"
+        );
+        return Ok((None, None));
+    };
+    let start_balance = first_balance - rows[0].amount;
+    let mut balance = start_balance;
+    for row in rows {
+        let expected_balance = balance + row.amount;
+        let actual_balance = row.balance.ok_or_else(|| {
+            anyhow!("Balance column must either be empty for all rows or for none")
+        })?;
+        ensure!(
+            expected_balance == actual_balance,
+            "Balance mismatch on {} '{}': expected {expected_balance} but the CSV says {actual_balance}",
+            row.date,
+            row.description,
+        );
+        balance = actual_balance;
+    }
+    Ok((Some(start_balance), Some(balance)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(toml: &str) -> Schema {
+        Schema::parse(toml).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(None, parse_amount("", '.').unwrap());
+        assert_eq!(None, parse_amount("  ", '.').unwrap());
+        assert_eq!(
+            Some(Decimal::new(12345, 2)),
+            parse_amount("123.45", '.').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(-12345, 2)),
+            parse_amount("-123.45", '.').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(-123456, 2)),
+            parse_amount("-$1,234.56", '.').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(-123456, 2)),
+            parse_amount("(1,234.56)", '.').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(123456, 2)),
+            parse_amount("1.234,56 EUR", ',').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(-123456, 2)),
+            parse_amount("-1'234.56", '.').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(-450, 2)),
+            parse_amount("- $4.50", '.').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(2500, 2)),
+            parse_amount("+ $25.00", '.').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(-123456, 2)),
+            parse_amount("1,234.56 USD-", '.').unwrap()
+        );
+        assert!(parse_amount("abc", '.').is_err());
+        assert!(parse_amount("12.34.5", '.').is_err());
+    }
+
+    #[test]
+    fn separators_from_schema() {
+        assert_eq!(
+            Some(Decimal::new(1234, 0)),
+            parse_amount("1,234", '.').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(1234, 0)),
+            parse_amount("1.234", ',').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(123456, 2)),
+            parse_amount("1.234,56", ',').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(125, 3)),
+            parse_amount("0.125", '.').unwrap()
+        );
+        assert_eq!(
+            Some(Decimal::new(1234567, 3)),
+            parse_amount("1,234.567", '.').unwrap()
+        );
+        // Decimal separator before the thousands separator
+        assert!(parse_amount("1.234,56", '.').is_err());
+        assert!(parse_amount("1,234.56", ',').is_err());
+    }
+
+    #[test]
+    fn reject_ambiguous_amounts() {
+        for (cell, decimal_separator) in [("1.234", '.'), ("1,234", ','), ("-12.345", '.')] {
+            let err = parse_amount(cell, decimal_separator).unwrap_err();
+            assert!(
+                err.to_string().contains("ambiguous"),
+                "'{cell}' should be rejected as ambiguous, got: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn reject_malformed_amounts() {
+        for cell in [
+            "1e5",
+            "1-2",
+            "--5",
+            "-(5)",
+            "-5-",
+            "$5 USD",
+            "5 EURO",
+            "5 usd",
+            "5 E",
+            "1.2.3",
+            "-",
+            "$",
+            "12,34",
+            "1,2345.00",
+            "1,234'567",
+        ] {
+            assert!(
+                parse_amount(cell, '.').is_err(),
+                "'{cell}' should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn book_counter_postings_to_counter_account() {
+        let schema = schema(
+            r#"
+name = "My Bank"
+account = "Checking"
+counter_account = "Uncategorized"
+date_format = "%m/%d/%Y"
+
+[columns]
+date = 0
+description = 1
+amount = 2
+"#,
+        );
+        let input = "Date,Description,Amount
+01/05/2024,Groceries,-25.50
+";
+        let ledger = load(&schema, input.as_bytes()).unwrap();
+        let transaction = &ledger.transactions[0];
+        assert!(transaction.is_balanced());
+        assert_eq!("Uncategorized", transaction.postings[1].account_name);
+        assert_eq!(
+            Amount::single_currency(Decimal::new(2550, 2)),
+            transaction.postings[1].amount
+        );
+        assert!(ledger.accounts.contains_key("Uncategorized"));
+    }
+
+    #[test]
+    fn load_with_amount_and_balance() {
+        let schema = schema(
+            r#"
+name = "My Bank"
+account = "Checking"
+date_format = "%m/%d/%Y"
+
+[columns]
+date = 0
+description = 1
+amount = 2
+balance = 3
+"#,
+        );
+        let input = "Date,Description,Amount,Balance
+01/02/2024,Salary,\"1,000.00\",\"1,100.00\"
+01/05/2024,Groceries,-25.50,1074.50
+
+01/05/2024,Coffee,-4.50,1070.00
+";
+        let ledger = load(&schema, input.as_bytes()).unwrap();
+        assert_eq!("My Bank", ledger.ledger_name);
+        assert_eq!("USD", ledger.ledger_currency);
+        assert_eq!(date(2024, 1, 2), ledger.dates.start_date);
+        assert_eq!(date(2024, 1, 5), ledger.dates.end_date);
+        let account = &ledger.accounts["Checking"];
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(10000, 2))),
+            account.start_balance
+        );
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(107000, 2))),
+            account.end_balance
+        );
+        assert_eq!(3, ledger.transactions.len());
+        assert_eq!("Groceries", ledger.transactions[1].description);
+        assert_eq!(
+            Amount::single_currency(Decimal::new(-2550, 2)),
+            ledger.transactions[1].postings[0].amount
+        );
+    }
+
+    #[test]
+    fn load_newest_first_with_debit_credit() {
+        let schema = schema(
+            r#"
+name = "My Card"
+account = "Credit Card"
+currency = "EUR"
+date_format = "%d.%m.%Y"
+delimiter = ";"
+decimal_separator = ","
+skip_lines = 2
+sign_convention = "positive-is-withdrawal"
+
+[columns]
+date = 0
+description = 1
+debit = 2
+credit = 3
+balance = 4
+"#,
+        );
+        let input = "Some Bank Export
+Account: 1234
+Datum;Text;Soll;Haben;Saldo
+05.01.2024;Payment;;100,00;20,00
+02.01.2024;Purchase;120,00;;120,00
+";
+        let ledger = load(&schema, input.as_bytes()).unwrap();
+        assert_eq!("EUR", ledger.ledger_currency);
+        assert_eq!(2, ledger.transactions.len());
+        assert_eq!("Purchase", ledger.transactions[0].description);
+        assert_eq!(
+            Amount::single_currency(Decimal::new(-12000, 2)),
+            ledger.transactions[0].postings[0].amount
+        );
+        assert_eq!(
+            Amount::single_currency(Decimal::new(10000, 2)),
+            ledger.transactions[1].postings[0].amount
+        );
+        let account = &ledger.accounts["Credit Card"];
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(0, 2))),
+            account.start_balance
+        );
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(-2000, 2))),
+            account.end_balance
+        );
+    }
+
+    #[test]
+    fn load_without_balance() {
+        let schema = schema(
+            r#"
+name = "My Bank"
+account = "Checking"
+date_format = "%Y-%m-%d"
+has_header_row = false
+
+[columns]
+date = 0
+description = 1
+amount = 2
+"#,
+        );
+        let input = "2024-01-02,Salary,1000\n2024-01-03,Rent,-800\n";
+        let ledger = load(&schema, input.as_bytes()).unwrap();
+        assert_eq!(2, ledger.transactions.len());
+        assert_eq!(None, ledger.accounts["Checking"].start_balance);
+        assert_eq!(None, ledger.accounts["Checking"].end_balance);
+    }
+
+    #[test]
+    fn balance_mismatch() {
+        let schema = schema(
+            r#"
+name = "My Bank"
+account = "Checking"
+date_format = "%Y-%m-%d"
+
+[columns]
+date = 0
+description = 1
+amount = 2
+balance = 3
+"#,
+        );
+        let input = "Date,Description,Amount,Balance
+2024-01-02,Salary,1000,1100
+2024-01-03,Rent,-800,301
+";
+        let err = load(&schema, input.as_bytes()).unwrap_err();
+        assert_eq!(
+            "Balance mismatch on 2024-01-03 'Rent': expected 300 but the CSV says 301",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_date() {
+        let schema = schema(
+            r#"
+name = "My Bank"
+account = "Checking"
+date_format = "%Y-%m-%d"
+
+[columns]
+date = 0
+description = 1
+amount = 2
+"#,
+        );
+        let input = "Date,Description,Amount\n01/02/2024,Salary,1000\n";
+        assert!(load(&schema, input.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn empty_file() {
+        let schema = schema(
+            r#"
+name = "My Bank"
+account = "Checking"
+date_format = "%Y-%m-%d"
+
+[columns]
+date = 0
+description = 1
+amount = 2
+"#,
+        );
+        let input = "Date,Description,Amount\n";
+        assert!(load(&schema, input.as_bytes()).is_err());
+    }
+}
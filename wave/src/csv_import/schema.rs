@@ -0,0 +1,233 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Describes the layout of a bank's CSV export so it can be imported without writing a dedicated parser.
+///
+/// Example:
+/// ```toml
+/// name = "My Bank"
+/// account = "Checking"
+/// counter_account = "Uncategorized"
+/// currency = "USD"
+/// date_format = "%m/%d/%Y"
+/// sign_convention = "positive-is-deposit"
+///
+/// [columns]
+/// date = 0
+/// description = 2
+/// amount = 3
+/// balance = 4
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    /// Name of the ledger, used as the beancount title
+    pub name: String,
+
+    /// Name of the account the CSV file describes. This is the name shown when mapping accounts to beancount accounts.
+    pub account: String,
+
+    /// Account the other side of each transaction is booked to, e.g. "Uncategorized", mapped to a beancount account
+    /// like [Schema::account]. Without it, each transaction only has a posting to [Schema::account] and is exported
+    /// as unbalanced with the `!` flag, which bean-check rejects until the transactions are categorized.
+    pub counter_account: Option<String>,
+
+    #[serde(default = "default_currency")]
+    pub currency: String,
+
+    /// chrono format string for the date column, e.g. "%Y-%m-%d" or "%m/%d/%Y"
+    pub date_format: String,
+
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+
+    #[serde(default = "default_decimal_separator")]
+    pub decimal_separator: char,
+
+    #[serde(default = "default_has_header_row")]
+    pub has_header_row: bool,
+
+    /// Number of lines to skip at the beginning of the file, e.g. for exports that add a preamble before the CSV header
+    #[serde(default)]
+    pub skip_lines: usize,
+
+    #[serde(default)]
+    pub sign_convention: SignConvention,
+
+    pub columns: Columns,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_decimal_separator() -> char {
+    '.'
+}
+
+fn default_has_header_row() -> bool {
+    true
+}
+
+/// Zero-based column indices
+#[derive(Debug, Clone, Deserialize)]
+pub struct Columns {
+    pub date: usize,
+    pub description: usize,
+    #[serde(flatten)]
+    pub amount: AmountColumns,
+    /// Running balance of the account after the transaction. If present, it is used to validate the amounts and to generate balance assertions.
+    pub balance: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum AmountColumns {
+    /// A single column with a signed amount
+    Amount { amount: usize },
+
+    /// Separate columns for debit and credit, each row has a value in exactly one of them.
+    /// The signed amount is computed as `debit - credit` before the sign convention is applied.
+    DebitCredit { debit: usize, credit: usize },
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignConvention {
+    /// Positive amounts are money coming into the account. This is what most bank accounts use.
+    #[default]
+    PositiveIsDeposit,
+
+    /// Positive amounts are money leaving the account, e.g. charges on many credit card exports.
+    /// The balance column is negated as well, i.e. a positive balance means money is owed.
+    PositiveIsWithdrawal,
+}
+
+impl Schema {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read schema file {}", path.display()))?;
+        Self::parse(&content)
+            .with_context(|| format!("Failed to parse schema file {}", path.display()))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_with_amount_column() {
+        let schema = Schema::parse(
+            r#"
+name = "My Bank"
+account = "Checking"
+date_format = "%m/%d/%Y"
+
+[columns]
+date = 0
+description = 2
+amount = 3
+balance = 4
+"#,
+        )
+        .unwrap();
+        assert_eq!("My Bank", schema.name);
+        assert_eq!("Checking", schema.account);
+        assert_eq!(None, schema.counter_account);
+        assert_eq!("USD", schema.currency);
+        assert_eq!(',', schema.delimiter);
+        assert_eq!('.', schema.decimal_separator);
+        assert!(schema.has_header_row);
+        assert_eq!(0, schema.skip_lines);
+        assert_eq!(SignConvention::PositiveIsDeposit, schema.sign_convention);
+        assert_eq!(0, schema.columns.date);
+        assert_eq!(2, schema.columns.description);
+        assert!(matches!(
+            schema.columns.amount,
+            AmountColumns::Amount { amount: 3 }
+        ));
+        assert_eq!(Some(4), schema.columns.balance);
+    }
+
+    #[test]
+    fn parse_with_debit_credit_columns() {
+        let schema = Schema::parse(
+            r#"
+name = "My Card"
+account = "Credit Card"
+currency = "EUR"
+date_format = "%d.%m.%Y"
+delimiter = ";"
+decimal_separator = ","
+has_header_row = false
+skip_lines = 3
+sign_convention = "positive-is-withdrawal"
+
+[columns]
+date = 1
+description = 0
+debit = 2
+credit = 3
+"#,
+        )
+        .unwrap();
+        assert_eq!("EUR", schema.currency);
+        assert_eq!(';', schema.delimiter);
+        assert_eq!(',', schema.decimal_separator);
+        assert!(!schema.has_header_row);
+        assert_eq!(3, schema.skip_lines);
+        assert_eq!(SignConvention::PositiveIsWithdrawal, schema.sign_convention);
+        assert!(matches!(
+            schema.columns.amount,
+            AmountColumns::DebitCredit {
+                debit: 2,
+                credit: 3
+            }
+        ));
+        assert_eq!(None, schema.columns.balance);
+    }
+
+    #[test]
+    fn missing_amount_column() {
+        let schema = Schema::parse(
+            r#"
+name = "My Bank"
+account = "Checking"
+date_format = "%m/%d/%Y"
+
+[columns]
+date = 0
+description = 2
+"#,
+        );
+        assert!(schema.is_err());
+    }
+
+    #[test]
+    fn unknown_field() {
+        let schema = Schema::parse(
+            r#"
+name = "My Bank"
+account = "Checking"
+date_format = "%m/%d/%Y"
+unknown = 5
+
+[columns]
+date = 0
+description = 2
+amount = 3
+"#,
+        );
+        assert!(schema.is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::ir::Ledger;
+
+/// Writes the intermediate representation after each operations stage to a JSON file, for debugging.
+/// The file is rewritten after each stage so it is still useful if a later stage fails.
+pub struct IrDump {
+    path: Option<PathBuf>,
+    stages: Vec<Stage>,
+}
+
+#[derive(Serialize)]
+struct Stage {
+    stage: String,
+    ledger: Ledger,
+}
+
+impl IrDump {
+    /// If `path` is `None`, recording stages does nothing
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            stages: vec![],
+        }
+    }
+
+    pub fn record(&mut self, stage: &str, ledger: &Ledger) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        self.stages.push(Stage {
+            stage: stage.to_string(),
+            ledger: ledger.clone(),
+        });
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create IR dump file {}", path.display()))?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), &self.stages)
+            .with_context(|| format!("Failed to write IR dump file {}", path.display()))?;
+        Ok(())
+    }
+}
@@ -1,15 +1,19 @@
-use std::{borrow::Cow, collections::HashMap, io::stdout};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    str::FromStr,
+};
 
 use anyhow::{anyhow, Result};
-use beancount_core::{
-    Amount, Balance, BcOption, Directive, Flag, IncompleteAmount, Open, PriceSpec,
-};
-use chrono::Days;
-use common_macros::{hash_map, hash_set};
+use beancount_core::{Amount, Balance, BcOption, Directive, Flag, IncompleteAmount, Open, Price};
+use chrono::{Datelike as _, Days, NaiveDate};
+use common_macros::hash_map;
+use rust_decimal::Decimal;
 
 use crate::{
     config::Config,
-    ir::{self, AccountInfo, Dates, Transaction, LEDGER_CURRENCY},
+    ir::{self, beancount::BeancountAccount, AccountInfo, Dates, Transaction},
 };
 
 fn opening_balance_account() -> beancount_core::Account<'static> {
@@ -19,41 +23,149 @@ fn opening_balance_account() -> beancount_core::Account<'static> {
     }
 }
 
-pub fn print_exported_transactions<'a>(ledger: crate::ir::Ledger, config: &Config) -> Result<()> {
-    print_exported_header(&ledger)?;
+fn retained_earnings_account() -> beancount_core::Account<'static> {
+    beancount_core::Account {
+        ty: beancount_core::AccountType::Equity,
+        parts: vec![Cow::Borrowed("Retained-Earnings")],
+    }
+}
+
+/// Month and day of the last day of the fiscal year, parsed from `MM-DD`
+#[derive(Debug, Clone, Copy)]
+pub struct FiscalYearEnd {
+    month: u32,
+    day: u32,
+}
+
+impl FiscalYearEnd {
+    fn date_in_year(self, year: i32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, self.month, self.day)
+            // Feb 29 in a non-leap year
+            .or_else(|| NaiveDate::from_ymd_opt(year, self.month, self.day - 1))
+            .expect("FiscalYearEnd was validated when parsing")
+    }
+}
+
+impl FromStr for FiscalYearEnd {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (month, day) = value
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Fiscal year end must have the format MM-DD"))?;
+        let month = month.parse()?;
+        let day = day.parse()?;
+        // Check against a leap year so that Feb 29 is allowed
+        NaiveDate::from_ymd_opt(2000, month, day)
+            .ok_or_else(|| anyhow!("Invalid fiscal year end: {value}"))?;
+        Ok(Self { month, day })
+    }
+}
+
+/// Render the ledger as a Beancount file into `out`, with account names and types taken from `config`.
+/// If `fiscal_year_end` is set, the balances of all Income and Expenses accounts are moved to
+/// `Equity:Retained-Earnings` at the end of each fiscal year within the ledger's date range.
+pub fn render_to_writer(
+    ledger: crate::ir::Ledger,
+    config: &Config,
+    fiscal_year_end: Option<FiscalYearEnd>,
+    out: &mut impl Write,
+) -> Result<()> {
+    write_exported_header(&ledger, fiscal_year_end.is_some(), out)?;
+
+    let closings = match fiscal_year_end {
+        Some(fiscal_year_end) => fiscal_year_closings(&ledger, config, fiscal_year_end)?,
+        None => FiscalYearClosings::default(),
+    };
 
     let balances = ledger.accounts.clone();
 
+    write_implied_prices(
+        &ledger.transactions,
+        &ledger.accounts,
+        &ledger.ledger_currency,
+        out,
+    )?;
+
     let (balanced_transactions, unbalanced_transactions): (Vec<_>, Vec<_>) = ledger
         .transactions
         .into_iter()
         .partition(|transaction| transaction.is_balanced());
 
-    print_accounts_and_contained_balanced_transactions(
+    write_accounts_and_contained_balanced_transactions(
         balanced_transactions,
         config,
         ledger.dates,
         balances,
+        &ledger.ledger_currency,
+        &closings.closed_amounts,
+        out,
     )?;
 
-    print_unbalanced_transactions(unbalanced_transactions, config, &ledger.accounts)?;
+    write_unbalanced_transactions(
+        unbalanced_transactions,
+        config,
+        &ledger.accounts,
+        &ledger.ledger_currency,
+        out,
+    )?;
+
+    write_fiscal_year_closings(
+        closings.transactions,
+        config,
+        &ledger.accounts,
+        &ledger.ledger_currency,
+        out,
+    )?;
 
     Ok(())
 }
 
-fn print_exported_header(ledger: &ir::Ledger) -> Result<()> {
-    println!(
-        "; Exported from Wave: {ledger_name}\n; Start Date: {start_date}\n; End Date: {end_date}\n",
+/// Render each transaction of the ledger on its own, with account names taken from `config`. Unlike
+/// [render_to_writer], there are no open directives, balance assertions or prices, for tools that only take
+/// the transactions.
+pub fn render_transactions(ledger: crate::ir::Ledger, config: &Config) -> Result<Vec<String>> {
+    ledger
+        .transactions
+        .into_iter()
+        .map(|transaction| {
+            let directive = transaction_to_beancount(
+                config,
+                transaction,
+                &ledger.accounts,
+                &ledger.ledger_currency,
+            )?;
+            let mut out = vec![];
+            beancount_render::render(
+                &mut out,
+                &beancount_core::Ledger {
+                    directives: vec![directive],
+                },
+            )?;
+            Ok(String::from_utf8(out)?)
+        })
+        .collect()
+}
+
+fn write_exported_header(
+    ledger: &ir::Ledger,
+    open_retained_earnings: bool,
+    out: &mut impl Write,
+) -> Result<()> {
+    writeln!(
+        out,
+        "; Exported from {source}: {ledger_name}\n; Start Date: {start_date}\n; End Date: {end_date}\n",
+        source = ledger.source,
         ledger_name = ledger.ledger_name,
         start_date = ledger.dates.start_date,
         end_date = ledger.dates.end_date
-    );
+    )?;
     let day_before_start_date = ledger
         .dates
         .start_date
         .checked_sub_days(Days::new(1))
         .ok_or_else(|| anyhow!("Failed to subtract a day from the start date"))?;
-    let directives = vec![
+    let mut directives = vec![
         Directive::Option(BcOption {
             name: Cow::Borrowed("title"),
             val: Cow::Borrowed(ledger.ledger_name.as_str()),
@@ -61,31 +173,262 @@ fn print_exported_header(ledger: &ir::Ledger) -> Result<()> {
         }),
         Directive::Option(BcOption {
             name: Cow::Borrowed("operating_currency"),
-            val: Cow::Borrowed(LEDGER_CURRENCY),
+            val: Cow::Borrowed(ledger.ledger_currency.as_str()),
             source: None,
         }),
         Directive::Open(Open {
             date: day_before_start_date.into(),
             account: opening_balance_account(),
-            currencies: vec![Cow::Borrowed(LEDGER_CURRENCY)],
+            currencies: vec![Cow::Borrowed(ledger.ledger_currency.as_str())],
             booking: None,
             meta: hash_map![],
             source: None,
         }),
     ];
+    if open_retained_earnings {
+        directives.push(Directive::Open(Open {
+            date: day_before_start_date.into(),
+            account: retained_earnings_account(),
+            currencies: vec![Cow::Borrowed(ledger.ledger_currency.as_str())],
+            booking: None,
+            meta: hash_map![],
+            source: None,
+        }));
+    }
+    let ledger = beancount_core::Ledger { directives };
+    beancount_render::render(out, &ledger)?;
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct FiscalYearClosings {
+    /// One transaction per fiscal year end, with postings that bring each Income and Expenses account to zero.
+    /// The counter posting to [retained_earnings_account] is added when rendering.
+    transactions: Vec<Transaction>,
+    /// Sum of all closing postings per account, needed to adjust the final balance assertions
+    closed_amounts: HashMap<String, ir::Amount>,
+}
+
+fn fiscal_year_closings(
+    ledger: &ir::Ledger,
+    config: &Config,
+    fiscal_year_end: FiscalYearEnd,
+) -> Result<FiscalYearClosings> {
+    let mut balances: BTreeMap<&str, ir::Amount> = BTreeMap::new();
+    for (name, account_info) in &ledger.accounts {
+        let account = config.lookup_beancount_account_name(name)?;
+        if matches!(
+            account.ty,
+            beancount_core::AccountType::Income | beancount_core::AccountType::Expenses
+        ) {
+            balances.insert(
+                name,
+                account_info.start_balance.unwrap_or_else(ir::Amount::zero),
+            );
+        }
+    }
+
+    let mut postings: Vec<(NaiveDate, &ir::Posting)> = ledger
+        .transactions
+        .iter()
+        .flat_map(|transaction| {
+            transaction
+                .postings
+                .iter()
+                .map(|posting| (transaction.date, posting))
+        })
+        .filter(|(_, posting)| balances.contains_key(posting.account_name.as_str()))
+        .collect();
+    postings.sort_by_key(|(date, _)| *date);
+    let mut postings = postings.into_iter().peekable();
+
+    let mut closings = FiscalYearClosings::default();
+    let closing_dates = (ledger.dates.start_date.year()..=ledger.dates.end_date.year())
+        .map(|year| fiscal_year_end.date_in_year(year))
+        .filter(|date| ledger.dates.start_date <= *date && *date <= ledger.dates.end_date);
+    for closing_date in closing_dates {
+        while let Some((_, posting)) = postings.next_if(|(date, _)| *date <= closing_date) {
+            *balances
+                .get_mut(posting.account_name.as_str())
+                .expect("We filtered postings to closed accounts") += posting.amount;
+        }
+        let closing_postings: Vec<ir::Posting> = balances
+            .iter_mut()
+            .filter(|(_, balance)| !balance.is_zero())
+            .map(|(name, balance)| {
+                let amount = -std::mem::replace(balance, ir::Amount::zero());
+                *closings
+                    .closed_amounts
+                    .entry(name.to_string())
+                    .or_insert_with(ir::Amount::zero) += amount;
+                ir::Posting {
+                    account_name: name.to_string(),
+                    amount,
+                    metadata: hash_map![],
+                }
+            })
+            .collect();
+        if !closing_postings.is_empty() {
+            closings.transactions.push(Transaction {
+                date: closing_date,
+                description: format!("Close fiscal year ending {closing_date}"),
+                payee: None,
+                metadata: hash_map![],
+                tags: vec![],
+                postings: closing_postings,
+            });
+        }
+    }
+
+    Ok(closings)
+}
+
+fn write_fiscal_year_closings(
+    transactions: Vec<Transaction>,
+    config: &Config,
+    accounts: &HashMap<String, AccountInfo>,
+    ledger_currency: &str,
+    out: &mut impl Write,
+) -> Result<()> {
+    if transactions.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "\n\n;; Fiscal Year Closing\n")?;
+    let directives =
+        fiscal_year_closing_directives(transactions, config, accounts, ledger_currency)?;
     let ledger = beancount_core::Ledger { directives };
-    beancount_render::render(&mut stdout(), &ledger)?;
+    beancount_render::render(out, &ledger)?;
+    Ok(())
+}
+
+/// The closing transactions with their counter posting to [retained_earnings_account]
+fn fiscal_year_closing_directives<'a>(
+    transactions: Vec<Transaction>,
+    config: &'a Config,
+    accounts: &'a HashMap<String, AccountInfo>,
+    ledger_currency: &'a str,
+) -> Result<Vec<Directive<'a>>> {
+    transactions
+        .into_iter()
+        .map(|transaction| {
+            let retained_earnings = -transaction
+                .postings
+                .iter()
+                .map(|posting| posting.amount.in_ledger_currency)
+                .sum::<Decimal>();
+            let Directive::Transaction(mut directive) =
+                transaction_to_beancount(config, transaction, accounts, ledger_currency)?
+            else {
+                unreachable!("transaction_to_beancount always returns a transaction");
+            };
+            directive.flag = Flag::Okay;
+            directive.postings.push(beancount_core::Posting {
+                account: retained_earnings_account(),
+                units: IncompleteAmount {
+                    num: Some(ir::beancount::format_amount(
+                        retained_earnings,
+                        Some(ledger_currency),
+                    )),
+                    currency: Some(Cow::Borrowed(ledger_currency)),
+                },
+                cost: None,
+                price: None,
+                flag: None,
+                meta: hash_map![],
+            });
+            Ok(Directive::Transaction(directive))
+        })
+        .collect()
+}
+
+/// Number of decimal places to round implied prices to
+const PRICE_DECIMAL_PLACES: u32 = 8;
+
+/// Postings in accounts that aren't in the ledger currency carry both amounts, which implies an exchange rate.
+/// Emit one price directive per currency and day so that tools like fava can value those accounts.
+/// If there are multiple postings for a currency on a day, their amounts are summed up and the average rate is used.
+fn write_implied_prices(
+    transactions: &[Transaction],
+    accounts: &HashMap<String, AccountInfo>,
+    ledger_currency: &str,
+    out: &mut impl Write,
+) -> Result<()> {
+    let directives = implied_prices(transactions, accounts, ledger_currency)?;
+    if directives.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "\n;; Implied Prices\n")?;
+    let ledger = beancount_core::Ledger { directives };
+    beancount_render::render(out, &ledger)?;
+    writeln!(out, "\n")?;
 
     Ok(())
 }
 
-fn print_accounts_and_contained_balanced_transactions(
+fn implied_prices<'a>(
+    transactions: &[Transaction],
+    accounts: &'a HashMap<String, AccountInfo>,
+    ledger_currency: &'a str,
+) -> Result<Vec<Directive<'a>>> {
+    let mut sums: BTreeMap<(NaiveDate, &str), (Decimal, Decimal)> = BTreeMap::new();
+    for transaction in transactions {
+        for posting in &transaction.postings {
+            let account_currency = &accounts
+                .get(&posting.account_name)
+                .ok_or_else(|| anyhow!("Account not found in accounts: {}", posting.account_name))?
+                .account_currency;
+            if account_currency == ledger_currency {
+                continue;
+            }
+            let (sum_in_account_currency, sum_in_ledger_currency) = sums
+                .entry((transaction.date, account_currency.as_str()))
+                .or_default();
+            *sum_in_account_currency += posting.amount.in_account_currency.abs();
+            *sum_in_ledger_currency += posting.amount.in_ledger_currency.abs();
+        }
+    }
+
+    let directives = sums
+        .into_iter()
+        .filter(|(_, (sum_in_account_currency, _))| !sum_in_account_currency.is_zero())
+        .map(
+            |((date, currency), (sum_in_account_currency, sum_in_ledger_currency))| {
+                Directive::Price(Price {
+                    date: date.into(),
+                    currency: Cow::Borrowed(currency),
+                    amount: Amount {
+                        num: (sum_in_ledger_currency / sum_in_account_currency)
+                            .round_dp(PRICE_DECIMAL_PLACES)
+                            .normalize(),
+                        currency: Cow::Borrowed(ledger_currency),
+                    },
+                    meta: hash_map![],
+                    source: None,
+                })
+            },
+        )
+        .collect();
+    Ok(directives)
+}
+
+fn write_accounts_and_contained_balanced_transactions(
     balanced_transactions: Vec<Transaction>,
     config: &Config,
     dates: Dates,
     accounts: HashMap<String, AccountInfo>,
+    ledger_currency: &str,
+    closed_amounts: &HashMap<String, ir::Amount>,
+    out: &mut impl Write,
 ) -> Result<()> {
     let mut account_ledgers = group_by_account(balanced_transactions.into_iter(), config)?;
+    let context = LedgerContext {
+        config,
+        dates,
+        accounts: &accounts,
+        ledger_currency,
+    };
 
     // Don't iterate over account_ledgers because they may not contain all accounts (e.g. they won't contain accounts that have all transactions assigned to other accounts)
     // Instead, iterate over all account names in the ledger. This makes sure we still print account opening directives and balance assertions for accounts that have no transactions.
@@ -95,29 +438,61 @@ fn print_accounts_and_contained_balanced_transactions(
             .remove(&beancount_account)
             .unwrap_or_else(|| vec![]);
 
-        print_account_and_transactions(
+        write_account_and_transactions(
+            &context,
             &account,
-            config,
             beancount_account,
             account_info,
-            dates,
             transactions,
-            &accounts,
+            closed_amounts
+                .get(account)
+                .copied()
+                .unwrap_or_else(ir::Amount::zero),
+            out,
         )?;
     }
 
     Ok(())
 }
 
-fn print_account_and_transactions(
+/// What the directives of all accounts of a ledger are rendered with
+struct LedgerContext<'a> {
+    config: &'a Config,
+    dates: Dates,
+    accounts: &'a HashMap<String, AccountInfo>,
+    ledger_currency: &'a str,
+}
+
+fn write_account_and_transactions(
+    context: &LedgerContext,
     import_account_name: &str,
-    config: &Config,
     account: beancount_core::Account,
     account_info: &AccountInfo,
-    dates: Dates,
     transactions: Vec<Transaction>,
-    accounts: &HashMap<String, AccountInfo>,
+    closed_amount: ir::Amount,
+    out: &mut impl Write,
 ) -> Result<()> {
+    let directives =
+        account_directives(context, account, account_info, transactions, closed_amount)?;
+    let ledger = beancount_core::Ledger { directives };
+
+    writeln!(out, "\n; Imported Account: {import_account_name}\n")?;
+    beancount_render::render(out, &ledger)?;
+    writeln!(out, "\n\n")?;
+
+    Ok(())
+}
+
+/// Open directive, balance assertions and transactions of one account. `closed_amount` is what fiscal year closings
+/// moved to the retained earnings, see [FiscalYearClosings::closed_amounts].
+fn account_directives<'a>(
+    context: &LedgerContext<'a>,
+    account: beancount_core::Account<'a>,
+    account_info: &'a AccountInfo,
+    transactions: Vec<Transaction>,
+    closed_amount: ir::Amount,
+) -> Result<Vec<Directive<'a>>> {
+    let dates = context.dates;
     let mut directives = vec![];
     // Open the account a day before the first transaction because the balance assertion must be on the day after the pad directive.
     let day_before_start_date = dates
@@ -137,65 +512,80 @@ fn print_account_and_transactions(
         meta: hash_map![],
         source: None,
     }));
-    if !account_info.start_balance.is_zero() {
-        directives.push(Directive::Pad(beancount_core::Pad {
-            date: day_before_start_date.into(),
-            pad_to_account: account.clone(),
-            pad_from_account: opening_balance_account(),
+    if let Some(start_balance) = account_info.start_balance {
+        if !start_balance.is_zero() {
+            directives.push(Directive::Pad(beancount_core::Pad {
+                date: day_before_start_date.into(),
+                pad_to_account: account.clone(),
+                pad_from_account: opening_balance_account(),
+                meta: hash_map![],
+                source: None,
+            }));
+        }
+        directives.push(Directive::Balance(Balance {
+            date: dates.start_date.into(),
+            account: account.clone(),
+            amount: Amount {
+                num: ir::beancount::format_amount(
+                    start_balance.in_account_currency,
+                    Some(&account_info.account_currency),
+                ),
+                currency: Cow::Borrowed(&account_info.account_currency),
+            },
+            tolerance: None,
             meta: hash_map![],
             source: None,
         }));
     }
-    directives.push(Directive::Balance(Balance {
-        date: dates.start_date.into(),
-        account: account.clone(),
-        amount: Amount {
-            num: account_info.start_balance.in_account_currency,
-            currency: Cow::Borrowed(&account_info.account_currency),
-        },
-        tolerance: None,
-        meta: hash_map![],
-        source: None,
-    }));
     directives.extend(
         transactions
             .into_iter()
-            .map(|transaction| transaction_to_beancount(config, transaction, accounts))
+            .map(|transaction| {
+                transaction_to_beancount(
+                    context.config,
+                    transaction,
+                    context.accounts,
+                    context.ledger_currency,
+                )
+            })
             .collect::<Result<Vec<_>>>()?
             .into_iter(),
     );
-    directives.push(Directive::Balance(Balance {
-        date: day_after_end_date.into(),
-        account: account.clone(),
-        amount: Amount {
-            num: account_info.end_balance.in_account_currency,
-            currency: Cow::Borrowed(&account_info.account_currency),
-        },
-        tolerance: None,
-        meta: hash_map![],
-        source: None,
-    }));
-    let ledger = beancount_core::Ledger { directives };
-
-    println!("\n; Imported Account: {import_account_name}\n");
-    beancount_render::render(&mut stdout(), &ledger)?;
-    println!("\n\n");
-
-    Ok(())
+    if let Some(end_balance) = account_info.end_balance {
+        // Fiscal year closings moved part of the balance to the retained earnings
+        let end_balance = end_balance + closed_amount;
+        directives.push(Directive::Balance(Balance {
+            date: day_after_end_date.into(),
+            account: account.clone(),
+            amount: Amount {
+                num: ir::beancount::format_amount(
+                    end_balance.in_account_currency,
+                    Some(&account_info.account_currency),
+                ),
+                currency: Cow::Borrowed(&account_info.account_currency),
+            },
+            tolerance: None,
+            meta: hash_map![],
+            source: None,
+        }));
+    }
+    Ok(directives)
 }
 
-fn print_unbalanced_transactions(
+fn write_unbalanced_transactions(
     unbalanced_transactions: Vec<Transaction>,
     config: &Config,
     accounts: &HashMap<String, AccountInfo>,
+    ledger_currency: &str,
+    out: &mut impl Write,
 ) -> Result<()> {
-    println!("\n\n;; Unbalanced Transactions\n");
+    writeln!(out, "\n\n;; Unbalanced Transactions\n")?;
     let directives = unbalanced_transactions
         .into_iter()
-        .map(|transaction| transaction_to_beancount(config, transaction, accounts))
+        .map(|transaction| transaction_to_beancount(config, transaction, accounts, ledger_currency))
         .collect::<Result<Vec<_>>>()?;
     let ledger = beancount_core::Ledger { directives };
-    beancount_render::render(&mut stdout(), &ledger)?;
+    beancount_render::render(out, &ledger)?;
     Ok(())
 }
 
@@ -203,58 +593,20 @@ fn transaction_to_beancount<'a>(
     config: &'a Config,
     transaction: crate::ir::Transaction,
     accounts: &'a HashMap<String, AccountInfo>,
+    ledger_currency: &'a str,
 ) -> Result<Directive<'a>> {
-    let flag = if transaction.is_balanced() {
-        Flag::Okay
-    } else {
-        Flag::Warning
-    };
-    Ok(Directive::Transaction(beancount_core::Transaction {
-        date: transaction.date.into(),
-        flag,
-        payee: None,
-        tags: hash_set![],
-        links: hash_set![],
-        narration: transaction.description.into(),
-        postings: transaction
-            .postings
-            .into_iter()
-            .map(|posting| posting_to_beancount(config, posting, accounts))
-            .collect::<Result<Vec<_>>>()?,
-        meta: hash_map![],
-        source: None,
-    }))
-}
-
-fn posting_to_beancount<'a>(
-    config: &'a Config,
-    posting: crate::ir::Posting,
-    accounts: &'a HashMap<String, AccountInfo>,
-) -> Result<beancount_core::Posting<'a>> {
-    let account_currency = &accounts
-        .get(&posting.account_name)
-        .ok_or_else(|| anyhow!("Account not found in accounts: {}", posting.account_name))?
-        .account_currency;
-    let price = if account_currency == LEDGER_CURRENCY {
-        None
-    } else {
-        Some(PriceSpec::Total(IncompleteAmount {
-            num: Some(posting.amount.in_ledger_currency.abs()),
-            currency: Some(Cow::Borrowed(LEDGER_CURRENCY)),
-        }))
-    };
-    Ok(beancount_core::Posting {
-        account: config.lookup_beancount_account_name(&posting.account_name)?,
-        units: IncompleteAmount {
-            num: Some(posting.amount.in_account_currency),
-            currency: Some(Cow::Borrowed(account_currency)),
-        },
-        cost: None,
-        price,
-        flag: None,
-        meta: hash_map![],
+    ir::beancount::transaction_to_beancount(transaction, Some(ledger_currency), |account_name| {
+        let account_currency = &accounts
+            .get(account_name)
+            .ok_or_else(|| anyhow!("Account not found in accounts: {account_name}"))?
+            .account_currency;
+        Ok(BeancountAccount {
+            account: config.lookup_beancount_account_name(account_name)?,
+            currency: Some(account_currency),
+        })
     })
 }
+
 fn group_by_account(
     transactions: impl Iterator<Item = Transaction>,
     config: &Config,
@@ -296,3 +648,256 @@ fn group_by_account(
 
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn usd(amount: i64) -> ir::Amount {
+        ir::Amount::single_currency(Decimal::new(amount, 2))
+    }
+
+    fn config() -> Config {
+        Config::from_yaml(
+            "beancount_account_names:\n  Checking: Assets:Checking\n  Sales: Income:Sales\n  Rent: Expenses:Rent\n",
+        )
+        .unwrap()
+    }
+
+    /// Moves `amount` from `from` to `to`
+    fn transaction(date: NaiveDate, from: &str, to: &str, amount: i64) -> Transaction {
+        let posting = |account_name: &str, amount: ir::Amount| ir::Posting {
+            account_name: account_name.to_string(),
+            amount,
+            metadata: hash_map![],
+        };
+        Transaction {
+            date,
+            description: format!("{from} to {to}"),
+            payee: None,
+            metadata: hash_map![],
+            tags: vec![],
+            postings: vec![posting(from, -usd(amount)), posting(to, usd(amount))],
+        }
+    }
+
+    fn account_info(start_balance: i64, end_balance: i64) -> AccountInfo {
+        AccountInfo {
+            start_balance: Some(usd(start_balance)),
+            end_balance: Some(usd(end_balance)),
+            account_currency: "USD".to_string(),
+        }
+    }
+
+    /// Runs over two and a half fiscal years if they end on Dec 31
+    fn ledger() -> ir::Ledger {
+        ir::Ledger {
+            source: "Wave".to_string(),
+            ledger_name: "Business".to_string(),
+            ledger_currency: "USD".to_string(),
+            dates: Dates {
+                start_date: date(2023, 7, 1),
+                end_date: date(2025, 3, 31),
+            },
+            accounts: hash_map![
+                "Checking".to_string() => account_info(0, 34000),
+                "Sales".to_string() => account_info(-5000, -39000),
+                "Rent".to_string() => account_info(0, 3000),
+            ],
+            transactions: vec![
+                transaction(date(2023, 8, 1), "Sales", "Checking", 20000),
+                transaction(date(2023, 12, 31), "Checking", "Rent", 3000),
+                transaction(date(2024, 1, 5), "Sales", "Checking", 10000),
+                transaction(date(2025, 1, 10), "Sales", "Checking", 4000),
+            ],
+        }
+    }
+
+    fn postings(transaction: &Transaction) -> Vec<(&str, ir::Amount)> {
+        transaction
+            .postings
+            .iter()
+            .map(|posting| (posting.account_name.as_str(), posting.amount))
+            .collect()
+    }
+
+    #[test]
+    fn imply_prices_of_foreign_currency_postings() {
+        let mut accounts = hash_map![
+            "Checking".to_string() => account_info(0, 0),
+            "Rent".to_string() => account_info(0, 0),
+        ];
+        accounts.insert(
+            "Euro Account".to_string(),
+            AccountInfo {
+                start_balance: None,
+                end_balance: None,
+                account_currency: "EUR".to_string(),
+            },
+        );
+        let foreign_posting = |in_account_currency: i64, in_ledger_currency: i64| ir::Posting {
+            account_name: "Euro Account".to_string(),
+            amount: ir::Amount {
+                in_account_currency: Decimal::new(in_account_currency, 2),
+                in_ledger_currency: Decimal::new(in_ledger_currency, 2),
+            },
+            metadata: hash_map![],
+        };
+        let mut transactions = vec![
+            transaction(date(2024, 1, 5), "Checking", "Rent", 1000),
+            transaction(date(2024, 1, 5), "Checking", "Rent", 2000),
+            transaction(date(2024, 1, 6), "Checking", "Rent", 3000),
+        ];
+        // Two postings on Jan 5 average to 31.00 USD / 30.00 EUR, Jan 6 is 1 USD / 3 EUR
+        transactions[0].postings[1] = foreign_posting(1000, -1100);
+        transactions[1].postings[1] = foreign_posting(-2000, 2000);
+        transactions[2].postings[1] = foreign_posting(300, 100);
+
+        let prices: Vec<(beancount_core::Date, String, Decimal, String)> =
+            implied_prices(&transactions, &accounts, "USD")
+                .unwrap()
+                .into_iter()
+                .map(|directive| {
+                    let Directive::Price(price) = directive else {
+                        panic!("Expected a price, got {directive:?}");
+                    };
+                    (
+                        price.date,
+                        price.currency.to_string(),
+                        price.amount.num,
+                        price.amount.currency.to_string(),
+                    )
+                })
+                .collect();
+        assert_eq!(
+            vec![
+                (
+                    date(2024, 1, 5).into(),
+                    "EUR".to_string(),
+                    Decimal::new(103333333, 8),
+                    "USD".to_string()
+                ),
+                (
+                    date(2024, 1, 6).into(),
+                    "EUR".to_string(),
+                    Decimal::new(33333333, 8),
+                    "USD".to_string()
+                ),
+            ],
+            prices
+        );
+    }
+
+    #[test]
+    fn fiscal_year_end_on_feb_29() {
+        let fiscal_year_end: FiscalYearEnd = "02-29".parse().unwrap();
+        assert_eq!(date(2023, 2, 28), fiscal_year_end.date_in_year(2023));
+        assert_eq!(date(2024, 2, 29), fiscal_year_end.date_in_year(2024));
+        assert!("02-30".parse::<FiscalYearEnd>().is_err());
+    }
+
+    #[test]
+    fn close_income_and_expenses_at_each_fiscal_year_end_in_the_ledger() {
+        let closings =
+            fiscal_year_closings(&ledger(), &config(), "12-31".parse().unwrap()).unwrap();
+
+        // Dec 31, 2025 is after the end of the ledger
+        let dates: Vec<NaiveDate> = closings
+            .transactions
+            .iter()
+            .map(|transaction| transaction.date)
+            .collect();
+        assert_eq!(vec![date(2023, 12, 31), date(2024, 12, 31)], dates);
+        // Including the start balance and the postings on the closing date, but not Assets
+        assert_eq!(
+            vec![("Rent", usd(-3000)), ("Sales", usd(25000))],
+            postings(&closings.transactions[0])
+        );
+        assert_eq!(
+            vec![("Sales", usd(10000))],
+            postings(&closings.transactions[1])
+        );
+        assert_eq!(
+            hash_map![
+                "Rent".to_string() => usd(-3000),
+                "Sales".to_string() => usd(35000),
+            ],
+            closings.closed_amounts
+        );
+    }
+
+    #[test]
+    fn close_on_feb_28_in_non_leap_years() {
+        let closings =
+            fiscal_year_closings(&ledger(), &config(), "02-29".parse().unwrap()).unwrap();
+
+        let dates: Vec<NaiveDate> = closings
+            .transactions
+            .iter()
+            .map(|transaction| transaction.date)
+            .collect();
+        assert_eq!(vec![date(2024, 2, 29), date(2025, 2, 28)], dates);
+    }
+
+    #[test]
+    fn adjust_end_balance_assertion_by_closed_amount() {
+        let ledger = ledger();
+        let config = config();
+        let closings = fiscal_year_closings(&ledger, &config, "12-31".parse().unwrap()).unwrap();
+
+        let context = LedgerContext {
+            config: &config,
+            dates: ledger.dates,
+            accounts: &ledger.accounts,
+            ledger_currency: &ledger.ledger_currency,
+        };
+        let directives = account_directives(
+            &context,
+            config.lookup_beancount_account_name("Sales").unwrap(),
+            &ledger.accounts["Sales"],
+            vec![],
+            closings.closed_amounts["Sales"],
+        )
+        .unwrap();
+        let Some(Directive::Balance(end_balance)) = directives.last() else {
+            panic!("Expected the end balance assertion last, got {directives:?}");
+        };
+        // Only the sales after the last closing are left
+        assert_eq!(Decimal::new(-4000, 2), end_balance.amount.num);
+    }
+
+    #[test]
+    fn book_closed_amounts_to_retained_earnings() {
+        let ledger = ledger();
+        let config = config();
+        let closings = fiscal_year_closings(&ledger, &config, "12-31".parse().unwrap()).unwrap();
+
+        let directives = fiscal_year_closing_directives(
+            closings.transactions,
+            &config,
+            &ledger.accounts,
+            &ledger.ledger_currency,
+        )
+        .unwrap();
+        let retained_earnings: Vec<Option<Decimal>> = directives
+            .iter()
+            .map(|directive| {
+                let Directive::Transaction(transaction) = directive else {
+                    panic!("Expected a transaction, got {directive:?}");
+                };
+                assert_eq!(Flag::Okay, transaction.flag);
+                let posting = transaction.postings.last().unwrap();
+                assert_eq!(retained_earnings_account(), posting.account);
+                posting.units.num
+            })
+            .collect();
+        assert_eq!(
+            vec![Some(Decimal::new(-22000, 2)), Some(Decimal::new(-10000, 2))],
+            retained_earnings
+        );
+    }
+}
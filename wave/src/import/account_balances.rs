@@ -0,0 +1,220 @@
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::io::Read;
+
+use super::parser::{parse_amount, WaveLedger};
+
+/// Ending balances per account, as listed in Wave's "Account Balances" report
+#[derive(Debug, PartialEq, Eq)]
+pub struct AccountBalances {
+    pub ending_balances: HashMap<String, Decimal>,
+}
+
+/// Parse Wave's "Account Balances" CSV export.
+/// The report starts with a few title lines, followed by a header row with an account column (e.g. "ACCOUNTS")
+/// and an ending balance column (e.g. "ENDING BALANCE"). Account rows are interleaved with section headings
+/// like "Assets" and "Total Assets" rows, which are skipped because they don't have a parseable balance or are totals.
+pub fn load(input_stream: impl Read) -> Result<AccountBalances> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(input_stream);
+    let mut columns = None;
+    let mut ending_balances = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        let Some((name_column, balance_column)) = columns else {
+            columns = find_columns(&record);
+            continue;
+        };
+        let name = record.get(name_column).unwrap_or_default().trim();
+        if name.is_empty() || name.starts_with("Total") {
+            continue;
+        }
+        let Some(balance) = record.get(balance_column).map(str::trim) else {
+            continue;
+        };
+        let Some(balance) = parse_amount(balance) else {
+            continue;
+        };
+        if ending_balances
+            .insert(name.to_string(), balance.amount)
+            .is_some()
+        {
+            return Err(anyhow!(
+                "Account '{name}' is listed multiple times in the account balances report"
+            ));
+        }
+    }
+    if columns.is_none() {
+        return Err(anyhow!(
+            "Couldn't find the header row with the account and ending balance columns in the account balances report"
+        ));
+    }
+    Ok(AccountBalances { ending_balances })
+}
+
+/// Returns the indices of the account name column and the ending balance column if `record` is the header row
+fn find_columns(record: &csv::StringRecord) -> Option<(usize, usize)> {
+    let headers: Vec<String> = record
+        .iter()
+        .map(|cell| cell.trim().to_uppercase())
+        .collect();
+    let name_column = headers
+        .iter()
+        .position(|header| matches!(header.as_str(), "ACCOUNTS" | "ACCOUNT" | "ACCOUNT NAME"))?;
+    let balance_column = headers
+        .iter()
+        .position(|header| header.starts_with("ENDING BALANCE"))
+        .or_else(|| headers.iter().position(|header| header == "BALANCE"))?;
+    Some((name_column, balance_column))
+}
+
+/// Check that the ending balances computed from the transactions export match the account balances report.
+/// Differences of at most `rounding_tolerance` are accepted. All discrepancies are reported in the returned error.
+pub fn check(
+    ledger: &WaveLedger,
+    account_balances: &AccountBalances,
+    rounding_tolerance: Decimal,
+) -> Result<()> {
+    let mut discrepancies = vec![];
+    for account in &ledger.accounts {
+        let computed = account.ending_balance.ending_balance.in_ledger_currency;
+        match account_balances.ending_balances.get(&account.name) {
+            Some(reported) => {
+                if (computed - reported).abs() > rounding_tolerance {
+                    discrepancies.push(format!(
+                        "Account '{}': transactions export ends at {} but the account balances report says {}",
+                        account.name, computed, reported
+                    ));
+                }
+            }
+            None => {
+                if !computed.is_zero() {
+                    discrepancies.push(format!(
+                        "Account '{}': transactions export ends at {} but the account is missing in the account balances report",
+                        account.name, computed
+                    ));
+                }
+            }
+        }
+    }
+    let mut missing_in_export: Vec<_> = account_balances
+        .ending_balances
+        .iter()
+        .filter(|(name, balance)| {
+            !balance.is_zero() && !ledger.accounts.iter().any(|account| &account.name == *name)
+        })
+        .collect();
+    missing_in_export.sort();
+    for (name, balance) in missing_in_export {
+        discrepancies.push(format!(
+            "Account '{name}': the account balances report says {balance} but the account is missing in the transactions export"
+        ));
+    }
+
+    if discrepancies.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "The transactions export doesn't match the account balances report:\n{}",
+            discrepancies.join("\n")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use common_macros::hash_map;
+
+    use super::*;
+    use crate::import::parser::{Account, EndingBalance};
+    use crate::ir::{Amount, LEDGER_CURRENCY};
+
+    const REPORT: &str = r#"Account Balances
+Personal
+Date Range: 2024-01-01 to 2024-11-30
+Report Type: Accrual (Paid & Unpaid)
+ACCOUNTS,STARTING BALANCE,DEBIT,CREDIT,NET MOVEMENT,ENDING BALANCE
+Assets,,,,,
+Checking,$123.45,$1.23,$15.67,-$14.44,$109.01
+"Savings, Joint","$1,000.00",$0.00,$0.00,$0.00,"$1,000.00"
+Total Assets,"$1,123.45",$1.23,$15.67,-$14.44,"$1,109.01"
+Liabilities,,,,,
+Credit Card,$0.00,$0.00,$0.00,$0.00,$0.00
+"#;
+
+    fn account(name: &str, ending_balance: Decimal) -> Account {
+        Account {
+            name: name.to_string(),
+            account_currency: LEDGER_CURRENCY.to_string(),
+            starting_balance: Amount::zero(),
+            postings: vec![],
+            ending_balance: EndingBalance {
+                total_debit: Amount::zero(),
+                total_credit: Amount::zero(),
+                ending_balance: Amount::single_currency(ending_balance),
+            },
+            balance_change: Amount::zero(),
+        }
+    }
+
+    fn ledger(accounts: Vec<Account>) -> WaveLedger {
+        WaveLedger {
+            ledger_name: "Personal".to_string(),
+            start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2024, 11, 30).unwrap(),
+            accounts,
+            skipped_rows: vec![],
+        }
+    }
+
+    #[test]
+    fn parse_report() {
+        assert_eq!(
+            load(REPORT.as_bytes()).unwrap(),
+            AccountBalances {
+                ending_balances: hash_map![
+                    "Checking".to_string() => Decimal::new(10901, 2),
+                    "Savings, Joint".to_string() => Decimal::new(100000, 2),
+                    "Credit Card".to_string() => Decimal::ZERO,
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn report_without_header() {
+        assert!(load("Account Balances\nChecking,$1.00\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn matching_balances() {
+        let balances = load(REPORT.as_bytes()).unwrap();
+        let ledger = ledger(vec![
+            account("Checking", Decimal::new(10901, 2)),
+            account("Savings, Joint", Decimal::new(100000, 2)),
+            account("Unused", Decimal::ZERO),
+        ]);
+        check(&ledger, &balances, Decimal::ZERO).unwrap();
+    }
+
+    #[test]
+    fn mismatching_balances() {
+        let balances = load(REPORT.as_bytes()).unwrap();
+        let ledger = ledger(vec![
+            account("Checking", Decimal::new(10902, 2)),
+            account("Other", Decimal::new(500, 2)),
+        ]);
+        assert_eq!(
+            check(&ledger, &balances, Decimal::ZERO).unwrap_err().to_string(),
+            "The transactions export doesn't match the account balances report:\n\
+            Account 'Checking': transactions export ends at 109.02 but the account balances report says 109.01\n\
+            Account 'Other': transactions export ends at 5.00 but the account is missing in the account balances report\n\
+            Account 'Savings, Joint': the account balances report says 1000.00 but the account is missing in the transactions export"
+        );
+        check(&ledger, &balances, Decimal::new(1, 2)).unwrap_err();
+    }
+}
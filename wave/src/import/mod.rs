@@ -1,35 +1,192 @@
 use anyhow::Result;
 use ariadne::{Color, Fmt as _, Label, Report, ReportKind, Source};
 use chumsky::Parser as _;
+use common_macros::hash_map;
+#[cfg(not(target_arch = "wasm32"))]
+use indicatif::{ProgressBar, ProgressStyle};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
 use std::io::Read;
+use std::ops::Range;
 
+mod account_balances;
 mod parser;
 
+#[cfg(feature = "fuzzing")]
+pub use parser::fuzz;
+
 use parser::{AccountType, WaveLedger};
 
-use crate::ir::{AccountInfo, Amount, Dates, Ledger, Posting, Transaction};
+use crate::config::{Config, DebitOrCredit};
+use crate::ir::{
+    AccountInfo, Amount, Dates, Ledger, Posting, Transaction, LEDGER_CURRENCY, ROUNDING_ACCOUNT,
+};
+
+pub struct Import {
+    pub ledger: Ledger,
+    /// Accounts whose type (debit vs credit) couldn't be inferred from the postings.
+    /// Their balances are stored as if they were debit accounts until [apply_account_types] is called.
+    pub accounts_with_unknown_type: Vec<String>,
+}
+
+/// Balances in the CSV that are off by at most `rounding_tolerance` are accepted and booked against [ROUNDING_ACCOUNT].
+/// With `lenient`, rows that fail to parse are skipped and reported on stderr instead of failing the import.
+pub fn load(input_stream: impl Read, rounding_tolerance: Decimal, lenient: bool) -> Result<Import> {
+    let wave_ledger = load_wave_ledger(input_stream, rounding_tolerance, lenient)?;
+    to_ir(wave_ledger, rounding_tolerance)
+}
+
+/// Whether the content looks like a Wave "Account Transactions" export. Only looks at the first line.
+pub fn detect(content: &[u8]) -> bool {
+    let content = content
+        .strip_prefix("\u{FEFF}".as_bytes())
+        .unwrap_or(content);
+    let first_line = content
+        .split(|&byte| byte == b'\n')
+        .next()
+        .unwrap_or_default();
+    first_line.strip_suffix(b"\r").unwrap_or(first_line) == b"Account Transactions"
+}
+
+/// Like [load], but for a CSV that is already in memory. Doesn't show a progress bar and returns parser errors
+/// in the error instead of printing them, so it also works in the browser.
+pub fn load_from_str(content: &str, rounding_tolerance: Decimal) -> Result<Import> {
+    let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+    match parser::ledger(rounding_tolerance, false, || {}).parse(content) {
+        Ok(wave_ledger) => to_ir(wave_ledger, rounding_tolerance),
+        Err(errors) => Err(anyhow::anyhow!(
+            "Failed to parse ledger\n{}",
+            render_parser_errors(content, errors, false)
+        )),
+    }
+}
 
-pub fn load(input_stream: impl Read) -> Result<Ledger> {
-    let wave_ledger = load_wave_ledger(input_stream)?;
-    to_ir(wave_ledger)
+/// Like [load], but additionally check the ending balances against Wave's "Account Balances" report
+/// and fail if they don't match, before any output is produced.
+pub fn load_and_check_account_balances(
+    input_stream: impl Read,
+    account_balances_report: impl Read,
+    rounding_tolerance: Decimal,
+    lenient: bool,
+) -> Result<Import> {
+    let wave_ledger = load_wave_ledger(input_stream, rounding_tolerance, lenient)?;
+    let account_balances = account_balances::load(account_balances_report)?;
+    account_balances::check(&wave_ledger, &account_balances, rounding_tolerance)?;
+    to_ir(wave_ledger, rounding_tolerance)
+}
+
+/// Use the account types from the config for accounts whose type couldn't be inferred during import
+pub fn apply_account_types(
+    mut ledger: Ledger,
+    accounts_with_unknown_type: &[String],
+    config: &Config,
+) -> Result<Ledger> {
+    for name in accounts_with_unknown_type {
+        let account_type = config.lookup_account_type(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Couldn't determine account type (debit vs credit) of account '{name}'. Please set its account_type in the config."
+            )
+        })?;
+        if account_type == DebitOrCredit::Credit {
+            let account = ledger
+                .accounts
+                .get_mut(name)
+                .ok_or_else(|| anyhow::anyhow!("Account not found in ledger: {name}"))?;
+            account.start_balance = account.start_balance.map(|balance| -balance);
+            account.end_balance = account.end_balance.map(|balance| -balance);
+        }
+    }
+    Ok(ledger)
 }
 
-fn load_wave_ledger(mut input_stream: impl Read) -> Result<WaveLedger> {
+fn load_wave_ledger(
+    mut input_stream: impl Read,
+    rounding_tolerance: Decimal,
+    lenient: bool,
+) -> Result<WaveLedger> {
     let mut content = String::new();
     input_stream.read_to_string(&mut content)?;
     let content = maybe_remove_byte_order_mark(content);
-    match parser::ledger().parse(content.as_str()) {
-        Ok(parsed) => Ok(parsed),
-        Err(errors) => {
-            for err in errors {
-                print_parser_error(&content, err)
+    #[cfg(not(target_arch = "wasm32"))]
+    let parsed = {
+        let progress = progress_bar(count_accounts(&content));
+        let parsed = parser::ledger(rounding_tolerance, lenient, {
+            let progress = progress.clone();
+            move || progress.inc(1)
+        })
+        .parse(content.as_str());
+        progress.finish_and_clear();
+        parsed
+    };
+    // There's no terminal to show a progress bar in
+    #[cfg(target_arch = "wasm32")]
+    let parsed = parser::ledger(rounding_tolerance, lenient, || {}).parse(content.as_str());
+    match parsed {
+        Ok(parsed) => {
+            if !parsed.skipped_rows.is_empty() {
+                for span in &parsed.skipped_rows {
+                    eprint!("{}", render_skipped_row(&content, span.clone(), true));
+                }
+                eprintln!(
+                    "Skipped {} rows that failed to parse",
+                    parsed.skipped_rows.len()
+                );
             }
+            Ok(parsed)
+        }
+        Err(errors) => {
+            print!("{}", render_parser_errors(&content, errors, true));
             Err(anyhow::anyhow!("Failed to parse ledger"))
         }
     }
 }
 
-fn print_parser_error(input: &str, err: chumsky::error::Simple<char>) {
+/// Cheap estimate of the number of accounts for the progress bar. Each account section has exactly one starting balance row.
+#[cfg(not(target_arch = "wasm32"))]
+fn count_accounts(content: &str) -> u64 {
+    content
+        .lines()
+        .filter(|line| line.trim_start_matches('"').starts_with("Starting Balance"))
+        .count() as u64
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn progress_bar(num_accounts: u64) -> ProgressBar {
+    ProgressBar::new(num_accounts).with_style(
+        ProgressStyle::with_template("Parsing accounts {wide_bar} {pos}/{len}")
+            .expect("Progress bar template is valid"),
+    )
+}
+
+/// Only the first errors are rendered, a file in an unexpected format would otherwise produce an error for every row
+const MAX_RENDERED_ERRORS: usize = 20;
+
+fn render_parser_errors(
+    input: &str,
+    errors: Vec<chumsky::error::Simple<char>>,
+    color: bool,
+) -> String {
+    let num_errors = errors.len();
+    let mut rendered: String = errors
+        .into_iter()
+        .take(MAX_RENDERED_ERRORS)
+        .map(|err| render_parser_error(input, err, color))
+        .collect();
+    if num_errors > MAX_RENDERED_ERRORS {
+        rendered += &format!("... and {} more errors\n", num_errors - MAX_RENDERED_ERRORS);
+    }
+    rendered
+}
+
+/// `color` adds terminal escape codes, which only make sense if the report is printed to a terminal
+fn render_parser_error(input: &str, err: chumsky::error::Simple<char>, color: bool) -> String {
+    let fg = |text: String, fg_color: Color| {
+        if color {
+            text.fg(fg_color).to_string()
+        } else {
+            text
+        }
+    };
     // Taken from https://github.com/zesterer/chumsky/blob/0.9/examples/json.rs
     let msg = if let chumsky::error::SimpleReason::Custom(msg) = err.reason() {
         msg.clone()
@@ -61,6 +218,7 @@ fn print_parser_error(input: &str, err: chumsky::error::Simple<char>) {
     };
 
     let report = Report::build(ReportKind::Error, err.span())
+        .with_config(ariadne::Config::default().with_color(color))
         .with_message(msg)
         .with_label(
             Label::new(err.span())
@@ -69,7 +227,7 @@ fn print_parser_error(input: &str, err: chumsky::error::Simple<char>) {
                     _ => format!(
                         "Unexpected {}",
                         err.found()
-                            .map(|c| format!("token {}", c.fg(Color::Red)))
+                            .map(|c| format!("token {}", fg(c.to_string(), Color::Red)))
                             .unwrap_or_else(|| "end of input".to_string())
                     ),
                 })
@@ -81,7 +239,7 @@ fn print_parser_error(input: &str, err: chumsky::error::Simple<char>) {
             Label::new(span.clone())
                 .with_message(format!(
                     "Unclosed delimiter {}",
-                    delimiter.fg(Color::Yellow)
+                    fg(delimiter.to_string(), Color::Yellow)
                 ))
                 .with_color(Color::Yellow),
         ),
@@ -89,7 +247,29 @@ fn print_parser_error(input: &str, err: chumsky::error::Simple<char>) {
         chumsky::error::SimpleReason::Custom(_) => report,
     };
 
-    report.finish().print(Source::from(&input)).unwrap();
+    let mut rendered = vec![];
+    report
+        .finish()
+        .write(Source::from(&input), &mut rendered)
+        .expect("Writing to a Vec can't fail");
+    String::from_utf8(rendered).expect("Reports are valid UTF-8")
+}
+
+/// Warning about a row that lenient parsing left out, pointing at its content
+fn render_skipped_row(input: &str, span: Range<usize>, color: bool) -> String {
+    let mut rendered = vec![];
+    Report::build(ReportKind::Warning, span.clone())
+        .with_config(ariadne::Config::default().with_color(color))
+        .with_message("Skipped a row that failed to parse")
+        .with_label(
+            Label::new(span)
+                .with_message("This row isn't imported")
+                .with_color(Color::Yellow),
+        )
+        .finish()
+        .write(Source::from(&input), &mut rendered)
+        .expect("Writing to a Vec can't fail");
+    String::from_utf8(rendered).expect("Reports are valid UTF-8")
 }
 
 fn maybe_remove_byte_order_mark(mut content: String) -> String {
@@ -99,47 +279,92 @@ fn maybe_remove_byte_order_mark(mut content: String) -> String {
     content
 }
 
-fn to_ir(ledger: WaveLedger) -> Result<Ledger> {
+fn to_ir(ledger: WaveLedger, rounding_tolerance: Decimal) -> Result<Import> {
     let ledger_name = ledger.ledger_name;
     let dates = Dates {
         start_date: ledger.start_date,
         end_date: ledger.end_date,
     };
-    let accounts = ledger
+    // The parser already validated the accounts, but we need the inferred account types and rounding adjustments
+    let validations = ledger
+        .accounts
+        .iter()
+        .map(|account| account.validate(rounding_tolerance))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut accounts_with_unknown_type = vec![];
+    let mut accounts: HashMap<String, AccountInfo> = ledger
         .accounts
         .iter()
-        .map(|account| {
-            Ok((
+        .zip(&validations)
+        .map(|(account, validation)| {
+            (
                 account.name.clone(),
-                match account.account_type() {
+                match validation.account_type {
                     Some(AccountType::Debit) => AccountInfo {
-                        start_balance: account.starting_balance,
-                        end_balance: account.ending_balance.ending_balance,
+                        start_balance: Some(account.starting_balance),
+                        end_balance: Some(account.ending_balance.ending_balance),
                         account_currency: account.account_currency.clone(),
                     },
                     Some(AccountType::Credit) => AccountInfo {
-                        start_balance: -account.starting_balance,
-                        end_balance: -account.ending_balance.ending_balance,
+                        start_balance: Some(-account.starting_balance),
+                        end_balance: Some(-account.ending_balance.ending_balance),
                         account_currency: account.account_currency.clone(),
                     },
                     None => {
-                        if account.starting_balance.is_zero() && account.ending_balance.ending_balance.is_zero() {
-                            AccountInfo {
-                                start_balance: Amount::zero(),
-                                end_balance: Amount::zero(),
-                                account_currency: account.account_currency.clone(),
-                            }
-                        } else {
-                            anyhow::bail!(
-                                "Couldn't determine account type (debit vs credit) of account '{}'. ",
-                                account.name
-                            );
+                        if !account.starting_balance.is_zero()
+                            || !account.ending_balance.ending_balance.is_zero()
+                        {
+                            accounts_with_unknown_type.push(account.name.clone());
+                        }
+                        AccountInfo {
+                            start_balance: Some(account.starting_balance),
+                            end_balance: Some(account.ending_balance.ending_balance),
+                            account_currency: account.account_currency.clone(),
                         }
                     }
                 },
-            ))
+            )
+        })
+        .collect();
+    let rounding_transactions: Vec<Transaction> = ledger
+        .accounts
+        .iter()
+        .zip(validations)
+        .flat_map(|(account, validation)| {
+            validation
+                .rounding_adjustments
+                .into_iter()
+                .map(|adjustment| Transaction {
+                    date: adjustment.date,
+                    description: "Rounding adjustment".to_string(),
+                    payee: None,
+                    metadata: hash_map![],
+                    tags: vec![],
+                    postings: vec![
+                        Posting {
+                            account_name: account.name.clone(),
+                            amount: adjustment.amount,
+                            metadata: hash_map![],
+                        },
+                        Posting {
+                            account_name: ROUNDING_ACCOUNT.to_string(),
+                            amount: Amount::single_currency(-adjustment.amount.in_ledger_currency),
+                            metadata: hash_map![],
+                        },
+                    ],
+                })
         })
-        .collect::<Result<_>>()?;
+        .collect();
+    if !rounding_transactions.is_empty() {
+        accounts.insert(
+            ROUNDING_ACCOUNT.to_string(),
+            AccountInfo {
+                start_balance: None,
+                end_balance: None,
+                account_currency: LEDGER_CURRENCY.to_string(),
+            },
+        );
+    }
     let transactions = ledger
         .accounts
         .into_iter()
@@ -149,18 +374,65 @@ fn to_ir(ledger: WaveLedger) -> Result<Ledger> {
                 Ok::<Transaction, anyhow::Error>(Transaction {
                     date: posting.date,
                     description: posting.description,
+                    payee: None,
+                    metadata: hash_map![],
+                    tags: vec![],
                     postings: vec![Posting {
                         account_name: account.name.clone(),
                         amount,
+                        metadata: hash_map![],
                     }],
                 })
             })
         })
+        .chain(rounding_transactions.into_iter().map(Ok))
         .collect::<Result<Vec<_>>>()?;
-    Ok(Ledger {
-        ledger_name,
-        transactions,
-        dates,
-        accounts,
+    Ok(Import {
+        ledger: Ledger {
+            source: "Wave".to_string(),
+            ledger_name,
+            ledger_currency: LEDGER_CURRENCY.to_string(),
+            transactions,
+            dates,
+            accounts,
+        },
+        accounts_with_unknown_type,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_from_str_returns_parser_errors_without_colors() {
+        let err = load_from_str("not a wave export", Decimal::ZERO)
+            .err()
+            .expect("Parsing should fail");
+        let message = err.to_string();
+        assert!(message.starts_with("Failed to parse ledger\n"), "{message}");
+        assert!(!message.contains('\u{1b}'), "{message}");
+    }
+
+    #[test]
+    fn load_from_str_limits_rendered_errors() {
+        let broken_rows = ",not a date,Some: Addition,$1.23,,$124.68\n".repeat(30);
+        let input = format!(
+            "Account Transactions
+Personal
+Date Range: 2024-01-01 to 2024-11-30
+Report Type: Accrual (Paid & Unpaid)
+ACCOUNT NUMBER,DATE,DESCRIPTION,DEBIT (In Business Currency),CREDIT (In Business Currency),BALANCE (In Business Currency)
+,First Account,,,,
+Starting Balance,,,,,$123.45
+{broken_rows}Totals and Ending Balance,,,$0.00,$0.00,$123.45
+Balance Change,,,$0.00,,"
+        );
+        let message = load_from_str(&input, Decimal::ZERO)
+            .err()
+            .expect("Parsing should fail")
+            .to_string();
+        // Each broken row has an error for the date and one for its cell
+        assert!(message.ends_with("... and 40 more errors\n"), "{message}");
+    }
+}
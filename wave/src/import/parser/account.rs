@@ -1,24 +1,21 @@
 use anyhow::{ensure, Result};
 use chrono::NaiveDate;
-use chumsky::{error::Simple, Parser as _};
+use chumsky::{error::Simple, prelude::skip_until, Parser as _};
 use rust_decimal::{prelude::Zero, Decimal};
+use std::ops::Range;
 
 use super::{
     header::ColumnSchema,
+    skipped_row,
     utils::{
-        amount_cell, amount_cell_opt, any_cell, cell_tag, comma, date_cell, empty_cell, row_end,
+        self, amount_cell, amount_cell_opt, any_cell, cell_tag, comma, date_cell, empty_cell,
+        row_end,
     },
 };
 use crate::ir::{Amount, LEDGER_CURRENCY, LEDGER_CURRENCY_SYMBOL};
 
 fn currency_symbol(currency: &str) -> Result<&'static str, String> {
-    match currency {
-        "USD" => Ok("$"),
-        "EUR" => Ok("€"),
-        "GBP" => Ok("£"),
-        "CHF" => Ok("CHF"),
-        _ => Err(format!("Unexpected currency {currency}")),
-    }
+    utils::currency_symbol(currency).ok_or_else(|| format!("Unexpected currency {currency}"))
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -31,57 +28,235 @@ pub struct Account {
     pub balance_change: Amount,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AccountType {
     Debit,
     Credit,
 }
 
+/// Which of the numbers in an account doesn't add up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceMismatchKind {
+    /// The posting only matches the running balance of a debit account, but earlier postings were booked like a credit account
+    DebitPostingInCreditAccount,
+    /// The posting only matches the running balance of a credit account, but earlier postings were booked like a debit account
+    CreditPostingInDebitAccount,
+    /// The posting matches the running balance neither as debit nor as credit account
+    Posting,
+    TotalDebit,
+    TotalCredit,
+    EndingBalance,
+    BalanceChange,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct BalanceMismatch {
+    pub account_name: String,
+    pub kind: BalanceMismatchKind,
+    /// Index into [Account::postings] of the offending posting, `None` if the mismatch is in the totals rows
+    pub posting_index: Option<usize>,
+    pub date: Option<NaiveDate>,
+    pub expected: Amount,
+    pub actual: Amount,
+}
+
+impl std::error::Error for BalanceMismatch {}
+
+impl std::fmt::Display for BalanceMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let what = match self.kind {
+            BalanceMismatchKind::DebitPostingInCreditAccount => {
+                "Posting is booked like a debit but the account is a credit account"
+            }
+            BalanceMismatchKind::CreditPostingInDebitAccount => {
+                "Posting is booked like a credit but the account is a debit account"
+            }
+            BalanceMismatchKind::Posting => "Posting balance mismatch",
+            BalanceMismatchKind::TotalDebit => "Total debit mismatch",
+            BalanceMismatchKind::TotalCredit => "Total credit mismatch",
+            BalanceMismatchKind::EndingBalance => "Ending balance mismatch",
+            BalanceMismatchKind::BalanceChange => "Balance change mismatch",
+        };
+        write!(f, "{what} in account '{}'", self.account_name)?;
+        if let Some(date) = self.date {
+            write!(f, " on {date}")?;
+        }
+        write!(
+            f,
+            ": expected {} but the CSV says {}",
+            format_amount(&self.expected),
+            format_amount(&self.actual)
+        )
+    }
+}
+
+fn format_amount(amount: &Amount) -> String {
+    if amount.in_account_currency == amount.in_ledger_currency {
+        amount.in_account_currency.to_string()
+    } else {
+        format!(
+            "{} ({} in {LEDGER_CURRENCY})",
+            amount.in_account_currency, amount.in_ledger_currency
+        )
+    }
+}
+
+/// A posting whose balance in the CSV is off by less than the rounding tolerance
+#[derive(Debug, PartialEq, Eq)]
+pub struct RoundingAdjustment {
+    pub date: NaiveDate,
+    /// Amount that needs to be added to the account (as debit minus credit) to match the balance in the CSV
+    pub amount: Amount,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Validation {
+    /// `None` if the postings don't tell, e.g. because there are none
+    pub account_type: Option<AccountType>,
+    pub rounding_adjustments: Vec<RoundingAdjustment>,
+}
+
+fn is_within_tolerance(actual: Amount, expected: Amount, rounding_tolerance: Decimal) -> bool {
+    let difference = actual - expected;
+    difference.in_account_currency.abs() <= rounding_tolerance
+        && difference.in_ledger_currency.abs() <= rounding_tolerance
+}
+
 impl Account {
-    pub fn validate(&self) -> Result<Option<AccountType>, &'static str> {
+    /// Check that the postings add up to the balances in the CSV and infer whether this is a debit or credit account.
+    /// Balances that are off by at most `rounding_tolerance` are accepted and returned as rounding adjustments.
+    pub fn validate(&self, rounding_tolerance: Decimal) -> Result<Validation, BalanceMismatch> {
+        let mismatch = |kind, posting_index, date, expected, actual| BalanceMismatch {
+            account_name: self.name.clone(),
+            kind,
+            posting_index,
+            date,
+            expected,
+            actual,
+        };
         let mut account_type = None;
+        let mut rounding_adjustments = vec![];
         let mut balance = self.starting_balance;
         let mut total_debit = Amount::zero();
         let mut total_credit = Amount::zero();
-        for posting in &self.postings {
-            if posting.balance == balance + posting.debit - posting.credit {
-                match account_type {
-                    None => account_type = Some(AccountType::Debit),
-                    Some(AccountType::Debit) => {}
-                    Some(AccountType::Credit) => return Err("Debit account balance mismatch"),
+        for (index, posting) in self.postings.iter().enumerate() {
+            let balance_if_debit_account = balance + posting.debit - posting.credit;
+            let balance_if_credit_account = balance - posting.debit + posting.credit;
+            let posting_mismatch = |kind, expected| {
+                mismatch(
+                    kind,
+                    Some(index),
+                    Some(posting.date),
+                    expected,
+                    posting.balance,
+                )
+            };
+            // Exact matches take precedence, otherwise a small posting could be within tolerance of both account types
+            let candidates = match account_type {
+                Some(AccountType::Credit) => [
+                    (AccountType::Credit, balance_if_credit_account),
+                    (AccountType::Debit, balance_if_debit_account),
+                ],
+                Some(AccountType::Debit) | None => [
+                    (AccountType::Debit, balance_if_debit_account),
+                    (AccountType::Credit, balance_if_credit_account),
+                ],
+            };
+            let matched = candidates
+                .iter()
+                .find(|(_, expected)| posting.balance == *expected)
+                .or_else(|| {
+                    candidates.iter().find(|(_, expected)| {
+                        is_within_tolerance(posting.balance, *expected, rounding_tolerance)
+                    })
+                });
+            let Some((posting_account_type, expected)) = matched else {
+                let expected = candidates[0].1;
+                return Err(posting_mismatch(BalanceMismatchKind::Posting, expected));
+            };
+            match (&account_type, posting_account_type) {
+                (None, _) => account_type = Some(*posting_account_type),
+                (Some(AccountType::Debit), AccountType::Debit)
+                | (Some(AccountType::Credit), AccountType::Credit) => {}
+                (Some(AccountType::Credit), AccountType::Debit) => {
+                    return Err(posting_mismatch(
+                        BalanceMismatchKind::DebitPostingInCreditAccount,
+                        balance_if_credit_account,
+                    ))
                 }
-                balance = posting.balance;
-            } else if posting.balance == balance - posting.debit + posting.credit {
-                match account_type {
-                    None => account_type = Some(AccountType::Credit),
-                    Some(AccountType::Debit) => return Err("Credit account balance mismatch"),
-                    Some(AccountType::Credit) => {}
+                (Some(AccountType::Debit), AccountType::Credit) => {
+                    return Err(posting_mismatch(
+                        BalanceMismatchKind::CreditPostingInDebitAccount,
+                        balance_if_debit_account,
+                    ))
                 }
-                balance = posting.balance;
-            } else {
-                return Err("Posting balance mismatch");
             }
+            if posting.balance != *expected {
+                let difference = posting.balance - *expected;
+                rounding_adjustments.push(RoundingAdjustment {
+                    date: posting.date,
+                    amount: match posting_account_type {
+                        AccountType::Debit => difference,
+                        AccountType::Credit => -difference,
+                    },
+                });
+            }
+            balance = posting.balance;
             total_debit += posting.debit;
             total_credit += posting.credit;
         }
-        if total_debit != self.ending_balance.total_debit {
-            return Err("Total debit mismatch");
+        if !is_within_tolerance(
+            total_debit,
+            self.ending_balance.total_debit,
+            rounding_tolerance,
+        ) {
+            return Err(mismatch(
+                BalanceMismatchKind::TotalDebit,
+                None,
+                None,
+                total_debit,
+                self.ending_balance.total_debit,
+            ));
         }
-        if total_credit != self.ending_balance.total_credit {
-            return Err("Total credit mismatch");
+        if !is_within_tolerance(
+            total_credit,
+            self.ending_balance.total_credit,
+            rounding_tolerance,
+        ) {
+            return Err(mismatch(
+                BalanceMismatchKind::TotalCredit,
+                None,
+                None,
+                total_credit,
+                self.ending_balance.total_credit,
+            ));
         }
         if balance != self.ending_balance.ending_balance {
-            return Err("Ending balance mismatch");
+            return Err(mismatch(
+                BalanceMismatchKind::EndingBalance,
+                None,
+                None,
+                balance,
+                self.ending_balance.ending_balance,
+            ));
         }
-        if self.starting_balance + self.balance_change != self.ending_balance.ending_balance {
-            return Err("Balance change mismatch");
+        if !is_within_tolerance(
+            self.starting_balance + self.balance_change,
+            self.ending_balance.ending_balance,
+            rounding_tolerance,
+        ) {
+            return Err(mismatch(
+                BalanceMismatchKind::BalanceChange,
+                None,
+                None,
+                self.ending_balance.ending_balance - self.starting_balance,
+                self.balance_change,
+            ));
         }
-        return Ok(account_type);
-    }
-
-    pub fn account_type(&self) -> Option<AccountType> {
-        // Unwrap is ok because this should already have been validated
-        self.validate().unwrap()
+        Ok(Validation {
+            account_type,
+            rounding_adjustments,
+        })
     }
 }
 
@@ -111,22 +286,59 @@ pub struct EndingBalance {
     pub ending_balance: Amount,
 }
 
+/// A row between the starting balance and the totals of an account
+enum PostingRow {
+    Posting(Posting, Range<usize>),
+    /// Only in lenient mode, the span of a row that failed to parse
+    Skipped(Range<usize>),
+    /// Only in strict mode, a row that failed to parse. Its error is reported and parsing continues with the next
+    /// row, so that all broken rows are reported at once.
+    Recovered,
+}
+
+/// The account and the spans of the rows that were skipped because they failed to parse. Rows are only skipped
+/// with `lenient`, a skipped posting usually still makes the account fail with a balance mismatch.
 pub fn account(
     column_schema: ColumnSchema,
-) -> impl chumsky::Parser<char, Account, Error = Simple<char>> {
+    rounding_tolerance: Decimal,
+    lenient: bool,
+) -> impl chumsky::Parser<char, (Account, Vec<Range<usize>>), Error = Simple<char>> {
+    // Keep the spans of the rows so that balance mismatches can be reported on the offending row
     account_header_row(column_schema)
         .then(
             starting_balance_row(column_schema).then_with(move |starting_balance| {
-                posting_row(column_schema, starting_balance.account_currency.clone())
+                let posting_row =
+                    posting_row(column_schema, starting_balance.account_currency.clone())
+                        .map_with_span(PostingRow::Posting);
+                let posting_row = if lenient {
+                    posting_row
+                        .or(skipped_row(true).map(PostingRow::Skipped))
+                        .boxed()
+                } else {
+                    // Only recover in rows that could be skipped, so that the totals row still ends the postings
+                    skipped_row(true)
+                        .rewind()
+                        .ignore_then(posting_row.recover_with(
+                            skip_until(['\n'], |_| PostingRow::Recovered).consume_end(),
+                        ))
+                        .boxed()
+                };
+                posting_row
                     .repeated()
-                    .then(ending_balance_row(
-                        column_schema,
-                        starting_balance.account_currency.clone(),
-                    ))
-                    .then(balance_change_row(
-                        column_schema,
-                        starting_balance.account_currency.clone(),
-                    ))
+                    .then(
+                        ending_balance_row(
+                            column_schema,
+                            starting_balance.account_currency.clone(),
+                        )
+                        .map_with_span(|ending_balance, span| (ending_balance, span)),
+                    )
+                    .then(
+                        balance_change_row(
+                            column_schema,
+                            starting_balance.account_currency.clone(),
+                        )
+                        .map_with_span(|balance_change, span| (balance_change, span)),
+                    )
                     .map(move |((postings, ending_balance), balance_change)| {
                         (
                             starting_balance.clone(),
@@ -138,8 +350,31 @@ pub fn account(
             }),
         )
         .try_map(
-            |(name, (starting_balance, postings, ending_balance, balance_change)), span| {
+            move |(
+                name,
+                (
+                    starting_balance,
+                    postings,
+                    (ending_balance, ending_balance_span),
+                    (balance_change, balance_change_span),
+                ),
+            ),
+                  span| {
                 let account_currency = starting_balance.account_currency;
+                let mut postings_with_spans = vec![];
+                let mut skipped_rows = vec![];
+                let mut has_recovered_rows = false;
+                for row in postings {
+                    match row {
+                        PostingRow::Posting(posting, span) => {
+                            postings_with_spans.push((posting, span))
+                        }
+                        PostingRow::Skipped(span) => skipped_rows.push(span),
+                        PostingRow::Recovered => has_recovered_rows = true,
+                    }
+                }
+                let (postings, posting_spans): (Vec<_>, Vec<_>) =
+                    postings_with_spans.into_iter().unzip();
                 let account = Account {
                     name,
                     account_currency,
@@ -148,10 +383,22 @@ pub fn account(
                     ending_balance,
                     balance_change,
                 };
-                account
-                    .validate()
-                    .map_err(|err| Simple::custom(span, err))?;
-                Ok(account)
+                if has_recovered_rows {
+                    // The errors of the recovered rows already fail the parse, and the balances can't add up
+                    // without their postings
+                    return Ok((account, skipped_rows));
+                }
+                account.validate(rounding_tolerance).map_err(|err| {
+                    let row_span = match (err.posting_index, err.kind) {
+                        (Some(posting_index), _) => posting_spans[posting_index].clone(),
+                        (None, BalanceMismatchKind::BalanceChange) => balance_change_span,
+                        (None, _) => ending_balance_span,
+                    };
+                    // Fall back to the whole account if chumsky reported an empty span for the row
+                    let row_span = if row_span.is_empty() { span } else { row_span };
+                    Simple::custom(row_span, err.to_string())
+                })?;
+                Ok((account, skipped_rows))
             },
         )
         .labelled("account")
@@ -194,7 +441,7 @@ fn starting_balance_row(
         ColumnSchema::GlobalLedgerCurrency => amount_in_ledger_currency
             .then_ignore(row_end())
             .try_map(|amount, span| {
-                if amount.currency_symbol != LEDGER_CURRENCY_SYMBOL {
+                if !amount.is_in_currency(LEDGER_CURRENCY_SYMBOL) {
                     return Err(Simple::custom(
                         span,
                         format!("Ledger currency symbol is not {LEDGER_CURRENCY}"),
@@ -230,7 +477,7 @@ fn starting_balance_row(
                             format!("Ledger currency is not {LEDGER_CURRENCY}"),
                         ));
                     }
-                    if amount_in_ledger_currency.currency_symbol != LEDGER_CURRENCY_SYMBOL {
+                    if !amount_in_ledger_currency.is_in_currency(LEDGER_CURRENCY_SYMBOL) {
                         return Err(Simple::custom(
                             span,
                             format!("Ledger currency symbol is not {LEDGER_CURRENCY}"),
@@ -240,8 +487,7 @@ fn starting_balance_row(
                         .map_err(|err| {
                             Simple::custom(span.clone(), format!("Invalid account currency: {err}"))
                         })?;
-                    if amount_in_account_currency.currency_symbol
-                        != expected_account_currency_symbol
+                    if !amount_in_account_currency.is_in_currency(expected_account_currency_symbol)
                     {
                         return Err(Simple::custom(
                             span,
@@ -291,7 +537,7 @@ fn posting_row(
         .try_map(|((((date, description), debit), credit), balance), span| {
             let debit = match debit {
                 Some(debit) => {
-                    if debit.currency_symbol != LEDGER_CURRENCY_SYMBOL {
+                    if !debit.is_in_currency(LEDGER_CURRENCY_SYMBOL) {
                         return Err(Simple::custom(
                             span,
                             format!("Debit currency symbol is not {LEDGER_CURRENCY}"),
@@ -303,7 +549,7 @@ fn posting_row(
             };
             let credit = match credit {
                 Some(credit) => {
-                    if credit.currency_symbol != LEDGER_CURRENCY_SYMBOL {
+                    if !credit.is_in_currency(LEDGER_CURRENCY_SYMBOL) {
                         return Err(Simple::custom(
                             span,
                             format!("Credit currency symbol is not {LEDGER_CURRENCY}"),
@@ -313,7 +559,7 @@ fn posting_row(
                 }
                 None => Decimal::zero(),
             };
-            if balance.currency_symbol != LEDGER_CURRENCY_SYMBOL {
+            if !balance.is_in_currency(LEDGER_CURRENCY_SYMBOL) {
                 return Err(Simple::custom(
                     span,
                     format!("Balance currency symbol is not {LEDGER_CURRENCY}"),
@@ -383,7 +629,7 @@ fn posting_row(
                         })?;
                     let debit_in_account_currency = match debit_in_account_currency {
                         Some(debit) => {
-                            if debit.currency_symbol != expected_account_currency_symbol {
+                            if !debit.is_in_currency(expected_account_currency_symbol) {
                                 return Err(Simple::custom(
                                     span,
                                     format!(
@@ -398,7 +644,7 @@ fn posting_row(
                     };
                     let credit_in_account_currency = match credit_in_account_currency {
                         Some(credit) => {
-                            if credit.currency_symbol != expected_account_currency_symbol {
+                            if !credit.is_in_currency(expected_account_currency_symbol) {
                                 return Err(Simple::custom(
                                     span,
                                     format!(
@@ -411,7 +657,7 @@ fn posting_row(
                         }
                         None => None,
                     };
-                    if balance_in_account_currency.currency_symbol != expected_account_currency_symbol {
+                    if !balance_in_account_currency.is_in_currency(expected_account_currency_symbol) {
                         return Err(Simple::custom(
                             span,
                             format!(
@@ -501,19 +747,19 @@ fn ending_balance_row(
         .then_ignore(comma())
         .then(amount_cell())
         .try_map(|((total_debit, total_credit), ending_balance), span| {
-            if total_debit.currency_symbol != LEDGER_CURRENCY_SYMBOL {
+            if !total_debit.is_in_currency(LEDGER_CURRENCY_SYMBOL) {
                 return Err(Simple::custom(
                     span,
                     format!("Total debit currency symbol is not {LEDGER_CURRENCY}"),
                 ));
             }
-            if total_credit.currency_symbol != LEDGER_CURRENCY_SYMBOL {
+            if !total_credit.is_in_currency(LEDGER_CURRENCY_SYMBOL) {
                 return Err(Simple::custom(
                     span,
                     format!("Total credit currency symbol is not {LEDGER_CURRENCY}"),
                 ));
             }
-            if ending_balance.currency_symbol != LEDGER_CURRENCY_SYMBOL {
+            if !ending_balance.is_in_currency(LEDGER_CURRENCY_SYMBOL) {
                 return Err(Simple::custom(
                     span,
                     format!("Ending balance currency symbol is not {LEDGER_CURRENCY}"),
@@ -578,21 +824,21 @@ fn ending_balance_row(
                         .map_err(|err| {
                             Simple::custom(span.clone(), format!("Invalid account currency: {err}"))
                         })?;
-                    if total_debit_in_account_currency.currency_symbol != expected_account_currency {
+                    if !total_debit_in_account_currency.is_in_currency(expected_account_currency) {
                         return Err(Simple::custom(
                             span,
                             format!("Expected total debit currency symbol '{expected_account_currency}' but got '{}'",
                             total_debit_in_account_currency.currency_symbol),
                         ));
                     }
-                    if total_credit_in_account_currency.currency_symbol != expected_account_currency {
+                    if !total_credit_in_account_currency.is_in_currency(expected_account_currency) {
                         return Err(Simple::custom(
                             span,
                             format!("Expected total credit currency symbol '{expected_account_currency}' but got '{}'",
                             total_credit_in_account_currency.currency_symbol),
                         ));
                     }
-                    if ending_balance_in_account_currency.currency_symbol != expected_account_currency {
+                    if !ending_balance_in_account_currency.is_in_currency(expected_account_currency) {
                         return Err(Simple::custom(
                             span,
                             format!("Expected ending balance currency symbol '{expected_account_currency}' but got '{}'",
@@ -653,7 +899,7 @@ fn balance_change_row(
         .then_ignore(comma())
         .then_ignore(empty_cell())
         .try_map(|amount, span| {
-            if amount.currency_symbol != LEDGER_CURRENCY_SYMBOL {
+            if !amount.is_in_currency(LEDGER_CURRENCY_SYMBOL) {
                 return Err(Simple::custom(span, "Currency symbol is not $"));
             }
             Ok(Amount {
@@ -699,7 +945,7 @@ fn balance_change_row(
                         .map_err(|err| {
                             Simple::custom(span.clone(), format!("Invalid account currency: {err}"))
                         })?;
-                    if balance_change_in_account_currency.currency_symbol != expected_account_currency_symbol {
+                    if !balance_change_in_account_currency.is_in_currency(expected_account_currency_symbol) {
                         return Err(Simple::custom(
                             span,
                             format!("Expected balance change currency symbol '{expected_account_currency_symbol}' but got '{}'",
@@ -1093,7 +1339,8 @@ Totals and Ending Balance,,,$0.00,$0.00,"$12.34"
 Balance Change,,,"$0.0",,"#;
         test_parser(
             input,
-            account(ColumnSchema::GlobalLedgerCurrency),
+            account(ColumnSchema::GlobalLedgerCurrency, Decimal::ZERO, false)
+                .map(|(account, _)| account),
             Account {
                 name: "My Bank Account".to_string(),
                 account_currency: LEDGER_CURRENCY.to_string(),
@@ -1133,7 +1380,8 @@ Totals and Ending Balance,,,"$0.00","$0.00","$12.34",USD,,"$0.00","$0.00","$12.3
 Balance Change,,,"$0.00",,,USD,,"$0.00",,,USD"#;
         test_parser(
             input,
-            account(ColumnSchema::PerAccountCurrency),
+            account(ColumnSchema::PerAccountCurrency, Decimal::ZERO, false)
+                .map(|(account, _)| account),
             Account {
                 name: "My Bank Account".to_string(),
                 account_currency: "USD".to_string(),
@@ -1173,7 +1421,8 @@ Totals and Ending Balance,,,"$0.00","$0.00","$12.34",USD,,"€0.00","€0.00","
 Balance Change,,,"$0.00",,,USD,,"€0.00",,,EUR"#;
         test_parser(
             input,
-            account(ColumnSchema::PerAccountCurrency),
+            account(ColumnSchema::PerAccountCurrency, Decimal::ZERO, false)
+                .map(|(account, _)| account),
             Account {
                 name: "My Bank Account".to_string(),
                 account_currency: "EUR".to_string(),
@@ -1215,7 +1464,8 @@ Totals and Ending Balance,,,$1.23,$15.67,$109.01
 Balance Change,,,-$14.44,,"#;
         test_parser(
             input,
-            account(ColumnSchema::GlobalLedgerCurrency),
+            account(ColumnSchema::GlobalLedgerCurrency, Decimal::ZERO, false)
+                .map(|(account, _)| account),
             Account {
                 name: "Some Account".to_string(),
                 account_currency: LEDGER_CURRENCY.to_string(),
@@ -1290,7 +1540,8 @@ Totals and Ending Balance,,,$1.23,$15.67,$109.01,USD,,$1.23,$15.67,$109.01,USD
 Balance Change,,,-$14.44,,,USD,,-$14.44,,,USD"#;
         test_parser(
             input,
-            account(ColumnSchema::PerAccountCurrency),
+            account(ColumnSchema::PerAccountCurrency, Decimal::ZERO, false)
+                .map(|(account, _)| account),
             Account {
                 name: "Some Account".to_string(),
                 account_currency: "USD".to_string(),
@@ -1365,7 +1616,8 @@ Totals and Ending Balance,,,$1.23,$15.67,$109.01,USD,,€2.23,€25.67,€200.01
 Balance Change,,,-$14.44,,,USD,,-€23.44,,,EUR"#;
         test_parser(
             input,
-            account(ColumnSchema::PerAccountCurrency),
+            account(ColumnSchema::PerAccountCurrency, Decimal::ZERO, false)
+                .map(|(account, _)| account),
             Account {
                 name: "Some Account".to_string(),
                 account_currency: "EUR".to_string(),
@@ -1440,7 +1692,8 @@ Totals and Ending Balance,,,$15.67,$1.23,$137.89
 Balance Change,,,$14.44,,"#;
         test_parser(
             input,
-            account(ColumnSchema::GlobalLedgerCurrency),
+            account(ColumnSchema::GlobalLedgerCurrency, Decimal::ZERO, false)
+                .map(|(account, _)| account),
             Account {
                 name: "Some Account".to_string(),
                 account_currency: LEDGER_CURRENCY.to_string(),
@@ -1515,7 +1768,8 @@ Totals and Ending Balance,,,$15.67,$1.23,$137.89,USD,,$15.67,$1.23,$137.89,USD
 Balance Change,,,$14.44,,,USD,,$14.44,,,USD"#;
         test_parser(
             input,
-            account(ColumnSchema::PerAccountCurrency),
+            account(ColumnSchema::PerAccountCurrency, Decimal::ZERO, false)
+                .map(|(account, _)| account),
             Account {
                 name: "Some Account".to_string(),
                 account_currency: "USD".to_string(),
@@ -1590,7 +1844,8 @@ Totals and Ending Balance,,,$15.67,$1.23,$137.89,USD,,€25.67,€2.23,€246.89
 Balance Change,,,$14.44,,,USD,,€23.44,,,EUR"#;
         test_parser(
             input,
-            account(ColumnSchema::PerAccountCurrency),
+            account(ColumnSchema::PerAccountCurrency, Decimal::ZERO, false)
+                .map(|(account, _)| account),
             Account {
                 name: "Some Account".to_string(),
                 account_currency: "EUR".to_string(),
@@ -1654,4 +1909,84 @@ Balance Change,,,$14.44,,,USD,,€23.44,,,EUR"#;
             "",
         )
     }
+
+    #[test]
+    fn given_global_schema_test_account_posting_balance_mismatch() {
+        let input = r#",Some Account,,,,
+Starting Balance,,,,,$123.45
+,2024-01-04,Some: Addition,$1.23,,$124.68
+,2024-04-04,Some: Withdrawal,,$15.67,$109.02
+Totals and Ending Balance,,,$1.23,$15.67,$109.02
+Balance Change,,,-$14.43,,"#;
+        let errors = account(ColumnSchema::GlobalLedgerCurrency, Decimal::ZERO, false)
+            .then_ignore(chumsky::prelude::end())
+            .parse(input)
+            .unwrap_err();
+        assert_eq!(1, errors.len());
+        let posting_row = ",2024-04-04,Some: Withdrawal,,$15.67,$109.02";
+        let row_start = input.find(posting_row).unwrap();
+        assert_eq!(row_start, errors[0].span().start);
+        assert_eq!(
+            &chumsky::error::SimpleReason::Custom(
+                "Posting balance mismatch in account 'Some Account' on 2024-04-04: expected 109.01 but the CSV says 109.02"
+                    .to_string()
+            ),
+            errors[0].reason()
+        );
+    }
+
+    #[test]
+    fn given_global_schema_test_account_ending_balance_mismatch() {
+        let input = r#",Some Account,,,,
+Starting Balance,,,,,$123.45
+,2024-01-04,Some: Addition,$1.23,,$124.68
+Totals and Ending Balance,,,$1.23,$0.00,$124.69
+Balance Change,,,$1.23,,"#;
+        let errors = account(ColumnSchema::GlobalLedgerCurrency, Decimal::ZERO, false)
+            .then_ignore(chumsky::prelude::end())
+            .parse(input)
+            .unwrap_err();
+        assert_eq!(1, errors.len());
+        let row_start = input.find("Totals and Ending Balance").unwrap();
+        assert_eq!(row_start, errors[0].span().start);
+        assert_eq!(
+            &chumsky::error::SimpleReason::Custom(
+                "Ending balance mismatch in account 'Some Account': expected 124.68 but the CSV says 124.69"
+                    .to_string()
+            ),
+            errors[0].reason()
+        );
+    }
+
+    #[test]
+    fn given_global_schema_test_account_balance_mismatch_within_rounding_tolerance() {
+        let input = r#",Some Account,,,,
+Starting Balance,,,,,$123.45
+,2024-01-04,Some: Addition,$1.23,,$124.68
+,2024-04-04,Some: Withdrawal,,$15.67,$109.02
+Totals and Ending Balance,,,$1.23,$15.67,$109.02
+Balance Change,,,-$14.43,,"#;
+        let (account, _) = account(
+            ColumnSchema::GlobalLedgerCurrency,
+            Decimal::new(1, 2),
+            false,
+        )
+        .then_ignore(chumsky::prelude::end())
+        .parse(input)
+        .unwrap();
+        assert_eq!(
+            Validation {
+                account_type: Some(AccountType::Debit),
+                rounding_adjustments: vec![RoundingAdjustment {
+                    date: NaiveDate::from_ymd_opt(2024, 4, 4).unwrap(),
+                    amount: Amount {
+                        in_ledger_currency: Decimal::new(1, 2),
+                        in_account_currency: Decimal::new(1, 2),
+                    },
+                }],
+            },
+            account.validate(Decimal::new(1, 2)).unwrap()
+        );
+        assert!(account.validate(Decimal::ZERO).is_err());
+    }
 }
@@ -0,0 +1,38 @@
+//! Entry points for the fuzz targets in `fuzz/`. They only check that the parsers don't panic or hang, whatever
+//! the input is.
+
+use chumsky::{prelude::end, Parser as _};
+use rust_decimal::Decimal;
+
+use super::utils::{amount_cell, any_cell, date_cell, parse_amount};
+use crate::import::{render_parser_errors, render_skipped_row};
+
+pub fn cell(input: &str) {
+    let _ = any_cell().then_ignore(end()).parse(input);
+}
+
+pub fn amount(input: &str) {
+    let _ = amount_cell().then_ignore(end()).parse(input);
+    let _ = parse_amount(input);
+}
+
+pub fn date(input: &str) {
+    let _ = date_cell().then_ignore(end()).parse(input);
+}
+
+/// Parses the ledger strictly and leniently and renders the errors and skipped rows like the import does
+pub fn ledger(input: &str) {
+    if let Err(errors) = super::ledger(Decimal::ZERO, false, || {}).parse(input) {
+        render_parser_errors(input, errors, false);
+    }
+    match super::ledger(Decimal::ZERO, true, || {}).parse(input) {
+        Ok(ledger) => {
+            for span in ledger.skipped_rows {
+                render_skipped_row(input, span, false);
+            }
+        }
+        Err(errors) => {
+            render_parser_errors(input, errors, false);
+        }
+    }
+}
@@ -1,13 +1,20 @@
 use chrono::NaiveDate;
 use chumsky::{error::Simple, prelude::end, Parser as _};
+use rust_decimal::Decimal;
+use std::ops::Range;
 
 mod utils;
-use utils::{empty_cell, row_end};
+use utils::{empty_cell, line_any_content, row_end};
 
 mod account;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
 mod header;
 
 pub use account::AccountType;
+#[cfg(test)]
+pub use account::{Account, EndingBalance};
+pub use utils::parse_amount;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct WaveLedger {
@@ -15,19 +22,57 @@ pub struct WaveLedger {
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
     pub accounts: Vec<account::Account>,
+    /// Spans of the rows that failed to parse and were left out, only in lenient mode
+    pub skipped_rows: Vec<Range<usize>>,
 }
 
-pub fn ledger() -> impl chumsky::Parser<char, WaveLedger, Error = Simple<char>> {
-    header::header().then_with(|header| {
-        account::account(header.column_schema)
-            .separated_by(row_with_empty_cell())
-            .then_ignore(row_with_empty_cell().or_not())
+/// `on_account_parsed` is called after each account was parsed and validated, e.g. to advance a progress bar.
+/// With `lenient`, rows that fail to parse are skipped instead of failing the whole ledger, see [skipped_row].
+pub fn ledger(
+    rounding_tolerance: Decimal,
+    lenient: bool,
+    on_account_parsed: impl Fn() + Clone + 'static,
+) -> impl chumsky::Parser<char, WaveLedger, Error = Simple<char>> {
+    header::header().then_with(move |header| {
+        let on_account_parsed = on_account_parsed.clone();
+        let account = account::account(header.column_schema, rounding_tolerance, lenient).map(
+            move |account| {
+                on_account_parsed();
+                account
+            },
+        );
+        let accounts = if lenient {
+            account
+                .map(|(account, skipped_rows)| (Some(account), skipped_rows))
+                .or(skipped_row(false).map(|span| (None, vec![span])))
+                .then_ignore(row_with_empty_cell().or_not())
+                .repeated()
+                .map(|items| {
+                    let mut accounts = vec![];
+                    let mut skipped_rows = vec![];
+                    for (account, account_skipped_rows) in items {
+                        accounts.extend(account);
+                        skipped_rows.extend(account_skipped_rows);
+                    }
+                    (accounts, skipped_rows)
+                })
+                .boxed()
+        } else {
+            account
+                .map(|(account, _)| account)
+                .separated_by(row_with_empty_cell())
+                .then_ignore(row_with_empty_cell().or_not())
+                .map(|accounts| (accounts, vec![]))
+                .boxed()
+        };
+        accounts
             .then_ignore(end())
-            .map(move |accounts| WaveLedger {
+            .map(move |(accounts, skipped_rows)| WaveLedger {
                 ledger_name: header.ledger_name.to_string(),
                 start_date: header.start_date,
                 end_date: header.end_date,
                 accounts,
+                skipped_rows,
             })
     })
 }
@@ -38,10 +83,37 @@ fn row_with_empty_cell() -> impl chumsky::Parser<char, (), Error = Simple<char>>
         .labelled("row with empty cell")
 }
 
+/// Rows that make up an account. They are never skipped so that an account with a broken structure still fails.
+const ACCOUNT_STRUCTURE_TAGS: &[&str] = &[
+    "Starting Balance",
+    "Totals and Ending Balance",
+    "Balance Change",
+];
+
+/// A non-empty row that is skipped in lenient mode, returns the span of its content. Rows of [ACCOUNT_STRUCTURE_TAGS]
+/// aren't skipped. Outside of accounts (`in_account` false), rows starting with an empty cell aren't skipped either,
+/// since they are the headers of the next account.
+fn skipped_row(in_account: bool) -> impl chumsky::Parser<char, Range<usize>, Error = Simple<char>> {
+    line_any_content()
+        .try_map(move |line, span: Range<usize>| {
+            let first_cell = line.trim_start_matches('"');
+            let is_structure = ACCOUNT_STRUCTURE_TAGS
+                .iter()
+                .any(|tag| first_cell.starts_with(tag))
+                || (!in_account && line.starts_with(','));
+            if line.is_empty() || is_structure {
+                return Err(Simple::custom(span, "Row can't be skipped"));
+            }
+            Ok(span.start..span.start + line.chars().count())
+        })
+        .labelled("skipped row")
+}
+
 #[cfg(test)]
 mod tests {
     use chumsky::Error;
     use rust_decimal::{prelude::Zero, Decimal};
+    use std::collections::BTreeSet;
     use utils::test_parser;
 
     use super::*;
@@ -74,7 +146,7 @@ Totals and Ending Balance,,,$15.67,$1.23,$137.89
 Balance Change,,,$14.44,,"#;
         test_parser(
             input,
-            ledger(),
+            ledger(Decimal::ZERO, false, || {}),
             WaveLedger {
                 ledger_name: "Personal".to_string(),
                 start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
@@ -201,11 +273,45 @@ Balance Change,,,$14.44,,"#;
                         },
                     },
                 ],
+                skipped_rows: vec![],
             },
             "",
         );
     }
 
+    #[test]
+    fn test_ledger_without_currency_symbols() {
+        let input = r#"Account Transactions
+Personal
+Date Range: 2024-01-01 to 2024-11-30
+Report Type: Accrual (Paid & Unpaid)
+ACCOUNT NUMBER,DATE,DESCRIPTION,DEBIT (In Business Currency),CREDIT (In Business Currency),BALANCE (In Business Currency)
+,First Account,,,,
+Starting Balance,,,,,"1,234.56"
+,2024-01-04,Some: Addition,1.23,,"1,235.79"
+,2024-04-04,Some: Withdrawal,,"1,250.00","(14.21)"
+Totals and Ending Balance,,,1.23,"$1,250.00",14.21-
+Balance Change,,,"(1,248.77)",,"#;
+        let wave_ledger = ledger(Decimal::ZERO, false, || {}).parse(input).unwrap();
+        let account = &wave_ledger.accounts[0];
+        assert_eq!(
+            Decimal::new(123456, 2),
+            account.starting_balance.in_ledger_currency
+        );
+        assert_eq!(
+            Decimal::new(-1421, 2),
+            account.postings[1].balance.in_ledger_currency
+        );
+        assert_eq!(
+            Decimal::new(-1421, 2),
+            account.ending_balance.ending_balance.in_ledger_currency
+        );
+        assert_eq!(
+            Decimal::new(-124877, 2),
+            account.balance_change.in_ledger_currency
+        );
+    }
+
     #[test]
     fn test_ledger_with_extra_data() {
         let input = r#"Account Transactions
@@ -229,7 +335,7 @@ Balance Change,,,$14.44,,
 ""
 bla"#;
         assert_eq!(
-            ledger().parse(input),
+            ledger(Decimal::ZERO, false, || {}).parse(input),
             Err(vec![
                 Simple::expected_input_found(654..655, [None], Some('b')).with_label("csv cell"),
                 Simple::custom(654..657, "Failed to parse cell content").with_label("csv cell")
@@ -237,6 +343,70 @@ bla"#;
         );
     }
 
+    #[test]
+    fn test_lenient_ledger_skips_malformed_rows() {
+        let input = r#"Account Transactions
+Personal
+Date Range: 2024-01-01 to 2024-11-30
+Report Type: Accrual (Paid & Unpaid)
+ACCOUNT NUMBER,DATE,DESCRIPTION,DEBIT (In Business Currency),CREDIT (In Business Currency),BALANCE (In Business Currency)
+,First Account,,,,
+Starting Balance,,,,,$123.45
+,2024-01-04,Some: Addition,$1.23,,$124.68
+,not a date,Stray row,,,
+,2024-04-04,Some: Withdrawal,,$15.67,$109.01
+Totals and Ending Balance,,,$1.23,$15.67,$109.01
+Balance Change,,,-$14.44,,
+""
+bla"#;
+        let wave_ledger = ledger(Decimal::ZERO, true, || {}).parse(input).unwrap();
+        assert_eq!(1, wave_ledger.accounts.len());
+        assert_eq!(2, wave_ledger.accounts[0].postings.len());
+        let skipped_rows: Vec<String> = wave_ledger
+            .skipped_rows
+            .into_iter()
+            .map(|span| input.chars().skip(span.start).take(span.len()).collect())
+            .collect();
+        assert_eq!(vec![",not a date,Stray row,,,", "bla"], skipped_rows);
+    }
+
+    #[test]
+    fn test_lenient_ledger_still_fails_on_balance_mismatch() {
+        let input = r#"Account Transactions
+Personal
+Date Range: 2024-01-01 to 2024-11-30
+Report Type: Accrual (Paid & Unpaid)
+ACCOUNT NUMBER,DATE,DESCRIPTION,DEBIT (In Business Currency),CREDIT (In Business Currency),BALANCE (In Business Currency)
+,First Account,,,,
+Starting Balance,,,,,$123.45
+,2024-01-04,Some: Addition,$1.23,,$124.68
+Totals and Ending Balance,,,$1.23,$0.00,$124.69
+Balance Change,,,$1.24,,"#;
+        assert!(ledger(Decimal::ZERO, true, || {}).parse(input).is_err());
+    }
+
+    #[test]
+    fn test_strict_ledger_reports_all_malformed_rows() {
+        let input = r#"Account Transactions
+Personal
+Date Range: 2024-01-01 to 2024-11-30
+Report Type: Accrual (Paid & Unpaid)
+ACCOUNT NUMBER,DATE,DESCRIPTION,DEBIT (In Business Currency),CREDIT (In Business Currency),BALANCE (In Business Currency)
+,First Account,,,,
+Starting Balance,,,,,$123.45
+,not a date,Some: Addition,$1.23,,$124.68
+,2024-04-04,Some: Withdrawal,,$15.6.7,$109.01
+Totals and Ending Balance,,,$1.23,$15.67,$109.01
+Balance Change,,,-$14.44,,"#;
+        let errors = ledger(Decimal::ZERO, false, || {})
+            .parse(input)
+            .unwrap_err();
+        let row_of = |offset: usize| input.chars().take(offset).filter(|c| *c == '\n').count();
+        // Both rows are reported, without a balance mismatch because of the missing postings
+        let rows: BTreeSet<usize> = errors.iter().map(|err| row_of(err.span().start)).collect();
+        assert_eq!(BTreeSet::from([7, 8]), rows, "{errors:?}");
+    }
+
     #[test]
     fn test_row_with_empty_cell() {
         test_parser("\n", row_with_empty_cell(), (), "");
@@ -1,47 +1,242 @@
 use chumsky::{
     error::Simple,
-    prelude::{just, one_of},
+    prelude::{choice, just, one_of},
     Parser as _,
 };
 use rust_decimal::Decimal;
+use std::ops::Range;
 
-use super::csv::cell;
+use super::csv::cell_with_fast_path;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Amount {
     pub amount: Decimal,
+    /// Empty if the amount didn't have a currency symbol
     pub currency_symbol: String,
 }
 
+impl Amount {
+    /// Whether the amount is in the currency with this symbol. An amount without a currency symbol is in the currency
+    /// its column expects, e.g. `(1,234.56)` in a column of ledger currency amounts.
+    pub fn is_in_currency(&self, currency_symbol: &str) -> bool {
+        self.currency_symbol.is_empty() || self.currency_symbol == currency_symbol
+    }
+}
+
+const CURRENCY_SYMBOLS: [&str; 4] = ["$", "€", "£", "CHF"];
+
+/// ISO codes of the currencies in [CURRENCY_SYMBOLS] with their symbol. Amounts can be written with either, e.g.
+/// `$123.45`, `USD 123.45` or `123.45 USD`, and are parsed with the symbol.
+const CURRENCY_CODES: [(&str, &str); 4] =
+    [("USD", "$"), ("EUR", "€"), ("GBP", "£"), ("CHF", "CHF")];
+
+/// What can separate an amount from a currency symbol or code, e.g. in `123.45 USD`
+const CURRENCY_SPACES: [char; 2] = [' ', '\u{a0}'];
+
+/// `,` like in `1,234.56`, `'` like in `1'234.56`, and space or no-break space like in `1 234,56`, see [parse_number]
+const THOUSANDS_SEPARATORS: [char; 4] = [',', '\'', ' ', '\u{a0}'];
+
+/// Amounts must be below 10^18, so summing up the postings of an account can't overflow [Decimal]
+const MAX_AMOUNT: Decimal = Decimal::from_parts(0xA764_0000, 0x0DE0_B6B3, 0, false, 0);
+
+/// The currency symbol for an ISO currency code, see [CURRENCY_CODES]
+pub fn currency_symbol(currency_code: &str) -> Option<&'static str> {
+    CURRENCY_CODES
+        .iter()
+        .find(|(code, _)| *code == currency_code)
+        .map(|(_, symbol)| *symbol)
+}
+
+/// Currency symbols and codes, each with the symbol it stands for
+fn currencies() -> impl Iterator<Item = (&'static str, &'static str)> {
+    CURRENCY_SYMBOLS
+        .iter()
+        .map(|symbol| (*symbol, *symbol))
+        .chain(CURRENCY_CODES)
+}
+
 pub fn amount_cell() -> impl chumsky::Parser<char, Amount, Error = Simple<char>> {
-    cell(amount()).labelled("amount cell")
+    cell_with_fast_path(parse_amount, amount()).labelled("amount cell")
 }
 
 pub fn amount_cell_opt() -> impl chumsky::Parser<char, Option<Amount>, Error = Simple<char>> {
-    cell(amount().or_not()).labelled("amount cell or empty cell")
+    cell_with_fast_path(
+        |content| {
+            if content.is_empty() {
+                Some(None)
+            } else {
+                parse_amount(content).map(Some)
+            }
+        },
+        amount().or_not(),
+    )
+    .labelled("amount cell or empty cell")
+}
+
+/// Parse an amount like `-$1,234.56`, `$-1,234.56`, `($1,234.56)`, `$1,234.56-`, `(1,234.56)`, `CHF1'234.56`,
+/// `€1 234,56`, `USD -1,234.56` or `1 234,56 EUR`. This is also the fast path for [amount] and accepts exactly the
+/// same inputs.
+pub fn parse_amount(content: &str) -> Option<Amount> {
+    let (mut negative, content) = if let Some(content) = content
+        .strip_prefix('(')
+        .and_then(|content| content.strip_suffix(')'))
+    {
+        (true, content)
+    } else if let Some(content) = content.strip_prefix('-') {
+        (true, content)
+    } else if let Some(content) = content.strip_suffix('-') {
+        (true, content)
+    } else {
+        (false, content)
+    };
+    let (currency_symbol, number) = if let Some((currency_symbol, number)) =
+        currencies().find_map(|(prefix, symbol)| Some((symbol, content.strip_prefix(prefix)?)))
+    {
+        let number = number.strip_prefix(CURRENCY_SPACES).unwrap_or(number);
+        // `$-123.45`, only if there isn't another sign already
+        match number.strip_prefix('-') {
+            Some(number) if !negative => {
+                negative = true;
+                (currency_symbol, number)
+            }
+            _ => (currency_symbol, number),
+        }
+    } else if let Some((currency_symbol, number)) =
+        currencies().find_map(|(suffix, symbol)| Some((symbol, content.strip_suffix(suffix)?)))
+    {
+        (
+            currency_symbol,
+            number.strip_suffix(CURRENCY_SPACES).unwrap_or(number),
+        )
+    } else {
+        ("", content)
+    };
+    let amount = parse_number(number).ok()?;
+    Some(Amount {
+        amount: if negative { -amount } else { amount },
+        currency_symbol: currency_symbol.to_string(),
+    })
+}
+
+/// Parse the number of an amount without sign and currency symbol. The decimal mark is `,` if it's followed by one or
+/// two digits at the end, like in `12,5` or `1 234,56`, and `.` otherwise. Thousands separators can't be followed by
+/// fewer than three digits, so a `,` there can only be a decimal mark. The integer part can use one of the
+/// [THOUSANDS_SEPARATORS] between groups of three digits.
+fn parse_number(number: &str) -> Result<Decimal, &'static str> {
+    const INVALID: &str = "Failed to parse amount";
+    let is_digits = |digits: &str| digits.bytes().all(|c| c.is_ascii_digit());
+    let (decimal_mark, integer, fraction) = match number.rsplit_once(',') {
+        Some((integer, fraction)) if (1..=2).contains(&fraction.len()) && is_digits(fraction) => {
+            (',', integer, fraction)
+        }
+        _ => {
+            let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+            ('.', integer, fraction)
+        }
+    };
+    if !is_digits(fraction) {
+        return Err(INVALID);
+    }
+    let mut digits = String::with_capacity(number.len());
+    match integer.chars().find(|c| !c.is_ascii_digit()) {
+        None => digits.push_str(integer),
+        Some(thousands_separator) => {
+            if thousands_separator == decimal_mark
+                || !THOUSANDS_SEPARATORS.contains(&thousands_separator)
+            {
+                return Err(INVALID);
+            }
+            let mut groups = integer.split(thousands_separator);
+            let first_group = groups.next().unwrap_or_default();
+            if !(1..=3).contains(&first_group.len()) || !is_digits(first_group) {
+                return Err(INVALID);
+            }
+            digits.push_str(first_group);
+            for group in groups {
+                if group.len() != 3 || !is_digits(group) {
+                    return Err(INVALID);
+                }
+                digits.push_str(group);
+            }
+        }
+    }
+    if number.contains(decimal_mark) {
+        digits.push('.');
+        digits.push_str(fraction);
+    }
+    let amount = Decimal::from_str_exact(&digits).map_err(|_| INVALID)?;
+    if amount >= MAX_AMOUNT {
+        return Err("Amount out of range");
+    }
+    Ok(amount)
 }
 
+/// Negative amounts can be written as `-$123.45`, `$-123.45`, `($123.45)` or `$123.45-`, depending on the locale.
+/// Some exports leave out the currency symbol, e.g. `(1,234.56)` or `1,234.56-`, or use a currency code instead, e.g.
+/// `USD -123.45` or `123.45 USD`.
 fn amount() -> impl chumsky::Parser<char, Amount, Error = Simple<char>> {
-    let maybe_negative = just("-").or_not();
-    let currency_symbol = just("$")
-        .or(just("€"))
-        .or(just("£"))
-        .or(just("CHF"))
-        .labelled("currency symbol");
-    let amount = one_of("0123456789.")
-        .then_ignore(just(',').or_not())
+    let currency = choice(
+        currencies()
+            .map(|(currency, symbol)| just(currency).to(symbol))
+            .collect::<Vec<_>>(),
+    )
+    .labelled("currency symbol or code");
+    let currency_prefix = currency
+        .clone()
+        .then_ignore(one_of(CURRENCY_SPACES).or_not());
+    let number = one_of("0123456789.,' \u{a0}")
         .repeated()
         .at_least(1)
-        .try_map(|content, span| {
-            Decimal::from_str_exact(&content.into_iter().collect::<String>())
-                .map_err(|_| Simple::custom(span, "Failed to parse amount"))
+        .collect::<String>();
+    let amount = number
+        .clone()
+        .try_map(|content, span: Range<usize>| {
+            parse_number(&content).map_err(|message| Simple::custom(span, message))
+        })
+        .labelled("number");
+    let minus_after_currency_prefix = currency_prefix
+        .clone()
+        .then_ignore(just('-'))
+        .then(amount.clone())
+        .map(|(currency_symbol, amount)| (currency_symbol, -amount));
+    // The number already took the space before the currency, since it can also be a thousands separator
+    let currency_suffix = number
+        .then(currency)
+        .try_map(|(content, currency_symbol), span: Range<usize>| {
+            let content = content.strip_suffix(CURRENCY_SPACES).unwrap_or(&content);
+            let amount = parse_number(content).map_err(|message| Simple::custom(span, message))?;
+            Ok((currency_symbol, amount))
         })
         .labelled("number");
-    maybe_negative
-        .then(currency_symbol)
-        .then(amount)
-        .map(|((negative, currency_symbol), amount)| Amount {
-            amount: if negative.is_some() { -amount } else { amount },
+    let unsigned_amount = currency_prefix
+        .then(amount.clone())
+        .or(currency_suffix)
+        .or(amount.map(|amount| ("", amount)));
+    let parenthesized_negative = unsigned_amount
+        .clone()
+        .delimited_by(just('('), just(')'))
+        .map(|(currency_symbol, amount)| (currency_symbol, -amount));
+    let leading_minus = just('-')
+        .ignore_then(unsigned_amount.clone())
+        .map(|(currency_symbol, amount)| (currency_symbol, -amount));
+    let maybe_trailing_minus = unsigned_amount.then(just('-').or_not()).map(
+        |((currency_symbol, amount), trailing_minus)| {
+            (
+                currency_symbol,
+                if trailing_minus.is_some() {
+                    -amount
+                } else {
+                    amount
+                },
+            )
+        },
+    );
+    parenthesized_negative
+        .or(leading_minus)
+        .or(minus_after_currency_prefix)
+        .or(maybe_trailing_minus)
+        .map(|(currency_symbol, amount)| Amount {
+            amount,
             currency_symbol: currency_symbol.to_string(),
         })
         .labelled("amount")
@@ -49,6 +244,7 @@ fn amount() -> impl chumsky::Parser<char, Amount, Error = Simple<char>> {
 
 #[cfg(test)]
 mod test {
+    use chumsky::prelude::end;
     use chumsky::Error as _;
     use rstest::rstest;
 
@@ -78,32 +274,58 @@ mod test {
         test_parser(&input, amount_cell_opt(), Some(expected), "");
     }
 
+    #[rstest]
+    fn without_currency_symbol(
+        #[values(
+            ("123.45", Decimal::new(12345, 2)),
+            ("\"1,234.56\"", Decimal::new(123456, 2)),
+            ("-123.45", Decimal::new(-12345, 2)),
+            ("\"(1,234.56)\"", Decimal::new(-123456, 2)),
+            ("\"1,234.56-\"", Decimal::new(-123456, 2))
+        )]
+        (input, expected): (&str, Decimal),
+    ) {
+        let expected = Amount {
+            amount: expected,
+            currency_symbol: String::new(),
+        };
+        test_parser(input, amount_cell(), expected.clone(), "");
+        test_parser(input, amount_cell_opt(), Some(expected), "");
+    }
+
+    #[rstest]
+    fn with_currency_code(
+        #[values(
+            ("USD123.45", "$", Decimal::new(12345, 2)),
+            ("USD 123.45", "$", Decimal::new(12345, 2)),
+            ("123.45 USD", "$", Decimal::new(12345, 2)),
+            ("123.45EUR", "€", Decimal::new(12345, 2)),
+            ("\"1 234,56 EUR\"", "€", Decimal::new(123456, 2)),
+            ("\"GBP 1,234.56\"", "£", Decimal::new(123456, 2)),
+            ("CHF 1'234.56", "CHF", Decimal::new(123456, 2)),
+            ("1'234.56 CHF", "CHF", Decimal::new(123456, 2)),
+            ("123.45 €", "€", Decimal::new(12345, 2)),
+            ("-123.45 USD", "$", Decimal::new(-12345, 2)),
+            ("USD -123.45", "$", Decimal::new(-12345, 2)),
+            ("(123.45 USD)", "$", Decimal::new(-12345, 2)),
+            ("123.45 USD-", "$", Decimal::new(-12345, 2))
+        )]
+        (input, currency_symbol, expected): (&str, &str, Decimal),
+    ) {
+        let expected = Amount {
+            amount: expected,
+            currency_symbol: currency_symbol.to_string(),
+        };
+        test_parser(input, amount_cell(), expected.clone(), "");
+        test_parser(input, amount_cell_opt(), Some(expected), "");
+    }
+
     #[test]
-    fn without_dollar_sign() {
-        assert_eq!(
-            amount_cell().parse("123.45"),
-            Err(vec![
-                Simple::expected_input_found(
-                    0..1,
-                    [Some('£'), Some('$'), Some('C'), Some('-'), Some('€')],
-                    Some('1')
-                )
-                .with_label("currency symbol"),
-                Simple::custom(0..6, "Failed to parse cell content").with_label("csv cell")
-            ])
-        );
-        assert_eq!(
-            amount_cell_opt().parse("123.45"),
-            Err(vec![
-                Simple::expected_input_found(
-                    0..1,
-                    [Some('-'), Some('C'), Some('£'), Some('$'), Some('€')],
-                    Some('1')
-                )
-                .with_label("currency symbol"),
-                Simple::custom(0..6, "Failed to parse cell content").with_label("csv cell")
-            ])
-        );
+    fn currency_symbol_for_code() {
+        assert_eq!(Some("€"), currency_symbol("EUR"));
+        assert_eq!(Some("CHF"), currency_symbol("CHF"));
+        assert_eq!(None, currency_symbol("JPY"));
+        assert_eq!(None, currency_symbol("$"));
     }
 
     #[test]
@@ -125,56 +347,29 @@ mod test {
     }
 
     #[test]
-    fn with_space() {
+    fn amount_out_of_range() {
+        assert_eq!(
+            amount_cell().parse("$79228162514264337593543950335"),
+            Err(vec![
+                Simple::custom(1..30, "Amount out of range").with_label("number"),
+                Simple::custom(0..30, "Failed to parse cell content").with_label("csv cell")
+            ])
+        );
+    }
+
+    #[test]
+    fn with_trailing_space() {
         assert_eq!(
             amount_cell().parse("$123.45 "),
             Err(vec![
-                Simple::expected_input_found(
-                    7..8,
-                    [
-                        Some('1'),
-                        None,
-                        Some(','),
-                        Some('.'),
-                        Some('3'),
-                        Some('8'),
-                        Some('5'),
-                        Some('4'),
-                        Some('0'),
-                        Some('6'),
-                        Some('2'),
-                        Some('7'),
-                        Some('9')
-                    ],
-                    Some(' ')
-                )
-                .with_label("number"),
+                Simple::custom(1..8, "Failed to parse amount").with_label("number"),
                 Simple::custom(0..8, "Failed to parse cell content").with_label("csv cell")
             ])
         );
         assert_eq!(
             amount_cell_opt().parse("$123.45 "),
             Err(vec![
-                Simple::expected_input_found(
-                    7..8,
-                    [
-                        Some('1'),
-                        None,
-                        Some(','),
-                        Some('.'),
-                        Some('3'),
-                        Some('8'),
-                        Some('5'),
-                        Some('4'),
-                        Some('0'),
-                        Some('6'),
-                        Some('2'),
-                        Some('7'),
-                        Some('9')
-                    ],
-                    Some(' ')
-                )
-                .with_label("number"),
+                Simple::custom(1..8, "Failed to parse amount").with_label("number"),
                 Simple::custom(0..8, "Failed to parse cell content").with_label("csv cell")
             ])
         );
@@ -191,6 +386,75 @@ mod test {
         test_parser(input, amount_cell_opt(), Some(expected), "");
     }
 
+    #[rstest]
+    fn with_other_thousand_separators(
+        #[values(
+            ("CHF1'234.56", "CHF", Decimal::new(123456, 2)),
+            ("\"€1 234,56\"", "€", Decimal::new(123456, 2)),
+            ("\"€1\u{a0}234\u{a0}567,8\"", "€", Decimal::new(12345678, 1)),
+            ("\"-€1 234\"", "€", Decimal::new(-1234, 0)),
+            ("\"€12,5\"", "€", Decimal::new(125, 1)),
+            ("\"234,56\"", "", Decimal::new(23456, 2)),
+            ("\"€1 234.56\"", "€", Decimal::new(123456, 2)),
+            ("\"CHF1'234,56\"", "CHF", Decimal::new(123456, 2)),
+            ("(1'234.56)", "", Decimal::new(-123456, 2))
+        )]
+        (input, currency_symbol, expected): (&str, &str, Decimal),
+    ) {
+        let expected = Amount {
+            amount: expected,
+            currency_symbol: currency_symbol.to_string(),
+        };
+        test_parser(input, amount_cell(), expected.clone(), "");
+        test_parser(input, amount_cell_opt(), Some(expected), "");
+    }
+
+    #[rstest]
+    fn thousands_not_in_groups_of_three(
+        #[values(
+            "\"$1,2345\"",
+            "\"$1234,567\"",
+            "\"$1,23,456\"",
+            "\"€1 23,4\"",
+            "\"$1,\"",
+            "\"$,123\""
+        )]
+        input: &str,
+    ) {
+        assert!(amount_cell().parse(input).is_err());
+    }
+
+    #[rstest]
+    fn mixed_thousand_separators(
+        #[values(
+            "\"$1,234'567.89\"",
+            "\"$1'234,567.89\"",
+            "\"€1 234'567,89\"",
+            "\"$1,234,56\""
+        )]
+        input: &str,
+    ) {
+        assert!(amount_cell().parse(input).is_err());
+    }
+
+    #[rstest]
+    fn more_or_fewer_than_two_decimal_places(
+        #[values(
+            ("\"$1,234.5678\"", "$", Decimal::new(12345678, 4)),
+            ("$0.001", "$", Decimal::new(1, 3)),
+            ("\"$1,500\"", "$", Decimal::new(1500, 0)),
+            ("(CHF12.345)", "CHF", Decimal::new(-12345, 3))
+        )]
+        (input, currency_symbol, expected): (&str, &str, Decimal),
+    ) {
+        let expected = Amount {
+            amount: expected,
+            currency_symbol: currency_symbol.to_string(),
+        };
+        test_parser(input, amount_cell(), expected.clone(), "");
+        test_parser(input, amount_cell_opt(), Some(expected), "");
+    }
+
     #[test]
     fn empty_cell() {
         assert_eq!(
@@ -198,10 +462,28 @@ mod test {
             Err(vec![
                 Simple::expected_input_found(
                     0..0,
-                    [Some('€'), Some('£'), Some('-'), Some('C'), Some('$')],
+                    [
+                        Some('€'),
+                        Some('£'),
+                        Some('-'),
+                        Some('C'),
+                        Some('$'),
+                        Some('('),
+                        Some('.'),
+                        Some('0'),
+                        Some('1'),
+                        Some('2'),
+                        Some('3'),
+                        Some('4'),
+                        Some('5'),
+                        Some('6'),
+                        Some('7'),
+                        Some('8'),
+                        Some('9')
+                    ],
                     None
                 )
-                .with_label("currency symbol"),
+                .with_label("amount"),
                 Simple::custom(0..0, "Failed to parse cell content").with_label("csv cell")
             ])
         );
@@ -222,4 +504,115 @@ mod test {
         test_parser(input, amount_cell(), expected.clone(), "");
         test_parser(input, amount_cell_opt(), Some(expected), "");
     }
+
+    #[rstest]
+    fn fast_path_agrees_with_parser(
+        #[values(
+            "$123.45",
+            "-$123.45",
+            "CHF1,234.56",
+            "€1,234,567.8",
+            "£.5",
+            "$5.",
+            "$",
+            "$,1",
+            "$1,,2",
+            "$1.2.3",
+            "123.45",
+            "$-1",
+            "$-1,234.56",
+            "CHF-1",
+            "$-1-",
+            "-$-1",
+            "($-1)",
+            "1,234.56",
+            "(1,234.56)",
+            "1,234.56-",
+            "-1",
+            "(-1)",
+            "-1-",
+            "--1",
+            "-",
+            "",
+            "$1 ",
+            "USD1",
+            "($123.45)",
+            "$123.45-",
+            "(CHF1,000)",
+            "-$1-",
+            "($1)-",
+            "(-$1)",
+            "($1",
+            "$1)",
+            "()",
+            "$1--",
+            "CHF1'234.56",
+            "€1 234,56",
+            "€1\u{a0}234,56",
+            "€1 234.56",
+            "$1'234,56",
+            "$1,234'567",
+            "$ 1",
+            "$1  234",
+            "$'1",
+            "1 234,56-",
+            "(€1 234,56)",
+            "USD123.45",
+            "USD 123.45",
+            "USD  123.45",
+            "USD -123.45",
+            "USD- 123.45",
+            "USD -123.45-",
+            "-USD 1",
+            "123.45 USD",
+            "123.45USD",
+            "123.45  USD",
+            "1 234,56 EUR",
+            "123.45 USD-",
+            "-123.45 USD",
+            "(123.45 USD)",
+            "1- USD",
+            "$1 USD",
+            "CHF1 CHF",
+            "USD",
+            " USD",
+            "USD1 ",
+            "JPY 1",
+            "1 JPY",
+            "$999999999999999999.99",
+            "$0.0001",
+            "$12.345",
+            "$1000000000000000000",
+            "$79228162514264337593543950335",
+            "$79228162514264337593543950336"
+        )]
+        input: &str,
+    ) {
+        assert_eq!(
+            parse_amount(input),
+            amount().then_ignore(end()).parse(input).ok()
+        );
+    }
+
+    #[rstest]
+    fn alternative_negative_formats(
+        #[values(
+            "-$1234.56",
+            "($1234.56)",
+            "$1234.56-",
+            "$-1234.56",
+            "\"-$1,234.56\"",
+            "\"$-1,234.56\"",
+            "\"($1,234.56)\"",
+            "\"$1,234.56-\""
+        )]
+        input: &str,
+    ) {
+        let expected = Amount {
+            amount: Decimal::new(-123456, 2),
+            currency_symbol: "$".to_string(),
+        };
+        test_parser(input, amount_cell(), expected.clone(), "");
+        test_parser(input, amount_cell_opt(), Some(expected), "");
+    }
 }
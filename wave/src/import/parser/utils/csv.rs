@@ -2,21 +2,27 @@ use std::ops::Range;
 
 use chumsky::{
     error::Simple,
-    prelude::{any, end, just, one_of},
+    prelude::{end, just, one_of},
     Parser as _,
 };
 
-/// Match a CSV cell, either enclosed in quotes or unquoted. The commas around the cell are not matched.
-pub fn cell<T>(
+/// Match a CSV cell, either enclosed in quotes or unquoted, and parse its content. The commas around the cell are not
+/// matched. The content is first parsed with `fast_path`, which works on the `&str` directly.
+/// Running a nested chumsky parser on each cell is slow, so the nested `content_parser` only runs if `fast_path`
+/// returns `None`, i.e. mostly to generate error messages. `fast_path` must not accept anything `content_parser` rejects,
+/// and must return the same value for anything it accepts.
+pub fn cell_with_fast_path<T>(
+    fast_path: impl Fn(&str) -> Option<T>,
     content_parser: impl chumsky::Parser<char, T, Error = Simple<char>>,
 ) -> impl chumsky::Parser<char, T, Error = Simple<char>> {
     let content_parser = content_parser.then_ignore(end());
-    quoted_cell()
-        .or(unquoted_cell())
-        .then_ignore(cell_end().rewind())
-        .validate(
+    raw_cell()
+        .validate(move |content, outer_span, emit| {
+            if let Some(parsed) = fast_path(&content) {
+                return Ok(parsed);
+            }
             // Take any errors thrown by the inner parser, adjust their span, and emit them.
-            move |content, outer_span, emit| match content_parser.parse(content.as_str()) {
+            match content_parser.parse(content.as_str()) {
                 Ok(parsed) => Ok(parsed),
                 Err(inner_errors) => {
                     for err in inner_errors.into_iter() {
@@ -27,12 +33,19 @@ pub fn cell<T>(
                     }
                     Err(Simple::custom(outer_span, "Failed to parse cell content"))
                 }
-            },
-        )
+            }
+        })
         .try_map(|parsed, _span| parsed)
         .labelled("csv cell")
 }
 
+/// Match a cell and return its unescaped content
+fn raw_cell() -> impl chumsky::Parser<char, String, Error = Simple<char>> {
+    quoted_cell()
+        .or(unquoted_cell())
+        .then_ignore(cell_end().rewind())
+}
+
 fn quoted_cell() -> impl chumsky::Parser<char, String, Error = Simple<char>> {
     let escaped_quote = just("\"\"").to('\"');
     let quoted_cell_content = quote().not().or(escaped_quote).repeated().collect();
@@ -70,7 +83,8 @@ fn quote() -> impl chumsky::Parser<char, (), Error = Simple<char>> {
 
 /// Match a cell with any content
 pub fn any_cell() -> impl chumsky::Parser<char, String, Error = Simple<char>> {
-    cell(any().repeated().collect())
+    // Any content is valid, so there's no need to run a content parser
+    raw_cell().labelled("csv cell")
 }
 
 /// Match an empty cell
@@ -82,9 +96,11 @@ pub fn empty_cell() -> impl chumsky::Parser<char, (), Error = Simple<char>> {
 pub fn cell_tag<'a>(
     expected_content: &'a str,
 ) -> impl chumsky::Parser<char, (), Error = Simple<char>> + use<'a> {
-    cell(just(expected_content))
-        .ignored()
-        .labelled("cell with specific content")
+    cell_with_fast_path(
+        move |content| (content == expected_content).then_some(()),
+        just(expected_content).ignored(),
+    )
+    .labelled("cell with specific content")
 }
 
 pub fn comma() -> impl chumsky::Parser<char, (), Error = Simple<char>> {
@@ -7,13 +7,23 @@ use chumsky::{
     Parser as _,
 };
 
-use super::csv::cell;
+use super::csv::cell_with_fast_path;
 
 pub fn date() -> impl chumsky::Parser<char, NaiveDate, Error = Simple<char>> {
     let digit = || one_of("0123456789");
     let separator = just('-');
-    let year = digit().repeated().exactly(4).try_map(parse_number::<i32>);
-    let month_or_day = || digit().repeated().exactly(2).try_map(parse_number::<u32>);
+    let year = digit()
+        .repeated()
+        .exactly(4)
+        .collect()
+        .try_map(parse_number::<i32>);
+    let month_or_day = || {
+        digit()
+            .repeated()
+            .exactly(2)
+            .collect()
+            .try_map(parse_number::<u32>)
+    };
     year.then_ignore(separator)
         .then(month_or_day())
         .then_ignore(separator)
@@ -25,10 +35,8 @@ pub fn date() -> impl chumsky::Parser<char, NaiveDate, Error = Simple<char>> {
         .labelled("date")
 }
 
-fn parse_number<N: FromStr>(content: Vec<char>, span: Range<usize>) -> Result<N, Simple<char>> {
+fn parse_number<N: FromStr>(content: String, span: Range<usize>) -> Result<N, Simple<char>> {
     content
-        .into_iter()
-        .collect::<String>()
         .parse()
         .map_err(|_err| Simple::custom(span, "Failed to parse number"))
 }
@@ -41,12 +49,34 @@ pub fn date_range() -> impl chumsky::Parser<char, (NaiveDate, NaiveDate), Error
 }
 
 pub fn date_cell() -> impl chumsky::Parser<char, NaiveDate, Error = Simple<char>> {
-    cell(date()).labelled("date cell")
+    cell_with_fast_path(parse_date, date()).labelled("date cell")
+}
+
+/// Fast path for [date], accepting exactly the same inputs
+fn parse_date(content: &str) -> Option<NaiveDate> {
+    let bytes = content.as_bytes();
+    let is_digits = |range: Range<usize>| bytes[range].iter().all(u8::is_ascii_digit);
+    if bytes.len() != 10
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !is_digits(0..4)
+        || !is_digits(5..7)
+        || !is_digits(8..10)
+    {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(
+        content[0..4].parse().ok()?,
+        content[5..7].parse().ok()?,
+        content[8..10].parse().ok()?,
+    )
 }
 
 #[cfg(test)]
 mod tests {
+    use chumsky::prelude::end;
     use chumsky::Error as _;
+    use rstest::rstest;
 
     use crate::import::parser::utils::testutils::test_parser;
 
@@ -279,4 +309,23 @@ mod tests {
             "\rfoo",
         );
     }
+
+    #[rstest]
+    #[case::valid("2021-01-01")]
+    #[case::end_of_year("2021-12-31")]
+    #[case::invalid_day("2021-02-30")]
+    #[case::invalid_month("2021-13-01")]
+    #[case::year_zero("0000-01-01")]
+    #[case::single_digit_month("2021-1-01")]
+    #[case::slashes("2021/01/01")]
+    #[case::signed_year("+021-01-01")]
+    #[case::trailing_digit("2021-01-011")]
+    #[case::empty("")]
+    #[case::fullwidth_digits("２０２１-01-01")]
+    fn fast_path_agrees_with_parser(#[case] input: &str) {
+        assert_eq!(
+            parse_date(input),
+            date().then_ignore(end()).parse(input).ok()
+        );
+    }
 }
@@ -5,7 +5,7 @@ mod line;
 #[cfg(test)]
 mod testutils;
 
-pub use amount::{amount_cell, amount_cell_opt};
+pub use amount::{amount_cell, amount_cell_opt, currency_symbol, parse_amount};
 pub use csv::{any_cell, cell_tag, comma, empty_cell, row_end};
 pub use date::{date_cell, date_range};
 pub use line::{line_any_content, line_tag};
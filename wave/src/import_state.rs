@@ -0,0 +1,244 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::ir::{Ledger, Transaction};
+
+/// Date ranges and transactions of previous imports, so an export that overlaps one of them is noticed instead of
+/// importing its transactions twice. Stored as a JSON file, see `--state`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ImportState {
+    imports: Vec<ImportedRange>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ImportedRange {
+    source: String,
+    ledger_name: String,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    /// See [fingerprint]
+    transactions: BTreeSet<String>,
+}
+
+/// A previous import of the same ledger whose date range overlaps the new export
+#[derive(Debug, PartialEq, Eq)]
+pub struct Overlap {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    /// Transactions of the new export in the overlapping days
+    pub num_transactions: usize,
+    /// How many of them were imported before
+    pub num_already_imported: usize,
+}
+
+impl ImportState {
+    /// A missing file is an empty state
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = match std::fs::read(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read import state {}", path.display()))
+            }
+        };
+        serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse import state {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_vec_pretty(self)?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write import state {}", path.display()))
+    }
+
+    /// Previous imports of the same source and ledger whose date range overlaps the one of `ledger`
+    pub fn overlaps(&self, ledger: &Ledger) -> Vec<Overlap> {
+        self.same_ledger(ledger)
+            .filter_map(|import| {
+                let start_date = import.start_date.max(ledger.dates.start_date);
+                let end_date = import.end_date.min(ledger.dates.end_date);
+                if start_date > end_date {
+                    return None;
+                }
+                let in_overlap: Vec<&Transaction> = ledger
+                    .transactions
+                    .iter()
+                    .filter(|transaction| (start_date..=end_date).contains(&transaction.date))
+                    .collect();
+                Some(Overlap {
+                    start_date,
+                    end_date,
+                    num_transactions: in_overlap.len(),
+                    num_already_imported: in_overlap
+                        .iter()
+                        .filter(|transaction| self.was_imported(ledger, transaction))
+                        .count(),
+                })
+            })
+            .collect()
+    }
+
+    /// Leave out the transactions of `ledger` that a previous import of the same ledger already had.
+    /// Returns how many were left out.
+    pub fn remove_imported(&self, ledger: &mut Ledger) -> usize {
+        let transactions = std::mem::take(&mut ledger.transactions);
+        let num_transactions = transactions.len();
+        ledger.transactions = transactions
+            .into_iter()
+            .filter(|transaction| !self.was_imported(ledger, transaction))
+            .collect();
+        num_transactions - ledger.transactions.len()
+    }
+
+    /// Remember the date range and transactions of `ledger` as imported
+    pub fn record(&mut self, ledger: &Ledger) {
+        self.imports.push(ImportedRange {
+            source: ledger.source.clone(),
+            ledger_name: ledger.ledger_name.clone(),
+            start_date: ledger.dates.start_date,
+            end_date: ledger.dates.end_date,
+            transactions: ledger.transactions.iter().map(fingerprint).collect(),
+        });
+    }
+
+    fn same_ledger<'a>(&'a self, ledger: &'a Ledger) -> impl Iterator<Item = &'a ImportedRange> {
+        self.imports.iter().filter(|import| {
+            import.source == ledger.source && import.ledger_name == ledger.ledger_name
+        })
+    }
+
+    fn was_imported(&self, ledger: &Ledger, transaction: &Transaction) -> bool {
+        let fingerprint = fingerprint(transaction);
+        self.same_ledger(ledger)
+            .any(|import| import.transactions.contains(&fingerprint))
+    }
+}
+
+/// Identifies a transaction by its date, description and postings, independent of the order of its postings
+fn fingerprint(transaction: &Transaction) -> String {
+    let mut postings: Vec<String> = transaction
+        .postings
+        .iter()
+        .map(|posting| {
+            format!(
+                "{}={}",
+                posting.account_name,
+                posting.amount.in_account_currency.normalize()
+            )
+        })
+        .collect();
+    postings.sort();
+    format!(
+        "{} {} [{}]",
+        transaction.date,
+        transaction.description,
+        postings.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use common_macros::hash_map;
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::ir::{Amount, Dates, Posting};
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn transaction(date: NaiveDate, description: &str) -> Transaction {
+        Transaction {
+            date,
+            description: description.to_string(),
+            payee: None,
+            metadata: hash_map![],
+            tags: vec![],
+            postings: vec![Posting {
+                account_name: "Checking".to_string(),
+                amount: Amount::single_currency(Decimal::new(-500, 2)),
+                metadata: hash_map![],
+            }],
+        }
+    }
+
+    fn ledger(
+        ledger_name: &str,
+        (start_date, end_date): (NaiveDate, NaiveDate),
+        transactions: Vec<Transaction>,
+    ) -> Ledger {
+        Ledger {
+            source: "Wave".to_string(),
+            ledger_name: ledger_name.to_string(),
+            ledger_currency: "USD".to_string(),
+            dates: Dates {
+                start_date,
+                end_date,
+            },
+            accounts: hash_map![],
+            transactions,
+        }
+    }
+
+    #[test]
+    fn detect_overlap_and_remove_imported_transactions() {
+        let mut state = ImportState::default();
+        state.record(&ledger(
+            "Business",
+            (date(1, 1), date(1, 31)),
+            vec![
+                transaction(date(1, 10), "Coffee"),
+                transaction(date(1, 30), "Rent"),
+            ],
+        ));
+
+        let mut new_ledger = ledger(
+            "Business",
+            (date(1, 25), date(2, 28)),
+            vec![
+                transaction(date(1, 30), "Rent"),
+                transaction(date(1, 31), "Late fee"),
+                transaction(date(2, 5), "Coffee"),
+            ],
+        );
+        assert_eq!(
+            vec![Overlap {
+                start_date: date(1, 25),
+                end_date: date(1, 31),
+                num_transactions: 2,
+                num_already_imported: 1,
+            }],
+            state.overlaps(&new_ledger)
+        );
+
+        assert_eq!(1, state.remove_imported(&mut new_ledger));
+        let descriptions: Vec<&str> = new_ledger
+            .transactions
+            .iter()
+            .map(|transaction| transaction.description.as_str())
+            .collect();
+        assert_eq!(vec!["Late fee", "Coffee"], descriptions);
+    }
+
+    #[test]
+    fn ignore_other_ledgers_and_adjacent_ranges() {
+        let mut state = ImportState::default();
+        state.record(&ledger("Business", (date(1, 1), date(1, 31)), vec![]));
+        state.record(&ledger("Personal", (date(2, 1), date(2, 28)), vec![]));
+
+        let new_ledger = ledger("Business", (date(2, 1), date(2, 28)), vec![]);
+        assert_eq!(Vec::<Overlap>::new(), state.overlaps(&new_ledger));
+    }
+
+    #[test]
+    fn load_missing_state_file() {
+        let state = ImportState::load(Path::new("no-such-import-state.json")).unwrap();
+        assert!(state.imports.is_empty());
+    }
+}
@@ -1,25 +1,235 @@
+//! Convert exports from Wave and other sources to Beancount.
+//!
+//! Besides the command line tool in [main], the Wave conversion can be embedded in other tools:
+//!
+//! * [import::load] reads a Wave "Account Transactions" CSV export into the intermediate representation in [ir].
+//! * [operations::process_wave_ledger] turns the imported postings into balanced transactions sorted by date.
+//! * [export::render_to_writer] renders the result as a Beancount file, using a [config::Config] for account names.
+//!
+//! With the `pyo3` feature, the conversions are also available as a Python module, including importers for beangulp.
+//! With the `wasm` feature, the crate builds for `wasm32-unknown-unknown` and exposes the conversion to JavaScript for
+//! a preview in the browser, see `web/index.html`.
+
 use anyhow::Result;
 
+mod amazon;
+#[cfg(not(target_arch = "wasm32"))]
 mod args;
-mod config;
-mod export;
-mod import;
-mod ir;
-mod operations;
+mod camt053;
+pub mod config;
+mod csv_import;
+#[cfg(not(target_arch = "wasm32"))]
+mod dump;
+pub mod export;
+pub mod import;
+#[cfg(not(target_arch = "wasm32"))]
+mod import_state;
+pub mod ir;
+pub mod operations;
+mod paypal;
+#[cfg(feature = "pyo3")]
+mod python;
+mod registry;
+mod venmo;
+#[cfg(feature = "wasm")]
+mod wasm;
 
+#[cfg(not(target_arch = "wasm32"))]
 pub fn main() -> Result<()> {
+    use anyhow::Context as _;
+    use args::Command;
+    use import_state::ImportState;
+    use ir::script::Script;
+    use registry::{ImportOptions, Registry};
+
     let args = args::parse();
-    let file = std::fs::File::open(args.from_csv).unwrap();
+    // Loaded before importing so that errors in the script show up right away
+    let script = args.script.as_deref().map(Script::load).transpose()?;
+
+    let mut dump = dump::IrDump::new(args.dump_ir);
+    let mut accounts_with_unknown_type = vec![];
+
+    let mut ledger = match args.command {
+        Command::Wave {
+            from_csv,
+            account_balances,
+            rounding_tolerance,
+            merge,
+            lenient,
+        } => {
+            let file = std::fs::File::open(from_csv).unwrap();
+
+            let import = match account_balances {
+                Some(account_balances) => import::load_and_check_account_balances(
+                    file,
+                    std::fs::File::open(account_balances)?,
+                    rounding_tolerance,
+                    lenient,
+                )?,
+                None => import::load(file, rounding_tolerance, lenient).unwrap(),
+            };
+            accounts_with_unknown_type = import.accounts_with_unknown_type;
+            let ledger = import.ledger;
+            dump.record("import", &ledger)?;
+            operations::process_wave_ledger(ledger, merge, rounding_tolerance, |stage, ledger| {
+                dump.record(stage, ledger)
+            })?
+        }
+        Command::CsvImport { schema, from_csv } => {
+            let schema = csv_import::Schema::load(&schema)?;
+            let file = std::fs::File::open(from_csv)?;
+
+            let ledger = csv_import::load(&schema, file)?;
+            dump.record("import", &ledger)?;
+            let ledger = operations::sort_transactions_by_date(ledger);
+            dump.record("sort_transactions_by_date", &ledger)?;
+            ledger
+        }
+        Command::Camt053 { from_xml } => {
+            let file = std::fs::File::open(from_xml)?;
+
+            let ledger = camt053::load(file)?;
+            dump.record("import", &ledger)?;
+            let ledger = operations::sort_transactions_by_date(ledger);
+            dump.record("sort_transactions_by_date", &ledger)?;
+            ledger
+        }
+        Command::Paypal {
+            from_csv,
+            currency,
+            date_format,
+        } => {
+            let file = std::fs::File::open(from_csv)?;
 
-    let ledger = import::load(file).unwrap();
-    let ledger = operations::merge_transactions_with_same_date_description_and_amount(ledger);
-    let ledger = operations::sort_transactions_by_date(ledger);
-    operations::check_transactions_are_balanced_per_date(&ledger)?;
+            let ledger = paypal::load(file, &currency, &date_format)?;
+            dump.record("import", &ledger)?;
+            let ledger = operations::sort_transactions_by_date(ledger);
+            dump.record("sort_transactions_by_date", &ledger)?;
+            ledger
+        }
+        Command::Amazon {
+            orders,
+            items,
+            refunds,
+        } => {
+            let orders = std::fs::File::open(orders)?;
+            let items = std::fs::File::open(items)?;
+            let refunds = refunds.map(std::fs::File::open).transpose()?;
 
-    let config =
-        config::prompt_edit_config(ledger.account_names().into_iter().map(str::to_string))?;
+            let ledger = amazon::load(orders, items, refunds)?;
+            dump.record("import", &ledger)?;
+            let ledger = operations::sort_transactions_by_date(ledger);
+            dump.record("sort_transactions_by_date", &ledger)?;
+            ledger
+        }
+        Command::Venmo { from_csv } => {
+            let file = std::fs::File::open(from_csv)?;
 
-    export::print_exported_transactions(ledger, &config)?;
+            let ledger = venmo::load(file)?;
+            dump.record("import", &ledger)?;
+            let ledger = operations::sort_transactions_by_date(ledger);
+            dump.record("sort_transactions_by_date", &ledger)?;
+            ledger
+        }
+        Command::Import {
+            file,
+            source,
+            schema,
+            rounding_tolerance,
+            merge,
+            currency,
+            date_format,
+        } => {
+            let options = ImportOptions {
+                rounding_tolerance,
+                merge,
+                schema: schema
+                    .as_deref()
+                    .map(csv_import::Schema::load)
+                    .transpose()?,
+                currency,
+                date_format,
+            };
+            let content = std::fs::read(&file)?;
+
+            let registry = Registry::builtin();
+            let importer = match source {
+                Some(source) => registry.get(&source)?,
+                None => registry
+                    .detect(&content, &options)
+                    .with_context(|| format!("Failed to import {}", file.display()))?,
+            };
+            let import = (importer.create)(&options)?
+                .load(&content, &mut |stage, ledger| dump.record(stage, ledger))?;
+            accounts_with_unknown_type = import.accounts_with_unknown_type;
+            import.ledger
+        }
+    };
+
+    // Checked before the script runs, so changing the script doesn't change which transactions count as imported
+    let state = match &args.state {
+        Some(path) => {
+            let mut state = ImportState::load(path)?;
+            ledger = skip_imported_transactions(&mut state, ledger, args.allow_overlap)?;
+            Some((path, state))
+        }
+        None => None,
+    };
+
+    let ledger = match script {
+        Some(script) => {
+            let ledger = operations::apply_script(ledger, &script)?;
+            dump.record("apply_script", &ledger)?;
+            ledger
+        }
+        None => ledger,
+    };
+
+    let config = config::prompt_edit_config(
+        ledger.account_names().into_iter().map(str::to_string),
+        &accounts_with_unknown_type,
+    )?;
+    let ledger = import::apply_account_types(ledger, &accounts_with_unknown_type, &config)?;
+
+    export::render_to_writer(
+        ledger,
+        &config,
+        args.fiscal_year_end,
+        &mut std::io::stdout().lock(),
+    )?;
+    // Only recorded once the export was written, so a failed import can be repeated
+    if let Some((path, state)) = state {
+        state.save(path)?;
+    }
 
     Ok(())
 }
+
+/// Refuse an export that overlaps a previous import unless `allow_overlap` is set, then leave out the transactions
+/// that were already imported. Records the export in `state`.
+#[cfg(not(target_arch = "wasm32"))]
+fn skip_imported_transactions(
+    state: &mut import_state::ImportState,
+    mut ledger: ir::Ledger,
+    allow_overlap: bool,
+) -> Result<ir::Ledger> {
+    let overlaps = state.overlaps(&ledger);
+    for overlap in &overlaps {
+        eprintln!(
+            "Warning: The export overlaps a previous import from {} to {}. {} of its transactions are in that range, {} of them were already imported.",
+            overlap.start_date,
+            overlap.end_date,
+            overlap.num_transactions,
+            overlap.num_already_imported,
+        );
+    }
+    if !overlaps.is_empty() {
+        if !allow_overlap {
+            anyhow::bail!("The export overlaps a previous import, pass --allow-overlap to import it without the transactions that were already imported");
+        }
+        let num_removed = state.remove_imported(&mut ledger);
+        eprintln!("Left out {num_removed} transactions that were already imported");
+    }
+    state.record(&ledger);
+    Ok(ledger)
+}
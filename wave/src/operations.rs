@@ -1,11 +1,57 @@
 use anyhow::Result;
 use chrono::NaiveDate;
+use common_macros::hash_map;
 use rust_decimal::prelude::Zero as _;
 use rust_decimal::Decimal;
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
 use std::hash::Hash;
 
-use crate::ir::{Ledger, Posting, Transaction};
+use crate::ir::{
+    script::Script, AccountInfo, Amount, Ledger, Posting, Transaction, ROUNDING_ACCOUNT,
+};
+
+/// How the single-account postings from Wave are combined into transactions
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum MergeMode {
+    /// Keep each posting as its own transaction
+    None,
+    /// Merge pairs of postings with the same date, description and opposite amounts
+    SameAmount,
+    /// Merge all postings with the same date and description into one transaction
+    SameDescription,
+}
+
+/// The operations applied to a ledger imported from Wave, in order: merging postings into transactions according
+/// to `merge`, checking that each date is balanced and sorting by date. `on_stage` is called with the name and
+/// result of each stage, e.g. to record them with `--dump-ir`.
+pub fn process_wave_ledger(
+    ledger: Ledger,
+    merge: MergeMode,
+    rounding_tolerance: Decimal,
+    mut on_stage: impl FnMut(&str, &Ledger) -> Result<()>,
+) -> Result<Ledger> {
+    let ledger = match merge {
+        MergeMode::None => ledger,
+        MergeMode::SameAmount => {
+            let ledger = merge_transactions_with_same_date_description_and_amount(ledger);
+            on_stage(
+                "merge_transactions_with_same_date_description_and_amount",
+                &ledger,
+            )?;
+            ledger
+        }
+        MergeMode::SameDescription => {
+            let ledger = merge_transactions_with_same_date_and_description(ledger);
+            on_stage("merge_transactions_with_same_date_and_description", &ledger)?;
+            ledger
+        }
+    };
+    let ledger = check_transactions_are_balanced_per_date(ledger, rounding_tolerance)?;
+    on_stage("check_transactions_are_balanced_per_date", &ledger)?;
+    let ledger = sort_transactions_by_date(ledger);
+    on_stage("sort_transactions_by_date", &ledger)?;
+    Ok(ledger)
+}
 
 pub fn merge_transactions_with_same_date_description_and_amount(ledger: Ledger) -> Ledger {
     let merged_transactions = group_by(
@@ -15,7 +61,9 @@ pub fn merge_transactions_with_same_date_description_and_amount(ledger: Ledger)
     );
 
     Ledger {
+        source: ledger.source,
         ledger_name: ledger.ledger_name,
+        ledger_currency: ledger.ledger_currency,
         dates: ledger.dates,
         accounts: ledger.accounts,
         transactions: merged_transactions
@@ -27,6 +75,34 @@ pub fn merge_transactions_with_same_date_description_and_amount(ledger: Ledger)
     }
 }
 
+/// Merge all postings with the same date and description into one transaction, regardless of their amounts
+pub fn merge_transactions_with_same_date_and_description(ledger: Ledger) -> Ledger {
+    let merged_transactions = group_by(
+        ledger.transactions.into_iter(),
+        |transaction| (transaction.date, transaction.description.clone()),
+        |transaction| transaction.postings.into_iter(),
+    );
+
+    Ledger {
+        source: ledger.source,
+        ledger_name: ledger.ledger_name,
+        ledger_currency: ledger.ledger_currency,
+        dates: ledger.dates,
+        accounts: ledger.accounts,
+        transactions: merged_transactions
+            .into_iter()
+            .map(|((date, description), postings)| Transaction {
+                date,
+                description,
+                payee: None,
+                metadata: hash_map![],
+                tags: vec![],
+                postings,
+            })
+            .collect(),
+    }
+}
+
 // Take all postings from a given date with a given description and generate transactions.
 // Any two postings with matching amounts will be merged to one transaction.
 // But if there is ambiguity, i.e. there are more than two postings with the same amount, they will be left as individual transactions.
@@ -60,6 +136,9 @@ fn transactions_from_postings(
             result.push(Transaction {
                 date,
                 description: description.clone(),
+                payee: None,
+                metadata: hash_map![],
+                tags: vec![],
                 postings: vec![positive_posting, negative_posting],
             });
         } else {
@@ -70,6 +149,9 @@ fn transactions_from_postings(
                     .map(|posting| Transaction {
                         date,
                         description: description.clone(),
+                        payee: None,
+                        metadata: hash_map![],
+                        tags: vec![],
                         postings: vec![posting],
                     }),
             );
@@ -79,26 +161,137 @@ fn transactions_from_postings(
     result.into_iter()
 }
 
-pub fn check_transactions_are_balanced_per_date(ledger: &Ledger) -> Result<()> {
+/// Check that the postings on each date add up to zero. Dates that are off by at most `rounding_tolerance`
+/// get an additional transaction booking the difference to [ROUNDING_ACCOUNT].
+/// Dates whose postings are all in the same foreign account currency also have to add up to zero in that currency,
+/// since rounding in Wave's converted amounts can hide a difference in the ledger currency. Errors list the sums per
+/// account currency to show which currency leg is unbalanced.
+pub fn check_transactions_are_balanced_per_date(
+    mut ledger: Ledger,
+    rounding_tolerance: Decimal,
+) -> Result<Ledger> {
     let postings_by_date = group_by(
         ledger.transactions.iter(),
         |transaction| transaction.date,
         |transaction| transaction.postings.iter(),
     );
+    let mut rounding_transactions = vec![];
     for (date, postings) in &postings_by_date {
-        let sum = postings
-            .iter()
-            .map(|posting| posting.amount.in_ledger_currency)
+        let legs = currency_legs(&ledger, postings);
+        let sum = legs
+            .values()
+            .map(|amount| amount.in_ledger_currency)
             .sum::<Decimal>();
-        if sum != Decimal::zero() {
+        if sum.abs() > rounding_tolerance {
             return Err(anyhow::anyhow!(
-                "Postings on date {:?} are not balanced: {:?}",
+                "Postings on date {:?} are not balanced, they add up to {} {}. Sums per account currency: {}. Postings: {:?}",
                 date,
+                sum,
+                ledger.ledger_currency,
+                describe_legs(&legs, &ledger.ledger_currency),
                 postings,
             ));
         }
+        if let [(currency, amount)] = legs.iter().collect::<Vec<_>>()[..] {
+            if *currency != ledger.ledger_currency
+                && amount.in_account_currency.abs() > rounding_tolerance
+            {
+                return Err(anyhow::anyhow!(
+                    "Postings on date {:?} are not balanced in {}, they add up to {} {} but to {} {} after conversion. Postings: {:?}",
+                    date,
+                    currency,
+                    amount.in_account_currency,
+                    currency,
+                    amount.in_ledger_currency,
+                    ledger.ledger_currency,
+                    postings,
+                ));
+            }
+        }
+        if sum != Decimal::zero() {
+            rounding_transactions.push(Transaction {
+                date: *date,
+                description: "Rounding adjustment".to_string(),
+                payee: None,
+                metadata: hash_map![],
+                tags: vec![],
+                postings: vec![Posting {
+                    account_name: ROUNDING_ACCOUNT.to_string(),
+                    amount: Amount::single_currency(-sum),
+                    metadata: hash_map![],
+                }],
+            });
+        }
+    }
+    if !rounding_transactions.is_empty() {
+        rounding_transactions.sort_by_key(|transaction| transaction.date);
+        ledger
+            .accounts
+            .entry(ROUNDING_ACCOUNT.to_string())
+            .or_insert_with(|| AccountInfo {
+                start_balance: None,
+                end_balance: None,
+                account_currency: ledger.ledger_currency.clone(),
+            });
+        ledger.transactions.extend(rounding_transactions);
+    }
+    Ok(ledger)
+}
+
+/// Sums of the postings per currency of their account. Accounts that aren't in the ledger are in the ledger currency.
+fn currency_legs<'a>(ledger: &'a Ledger, postings: &[&Posting]) -> BTreeMap<&'a str, Amount> {
+    let mut legs: BTreeMap<&str, Amount> = BTreeMap::new();
+    for posting in postings {
+        let currency = ledger
+            .accounts
+            .get(&posting.account_name)
+            .map_or(ledger.ledger_currency.as_str(), |account| {
+                account.account_currency.as_str()
+            });
+        *legs.entry(currency).or_insert_with(Amount::zero) += posting.amount;
+    }
+    legs
+}
+
+fn describe_legs(legs: &BTreeMap<&str, Amount>, ledger_currency: &str) -> String {
+    legs.iter()
+        .map(|(currency, amount)| {
+            if *currency == ledger_currency {
+                format!("{} {currency}", amount.in_ledger_currency)
+            } else {
+                format!(
+                    "{} {currency} ({} {ledger_currency})",
+                    amount.in_account_currency, amount.in_ledger_currency
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Pass all transactions through the script, see [Script]. Accounts the script books counter postings to are added
+/// to the ledger in the ledger currency.
+pub fn apply_script(mut ledger: Ledger, script: &Script) -> Result<Ledger> {
+    let mut transactions = Vec::with_capacity(ledger.transactions.len());
+    for transaction in ledger.transactions {
+        if let Some(transaction) = script.apply(transaction)? {
+            transactions.push(transaction);
+        }
+    }
+    for transaction in &transactions {
+        for posting in &transaction.postings {
+            ledger
+                .accounts
+                .entry(posting.account_name.clone())
+                .or_insert_with(|| AccountInfo {
+                    start_balance: None,
+                    end_balance: None,
+                    account_currency: ledger.ledger_currency.clone(),
+                });
+        }
     }
-    Ok(())
+    ledger.transactions = transactions;
+    Ok(ledger)
 }
 
 pub fn sort_transactions_by_date(mut ledger: Ledger) -> Ledger {
@@ -131,3 +324,103 @@ where
     }
     grouped
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::Dates;
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+    }
+
+    fn posting(account_name: &str, in_account_currency: i64, in_ledger_currency: i64) -> Posting {
+        Posting {
+            account_name: account_name.to_string(),
+            amount: Amount {
+                in_account_currency: Decimal::new(in_account_currency, 2),
+                in_ledger_currency: Decimal::new(in_ledger_currency, 2),
+            },
+            metadata: hash_map![],
+        }
+    }
+
+    /// A ledger in USD with a USD and two EUR accounts and one transaction with the given postings
+    fn ledger(postings: Vec<Posting>) -> Ledger {
+        let account = |currency: &str| AccountInfo {
+            start_balance: None,
+            end_balance: None,
+            account_currency: currency.to_string(),
+        };
+        Ledger {
+            source: "Wave".to_string(),
+            ledger_name: "Test".to_string(),
+            ledger_currency: "USD".to_string(),
+            dates: Dates {
+                start_date: date(),
+                end_date: date(),
+            },
+            accounts: hash_map![
+                "Assets:Checking".to_string() => account("USD"),
+                "Assets:Euro".to_string() => account("EUR"),
+                "Assets:EuroSavings".to_string() => account("EUR"),
+            ],
+            transactions: vec![Transaction {
+                date: date(),
+                description: "Transfer".to_string(),
+                payee: None,
+                metadata: hash_map![],
+                tags: vec![],
+                postings,
+            }],
+        }
+    }
+
+    #[test]
+    fn book_conversion_rounding() {
+        let ledger = ledger(vec![
+            posting("Assets:Euro", -1000, -1083),
+            posting("Assets:EuroSavings", 1000, 1084),
+        ]);
+        let ledger = check_transactions_are_balanced_per_date(ledger, Decimal::new(1, 2)).unwrap();
+        assert_eq!(2, ledger.transactions.len());
+        assert_eq!(
+            ROUNDING_ACCOUNT,
+            ledger.transactions[1].postings[0].account_name
+        );
+    }
+
+    #[test]
+    fn reject_unbalanced_foreign_currency() {
+        // Balanced after conversion, but 0.05 EUR are missing
+        let ledger = ledger(vec![
+            posting("Assets:Euro", -1005, -1084),
+            posting("Assets:EuroSavings", 1000, 1084),
+        ]);
+        let err = check_transactions_are_balanced_per_date(ledger, Decimal::new(1, 2))
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().starts_with(
+                "Postings on date 2024-03-01 are not balanced in EUR, they add up to -0.05 EUR"
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn report_unbalanced_currency_leg() {
+        let ledger = ledger(vec![
+            posting("Assets:Euro", -1000, -1084),
+            posting("Assets:Checking", 1000, 1000),
+        ]);
+        let err = check_transactions_are_balanced_per_date(ledger, Decimal::new(1, 2))
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string()
+                .contains("Sums per account currency: -10.00 EUR (-10.84 USD), 10.00 USD."),
+            "{err}"
+        );
+    }
+}
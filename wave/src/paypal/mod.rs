@@ -0,0 +1,530 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use chrono::{NaiveDate, NaiveTime};
+use common_macros::hash_map;
+use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+use crate::csv_import::parse_amount;
+use crate::ir::{AccountInfo, Amount, Dates, Ledger, MetaValue, Posting, Transaction};
+
+const PAYPAL_ACCOUNT: &str = "PayPal";
+/// Fees PayPal deducted from received payments
+const FEES_ACCOUNT: &str = "PayPal Fees";
+/// Money PayPal holds back, e.g. for open authorizations or payment reviews. It is released back later.
+const HOLDS_ACCOUNT: &str = "PayPal Holds";
+/// Clearing account for money moved between PayPal and a bank account or card
+const TRANSFERS_ACCOUNT: &str = "PayPal Transfers";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Payment,
+    Hold,
+    Transfer,
+    CurrencyConversion,
+}
+
+#[derive(Debug)]
+struct Row {
+    date: NaiveDate,
+    time: NaiveTime,
+    kind: Kind,
+    type_name: String,
+    description: String,
+    currency: String,
+    fee: Decimal,
+    net: Decimal,
+    balance: Option<Decimal>,
+    transaction_id: String,
+    reference_id: Option<String>,
+}
+
+/// Load a PayPal "Activity download" CSV file. The file must use `.` as decimal separator.
+pub fn load(input_stream: impl Read, ledger_currency: &str, date_format: &str) -> Result<Ledger> {
+    let mut rows = parse_rows(input_stream, date_format)?;
+    // PayPal lists activity newest first by default. Holds and their releases can share a timestamp, so use a stable sort.
+    rows.sort_by_key(|row| (row.date, row.time));
+    to_ir(rows, ledger_currency)
+}
+
+/// Whether the content looks like a PayPal activity CSV, i.e. its header row has the columns [load] needs
+pub fn detect(content: &[u8]) -> bool {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content);
+    reader
+        .headers()
+        .is_ok_and(|headers| Headers::new(headers).is_ok())
+}
+
+fn parse_rows(input_stream: impl Read, date_format: &str) -> Result<Vec<Row>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(input_stream);
+    let headers = Headers::new(reader.headers()?)?;
+    let mut rows = vec![];
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = record
+            .position()
+            .map(|position| position.line())
+            .unwrap_or(index as u64);
+        if let Some(row) = parse_row(&headers, &record, date_format)
+            .with_context(|| format!("Failed to parse CSV row {line}"))?
+        {
+            rows.push(row);
+        }
+    }
+    Ok(rows)
+}
+
+/// Column indices. PayPal lets users choose which columns to include in the export, so columns are looked up by name.
+struct Headers {
+    date: usize,
+    time: Option<usize>,
+    name: usize,
+    type_name: usize,
+    status: usize,
+    currency: usize,
+    fee: Option<usize>,
+    net: usize,
+    transaction_id: usize,
+    reference_id: Option<usize>,
+    balance: Option<usize>,
+    balance_impact: Option<usize>,
+    item_title: Option<usize>,
+    subject: Option<usize>,
+}
+
+impl Headers {
+    fn new(record: &csv::StringRecord) -> Result<Self> {
+        let names: Vec<&str> = record
+            .iter()
+            .map(|name| name.trim_start_matches('\u{feff}').trim())
+            .collect();
+        let optional = |name: &str| names.iter().position(|column| *column == name);
+        let required = |name: &str| {
+            optional(name).ok_or_else(|| anyhow!("CSV file doesn't have a '{name}' column"))
+        };
+        Ok(Self {
+            date: required("Date")?,
+            time: optional("Time"),
+            name: required("Name")?,
+            type_name: required("Type")?,
+            status: required("Status")?,
+            currency: required("Currency")?,
+            fee: optional("Fee"),
+            net: required("Net")?,
+            transaction_id: required("Transaction ID")?,
+            reference_id: optional("Reference Txn ID"),
+            balance: optional("Balance"),
+            balance_impact: optional("Balance Impact"),
+            item_title: optional("Item Title"),
+            subject: optional("Subject"),
+        })
+    }
+}
+
+/// Returns `None` for rows that don't affect the PayPal balance
+fn parse_row(
+    headers: &Headers,
+    record: &csv::StringRecord,
+    date_format: &str,
+) -> Result<Option<Row>> {
+    let cell = |index: usize| record.get(index).map(str::trim).unwrap_or_default();
+    let optional_cell = |index: Option<usize>| {
+        index
+            .map(cell)
+            .filter(|content| !content.is_empty() && *content != "...")
+    };
+
+    let status = cell(headers.status);
+    if matches!(
+        status,
+        "Pending" | "Denied" | "Canceled" | "Cancelled" | "Failed"
+    ) {
+        return Ok(None);
+    }
+    // Memo rows describe e.g. the card or bank account a payment was funded from, but don't touch the PayPal balance.
+    if optional_cell(headers.balance_impact) == Some("Memo") {
+        return Ok(None);
+    }
+
+    let date = NaiveDate::parse_from_str(cell(headers.date), date_format).with_context(|| {
+        format!(
+            "Failed to parse date '{}' with format '{date_format}'",
+            cell(headers.date)
+        )
+    })?;
+    let time = optional_cell(headers.time)
+        .map(|time| {
+            NaiveTime::parse_from_str(time, "%H:%M:%S")
+                .with_context(|| format!("Failed to parse time '{time}'"))
+        })
+        .transpose()?
+        .unwrap_or(NaiveTime::MIN);
+    let amount = |index: Option<usize>| -> Result<Option<Decimal>> {
+        index
+            .map(|index| parse_amount(cell(index), '.'))
+            .transpose()
+            .map(Option::flatten)
+    };
+
+    let type_name = cell(headers.type_name).to_string();
+    let name = cell(headers.name);
+    let item = optional_cell(headers.item_title).or_else(|| optional_cell(headers.subject));
+    let description = match (name.is_empty(), item) {
+        (false, Some(item)) => format!("{name}: {item}"),
+        (false, None) => name.to_string(),
+        (true, Some(item)) => item.to_string(),
+        (true, None) => type_name.clone(),
+    };
+
+    Ok(Some(Row {
+        date,
+        time,
+        kind: classify(&type_name),
+        description,
+        currency: cell(headers.currency).to_string(),
+        fee: amount(headers.fee)?.unwrap_or_default(),
+        net: amount(Some(headers.net))?.ok_or_else(|| anyhow!("Net column is empty"))?,
+        balance: amount(headers.balance)?,
+        transaction_id: cell(headers.transaction_id).to_string(),
+        reference_id: optional_cell(headers.reference_id).map(str::to_string),
+        type_name,
+    }))
+}
+
+fn classify(type_name: &str) -> Kind {
+    let type_name = type_name.to_lowercase();
+    if type_name.contains("currency conversion") {
+        Kind::CurrencyConversion
+    } else if type_name.contains("hold") || type_name.contains("release") {
+        Kind::Hold
+    } else if [
+        "withdrawal",
+        "transfer to bank",
+        "transfer from bank",
+        "bank deposit",
+        "card deposit",
+        "general deposit",
+    ]
+    .iter()
+    .any(|pattern| type_name.contains(pattern))
+    {
+        Kind::Transfer
+    } else {
+        Kind::Payment
+    }
+}
+
+fn to_ir(rows: Vec<Row>, ledger_currency: &str) -> Result<Ledger> {
+    ensure!(
+        !rows.is_empty(),
+        "CSV file doesn't contain any transactions"
+    );
+
+    // Payments in a foreign currency come with two currency conversion rows that reference the payment,
+    // one in the foreign currency and one in the ledger currency. Book the payment with the ledger currency amount.
+    let conversions: HashMap<&str, &Row> = rows
+        .iter()
+        .filter(|row| row.kind == Kind::CurrencyConversion && row.currency == ledger_currency)
+        .filter_map(|row| Some((row.reference_id.as_deref()?, row)))
+        .collect();
+    let mut used_conversions = HashSet::new();
+
+    let mut transactions = vec![];
+    let mut ledger_currency_rows = vec![];
+    for row in &rows {
+        if row.kind == Kind::CurrencyConversion {
+            // Handled together with the payment they reference
+            continue;
+        }
+        let mut metadata = hash_map![
+            "paypal_id".to_string() => row.transaction_id.clone().into()
+        ];
+        let (net, fee) = if row.currency == ledger_currency {
+            ledger_currency_rows.push(row);
+            (row.net, row.fee)
+        } else {
+            let conversion = conversions.get(row.transaction_id.as_str()).ok_or_else(|| {
+                anyhow!(
+                    "{} transaction {} on {} has no currency conversion into {ledger_currency}. Balances in multiple currencies aren't supported.",
+                    row.currency,
+                    row.transaction_id,
+                    row.date
+                )
+            })?;
+            used_conversions.insert(conversion.transaction_id.as_str());
+            ledger_currency_rows.push(conversion);
+            metadata.insert(
+                "original_amount".to_string(),
+                format!("{} {}", row.net, row.currency).into(),
+            );
+            // The fee was charged in the foreign currency and is already included in the converted amount
+            (conversion.net, Decimal::ZERO)
+        };
+        transactions.push(to_transaction(row, net, fee, metadata));
+    }
+
+    // Conversions whose payment isn't part of the export, e.g. converting money between balances
+    for row in &rows {
+        if row.kind == Kind::CurrencyConversion
+            && row.currency == ledger_currency
+            && !used_conversions.contains(row.transaction_id.as_str())
+        {
+            ledger_currency_rows.push(row);
+            let metadata = hash_map![
+                "paypal_id".to_string() => row.transaction_id.clone().into()
+            ];
+            transactions.push(to_transaction(row, row.net, row.fee, metadata));
+        }
+    }
+    ledger_currency_rows.sort_by_key(|row| (row.date, row.time));
+
+    let (start_balance, end_balance) = balances(&ledger_currency_rows);
+    let dates = Dates {
+        start_date: rows.iter().map(|row| row.date).min().unwrap(),
+        end_date: rows.iter().map(|row| row.date).max().unwrap(),
+    };
+
+    let account_info = |start_balance: Option<Decimal>, end_balance: Option<Decimal>| AccountInfo {
+        start_balance: start_balance.map(Amount::single_currency),
+        end_balance: end_balance.map(Amount::single_currency),
+        account_currency: ledger_currency.to_string(),
+    };
+    let mut accounts = hash_map![
+        PAYPAL_ACCOUNT.to_string() => account_info(start_balance, end_balance)
+    ];
+    for transaction in &transactions {
+        for posting in &transaction.postings {
+            accounts
+                .entry(posting.account_name.clone())
+                .or_insert_with(|| account_info(None, None));
+        }
+    }
+
+    Ok(Ledger {
+        source: "PayPal".to_string(),
+        ledger_name: "PayPal".to_string(),
+        ledger_currency: ledger_currency.to_string(),
+        dates,
+        accounts,
+        transactions,
+    })
+}
+
+fn to_transaction(
+    row: &Row,
+    net: Decimal,
+    fee: Decimal,
+    metadata: HashMap<String, MetaValue>,
+) -> Transaction {
+    let mut postings = vec![Posting {
+        account_name: PAYPAL_ACCOUNT.to_string(),
+        amount: Amount::single_currency(net),
+        metadata,
+    }];
+    match row.kind {
+        // Holds and transfers move money between PayPal accounts we know, so they're balanced
+        Kind::Hold | Kind::Transfer => {
+            let counter_account = if row.kind == Kind::Hold {
+                HOLDS_ACCOUNT
+            } else {
+                TRANSFERS_ACCOUNT
+            };
+            postings.push(Posting {
+                account_name: counter_account.to_string(),
+                amount: Amount::single_currency(-net),
+                metadata: hash_map![],
+            });
+        }
+        // The counter posting for payments depends on what was bought or sold and is left to the user.
+        // The fee is deducted from the gross amount and booked separately so it doesn't have to be split off by hand.
+        Kind::Payment | Kind::CurrencyConversion => {
+            if !fee.is_zero() {
+                postings.push(Posting {
+                    account_name: FEES_ACCOUNT.to_string(),
+                    amount: Amount::single_currency(-fee),
+                    metadata: hash_map![],
+                });
+            }
+        }
+    }
+    Transaction {
+        date: row.date,
+        description: if row.kind == Kind::Payment {
+            row.description.clone()
+        } else {
+            format!("{}: {}", row.type_name, row.description)
+        },
+        payee: None,
+        metadata: hash_map![],
+        tags: vec![],
+        postings,
+    }
+}
+
+/// Returns the balances before the first and after the last row
+fn balances(rows: &[&Row]) -> (Option<Decimal>, Option<Decimal>) {
+    let start_balance = rows
+        .first()
+        .and_then(|first| Some(first.balance? - first.net));
+    let end_balance = rows.last().and_then(|last| last.balance);
+    (start_balance, end_balance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "\u{feff}\"Date\",\"Time\",\"TimeZone\",\"Name\",\"Type\",\"Status\",\"Currency\",\"Gross\",\"Fee\",\"Net\",\"From Email Address\",\"To Email Address\",\"Transaction ID\",\"Item Title\",\"Reference Txn ID\",\"Balance\",\"Subject\",\"Balance Impact\"\n";
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn load_rows(rows: &str) -> Result<Ledger> {
+        load(format!("{HEADER}{rows}").as_bytes(), "USD", "%m/%d/%Y")
+    }
+
+    fn amounts(transaction: &Transaction) -> Vec<(&str, Decimal)> {
+        transaction
+            .postings
+            .iter()
+            .map(|posting| {
+                (
+                    posting.account_name.as_str(),
+                    posting.amount.in_account_currency,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn payment_with_fee() {
+        let ledger = load_rows(
+            "\"01/05/2024\",\"10:00:00\",\"PST\",\"Jane Doe\",\"Website Payment\",\"Completed\",\"USD\",\"100.00\",\"-3.20\",\"96.80\",\"jane@example.com\",\"me@example.com\",\"1AB\",\"Widget\",\"\",\"196.80\",\"\",\"Credit\"\n",
+        )
+        .unwrap();
+        assert_eq!(1, ledger.transactions.len());
+        let transaction = &ledger.transactions[0];
+        assert_eq!(date(2024, 1, 5), transaction.date);
+        assert_eq!("Jane Doe: Widget", transaction.description);
+        assert_eq!(
+            vec![
+                (PAYPAL_ACCOUNT, Decimal::new(9680, 2)),
+                (FEES_ACCOUNT, Decimal::new(320, 2)),
+            ],
+            amounts(transaction)
+        );
+        assert!(!transaction.is_balanced());
+        assert_eq!(
+            MetaValue::from("1AB"),
+            transaction.postings[0].metadata["paypal_id"]
+        );
+        let account = &ledger.accounts[PAYPAL_ACCOUNT];
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(10000, 2))),
+            account.start_balance
+        );
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(19680, 2))),
+            account.end_balance
+        );
+        assert!(ledger.accounts.contains_key(FEES_ACCOUNT));
+    }
+
+    #[test]
+    fn holds_and_transfers_are_balanced() {
+        // Newest first, the way PayPal exports it
+        let ledger = load_rows(concat!(
+            "\"01/07/2024\",\"09:00:00\",\"PST\",\"\",\"General Withdrawal\",\"Completed\",\"USD\",\"-50.00\",\"0.00\",\"-50.00\",\"\",\"\",\"3CD\",\"\",\"\",\"0.00\",\"\",\"Debit\"\n",
+            "\"01/06/2024\",\"12:00:00\",\"PST\",\"Shop\",\"Reversal of General Account Hold\",\"Completed\",\"USD\",\"20.00\",\"0.00\",\"20.00\",\"\",\"\",\"2BC\",\"\",\"1AB\",\"50.00\",\"\",\"Credit\"\n",
+            "\"01/06/2024\",\"11:00:00\",\"PST\",\"Shop\",\"Account Hold for Open Authorization\",\"Completed\",\"USD\",\"-20.00\",\"0.00\",\"-20.00\",\"\",\"\",\"1AB\",\"\",\"\",\"30.00\",\"\",\"Debit\"\n",
+        ))
+        .unwrap();
+        assert_eq!(3, ledger.transactions.len());
+        assert!(ledger
+            .transactions
+            .iter()
+            .all(|transaction| transaction.is_balanced()));
+        assert_eq!(
+            vec![
+                (PAYPAL_ACCOUNT, Decimal::new(-2000, 2)),
+                (HOLDS_ACCOUNT, Decimal::new(2000, 2)),
+            ],
+            amounts(&ledger.transactions[0])
+        );
+        assert_eq!(
+            vec![
+                (PAYPAL_ACCOUNT, Decimal::new(-5000, 2)),
+                (TRANSFERS_ACCOUNT, Decimal::new(5000, 2)),
+            ],
+            amounts(&ledger.transactions[2])
+        );
+        let account = &ledger.accounts[PAYPAL_ACCOUNT];
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(5000, 2))),
+            account.start_balance
+        );
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::ZERO)),
+            account.end_balance
+        );
+    }
+
+    #[test]
+    fn skips_memo_and_pending_rows() {
+        let ledger = load_rows(concat!(
+            "\"01/05/2024\",\"10:00:00\",\"PST\",\"Shop\",\"Express Checkout Payment\",\"Completed\",\"USD\",\"-10.00\",\"0.00\",\"-10.00\",\"\",\"\",\"1AB\",\"\",\"\",\"0.00\",\"\",\"Debit\"\n",
+            "\"01/05/2024\",\"10:00:00\",\"PST\",\"\",\"General Card Deposit\",\"Completed\",\"USD\",\"10.00\",\"0.00\",\"10.00\",\"\",\"\",\"2BC\",\"\",\"1AB\",\"10.00\",\"\",\"Memo\"\n",
+            "\"01/06/2024\",\"10:00:00\",\"PST\",\"Shop\",\"Express Checkout Payment\",\"Pending\",\"USD\",\"-5.00\",\"0.00\",\"-5.00\",\"\",\"\",\"3CD\",\"\",\"\",\"0.00\",\"\",\"Debit\"\n",
+        ))
+        .unwrap();
+        assert_eq!(1, ledger.transactions.len());
+        assert_eq!("Shop", ledger.transactions[0].description);
+    }
+
+    #[test]
+    fn foreign_currency_payment() {
+        let ledger = load_rows(concat!(
+            "\"01/05/2024\",\"10:00:00\",\"PST\",\"Laden\",\"Express Checkout Payment\",\"Completed\",\"EUR\",\"-10.00\",\"0.00\",\"-10.00\",\"\",\"\",\"1AB\",\"\",\"\",\"0.00\",\"\",\"Debit\"\n",
+            "\"01/05/2024\",\"10:00:00\",\"PST\",\"\",\"General Currency Conversion\",\"Completed\",\"EUR\",\"10.00\",\"0.00\",\"10.00\",\"\",\"\",\"2BC\",\"\",\"1AB\",\"0.00\",\"\",\"Credit\"\n",
+            "\"01/05/2024\",\"10:00:00\",\"PST\",\"\",\"General Currency Conversion\",\"Completed\",\"USD\",\"-11.05\",\"0.00\",\"-11.05\",\"\",\"\",\"3CD\",\"\",\"1AB\",\"88.95\",\"\",\"Debit\"\n",
+        ))
+        .unwrap();
+        assert_eq!(1, ledger.transactions.len());
+        let transaction = &ledger.transactions[0];
+        assert_eq!(
+            vec![(PAYPAL_ACCOUNT, Decimal::new(-1105, 2))],
+            amounts(transaction)
+        );
+        assert_eq!(
+            MetaValue::from("-10.00 EUR"),
+            transaction.postings[0].metadata["original_amount"]
+        );
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(10000, 2))),
+            ledger.accounts[PAYPAL_ACCOUNT].start_balance
+        );
+    }
+
+    #[test]
+    fn foreign_currency_without_conversion() {
+        let result = load_rows(
+            "\"01/05/2024\",\"10:00:00\",\"PST\",\"Laden\",\"Express Checkout Payment\",\"Completed\",\"EUR\",\"-10.00\",\"0.00\",\"-10.00\",\"\",\"\",\"1AB\",\"\",\"\",\"0.00\",\"\",\"Debit\"\n",
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn missing_column() {
+        let result = load(
+            "\"Date\",\"Name\"\n\"01/05/2024\",\"Shop\"\n".as_bytes(),
+            "USD",
+            "%m/%d/%Y",
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,233 @@
+//! Python bindings, built with `maturin build --features pyo3` from this directory. They're the `_native` module of
+//! the Python package in `python/`, which re-exports them and adds importers for beangulp.
+//!
+//! ```python
+//! from beancount_import_wave import convert_wave
+//!
+//! print(convert_wave("transactions.csv", "accounts.yaml", rounding_tolerance="0.01"))
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr as _;
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum as _;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use rust_decimal::Decimal;
+
+use crate::config::Config;
+use crate::csv_import;
+use crate::export::{self, FiscalYearEnd};
+use crate::import::{self, Import};
+use crate::ir::{script::Script, AccountInfo, Transaction};
+use crate::operations::{self, MergeMode};
+use crate::registry::{ImportOptions, Registry};
+
+fn to_py_err(err: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{err:#}"))
+}
+
+fn parse_rounding_tolerance(rounding_tolerance: &str) -> Result<Decimal> {
+    Decimal::from_str(rounding_tolerance)
+        .map_err(|err| anyhow!("Invalid rounding tolerance {rounding_tolerance}: {err}"))
+}
+
+fn load(csv_path: &Path, rounding_tolerance: &str) -> Result<(Import, Decimal)> {
+    let rounding_tolerance = parse_rounding_tolerance(rounding_tolerance)?;
+    let import = import::load(std::fs::File::open(csv_path)?, rounding_tolerance, false)?;
+    Ok((import, rounding_tolerance))
+}
+
+/// Import a Wave "Account Transactions" CSV export. Returns the postings as a dict in the format of `--dump-ir`.
+#[pyfunction]
+#[pyo3(signature = (csv_path, rounding_tolerance="0"))]
+fn load_wave(py: Python<'_>, csv_path: PathBuf, rounding_tolerance: &str) -> PyResult<PyObject> {
+    let (import, _) = load(&csv_path, rounding_tolerance).map_err(to_py_err)?;
+    let json = serde_json::to_string(&import.ledger).map_err(|err| to_py_err(err.into()))?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+/// Convert a Wave "Account Transactions" CSV export to Beancount like the `wave` command, with the account names
+/// taken from the YAML config at `config_path` instead of asking for them.
+#[pyfunction]
+#[pyo3(signature = (csv_path, config_path, rounding_tolerance="0", merge="same-amount", fiscal_year_end=None, script=None))]
+fn convert_wave(
+    csv_path: PathBuf,
+    config_path: PathBuf,
+    rounding_tolerance: &str,
+    merge: &str,
+    fiscal_year_end: Option<&str>,
+    script: Option<PathBuf>,
+) -> PyResult<String> {
+    convert(
+        &csv_path,
+        &config_path,
+        rounding_tolerance,
+        merge,
+        fiscal_year_end,
+        script.as_deref(),
+    )
+    .map_err(to_py_err)
+}
+
+fn convert(
+    csv_path: &Path,
+    config_path: &Path,
+    rounding_tolerance: &str,
+    merge: &str,
+    fiscal_year_end: Option<&str>,
+    script: Option<&Path>,
+) -> Result<String> {
+    let merge = MergeMode::from_str(merge, true).map_err(|err| anyhow!(err))?;
+    let fiscal_year_end = fiscal_year_end.map(FiscalYearEnd::from_str).transpose()?;
+    let script = script.map(Script::load).transpose()?;
+    let config = Config::load(config_path)?;
+
+    let (import, rounding_tolerance) = load(csv_path, rounding_tolerance)?;
+    let ledger =
+        operations::process_wave_ledger(import.ledger, merge, rounding_tolerance, |_, _| Ok(()))?;
+    let ledger = match script {
+        Some(script) => operations::apply_script(ledger, &script)?,
+        None => ledger,
+    };
+    let ledger = import::apply_account_types(ledger, &import.accounts_with_unknown_type, &config)?;
+
+    let mut out = vec![];
+    export::render_to_writer(ledger, &config, fiscal_year_end, &mut out)?;
+    Ok(String::from_utf8(out)?)
+}
+
+/// [ImportOptions] from the options of the Python functions, which are passed as strings like on the command line
+fn import_options(
+    rounding_tolerance: &str,
+    merge: &str,
+    schema: Option<&Path>,
+    currency: &str,
+    date_format: &str,
+) -> Result<ImportOptions> {
+    Ok(ImportOptions {
+        rounding_tolerance: parse_rounding_tolerance(rounding_tolerance)?,
+        merge: MergeMode::from_str(merge, true).map_err(|err| anyhow!(err))?,
+        schema: schema.map(csv_import::Schema::load).transpose()?,
+        currency: currency.to_string(),
+        date_format: date_format.to_string(),
+    })
+}
+
+/// Whether `path` looks like an export of `source`, see [extract] for the arguments
+#[pyfunction]
+#[pyo3(signature = (source, path, rounding_tolerance="0", merge="same-amount", schema=None, currency="USD", date_format="%m/%d/%Y"))]
+fn identify(
+    source: &str,
+    path: PathBuf,
+    rounding_tolerance: &str,
+    merge: &str,
+    schema: Option<PathBuf>,
+    currency: &str,
+    date_format: &str,
+) -> PyResult<bool> {
+    let options = import_options(
+        rounding_tolerance,
+        merge,
+        schema.as_deref(),
+        currency,
+        date_format,
+    )
+    .map_err(to_py_err)?;
+    let registry = Registry::builtin();
+    let importer = registry.get(source).map_err(to_py_err)?;
+    // beangulp asks every importer about every file, including ones that can't be read
+    Ok(std::fs::read(path).is_ok_and(|content| (importer.detect)(&content, &options)))
+}
+
+/// Import `path` with `source`, which is `wave`, `csv-import`, `camt053`, `paypal` or `venmo`, and render each
+/// transaction as Beancount, with the account names taken from the YAML config at `config_path`.
+/// Returns pairs of the Beancount text and a description of the imported transaction.
+#[pyfunction]
+#[pyo3(signature = (source, path, config_path, rounding_tolerance="0", merge="same-amount", schema=None, currency="USD", date_format="%m/%d/%Y", script=None))]
+#[allow(clippy::too_many_arguments)]
+fn extract(
+    source: &str,
+    path: PathBuf,
+    config_path: PathBuf,
+    rounding_tolerance: &str,
+    merge: &str,
+    schema: Option<PathBuf>,
+    currency: &str,
+    date_format: &str,
+    script: Option<PathBuf>,
+) -> PyResult<Vec<(String, String)>> {
+    let options = import_options(
+        rounding_tolerance,
+        merge,
+        schema.as_deref(),
+        currency,
+        date_format,
+    )
+    .map_err(to_py_err)?;
+    extract_source(source, &path, &config_path, &options, script.as_deref()).map_err(to_py_err)
+}
+
+fn extract_source(
+    source: &str,
+    path: &Path,
+    config_path: &Path,
+    options: &ImportOptions,
+    script: Option<&Path>,
+) -> Result<Vec<(String, String)>> {
+    let script = script.map(Script::load).transpose()?;
+    let config = Config::load(config_path)?;
+
+    let import = (Registry::builtin().get(source)?.create)(options)?
+        .load(&std::fs::read(path)?, &mut |_, _| Ok(()))?;
+    let ledger = match script {
+        Some(script) => operations::apply_script(import.ledger, &script)?,
+        None => import.ledger,
+    };
+    let ledger = import::apply_account_types(ledger, &import.accounts_with_unknown_type, &config)?;
+
+    let descriptions: Vec<String> = ledger
+        .transactions
+        .iter()
+        .map(|transaction| describe_transaction(transaction, &ledger.accounts))
+        .collect();
+    let rendered = export::render_transactions(ledger, &config)?;
+    Ok(rendered.into_iter().zip(descriptions).collect())
+}
+
+/// The transaction as the import source has it, with its own account names
+fn describe_transaction(
+    transaction: &Transaction,
+    accounts: &HashMap<String, AccountInfo>,
+) -> String {
+    let postings = transaction
+        .postings
+        .iter()
+        .map(|posting| {
+            let currency = accounts
+                .get(&posting.account_name)
+                .map_or("", |account| account.account_currency.as_str());
+            format!(
+                "{} {} {currency}",
+                posting.account_name, posting.amount.in_account_currency
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "{} {} ({postings})",
+        transaction.date, transaction.description
+    )
+}
+
+#[pymodule]
+#[pyo3(name = "_native")]
+fn beancount_import_wave(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(load_wave, m)?)?;
+    m.add_function(wrap_pyfunction!(convert_wave, m)?)?;
+    m.add_function(wrap_pyfunction!(identify, m)?)?;
+    m.add_function(wrap_pyfunction!(extract, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,261 @@
+//! The import sources that can read a single export file, so the `import` command and the Python bindings can pick
+//! one by name or by looking at the file.
+
+use anyhow::{anyhow, bail, Result};
+use rust_decimal::Decimal;
+
+use crate::import::{self, Import};
+use crate::ir::Ledger;
+use crate::operations::{self, MergeMode};
+use crate::{camt053, csv_import, paypal, venmo};
+
+/// Options of the import sources, named like the options of their commands. Each source only uses some of them.
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub rounding_tolerance: Decimal,
+    pub merge: MergeMode,
+    /// Needed by `csv-import`, the other sources ignore it
+    pub schema: Option<csv_import::Schema>,
+    pub currency: String,
+    pub date_format: String,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            rounding_tolerance: Decimal::ZERO,
+            merge: MergeMode::SameAmount,
+            schema: None,
+            currency: "USD".to_string(),
+            date_format: "%m/%d/%Y".to_string(),
+        }
+    }
+}
+
+/// Reads an export into the intermediate representation, with balanced transactions sorted by date
+pub trait Importer {
+    /// `on_stage` gets the ledger after each processing stage, for `--dump-ir`
+    fn load(
+        &self,
+        content: &[u8],
+        on_stage: &mut dyn FnMut(&str, &Ledger) -> Result<()>,
+    ) -> Result<Import>;
+}
+
+pub struct ImporterInfo {
+    /// Name of the source, the same as the command that imports it
+    pub name: &'static str,
+    /// Whether the content is an export of this source. Should be cheap for files of other sources.
+    pub detect: fn(&[u8], &ImportOptions) -> bool,
+    pub create: fn(&ImportOptions) -> Result<Box<dyn Importer>>,
+}
+
+pub struct Registry {
+    importers: Vec<ImporterInfo>,
+}
+
+impl Registry {
+    /// All sources except `amazon`, which needs several files
+    pub fn builtin() -> Self {
+        let mut registry = Self { importers: vec![] };
+        registry.register(ImporterInfo {
+            name: "wave",
+            detect: |content, _| import::detect(content),
+            create: |options| {
+                Ok(Box::new(WaveImporter {
+                    rounding_tolerance: options.rounding_tolerance,
+                    merge: options.merge,
+                }))
+            },
+        });
+        registry.register(ImporterInfo {
+            name: "csv-import",
+            // Bank CSVs have no common layout, so the file has to parse with the schema
+            detect: |content, options| {
+                options
+                    .schema
+                    .as_ref()
+                    .is_some_and(|schema| csv_import::load(schema, content).is_ok())
+            },
+            create: |options| {
+                let schema = options
+                    .schema
+                    .clone()
+                    .ok_or_else(|| anyhow!("The csv-import source needs a schema"))?;
+                Ok(sorted(move |content| csv_import::load(&schema, content)))
+            },
+        });
+        registry.register(ImporterInfo {
+            name: "camt053",
+            detect: |content, _| camt053::detect(content),
+            create: |_| Ok(sorted(|content| camt053::load(content))),
+        });
+        registry.register(ImporterInfo {
+            name: "paypal",
+            detect: |content, _| paypal::detect(content),
+            create: |options| {
+                let ImportOptions {
+                    currency,
+                    date_format,
+                    ..
+                } = options.clone();
+                Ok(sorted(move |content| {
+                    paypal::load(content, &currency, &date_format)
+                }))
+            },
+        });
+        registry.register(ImporterInfo {
+            name: "venmo",
+            detect: |content, _| venmo::detect(content),
+            create: |_| Ok(sorted(|content| venmo::load(content))),
+        });
+        registry
+    }
+
+    fn register(&mut self, importer: ImporterInfo) {
+        assert!(
+            self.importers
+                .iter()
+                .all(|other| other.name != importer.name),
+            "Importer {} is registered twice",
+            importer.name
+        );
+        self.importers.push(importer);
+    }
+
+    fn names(&self) -> String {
+        self.importers
+            .iter()
+            .map(|importer| importer.name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn get(&self, name: &str) -> Result<&ImporterInfo> {
+        self.importers
+            .iter()
+            .find(|importer| importer.name == name)
+            .ok_or_else(|| anyhow!("Unknown source {name}, expected one of {}", self.names()))
+    }
+
+    /// The only source whose `detect` accepts the content
+    pub fn detect(&self, content: &[u8], options: &ImportOptions) -> Result<&ImporterInfo> {
+        let matches: Vec<&ImporterInfo> = self
+            .importers
+            .iter()
+            .filter(|importer| (importer.detect)(content, options))
+            .collect();
+        match matches.as_slice() {
+            [importer] => Ok(importer),
+            [] => bail!(
+                "Couldn't detect the format of the file, pass --source with one of {}",
+                self.names()
+            ),
+            _ => bail!(
+                "The file looks like an export of several sources ({}), pass --source to pick one",
+                matches
+                    .iter()
+                    .map(|importer| importer.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+struct WaveImporter {
+    rounding_tolerance: Decimal,
+    merge: MergeMode,
+}
+
+impl Importer for WaveImporter {
+    fn load(
+        &self,
+        content: &[u8],
+        on_stage: &mut dyn FnMut(&str, &Ledger) -> Result<()>,
+    ) -> Result<Import> {
+        let import = import::load_from_str(std::str::from_utf8(content)?, self.rounding_tolerance)?;
+        on_stage("import", &import.ledger)?;
+        let ledger = operations::process_wave_ledger(
+            import.ledger,
+            self.merge,
+            self.rounding_tolerance,
+            on_stage,
+        )?;
+        Ok(Import {
+            ledger,
+            accounts_with_unknown_type: import.accounts_with_unknown_type,
+        })
+    }
+}
+
+/// Sources whose exports already have balanced transactions, they only need sorting
+struct SortedImporter<F>(F);
+
+fn sorted(load: impl Fn(&[u8]) -> Result<Ledger> + 'static) -> Box<dyn Importer> {
+    Box::new(SortedImporter(load))
+}
+
+impl<F: Fn(&[u8]) -> Result<Ledger>> Importer for SortedImporter<F> {
+    fn load(
+        &self,
+        content: &[u8],
+        on_stage: &mut dyn FnMut(&str, &Ledger) -> Result<()>,
+    ) -> Result<Import> {
+        let ledger = (self.0)(content)?;
+        on_stage("import", &ledger)?;
+        let ledger = operations::sort_transactions_by_date(ledger);
+        on_stage("sort_transactions_by_date", &ledger)?;
+        Ok(Import {
+            ledger,
+            accounts_with_unknown_type: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAVE: &str =
+        "\u{feff}Account Transactions\nMy Business\nDate Range: 2023-01-01 to 2023-12-31\n";
+    const CAMT053: &str = "<?xml version=\"1.0\"?>\n<Document xmlns=\"urn:iso:std:iso:20022:tech:xsd:camt.053.001.08\">\n";
+    const PAYPAL: &str = "\u{feff}\"Date\",\"Time\",\"TimeZone\",\"Name\",\"Type\",\"Status\",\"Currency\",\"Gross\",\"Fee\",\"Net\",\"Transaction ID\"\n";
+    const VENMO: &str = "Account Statement - (@user) ,,,\nAccount Activity,,,\n,ID,Datetime,Type,Status,Note,From,To,Amount (total),Amount (fee),Funding Source,Destination,Beginning Balance,Ending Balance\n";
+
+    fn detect(content: &str) -> Result<&'static str> {
+        Ok(Registry::builtin()
+            .detect(content.as_bytes(), &ImportOptions::default())?
+            .name)
+    }
+
+    #[test]
+    fn detect_sources() {
+        assert_eq!("wave", detect(WAVE).unwrap());
+        assert_eq!("camt053", detect(CAMT053).unwrap());
+        assert_eq!("paypal", detect(PAYPAL).unwrap());
+        assert_eq!("venmo", detect(VENMO).unwrap());
+    }
+
+    #[test]
+    fn detect_unknown_format() {
+        let err = detect("Date,Description,Amount\n2023-01-01,Coffee,-3.50\n").unwrap_err();
+        assert!(err.to_string().contains("one of wave, csv-import"));
+    }
+
+    #[test]
+    fn csv_import_needs_schema() {
+        let registry = Registry::builtin();
+        let importer = registry.get("csv-import").unwrap();
+        assert!(!(importer.detect)(
+            b"2023-01-01,Coffee,-3.50\n",
+            &ImportOptions::default()
+        ));
+        assert!((importer.create)(&ImportOptions::default()).is_err());
+    }
+
+    #[test]
+    fn unknown_source() {
+        assert!(Registry::builtin().get("ofx").is_err());
+    }
+}
@@ -0,0 +1,394 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use chrono::NaiveDateTime;
+use common_macros::hash_map;
+use rust_decimal::Decimal;
+use std::io::Read;
+
+use crate::csv_import::parse_amount;
+use crate::ir::{AccountInfo, Amount, Dates, Ledger, Posting, Transaction};
+
+const VENMO_ACCOUNT: &str = "Venmo";
+const FEES_ACCOUNT: &str = "Venmo Fees";
+/// Funding source of payments that were paid out of the Venmo balance instead of a linked bank account or card
+const VENMO_BALANCE: &str = "Venmo balance";
+
+#[derive(Debug)]
+struct Row {
+    datetime: NaiveDateTime,
+    id: String,
+    type_name: String,
+    note: String,
+    from: String,
+    to: String,
+    total: Decimal,
+    fee: Decimal,
+    funding_source: String,
+    destination: String,
+}
+
+#[derive(Debug, Default)]
+struct Statement {
+    username: Option<String>,
+    beginning_balance: Option<Decimal>,
+    ending_balance: Option<Decimal>,
+    rows: Vec<Row>,
+}
+
+/// Load a Venmo account statement CSV file
+pub fn load(input_stream: impl Read) -> Result<Ledger> {
+    let statement = parse_statement(input_stream)?;
+    to_ir(statement)
+}
+
+/// Whether the content looks like a Venmo statement, i.e. the header row [load] looks for is within the first rows
+pub fn detect(content: &[u8]) -> bool {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content)
+        .records()
+        .take(5)
+        .map_while(Result::ok)
+        .any(|record| Headers::new(&record).is_ok())
+}
+
+/// Column indices, looked up by name because the statement layout changed several times over the years
+struct Headers {
+    id: usize,
+    datetime: usize,
+    type_name: usize,
+    status: usize,
+    note: usize,
+    from: usize,
+    to: usize,
+    total: usize,
+    fee: Option<usize>,
+    funding_source: usize,
+    destination: usize,
+    beginning_balance: Option<usize>,
+    ending_balance: Option<usize>,
+}
+
+impl Headers {
+    fn new(record: &csv::StringRecord) -> Result<Self> {
+        let names: Vec<&str> = record.iter().map(str::trim).collect();
+        let optional = |name: &str| names.iter().position(|column| *column == name);
+        let required = |name: &str| {
+            optional(name).ok_or_else(|| anyhow!("CSV file doesn't have a '{name}' column"))
+        };
+        Ok(Self {
+            id: required("ID")?,
+            datetime: required("Datetime")?,
+            type_name: required("Type")?,
+            status: required("Status")?,
+            note: required("Note")?,
+            from: required("From")?,
+            to: required("To")?,
+            total: required("Amount (total)")?,
+            fee: optional("Amount (fee)"),
+            funding_source: required("Funding Source")?,
+            destination: required("Destination")?,
+            beginning_balance: optional("Beginning Balance"),
+            ending_balance: optional("Ending Balance"),
+        })
+    }
+}
+
+fn parse_statement(input_stream: impl Read) -> Result<Statement> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(input_stream);
+    let mut statement = Statement::default();
+    let mut headers = None;
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = record
+            .position()
+            .map(|position| position.line())
+            .unwrap_or(index as u64);
+        let Some(headers) = &headers else {
+            // The header row is preceded by a title like "Account Statement - (@username)"
+            if record.iter().any(|cell| cell.trim() == "Datetime") {
+                headers = Some(Headers::new(&record)?);
+            } else if statement.username.is_none() {
+                statement.username = record.iter().find_map(username);
+            }
+            continue;
+        };
+        parse_record(headers, &record, &mut statement)
+            .with_context(|| format!("Failed to parse CSV row {line}"))?;
+    }
+    ensure!(
+        headers.is_some(),
+        "Not a Venmo statement, the header row wasn't found"
+    );
+    Ok(statement)
+}
+
+fn username(cell: &str) -> Option<String> {
+    let start = cell.find("(@")?;
+    let end = cell[start..].find(')')? + start;
+    Some(cell[start + 1..end].to_string())
+}
+
+fn parse_record(
+    headers: &Headers,
+    record: &csv::StringRecord,
+    statement: &mut Statement,
+) -> Result<()> {
+    let cell = |index: usize| record.get(index).map(str::trim).unwrap_or_default();
+    let amount = |index: Option<usize>| -> Result<Option<Decimal>> {
+        index
+            .map(|index| parse_amount(cell(index), '.'))
+            .transpose()
+            .map(Option::flatten)
+    };
+
+    // The first row after the header only has the beginning balance, the last one only the ending balance.
+    if let Some(beginning_balance) = amount(headers.beginning_balance)? {
+        statement.beginning_balance.get_or_insert(beginning_balance);
+    }
+    if let Some(ending_balance) = amount(headers.ending_balance)? {
+        statement.ending_balance = Some(ending_balance);
+    }
+    if cell(headers.id).is_empty() {
+        return Ok(());
+    }
+    if !matches!(cell(headers.status), "Complete" | "Completed" | "Issued") {
+        return Ok(());
+    }
+
+    let datetime = NaiveDateTime::parse_from_str(cell(headers.datetime), "%Y-%m-%dT%H:%M:%S")
+        .with_context(|| format!("Failed to parse date '{}'", cell(headers.datetime)))?;
+    statement.rows.push(Row {
+        datetime,
+        id: cell(headers.id).to_string(),
+        type_name: cell(headers.type_name).to_string(),
+        note: cell(headers.note).to_string(),
+        from: cell(headers.from).to_string(),
+        to: cell(headers.to).to_string(),
+        total: amount(Some(headers.total))?
+            .ok_or_else(|| anyhow!("Amount (total) column is empty"))?,
+        fee: amount(headers.fee)?.unwrap_or_default(),
+        funding_source: cell(headers.funding_source).to_string(),
+        destination: cell(headers.destination).to_string(),
+    });
+    Ok(())
+}
+
+fn to_ir(mut statement: Statement) -> Result<Ledger> {
+    ensure!(
+        !statement.rows.is_empty(),
+        "Statement doesn't contain any transactions"
+    );
+    statement.rows.sort_by_key(|row| row.datetime);
+
+    let dates = Dates {
+        start_date: statement.rows.first().unwrap().datetime.date(),
+        end_date: statement.rows.last().unwrap().datetime.date(),
+    };
+    let transactions: Vec<Transaction> = statement.rows.into_iter().map(to_transaction).collect();
+
+    let account_info = |start_balance: Option<Decimal>, end_balance: Option<Decimal>| AccountInfo {
+        start_balance: start_balance.map(Amount::single_currency),
+        end_balance: end_balance.map(Amount::single_currency),
+        account_currency: "USD".to_string(),
+    };
+    let mut accounts = hash_map![
+        VENMO_ACCOUNT.to_string() => account_info(statement.beginning_balance, statement.ending_balance)
+    ];
+    for transaction in &transactions {
+        for posting in &transaction.postings {
+            accounts
+                .entry(posting.account_name.clone())
+                .or_insert_with(|| account_info(None, None));
+        }
+    }
+
+    Ok(Ledger {
+        source: "Venmo".to_string(),
+        ledger_name: match statement.username {
+            Some(username) => format!("Venmo ({username})"),
+            None => "Venmo".to_string(),
+        },
+        // Venmo only supports US accounts
+        ledger_currency: "USD".to_string(),
+        dates,
+        accounts,
+        transactions,
+    })
+}
+
+fn to_transaction(row: Row) -> Transaction {
+    let is_transfer = row.type_name.ends_with("Transfer");
+    // Outgoing payments are charged to the funding source. Only those funded by the Venmo balance touch the Venmo account.
+    let account = if !is_transfer
+        && row.total.is_sign_negative()
+        && !row.funding_source.is_empty()
+        && row.funding_source != VENMO_BALANCE
+    {
+        row.funding_source.clone()
+    } else {
+        VENMO_ACCOUNT.to_string()
+    };
+    let fee = -row.fee;
+
+    let mut postings = vec![Posting {
+        account_name: account,
+        amount: Amount::single_currency(row.total),
+        metadata: hash_map![
+            "venmo_id".to_string() => row.id.into()
+        ],
+    }];
+    if !fee.is_zero() {
+        postings.push(Posting {
+            account_name: FEES_ACCOUNT.to_string(),
+            amount: Amount::single_currency(fee),
+            metadata: hash_map![],
+        });
+    }
+
+    let description = if is_transfer {
+        // Transfers out of Venmo go to the destination, transfers into Venmo come from the funding source
+        let counter_account = if row.total.is_sign_negative() {
+            &row.destination
+        } else {
+            &row.funding_source
+        };
+        if !counter_account.is_empty() {
+            postings.push(Posting {
+                account_name: counter_account.clone(),
+                amount: Amount::single_currency(-row.total - fee),
+                metadata: hash_map![],
+            });
+        }
+        format!("{}: {}", row.type_name, counter_account)
+    } else {
+        // The counter posting depends on what the payment was for and is left to the user
+        let counterparty = if row.total.is_sign_negative() {
+            &row.to
+        } else {
+            &row.from
+        };
+        if row.note.is_empty() {
+            counterparty.clone()
+        } else {
+            format!("{counterparty}: {}", row.note)
+        }
+    };
+
+    Transaction {
+        date: row.datetime.date(),
+        description,
+        payee: None,
+        metadata: hash_map![],
+        tags: vec![],
+        postings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::MetaValue;
+    use chrono::NaiveDate;
+
+    const STATEMENT: &str = "\
+Account Statement - (@john-smith) ,,,,,,,,,,,,,,,,,,,,,
+Account Activity,,,,,,,,,,,,,,,,,,,,,
+,ID,Datetime,Type,Status,Note,From,To,Amount (total),Amount (tip),Amount (tax),Amount (fee),Tax Rate,Tax Exempt,Funding Source,Destination,Beginning Balance,Ending Balance,Statement Period Venmo Fees,Terminal Location,Year to Date Venmo Fees,Disclaimer
+,,,,,,,,,,,,,,,,$10.00,,,,,
+,1001,2024-01-05T18:30:00,Payment,Complete,Dinner,Jane Doe,John Smith,+ $25.00,,0,,0,,,Venmo balance,,,,Venmo,,
+,1002,2024-01-06T09:00:00,Payment,Complete,Coffee,John Smith,Cafe,- $4.50,,0,,0,,Visa Credit *1234,,,,,Venmo,,
+,1003,2024-01-07T12:00:00,Instant Transfer,Complete,,,,- $30.00,,0,- $0.53,0,,,Chase Checking *5678,,,,Venmo,,
+,1004,2024-01-08T12:00:00,Payment,Pending,Rent,John Smith,Jane Doe,- $500.00,,0,,0,,Venmo balance,,,,,Venmo,,
+,,,,,,,,,,,,,,,,,$5.00,$0.53,,$0.53,
+In case of errors or questions about your electronic transfers,,,,,,,,,,,,,,,,,,,,,
+";
+
+    fn amounts(transaction: &Transaction) -> Vec<(&str, Decimal)> {
+        transaction
+            .postings
+            .iter()
+            .map(|posting| {
+                (
+                    posting.account_name.as_str(),
+                    posting.amount.in_account_currency,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn load_statement() {
+        let ledger = load(STATEMENT.as_bytes()).unwrap();
+        assert_eq!("Venmo (@john-smith)", ledger.ledger_name);
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+            ledger.dates.start_date
+        );
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 1, 7).unwrap(),
+            ledger.dates.end_date
+        );
+        let account = &ledger.accounts[VENMO_ACCOUNT];
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(1000, 2))),
+            account.start_balance
+        );
+        assert_eq!(
+            Some(Amount::single_currency(Decimal::new(500, 2))),
+            account.end_balance
+        );
+
+        assert_eq!(3, ledger.transactions.len());
+
+        let received = &ledger.transactions[0];
+        assert_eq!("Jane Doe: Dinner", received.description);
+        assert_eq!(
+            vec![(VENMO_ACCOUNT, Decimal::new(2500, 2))],
+            amounts(received)
+        );
+        assert_eq!(
+            MetaValue::from("1001"),
+            received.postings[0].metadata["venmo_id"]
+        );
+
+        let paid_by_card = &ledger.transactions[1];
+        assert_eq!("Cafe: Coffee", paid_by_card.description);
+        assert_eq!(
+            vec![("Visa Credit *1234", Decimal::new(-450, 2))],
+            amounts(paid_by_card)
+        );
+
+        let transfer = &ledger.transactions[2];
+        assert_eq!(
+            "Instant Transfer: Chase Checking *5678",
+            transfer.description
+        );
+        assert_eq!(
+            vec![
+                (VENMO_ACCOUNT, Decimal::new(-3000, 2)),
+                (FEES_ACCOUNT, Decimal::new(53, 2)),
+                ("Chase Checking *5678", Decimal::new(2947, 2)),
+            ],
+            amounts(transfer)
+        );
+        assert!(transfer.is_balanced());
+    }
+
+    #[test]
+    fn not_a_venmo_statement() {
+        assert!(load("Date,Amount\n2024-01-01,5.00\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_username() {
+        assert_eq!(
+            Some("@john-smith".to_string()),
+            username("Account Statement - (@john-smith) ")
+        );
+        assert_eq!(None, username("Account Activity"));
+    }
+}
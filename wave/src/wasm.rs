@@ -0,0 +1,63 @@
+//! JavaScript bindings for the preview page in `web/`. Build them with
+//!
+//! ```sh
+//! cargo rustc -p beancount-import-wave --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//! wasm-bindgen --target web --out-dir wave/web/pkg target/wasm32-unknown-unknown/release/beancount_import_wave.wasm
+//! ```
+
+use std::str::FromStr as _;
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum as _;
+use rust_decimal::Decimal;
+use wasm_bindgen::prelude::*;
+
+use crate::config::Config;
+use crate::export;
+use crate::import::{self, Import};
+use crate::operations::{self, MergeMode};
+
+fn to_js_err(err: anyhow::Error) -> JsError {
+    JsError::new(&format!("{err:#}"))
+}
+
+fn load(csv: &str, rounding_tolerance: &str) -> Result<(Import, Decimal)> {
+    let rounding_tolerance = Decimal::from_str(rounding_tolerance)
+        .map_err(|err| anyhow!("Invalid rounding tolerance {rounding_tolerance}: {err}"))?;
+    let import = import::load_from_str(csv, rounding_tolerance)?;
+    Ok((import, rounding_tolerance))
+}
+
+/// Parse the content of a Wave "Account Transactions" CSV export. Returns the postings as JSON in the format of
+/// `--dump-ir`.
+#[wasm_bindgen]
+pub fn parse_to_json(csv: &str, rounding_tolerance: &str) -> Result<String, JsError> {
+    let (import, _) = load(csv, rounding_tolerance).map_err(to_js_err)?;
+    serde_json::to_string(&import.ledger).map_err(|err| to_js_err(err.into()))
+}
+
+/// Convert the content of a Wave "Account Transactions" CSV export to Beancount like the `wave` command, with the
+/// account names taken from the YAML `config` instead of asking for them.
+#[wasm_bindgen]
+pub fn convert_to_beancount(
+    csv: &str,
+    config: &str,
+    rounding_tolerance: &str,
+    merge: &str,
+) -> Result<String, JsError> {
+    convert(csv, config, rounding_tolerance, merge).map_err(to_js_err)
+}
+
+fn convert(csv: &str, config: &str, rounding_tolerance: &str, merge: &str) -> Result<String> {
+    let merge = MergeMode::from_str(merge, true).map_err(|err| anyhow!(err))?;
+    let config = Config::from_yaml(config)?;
+
+    let (import, rounding_tolerance) = load(csv, rounding_tolerance)?;
+    let ledger =
+        operations::process_wave_ledger(import.ledger, merge, rounding_tolerance, |_, _| Ok(()))?;
+    let ledger = import::apply_account_types(ledger, &import.accounts_with_unknown_type, &config)?;
+
+    let mut out = vec![];
+    export::render_to_writer(ledger, &config, None, &mut out)?;
+    Ok(String::from_utf8(out)?)
+}